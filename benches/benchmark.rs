@@ -96,6 +96,27 @@ fn call_lua_function(c: &mut Criterion) {
     });
 }
 
+fn call_lua_function_fast(c: &mut Criterion) {
+    let lua = Lua::new();
+
+    c.bench_function("call_fast Lua function [sum] 3 10", |b| {
+        b.iter_batched_ref(
+            || {
+                collect_gc_twice(&lua);
+                lua.load("function(a, b, c) return a + b + c end")
+                    .eval::<LuaFunction>()
+                    .unwrap()
+            },
+            |function| {
+                for i in 0..10 {
+                    let _result: i64 = function.call_fast((i, i + 1, i + 2)).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
 fn call_sum_callback(c: &mut Criterion) {
     let lua = Lua::new();
     let callback = lua
@@ -307,6 +328,7 @@ criterion_group! {
         create_string_table,
         create_function,
         call_lua_function,
+        call_lua_function_fast,
         call_sum_callback,
         call_async_sum_callback,
         call_concat_callback,
@@ -19,6 +19,17 @@ fn main() {
     #[cfg(all(feature = "luau", feature = "module"))]
     compile_error!("Luau does not support module mode");
 
+    // Vendored Lua sources need libc and `setjmp`/`longjmp`, which `wasm32-unknown-unknown`
+    // lacks. Builds are not rejected, to allow setups providing their own libc shim.
+    #[cfg(any(feature = "luau", feature = "vendored"))]
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() == Ok("wasm32")
+        && std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("unknown")
+    {
+        println!(
+            "cargo:warning=wasm32-unknown-unknown is not supported by vendored builds without a libc shim"
+        );
+    }
+
     #[cfg(any(not(feature = "module"), target_os = "windows"))]
     find::probe_lua();
 
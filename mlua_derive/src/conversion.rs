@@ -0,0 +1,185 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{
+    parse_quote, Data, DeriveInput, Error, Fields, GenericParam, Generics, Ident, LitStr, Result,
+};
+
+#[derive(Default)]
+struct FieldAttributes {
+    rename: Option<LitStr>,
+    default: bool,
+    skip: bool,
+}
+
+impl FieldAttributes {
+    fn parse(&mut self, meta: ParseNestedMeta) -> Result<()> {
+        if meta.path.is_ident("rename") {
+            self.rename = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("default") {
+            self.default = true;
+        } else if meta.path.is_ident("skip") {
+            self.skip = true;
+        } else {
+            return Err(meta.error("unsupported field attribute"));
+        }
+        Ok(())
+    }
+}
+
+struct Field {
+    ident: Ident,
+    name: LitStr,
+    default: bool,
+}
+
+struct Struct {
+    fields: Vec<Field>,
+    skipped: Vec<Ident>,
+}
+
+fn parse_struct(input: &DeriveInput) -> Result<Struct> {
+    let named_fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    input,
+                    "expected a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "expected a struct with named fields",
+            ))
+        }
+    };
+
+    let mut fields = Vec::new();
+    let mut skipped = Vec::new();
+    for field in named_fields {
+        let mut field_attrs = FieldAttributes::default();
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("lua"))
+        {
+            attr.parse_nested_meta(|meta| field_attrs.parse(meta))?;
+        }
+        let ident = field.ident.clone().expect("named field");
+        if field_attrs.skip {
+            skipped.push(ident);
+            continue;
+        }
+        let name =
+            (field_attrs.rename).unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+        fields.push(Field {
+            ident,
+            name,
+            default: field_attrs.default,
+        });
+    }
+
+    Ok(Struct { fields, skipped })
+}
+
+// Adds the `'lua` lifetime used by the conversion traits, unless the struct already declares it,
+// and bounds every type parameter by the conversion trait (`bound`)
+fn with_lua_lifetime(generics: &Generics, bound: TokenStream) -> Generics {
+    let mut generics = generics.clone();
+    if !generics.lifetimes().any(|lt| lt.lifetime.ident == "lua") {
+        generics
+            .params
+            .insert(0, GenericParam::Lifetime(parse_quote!('lua)));
+    }
+    let params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(parse_quote!(#param: #bound<'lua>));
+    }
+    generics
+}
+
+pub(crate) fn derive_from_lua(input: DeriveInput) -> Result<TokenStream> {
+    let Struct { fields, skipped } = parse_struct(&input)?;
+
+    let ident = &input.ident;
+    let ident_str = ident.to_string();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let generics = with_lua_lifetime(&input.generics, quote!(::mlua::FromLua));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+    let getters = fields.iter().map(|field| {
+        let Field {
+            ident,
+            name,
+            default,
+        } = field;
+        if *default {
+            quote! {
+                #ident: table.get::<_, ::std::option::Option<_>>(#name)
+                    .map_err(|err| err.__with_field_path(#name))?
+                    .unwrap_or_default(),
+            }
+        } else {
+            quote! {
+                #ident: table.get(#name).map_err(|err| err.__with_field_path(#name))?,
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::mlua::FromLua<'lua> for #ident #ty_generics #where_clause {
+            fn from_lua(value: ::mlua::Value<'lua>, _: &'lua ::mlua::Lua) -> ::mlua::Result<Self> {
+                let table = match value {
+                    ::mlua::Value::Table(table) => table,
+                    value => {
+                        return Err(::mlua::Error::FromLuaConversionError {
+                            from: value.type_name(),
+                            to: #ident_str,
+                            message: Some("expected table".to_string()),
+                        })
+                    }
+                };
+                Ok(#ident {
+                    #(#getters)*
+                    #(
+                        #skipped: ::std::default::Default::default(),
+                    )*
+                })
+            }
+        }
+    })
+}
+
+pub(crate) fn derive_into_lua(input: DeriveInput) -> Result<TokenStream> {
+    let Struct { fields, .. } = parse_struct(&input)?;
+
+    let ident = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let generics = with_lua_lifetime(&input.generics, quote!(::mlua::IntoLua));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+    let names = fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let idents = fields.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    let fields_len = fields.len() as i32;
+
+    Ok(quote! {
+        impl #impl_generics ::mlua::IntoLua<'lua> for #ident #ty_generics #where_clause {
+            fn into_lua(self, lua: &'lua ::mlua::Lua) -> ::mlua::Result<::mlua::Value<'lua>> {
+                let table = lua.create_table_with_capacity(0, #fields_len)?;
+                #(
+                    table.raw_set(#names, self.#idents)?;
+                )*
+                Ok(::mlua::Value::Table(table))
+            }
+        }
+    })
+}
@@ -7,7 +7,7 @@ use syn::{parse_macro_input, ItemFn, LitStr, Result};
 #[cfg(feature = "macros")]
 use {
     crate::chunk::Chunk, proc_macro::TokenTree, proc_macro2::TokenStream as TokenStream2,
-    proc_macro_error::proc_macro_error,
+    proc_macro_error::proc_macro_error, syn::DeriveInput,
 };
 
 #[derive(Default)]
@@ -62,6 +62,33 @@ pub fn lua_module(attr: TokenStream, item: TokenStream) -> TokenStream {
     wrapped.into()
 }
 
+#[cfg(feature = "macros")]
+#[proc_macro_derive(LuaObject, attributes(lua))]
+pub fn lua_object(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    object::derive(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_derive(FromLua, attributes(lua))]
+pub fn from_lua(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    conversion::derive_from_lua(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[cfg(feature = "macros")]
+#[proc_macro_derive(IntoLua, attributes(lua))]
+pub fn into_lua(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    conversion::derive_into_lua(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
 #[cfg(feature = "macros")]
 fn to_ident(tt: &TokenTree) -> TokenStream2 {
     let s: TokenStream = tt.clone().into();
@@ -140,4 +167,8 @@ pub fn chunk(input: TokenStream) -> TokenStream {
 #[cfg(feature = "macros")]
 mod chunk;
 #[cfg(feature = "macros")]
+mod conversion;
+#[cfg(feature = "macros")]
+mod object;
+#[cfg(feature = "macros")]
 mod token;
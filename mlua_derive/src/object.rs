@@ -0,0 +1,168 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{Data, DeriveInput, Error, Fields, Ident, LitStr, Path, Result};
+
+#[derive(Default)]
+struct ObjectAttributes {
+    methods: Option<Path>,
+}
+
+impl ObjectAttributes {
+    fn parse(&mut self, meta: ParseNestedMeta) -> Result<()> {
+        if meta.path.is_ident("methods") {
+            self.methods = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("unsupported struct attribute"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct FieldAttributes {
+    rename: Option<LitStr>,
+    readonly: bool,
+    skip: bool,
+}
+
+impl FieldAttributes {
+    fn parse(&mut self, meta: ParseNestedMeta) -> Result<()> {
+        if meta.path.is_ident("rename") {
+            self.rename = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("readonly") {
+            self.readonly = true;
+        } else if meta.path.is_ident("skip") {
+            self.skip = true;
+        } else {
+            return Err(meta.error("unsupported field attribute"));
+        }
+        Ok(())
+    }
+}
+
+struct Field {
+    ident: Ident,
+    name: LitStr,
+    readonly: bool,
+}
+
+pub(crate) fn derive(input: DeriveInput) -> Result<TokenStream> {
+    let mut attrs = ObjectAttributes::default();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("lua"))
+    {
+        attr.parse_nested_meta(|meta| attrs.parse(meta))?;
+    }
+
+    let named_fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input,
+                    "expected a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input,
+                "expected a struct with named fields",
+            ))
+        }
+    };
+
+    // Field metadata shared by the `UserData` and table conversion implementations
+    let mut fields = Vec::new();
+    let mut skipped = Vec::new();
+    for field in named_fields {
+        let mut field_attrs = FieldAttributes::default();
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("lua"))
+        {
+            attr.parse_nested_meta(|meta| field_attrs.parse(meta))?;
+        }
+        let ident = field.ident.clone().expect("named field");
+        if field_attrs.skip {
+            skipped.push(ident);
+            continue;
+        }
+        let name =
+            (field_attrs.rename).unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+        fields.push(Field {
+            ident,
+            name,
+            readonly: field_attrs.readonly,
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let getters = fields.iter().map(|Field { ident, name, .. }| {
+        quote! {
+            fields.add_field_method_get(#name, |_, this| Ok(::std::clone::Clone::clone(&this.#ident)));
+        }
+    });
+    let setters = fields
+        .iter()
+        .filter(|f| !f.readonly)
+        .map(|Field { ident, name, .. }| {
+            quote! {
+                fields.add_field_method_set(#name, |_, this, value| {
+                    this.#ident = value;
+                    Ok(())
+                });
+            }
+        });
+    let add_methods = attrs.methods.map(|path| {
+        quote! {
+            fn add_methods<'lua, M: ::mlua::UserDataMethods<'lua, Self>>(methods: &mut M) {
+                #path(methods);
+            }
+        }
+    });
+
+    let names = fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let idents = fields.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    let fields_len = fields.len() as i32;
+
+    Ok(quote! {
+        impl #impl_generics ::mlua::UserData for #ident #ty_generics #where_clause {
+            fn add_fields<'lua, F: ::mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+                #(#getters)*
+                #(#setters)*
+            }
+
+            #add_methods
+        }
+
+        impl #impl_generics ::mlua::LuaObject for #ident #ty_generics #where_clause {
+            const FIELDS: &'static [&'static str] = &[#(#names),*];
+
+            fn to_table<'lua>(&self, lua: &'lua ::mlua::Lua) -> ::mlua::Result<::mlua::Table<'lua>> {
+                let table = lua.create_table_with_capacity(0, #fields_len)?;
+                #(
+                    table.raw_set(#names, ::std::clone::Clone::clone(&self.#idents))?;
+                )*
+                Ok(table)
+            }
+
+            fn from_table(table: ::mlua::Table) -> ::mlua::Result<Self> {
+                Ok(#ident {
+                    #(
+                        #idents: table.get(#names)?,
+                    )*
+                    #(
+                        #skipped: ::std::default::Default::default(),
+                    )*
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,785 @@
+use std::fmt::Write as _;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+
+/// Generator of typed Rust wrappers for functions defined by Lua scripts.
+///
+/// Reads Luau type definitions (or annotated Lua stubs) describing functions that scripts
+/// provide, and emits the source code of a struct with a method per function. The methods look up
+/// the function in an environment table (usually [`Lua::globals`]) and call it, so calls from Rust
+/// are type-checked instead of relying on string names. Intended to be used in build scripts.
+///
+/// Supported definitions:
+/// - `declare function name(param: type, ...): return_type`
+/// - `declare module: { name: (param: type, ...) -> return_type, ... }`
+/// - `function name(param: type, ...): return_type` and `function module.name(...)` stubs
+///   (function bodies are ignored)
+///
+/// Comments preceding a definition are used as documentation of the generated method. Methods
+/// are named after the function in snake case, prefixed by the module name if there is one.
+///
+/// Luau types are mapped to Rust types as follows: `number` to [`Number`], `integer` to
+/// [`Integer`], `string` to `&str` (parameters) or `String` (return values), `boolean` to `bool`,
+/// `T?` to `Option<T>`, `{T}` to `Vec<T>`, `{[K]: V}` to `HashMap<K, V>`, `...T` to
+/// [`Variadic<T>`], `table`/`thread`/`userdata`/function types to the corresponding handles and
+/// `any`/`unknown`/union types to [`Value`]. Other type names are used as is, so they must refer
+/// to Rust types implementing [`IntoLua`] or [`FromLua`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Bindgen, Result};
+/// # fn main() -> Result<()> {
+/// let code = Bindgen::new("ScriptApi")
+///     .definitions(
+///         r#"
+///         -- Called when a player joins the game
+///         declare function on_player_join(player: Player): ()
+///         declare function score(name: string): number?
+///         "#,
+///     )
+///     .generate()?;
+///
+/// assert!(code.contains("pub fn on_player_join(&self, player: Player) -> ::mlua::Result<()>"));
+/// assert!(code.contains(
+///     "pub fn score(&self, name: &str) -> ::mlua::Result<Option<::mlua::Number>>"
+/// ));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// In a build script, the generated code is usually written to `OUT_DIR` and included with
+/// `include!(concat!(env!("OUT_DIR"), "/script_api.rs"))`. The wrappers are then created using
+/// `ScriptApi::new(lua.globals())`.
+///
+/// [`Lua::globals`]: crate::Lua::globals
+/// [`Number`]: crate::Number
+/// [`Integer`]: crate::Integer
+/// [`Variadic<T>`]: crate::Variadic
+/// [`Value`]: crate::Value
+/// [`IntoLua`]: crate::IntoLua
+/// [`FromLua`]: crate::FromLua
+///
+/// Requires `feature = "bindgen"`
+#[derive(Clone, Debug)]
+pub struct Bindgen {
+    name: StdString,
+    sources: Vec<StdString>,
+}
+
+impl Bindgen {
+    /// Creates a new generator of a struct with the given name.
+    pub fn new(name: impl Into<StdString>) -> Self {
+        Bindgen {
+            name: name.into(),
+            sources: Vec::new(),
+        }
+    }
+
+    /// Adds definitions of functions to generate wrappers for.
+    #[must_use]
+    pub fn definitions(mut self, source: impl Into<StdString>) -> Self {
+        self.sources.push(source.into());
+        self
+    }
+
+    /// Generates the Rust source code of the wrappers.
+    ///
+    /// Returns [`Error::SyntaxError`] if definitions cannot be parsed.
+    pub fn generate(&self) -> Result<StdString> {
+        let mut functions: Vec<FunctionDef> = Vec::new();
+        for source in &self.sources {
+            for func in parse(source)? {
+                if functions.iter().any(|f| f.method == func.method) {
+                    return Err(syntax_error(
+                        func.line,
+                        format!("duplicate function `{}`", func.path.join(".")),
+                    ));
+                }
+                functions.push(func);
+            }
+        }
+
+        let name = &self.name;
+        let mut code = StdString::new();
+        let _ = write!(
+            code,
+            "// Generated by `mlua::Bindgen`, do not edit.
+
+/// Typed wrappers for functions defined by Lua scripts.
+pub struct {name}<'lua> {{
+    env: ::mlua::Table<'lua>,
+}}
+
+impl<'lua> {name}<'lua> {{
+    /// Creates wrappers for functions stored in the environment table `env`.
+    pub fn new(env: ::mlua::Table<'lua>) -> Self {{
+        {name} {{ env }}
+    }}
+"
+        );
+        for func in &functions {
+            code.push('\n');
+            write_function(&mut code, func);
+        }
+        code.push_str("}\n");
+        Ok(code)
+    }
+}
+
+struct FunctionDef {
+    path: Vec<StdString>,
+    method: StdString,
+    doc: Vec<StdString>,
+    params: Vec<Param>,
+    returns: Ty,
+    line: usize,
+}
+
+struct Param {
+    name: Option<StdString>,
+    ty: Ty,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Ty {
+    Nil,
+    Boolean,
+    Number,
+    Integer,
+    String,
+    Any,
+    Table,
+    Function,
+    Thread,
+    UserData,
+    Named(StdString),
+    Optional(Box<Ty>),
+    Array(Box<Ty>),
+    Map(Box<Ty>, Box<Ty>),
+    Tuple(Vec<Ty>),
+    Variadic(Box<Ty>),
+}
+
+fn write_function(code: &mut StdString, func: &FunctionDef) {
+    for line in &func.doc {
+        let _ = writeln!(
+            code,
+            "    ///{}{line}",
+            if line.is_empty() { "" } else { " " }
+        );
+    }
+
+    let mut params = StdString::from("&self");
+    let mut args = Vec::new();
+    for (i, param) in func.params.iter().enumerate() {
+        let name = match (&param.name, &param.ty) {
+            (Some(name), _) => rust_ident(&snake_case(name)),
+            (None, Ty::Variadic(_)) => "args".into(),
+            (None, _) => format!("arg{}", i + 1),
+        };
+        let _ = write!(params, ", {name}: {}", rust_type(&param.ty, true));
+        args.push(name);
+    }
+    let _ = writeln!(
+        code,
+        "    pub fn {}({params}) -> ::mlua::Result<{}> {{",
+        func.method,
+        rust_type(&func.returns, false)
+    );
+
+    let (last, modules) = func.path.split_last().expect("function path is empty");
+    let mut lookup = StdString::from("self.env");
+    for module in modules {
+        let _ = write!(lookup, ".get::<_, ::mlua::Table<'lua>>({module:?})?");
+    }
+    let _ = writeln!(
+        code,
+        "        let func: ::mlua::Function<'lua> = {lookup}.get({last:?})?;"
+    );
+    match args.len() {
+        0 => code.push_str("        func.call(())\n"),
+        _ => {
+            let _ = writeln!(code, "        func.call(({},))", args.join(", "));
+        }
+    }
+    code.push_str("    }\n");
+}
+
+fn rust_type(ty: &Ty, param: bool) -> StdString {
+    match ty {
+        Ty::Nil => "()".into(),
+        Ty::Boolean => "bool".into(),
+        Ty::Number => "::mlua::Number".into(),
+        Ty::Integer => "::mlua::Integer".into(),
+        Ty::String if param => "&str".into(),
+        Ty::String => "::std::string::String".into(),
+        Ty::Any => "::mlua::Value<'lua>".into(),
+        Ty::Table => "::mlua::Table<'lua>".into(),
+        Ty::Function => "::mlua::Function<'lua>".into(),
+        Ty::Thread => "::mlua::Thread<'lua>".into(),
+        Ty::UserData => "::mlua::AnyUserData<'lua>".into(),
+        Ty::Named(name) => name.clone(),
+        Ty::Optional(ty) => format!("Option<{}>", rust_type(ty, param)),
+        Ty::Array(ty) => format!("Vec<{}>", rust_type(ty, false)),
+        Ty::Map(key, value) => {
+            // Floats cannot be used as keys of a `HashMap`
+            let key = match **key {
+                Ty::Number => "::mlua::Integer".into(),
+                ref key => rust_type(key, false),
+            };
+            let value = rust_type(value, false);
+            format!("::std::collections::HashMap<{key}, {value}>")
+        }
+        Ty::Tuple(types) => {
+            let types = types.iter().map(|ty| rust_type(ty, param));
+            format!("({})", types.collect::<Vec<_>>().join(", "))
+        }
+        Ty::Variadic(ty) => format!("::mlua::Variadic<{}>", rust_type(ty, false)),
+    }
+}
+
+fn snake_case(name: &str) -> StdString {
+    let mut result = StdString::with_capacity(name.len());
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && prev_lower {
+            result.push('_');
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        result.push(c.to_ascii_lowercase());
+    }
+    result
+}
+
+fn rust_ident(name: &str) -> StdString {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do",
+        "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let",
+        "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+        "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+        "virtual", "where", "while", "yield",
+    ];
+    match name {
+        "self" | "super" | "crate" | "new" => format!("{name}_"),
+        name if KEYWORDS.contains(&name) => format!("r#{name}"),
+        name => name.into(),
+    }
+}
+
+fn syntax_error(line: usize, message: impl AsRef<str>) -> Error {
+    Error::SyntaxError {
+        message: format!("line {line}: {}", message.as_ref()),
+        incomplete_input: false,
+    }
+}
+
+//
+// Lexer
+//
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(StdString),
+    Symbol(&'static str),
+    Literal,
+}
+
+struct Lexed {
+    token: Token,
+    line: usize,
+}
+
+// Whole-line comment
+struct Comment {
+    line: usize,
+    text: StdString,
+}
+
+const SYMBOLS: &[&str] = &[
+    "...", "->", "..", "::", "==", "~=", "<=", ">=", "//", "(", ")", "{", "}", "[", "]", ":", ",",
+    ";", "?", "|", "&", "<", ">", "=", ".", "+", "-", "*", "/", "%", "^", "#", "~", "@",
+];
+
+fn tokenize(source: &str) -> Result<(Vec<Lexed>, Vec<Comment>)> {
+    let src = source.as_bytes();
+    let (mut tokens, mut comments) = (Vec::new(), Vec::new());
+    let (mut pos, mut line, mut last_token_line) = (0, 1, 0);
+
+    while pos < src.len() {
+        let c = src[pos];
+        if c == b'\n' {
+            line += 1;
+            pos += 1;
+        } else if c.is_ascii_whitespace() {
+            pos += 1;
+        } else if src[pos..].starts_with(b"--") {
+            pos += 2;
+            if let Some(level) = long_bracket_level(&src[pos..]) {
+                pos = skip_long_bracket(src, pos, level, &mut line)?;
+                continue;
+            }
+            let end = (src[pos..].iter().position(|&c| c == b'\n')).map_or(src.len(), |n| pos + n);
+            if last_token_line != line {
+                let text = StdString::from_utf8_lossy(&src[pos..end]);
+                let text = text.trim_start_matches('-');
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                comments.push(Comment {
+                    line,
+                    text: text.into(),
+                });
+            }
+            pos = end;
+        } else if c == b'"' || c == b'\'' || c == b'`' {
+            pos += 1;
+            loop {
+                match src.get(pos) {
+                    None => return Err(syntax_error(line, "unfinished string")),
+                    Some(b'\\') => pos += 2,
+                    Some(&q) if q == c => break,
+                    Some(b'\n') => {
+                        line += 1;
+                        pos += 1;
+                    }
+                    Some(_) => pos += 1,
+                }
+            }
+            pos += 1;
+            tokens.push(Lexed {
+                token: Token::Literal,
+                line,
+            });
+            last_token_line = line;
+        } else if let Some(level) = long_bracket_level(&src[pos..]) {
+            let start_line = line;
+            pos = skip_long_bracket(src, pos, level, &mut line)?;
+            tokens.push(Lexed {
+                token: Token::Literal,
+                line: start_line,
+            });
+            last_token_line = line;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = pos;
+            while pos < src.len() && (src[pos].is_ascii_alphanumeric() || src[pos] == b'_') {
+                pos += 1;
+            }
+            let name = std::str::from_utf8(&src[start..pos]).unwrap();
+            tokens.push(Lexed {
+                token: Token::Name(name.into()),
+                line,
+            });
+            last_token_line = line;
+        } else if c.is_ascii_digit() {
+            while pos < src.len() && (src[pos].is_ascii_alphanumeric() || src[pos] == b'.') {
+                pos += 1;
+            }
+            tokens.push(Lexed {
+                token: Token::Literal,
+                line,
+            });
+            last_token_line = line;
+        } else if let Some(symbol) = SYMBOLS
+            .iter()
+            .find(|s| src[pos..].starts_with(s.as_bytes()))
+        {
+            pos += symbol.len();
+            tokens.push(Lexed {
+                token: Token::Symbol(symbol),
+                line,
+            });
+            last_token_line = line;
+        } else {
+            let c = source[pos..].chars().next().unwrap();
+            return Err(syntax_error(line, format!("unexpected character `{c}`")));
+        }
+    }
+    Ok((tokens, comments))
+}
+
+// Returns the level of a long bracket (`[[` or `[==[`) starting at the beginning of `src`
+fn long_bracket_level(src: &[u8]) -> Option<usize> {
+    let rest = src.strip_prefix(b"[")?;
+    let level = rest.iter().take_while(|&&c| c == b'=').count();
+    (rest.get(level) == Some(&b'[')).then_some(level)
+}
+
+fn skip_long_bracket(src: &[u8], pos: usize, level: usize, line: &mut usize) -> Result<usize> {
+    let close = format!("]{}]", "=".repeat(level));
+    let start = pos + level + 2;
+    let end = (src[start..].windows(close.len()))
+        .position(|w| w == close.as_bytes())
+        .ok_or_else(|| syntax_error(*line, "unfinished long string or comment"))?;
+    *line += src[start..start + end]
+        .iter()
+        .filter(|&&c| c == b'\n')
+        .count();
+    Ok(start + end + close.len())
+}
+
+//
+// Parser
+//
+
+struct Parser {
+    tokens: Vec<Lexed>,
+    comments: Vec<Comment>,
+    pos: usize,
+}
+
+fn parse(source: &str) -> Result<Vec<FunctionDef>> {
+    let (tokens, comments) = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        comments,
+        pos: 0,
+    };
+    let mut functions = Vec::new();
+    while let Some(token) = parser.peek(0) {
+        match token {
+            Token::Name(name) if name == "local" => {
+                // Local functions are not visible to the host
+                parser.pos += 1;
+                parser.eat_name("function");
+            }
+            Token::Name(name) if name == "declare" => {
+                let line = parser.line();
+                parser.pos += 1;
+                if parser.eat_name("function") {
+                    let name = parser.expect_name()?;
+                    let doc = parser.doc(line);
+                    functions.push(parser.parse_function(vec![name], doc, line, true)?);
+                } else if matches!(parser.peek(1), Some(Token::Symbol(":"))) {
+                    let module = parser.expect_name()?;
+                    parser.pos += 1;
+                    parser.parse_module(module, &mut functions)?;
+                }
+            }
+            Token::Name(name) if name == "function" => {
+                let line = parser.line();
+                parser.pos += 1;
+                // Anonymous functions are skipped
+                if let Some(Token::Name(_)) = parser.peek(0) {
+                    let mut path = vec![parser.expect_name()?];
+                    while parser.eat_symbol(".") {
+                        path.push(parser.expect_name()?);
+                    }
+                    // Methods (`function obj:method()`) are not supported
+                    if parser.eat_symbol(":") {
+                        continue;
+                    }
+                    let doc = parser.doc(line);
+                    functions.push(parser.parse_function(path, doc, line, false)?);
+                }
+            }
+            _ => parser.pos += 1,
+        }
+    }
+    Ok(functions)
+}
+
+impl Parser {
+    fn peek(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n).map(|t| &t.token)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos).or_else(|| self.tokens.last()) {
+            Some(token) => token.line,
+            None => 1,
+        }
+    }
+
+    fn error(&self, message: impl AsRef<str>) -> Error {
+        syntax_error(self.line(), message)
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(0), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn eat_name(&mut self, name: &str) -> bool {
+        if matches!(self.peek(0), Some(Token::Name(n)) if n == name) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        match self.eat_symbol(symbol) {
+            true => Ok(()),
+            false => Err(self.error(format!("expected `{symbol}`"))),
+        }
+    }
+
+    fn expect_name(&mut self) -> Result<StdString> {
+        match self.peek(0) {
+            Some(Token::Name(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected name")),
+        }
+    }
+
+    // Collects whole-line comments directly preceding the given line
+    fn doc(&self, line: usize) -> Vec<StdString> {
+        let mut doc = Vec::new();
+        let mut expected = line;
+        for comment in self.comments.iter().rev() {
+            if comment.line >= line {
+                continue;
+            }
+            if comment.line + 1 != expected {
+                break;
+            }
+            doc.push(comment.text.clone());
+            expected = comment.line;
+        }
+        doc.reverse();
+        doc
+    }
+
+    // Parses the signature of a function after its name
+    fn parse_function(
+        &mut self,
+        path: Vec<StdString>,
+        doc: Vec<StdString>,
+        line: usize,
+        declared: bool,
+    ) -> Result<FunctionDef> {
+        if self.eat_symbol("<") {
+            return Err(self.error("generic functions are not supported"));
+        }
+        self.expect_symbol("(")?;
+        let params = self.parse_params(true)?;
+        let returns = match self.eat_symbol(":") {
+            true => self.parse_type()?,
+            false if declared => return Err(self.error("expected return type")),
+            false => Ty::Nil,
+        };
+
+        let method = path.iter().map(|s| snake_case(s)).collect::<Vec<_>>();
+        Ok(FunctionDef {
+            method: rust_ident(&method.join("_")),
+            path,
+            doc,
+            params,
+            returns,
+            line,
+        })
+    }
+
+    // Parses `{ name: (params) -> returns, ... }` of a declared module
+    fn parse_module(&mut self, module: StdString, functions: &mut Vec<FunctionDef>) -> Result<()> {
+        self.expect_symbol("{")?;
+        while !self.eat_symbol("}") {
+            let line = self.line();
+            if self.eat_symbol("[") {
+                // Indexers are ignored
+                self.parse_type()?;
+                self.expect_symbol("]")?;
+                self.expect_symbol(":")?;
+                self.parse_type()?;
+            } else {
+                let name = self.expect_name()?;
+                self.expect_symbol(":")?;
+                if self.eat_symbol("(") {
+                    let params = self.parse_params(false)?;
+                    if self.eat_symbol("->") {
+                        let path = vec![module.clone(), name];
+                        let method = format!("{}_{}", snake_case(&path[0]), snake_case(&path[1]));
+                        functions.push(FunctionDef {
+                            method: rust_ident(&method),
+                            path,
+                            doc: self.doc(line),
+                            params,
+                            returns: self.parse_type()?,
+                            line,
+                        });
+                    }
+                } else {
+                    self.parse_type()?;
+                }
+            }
+            if !self.eat_symbol(",") && !self.eat_symbol(";") {
+                self.expect_symbol("}")?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // Parses parameters after `(`, including the closing `)`.
+    // In function headers a name without type is a parameter of any type.
+    fn parse_params(&mut self, header: bool) -> Result<Vec<Param>> {
+        let mut params = Vec::new();
+        while !self.eat_symbol(")") {
+            if self.eat_symbol("...") {
+                let ty = match self.eat_symbol(":") || !header {
+                    true if !matches!(self.peek(0), Some(Token::Symbol(")"))) => {
+                        self.parse_type()?
+                    }
+                    _ => Ty::Any,
+                };
+                params.push(Param {
+                    name: None,
+                    ty: Ty::Variadic(Box::new(ty)),
+                });
+            } else if let (Some(Token::Name(_)), Some(Token::Symbol(":"))) =
+                (self.peek(0), self.peek(1))
+            {
+                let name = self.expect_name()?;
+                self.pos += 1;
+                params.push(Param {
+                    name: Some(name),
+                    ty: self.parse_type()?,
+                });
+            } else if header {
+                params.push(Param {
+                    name: Some(self.expect_name()?),
+                    ty: Ty::Any,
+                });
+            } else {
+                params.push(Param {
+                    name: None,
+                    ty: self.parse_type()?,
+                });
+            }
+            if !self.eat_symbol(",") {
+                self.expect_symbol(")")?;
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    fn parse_type(&mut self) -> Result<Ty> {
+        if self.eat_symbol("...") {
+            return Ok(Ty::Variadic(Box::new(self.parse_type()?)));
+        }
+        // Leading separators are allowed in unions and intersections
+        let mut union = self.eat_symbol("|") || self.eat_symbol("&");
+        let mut ty = self.parse_simple_type()?;
+        loop {
+            if self.eat_symbol("?") {
+                ty = Ty::Optional(Box::new(ty));
+            } else if self.eat_symbol("|") || self.eat_symbol("&") {
+                self.parse_simple_type()?;
+                union = true;
+            } else {
+                break;
+            }
+        }
+        Ok(if union { Ty::Any } else { ty })
+    }
+
+    fn parse_simple_type(&mut self) -> Result<Ty> {
+        let token = self.peek(0).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Name(name)) => Ok(match name.as_str() {
+                "nil" => Ty::Nil,
+                "boolean" | "true" | "false" => Ty::Boolean,
+                "number" => Ty::Number,
+                "integer" => Ty::Integer,
+                "string" => Ty::String,
+                "any" | "unknown" => Ty::Any,
+                "table" => Ty::Table,
+                "function" => Ty::Function,
+                "thread" => Ty::Thread,
+                "userdata" => Ty::UserData,
+                "typeof" => {
+                    self.expect_symbol("(")?;
+                    self.skip_until(")")?;
+                    Ty::Any
+                }
+                _ => {
+                    let mut name = name;
+                    while self.eat_symbol(".") {
+                        name = self.expect_name()?;
+                    }
+                    if self.eat_symbol("<") {
+                        // Type arguments are not supported
+                        self.skip_until(">")?;
+                        return Ok(Ty::Any);
+                    }
+                    Ty::Named(name)
+                }
+            }),
+            Some(Token::Literal) => Ok(Ty::String),
+            Some(Token::Symbol("{")) => {
+                if self.eat_symbol("}") {
+                    return Ok(Ty::Table);
+                }
+                if self.eat_symbol("[") {
+                    let key = self.parse_type()?;
+                    self.expect_symbol("]")?;
+                    self.expect_symbol(":")?;
+                    let value = self.parse_type()?;
+                    self.eat_symbol(",");
+                    self.expect_symbol("}")?;
+                    return Ok(Ty::Map(Box::new(key), Box::new(value)));
+                }
+                if let (Some(Token::Name(_)), Some(Token::Symbol(":"))) =
+                    (self.peek(0), self.peek(1))
+                {
+                    // Table with named fields
+                    self.skip_until("}")?;
+                    return Ok(Ty::Table);
+                }
+                let ty = self.parse_type()?;
+                self.expect_symbol("}")?;
+                Ok(Ty::Array(Box::new(ty)))
+            }
+            Some(Token::Symbol("(")) => {
+                let mut types = (self.parse_params(false)?.into_iter())
+                    .map(|param| param.ty)
+                    .collect::<Vec<_>>();
+                if self.eat_symbol("->") {
+                    self.parse_type()?;
+                    return Ok(Ty::Function);
+                }
+                Ok(match types.len() {
+                    0 => Ty::Nil,
+                    1 => types.remove(0),
+                    _ => Ty::Tuple(types),
+                })
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.error("expected type"))
+            }
+        }
+    }
+
+    // Skips tokens until the matching closing symbol (the opening one is already consumed)
+    fn skip_until(&mut self, close: &str) -> Result<()> {
+        let open = match close {
+            ")" => "(",
+            "}" => "{",
+            _ => "<",
+        };
+        let mut depth = 1;
+        while let Some(token) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            match token.token {
+                Token::Symbol(s) if s == open => depth += 1,
+                Token::Symbol(s) if s == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(self.error(format!("expected `{close}`")))
+    }
+}
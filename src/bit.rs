@@ -0,0 +1,182 @@
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::{MultiValue, Value};
+
+/// Width of integers used by the `bit` library (see [`Lua::load_bit_library`]).
+///
+/// The same operations are available from Rust as methods of this type, so results computed on
+/// the Rust side always match the results computed by scripts.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::BitWidth;
+/// assert_eq!(BitWidth::Bits32.band(&[0xff, 0x0f]), 0x0f);
+/// assert_eq!(BitWidth::Bits32.bnot(0), -1);
+/// assert_eq!(BitWidth::Bits32.rshift(-1, 28), 0xf);
+/// assert_eq!(BitWidth::Bits64.rshift(-1, 60), 0xf);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitWidth {
+    /// 32-bit operations compatible with the LuaJIT `bit` module.
+    ///
+    /// Results are normalized to signed 32-bit integers.
+    Bits32,
+    /// 64-bit operations.
+    ///
+    /// On Lua versions without an integer subtype (Lua 5.1, LuaJIT and Luau) results beyond
+    /// 2^53 cannot be represented exactly.
+    Bits64,
+}
+
+impl BitWidth {
+    /// Normalizes the value to the integer width.
+    pub const fn tobit(self, x: i64) -> i64 {
+        match self {
+            BitWidth::Bits32 => x as i32 as i64,
+            BitWidth::Bits64 => x,
+        }
+    }
+
+    /// Returns the bitwise "and" of all arguments.
+    pub fn band(self, args: &[i64]) -> i64 {
+        self.tobit(args.iter().fold(-1, |acc, x| acc & x))
+    }
+
+    /// Returns the bitwise "or" of all arguments.
+    pub fn bor(self, args: &[i64]) -> i64 {
+        self.tobit(args.iter().fold(0, |acc, x| acc | x))
+    }
+
+    /// Returns the bitwise "xor" of all arguments.
+    pub fn bxor(self, args: &[i64]) -> i64 {
+        self.tobit(args.iter().fold(0, |acc, x| acc ^ x))
+    }
+
+    /// Returns the bitwise "not" of the argument.
+    pub const fn bnot(self, x: i64) -> i64 {
+        self.tobit(!x)
+    }
+
+    /// Returns the bitwise logical left shift of `x` by `n` bits.
+    ///
+    /// Only the lower 5 (or 6) bits of the shift count are used.
+    pub const fn lshift(self, x: i64, n: u32) -> i64 {
+        match self {
+            BitWidth::Bits32 => ((x as u32) << (n & 31)) as i32 as i64,
+            BitWidth::Bits64 => ((x as u64) << (n & 63)) as i64,
+        }
+    }
+
+    /// Returns the bitwise logical right shift of `x` by `n` bits.
+    ///
+    /// Only the lower 5 (or 6) bits of the shift count are used.
+    pub const fn rshift(self, x: i64, n: u32) -> i64 {
+        match self {
+            BitWidth::Bits32 => ((x as u32) >> (n & 31)) as i32 as i64,
+            BitWidth::Bits64 => ((x as u64) >> (n & 63)) as i64,
+        }
+    }
+
+    /// Returns the bitwise arithmetic right shift of `x` by `n` bits.
+    ///
+    /// Only the lower 5 (or 6) bits of the shift count are used.
+    pub const fn arshift(self, x: i64, n: u32) -> i64 {
+        match self {
+            BitWidth::Bits32 => ((x as i32) >> (n & 31)) as i64,
+            BitWidth::Bits64 => x >> (n & 63),
+        }
+    }
+
+    /// Returns `x` rotated left by `n` bits.
+    pub const fn rol(self, x: i64, n: u32) -> i64 {
+        match self {
+            BitWidth::Bits32 => (x as u32).rotate_left(n & 31) as i32 as i64,
+            BitWidth::Bits64 => (x as u64).rotate_left(n & 63) as i64,
+        }
+    }
+
+    /// Returns `x` rotated right by `n` bits.
+    pub const fn ror(self, x: i64, n: u32) -> i64 {
+        match self {
+            BitWidth::Bits32 => (x as u32).rotate_right(n & 31) as i32 as i64,
+            BitWidth::Bits64 => (x as u64).rotate_right(n & 63) as i64,
+        }
+    }
+
+    // Converts a Lua value to an integer of this width
+    fn check_arg(self, value: &Value, pos: usize) -> Result<i64> {
+        let x = match *value {
+            #[allow(clippy::useless_conversion)]
+            Value::Integer(i) => i64::from(i),
+            Value::Number(n) if n.is_finite() && n.fract() == 0.0 => match self {
+                // Wrap around like LuaJIT does
+                BitWidth::Bits32 => n.rem_euclid(4294967296.0) as i64,
+                BitWidth::Bits64 if (i64::MIN as f64..i64::MAX as f64).contains(&n) => n as i64,
+                BitWidth::Bits64 => {
+                    return Err(bad_argument(pos, "number has no integer representation"))
+                }
+            },
+            Value::Number(_) => {
+                return Err(bad_argument(pos, "number has no integer representation"))
+            }
+            _ => {
+                let msg = format!("number expected, got {}", value.type_name());
+                return Err(bad_argument(pos, &msg));
+            }
+        };
+        Ok(self.tobit(x))
+    }
+}
+
+fn bad_argument(pos: usize, msg: &str) -> Error {
+    Error::BadArgument {
+        to: None,
+        pos,
+        name: None,
+        cause: std::sync::Arc::new(Error::RuntimeError(msg.to_string())),
+    }
+}
+
+// Creates a table with `bit` library functions
+pub(crate) fn create_bit_library<'lua>(lua: &'lua Lua, width: BitWidth) -> Result<Table<'lua>> {
+    let bit = lua.create_table_with_capacity(0, 10)?;
+
+    let fold = |name, op: fn(BitWidth, &[i64]) -> i64| -> Result<()> {
+        let func = lua.create_function(move |_, args: MultiValue| {
+            let args = (args.iter().enumerate())
+                .map(|(i, arg)| width.check_arg(arg, i + 1))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(op(width, &args))
+        })?;
+        bit.raw_set(name, func)
+    };
+    fold("band", BitWidth::band)?;
+    fold("bor", BitWidth::bor)?;
+    fold("bxor", BitWidth::bxor)?;
+
+    let unary = |name, op: fn(BitWidth, i64) -> i64| -> Result<()> {
+        let func =
+            lua.create_function(move |_, x: Value| Ok(op(width, width.check_arg(&x, 1)?)))?;
+        bit.raw_set(name, func)
+    };
+    unary("tobit", BitWidth::tobit)?;
+    unary("bnot", BitWidth::bnot)?;
+
+    let shift = |name, op: fn(BitWidth, i64, u32) -> i64| -> Result<()> {
+        let func = lua.create_function(move |_, (x, n): (Value, Value)| {
+            let x = width.check_arg(&x, 1)?;
+            let n = width.check_arg(&n, 2)?;
+            Ok(op(width, x, n as u32))
+        })?;
+        bit.raw_set(name, func)
+    };
+    shift("lshift", BitWidth::lshift)?;
+    shift("rshift", BitWidth::rshift)?;
+    shift("arshift", BitWidth::arshift)?;
+    shift("rol", BitWidth::rol)?;
+    shift("ror", BitWidth::ror)?;
+
+    Ok(bit)
+}
@@ -4,13 +4,22 @@ use std::ffi::CString;
 use std::io::Result as IoResult;
 use std::path::{Path, PathBuf};
 use std::string::String as StdString;
+use std::sync::Arc;
 
 use crate::error::{Error, ErrorContext, Result};
 use crate::function::Function;
 use crate::lua::Lua;
+use crate::source_map::SourceMap;
 use crate::table::Table;
+use crate::types::{MaybeSend, Preprocessor};
 use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti};
 
+#[cfg(not(feature = "luau"))]
+use {
+    crate::hook::{Debug, HookTriggers, StepControl, StepInfo},
+    std::{cell::Cell, mem},
+};
+
 /// Trait for types [loadable by Lua] and convertible to a [`Chunk`]
 ///
 /// [loadable by Lua]: https://www.lua.org/manual/5.4/manual.html#3.3.2
@@ -104,6 +113,10 @@ pub struct Chunk<'lua, 'a> {
     pub(crate) env: Result<Option<Table<'lua>>>,
     pub(crate) mode: Option<ChunkMode>,
     pub(crate) source: IoResult<Cow<'a, [u8]>>,
+    pub(crate) source_map: Option<SourceMap>,
+    pub(crate) preprocessor: Option<Preprocessor>,
+    // Source returned by the preprocessor, kept after loading the chunk
+    pub(crate) preprocessed_source: Option<StdString>,
     #[cfg(feature = "luau")]
     pub(crate) compiler: Option<Compiler>,
 }
@@ -280,6 +293,55 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         self
     }
 
+    /// Attaches a source map to this chunk, for code generated from another source.
+    ///
+    /// Errors and tracebacks raised by the chunk report positions in the original source
+    /// (traceback frames also keep the generated position). The map is registered by the chunk
+    /// name and replaces maps of previously loaded chunks with the same name. Up to 256 most
+    /// recently loaded chunks keep their maps.
+    ///
+    /// Error messages refer to chunks by their short name (truncated by Lua), so chunks with source
+    /// maps should have names differing in the first few dozen characters. Otherwise positions are
+    /// mapped using the most recently loaded chunk.
+    ///
+    /// See [`SourceMap`] for details.
+    pub fn set_source_map(mut self, map: SourceMap) -> Self {
+        self.source_map = Some(map);
+        self
+    }
+
+    /// Sets a function transforming the source of this chunk before it's loaded, replacing the
+    /// preprocessor set using [`Lua::set_preprocessor`].
+    ///
+    /// The preprocessor is not applied to binary chunks. Line numbers in error messages refer to
+    /// the transformed source, which can be retrieved using [`Lua::preprocessed_source`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum = lua
+    ///     .load("return a ++ b")
+    ///     .set_preprocessor(|source| Ok(source.replace("++", "+")))
+    ///     .set_environment(lua.load("return {a = 1, b = 2}").eval::<mlua::Table>()?)
+    ///     .eval::<i64>()?;
+    /// assert_eq!(sum, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::set_preprocessor`]: crate::Lua::set_preprocessor
+    /// [`Lua::preprocessed_source`]: crate::Lua::preprocessed_source
+    pub fn set_preprocessor<F>(mut self, preprocessor: F) -> Self
+    where
+        F: Fn(&str) -> Result<StdString> + MaybeSend + 'static,
+    {
+        self.preprocessor = Some(Arc::new(preprocessor));
+        self
+    }
+
     /// Sets or overwrites a Luau compiler used for this chunk.
     ///
     /// See [`Compiler`] for details and possible options.
@@ -313,12 +375,134 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         self.call_async(()).await
     }
 
+    /// Executes this chunk of code line by line.
+    ///
+    /// `step` is called before each line of Lua code is executed (including lines of Lua functions
+    /// called by the chunk) with the line number and active local variables, and decides how the
+    /// execution proceeds. The callback runs synchronously, so the host can pause the execution
+    /// by not returning from it (eg. while waiting for user input), which is useful for
+    /// interactive tutorials.
+    ///
+    /// Returns `false` if the execution was aborted by the callback.
+    ///
+    /// The line hook is installed on top of the active one (see [`Lua::push_hook`]) for the
+    /// duration of the call.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/lua51/luajit"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, StepControl};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let mut lines = Vec::new();
+    /// let completed = lua
+    ///     .load("local x = 1\nx = x + 1\nprint(x)")
+    ///     .exec_stepped(|step| {
+    ///         lines.push(step.line);
+    ///         match step.line {
+    ///             3 => StepControl::Abort,
+    ///             _ => StepControl::Continue,
+    ///         }
+    ///     })?;
+    /// assert!(!completed);
+    /// assert_eq!(lines, [1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::push_hook`]: crate::Lua::push_hook
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn exec_stepped<F>(self, mut step: F) -> Result<bool>
+    where
+        F: FnMut(StepInfo<'lua>) -> StepControl,
+    {
+        let lua = self.lua;
+        let func = self.into_function()?;
+
+        let control = Cell::new(StepControl::Continue);
+        let mut on_line = |debug: Debug<'lua>| -> Result<()> {
+            if control.get() == StepControl::Continue {
+                control.set(step(StepInfo {
+                    line: debug.curr_line().max(0) as usize,
+                    source: debug.source().short_src.map(|s| s.into_owned()),
+                    function: debug.names().name.map(|s| s.into_owned()),
+                    locals: debug.locals()?,
+                }));
+            }
+            match control.get() {
+                // Keep raising the error in case the script catches it
+                StepControl::Abort => Err(Error::RuntimeError("execution aborted".to_string())),
+                _ => Ok(()),
+            }
+        };
+
+        // The hook is removed before `on_line` goes out of scope, so it's safe to erase lifetimes
+        struct StepHook(*mut (dyn FnMut(Debug<'static>) -> Result<()> + 'static));
+        unsafe impl Send for StepHook {}
+        impl StepHook {
+            unsafe fn call(&self, debug: Debug) -> Result<()> {
+                (*self.0)(mem::transmute::<Debug, Debug<'static>>(debug))
+            }
+        }
+        let on_line: &mut (dyn FnMut(Debug<'lua>) -> Result<()> + '_) = &mut on_line;
+        let hook = StepHook(unsafe { mem::transmute(on_line) });
+
+        let guard = lua.push_hook(HookTriggers::EVERY_LINE, move |_, debug| unsafe {
+            hook.call(debug)
+        })?;
+        let result = func.call::<_, ()>(());
+        drop(guard);
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(_) if control.get() == StepControl::Abort => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Executes this chunk of code, aborting it once `budget` VM instructions are executed.
+    ///
+    /// Returns [`Error::InstructionLimitExceeded`] if the budget is exhausted, even if the script
+    /// catches the error. Every call gets a new budget. Instructions are counted by a count hook
+    /// installed on top of the active one (see [`Lua::push_hook`]) for the duration of the call;
+    /// budgets larger than 1000 are checked every 1000 instructions. Coroutines created during
+    /// the call inherit the hook, so instructions they execute are counted as well.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/lua51/luajit"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load("local x = 0 for i = 1, 10 do x = x + i end").exec_with_budget(10_000)?;
+    ///
+    /// let result = lua.load("while true do end").exec_with_budget(10_000);
+    /// assert!(matches!(result, Err(Error::InstructionLimitExceeded(10_000))));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::push_hook`]: crate::Lua::push_hook
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn exec_with_budget(self, budget: u64) -> Result<()> {
+        let lua = self.lua;
+        let func = self.into_function()?;
+        lua.with_instruction_limit(budget, || func.call(()))
+    }
+
     /// Evaluate the chunk as either an expression or block.
     ///
     /// If the chunk can be parsed as an expression, this loads and executes the chunk and returns
     /// the value that it evaluates to. Otherwise, the chunk is interpreted as a block as normal,
     /// and this is equivalent to calling `exec`.
-    pub fn eval<R: FromLuaMulti<'lua>>(self) -> Result<R> {
+    pub fn eval<R: FromLuaMulti<'lua>>(mut self) -> Result<R> {
+        self.preprocess()?;
         // Bytecode is always interpreted as a statement.
         // For source code, first try interpreting the lua as an expression by adding
         // "return", then as a statement. This is the same thing the
@@ -341,10 +525,11 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// [`eval`]: #method.eval
     #[cfg(feature = "async")]
     #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
-    pub async fn eval_async<R>(self) -> Result<R>
+    pub async fn eval_async<R>(mut self) -> Result<R>
     where
         R: FromLuaMulti<'lua> + 'lua,
     {
+        self.preprocess()?;
         if self.detect_mode() == ChunkMode::Binary {
             self.call_async(()).await
         } else if let Ok(function) = self.to_expression() {
@@ -381,8 +566,8 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     /// Load this chunk into a regular `Function`.
     ///
     /// This simply compiles the chunk without actually executing it.
-    #[cfg_attr(not(feature = "luau"), allow(unused_mut))]
     pub fn into_function(mut self) -> Result<Function<'lua>> {
+        self.preprocess()?;
         #[cfg(feature = "luau")]
         if self.compiler.is_some() {
             // We don't need to compile source if no compiler set
@@ -390,8 +575,83 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
         }
 
         let name = Self::convert_name(self.name)?;
+        let func = self
+            .lua
+            .load_chunk(Some(&name), self.env?, self.mode, self.source?.as_ref())?;
+        if let Some(map) = self.source_map {
+            self.lua.set_source_map(&func, map);
+        }
         self.lua
-            .load_chunk(Some(&name), self.env?, self.mode, self.source?.as_ref())
+            .set_preprocessed_source(&func, self.preprocessed_source);
+        Ok(func)
+    }
+
+    /// Compiles this chunk to bytecode without executing it.
+    ///
+    /// The bytecode can be stored (eg. at build time) and loaded later with [`Lua::load`], which
+    /// detects binary chunks automatically (see [`Lua::allow_bytecode`]). Debug information is
+    /// kept; use [`Function::dump`] to get stripped bytecode.
+    ///
+    /// For Luau, the chunk is compiled using the compiler set for the chunk or the Lua instance
+    /// (see [`Chunk::set_compiler`]).
+    ///
+    /// Binary chunks are returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let bytecode = lua.load("return 1 + 2").set_name("sum").into_bytecode()?;
+    ///
+    /// let other = Lua::new();
+    /// assert_eq!(other.load(&bytecode).eval::<i32>()?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Lua::load`]: crate::Lua::load
+    /// [`Lua::allow_bytecode`]: crate::Lua::allow_bytecode
+    pub fn into_bytecode(mut self) -> Result<Vec<u8>> {
+        self.preprocess()?;
+        if self.detect_mode() == ChunkMode::Binary {
+            return Ok(self.source?.into_owned());
+        }
+
+        let name = Self::convert_name(self.name)?;
+        let source = self.source?;
+        #[cfg(feature = "luau")]
+        {
+            let data = self.compiler.unwrap_or_default().compile(source);
+            // Loading the bytecode reports compilation errors
+            (self.lua).load_chunk(Some(&name), None, Some(ChunkMode::Binary), &data)?;
+            Ok(data)
+        }
+        #[cfg(not(feature = "luau"))]
+        {
+            let func = (self.lua).load_chunk(Some(&name), None, Some(ChunkMode::Text), &source)?;
+            Ok(func.dump(false))
+        }
+    }
+
+    /// Applies the preprocessor to the source of a text chunk.
+    ///
+    /// It does nothing if the chunk is already preprocessed.
+    fn preprocess(&mut self) -> Result<()> {
+        let preprocessor = match self.preprocessor.take() {
+            Some(preprocessor) if self.detect_mode() == ChunkMode::Text => preprocessor,
+            _ => return Ok(()),
+        };
+        if let Ok(ref source) = self.source {
+            let source = std::str::from_utf8(source).map_err(|err| {
+                Error::RuntimeError(format!("cannot preprocess non UTF-8 source: {err}"))
+            })?;
+            let source = preprocessor(source)?;
+            self.source = Ok(Cow::Owned(source.clone().into_bytes()));
+            self.preprocessed_source = Some(source);
+        }
+        Ok(())
     }
 
     /// Compiles the chunk and changes mode to binary.
@@ -425,6 +685,9 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
     pub(crate) fn try_cache(mut self) -> Self {
         struct ChunksCache(HashMap<Vec<u8>, Vec<u8>>);
 
+        // Internal chunks are never preprocessed
+        self.preprocessor = None;
+
         // Try to fetch compiled chunk from cache
         let mut text_source = None;
         if let Ok(ref source) = self.source {
@@ -473,8 +736,12 @@ impl<'lua, 'a> Chunk<'lua, 'a> {
             .unwrap_or(source);
 
         let name = Self::convert_name(self.name.clone())?;
-        self.lua
-            .load_chunk(Some(&name), self.env.clone()?, None, &source)
+        let func = (self.lua).load_chunk(Some(&name), self.env.clone()?, None, &source)?;
+        if let Some(map) = self.source_map.clone() {
+            self.lua.set_source_map(&func, map);
+        }
+        (self.lua).set_preprocessed_source(&func, self.preprocessed_source.clone());
+        Ok(func)
     }
 
     fn detect_mode(&self) -> ChunkMode {
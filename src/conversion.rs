@@ -1,25 +1,33 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::convert::TryInto;
 use std::ffi::{CStr, CString};
 use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::result::Result as StdResult;
 use std::string::String as StdString;
 
 use bstr::{BStr, BString};
-use num_traits::cast;
+use num_traits::{cast, NumCast, ToPrimitive};
 
 use crate::error::{Error, Result};
 use crate::function::{Function, WrappedFunction};
 use crate::lua::Lua;
+use crate::private::Sealed;
 use crate::string::String;
 use crate::table::Table;
 use crate::thread::Thread;
-use crate::types::{LightUserData, MaybeSend};
-use crate::userdata::{AnyUserData, UserData, UserDataRef, UserDataRefMut};
+use crate::types::{Integer, LightUserData, MaybeSend, Number};
+use crate::userdata::{
+    AnyUserData, MetaMethod, UserData, UserDataMethods, UserDataRef, UserDataRefMut,
+};
 use crate::value::{FromLua, IntoLua, Nil, Value};
 
 #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
-use crate::{function::OwnedFunction, table::OwnedTable, userdata::OwnedAnyUserData};
+use crate::{
+    function::OwnedFunction, string::OwnedString, table::OwnedTable, thread::OwnedThread,
+    userdata::OwnedAnyUserData,
+};
 
 #[cfg(feature = "async")]
 use crate::function::WrappedAsyncFunction;
@@ -58,6 +66,24 @@ impl<'lua> FromLua<'lua> for String<'lua> {
     }
 }
 
+#[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+impl<'lua> IntoLua<'lua> for OwnedString {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::String(String(lua.adopt_owned_ref(self.0))))
+    }
+}
+
+#[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+impl<'lua> FromLua<'lua> for OwnedString {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<OwnedString> {
+        String::from_lua(value, lua).map(|s| s.into_owned())
+    }
+}
+
 impl<'lua> IntoLua<'lua> for Table<'lua> {
     #[inline]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
@@ -74,7 +100,8 @@ impl<'lua> FromLua<'lua> for Table<'lua> {
                 from: value.type_name(),
                 to: "table",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -113,7 +140,8 @@ impl<'lua> FromLua<'lua> for Function<'lua> {
                 from: value.type_name(),
                 to: "function",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -167,11 +195,30 @@ impl<'lua> FromLua<'lua> for Thread<'lua> {
                 from: value.type_name(),
                 to: "thread",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
 
+#[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+impl<'lua> IntoLua<'lua> for OwnedThread {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Thread(Thread(lua.adopt_owned_ref(self.0))))
+    }
+}
+
+#[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+impl<'lua> FromLua<'lua> for OwnedThread {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<OwnedThread> {
+        Thread::from_lua(value, lua).map(|s| s.into_owned())
+    }
+}
+
 impl<'lua> IntoLua<'lua> for AnyUserData<'lua> {
     #[inline]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
@@ -188,7 +235,8 @@ impl<'lua> FromLua<'lua> for AnyUserData<'lua> {
                 from: value.type_name(),
                 to: "userdata",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -220,11 +268,13 @@ impl<'lua, T: 'static + MaybeSend + UserData> IntoLua<'lua> for T {
 
 impl<'lua, T: 'static> FromLua<'lua> for UserDataRef<'lua, T> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
-        Self::from_value(value)
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        from_value_or_coerce::<T>(value, lua)
     }
 }
 
+// Coercions are not applied here: changes made through the reference would go to a temporary
+// userdata instead of the value passed by the caller
 impl<'lua, T: 'static> FromLua<'lua> for UserDataRefMut<'lua, T> {
     #[inline]
     fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
@@ -232,6 +282,27 @@ impl<'lua, T: 'static> FromLua<'lua> for UserDataRefMut<'lua, T> {
     }
 }
 
+// Falls back to coercions registered for `T` if the value is not a userdata of type `T`
+fn from_value_or_coerce<'lua, T: 'static>(
+    value: Value<'lua>,
+    lua: &'lua Lua,
+) -> Result<UserDataRef<'lua, T>> {
+    if !lua.has_coercions::<T>() {
+        return UserDataRef::from_value(value);
+    }
+    match UserDataRef::from_value(value.clone()) {
+        Err(
+            err @ (Error::FromLuaConversionError { .. }
+            | Error::FromLuaConversionDetails { .. }
+            | Error::UserDataTypeMismatch),
+        ) => match lua.coerce_to_lua::<T>(value) {
+            Some(value) => UserDataRef::from_value(value?),
+            None => Err(err),
+        },
+        res => res,
+    }
+}
+
 impl<'lua> IntoLua<'lua> for Error {
     #[inline]
     fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
@@ -287,7 +358,8 @@ impl<'lua> FromLua<'lua> for LightUserData {
                 from: value.type_name(),
                 to: "light userdata",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -310,11 +382,56 @@ impl<'lua> FromLua<'lua> for crate::types::Vector {
                 from: value.type_name(),
                 to: "vector",
                 message: None,
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
 
+#[cfg(all(feature = "vector", not(feature = "luau")))]
+impl<'lua> IntoLua<'lua> for crate::types::Vector {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        crate::vector::create_vector(lua, self)
+    }
+}
+
+#[cfg(all(feature = "vector", not(feature = "luau")))]
+impl<'lua> FromLua<'lua> for crate::types::Vector {
+    #[inline]
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::UserData(ref ud) => crate::vector::userdata_to_vector(ud),
+            _ => None,
+        }
+        .ok_or_else(|| crate::vector::vector_conversion_error(&value))
+    }
+}
+
+#[cfg(all(
+    feature = "glam",
+    any(feature = "luau", feature = "vector"),
+    not(feature = "luau-vector4")
+))]
+impl<'lua> IntoLua<'lua> for glam::Vec3 {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        crate::types::Vector::from(self).into_lua(lua)
+    }
+}
+
+#[cfg(all(
+    feature = "glam",
+    any(feature = "luau", feature = "vector"),
+    not(feature = "luau-vector4")
+))]
+impl<'lua> FromLua<'lua> for glam::Vec3 {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        crate::types::Vector::from_lua(value, lua).map(Self::from)
+    }
+}
+
 impl<'lua> IntoLua<'lua> for StdString {
     #[inline]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
@@ -326,15 +443,15 @@ impl<'lua> FromLua<'lua> for StdString {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
-        Ok(lua
+        let string = lua
             .coerce_string(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "String",
                 message: Some("expected string or number".to_string()),
-            })?
-            .to_str()?
-            .to_owned())
+            })?;
+        check_string_len(lua, &string, "String")?;
+        Ok(string.to_str()?.to_owned())
     }
 }
 
@@ -363,16 +480,15 @@ impl<'lua> FromLua<'lua> for Box<str> {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
-        Ok(lua
+        let string = lua
             .coerce_string(value)?
             .ok_or_else(|| Error::FromLuaConversionError {
                 from: ty,
                 to: "Box<str>",
                 message: Some("expected string or number".to_string()),
-            })?
-            .to_str()?
-            .to_owned()
-            .into_boxed_str())
+            })?;
+        check_string_len(lua, &string, "Box<str>")?;
+        Ok(string.to_str()?.to_owned().into_boxed_str())
     }
 }
 
@@ -394,6 +510,7 @@ impl<'lua> FromLua<'lua> for CString {
                 to: "CString",
                 message: Some("expected string or number".to_string()),
             })?;
+        check_string_len(lua, &string, "CString")?;
 
         match CStr::from_bytes_with_nul(string.as_bytes_with_nul()) {
             Ok(s) => Ok(s.into()),
@@ -401,7 +518,8 @@ impl<'lua> FromLua<'lua> for CString {
                 from: ty,
                 to: "CString",
                 message: Some("invalid C-style string".to_string()),
-            }),
+            }
+            .with_value_preview(Value::String(string).preview())),
         }
     }
 }
@@ -431,16 +549,15 @@ impl<'lua> FromLua<'lua> for BString {
     #[inline]
     fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         let ty = value.type_name();
-        Ok(BString::from(
-            lua.coerce_string(value)?
-                .ok_or_else(|| Error::FromLuaConversionError {
-                    from: ty,
-                    to: "String",
-                    message: Some("expected string or number".to_string()),
-                })?
-                .as_bytes()
-                .to_vec(),
-        ))
+        let string = lua
+            .coerce_string(value)?
+            .ok_or_else(|| Error::FromLuaConversionError {
+                from: ty,
+                to: "String",
+                message: Some("expected string or number".to_string()),
+            })?;
+        check_string_len(lua, &string, "BString")?;
+        Ok(BString::from(string.as_bytes().to_vec()))
     }
 }
 
@@ -451,51 +568,396 @@ impl<'lua> IntoLua<'lua> for &BStr {
     }
 }
 
+/// Policy used to convert a Lua number with a fractional part into an integer.
+///
+/// [`ConversionPolicy::Trunc`] is the policy used by the [`FromLua`] implementations
+/// of the Rust integer types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ConversionPolicy {
+    /// Discard the fractional part (round towards zero).
+    #[default]
+    Trunc,
+    /// Round to the nearest integer, rounding half-way cases away from zero.
+    Round,
+    /// Fail if the number has a fractional part.
+    Exact,
+}
+
+impl ConversionPolicy {
+    /// Applies the policy to a number, returning an integral number.
+    ///
+    /// Returns `None` if the policy is [`ConversionPolicy::Exact`] and the number has no
+    /// integer representation.
+    pub fn apply(self, n: Number) -> Option<Number> {
+        match self {
+            ConversionPolicy::Trunc => Some(n.trunc()),
+            ConversionPolicy::Round => Some(n.round()),
+            ConversionPolicy::Exact if n.fract() == 0.0 => Some(n),
+            ConversionPolicy::Exact => None,
+        }
+    }
+}
+
+/// Policy used to convert Rust integers exceeding the range of Lua integers (eg. `u64` values
+/// above `i64::MAX`) into Lua values.
+///
+/// Set using [`Lua::set_integer_overflow`]. For a lossless representation of `u64` values
+/// independent of the policy and Lua version, see [`Lua::create_u64`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum IntegerOverflow {
+    /// Convert into a Lua number, possibly losing precision.
+    #[default]
+    Float,
+    /// Fail with a conversion error.
+    Error,
+    /// Reinterpret values in the range of the unsigned Lua integer type (eg. `u64` for 64-bit
+    /// integers) as negative Lua integers, in two's complement.
+    ///
+    /// Negative Lua integers are converted back into Rust unsigned types the same way.
+    Wrap,
+}
+
+/// Table representation of Rust sets (`HashSet` and `BTreeSet`) converted into Lua values.
+///
+/// Set using [`Lua::set_set_mode`]. Conversion of tables into Rust sets accepts both
+/// representations: a table with a non-empty sequence part is read as an array, otherwise its
+/// keys are used.
+///
+/// Other collections use a fixed representation: `Vec`, `VecDeque` and `BinaryHeap` are
+/// converted into sequences (a `BinaryHeap` in ascending order), maps into tables with the same
+/// keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum SetMode {
+    /// Elements are stored as table keys with `true` values (eg. `{a = true, b = true}`).
+    #[default]
+    Keys,
+    /// Elements are stored in a sequence (eg. `{"a", "b"}`), in the set iteration order.
+    Array,
+}
+
+/// Limits enforced when converting Lua values into Rust values.
+///
+/// Set using [`Lua::set_conversion_limits`]. The limits apply to conversions of tables into Rust
+/// collections (eg. `Vec<T>` or `HashMap<K, V>`), of strings into Rust strings and to the serde
+/// bridge, protecting the host from untrusted scripts constructing pathological structures.
+///
+/// All limits are disabled by default.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{ConversionLimits, Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// lua.set_conversion_limits(ConversionLimits::new().max_table_entries(100));
+/// let table = lua.load("local t = {} for i = 1, 1000 do t[i] = i end return t").eval()?;
+/// assert!(lua.unpack::<Vec<i32>>(table).is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConversionLimits {
+    /// Maximum nesting depth of converted tables.
+    ///
+    /// Exceeding the limit fails with [`Error::ConversionDepthExceeded`].
+    pub max_depth: Option<usize>,
+    /// Maximum number of entries of a converted table.
+    pub max_table_entries: Option<usize>,
+    /// Maximum length (in bytes) of a converted string.
+    pub max_string_len: Option<usize>,
+}
+
+impl ConversionLimits {
+    /// Returns a new instance of `ConversionLimits` with all limits disabled.
+    pub const fn new() -> Self {
+        ConversionLimits {
+            max_depth: None,
+            max_table_entries: None,
+            max_string_len: None,
+        }
+    }
+
+    /// Sets [`max_depth`] limit.
+    ///
+    /// [`max_depth`]: #structfield.max_depth
+    #[must_use]
+    pub const fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sets [`max_table_entries`] limit.
+    ///
+    /// [`max_table_entries`]: #structfield.max_table_entries
+    #[must_use]
+    pub const fn max_table_entries(mut self, entries: usize) -> Self {
+        self.max_table_entries = Some(entries);
+        self
+    }
+
+    /// Sets [`max_string_len`] limit.
+    ///
+    /// [`max_string_len`]: #structfield.max_string_len
+    #[must_use]
+    pub const fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = Some(len);
+        self
+    }
+
+    // Returns the error message if a table having at least `n` entries exceeds the limit
+    pub(crate) fn check_table_entries(&self, n: usize) -> StdResult<(), StdString> {
+        match self.max_table_entries {
+            Some(max) if n > max => Err(format!("table has more than {max} entries")),
+            _ => Ok(()),
+        }
+    }
+
+    // Returns the error message if a string of length `len` exceeds the limit
+    pub(crate) fn check_string_len(&self, len: usize) -> StdResult<(), StdString> {
+        match self.max_string_len {
+            Some(max) if len > max => Err(format!("string is longer than {max} bytes")),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Collects entries of a table into a Rust collection, enforcing `ConversionLimits`
+fn collect_table<T, C: FromIterator<T>>(
+    lua: &Lua,
+    to: &'static str,
+    iter: impl Iterator<Item = Result<T>>,
+) -> Result<C> {
+    let _depth = lua.enter_from_conversion()?;
+    let limits = lua.conversion_limits();
+    iter.enumerate()
+        .map(|(i, item)| {
+            limits
+                .check_table_entries(i + 1)
+                .map_err(|message| Error::FromLuaConversionError {
+                    from: "table",
+                    to,
+                    message: Some(message),
+                })?;
+            item
+        })
+        .collect()
+}
+
+// Checks the length of a string converted into a Rust string against `ConversionLimits`
+fn check_string_len(lua: &Lua, s: &String, to: &'static str) -> Result<()> {
+    (lua.conversion_limits().check_string_len(s.as_bytes().len())).map_err(|message| {
+        Error::FromLuaConversionError {
+            from: "string",
+            to,
+            message: Some(message),
+        }
+    })
+}
+
+// Reinterprets a value in the range of the unsigned counterpart of `Integer` as `Integer`
+// (the range check is always true for 64-bit integers)
+#[allow(clippy::absurd_extreme_comparisons)]
+fn wrap_integer(u: u64) -> Option<Integer> {
+    (u <= u64::MAX >> (64 - Integer::BITS)).then_some(u as Integer)
+}
+
+// Reverses `wrap_integer`
+fn unwrap_integer(i: Integer) -> u64 {
+    i as u64 & (u64::MAX >> (64 - Integer::BITS))
+}
+
+fn int_into_lua<'lua, T: NumCast + ToPrimitive + Copy>(
+    v: T,
+    lua: &'lua Lua,
+    from: &'static str,
+) -> Result<Value<'lua>> {
+    if let Some(i) = cast(v) {
+        return Ok(Value::Integer(i));
+    }
+    let out_of_range = || Error::ToLuaConversionError {
+        from,
+        to: "integer",
+        message: Some("out of range".to_owned()),
+    };
+    match lua.integer_overflow() {
+        // Conversion to Number never fails
+        IntegerOverflow::Float => cast(v).map(Value::Number).ok_or_else(out_of_range),
+        IntegerOverflow::Error => Err(out_of_range()),
+        IntegerOverflow::Wrap => (v.to_u64().and_then(wrap_integer))
+            .map(Value::Integer)
+            .ok_or_else(out_of_range),
+    }
+}
+
+// Lossless representation of `u64` values, created by `Lua::create_u64`
+pub(crate) struct BoxedU64(pub(crate) u64);
+
+impl UserData for BoxedU64 {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        fn result<'lua>(lua: &'lua Lua, v: Option<u64>, msg: &str) -> Result<AnyUserData<'lua>> {
+            match v {
+                Some(v) => lua.create_u64(v),
+                None => Err(Error::RuntimeError(msg.to_string())),
+            }
+        }
+
+        // Operands are converted using `FromLua` for `u64`, which accepts boxed values
+        methods.add_meta_function(MetaMethod::Add, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_add(b), "u64 overflow")
+        });
+        methods.add_meta_function(MetaMethod::Sub, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_sub(b), "u64 overflow")
+        });
+        methods.add_meta_function(MetaMethod::Mul, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_mul(b), "u64 overflow")
+        });
+        methods.add_meta_function(MetaMethod::Div, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_div(b), "division by zero")
+        });
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        methods.add_meta_function(MetaMethod::IDiv, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_div(b), "division by zero")
+        });
+        methods.add_meta_function(MetaMethod::Mod, |lua, (a, b): (u64, u64)| {
+            result(lua, a.checked_rem(b), "division by zero")
+        });
+        methods.add_meta_function(MetaMethod::Eq, |_, (a, b): (u64, u64)| Ok(a == b));
+        methods.add_meta_function(MetaMethod::Lt, |_, (a, b): (u64, u64)| Ok(a < b));
+        methods.add_meta_function(MetaMethod::Le, |_, (a, b): (u64, u64)| Ok(a <= b));
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| Ok(this.0.to_string()));
+    }
+}
+
+/// Checked conversion of Lua numbers into Rust integer types.
+///
+/// This is the same conversion used by the [`FromLua`] implementations of the integer types,
+/// which allows host code to follow the binding's conversion rules exactly.
+///
+/// ```
+/// # use mlua::{CheckedConversion, ConversionPolicy, Integer, Value};
+/// assert_eq!(Integer::checked_from_value(Value::Number(2.5), ConversionPolicy::Round).unwrap(), 3);
+/// assert!(u8::checked_from_value(Value::Integer(256), ConversionPolicy::Trunc).is_err());
+/// ```
+pub trait CheckedConversion: Sized + Sealed {
+    /// Converts a Lua value into `Self` using the provided policy.
+    ///
+    /// Strings are coerced to numbers in a manner consistent with Lua's internal behavior.
+    fn checked_from_value(value: Value, policy: ConversionPolicy) -> Result<Self>;
+
+    /// Converts a Lua number into `Self` using the provided policy.
+    fn checked_from_number(n: Number, policy: ConversionPolicy) -> Result<Self>;
+}
+
+fn int_from_number<T: NumCast>(
+    n: Number,
+    from: &'static str,
+    to: &'static str,
+    policy: ConversionPolicy,
+    preview: impl Fn() -> Option<StdString>,
+) -> Result<T> {
+    let n = policy.apply(n).ok_or_else(|| {
+        Error::FromLuaConversionError {
+            from,
+            to,
+            message: Some("number has no integer representation".to_string()),
+        }
+        .with_value_preview(preview())
+    })?;
+    cast(n).ok_or_else(|| {
+        Error::FromLuaConversionError {
+            from,
+            to,
+            message: Some("out of range".to_owned()),
+        }
+        .with_value_preview(preview())
+    })
+}
+
+fn int_from_value<'lua, T: NumCast>(
+    value: Value<'lua>,
+    lua: Option<&'lua Lua>,
+    to: &'static str,
+    policy: ConversionPolicy,
+) -> Result<T> {
+    let ty = value.type_name();
+    let out_of_range = |value: &Value| {
+        Error::FromLuaConversionError {
+            from: ty,
+            to,
+            message: Some("out of range".to_owned()),
+        }
+        .with_value_preview(value.preview())
+    };
+    let not_a_number = |value: &Value| {
+        Error::FromLuaConversionError {
+            from: ty,
+            to,
+            message: Some("expected number or string coercible to number".to_string()),
+        }
+        .with_value_preview(value.preview())
+    };
+    let lua = match (&value, lua) {
+        (&Value::Integer(i), _) => {
+            return cast(i)
+                .or_else(|| match lua {
+                    Some(lua) if i < 0 && lua.integer_overflow() == IntegerOverflow::Wrap => {
+                        cast(unwrap_integer(i))
+                    }
+                    _ => None,
+                })
+                .ok_or_else(|| out_of_range(&value))
+        }
+        (Value::UserData(ud), _) if ud.is::<BoxedU64>() => {
+            let u = ud.borrow::<BoxedU64>()?.0;
+            return cast(u).ok_or_else(|| out_of_range(&value));
+        }
+        (&Value::Number(n), _) => {
+            return int_from_number(n, ty, to, policy, || Value::Number(n).preview())
+        }
+        (_, Some(lua)) => lua,
+        (Value::String(s), None) => s.0.lua,
+        (_, None) => return Err(not_a_number(&value)),
+    };
+    if let Some(i) = lua.coerce_integer(value.clone())? {
+        return cast(i).ok_or_else(|| out_of_range(&value));
+    }
+    match lua.coerce_number(value.clone())? {
+        Some(n) => int_from_number(n, ty, to, policy, || value.preview()),
+        None => Err(not_a_number(&value)),
+    }
+}
+
 macro_rules! lua_convert_int {
     ($x:ty) => {
         impl<'lua> IntoLua<'lua> for $x {
             #[inline]
-            fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
-                cast(self)
-                    .map(Value::Integer)
-                    .or_else(|| cast(self).map(Value::Number))
-                    // This is impossible error because conversion to Number never fails
-                    .ok_or_else(|| Error::ToLuaConversionError {
-                        from: stringify!($x),
-                        to: "number",
-                        message: Some("out of range".to_owned()),
-                    })
+            fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+                int_into_lua(self, lua, stringify!($x))
             }
         }
 
         impl<'lua> FromLua<'lua> for $x {
             #[inline]
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
-                let ty = value.type_name();
-                (match value {
-                    Value::Integer(i) => cast(i),
-                    Value::Number(n) => cast(n),
-                    _ => {
-                        if let Some(i) = lua.coerce_integer(value.clone())? {
-                            cast(i)
-                        } else {
-                            cast(lua.coerce_number(value)?.ok_or_else(|| {
-                                Error::FromLuaConversionError {
-                                    from: ty,
-                                    to: stringify!($x),
-                                    message: Some(
-                                        "expected number or string coercible to number".to_string(),
-                                    ),
-                                }
-                            })?)
-                        }
-                    }
-                })
-                .ok_or_else(|| Error::FromLuaConversionError {
-                    from: ty,
-                    to: stringify!($x),
-                    message: Some("out of range".to_owned()),
-                })
+                int_from_value(value, Some(lua), stringify!($x), ConversionPolicy::Trunc)
+            }
+        }
+
+        impl Sealed for $x {}
+
+        impl CheckedConversion for $x {
+            #[inline]
+            fn checked_from_value(value: Value, policy: ConversionPolicy) -> Result<Self> {
+                int_from_value(value, None, stringify!($x), policy)
+            }
+
+            #[inline]
+            fn checked_from_number(n: Number, policy: ConversionPolicy) -> Result<Self> {
+                let preview = || Value::Number(n).preview();
+                int_from_number(n, "number", stringify!($x), policy, preview)
             }
         }
     };
@@ -533,17 +995,25 @@ macro_rules! lua_convert_float {
             #[inline]
             fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
                 let ty = value.type_name();
-                lua.coerce_number(value)?
-                    .ok_or_else(|| Error::FromLuaConversionError {
-                        from: ty,
-                        to: stringify!($x),
-                        message: Some("expected number or string coercible to number".to_string()),
-                    })
-                    .and_then(|n| {
-                        cast(n).ok_or_else(|| Error::FromLuaConversionError {
+                lua.coerce_number(value.clone())?
+                    .ok_or_else(|| {
+                        Error::FromLuaConversionError {
                             from: ty,
                             to: stringify!($x),
-                            message: Some("number out of range".to_string()),
+                            message: Some(
+                                "expected number or string coercible to number".to_string(),
+                            ),
+                        }
+                        .with_value_preview(value.preview())
+                    })
+                    .and_then(|n| {
+                        cast(n).ok_or_else(|| {
+                            Error::FromLuaConversionError {
+                                from: ty,
+                                to: stringify!($x),
+                                message: Some("number out of range".to_string()),
+                            }
+                            .with_value_preview(Value::Number(n).preview())
                         })
                     })
             }
@@ -581,22 +1051,22 @@ where
     T: FromLua<'lua>,
 {
     #[inline]
-    fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
             #[cfg(feature = "luau")]
             #[rustfmt::skip]
             Value::Vector(v) if N == crate::types::Vector::SIZE => unsafe {
                 use std::{mem, ptr};
                 let mut arr: [mem::MaybeUninit<T>; N] = mem::MaybeUninit::uninit().assume_init();
-                ptr::write(arr[0].as_mut_ptr() , T::from_lua(Value::Number(v.x() as _), _lua)?);
-                ptr::write(arr[1].as_mut_ptr(), T::from_lua(Value::Number(v.y() as _), _lua)?);
-                ptr::write(arr[2].as_mut_ptr(), T::from_lua(Value::Number(v.z() as _), _lua)?);
+                ptr::write(arr[0].as_mut_ptr() , T::from_lua(Value::Number(v.x() as _), lua)?);
+                ptr::write(arr[1].as_mut_ptr(), T::from_lua(Value::Number(v.y() as _), lua)?);
+                ptr::write(arr[2].as_mut_ptr(), T::from_lua(Value::Number(v.z() as _), lua)?);
                 #[cfg(feature = "luau-vector4")]
-                ptr::write(arr[3].as_mut_ptr(), T::from_lua(Value::Number(v.w() as _), _lua)?);
+                ptr::write(arr[3].as_mut_ptr(), T::from_lua(Value::Number(v.w() as _), lua)?);
                 Ok(mem::transmute_copy(&arr))
             },
             Value::Table(table) => {
-                let vec = table.sequence_values().collect::<Result<Vec<_>>>()?;
+                let vec: Vec<T> = collect_table(lua, "Array", table.sequence_values())?;
                 vec.try_into()
                     .map_err(|vec: Vec<T>| Error::FromLuaConversionError {
                         from: "Table",
@@ -608,7 +1078,8 @@ where
                 from: value.type_name(),
                 to: "Array",
                 message: Some("expected table".to_string()),
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -636,14 +1107,15 @@ impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Vec<T> {
 
 impl<'lua, T: FromLua<'lua>> FromLua<'lua> for Vec<T> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _lua: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) => table.sequence_values().collect(),
+            Value::Table(table) => collect_table(lua, "Vec", table.sequence_values()),
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "Vec",
                 message: Some("expected table".to_string()),
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -661,15 +1133,16 @@ impl<'lua, K: Eq + Hash + FromLua<'lua>, V: FromLua<'lua>, S: BuildHasher + Defa
     for HashMap<K, V, S>
 {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         if let Value::Table(table) = value {
-            table.pairs().collect()
+            collect_table(lua, "HashMap", table.pairs())
         } else {
             Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "HashMap",
                 message: Some("expected table".to_string()),
-            })
+            }
+            .with_value_preview(value.preview()))
         }
     }
 }
@@ -683,15 +1156,16 @@ impl<'lua, K: Ord + IntoLua<'lua>, V: IntoLua<'lua>> IntoLua<'lua> for BTreeMap<
 
 impl<'lua, K: Ord + FromLua<'lua>, V: FromLua<'lua>> FromLua<'lua> for BTreeMap<K, V> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         if let Value::Table(table) = value {
-            table.pairs().collect()
+            collect_table(lua, "BTreeMap", table.pairs())
         } else {
             Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "BTreeMap",
                 message: Some("expected table".to_string()),
-            })
+            }
+            .with_value_preview(value.preview()))
         }
     }
 }
@@ -699,26 +1173,27 @@ impl<'lua, K: Ord + FromLua<'lua>, V: FromLua<'lua>> FromLua<'lua> for BTreeMap<
 impl<'lua, T: Eq + Hash + IntoLua<'lua>, S: BuildHasher> IntoLua<'lua> for HashSet<T, S> {
     #[inline]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
-        Ok(Value::Table(lua.create_table_from(
-            self.into_iter().map(|val| (val, true)),
-        )?))
+        set_into_lua(lua, self)
     }
 }
 
 impl<'lua, T: Eq + Hash + FromLua<'lua>, S: BuildHasher + Default> FromLua<'lua> for HashSet<T, S> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) if table.raw_len() > 0 => table.sequence_values().collect(),
-            Value::Table(table) => table
-                .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
-                .collect(),
+            Value::Table(table) if table.raw_len() > 0 => {
+                collect_table(lua, "HashSet", table.sequence_values())
+            }
+            Value::Table(table) => {
+                let keys = (table.pairs::<T, Value<'lua>>()).map(|res| res.map(|(k, _)| k));
+                collect_table(lua, "HashSet", keys)
+            }
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "HashSet",
                 message: Some("expected table".to_string()),
-            }),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
@@ -726,30 +1201,89 @@ impl<'lua, T: Eq + Hash + FromLua<'lua>, S: BuildHasher + Default> FromLua<'lua>
 impl<'lua, T: Ord + IntoLua<'lua>> IntoLua<'lua> for BTreeSet<T> {
     #[inline]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
-        Ok(Value::Table(lua.create_table_from(
-            self.into_iter().map(|val| (val, true)),
-        )?))
+        set_into_lua(lua, self)
     }
 }
 
 impl<'lua, T: Ord + FromLua<'lua>> FromLua<'lua> for BTreeSet<T> {
     #[inline]
-    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
         match value {
-            Value::Table(table) if table.raw_len() > 0 => table.sequence_values().collect(),
-            Value::Table(table) => table
-                .pairs::<T, Value<'lua>>()
-                .map(|res| res.map(|(k, _)| k))
-                .collect(),
+            Value::Table(table) if table.raw_len() > 0 => {
+                collect_table(lua, "BTreeSet", table.sequence_values())
+            }
+            Value::Table(table) => {
+                let keys = (table.pairs::<T, Value<'lua>>()).map(|res| res.map(|(k, _)| k));
+                collect_table(lua, "BTreeSet", keys)
+            }
             _ => Err(Error::FromLuaConversionError {
                 from: value.type_name(),
                 to: "BTreeSet",
                 message: Some("expected table".to_string()),
-            }),
+            }
+            .with_value_preview(value.preview())),
+        }
+    }
+}
+
+impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for VecDeque<T> {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(lua.create_sequence_from(self)?))
+    }
+}
+
+impl<'lua, T: FromLua<'lua>> FromLua<'lua> for VecDeque<T> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::Table(table) => collect_table(lua, "VecDeque", table.sequence_values()),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "VecDeque",
+                message: Some("expected table".to_string()),
+            }
+            .with_value_preview(value.preview())),
         }
     }
 }
 
+impl<'lua, T: Ord + IntoLua<'lua>> IntoLua<'lua> for BinaryHeap<T> {
+    #[inline]
+    fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::Table(
+            lua.create_sequence_from(self.into_sorted_vec())?,
+        ))
+    }
+}
+
+impl<'lua, T: Ord + FromLua<'lua>> FromLua<'lua> for BinaryHeap<T> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::Table(table) => collect_table(lua, "BinaryHeap", table.sequence_values()),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "BinaryHeap",
+                message: Some("expected table".to_string()),
+            }
+            .with_value_preview(value.preview())),
+        }
+    }
+}
+
+// Converts a Rust set into a table using the `SetMode` of the Lua state
+fn set_into_lua<'lua, T: IntoLua<'lua>>(
+    lua: &'lua Lua,
+    set: impl IntoIterator<Item = T>,
+) -> Result<Value<'lua>> {
+    let table = match lua.set_mode() {
+        SetMode::Keys => lua.create_table_from(set.into_iter().map(|val| (val, true)))?,
+        SetMode::Array => lua.create_sequence_from(set)?,
+    };
+    Ok(Value::Table(table))
+}
+
 impl<'lua, T: IntoLua<'lua>> IntoLua<'lua> for Option<T> {
     #[inline]
     fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
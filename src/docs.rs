@@ -0,0 +1,210 @@
+use std::fmt::Write as _;
+use std::os::raw::c_void;
+use std::string::String as StdString;
+
+use rustc_hash::FxHashMap;
+
+use crate::error::Result;
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::Value;
+
+/// Documentation of a host function, attached at registration.
+///
+/// See [`Lua::create_function_with_meta`] and [`UserDataMethods::document_method`].
+///
+/// [`UserDataMethods::document_method`]: crate::UserDataMethods::document_method
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FnMeta {
+    /// Description of the function.
+    pub doc: StdString,
+    /// Parameters, eg. `"name: string"`.
+    pub params: Vec<StdString>,
+    /// Return values, eg. `"boolean"`.
+    pub returns: Vec<StdString>,
+}
+
+impl FnMeta {
+    /// Creates a new `FnMeta` with the given description.
+    pub fn new(doc: impl Into<StdString>) -> Self {
+        FnMeta {
+            doc: doc.into(),
+            params: Vec::new(),
+            returns: Vec::new(),
+        }
+    }
+
+    /// Adds a parameter.
+    #[must_use]
+    pub fn param(mut self, param: impl Into<StdString>) -> Self {
+        self.params.push(param.into());
+        self
+    }
+
+    /// Adds a return value.
+    #[must_use]
+    pub fn returns(mut self, ret: impl Into<StdString>) -> Self {
+        self.returns.push(ret.into());
+        self
+    }
+
+    /// Formats the function signature, eg. `add(a: number, b: number) -> number`.
+    pub fn signature(&self, name: &str) -> StdString {
+        let mut sig = format!("{name}({})", self.params.join(", "));
+        if !self.returns.is_empty() {
+            let _ = write!(sig, " -> {}", self.returns.join(", "));
+        }
+        sig
+    }
+}
+
+/// Documentation entry returned by [`Lua::api_docs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiDoc {
+    /// Name of the function (eg. `math2.add` or `Counter:increment` for userdata methods).
+    ///
+    /// Functions are named after the global variable (or field of a global table) holding them,
+    /// `None` if such a variable cannot be found.
+    pub name: Option<StdString>,
+    /// The attached documentation.
+    pub meta: FnMeta,
+}
+
+impl ApiDoc {
+    fn help(&self) -> StdString {
+        let name = self.name.as_deref().unwrap_or("function");
+        let mut help = self.meta.signature(name);
+        for line in self.meta.doc.lines() {
+            let _ = write!(help, "\n    {line}");
+        }
+        help
+    }
+}
+
+// Registry key of the weak-keyed table mapping functions to their documentation
+const API_DOCS_REGISTRY_KEY: &str = "__mlua_api_docs";
+
+fn docs_table<'lua>(lua: &'lua Lua) -> Result<Table<'lua>> {
+    if let Some(docs) = lua.named_registry_value::<Option<Table>>(API_DOCS_REGISTRY_KEY)? {
+        return Ok(docs);
+    }
+    let docs = lua.create_table()?;
+    docs.set_metatable(Some(lua.create_table_from([("__mode", "k")])?));
+    lua.set_named_registry_value(API_DOCS_REGISTRY_KEY, docs.clone())?;
+    Ok(docs)
+}
+
+pub(crate) fn set_meta(lua: &Lua, func: &Function, name: Option<&str>, meta: FnMeta) -> Result<()> {
+    let entry = lua.create_table_with_capacity(0, 4)?;
+    entry.raw_set("name", name)?;
+    entry.raw_set("doc", meta.doc)?;
+    entry.raw_set("params", meta.params)?;
+    entry.raw_set("returns", meta.returns)?;
+    docs_table(lua)?.raw_set(func.clone(), entry)
+}
+
+fn entry_to_doc(
+    entry: Table,
+    names: &FxHashMap<*const c_void, StdString>,
+    ptr: *const c_void,
+) -> Result<ApiDoc> {
+    let name = match entry.raw_get::<_, Option<StdString>>("name")? {
+        Some(name) => Some(name),
+        None => names.get(&ptr).cloned(),
+    };
+    Ok(ApiDoc {
+        name,
+        meta: FnMeta {
+            doc: entry.raw_get("doc")?,
+            params: entry.raw_get("params")?,
+            returns: entry.raw_get("returns")?,
+        },
+    })
+}
+
+// Names functions after the globals (or fields of global tables) holding them
+fn global_names(lua: &Lua) -> Result<FxHashMap<*const c_void, StdString>> {
+    let globals = lua.globals();
+    let mut names = FxHashMap::default();
+    for pair in globals.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        let key = match key {
+            Value::String(key) => key.to_string_lossy().into_owned(),
+            _ => continue,
+        };
+        match value {
+            Value::Function(_) => {
+                names.entry(value.to_pointer()).or_insert(key);
+            }
+            Value::Table(t) if t != globals => {
+                for pair in t.pairs::<Value, Value>() {
+                    if let (Value::String(field), func @ Value::Function(_)) = pair? {
+                        let name = format!("{key}.{}", field.to_string_lossy());
+                        names.entry(func.to_pointer()).or_insert(name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
+pub(crate) fn api_docs(lua: &Lua) -> Result<Vec<ApiDoc>> {
+    let names = global_names(lua)?;
+    let mut docs = Vec::new();
+    for pair in docs_table(lua)?.pairs::<Value, Table>() {
+        let (func, entry) = pair?;
+        docs.push(entry_to_doc(entry, &names, func.to_pointer())?);
+    }
+    docs.sort_by(|a, b| match (&a.name, &b.name) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    Ok(docs)
+}
+
+// Returns the help text for a value passed to `help`
+pub(crate) fn help(lua: &Lua, value: Value) -> Result<StdString> {
+    let docs = docs_table(lua)?;
+    let names = global_names(lua)?;
+    let lines = match &value {
+        Value::Nil => (api_docs(lua)?.iter())
+            .map(|doc| doc.help())
+            .collect::<Vec<_>>(),
+        Value::Function(_) => match docs.raw_get::<_, Option<Table>>(value.clone())? {
+            Some(entry) => vec![entry_to_doc(entry, &names, value.to_pointer())?.help()],
+            None => Vec::new(),
+        },
+        Value::Table(t) => {
+            let mut lines = Vec::new();
+            for pair in t.clone().pairs::<Value, Value>() {
+                if let (Value::String(key), func @ Value::Function(_)) = pair? {
+                    let ptr = func.to_pointer();
+                    if let Some(entry) = docs.raw_get::<_, Option<Table>>(func)? {
+                        let mut doc = entry_to_doc(entry, &names, ptr)?;
+                        doc.name
+                            .get_or_insert_with(|| key.to_string_lossy().into_owned());
+                        lines.push((doc.name.clone(), doc.help()));
+                    }
+                }
+            }
+            lines.sort();
+            lines.into_iter().map(|(_, help)| help).collect()
+        }
+        Value::UserData(ud) => {
+            let type_name = ud.get_metatable()?.get::<StdString>("__name")?;
+            let prefix = format!("{type_name}:");
+            (api_docs(lua)?.iter())
+                .filter(|doc| matches!(&doc.name, Some(name) if name.starts_with(&prefix)))
+                .map(|doc| doc.help())
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    if lines.is_empty() {
+        return Ok("no documentation available".to_string());
+    }
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,261 @@
+use std::os::raw::c_void;
+
+use rustc_hash::FxHashMap;
+
+use crate::error::{Error, Result};
+use crate::function::{Function, FunctionKind};
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::{Nil, Value};
+
+#[cfg(not(feature = "luau"))]
+use crate::{
+    chunk::ChunkMode,
+    util::{check_stack, StackGuard},
+};
+
+/// Controls how [`Lua::duplicate`] copies values that cannot be moved between states as is.
+///
+/// [`Lua::duplicate`]: crate::Lua::duplicate
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct DuplicatePolicy {
+    /// If true, Lua functions are copied by loading their bytecode into the new state. Upvalues
+    /// are copied by value, so they are no longer shared between closures.
+    ///
+    /// Otherwise (and always on Luau, which cannot dump functions) Lua functions are treated as
+    /// unsupported values.
+    ///
+    /// Default: **true**
+    pub copy_functions: bool,
+
+    /// If true, unsupported values are skipped: table entries and upvalues holding them are left
+    /// empty. Otherwise [`Lua::duplicate`] returns an error.
+    ///
+    /// Unsupported values are userdata, threads and Rust/C functions that are not part of the
+    /// standard libraries.
+    ///
+    /// Default: **true**
+    ///
+    /// [`Lua::duplicate`]: crate::Lua::duplicate
+    pub skip_unsupported: bool,
+}
+
+impl Default for DuplicatePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DuplicatePolicy {
+    /// Returns a new instance of `DuplicatePolicy` with default parameters.
+    pub const fn new() -> Self {
+        DuplicatePolicy {
+            copy_functions: true,
+            skip_unsupported: true,
+        }
+    }
+
+    /// Sets [`copy_functions`] option.
+    ///
+    /// [`copy_functions`]: #structfield.copy_functions
+    #[must_use]
+    pub const fn copy_functions(mut self, enabled: bool) -> Self {
+        self.copy_functions = enabled;
+        self
+    }
+
+    /// Sets [`skip_unsupported`] option.
+    ///
+    /// [`skip_unsupported`]: #structfield.skip_unsupported
+    #[must_use]
+    pub const fn skip_unsupported(mut self, enabled: bool) -> Self {
+        self.skip_unsupported = enabled;
+        self
+    }
+}
+
+// Deep copies values from one Lua state into another, preserving identity of tables and functions
+struct Duplicator<'a, 'b> {
+    dst: &'b Lua,
+    policy: DuplicatePolicy,
+    tables: FxHashMap<*const c_void, Table<'b>>,
+    functions: FxHashMap<*const c_void, Function<'b>>,
+    // Built-in Rust/C functions and userdata (eg. `io.stdout`) of the new state
+    builtins: FxHashMap<*const c_void, Value<'b>>,
+    // Tables of the new state (eg. standard libraries) receiving contents of the source tables
+    targets: FxHashMap<*const c_void, Table<'b>>,
+    #[cfg(not(feature = "luau"))]
+    src: &'a Lua,
+    #[cfg(feature = "luau")]
+    _src: std::marker::PhantomData<&'a Lua>,
+}
+
+/// Copies globals of `src` into the (freshly created) `dst` state.
+pub(crate) fn copy_globals(src: &Lua, dst: &Lua, policy: DuplicatePolicy) -> Result<()> {
+    let mut dup = Duplicator {
+        dst,
+        policy,
+        tables: FxHashMap::default(),
+        functions: FxHashMap::default(),
+        builtins: FxHashMap::default(),
+        targets: FxHashMap::default(),
+        #[cfg(not(feature = "luau"))]
+        src,
+        #[cfg(feature = "luau")]
+        _src: std::marker::PhantomData,
+    };
+    let globals = src.globals();
+    // Standard libraries are nested at most two levels deep (eg. `package.loaded.string`)
+    dup.match_builtins(src, &globals, &dst.globals(), 2)?;
+    dup.copy_table(&globals)?;
+    Ok(())
+}
+
+impl<'a, 'b> Duplicator<'a, 'b> {
+    // Matches built-in tables, functions and userdata of the new state with the source ones,
+    // found under the same keys
+    fn match_builtins(
+        &mut self,
+        src: &'a Lua,
+        src_table: &Table<'a>,
+        dst_table: &Table<'b>,
+        depth: usize,
+    ) -> Result<()> {
+        self.targets
+            .insert(src_table.to_pointer(), dst_table.clone());
+        for pair in dst_table.clone().pairs::<Value, Value>() {
+            let (key, dst_value) = pair?;
+            let src_value = match key {
+                Value::String(s) => {
+                    src_table.raw_get::<_, Value>(src.create_string(s.as_bytes())?)?
+                }
+                Value::Integer(i) => src_table.raw_get::<_, Value>(i)?,
+                _ => continue,
+            };
+            match (src_value, dst_value) {
+                (Value::Function(f), g @ Value::Function(_))
+                    if f.info().kind == FunctionKind::C =>
+                {
+                    let ptr = Value::Function(f).to_pointer();
+                    self.builtins.entry(ptr).or_insert(g);
+                }
+                (ud @ Value::UserData(_), g @ Value::UserData(_)) => {
+                    self.builtins.entry(ud.to_pointer()).or_insert(g);
+                }
+                (Value::Table(t), Value::Table(u))
+                    if depth > 0 && !self.targets.contains_key(&t.to_pointer()) =>
+                {
+                    self.match_builtins(src, &t, &u, depth - 1)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_value(&mut self, value: Value<'a>) -> Result<Option<Value<'b>>> {
+        Ok(Some(match value {
+            Value::Nil => Nil,
+            Value::Boolean(b) => Value::Boolean(b),
+            Value::LightUserData(ud) => Value::LightUserData(ud),
+            Value::Integer(i) => Value::Integer(i),
+            Value::Number(n) => Value::Number(n),
+            #[cfg(feature = "luau")]
+            Value::Vector(v) => Value::Vector(v),
+            Value::String(s) => Value::String(self.dst.create_string(s.as_bytes())?),
+            Value::Table(t) => Value::Table(self.copy_table(&t)?),
+            Value::Function(f) => match self.copy_function(&f)? {
+                Some(f) => Value::Function(f),
+                None => return self.unsupported("function"),
+            },
+            value => match self.builtins.get(&value.to_pointer()) {
+                Some(builtin) => builtin.clone(),
+                None => return self.unsupported(value.type_name()),
+            },
+        }))
+    }
+
+    fn unsupported(&self, type_name: &str) -> Result<Option<Value<'b>>> {
+        if self.policy.skip_unsupported {
+            return Ok(None);
+        }
+        Err(Error::RuntimeError(format!(
+            "cannot copy {type_name} value into the duplicated Lua state"
+        )))
+    }
+
+    fn copy_table(&mut self, table: &Table<'a>) -> Result<Table<'b>> {
+        let ptr = table.to_pointer();
+        if let Some(copy) = self.tables.get(&ptr) {
+            return Ok(copy.clone());
+        }
+        let copy = match self.targets.remove(&ptr) {
+            Some(target) => target,
+            None => self.dst.create_table()?,
+        };
+        self.tables.insert(ptr, copy.clone());
+
+        for pair in table.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            if let (Some(key), Some(value)) = (self.copy_value(key)?, self.copy_value(value)?) {
+                copy.raw_set(key, value)?;
+            }
+        }
+        if let Some(mt) = table.get_metatable() {
+            let mt = self.copy_table(&mt)?;
+            copy.set_metatable(Some(mt));
+        }
+        Ok(copy)
+    }
+
+    fn copy_function(&mut self, func: &Function<'a>) -> Result<Option<Function<'b>>> {
+        let ptr = Value::Function(func.clone()).to_pointer();
+        if let Some(copy) = self.functions.get(&ptr) {
+            return Ok(Some(copy.clone()));
+        }
+        if let Some(Value::Function(builtin)) = self.builtins.get(&ptr) {
+            return Ok(Some(builtin.clone()));
+        }
+
+        #[cfg(not(feature = "luau"))]
+        if self.policy.copy_functions && func.info().kind != FunctionKind::C {
+            let copy = (self.dst.load(func.dump(false)))
+                .set_mode(ChunkMode::Binary)
+                .into_function()?;
+            // Register the copy before copying upvalues, as they can refer to the function itself
+            self.functions.insert(ptr, copy.clone());
+            self.copy_upvalues(func, &copy)?;
+            return Ok(Some(copy));
+        }
+        Ok(None)
+    }
+
+    #[cfg(not(feature = "luau"))]
+    fn copy_upvalues(&mut self, func: &Function<'a>, copy: &Function<'b>) -> Result<()> {
+        let (src_state, dst_state) = (self.src.state(), self.dst.state());
+        for i in 1.. {
+            let value = unsafe {
+                let _sg = StackGuard::new(src_state);
+                check_stack(src_state, 2)?;
+
+                self.src.push_ref(&func.0);
+                if ffi::lua_getupvalue(src_state, -1, i).is_null() {
+                    break;
+                }
+                self.src.pop_value()
+            };
+            // In Lua 5.2+ the `_ENV` upvalue refers to the source globals, which map to the new ones
+            let value = self.copy_value(value)?.unwrap_or(Nil);
+            unsafe {
+                let _sg = StackGuard::new(dst_state);
+                check_stack(dst_state, 2)?;
+
+                self.dst.push_ref(&copy.0);
+                self.dst.push_value(value)?;
+                ffi::lua_setupvalue(dst_state, -2, i);
+            }
+        }
+        Ok(())
+    }
+}
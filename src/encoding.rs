@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+
+/// Binary-to-text encoding supported by [`Lua::encode`], [`Lua::decode`] and the `encoding`
+/// library (see [`Lua::load_encoding_library`]).
+///
+/// The output is written into a buffer allocated once with the final size, so large blobs are
+/// not copied around during conversion.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Encoding, Result};
+/// # fn main() -> Result<()> {
+/// let mut out = Vec::new();
+/// Encoding::Base64.encode_into(b"hello", &mut out);
+/// assert_eq!(out, b"aGVsbG8=");
+///
+/// out.clear();
+/// Encoding::Hex.decode_into(b"6869", &mut out)?;
+/// assert_eq!(out, b"hi");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Lowercase hexadecimal encoding. Decoding accepts both lowercase and uppercase digits.
+    Hex,
+    /// Standard base64 encoding ([RFC 4648]) with padding. Decoding accepts missing padding.
+    ///
+    /// [RFC 4648]: https://datatracker.ietf.org/doc/html/rfc4648#section-4
+    Base64,
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+impl Encoding {
+    /// Returns the name of the encoding used by the `encoding` library, eg. `"base64"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "base64",
+        }
+    }
+
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"hex" => Some(Encoding::Hex),
+            b"base64" => Some(Encoding::Base64),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of `len` bytes once encoded.
+    pub const fn encoded_len(self, len: usize) -> usize {
+        match self {
+            Encoding::Hex => len * 2,
+            Encoding::Base64 => len.div_ceil(3) * 4,
+        }
+    }
+
+    /// Returns the maximum length of `data` once decoded (the exact length for valid data).
+    pub fn decoded_len(self, data: &[u8]) -> usize {
+        match self {
+            Encoding::Hex => data.len() / 2,
+            Encoding::Base64 => {
+                let len = data.len() - data.iter().rev().take(2).filter(|&&c| c == b'=').count();
+                len / 4 * 3 + (len % 4).saturating_sub(1)
+            }
+        }
+    }
+
+    /// Encodes `data`, appending the result to `out`.
+    pub fn encode_into(self, data: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        out.resize(start + self.encoded_len(data.len()), 0);
+        self.encode_to_slice(data, &mut out[start..]);
+    }
+
+    // Encodes `data` into `out`, which must be exactly `encoded_len` bytes long
+    pub(crate) fn encode_to_slice(self, data: &[u8], out: &mut [u8]) {
+        match self {
+            Encoding::Hex => {
+                for (&b, out) in data.iter().zip(out.chunks_exact_mut(2)) {
+                    out[0] = HEX_DIGITS[(b >> 4) as usize];
+                    out[1] = HEX_DIGITS[(b & 15) as usize];
+                }
+            }
+            Encoding::Base64 => {
+                let sextet = |n: u32, i: u32| BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize];
+                let mut chunks = data.chunks_exact(3);
+                let mut out = out.chunks_exact_mut(4);
+                for (chunk, out) in (&mut chunks).zip(&mut out) {
+                    let n = u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]);
+                    out.copy_from_slice(&[sextet(n, 0), sextet(n, 1), sextet(n, 2), sextet(n, 3)]);
+                }
+                let last = out.next();
+                match (chunks.remainder(), last) {
+                    (&[a], Some(out)) => {
+                        let n = u32::from_be_bytes([0, a, 0, 0]);
+                        out.copy_from_slice(&[sextet(n, 0), sextet(n, 1), b'=', b'=']);
+                    }
+                    (&[a, b], Some(out)) => {
+                        let n = u32::from_be_bytes([0, a, b, 0]);
+                        out.copy_from_slice(&[sextet(n, 0), sextet(n, 1), sextet(n, 2), b'=']);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Decodes `data`, appending the result to `out`.
+    ///
+    /// Returns an error if `data` is not valid for the encoding, `out` may contain partially
+    /// decoded data in that case.
+    pub fn decode_into(self, data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        let start = out.len();
+        out.resize(start + self.decoded_len(data), 0);
+        let res = self.decode_to_slice(data, &mut out[start..]);
+        out.truncate(start + *res.as_ref().unwrap_or(&0));
+        res.map(|_| ())
+    }
+
+    // Decodes `data` into `out`, which must be at least `decoded_len` bytes long.
+    // Returns the number of bytes written.
+    pub(crate) fn decode_to_slice(self, data: &[u8], out: &mut [u8]) -> Result<usize> {
+        match self {
+            Encoding::Hex => {
+                if data.len() % 2 == 1 {
+                    return Err(self.decode_error("odd number of digits"));
+                }
+                for (i, pair) in data.chunks_exact(2).enumerate() {
+                    let digit = |j: usize| {
+                        (pair[j] as char)
+                            .to_digit(16)
+                            .ok_or_else(|| self.invalid_byte(pair[j], i * 2 + j))
+                    };
+                    out[i] = ((digit(0)? << 4) | digit(1)?) as u8;
+                }
+                Ok(data.len() / 2)
+            }
+            Encoding::Base64 => {
+                let data = match data {
+                    [rest @ .., b'=', b'='] if rest.len() % 4 == 2 => rest,
+                    [rest @ .., b'='] if rest.len() % 4 == 3 => rest,
+                    data => data,
+                };
+                if data.len() % 4 == 1 {
+                    return Err(self.decode_error("invalid length"));
+                }
+                let mut len = 0;
+                for (i, chunk) in data.chunks(4).enumerate() {
+                    let mut n = 0;
+                    for (j, &c) in chunk.iter().enumerate() {
+                        let sextet = match c {
+                            b'A'..=b'Z' => c - b'A',
+                            b'a'..=b'z' => c - b'a' + 26,
+                            b'0'..=b'9' => c - b'0' + 52,
+                            b'+' => 62,
+                            b'/' => 63,
+                            _ => return Err(self.invalid_byte(c, i * 4 + j)),
+                        };
+                        n |= (sextet as u32) << (18 - 6 * j);
+                    }
+                    let bytes = &n.to_be_bytes()[1..chunk.len()];
+                    out[len..len + bytes.len()].copy_from_slice(bytes);
+                    len += bytes.len();
+                }
+                Ok(len)
+            }
+        }
+    }
+
+    fn invalid_byte(self, byte: u8, pos: usize) -> Error {
+        let msg = format!(
+            "invalid character {:?} at position {}",
+            byte as char,
+            pos + 1
+        );
+        self.decode_error(&msg)
+    }
+
+    fn decode_error(self, msg: &str) -> Error {
+        Error::RuntimeError(format!("invalid {} data: {msg}", self.name()))
+    }
+}
+
+pub(crate) fn encode<'lua>(
+    lua: &'lua Lua,
+    data: &[u8],
+    encoding: Encoding,
+) -> Result<String<'lua>> {
+    lua.create_string_with(encoding.encoded_len(data.len()), |out| {
+        encoding.encode_to_slice(data, out);
+        Ok(out.len())
+    })
+}
+
+pub(crate) fn decode<'lua>(
+    lua: &'lua Lua,
+    data: &[u8],
+    encoding: Encoding,
+) -> Result<String<'lua>> {
+    lua.create_string_with(encoding.decoded_len(data), |out| {
+        encoding.decode_to_slice(data, out)
+    })
+}
+
+// Creates a table with `encoding` library functions
+pub(crate) fn create_encoding_library<'lua>(lua: &'lua Lua) -> Result<Table<'lua>> {
+    let check_encoding = |name: String| {
+        Encoding::from_name(name.as_bytes()).ok_or_else(|| Error::BadArgument {
+            to: None,
+            pos: 2,
+            name: None,
+            cause: Arc::new(Error::RuntimeError(format!(
+                "unknown encoding '{}'",
+                name.to_string_lossy()
+            ))),
+        })
+    };
+
+    let lib = lua.create_table_with_capacity(0, 2)?;
+    lib.raw_set(
+        "encode",
+        lua.create_function(move |lua, (data, name): (String, String)| {
+            encode(lua, data.as_bytes(), check_encoding(name)?)
+        })?,
+    )?;
+    lib.raw_set(
+        "decode",
+        lua.create_function(move |lua, (data, name): (String, String)| {
+            decode(lua, data.as_bytes(), check_encoding(name)?)
+        })?,
+    )?;
+    Ok(lib)
+}
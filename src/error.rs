@@ -1,3 +1,4 @@
+use std::backtrace::{Backtrace, BacktraceStatus};
 use std::error::Error as StdError;
 use std::fmt;
 use std::io::Error as IoError;
@@ -7,6 +8,7 @@ use std::str::Utf8Error;
 use std::string::String as StdString;
 use std::sync::Arc;
 
+use crate::lua::Lua;
 use crate::private::Sealed;
 
 /// Error type returned by `mlua` methods.
@@ -34,6 +36,20 @@ pub enum Error {
     /// The Lua VM returns this error when the allocator does not return the requested memory, aka
     /// it is an out-of-memory error.
     MemoryError(StdString),
+    /// Memory limit of the Lua state was exceeded.
+    ///
+    /// Generated instead of [`MemoryError`] when an allocation fails because it would pass the
+    /// limit (stored in this variant) set by [`Lua::set_memory_limit`].
+    ///
+    /// [`MemoryError`]: Error::MemoryError
+    /// [`Lua::set_memory_limit`]: crate::Lua::set_memory_limit
+    MemoryLimitExceeded(usize),
+    /// Lua code executed more VM instructions than allowed.
+    ///
+    /// The limit is stored in this variant, see [`Chunk::exec_with_budget`].
+    ///
+    /// [`Chunk::exec_with_budget`]: crate::Chunk::exec_with_budget
+    InstructionLimitExceeded(u64),
     /// Lua garbage collector error, aka `LUA_ERRGCMM`.
     ///
     /// The Lua VM returns this error when there is an error running a `__gc` metamethod.
@@ -71,6 +87,12 @@ pub enum Error {
     StackError,
     /// Too many arguments to `Function::bind`
     BindError,
+    /// A Rust value is nested deeper than allowed when converting it to a Lua value.
+    ///
+    /// The limit is stored in this variant and can be set using [`Lua::set_max_conversion_depth`].
+    ///
+    /// [`Lua::set_max_conversion_depth`]: crate::Lua::set_max_conversion_depth
+    ConversionDepthExceeded(usize),
     /// Bad argument received from Lua (usually when calling a function).
     ///
     /// This error can help to identify the argument that caused the error
@@ -85,6 +107,19 @@ pub enum Error {
         /// Underlying error returned when converting argument to a Lua value.
         cause: Arc<Error>,
     },
+    /// A function was called with a wrong number of arguments.
+    ///
+    /// Returned by functions created using [`Lua::create_function_with_arity`].
+    ///
+    /// [`Lua::create_function_with_arity`]: crate::Lua::create_function_with_arity
+    WrongArity {
+        /// Number of arguments the function expects.
+        expected: usize,
+        /// Number of arguments the function was called with.
+        got: usize,
+        /// Name of the called function, if known.
+        name: Option<StdString>,
+    },
     /// A Rust value could not be converted to a Lua value.
     ToLuaConversionError {
         /// Name of the Rust type that could not be converted.
@@ -103,6 +138,20 @@ pub enum Error {
         /// A string containing more detailed error information.
         message: Option<StdString>,
     },
+    /// A [`FromLuaConversionError`] annotated with the value that could not be converted and its
+    /// location in the converted structure.
+    ///
+    /// Use [`Error::conversion_context`] to read the details regardless of this wrapper.
+    ///
+    /// [`FromLuaConversionError`]: Error::FromLuaConversionError
+    FromLuaConversionDetails {
+        /// A short (truncated and escaped) preview of the value, for primitive values only.
+        value: Option<StdString>,
+        /// Location of the value inside the converted structure (eg. `[2]["name"]`).
+        path: Option<StdString>,
+        /// Underlying conversion error.
+        cause: Arc<Error>,
+    },
     /// [`Thread::resume`] was called on an inactive coroutine.
     ///
     /// A coroutine is inactive if its main function has returned or if an error has occurred inside
@@ -148,10 +197,28 @@ pub enum Error {
     /// [`AnyUserData`]: crate::AnyUserData
     /// [`UserData`]: crate::UserData
     UserDataBorrowMutError,
+    /// An [`AnyUserData`] borrow would have to wait because the userdata is currently borrowed.
+    ///
+    /// Returned by [`AnyUserData::try_borrow`] and [`AnyUserData::try_borrow_mut`] so callers can
+    /// tell a busy userdata (eg. used by a pending async method) from other borrow failures and
+    /// try again later.
+    ///
+    /// [`AnyUserData`]: crate::AnyUserData
+    /// [`AnyUserData::try_borrow`]: crate::AnyUserData::try_borrow
+    /// [`AnyUserData::try_borrow_mut`]: crate::AnyUserData::try_borrow_mut
+    UserDataWouldBlock,
     /// A [`MetaMethod`] operation is restricted (typically for `__gc` or `__metatable`).
     ///
     /// [`MetaMethod`]: crate::MetaMethod
     MetaMethodRestricted(StdString),
+    /// Fields, methods or metamethods registered for a userdata type conflict with each other.
+    ///
+    /// This can happen when the same name is registered twice, a field and a method share the
+    /// same name, or a regular method is named like a metamethod. Conflicts are reported when the
+    /// userdata type is registered, if the type opted in with [`UserDataMethods::deny_overrides`].
+    ///
+    /// [`UserDataMethods::deny_overrides`]: crate::UserDataMethods::deny_overrides
+    RegistrationConflict(StdString),
     /// A [`MetaMethod`] (eg. `__index` or `__newindex`) has invalid type.
     ///
     /// [`MetaMethod`]: crate::MetaMethod
@@ -187,6 +254,16 @@ pub enum Error {
     #[cfg(feature = "serialize")]
     #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
     DeserializeError(StdString),
+    /// A value created by a different Lua state was passed to this one.
+    ///
+    /// Without the `strict-no-panic` feature this is a panic.
+    #[cfg(feature = "strict-no-panic")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "strict-no-panic")))]
+    StateMismatch,
+    /// An internal limitation or inconsistency that would otherwise cause a panic.
+    #[cfg(feature = "strict-no-panic")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "strict-no-panic")))]
+    Internal(StdString),
     /// A custom error.
     ///
     /// This can be used for returning user-defined errors from callbacks.
@@ -216,6 +293,12 @@ impl fmt::Display for Error {
             Error::MemoryError(ref msg) => {
                 write!(fmt, "memory error: {msg}")
             }
+            Error::MemoryLimitExceeded(limit) => {
+                write!(fmt, "memory limit ({limit} bytes) exceeded")
+            }
+            Error::InstructionLimitExceeded(limit) => {
+                write!(fmt, "instruction limit ({limit}) exceeded")
+            }
             #[cfg(any(feature = "lua53", feature = "lua52"))]
             Error::GarbageCollectorError(ref msg) => {
                 write!(fmt, "garbage collector error: {msg}")
@@ -242,6 +325,9 @@ impl fmt::Display for Error {
                 fmt,
                 "too many arguments to Function::bind"
             ),
+            Error::ConversionDepthExceeded(depth) => {
+                write!(fmt, "maximum conversion depth ({depth}) exceeded")
+            }
             Error::BadArgument { ref to, pos, ref name, ref cause } => {
                 if let Some(name) = name {
                     write!(fmt, "bad argument `{name}`")?;
@@ -253,6 +339,13 @@ impl fmt::Display for Error {
                 }
                 write!(fmt, ": {cause}")
             },
+            Error::WrongArity { expected, got, ref name } => {
+                write!(fmt, "wrong number of arguments")?;
+                if let Some(name) = name {
+                    write!(fmt, " to `{name}`")?;
+                }
+                write!(fmt, " (expected {expected}, got {got})")
+            }
             Error::ToLuaConversionError { from, to, ref message } => {
                 write!(fmt, "error converting {from} to Lua {to}")?;
                 match *message {
@@ -267,12 +360,33 @@ impl fmt::Display for Error {
                     Some(ref message) => write!(fmt, " ({message})"),
                 }
             }
+            Error::FromLuaConversionDetails { ref value, ref path, ref cause } => {
+                let Error::FromLuaConversionError { from, to, ref message } = **cause else {
+                    return write!(fmt, "{cause}");
+                };
+                write!(fmt, "error converting Lua {from}")?;
+                if let Some(value) = value {
+                    write!(fmt, " {value}")?;
+                }
+                write!(fmt, " to {to}")?;
+                if let Some(path) = path {
+                    write!(fmt, " at {path}")?;
+                }
+                match *message {
+                    None => Ok(()),
+                    Some(ref message) => write!(fmt, " ({message})"),
+                }
+            }
             Error::CoroutineInactive => write!(fmt, "cannot resume inactive coroutine"),
             Error::UserDataTypeMismatch => write!(fmt, "userdata is not expected type"),
             Error::UserDataDestructed => write!(fmt, "userdata has been destructed"),
             Error::UserDataBorrowError => write!(fmt, "error borrowing userdata"),
             Error::UserDataBorrowMutError => write!(fmt, "error mutably borrowing userdata"),
+            Error::UserDataWouldBlock => write!(fmt, "userdata is currently borrowed"),
             Error::MetaMethodRestricted(ref method) => write!(fmt, "metamethod {method} is restricted"),
+            Error::RegistrationConflict(ref message) => {
+                write!(fmt, "userdata registration conflict: {message}")
+            }
             Error::MetaMethodTypeError { ref method, type_name, ref message } => {
                 write!(fmt, "metamethod {method} has unsupported type {type_name}")?;
                 match *message {
@@ -317,6 +431,12 @@ impl fmt::Display for Error {
             Error::DeserializeError(ref err) => {
                 write!(fmt, "deserialize error: {err}")
             },
+            #[cfg(feature = "strict-no-panic")]
+            Error::StateMismatch => {
+                write!(fmt, "value created from a different main Lua state")
+            }
+            #[cfg(feature = "strict-no-panic")]
+            Error::Internal(ref msg) => write!(fmt, "internal error: {msg}"),
             Error::ExternalError(ref err) => write!(fmt, "{err}"),
             Error::WithContext { ref context, ref cause } => {
                 writeln!(fmt, "{context}")?;
@@ -365,6 +485,45 @@ impl Error {
         }
     }
 
+    /// Returns details about a failed Lua to Rust conversion, if this error was caused by one.
+    ///
+    /// Looks through [`BadArgument`], [`CallbackError`] and [`WithContext`] wrappers to find the
+    /// underlying [`FromLuaConversionError`].
+    ///
+    /// [`BadArgument`]: Error::BadArgument
+    /// [`CallbackError`]: Error::CallbackError
+    /// [`WithContext`]: Error::WithContext
+    /// [`FromLuaConversionError`]: Error::FromLuaConversionError
+    pub fn conversion_context(&self) -> Option<ConversionContext> {
+        match self {
+            Error::FromLuaConversionError { from, to, message } => Some(ConversionContext {
+                from,
+                to,
+                message: message.as_deref(),
+                value: None,
+                path: None,
+                argument: None,
+            }),
+            Error::FromLuaConversionDetails { value, path, cause } => {
+                cause.conversion_context().map(|ctx| ConversionContext {
+                    value: value.as_deref(),
+                    path: path.as_deref(),
+                    ..ctx
+                })
+            }
+            Error::BadArgument {
+                pos, name, cause, ..
+            } => cause.conversion_context().map(|ctx| ConversionContext {
+                argument: Some((*pos, name.as_deref())),
+                ..ctx
+            }),
+            Error::CallbackError { cause, .. } | Error::WithContext { cause, .. } => {
+                cause.conversion_context()
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn bad_self_argument(to: &str, cause: Error) -> Self {
         Error::BadArgument {
             to: Some(to.to_string()),
@@ -385,6 +544,71 @@ impl Error {
             message: message.into().map(|s| s.into()),
         }
     }
+
+    /// Attaches a preview of the value that failed to convert to a [`FromLuaConversionError`].
+    ///
+    /// [`FromLuaConversionError`]: Error::FromLuaConversionError
+    pub(crate) fn with_value_preview(self, value: Option<StdString>) -> Self {
+        match (self, value) {
+            (err @ Error::FromLuaConversionError { .. }, Some(value)) => {
+                Error::FromLuaConversionDetails {
+                    value: Some(value),
+                    path: None,
+                    cause: Arc::new(err),
+                }
+            }
+            (err, _) => err,
+        }
+    }
+
+    /// Prepends `segment` to the path of a [`FromLuaConversionError`].
+    ///
+    /// [`FromLuaConversionError`]: Error::FromLuaConversionError
+    pub(crate) fn with_conversion_path(self, segment: impl FnOnce() -> StdString) -> Self {
+        match self {
+            Error::FromLuaConversionDetails { value, path, cause } => {
+                let mut segment = segment();
+                segment.push_str(path.as_deref().unwrap_or_default());
+                Error::FromLuaConversionDetails {
+                    value,
+                    path: Some(segment),
+                    cause,
+                }
+            }
+            err @ Error::FromLuaConversionError { .. } => Error::FromLuaConversionDetails {
+                value: None,
+                path: Some(segment()),
+                cause: Arc::new(err),
+            },
+            err => err,
+        }
+    }
+
+    // Used by `#[derive(FromLua)]` to add the name of the struct field that failed to convert
+    #[doc(hidden)]
+    pub fn __with_field_path(self, field: &str) -> Self {
+        self.with_conversion_path(|| format!("[\"{}\"]", field.escape_debug()))
+    }
+}
+
+/// Details about a failed Lua to Rust conversion.
+///
+/// Returned by [`Error::conversion_context`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConversionContext<'a> {
+    /// Name of the Lua type that could not be converted.
+    pub from: &'static str,
+    /// Name of the Rust type that could not be created.
+    pub to: &'static str,
+    /// More detailed error information.
+    pub message: Option<&'a str>,
+    /// A short (truncated and escaped) preview of the value that could not be converted.
+    pub value: Option<&'a str>,
+    /// Location of the value inside the converted structure (eg. `[2]["name"]`).
+    pub path: Option<&'a str>,
+    /// Position and name (if known) of the function argument that could not be converted.
+    pub argument: Option<(usize, Option<&'a str>)>,
 }
 
 pub trait ExternalError {
@@ -454,6 +678,35 @@ impl<T> ErrorContext for StdResult<T, Error> {
     }
 }
 
+/// Provides the `with_lua_context` method for `Result<T, Error>`.
+pub trait LuaResultExt: Sealed {
+    /// Wraps the error value with additional context and a traceback of the current Lua stack.
+    ///
+    /// If Rust backtraces are enabled (eg. using the `RUST_BACKTRACE` environment variable), a
+    /// Rust backtrace is captured as well. Both are rendered after the context message when the
+    /// error is displayed.
+    ///
+    /// The traceback is captured only once an error does occur.
+    fn with_lua_context<C: fmt::Display>(self, lua: &Lua, context: C) -> Self;
+}
+
+impl<T> LuaResultExt for StdResult<T, Error> {
+    fn with_lua_context<C: fmt::Display>(self, lua: &Lua, context: C) -> Self {
+        self.map_err(|err| {
+            let mut context = context.to_string();
+            if let Some(traceback) = lua.traceback() {
+                context.push('\n');
+                context.push_str(&traceback);
+            }
+            let backtrace = Backtrace::capture();
+            if backtrace.status() == BacktraceStatus::Captured {
+                context.push_str(&format!("\nRust backtrace:\n{backtrace}"));
+            }
+            err.context(context)
+        })
+    }
+}
+
 impl From<AddrParseError> for Error {
     fn from(err: AddrParseError) -> Self {
         Error::external(err)
@@ -472,6 +725,338 @@ impl From<Utf8Error> for Error {
     }
 }
 
+/// Serializable representation of an [`Error`], for sending errors across process boundaries.
+///
+/// Created by [`Error::to_portable`] and converted back using [`Error::from_portable`]. Unlike
+/// [`Error`] it does not hold any external error objects, only their messages, so it can be
+/// shipped (eg. from worker processes running scripts to an aggregator) using any serde format.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Error, Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let err = lua.load("error('boom')").exec().unwrap_err();
+///
+/// let portable = err.to_portable();
+/// assert_eq!(portable.kind, "RuntimeError");
+///
+/// let err = Error::from_portable(portable);
+/// assert!(matches!(err, Error::RuntimeError(msg) if msg.contains("boom")));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "serialize")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct PortableError {
+    /// Name of the [`Error`] variant, eg. `"RuntimeError"`.
+    pub kind: StdString,
+    /// The error message, not including the underlying error.
+    pub message: StdString,
+    /// Lua stack traceback frames (of a [`CallbackError`]), most recent first.
+    ///
+    /// [`CallbackError`]: Error::CallbackError
+    pub traceback: Vec<StdString>,
+    /// Underlying error.
+    pub cause: Option<Box<PortableError>>,
+    /// The exceeded limit of [`MemoryLimitExceeded`], [`InstructionLimitExceeded`] and
+    /// [`ConversionDepthExceeded`] errors.
+    ///
+    /// [`MemoryLimitExceeded`]: Error::MemoryLimitExceeded
+    /// [`InstructionLimitExceeded`]: Error::InstructionLimitExceeded
+    /// [`ConversionDepthExceeded`]: Error::ConversionDepthExceeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// Number of arguments expected by the function of a [`WrongArity`] error.
+    ///
+    /// [`WrongArity`]: Error::WrongArity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<usize>,
+    /// Number of arguments the function of a [`WrongArity`] error was called with.
+    ///
+    /// [`WrongArity`]: Error::WrongArity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub got: Option<usize>,
+    /// Name of the function of a [`WrongArity`] error, if known.
+    ///
+    /// [`WrongArity`]: Error::WrongArity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<StdString>,
+}
+
+#[cfg(feature = "serialize")]
+impl PortableError {
+    fn new(kind: &str, message: impl Into<StdString>) -> Self {
+        PortableError {
+            kind: kind.to_string(),
+            message: message.into(),
+            traceback: Vec::new(),
+            cause: None,
+            limit: None,
+            expected: None,
+            got: None,
+            function: None,
+        }
+    }
+
+    fn with_limit(mut self, limit: impl Into<u64>) -> Self {
+        self.limit = Some(limit.into());
+        self
+    }
+
+    fn with_cause(mut self, cause: PortableError) -> Self {
+        self.cause = Some(Box::new(cause));
+        self
+    }
+
+    fn from_std_error(err: &(dyn StdError + 'static)) -> Self {
+        if let Some(err) = err.downcast_ref::<PortableError>() {
+            return err.clone();
+        }
+        let portable = PortableError::new("ExternalError", err.to_string());
+        match err.source() {
+            Some(source) => portable.with_cause(Self::from_std_error(source)),
+            None => portable,
+        }
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl fmt::Display for PortableError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.cause {
+            Some(ref cause) if self.message.is_empty() => write!(fmt, "{cause}")?,
+            Some(ref cause) => write!(fmt, "{}: {cause}", self.message)?,
+            None => write!(fmt, "{}", self.message)?,
+        }
+        if !self.traceback.is_empty() {
+            write!(fmt, "\nstack traceback:")?;
+            for frame in &self.traceback {
+                write!(fmt, "\n\t{frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl StdError for PortableError {}
+
+#[cfg(feature = "serialize")]
+impl Error {
+    /// Converts this error into a [`PortableError`] that can be serialized.
+    ///
+    /// The variant name, message, traceback frames and the chain of underlying errors (including
+    /// sources of external errors) are kept.
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn to_portable(&self) -> PortableError {
+        let kind = self.kind_name();
+        match *self {
+            Error::SyntaxError { ref message, .. } => PortableError::new(kind, message),
+            Error::RuntimeError(ref msg)
+            | Error::MemoryError(ref msg)
+            | Error::SafetyError(ref msg)
+            | Error::MetaMethodRestricted(ref msg)
+            | Error::RegistrationConflict(ref msg)
+            | Error::SerializeError(ref msg)
+            | Error::DeserializeError(ref msg) => PortableError::new(kind, msg),
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            Error::GarbageCollectorError(ref msg) => PortableError::new(kind, msg),
+            Error::BadArgument {
+                ref to,
+                pos,
+                ref name,
+                ref cause,
+            } => {
+                let mut message = match name {
+                    Some(name) => format!("bad argument `{name}`"),
+                    None => format!("bad argument #{pos}"),
+                };
+                if let Some(to) = to {
+                    message.push_str(&format!(" to `{to}`"));
+                }
+                PortableError::new(kind, message).with_cause(cause.to_portable())
+            }
+            Error::CallbackError {
+                ref traceback,
+                ref cause,
+            } => {
+                let mut portable = PortableError::new(kind, "").with_cause(cause.to_portable());
+                portable.traceback = (traceback.lines())
+                    .skip_while(|line| line.trim() == "stack traceback:")
+                    .map(|line| line.trim().to_string())
+                    .collect();
+                portable
+            }
+            Error::ExternalError(ref err) => PortableError::from_std_error(err.as_ref()),
+            Error::WithContext {
+                ref context,
+                ref cause,
+            } => PortableError::new(kind, context).with_cause(cause.to_portable()),
+            Error::MemoryLimitExceeded(limit) | Error::ConversionDepthExceeded(limit) => {
+                PortableError::new(kind, self.to_string()).with_limit(limit as u64)
+            }
+            Error::InstructionLimitExceeded(limit) => {
+                PortableError::new(kind, self.to_string()).with_limit(limit)
+            }
+            Error::WrongArity {
+                expected,
+                got,
+                ref name,
+            } => PortableError {
+                expected: Some(expected),
+                got: Some(got),
+                function: name.clone(),
+                ..PortableError::new(kind, self.to_string())
+            },
+            _ => PortableError::new(kind, self.to_string()),
+        }
+    }
+
+    /// Converts a [`PortableError`] back into an error.
+    ///
+    /// Variants holding only messages, tracebacks, limits, arities or underlying errors are
+    /// restored as is (syntax errors lose the `incomplete_input` flag). Other errors are returned as
+    /// [`ExternalError`] wrapping the [`PortableError`], which can be inspected using
+    /// [`Error::downcast_ref`].
+    ///
+    /// [`ExternalError`]: Error::ExternalError
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    pub fn from_portable(portable: PortableError) -> Self {
+        let PortableError {
+            kind,
+            message,
+            traceback,
+            cause,
+            limit,
+            expected,
+            got,
+            function,
+        } = portable;
+        let cause = cause.map(|cause| Arc::new(Error::from_portable(*cause)));
+        let usize_limit = limit.and_then(|limit| usize::try_from(limit).ok());
+        match (kind.as_str(), cause) {
+            ("SyntaxError", None) => Error::SyntaxError {
+                message,
+                incomplete_input: false,
+            },
+            ("RuntimeError", None) => Error::RuntimeError(message),
+            ("MemoryError", None) => Error::MemoryError(message),
+            ("SafetyError", None) => Error::SafetyError(message),
+            ("MetaMethodRestricted", None) => Error::MetaMethodRestricted(message),
+            ("RegistrationConflict", None) => Error::RegistrationConflict(message),
+            ("SerializeError", None) => Error::SerializeError(message),
+            ("DeserializeError", None) => Error::DeserializeError(message),
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            ("GarbageCollectorError", None) => Error::GarbageCollectorError(message),
+            ("MemoryLimitNotAvailable", None) => Error::MemoryLimitNotAvailable,
+            ("MainThreadNotAvailable", None) => Error::MainThreadNotAvailable,
+            ("RecursiveMutCallback", None) => Error::RecursiveMutCallback,
+            ("CallbackDestructed", None) => Error::CallbackDestructed,
+            ("StackError", None) => Error::StackError,
+            ("BindError", None) => Error::BindError,
+            ("CoroutineInactive", None) => Error::CoroutineInactive,
+            ("UserDataTypeMismatch", None) => Error::UserDataTypeMismatch,
+            ("UserDataDestructed", None) => Error::UserDataDestructed,
+            ("UserDataBorrowError", None) => Error::UserDataBorrowError,
+            ("UserDataBorrowMutError", None) => Error::UserDataBorrowMutError,
+            ("MismatchedRegistryKey", None) => Error::MismatchedRegistryKey,
+            ("PreviouslyResumedPanic", None) => Error::PreviouslyResumedPanic,
+            ("MemoryLimitExceeded", None) if usize_limit.is_some() => {
+                Error::MemoryLimitExceeded(usize_limit.unwrap())
+            }
+            ("InstructionLimitExceeded", None) if limit.is_some() => {
+                Error::InstructionLimitExceeded(limit.unwrap())
+            }
+            ("ConversionDepthExceeded", None) if usize_limit.is_some() => {
+                Error::ConversionDepthExceeded(usize_limit.unwrap())
+            }
+            ("WrongArity", None) if expected.is_some() && got.is_some() => Error::WrongArity {
+                expected: expected.unwrap(),
+                got: got.unwrap(),
+                name: function,
+            },
+            ("CallbackError", Some(cause)) => {
+                let mut traceback_str = StdString::from("stack traceback:");
+                for frame in &traceback {
+                    traceback_str.push_str("\n\t");
+                    traceback_str.push_str(frame);
+                }
+                Error::CallbackError {
+                    traceback: traceback_str,
+                    cause,
+                }
+            }
+            ("WithContext", Some(cause)) => Error::WithContext {
+                context: message,
+                cause,
+            },
+            (_, cause) => {
+                let cause = cause.map(|cause| Box::new(cause.to_portable()));
+                Error::external(PortableError {
+                    kind,
+                    message,
+                    traceback,
+                    cause,
+                    limit,
+                    expected,
+                    got,
+                    function,
+                })
+            }
+        }
+    }
+
+    // Returns name of the error variant
+    fn kind_name(&self) -> &'static str {
+        match *self {
+            Error::SyntaxError { .. } => "SyntaxError",
+            Error::RuntimeError(_) => "RuntimeError",
+            Error::MemoryError(_) => "MemoryError",
+            Error::MemoryLimitExceeded(_) => "MemoryLimitExceeded",
+            Error::InstructionLimitExceeded(_) => "InstructionLimitExceeded",
+            #[cfg(any(feature = "lua53", feature = "lua52"))]
+            Error::GarbageCollectorError(_) => "GarbageCollectorError",
+            Error::SafetyError(_) => "SafetyError",
+            Error::MemoryLimitNotAvailable => "MemoryLimitNotAvailable",
+            Error::MainThreadNotAvailable => "MainThreadNotAvailable",
+            Error::RecursiveMutCallback => "RecursiveMutCallback",
+            Error::CallbackDestructed => "CallbackDestructed",
+            Error::StackError => "StackError",
+            Error::BindError => "BindError",
+            Error::ConversionDepthExceeded(_) => "ConversionDepthExceeded",
+            Error::BadArgument { .. } => "BadArgument",
+            Error::WrongArity { .. } => "WrongArity",
+            Error::ToLuaConversionError { .. } => "ToLuaConversionError",
+            Error::FromLuaConversionError { .. } => "FromLuaConversionError",
+            Error::FromLuaConversionDetails { .. } => "FromLuaConversionDetails",
+            Error::CoroutineInactive => "CoroutineInactive",
+            Error::UserDataTypeMismatch => "UserDataTypeMismatch",
+            Error::UserDataDestructed => "UserDataDestructed",
+            Error::UserDataBorrowError => "UserDataBorrowError",
+            Error::UserDataBorrowMutError => "UserDataBorrowMutError",
+            Error::UserDataWouldBlock => "UserDataWouldBlock",
+            Error::MetaMethodRestricted(_) => "MetaMethodRestricted",
+            Error::RegistrationConflict(_) => "RegistrationConflict",
+            Error::MetaMethodTypeError { .. } => "MetaMethodTypeError",
+            Error::MismatchedRegistryKey => "MismatchedRegistryKey",
+            Error::CallbackError { .. } => "CallbackError",
+            Error::PreviouslyResumedPanic => "PreviouslyResumedPanic",
+            Error::SerializeError(_) => "SerializeError",
+            Error::DeserializeError(_) => "DeserializeError",
+            #[cfg(feature = "strict-no-panic")]
+            Error::StateMismatch => "StateMismatch",
+            #[cfg(feature = "strict-no-panic")]
+            Error::Internal(_) => "Internal",
+            Error::ExternalError(_) => "ExternalError",
+            Error::WithContext { .. } => "WithContext",
+        }
+    }
+}
+
 #[cfg(feature = "serialize")]
 impl serde::ser::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
@@ -0,0 +1,394 @@
+use std::fmt;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::{self, Future};
+use futures_util::task::{self, ArcWake, AtomicWaker, Context, Poll, Waker};
+use rustc_hash::FxHashMap;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::thread::Thread;
+use crate::types::RegistryKey;
+use crate::value::MultiValue;
+
+/// Identifier of an async task.
+///
+/// Every [`AsyncThread`] (and task spawned using [`Lua::spawn_async`]) gets a unique id.
+/// Async threads polled while another one is running belong to the same logical task and share
+/// its id, see [`Lua::current_task_id`].
+///
+/// [`AsyncThread`]: crate::AsyncThread
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(pub(crate) u64);
+
+impl TaskId {
+    /// Returns the numeric value of the identifier.
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task {}", self.0)
+    }
+}
+
+/// Bridges wakeups of async Lua tasks into a host executor or scheduler.
+///
+/// Tasks spawned using [`Lua::spawn_async`] are polled by [`Lua::poll_pending_async`]. When a
+/// future awaited by a task becomes ready, the task is marked as pending and the integration is
+/// notified (possibly from another thread), so that the host can schedule the next poll.
+///
+/// The integration is set using [`Lua::set_executor_integration`]. Without an integration,
+/// the host is expected to call [`Lua::poll_pending_async`] periodically (eg. once per tick).
+///
+/// Closures `Fn(TaskId)` implement this trait. [`ExecutorSignal`] works with any executor, and
+/// `TokioIntegration` and `AsyncStdIntegration` (with the `tokio` and `async-std` features)
+/// also drive the tasks.
+pub trait LuaExecutorIntegration: Send + Sync + 'static {
+    /// Called when a task is woken and must be polled again.
+    fn schedule(&self, task: TaskId);
+}
+
+impl<F> LuaExecutorIntegration for F
+where
+    F: Fn(TaskId) + Send + Sync + 'static,
+{
+    fn schedule(&self, task: TaskId) {
+        self(task)
+    }
+}
+
+/// Executor integration waking a future when any task must be polled.
+///
+/// Works with any executor (eg. [tokio] or [async-std]): the host awaits [`ExecutorSignal::wait`]
+/// in a local task and calls [`Lua::poll_pending_async`] each time it completes.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::{Duration, Instant};
+/// use mlua::{ExecutorSignal, Lua, Result};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     let signal = Arc::new(ExecutorSignal::new());
+///     lua.set_executor_integration(signal.clone());
+///
+///     let sleep = lua.create_async_function(|_, ms: u64| async move {
+///         tokio::time::sleep(Duration::from_millis(ms)).await;
+///         Ok(())
+///     })?;
+///     lua.globals().set("sleep", sleep)?;
+///     let func = lua.load("sleep(10) return 'done'").into_function()?;
+///     lua.spawn_async(func, ())?;
+///
+///     let start = Instant::now();
+///     while lua.pending_async_tasks() > 0 {
+///         signal.wait().await;
+///         for (_, result) in lua.poll_pending_async(start.elapsed()) {
+///             assert_eq!(result?.pop_front().unwrap().to_string()?, "done");
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+///
+/// [tokio]: https://docs.rs/tokio
+/// [async-std]: https://docs.rs/async-std
+#[derive(Debug)]
+pub struct ExecutorSignal {
+    waker: AtomicWaker,
+    notified: AtomicBool,
+}
+
+impl Default for ExecutorSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutorSignal {
+    /// Creates a new signal.
+    ///
+    /// The signal starts notified, so that newly spawned tasks are polled.
+    pub fn new() -> Self {
+        ExecutorSignal {
+            waker: AtomicWaker::new(),
+            notified: AtomicBool::new(true),
+        }
+    }
+
+    /// Waits until a task must be polled.
+    pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+        future::poll_fn(move |cx| {
+            self.waker.register(cx.waker());
+            match self.notified.swap(false, Ordering::AcqRel) {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        })
+    }
+}
+
+impl LuaExecutorIntegration for ExecutorSignal {
+    fn schedule(&self, _task: TaskId) {
+        self.notified.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+/// Executor integration for [tokio].
+///
+/// Tasks are scheduled through a [`tokio::sync::Notify`] and [`Lua::async_now`] follows the
+/// tokio clock, so [paused time] advances it in tests. Lua values are not `Send`, so
+/// [`TokioIntegration::run`] must be awaited on a current-thread runtime or in a [`LocalSet`].
+///
+/// Requires `feature = "tokio"`
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use mlua::{Lua, Result, TokioIntegration};
+///
+/// #[tokio::main(flavor = "current_thread")]
+/// async fn main() -> Result<()> {
+///     let lua = Lua::new();
+///     let integration = Arc::new(TokioIntegration::new());
+///     lua.set_executor_integration(integration.clone());
+///
+///     let sleep = lua.create_async_function(|_, ms: u64| async move {
+///         tokio::time::sleep(Duration::from_millis(ms)).await;
+///         Ok(())
+///     })?;
+///     lua.globals().set("sleep", sleep)?;
+///     let func = lua.load("sleep(10) return 'done'").into_function()?;
+///     lua.spawn_async(func, ())?;
+///
+///     for (_, result) in integration.run(&lua).await {
+///         assert_eq!(result?.pop_front().unwrap().to_string()?, "done");
+///     }
+///     Ok(())
+/// }
+/// ```
+///
+/// [tokio]: https://docs.rs/tokio
+/// [paused time]: https://docs.rs/tokio/latest/tokio/time/fn.pause.html
+/// [`LocalSet`]: https://docs.rs/tokio/latest/tokio/task/struct.LocalSet.html
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[derive(Debug)]
+pub struct TokioIntegration {
+    notify: tokio::sync::Notify,
+    start: tokio::time::Instant,
+}
+
+#[cfg(feature = "tokio")]
+impl Default for TokioIntegration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl TokioIntegration {
+    /// Creates a new integration.
+    pub fn new() -> Self {
+        TokioIntegration {
+            notify: tokio::sync::Notify::new(),
+            start: tokio::time::Instant::now(),
+        }
+    }
+
+    /// Polls the async tasks of `lua` whenever one of them is woken, until all of them finish.
+    ///
+    /// Returns the results of the finished tasks in the order they finished.
+    pub async fn run<'lua>(&self, lua: &'lua Lua) -> Vec<(TaskId, Result<MultiValue<'lua>>)> {
+        let mut finished = lua.poll_pending_async(self.start.elapsed());
+        while lua.pending_async_tasks() > 0 {
+            self.notify.notified().await;
+            finished.extend(lua.poll_pending_async(self.start.elapsed()));
+        }
+        finished
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl LuaExecutorIntegration for TokioIntegration {
+    fn schedule(&self, _task: TaskId) {
+        // Stores a permit if `run` is not waiting right now
+        self.notify.notify_one();
+    }
+}
+
+/// Executor integration for [async-std].
+///
+/// Tasks are scheduled through an [`async_std::channel`]. Lua values are not `Send`, so
+/// [`AsyncStdIntegration::run`] must be awaited in a local task (eg. using
+/// [`async_std::task::block_on`] or [`async_std::task::spawn_local`]).
+///
+/// Requires `feature = "async-std"`
+///
+/// [async-std]: https://docs.rs/async-std
+#[cfg(feature = "async-std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-std")))]
+#[derive(Debug)]
+pub struct AsyncStdIntegration {
+    sender: async_std::channel::Sender<()>,
+    receiver: async_std::channel::Receiver<()>,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "async-std")]
+impl Default for AsyncStdIntegration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl AsyncStdIntegration {
+    /// Creates a new integration.
+    pub fn new() -> Self {
+        let (sender, receiver) = async_std::channel::bounded(1);
+        AsyncStdIntegration {
+            sender,
+            receiver,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    /// Polls the async tasks of `lua` whenever one of them is woken, until all of them finish.
+    ///
+    /// Returns the results of the finished tasks in the order they finished.
+    pub async fn run<'lua>(&self, lua: &'lua Lua) -> Vec<(TaskId, Result<MultiValue<'lua>>)> {
+        let mut finished = lua.poll_pending_async(self.start.elapsed());
+        while lua.pending_async_tasks() > 0 {
+            // The channel is never closed, as both ends are owned by `self`
+            let _ = self.receiver.recv().await;
+            finished.extend(lua.poll_pending_async(self.start.elapsed()));
+        }
+        finished
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl LuaExecutorIntegration for AsyncStdIntegration {
+    fn schedule(&self, _task: TaskId) {
+        // A full channel already has a pending notification
+        let _ = self.sender.try_send(());
+    }
+}
+
+struct TaskWaker {
+    id: TaskId,
+    woken: AtomicBool,
+    integration: Option<Arc<dyn LuaExecutorIntegration>>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if !arc_self.woken.swap(true, Ordering::AcqRel) {
+            if let Some(integration) = &arc_self.integration {
+                integration.schedule(arc_self.id);
+            }
+        }
+    }
+}
+
+struct Task {
+    thread: RegistryKey,
+    waker: Arc<TaskWaker>,
+}
+
+// Async tasks spawned in a Lua instance
+#[derive(Default)]
+pub(crate) struct AsyncTasks {
+    tasks: FxHashMap<TaskId, Task>,
+    next_id: u64,
+    integration: Option<Arc<dyn LuaExecutorIntegration>>,
+    now: Option<Duration>,
+}
+
+impl AsyncTasks {
+    pub(crate) fn set_integration(&mut self, integration: Option<Arc<dyn LuaExecutorIntegration>>) {
+        self.integration = integration;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub(crate) fn now(&self) -> Option<Duration> {
+        self.now
+    }
+
+    pub(crate) fn set_now(&mut self, now: Option<Duration>) -> Option<Duration> {
+        mem::replace(&mut self.now, now)
+    }
+
+    // Allocates a new unique task id
+    pub(crate) fn next_id(&mut self) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    // Adds a task running the given thread, it will be polled on the next poll
+    pub(crate) fn insert(&mut self, thread: RegistryKey) -> TaskId {
+        let id = self.next_id();
+        let waker = Arc::new(TaskWaker {
+            id,
+            woken: AtomicBool::new(true),
+            integration: self.integration.clone(),
+        });
+        self.tasks.insert(id, Task { thread, waker });
+        if let Some(integration) = &self.integration {
+            integration.schedule(id);
+        }
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: TaskId) -> Option<RegistryKey> {
+        self.tasks.remove(&id).map(|task| task.thread)
+    }
+
+    // Returns the woken tasks (clearing their flags) in spawn order
+    pub(crate) fn take_woken(&self) -> Vec<TaskId> {
+        let mut woken = (self.tasks.iter())
+            .filter(|(_, task)| task.waker.woken.swap(false, Ordering::AcqRel))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        woken.sort_unstable();
+        woken
+    }
+
+    pub(crate) fn task<'lua>(&self, lua: &'lua Lua, id: TaskId) -> Option<(Thread<'lua>, Waker)> {
+        let task = self.tasks.get(&id)?;
+        let thread = lua.registry_value::<Thread>(&task.thread).ok()?;
+        Some((thread, task::waker(task.waker.clone())))
+    }
+}
+
+// Polls a task thread once, returning its result if it has finished
+pub(crate) fn poll_thread<'lua>(
+    id: TaskId,
+    thread: Thread<'lua>,
+    waker: &Waker,
+) -> Option<Result<MultiValue<'lua>>> {
+    let mut fut = thread.into_async::<_, MultiValue>(());
+    fut.set_task_id(id);
+    match Pin::new(&mut fut).poll(&mut Context::from_waker(waker)) {
+        Poll::Ready(result) => Some(result),
+        Poll::Pending => None,
+    }
+}
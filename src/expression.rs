@@ -0,0 +1,254 @@
+use std::string::String as StdString;
+
+/// Restrictions applied by [`Lua::eval_expression`] to the evaluated expression.
+///
+/// Statements, loops, function definitions and assignments are never allowed.
+///
+/// [`Lua::eval_expression`]: crate::Lua::eval_expression
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ExpressionPolicy {
+    /// If true, the expression can call functions (and methods) available in its environment.
+    ///
+    /// Default: **true**
+    pub allow_function_calls: bool,
+
+    /// If true, the expression can contain table constructors (`{ ... }`).
+    ///
+    /// Default: **false**
+    pub allow_table_constructors: bool,
+
+    /// The maximum length of the expression source (in bytes).
+    ///
+    /// Default: **1024**
+    pub max_length: usize,
+}
+
+impl Default for ExpressionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExpressionPolicy {
+    /// Returns a new instance of `ExpressionPolicy` with default parameters.
+    pub const fn new() -> Self {
+        ExpressionPolicy {
+            allow_function_calls: true,
+            allow_table_constructors: false,
+            max_length: 1024,
+        }
+    }
+
+    /// Sets [`allow_function_calls`] option.
+    ///
+    /// [`allow_function_calls`]: #structfield.allow_function_calls
+    #[must_use]
+    pub const fn allow_function_calls(mut self, enabled: bool) -> Self {
+        self.allow_function_calls = enabled;
+        self
+    }
+
+    /// Sets [`allow_table_constructors`] option.
+    ///
+    /// [`allow_table_constructors`]: #structfield.allow_table_constructors
+    #[must_use]
+    pub const fn allow_table_constructors(mut self, enabled: bool) -> Self {
+        self.allow_table_constructors = enabled;
+        self
+    }
+
+    /// Sets [`max_length`] option.
+    ///
+    /// [`max_length`]: #structfield.max_length
+    #[must_use]
+    pub const fn max_length(mut self, length: usize) -> Self {
+        self.max_length = length;
+        self
+    }
+}
+
+// Keywords that can only appear in statements or function definitions
+const FORBIDDEN_KEYWORDS: &[&str] = &[
+    "break", "do", "else", "elseif", "end", "for", "function", "goto", "if", "in", "local",
+    "repeat", "return", "then", "until", "while",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token {
+    // Name or closing bracket: a following `(`, `{` or string starts a call
+    Callable,
+    Other,
+}
+
+/// Checks that the source is a single expression allowed by the policy.
+///
+/// This is a lexical pre-pass: it only rejects forbidden constructs, the rest of validation is
+/// left to the Lua compiler.
+pub(crate) fn validate(src: &[u8], policy: &ExpressionPolicy) -> Result<(), StdString> {
+    if src.len() > policy.max_length {
+        return Err(format!(
+            "expression is too long ({} > {} bytes)",
+            src.len(),
+            policy.max_length
+        ));
+    }
+
+    let mut brackets = Vec::new();
+    let mut prev = Token::Other;
+    let mut is_empty = true;
+    let mut i = 0;
+    while i < src.len() {
+        let c = src[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        // Comments
+        if src[i..].starts_with(b"--") {
+            i += 2;
+            match long_bracket_level(&src[i..]) {
+                Some(level) => i = skip_long_bracket(src, i, level)?,
+                None => {
+                    while i < src.len() && src[i] != b'\n' {
+                        i += 1;
+                    }
+                }
+            }
+            continue;
+        }
+
+        is_empty = false;
+        let token = match c {
+            b'"' | b'\'' => {
+                check_call(prev, policy)?;
+                i += 1;
+                while i < src.len() && src[i] != c {
+                    i += if src[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+                Token::Other
+            }
+            b'[' if long_bracket_level(&src[i..]).is_some() => {
+                check_call(prev, policy)?;
+                let level = long_bracket_level(&src[i..]).unwrap();
+                i = skip_long_bracket(src, i, level)?;
+                Token::Other
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                let start = i;
+                while i < src.len() && (src[i].is_ascii_alphanumeric() || src[i] == b'_') {
+                    i += 1;
+                }
+                let word = std::str::from_utf8(&src[start..i]).unwrap_or_default();
+                if FORBIDDEN_KEYWORDS.contains(&word) {
+                    return Err(format!("`{word}` is not allowed in expressions"));
+                }
+                match word {
+                    "and" | "or" | "not" | "nil" | "true" | "false" => Token::Other,
+                    _ => Token::Callable,
+                }
+            }
+            b'0'..=b'9' => {
+                let start = i;
+                while i < src.len() {
+                    let c = src[i];
+                    let is_exp = i > start && matches!(src[i - 1], b'e' | b'E' | b'p' | b'P');
+                    if c.is_ascii_alphanumeric()
+                        || c == b'.'
+                        || (is_exp && (c == b'+' || c == b'-'))
+                    {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                Token::Other
+            }
+            b'(' | b'[' | b'{' => {
+                if c == b'(' || c == b'{' {
+                    check_call(prev, policy)?;
+                }
+                if c == b'{' && !policy.allow_table_constructors {
+                    return Err("table constructors are not allowed in expressions".to_string());
+                }
+                brackets.push(c);
+                i += 1;
+                Token::Other
+            }
+            b')' | b']' | b'}' => {
+                brackets.pop();
+                i += 1;
+                Token::Callable
+            }
+            b':' if src.get(i + 1) == Some(&b':') => {
+                return Err("labels are not allowed in expressions".to_string());
+            }
+            b':' => {
+                if !policy.allow_function_calls {
+                    return Err("method calls are not allowed in expressions".to_string());
+                }
+                i += 1;
+                Token::Other
+            }
+            b'=' | b'<' | b'>' | b'~' => {
+                if src.get(i + 1) == Some(&b'=') {
+                    i += 2;
+                } else if c == b'=' && brackets.last() != Some(&b'{') {
+                    return Err("assignments are not allowed in expressions".to_string());
+                } else {
+                    i += 1;
+                }
+                Token::Other
+            }
+            b',' | b';' if brackets.is_empty() => {
+                return Err("only a single expression is allowed".to_string());
+            }
+            b'`' => {
+                return Err("string interpolation is not allowed in expressions".to_string());
+            }
+            _ => {
+                i += 1;
+                Token::Other
+            }
+        };
+        prev = token;
+    }
+
+    if is_empty {
+        return Err("empty expression".to_string());
+    }
+    Ok(())
+}
+
+fn check_call(prev: Token, policy: &ExpressionPolicy) -> Result<(), StdString> {
+    if prev == Token::Callable && !policy.allow_function_calls {
+        return Err("function calls are not allowed in expressions".to_string());
+    }
+    Ok(())
+}
+
+// Returns the level of a long bracket (`[[`, `[=[`, ...) starting at the beginning of `src`
+fn long_bracket_level(src: &[u8]) -> Option<usize> {
+    if src.first() != Some(&b'[') {
+        return None;
+    }
+    let level = src[1..].iter().take_while(|&&c| c == b'=').count();
+    if src.get(level + 1) == Some(&b'[') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+// Skips a long string or comment starting at `start`, returns the position after its end
+fn skip_long_bracket(src: &[u8], start: usize, level: usize) -> Result<usize, StdString> {
+    let close = [b"]".as_slice(), &b"=".repeat(level), b"]"].concat();
+    let body = start + level + 2;
+    src[body..]
+        .windows(close.len())
+        .position(|w| w == close)
+        .map(|pos| body + pos + close.len())
+        .ok_or_else(|| "unfinished long string or comment".to_string())
+}
@@ -1,19 +1,23 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::mem;
 use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
 use std::ptr;
 use std::slice;
 
+#[cfg(not(feature = "luau"))]
+use crate::chunk::ChunkMode;
 use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::memory::MemoryState;
 use crate::table::Table;
-use crate::types::{Callback, LuaRef, MaybeSend};
+use crate::types::{Callback, Integer, LuaRef, MaybeSend, RegistryKey};
 use crate::util::{
     assert_stack, check_stack, error_traceback, linenumber_to_usize, pop_error, ptr_to_lossy_str,
     ptr_to_str, StackGuard,
 };
-use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti};
+use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Value};
 
 #[cfg(feature = "async")]
 use {
@@ -69,6 +73,68 @@ pub struct FunctionInfo {
     pub line_defined: Option<usize>,
     /// The line number where the definition of the function ends (not set by Luau).
     pub last_line_defined: Option<usize>,
+    /// Kind of the function, a typed version of `what`.
+    pub kind: FunctionKind,
+    /// Path of the chunk source, as resolved by the resolver set using
+    /// [`Lua::set_source_resolver`] (`None` if there is no resolver or it cannot resolve the source).
+    pub resolved_source: Option<PathBuf>,
+    /// The number of upvalues of the function.
+    pub num_upvalues: usize,
+    /// The number of parameters of the function (always 0 for C functions).
+    ///
+    /// Always `None` for Lua 5.1 and LuaJIT.
+    pub num_params: Option<usize>,
+    /// Whether the function is variadic (always `true` for C functions).
+    ///
+    /// Always `None` for Lua 5.1 and LuaJIT.
+    pub is_vararg: Option<bool>,
+}
+
+/// Kind of a function, as reported by [`Function::info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FunctionKind {
+    /// A Lua function.
+    Lua,
+    /// A C (or Rust) function.
+    C,
+    /// The main part of a chunk.
+    Main,
+}
+
+impl FunctionKind {
+    fn from_what(what: Option<&str>) -> Self {
+        match what {
+            Some("C") => FunctionKind::C,
+            Some("main") | None => FunctionKind::Main,
+            _ => FunctionKind::Lua,
+        }
+    }
+}
+
+/// Defines how a Rust callback behaves when it's called again while a previous call to it is
+/// still running, eg. when a script calls back into the host from inside a host call.
+///
+/// Set for a function using [`Lua::create_function_with_reentrancy`] or
+/// [`Lua::create_function_mut_with_reentrancy`]. Userdata methods called while the userdata is
+/// mutably borrowed are controlled by [`BorrowPolicy`] instead.
+///
+/// [`Lua::create_function_with_reentrancy`]: crate::Lua::create_function_with_reentrancy
+/// [`Lua::create_function_mut_with_reentrancy`]: crate::Lua::create_function_mut_with_reentrancy
+/// [`BorrowPolicy`]: crate::BorrowPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum ReentrancyPolicy {
+    /// Re-entrant calls run as usual.
+    ///
+    /// Mutable callbacks cannot run re-entrantly and fail with [`Error::RecursiveMutCallback`].
+    #[default]
+    Allow,
+    /// Re-entrant calls fail with a runtime error naming the callback, which Lua code receives
+    /// as a plain string message.
+    Error,
+    /// Re-entrant calls return no values immediately and are run, in order, after the running
+    /// call finishes. Errors of the deferred calls are returned by the outer call.
+    Queue,
 }
 
 /// Luau function coverage snapshot.
@@ -146,7 +212,125 @@ impl<'lua> Function<'lua> {
             }
             let nresults = ffi::lua_gettop(state) - stack_start;
             let mut results = args; // Reuse MultiValue container
-            assert_stack(state, 2);
+            check_stack(state, 2)?;
+            for _ in 0..nresults {
+                results.push_front(lua.pop_value());
+            }
+            ffi::lua_pop(state, 1);
+            results
+        };
+        R::from_lua_multi(results, lua)
+    }
+
+    /// Calls the function without capturing a traceback on error.
+    ///
+    /// This is the same as [`call`], except that no message handler is installed for the call.
+    /// It makes calls cheaper, which matters in hot loops, at the cost of error details: Lua
+    /// runtime errors have no stack traceback attached. Errors raised by Rust callbacks are
+    /// still returned as [`Error::CallbackError`] (with their own traceback) and Rust panics are
+    /// still propagated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let add: Function = lua.load("function(a, b) return a + b end").eval()?;
+    /// let mut sum = 0;
+    /// for i in 0..1000 {
+    ///     sum = add.call_fast::<_, i64>((sum, i))?;
+    /// }
+    /// assert_eq!(sum, 499500);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`call`]: #method.call
+    /// [`Error::CallbackError`]: crate::Error::CallbackError
+    pub fn call_fast<A: IntoLuaMulti<'lua>, R: FromLuaMulti<'lua>>(&self, args: A) -> Result<R> {
+        let lua = self.0.lua;
+        let state = lua.state();
+
+        let mut args = args.into_lua_multi(lua)?;
+        let nargs = args.len() as c_int;
+
+        let results = unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, nargs + 2)?;
+
+            let stack_start = ffi::lua_gettop(state);
+            lua.push_ref(&self.0);
+            for arg in args.drain_all() {
+                lua.push_value(arg)?;
+            }
+            let ret = ffi::lua_pcall(state, nargs, ffi::LUA_MULTRET, 0);
+            if ret != ffi::LUA_OK {
+                return Err(pop_error(state, ret));
+            }
+            let nresults = ffi::lua_gettop(state) - stack_start;
+            let mut results = args; // Reuse MultiValue container
+            check_stack(state, 2)?;
+            for _ in 0..nresults {
+                results.push_front(lua.pop_value());
+            }
+            results
+        };
+        R::from_lua_multi(results, lua)
+    }
+
+    /// Calls the function, passing the sequence part of `args` table as function arguments.
+    ///
+    /// This is equivalent to `f(table.unpack(args, 1, args.n or #args))` in Lua: if the table has
+    /// an integer field `n` (eg. created by `table.pack`), it's used as the number of arguments,
+    /// otherwise the raw length of the table is used. Arguments are moved to the Lua stack
+    /// directly, without converting them to Rust values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let sum: Function = lua.load("function(...) local s = 0; for _, v in ipairs({...}) do s = s + v end; return s end").eval()?;
+    /// let args = lua.create_sequence_from([1, 2, 3])?;
+    /// assert_eq!(sum.call_spread::<i32>(args)?, 6);
+    ///
+    /// let packed: Table = lua.load("{ n = 3, 4, 5, nil }").eval()?;
+    /// assert_eq!(sum.call_spread::<i32>(packed)?, 9);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn call_spread<R: FromLuaMulti<'lua>>(&self, args: Table<'lua>) -> Result<R> {
+        let lua = self.0.lua;
+        let state = lua.state();
+
+        let nargs = match args.raw_get::<_, Value>("n")? {
+            Value::Integer(n) => n,
+            Value::Number(n) if n.fract() == 0.0 => n as Integer,
+            _ => args.raw_len(),
+        };
+        let nargs = nargs.clamp(0, c_int::MAX as Integer - 4) as c_int;
+
+        let results = unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, nargs + 4)?;
+
+            MemoryState::relax_limit_with(state, || ffi::lua_pushcfunction(state, error_traceback));
+            let stack_start = ffi::lua_gettop(state);
+            lua.push_ref(&self.0);
+            lua.push_ref_checked(&args.0)?;
+            for i in 1..=nargs {
+                ffi::lua_rawgeti(state, stack_start + 2, i as _);
+            }
+            ffi::lua_remove(state, stack_start + 2);
+            let ret = ffi::lua_pcall(state, nargs, ffi::LUA_MULTRET, stack_start);
+            if ret != ffi::LUA_OK {
+                return Err(pop_error(state, ret));
+            }
+            let nresults = ffi::lua_gettop(state) - stack_start;
+            let mut results = lua.new_multivalue_from_pool();
+            check_stack(state, 2)?;
             for _ in 0..nresults {
                 results.push_front(lua.pop_value());
             }
@@ -286,6 +470,83 @@ impl<'lua> Function<'lua> {
         .call((self.clone(), args_wrapper))
     }
 
+    /// Returns a function that, when called, calls `self` and passes all of its results to
+    /// `next`, returning the results of `next`.
+    ///
+    /// The pipeline is constructed inside Lua, so calling the returned function does not involve
+    /// any Rust-side orchestration. If any of the functions yield (eg. async Rust functions), the
+    /// returned function can be used as an async function as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let add: Function = lua.load("function(a, b) return a + b end").eval()?;
+    /// let double: Function = lua.load("function(x) return x * 2 end").eval()?;
+    ///
+    /// let add_then_double = add.then(&double)?;
+    /// assert_eq!(add_then_double.call::<_, i32>((1, 2))?, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn then(&self, next: &Function<'lua>) -> Result<Function<'lua>> {
+        Function::compose(&[self.clone(), next.clone()])
+    }
+
+    /// Returns a function that, when called, calls `self` and passes all of its results to the
+    /// Rust closure `func`.
+    ///
+    /// This is a shortcut for [`Lua::create_function`] followed by [`Function::then`].
+    pub fn and_then_rust<A, R, F>(&self, func: F) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        let next = self.0.lua.create_function(func)?;
+        self.then(&next)
+    }
+
+    /// Composes a list of functions into a single pipeline function.
+    ///
+    /// Calling the returned function calls the first function with the provided arguments and
+    /// passes the results of each function as arguments to the next one. The results of the last
+    /// function are returned.
+    ///
+    /// Returns an error if `funcs` is empty.
+    pub fn compose(funcs: &[Function<'lua>]) -> Result<Function<'lua>> {
+        let lua = match funcs {
+            [] => {
+                let err = "cannot compose an empty list of functions".to_string();
+                return Err(Error::RuntimeError(err));
+            }
+            [func] => return Ok(func.clone()),
+            [func, ..] => func.0.lua,
+        };
+
+        let funcs = funcs.iter().cloned().map(Value::Function).collect();
+        lua.load(
+            r#"
+            local funcs = {...}
+            local n = #funcs
+            local function step(i, ...)
+                if i == n then
+                    return funcs[i](...)
+                end
+                return step(i + 1, funcs[i](...))
+            end
+            return function(...)
+                return step(1, ...)
+            end
+            "#,
+        )
+        .try_cache()
+        .set_name("__mlua_compose")
+        .call(MultiValue::from_vec(funcs))
+    }
+
     /// Returns the environment of the Lua function.
     ///
     /// By default Lua functions shares a global environment.
@@ -342,7 +603,7 @@ impl<'lua> Function<'lua> {
 
             #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
             {
-                lua.push_ref(&env.0);
+                lua.push_ref_checked(&env.0)?;
                 ffi::lua_setfenv(state, -2);
             }
             #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
@@ -377,17 +638,17 @@ impl<'lua> Function<'lua> {
     pub fn info(&self) -> FunctionInfo {
         let lua = self.0.lua;
         let state = lua.state();
-        unsafe {
+        let mut info = unsafe {
             let _sg = StackGuard::new(state);
             assert_stack(state, 1);
 
             let mut ar: ffi::lua_Debug = mem::zeroed();
             lua.push_ref(&self.0);
             #[cfg(not(feature = "luau"))]
-            let res = ffi::lua_getinfo(state, cstr!(">Sn"), &mut ar);
+            let res = ffi::lua_getinfo(state, cstr!(">Snu"), &mut ar);
             #[cfg(feature = "luau")]
-            let res = ffi::lua_getinfo(state, -1, cstr!("sn"), &mut ar);
-            mlua_assert!(res != 0, "lua_getinfo failed with `>Sn`");
+            let res = ffi::lua_getinfo(state, -1, cstr!("snua"), &mut ar);
+            mlua_assert!(res != 0, "lua_getinfo failed with `>Snu`");
 
             FunctionInfo {
                 name: ptr_to_lossy_str(ar.name).map(|s| s.into_owned()),
@@ -409,8 +670,24 @@ impl<'lua> Function<'lua> {
                 last_line_defined: linenumber_to_usize(ar.lastlinedefined),
                 #[cfg(feature = "luau")]
                 last_line_defined: None,
+                kind: FunctionKind::from_what(ptr_to_str(ar.what)),
+                resolved_source: None,
+                #[cfg(not(feature = "luau"))]
+                num_upvalues: ar.nups as usize,
+                #[cfg(feature = "luau")]
+                num_upvalues: ar.nupvals as usize,
+                #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+                num_params: Some(ar.nparams as usize),
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                num_params: None,
+                #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+                is_vararg: Some(ar.isvararg != 0),
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                is_vararg: None,
             }
-        }
+        };
+        info.resolved_source = lua.resolve_source(info.source.as_deref().unwrap_or_default());
+        info
     }
 
     /// Dumps the function as a binary chunk.
@@ -452,6 +729,85 @@ impl<'lua> Function<'lua> {
         data
     }
 
+    /// Copies the Lua function into another Lua instance.
+    ///
+    /// The function is dumped to bytecode and loaded by the `target` instance, so logic compiled
+    /// once can be cheaply distributed to (eg.) worker states. Upvalues of the function are mapped
+    /// by the `translate` callback, which receives the upvalue name and its value in this instance
+    /// and returns the value to use in the `target` instance.
+    ///
+    /// The `_ENV` upvalue (Lua 5.2+) is not passed to the callback, the copied function always
+    /// uses the globals of the `target` instance (as well as in Lua 5.1 and LuaJIT).
+    ///
+    /// Returns an error for Rust/C functions, or if the `target` instance cannot load the bytecode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let worker = Lua::new();
+    ///
+    /// let scale = lua.load("local k = 10; return function(x) return x * k end").eval::<mlua::Function>()?;
+    /// let scale = scale.clone_into(&worker, |_name, value| match value {
+    ///     Value::Integer(i) => Ok(Value::Integer(i)),
+    ///     _ => Ok(Value::Nil),
+    /// })?;
+    /// assert_eq!(scale.call::<_, i64>(5)?, 50);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn clone_into<'target, F>(
+        &self,
+        target: &'target Lua,
+        mut translate: F,
+    ) -> Result<Function<'target>>
+    where
+        F: FnMut(&str, Value<'lua>) -> Result<Value<'target>>,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            lua.push_ref(&self.0);
+            if ffi::lua_iscfunction(state, -1) != 0 {
+                return Err(Error::RuntimeError(
+                    "cannot copy a Rust/C function into another Lua instance".to_string(),
+                ));
+            }
+
+            let chunk = target.load(self.dump(false)).set_mode(ChunkMode::Binary);
+            let func = chunk.into_function()?;
+
+            let target_state = target.state();
+            let _target_sg = StackGuard::new(target_state);
+            check_stack(target_state, 2)?;
+            target.push_ref(&func.0);
+
+            for i in 1.. {
+                let name = ffi::lua_getupvalue(state, -1, i);
+                if name.is_null() {
+                    break;
+                }
+                let name = ptr_to_lossy_str(name).unwrap_or_default().into_owned();
+                let value = lua.pop_value();
+                let value = match &*name {
+                    "_ENV" => Value::Table(target.globals()),
+                    _ => translate(&name, value)?,
+                };
+                target.push_value(value)?;
+                ffi::lua_setupvalue(target_state, -2, i);
+            }
+
+            Ok(func)
+        }
+    }
+
     /// Retrieves recorded coverage information about this Lua function including inner calls.
     ///
     /// This function takes a callback as an argument and calls it providing [`CoverageInfo`] snapshot
@@ -551,6 +907,83 @@ impl OwnedFunction {
     }
 }
 
+// Tracks whether a callback is running to apply its `ReentrancyPolicy`
+pub(crate) struct ReentrancyGuard {
+    policy: ReentrancyPolicy,
+    running: Cell<bool>,
+    // Arguments of deferred calls, stored as tables with an explicit `n` field
+    queue: RefCell<VecDeque<RegistryKey>>,
+}
+
+impl ReentrancyGuard {
+    pub(crate) fn new(policy: ReentrancyPolicy) -> Self {
+        ReentrancyGuard {
+            policy,
+            running: Cell::new(false),
+            queue: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn call<'lua>(
+        &self,
+        lua: &'lua Lua,
+        args: MultiValue<'lua>,
+        func: impl Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>>,
+    ) -> Result<MultiValue<'lua>> {
+        if self.running.get() {
+            match self.policy {
+                ReentrancyPolicy::Allow => return func(lua, args),
+                ReentrancyPolicy::Error => {
+                    // The running function is at level 0
+                    let name = (lua.inspect_stack(0))
+                        .and_then(|ar| ar.names().name.map(|name| format!(" '{name}'")))
+                        .unwrap_or_default();
+                    return Err(Error::RuntimeError(format!(
+                        "function{name} is not re-entrant: it was called again while \
+                        its previous call was still running"
+                    )));
+                }
+                ReentrancyPolicy::Queue => {
+                    let n = args.len();
+                    let deferred = lua.create_sequence_from(args)?;
+                    deferred.raw_set("n", n)?;
+                    let key = lua.create_registry_value(deferred)?;
+                    self.queue.borrow_mut().push_back(key);
+                    return Ok(MultiValue::new());
+                }
+            }
+        }
+
+        self.running.set(true);
+        let _reset = ReentrancyReset(self);
+        let results = func(lua, args)?;
+        loop {
+            let key = match self.queue.borrow_mut().pop_front() {
+                Some(key) => key,
+                None => break,
+            };
+            let deferred: Table = lua.registry_value(&key)?;
+            lua.remove_registry_value(key)?;
+            let n: usize = deferred.raw_get("n")?;
+            let args = (1..=n)
+                .map(|i| deferred.raw_get::<_, Value>(i))
+                .collect::<Result<MultiValue>>()?;
+            func(lua, args)?;
+        }
+        Ok(results)
+    }
+}
+
+// Marks the callback as not running (and drops deferred calls) even if it fails or panics
+struct ReentrancyReset<'a>(&'a ReentrancyGuard);
+
+impl<'a> Drop for ReentrancyReset<'a> {
+    fn drop(&mut self) {
+        self.0.queue.borrow_mut().clear();
+        self.0.running.set(false);
+    }
+}
+
 pub(crate) struct WrappedFunction<'lua>(pub(crate) Callback<'lua, 'static>);
 
 #[cfg(feature = "async")]
@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::string::String as StdString;
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::string::String;
+use crate::types::{MaybeSend, ViolationCallback};
+use crate::value::{FromLua, IntoLua, Nil, Value};
+
+/// Policy of writes to global variables, enforced by [`Lua::protect_globals`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{GlobalPolicy, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// lua.globals().set("score", 0)?;
+/// lua.protect_globals(
+///     GlobalPolicy::new()
+///         .allow_new(false)
+///         .writable(["score"])
+///         .on_violation(|_, violation| {
+///             eprintln!("write to `{}` rejected\n{:?}", violation.key, violation.traceback);
+///             Ok(())
+///         }),
+/// )?;
+///
+/// lua.load("score = score + 1").exec()?;
+/// assert!(lua.load("print = nil").exec().is_err());
+/// assert!(lua.load("level = 2").exec().is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[non_exhaustive]
+pub struct GlobalPolicy {
+    /// If true, Lua code can create new global variables (and modify them afterwards).
+    ///
+    /// Default: **true**
+    pub allow_new: bool,
+
+    /// Names of global variables that Lua code can always assign.
+    ///
+    /// Default: **empty**
+    pub writable: Vec<StdString>,
+
+    on_violation: Option<ViolationCallback>,
+}
+
+impl Default for GlobalPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for GlobalPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlobalPolicy")
+            .field("allow_new", &self.allow_new)
+            .field("writable", &self.writable)
+            .field("on_violation", &self.on_violation.is_some())
+            .finish()
+    }
+}
+
+impl GlobalPolicy {
+    /// Returns a new instance of `GlobalPolicy` with default parameters.
+    pub const fn new() -> Self {
+        GlobalPolicy {
+            allow_new: true,
+            writable: Vec::new(),
+            on_violation: None,
+        }
+    }
+
+    /// Sets [`allow_new`] option.
+    ///
+    /// [`allow_new`]: #structfield.allow_new
+    #[must_use]
+    pub const fn allow_new(mut self, enabled: bool) -> Self {
+        self.allow_new = enabled;
+        self
+    }
+
+    /// Sets [`writable`] option.
+    ///
+    /// [`writable`]: #structfield.writable
+    #[must_use]
+    pub fn writable<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<StdString>,
+    {
+        self.writable = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets a function to be called when Lua code attempts a forbidden write.
+    ///
+    /// The write is rejected with a runtime error after the function returns. If the function
+    /// returns an error, that error is raised instead.
+    #[must_use]
+    pub fn on_violation<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Lua, &GlobalViolation) -> Result<()> + MaybeSend + 'static,
+    {
+        self.on_violation = Some(Box::new(callback));
+        self
+    }
+}
+
+/// Forbidden write to a global variable, reported to [`GlobalPolicy::on_violation`].
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct GlobalViolation {
+    /// Name of the global variable (the key converted to a string).
+    pub key: StdString,
+    /// True if the write would create a new global variable.
+    pub new: bool,
+    /// Traceback of the Lua code attempting the write.
+    pub traceback: Option<StdString>,
+}
+
+/// Changes of global variables staged by [`Lua::update_globals`].
+///
+/// [`Lua::update_globals`]: crate::Lua::update_globals
+pub struct GlobalsTransaction<'lua> {
+    lua: &'lua Lua,
+    changes: Vec<(String<'lua>, Value<'lua>)>,
+}
+
+impl<'lua> GlobalsTransaction<'lua> {
+    /// Stages setting a global variable.
+    pub fn set<V: IntoLua<'lua>>(&mut self, name: &str, value: V) -> Result<()> {
+        let value = value.into_lua(self.lua)?;
+        match self.changes.iter_mut().find(|(key, _)| key == name) {
+            Some((_, staged)) => *staged = value,
+            None => (self.changes).push((self.lua.create_string(name)?, value)),
+        }
+        Ok(())
+    }
+
+    /// Stages removing a global variable.
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.set(name, Nil)
+    }
+
+    /// Returns the value of a global variable, taking staged changes into account.
+    pub fn get<V: FromLua<'lua>>(&self, name: &str) -> Result<V> {
+        match self.changes.iter().find(|(key, _)| key == name) {
+            Some((_, value)) => V::from_lua(value.clone(), self.lua),
+            None => self.lua.globals().get(name),
+        }
+    }
+}
+
+pub(crate) fn update_globals<'lua, F, R>(lua: &'lua Lua, f: F) -> Result<R>
+where
+    F: FnOnce(&mut GlobalsTransaction<'lua>) -> Result<R>,
+{
+    let mut tx = GlobalsTransaction {
+        lua,
+        changes: Vec::new(),
+    };
+    let result = f(&mut tx)?;
+
+    // Keys are created in advance and raw writes do not call metamethods, so no Lua code can
+    // run until all the changes are applied.
+    let globals = lua.globals();
+    let mut applied = Vec::with_capacity(tx.changes.len());
+    for (key, value) in tx.changes {
+        let write = globals.raw_get::<_, Value>(key.clone()).and_then(|prev| {
+            globals.raw_set(key.clone(), value)?;
+            Ok((key, prev))
+        });
+        match write {
+            Ok(change) => applied.push(change),
+            Err(err) => {
+                for (key, prev) in applied.into_iter().rev() {
+                    let _ = globals.raw_set(key, prev);
+                }
+                return Err(err);
+            }
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn protect_globals(lua: &Lua, policy: GlobalPolicy) -> Result<()> {
+    if lua.has_protected_globals() {
+        return Err(Error::RuntimeError(
+            "cannot protect globals: globals are already protected".to_string(),
+        ));
+    }
+    let globals = lua.globals();
+    if globals.has_metatable() {
+        return Err(Error::RuntimeError(
+            "cannot protect globals: global table already has a metatable".to_string(),
+        ));
+    }
+
+    let GlobalPolicy {
+        allow_new,
+        writable,
+        on_violation,
+    } = policy;
+
+    // Global variables created by Lua code are not protected
+    let created = RefCell::new(HashSet::new());
+    let newindex = lua.create_function(move |lua, (_, key, value): (Value, Value, Value)| {
+        let globals = lua.globals();
+        let name = key.to_string()?;
+        let new = matches!(globals.raw_get(key.clone())?, Value::Nil);
+        if writable.contains(&name) || (new && allow_new) || created.borrow().contains(&name) {
+            if new {
+                created.borrow_mut().insert(name);
+            }
+            return globals.raw_set(key, value);
+        }
+
+        let violation = GlobalViolation {
+            traceback: lua.traceback(),
+            key: name,
+            new,
+        };
+        if let Some(on_violation) = &on_violation {
+            on_violation(lua, &violation)?;
+        }
+        let action = if new { "create" } else { "modify" };
+        Err(Error::RuntimeError(format!(
+            "attempt to {action} protected global '{}'",
+            violation.key
+        )))
+    })?;
+
+    // Lua code gets an empty proxy, so that every write to it can be intercepted
+    let proxy = lua.create_table()?;
+    let pairs: Function = lua
+        .load("local t, next = ... return function() return next, t, nil end")
+        .try_cache()
+        .set_name("=__pairs")
+        .call((globals.clone(), globals.raw_get::<_, Value>("next")?))?;
+
+    let mt = lua.create_table_with_capacity(0, 5)?;
+    mt.raw_set("__index", globals.clone())?;
+    mt.raw_set("__newindex", newindex)?;
+    #[cfg(feature = "luau")]
+    mt.raw_set("__iter", pairs.clone())?;
+    mt.raw_set("__pairs", pairs)?;
+    mt.raw_set("__metatable", "protected")?;
+    proxy.set_metatable(Some(mt));
+    if globals.raw_get::<_, Value>("_G")? == Value::Table(globals.clone()) {
+        globals.raw_set("_G", proxy.clone())?;
+    }
+    lua.set_protected_globals(&globals, &proxy)
+}
@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+use std::os::raw::c_void;
+use std::string::String as StdString;
+
+use rustc_hash::FxHashMap;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::{HashOptions, Table};
+use crate::value::Value;
+
+/// Result of [`Lua::heap_analysis`].
+///
+/// All lists are sorted from the most to the least significant entry.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct HeapAnalysis {
+    /// Number of reachable tables.
+    pub tables: usize,
+    /// Number of reachable distinct strings.
+    pub strings: usize,
+    /// Total length (in bytes) of reachable distinct strings.
+    pub string_bytes: usize,
+    /// String keys used by more than one table.
+    pub duplicate_keys: Vec<KeyUsage>,
+    /// Groups of structurally identical non-empty tables (see [`Table::structural_hash`]).
+    pub duplicate_tables: Vec<TableGroup>,
+    /// Tables retaining the largest number of entries.
+    pub largest_retainers: Vec<Retainer>,
+}
+
+/// A string key shared by several tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyUsage {
+    /// The key (lossy converted to UTF-8).
+    pub key: StdString,
+    /// Number of tables having this key.
+    pub tables: usize,
+}
+
+/// A group of structurally identical tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableGroup {
+    /// Paths of the tables in the group.
+    pub paths: Vec<StdString>,
+    /// Number of entries in each table.
+    pub entries: usize,
+}
+
+/// A table and the amount of data reachable only through it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Retainer {
+    /// Path of the table.
+    pub path: StdString,
+    /// Number of entries in the table and all tables first reached through it.
+    pub retained_entries: usize,
+    /// Total length of strings first reached through the table.
+    pub retained_string_bytes: usize,
+}
+
+struct Node<'lua> {
+    // Taken when the table is processed, to not hold references to all tables at once
+    table: Option<Table<'lua>>,
+    path: StdString,
+    parent: Option<usize>,
+    entries: usize,
+    string_bytes: usize,
+}
+
+// Maximum number of retainers to report
+const MAX_RETAINERS: usize = 20;
+
+// Only Lua 5.4 and Luau can convert strings to pointers, other versions identify strings by content
+#[cfg(any(feature = "lua54", feature = "luau"))]
+type StringId = *const c_void;
+#[cfg(not(any(feature = "lua54", feature = "luau")))]
+type StringId = Vec<u8>;
+
+#[cfg(any(feature = "lua54", feature = "luau"))]
+fn string_id(s: &String) -> StringId {
+    s.to_pointer()
+}
+
+#[cfg(not(any(feature = "lua54", feature = "luau")))]
+fn string_id(s: &String) -> StringId {
+    s.as_bytes().to_vec()
+}
+
+pub(crate) fn analyze(lua: &Lua) -> Result<HeapAnalysis> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut visited = FxHashMap::<*const c_void, usize>::default();
+    let mut strings = FxHashMap::<StringId, usize>::default();
+    let mut keys = FxHashMap::<Vec<u8>, usize>::default();
+    let mut groups = FxHashMap::<u64, TableGroup>::default();
+    let hash_options = HashOptions::new().deny_recursive_tables(false);
+
+    // Walk tables in breadth-first order to have the shortest paths
+    let mut queue = VecDeque::new();
+    for (table, path) in [(lua.globals(), "_G"), (lua.registry_table()?, "registry")] {
+        if visited.insert(table.to_pointer(), nodes.len()).is_none() {
+            queue.push_back(nodes.len());
+            nodes.push(Node {
+                table: Some(table),
+                path: path.to_string(),
+                parent: None,
+                entries: 0,
+                string_bytes: 0,
+            });
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        let table = nodes[index]
+            .table
+            .take()
+            .expect("table is already processed");
+        let (mut entries, mut string_bytes) = (0, 0);
+        for pair in table.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            entries += 1;
+            if let Value::String(key) = &key {
+                *keys.entry(key.as_bytes().to_vec()).or_default() += 1;
+            }
+            for value in [&key, &value] {
+                match value {
+                    Value::String(s) => {
+                        let len = s.as_bytes().len();
+                        if strings.insert(string_id(s), len).is_none() {
+                            string_bytes += len;
+                        }
+                    }
+                    Value::Table(t) => {
+                        let ptr = t.to_pointer();
+                        if visited.contains_key(&ptr) {
+                            continue;
+                        }
+                        visited.insert(ptr, nodes.len());
+                        queue.push_back(nodes.len());
+                        let path = format!("{}{}", nodes[index].path, path_segment(&key));
+                        nodes.push(Node {
+                            table: Some(t.clone()),
+                            path,
+                            parent: Some(index),
+                            entries: 0,
+                            string_bytes: 0,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        nodes[index].entries = entries;
+        nodes[index].string_bytes = string_bytes;
+
+        if entries > 0 {
+            let hash = table.structural_hash(hash_options)?;
+            let group = groups.entry(hash).or_insert_with(|| TableGroup {
+                paths: Vec::new(),
+                entries,
+            });
+            group.paths.push(nodes[index].path.clone());
+        }
+    }
+
+    // Children are always placed after their parents, so a reverse pass accumulates sizes
+    let mut retained: Vec<(usize, usize)> = (nodes.iter())
+        .map(|node| (node.entries, node.string_bytes))
+        .collect();
+    for (index, node) in nodes.iter().enumerate().rev() {
+        if let Some(parent) = node.parent {
+            retained[parent].0 += retained[index].0;
+            retained[parent].1 += retained[index].1;
+        }
+    }
+    let mut largest_retainers = (nodes.iter().zip(&retained))
+        .filter(|(node, _)| node.parent.is_some())
+        .map(|(node, &(entries, bytes))| Retainer {
+            path: node.path.clone(),
+            retained_entries: entries,
+            retained_string_bytes: bytes,
+        })
+        .collect::<Vec<_>>();
+    largest_retainers.sort_by_key(|r| std::cmp::Reverse(r.retained_entries));
+    largest_retainers.truncate(MAX_RETAINERS);
+
+    let mut duplicate_tables = (groups.into_values())
+        .filter(|group| group.paths.len() > 1)
+        .collect::<Vec<_>>();
+    duplicate_tables.sort_by(|a, b| {
+        let (a_size, b_size) = (a.paths.len() * a.entries, b.paths.len() * b.entries);
+        b_size.cmp(&a_size).then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    let mut duplicate_keys = (keys.into_iter())
+        .filter(|&(_, tables)| tables > 1)
+        .map(|(key, tables)| KeyUsage {
+            key: StdString::from_utf8_lossy(&key).into_owned(),
+            tables,
+        })
+        .collect::<Vec<_>>();
+    duplicate_keys.sort_by(|a, b| b.tables.cmp(&a.tables).then_with(|| a.key.cmp(&b.key)));
+
+    Ok(HeapAnalysis {
+        tables: nodes.len(),
+        strings: strings.len(),
+        string_bytes: strings.values().sum(),
+        duplicate_keys,
+        duplicate_tables,
+        largest_retainers,
+    })
+}
+
+// Formats a table key as a path segment (eg. `.name` or `[1]`)
+fn path_segment(key: &Value) -> StdString {
+    match key {
+        Value::String(s) => match s.to_str() {
+            Ok(s) if is_identifier(s) => format!(".{s}"),
+            _ => format!("[{:?}]", s.to_string_lossy()),
+        },
+        Value::Integer(i) => format!("[{i}]"),
+        Value::Number(n) => format!("[{n}]"),
+        Value::Boolean(b) => format!("[{b}]"),
+        key => format!("[{}]", key.type_name()),
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -3,11 +3,27 @@ use std::cell::UnsafeCell;
 #[cfg(not(feature = "luau"))]
 use std::ops::{BitOr, BitOrAssign};
 use std::os::raw::c_int;
+#[cfg(not(feature = "luau"))]
+use std::string::String as StdString;
+#[cfg(not(feature = "luau"))]
+use std::time::Duration;
 
 use ffi::lua_Debug;
 
 use crate::lua::Lua;
+use crate::source_map::MappedLocation;
 use crate::util::{linenumber_to_usize, ptr_to_lossy_str, ptr_to_str};
+#[cfg(not(feature = "luau"))]
+use {
+    crate::{
+        error::Result,
+        lua::ExtraData,
+        util::{check_stack, StackGuard},
+        value::Value,
+    },
+    rustc_hash::FxHashMap,
+    std::{ffi::CStr, mem, sync::Weak},
+};
 
 /// Contains information about currently executing Lua code.
 ///
@@ -139,6 +155,20 @@ impl<'lua> Debug<'lua> {
         }
     }
 
+    /// Returns the position of the current line in the original source, if the running chunk has
+    /// a [`SourceMap`] attached.
+    ///
+    /// The generated position is available using [`source`] and [`curr_line`].
+    ///
+    /// [`SourceMap`]: crate::SourceMap
+    /// [`source`]: #method.source
+    /// [`curr_line`]: #method.curr_line
+    pub fn mapped_location(&self) -> Option<MappedLocation> {
+        let source = self.source().source?;
+        let map = self.lua.source_map(&source)?;
+        map.location(usize::try_from(self.curr_line()).ok()?)
+    }
+
     /// Corresponds to the `t` what mask. Returns true if the hook is in a function tail call, false
     /// otherwise.
     #[cfg(not(feature = "luau"))]
@@ -184,6 +214,31 @@ impl<'lua> Debug<'lua> {
             stack
         }
     }
+
+    /// Returns names and values of active local variables (excluding internal temporaries).
+    #[cfg(not(feature = "luau"))]
+    pub(crate) fn locals(&self) -> Result<Vec<(StdString, Value<'lua>)>> {
+        unsafe {
+            let state = self.lua.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            let mut locals = Vec::new();
+            for n in 1.. {
+                let name = ffi::lua_getlocal(state, self.ar.get(), n);
+                if name.is_null() {
+                    break;
+                }
+                let value = self.lua.pop_value();
+                match ptr_to_lossy_str(name) {
+                    // Internal variables (eg. loop state) are named like `(for state)`
+                    Some(name) if !name.starts_with('(') => locals.push((name.into_owned(), value)),
+                    _ => {}
+                }
+            }
+            Ok(locals)
+        }
+    }
 }
 
 enum ActivationRecord {
@@ -277,6 +332,10 @@ pub struct HookTriggers {
     ///
     /// Setting this option to a low value can incur a very high overhead.
     pub every_nth_instruction: Option<u32>,
+    // Set by `every_nth_line`
+    pub(crate) every_nth_line: Option<u32>,
+    // Set by `every`
+    pub(crate) every: Option<Duration>,
 }
 
 #[cfg(not(feature = "luau"))]
@@ -290,6 +349,12 @@ impl HookTriggers {
     /// An instance of `HookTriggers` with `every_line` trigger set.
     pub const EVERY_LINE: Self = HookTriggers::new().every_line();
 
+    /// Number of VM instructions between checks of the [`every`] trigger when it's the only
+    /// trigger set.
+    ///
+    /// [`every`]: #method.every
+    pub const SAMPLING_INSTRUCTIONS: u32 = 1000;
+
     /// Returns a new instance of `HookTriggers` with all triggers disabled.
     pub const fn new() -> Self {
         HookTriggers {
@@ -297,6 +362,8 @@ impl HookTriggers {
             on_returns: false,
             every_line: false,
             every_nth_instruction: None,
+            every_nth_line: None,
+            every: None,
         }
     }
 
@@ -332,6 +399,28 @@ impl HookTriggers {
         self
     }
 
+    /// Returns an instance of `HookTriggers` that calls the hook before executing every `n`-th
+    /// new line.
+    ///
+    /// Line events are counted by the hook dispatcher and the hook is called only for every
+    /// `n`-th of them. Has no effect if [`every_line`] is set.
+    ///
+    /// [`every_line`]: #structfield.every_line
+    pub const fn every_nth_line(mut self, n: u32) -> Self {
+        self.every_nth_line = Some(n);
+        self
+    }
+
+    /// Returns an instance of `HookTriggers` that calls the hook at most once per the given
+    /// interval, dropping the events triggered in between.
+    ///
+    /// If no other trigger is set, the hook is checked every
+    /// [`SAMPLING_INSTRUCTIONS`](Self::SAMPLING_INSTRUCTIONS) VM instructions.
+    pub const fn every(mut self, interval: Duration) -> Self {
+        self.every = Some(interval);
+        self
+    }
+
     // Compute the mask to pass to `lua_sethook`.
     pub(crate) const fn mask(&self) -> c_int {
         let mut mask: c_int = 0;
@@ -341,10 +430,10 @@ impl HookTriggers {
         if self.on_returns {
             mask |= ffi::LUA_MASKRET
         }
-        if self.every_line {
+        if self.every_line || self.every_nth_line.is_some() {
             mask |= ffi::LUA_MASKLINE
         }
-        if self.every_nth_instruction.is_some() {
+        if self.every_nth_instruction.is_some() || (mask == 0 && self.every.is_some()) {
             mask |= ffi::LUA_MASKCOUNT
         }
         mask
@@ -353,8 +442,13 @@ impl HookTriggers {
     // Returns the `count` parameter to pass to `lua_sethook`, if applicable. Otherwise, zero is
     // returned.
     pub(crate) const fn count(&self) -> c_int {
-        let Some(n) = self.every_nth_instruction else { return 0 };
-        n as c_int
+        if let Some(n) = self.every_nth_instruction {
+            return n as c_int;
+        }
+        if self.mask() == ffi::LUA_MASKCOUNT {
+            return Self::SAMPLING_INSTRUCTIONS as c_int;
+        }
+        0
     }
 }
 
@@ -369,6 +463,12 @@ impl BitOr for HookTriggers {
         if self.every_nth_instruction.is_none() && rhs.every_nth_instruction.is_some() {
             self.every_nth_instruction = rhs.every_nth_instruction;
         }
+        if self.every_nth_line.is_none() && rhs.every_nth_line.is_some() {
+            self.every_nth_line = rhs.every_nth_line;
+        }
+        if self.every.is_none() && rhs.every.is_some() {
+            self.every = rhs.every;
+        }
         self
     }
 }
@@ -379,3 +479,256 @@ impl BitOrAssign for HookTriggers {
         *self = *self | rhs;
     }
 }
+
+// Throttles hook calls according to the `every_nth_line` and `every` triggers
+#[cfg(not(feature = "luau"))]
+#[derive(Default)]
+pub(crate) struct HookSampler {
+    lines: u32,
+    last_call: Option<Duration>,
+}
+
+#[cfg(not(feature = "luau"))]
+impl HookSampler {
+    // Returns `true` if the hook should be called for the event.
+    // The `every` trigger is ignored if `now` doesn't return the current time.
+    pub(crate) fn sample(
+        &mut self,
+        triggers: &HookTriggers,
+        event: c_int,
+        now: impl FnOnce() -> Option<Duration>,
+    ) -> bool {
+        if let Some(n) = triggers.every_nth_line {
+            if event == ffi::LUA_HOOKLINE && !triggers.every_line {
+                self.lines += 1;
+                if self.lines < n {
+                    return false;
+                }
+                self.lines = 0;
+            }
+        }
+        if let (Some(interval), Some(now)) = (triggers.every, triggers.every.and_then(|_| now())) {
+            if matches!(self.last_call, Some(last) if now.saturating_sub(last) < interval) {
+                return false;
+            }
+            self.last_call = Some(now);
+        }
+        true
+    }
+}
+
+/// A hook installed by [`Lua::push_hook`].
+///
+/// Dropping the guard removes the hook and restores the hook that was active before it
+/// (if it's still the active one).
+///
+/// [`Lua::push_hook`]: crate::Lua::push_hook
+#[cfg(not(feature = "luau"))]
+#[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+#[must_use = "hook is removed when the guard is dropped"]
+pub struct HookGuard<'lua> {
+    lua: &'lua Lua,
+    id: usize,
+}
+
+#[cfg(not(feature = "luau"))]
+impl<'lua> HookGuard<'lua> {
+    pub(crate) fn new(lua: &'lua Lua, id: usize) -> Self {
+        HookGuard { lua, id }
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+impl<'lua> Drop for HookGuard<'lua> {
+    fn drop(&mut self) {
+        self.lua.pop_hook(self.id);
+    }
+}
+
+/// Counts executed VM instructions until dropped.
+///
+/// Created by [`Lua::count_instructions`].
+///
+/// [`Lua::count_instructions`]: crate::Lua::count_instructions
+#[must_use = "instructions are counted until the guard is dropped"]
+pub struct InstructionCounter<'lua> {
+    #[cfg(not(feature = "luau"))]
+    _hook: HookGuard<'lua>,
+    #[cfg(feature = "luau")]
+    lua: &'lua Lua,
+}
+
+impl<'lua> InstructionCounter<'lua> {
+    #[cfg(not(feature = "luau"))]
+    pub(crate) fn new(hook: HookGuard<'lua>) -> Self {
+        InstructionCounter { _hook: hook }
+    }
+
+    #[cfg(feature = "luau")]
+    pub(crate) fn new(lua: &'lua Lua) -> Self {
+        InstructionCounter { lua }
+    }
+}
+
+#[cfg(feature = "luau")]
+impl<'lua> Drop for InstructionCounter<'lua> {
+    fn drop(&mut self) {
+        self.lua.stop_counting_instructions();
+    }
+}
+
+/// Information about the line about to be executed, passed to the callback of
+/// [`Chunk::exec_stepped`].
+///
+/// [`Chunk::exec_stepped`]: crate::Chunk::exec_stepped
+#[cfg(not(feature = "luau"))]
+#[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct StepInfo<'lua> {
+    /// The line number about to be executed.
+    pub line: usize,
+    /// A "printable" version of the source of the running function (eg. the chunk name).
+    pub source: Option<StdString>,
+    /// Name of the running function (`None` for the main chunk or if it cannot be found).
+    pub function: Option<StdString>,
+    /// Active local variables of the running function, in declaration order.
+    pub locals: Vec<(StdString, Value<'lua>)>,
+}
+
+/// Determines how [`Chunk::exec_stepped`] proceeds after a step.
+///
+/// [`Chunk::exec_stepped`]: crate::Chunk::exec_stepped
+#[cfg(not(feature = "luau"))]
+#[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepControl {
+    /// Executes the line and stops before the next one.
+    Continue,
+    /// Runs the rest of the chunk without stopping.
+    Finish,
+    /// Aborts the execution.
+    Abort,
+}
+
+#[cfg(not(feature = "luau"))]
+pub(crate) fn is_call_event(event: c_int) -> bool {
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    return event == ffi::LUA_HOOKCALL || event == ffi::LUA_HOOKTAILCALL;
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    return event == ffi::LUA_HOOKCALL;
+}
+
+#[cfg(not(feature = "luau"))]
+pub(crate) fn is_return_event(event: c_int) -> bool {
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    return event == ffi::LUA_HOOKRET;
+    // Event 4 is `LUA_HOOKTAILRET` on Lua 5.1
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    return event == ffi::LUA_HOOKRET || event == ffi::LUA_HOOKTAILCALL;
+}
+
+// Hook events required to enforce thread limits (set by `ThreadBuilder`)
+#[cfg(not(feature = "luau"))]
+pub(crate) const THREAD_LIMITS_MASK: c_int = ffi::LUA_MASKCALL | ffi::LUA_MASKRET;
+
+// Execution limits of a thread (zero means no limit)
+#[cfg(not(feature = "luau"))]
+#[derive(Clone, Copy)]
+pub(crate) struct ThreadLimits {
+    pub(crate) stack_limit: c_int,
+    pub(crate) c_call_limit: c_int,
+    // Upper bounds of the number of active function calls and active C function calls, tracked
+    // on call and return events. Frames unwound by errors don't generate return events, so the
+    // exact numbers are recounted when a limit seems to be exceeded.
+    pub(crate) depth: c_int,
+    pub(crate) c_depth: c_int,
+    // Tells apart limits of a new thread allocated at the address of a collected one
+    pub(crate) id: u64,
+}
+
+// Removes the limits of a thread when the thread is collected
+#[cfg(not(feature = "luau"))]
+pub(crate) struct ThreadLimitsGuard {
+    pub(crate) extra: Weak<UnsafeCell<ExtraData>>,
+    pub(crate) thread: *mut ffi::lua_State,
+    pub(crate) id: u64,
+}
+
+#[cfg(not(feature = "luau"))]
+impl Drop for ThreadLimitsGuard {
+    fn drop(&mut self) {
+        if let Some(extra) = self.extra.upgrade() {
+            let thread_limits = unsafe { (*extra.get()).thread_limits_mut() };
+            if thread_limits.get(&self.thread).map(|limits| limits.id) == Some(self.id) {
+                thread_limits.remove(&self.thread);
+            }
+        }
+    }
+}
+
+// Tracks the call depth of a thread with execution limits, raising an error if a limit is
+// exceeded. Returns `false` if the thread has no limits.
+#[cfg(not(feature = "luau"))]
+pub(crate) unsafe fn check_thread_limits(
+    state: *mut ffi::lua_State,
+    thread_limits: &mut FxHashMap<*mut ffi::lua_State, ThreadLimits>,
+    ar: *mut ffi::lua_Debug,
+) -> bool {
+    let mut limits = match thread_limits.get(&state) {
+        Some(&limits) => limits,
+        None => return false,
+    };
+    let delta = match (*ar).event {
+        ffi::LUA_HOOKCALL => 1,
+        event if is_return_event(event) => -1,
+        // Tail calls replace the frame of the caller
+        _ => return true,
+    };
+
+    let is_c_function = |ar: *mut ffi::lua_Debug| {
+        ffi::lua_getinfo(state, cstr!("S"), ar) != 0
+            && CStr::from_ptr((*ar).what).to_bytes() == b"C"
+    };
+    // Counts active function calls matching the predicate
+    let count_calls = |pred: &dyn Fn(*mut ffi::lua_Debug) -> bool| {
+        let mut ar2: ffi::lua_Debug = mem::zeroed();
+        let (mut level, mut count) = (0, 0);
+        while ffi::lua_getstack(state, level, &mut ar2) != 0 {
+            if pred(&mut ar2) {
+                count += 1;
+            }
+            level += 1;
+        }
+        count
+    };
+
+    limits.depth = (limits.depth + delta).max(0);
+    if limits.c_call_limit > 0 && is_c_function(ar) {
+        limits.c_depth = (limits.c_depth + delta).max(0);
+        if delta > 0 && limits.c_depth > limits.c_call_limit {
+            limits.c_depth = count_calls(&is_c_function);
+        }
+    }
+    if limits.stack_limit > 0 && delta > 0 && limits.depth > limits.stack_limit {
+        limits.depth = count_calls(&|_| true);
+    }
+    // Store the result before raising an error, which can run finalizers
+    thread_limits.insert(state, limits);
+
+    if limits.stack_limit > 0 && limits.depth > limits.stack_limit {
+        ffi::luaL_error(
+            state,
+            cstr!("stack overflow (thread call depth limit of %d exceeded)"),
+            limits.stack_limit,
+        );
+    }
+    if limits.c_call_limit > 0 && limits.c_depth > limits.c_call_limit {
+        ffi::luaL_error(
+            state,
+            cstr!("C stack overflow (thread C call depth limit of %d exceeded)"),
+            limits.c_call_limit,
+        );
+    }
+    true
+}
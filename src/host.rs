@@ -0,0 +1,203 @@
+use std::string::String as StdString;
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::multi::Variadic;
+use crate::stdlib::StdLib;
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::value::Value;
+
+/// Features of the embedding, as returned by [`Lua::capabilities`].
+///
+/// Scripts can read the same information from the `host.capabilities` table installed by
+/// [`Lua::expose_capabilities`].
+///
+/// [`Lua::capabilities`]: crate::Lua::capabilities
+/// [`Lua::expose_capabilities`]: crate::Lua::expose_capabilities
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Lua version, the value of `_VERSION` (eg. `"Lua 5.4"`).
+    pub version: StdString,
+    /// Version of mlua.
+    pub mlua_version: &'static str,
+    /// Names of the loaded standard libraries.
+    pub std_libs: Vec<&'static str>,
+    /// Names of the other modules registered in `package.loaded`, sorted.
+    pub modules: Vec<StdString>,
+    /// Whether async functions are supported (`feature = "async"`).
+    pub async_support: bool,
+}
+
+impl Capabilities {
+    /// Returns true if `name` is a loaded standard library or a registered module.
+    pub fn has(&self, name: &str) -> bool {
+        self.std_libs.contains(&name) || self.modules.iter().any(|m| m == name)
+    }
+}
+
+pub(crate) fn set_print_handler<F>(lua: &Lua, handler: F) -> Result<()>
+where
+    F: Fn(&Lua, &str) -> Result<()> + MaybeSend + 'static,
+{
+    let print = lua.create_function(move |lua, args: Variadic<Value>| {
+        let mut line = StdString::new();
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                line.push('\t');
+            }
+            match arg {
+                Value::String(s) => line.push_str(&s.to_string_lossy()),
+                arg => line.push_str(&arg.to_string()?),
+            }
+        }
+        handler(lua, &line)
+    })?;
+    lua.globals().set("print", print)
+}
+
+pub(crate) fn set_clock(lua: &Lua) -> Result<()> {
+    let globals = lua.globals();
+    let os = match globals.get::<_, Option<Table>>("os")? {
+        Some(os) => os,
+        None => {
+            let os = lua.create_table()?;
+            globals.set("os", os.clone())?;
+            os
+        }
+    };
+    let func =
+        lua.create_function(|lua, ()| Ok(lua.clock_now().unwrap_or_default().as_secs_f64()))?;
+    os.raw_set("clock", func)
+}
+
+pub(crate) fn capabilities(lua: &Lua) -> Result<Capabilities> {
+    let version = match lua.globals().raw_get::<_, Option<StdString>>("_VERSION")? {
+        Some(version) => version,
+        None => default_version().to_string(),
+    };
+
+    let std_libs = lua.std_libs().names();
+    let all_std_libs = StdLib::ALL.names();
+    // `coroutine` is part of the base library in Lua 5.1
+    #[cfg(any(feature = "lua51", feature = "luajit"))]
+    let (std_libs, all_std_libs) = (
+        [&[ffi::LUA_COLIBNAME], &*std_libs].concat(),
+        [&[ffi::LUA_COLIBNAME], &*all_std_libs].concat(),
+    );
+    let mut modules = Vec::new();
+    if let Some(loaded) = lua.named_registry_value::<Option<Table>>("_LOADED")? {
+        for pair in loaded.pairs::<Value, Value>() {
+            if let (Value::String(name), _) = pair? {
+                match name.to_str() {
+                    Ok(name) if name != "_G" && !all_std_libs.contains(&name) => {
+                        modules.push(name.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    modules.sort();
+
+    Ok(Capabilities {
+        version,
+        mlua_version: env!("CARGO_PKG_VERSION"),
+        std_libs,
+        modules,
+        async_support: cfg!(feature = "async"),
+    })
+}
+
+pub(crate) fn expose_capabilities(lua: &Lua) -> Result<()> {
+    let caps = capabilities(lua)?;
+    let std_libs = name_set(
+        lua,
+        caps.std_libs.iter().copied(),
+        "host.capabilities.std_libs",
+    )?;
+    let modules = name_set(
+        lua,
+        caps.modules.iter().map(|m| m.as_str()),
+        "host.capabilities.modules",
+    )?;
+
+    let t = lua.create_table()?;
+    t.raw_set("version", caps.version)?;
+    t.raw_set("mlua_version", caps.mlua_version)?;
+    t.raw_set("std_libs", std_libs)?;
+    t.raw_set("modules", modules)?;
+    t.raw_set("async", caps.async_support)?;
+    let capabilities = read_only(lua, t, "host.capabilities")?;
+
+    let globals = lua.globals();
+    let host = match globals.get::<_, Option<Table>>("host")? {
+        Some(host) => host,
+        None => {
+            let host = lua.create_table()?;
+            globals.set("host", host.clone())?;
+            host
+        }
+    };
+    host.raw_set("capabilities", capabilities)
+}
+
+// Returns a read-only table with `true` values for the given names
+fn name_set<'lua, 'a>(
+    lua: &'lua Lua,
+    names: impl Iterator<Item = &'a str>,
+    path: &str,
+) -> Result<Table<'lua>> {
+    let t = lua.create_table()?;
+    for name in names {
+        t.raw_set(name, true)?;
+    }
+    read_only(lua, t, path)
+}
+
+// Returns a read-only view of `t`
+fn read_only<'lua>(lua: &'lua Lua, t: Table<'lua>, path: &str) -> Result<Table<'lua>> {
+    #[cfg(feature = "luau")]
+    {
+        let _ = (lua, path);
+        t.set_readonly(true);
+        Ok(t)
+    }
+
+    #[cfg(not(feature = "luau"))]
+    {
+        let msg = format!("attempt to modify read-only table '{path}'");
+        let newindex = lua.create_function(move |_, _: Variadic<Value>| {
+            Err::<(), _>(crate::error::Error::RuntimeError(msg.clone()))
+        })?;
+        let pairs = lua
+            .load("local t, next = ... return function() return next, t, nil end")
+            .try_cache()
+            .set_name("=__pairs")
+            .call::<_, Value>((t.clone(), lua.globals().raw_get::<_, Value>("next")?))?;
+
+        let mt = lua.create_table_with_capacity(0, 4)?;
+        mt.raw_set("__index", t)?;
+        mt.raw_set("__newindex", newindex)?;
+        mt.raw_set("__pairs", pairs)?;
+        mt.raw_set("__metatable", false)?;
+        let proxy = lua.create_table()?;
+        proxy.set_metatable(Some(mt));
+        Ok(proxy)
+    }
+}
+
+fn default_version() -> &'static str {
+    if cfg!(feature = "lua54") {
+        "Lua 5.4"
+    } else if cfg!(feature = "lua53") {
+        "Lua 5.3"
+    } else if cfg!(feature = "lua52") {
+        "Lua 5.2"
+    } else if cfg!(feature = "luau") {
+        "Luau"
+    } else {
+        "Lua 5.1"
+    }
+}
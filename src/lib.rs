@@ -79,76 +79,157 @@
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "bindgen")]
+mod bindgen;
+mod bit;
 mod chunk;
 mod conversion;
+mod docs;
+mod duplicate;
+mod encoding;
 mod error;
+#[cfg(feature = "async")]
+mod executor;
+mod expression;
 mod function;
+mod globals;
+mod heap;
 mod hook;
+mod host;
+mod locale;
 mod lua;
 #[cfg(feature = "luau")]
 mod luau;
 mod memory;
+mod mirror;
 mod multi;
+mod pack;
+mod sandbox;
 mod scope;
+mod shared;
+mod signal;
+mod source_map;
 mod stdlib;
 mod string;
+mod string_builder;
 mod table;
+mod table_class;
 mod thread;
+#[cfg(feature = "async")]
+mod timers;
 mod types;
 mod userdata;
 mod userdata_ext;
 mod userdata_impl;
 mod util;
 mod value;
+#[cfg(all(feature = "vector", not(feature = "luau")))]
+mod vector;
+mod weak;
 
 pub mod prelude;
 
 pub use ffi::{lua_CFunction, lua_State};
 
+pub use crate::bit::BitWidth;
 pub use crate::chunk::{AsChunk, Chunk, ChunkMode};
-pub use crate::error::{Error, ErrorContext, ExternalError, ExternalResult, Result};
-pub use crate::function::{Function, FunctionInfo};
-pub use crate::hook::{Debug, DebugEvent, DebugNames, DebugSource, DebugStack};
-pub use crate::lua::{GCMode, Lua, LuaOptions};
+pub use crate::conversion::{
+    CheckedConversion, ConversionLimits, ConversionPolicy, IntegerOverflow, SetMode,
+};
+pub use crate::docs::{ApiDoc, FnMeta};
+pub use crate::duplicate::DuplicatePolicy;
+pub use crate::encoding::Encoding;
+pub use crate::error::{
+    ConversionContext, Error, ErrorContext, ExternalError, ExternalResult, LuaResultExt, Result,
+};
+pub use crate::expression::ExpressionPolicy;
+pub use crate::function::{Function, FunctionInfo, FunctionKind, ReentrancyPolicy};
+pub use crate::globals::{GlobalPolicy, GlobalViolation, GlobalsTransaction};
+pub use crate::heap::{HeapAnalysis, KeyUsage, Retainer, TableGroup};
+pub use crate::hook::{Debug, DebugEvent, DebugNames, DebugSource, DebugStack, InstructionCounter};
+pub use crate::host::Capabilities;
+pub use crate::locale::LocalePolicy;
+pub use crate::lua::{GCMode, GcStats, Lua, LuaOptions, TracebackOptions};
+pub use crate::mirror::ValueMirror;
 pub use crate::multi::Variadic;
+pub use crate::sandbox::{
+    Sandbox, SandboxBuilder, DEFAULT_SANDBOX_BLOCKED, DEFAULT_SANDBOX_GLOBALS,
+};
 pub use crate::scope::Scope;
+pub use crate::shared::SharedDataSegment;
+pub use crate::signal::Signal;
+pub use crate::source_map::{MappedLocation, SourceMap};
 pub use crate::stdlib::StdLib;
 pub use crate::string::String;
-pub use crate::table::{Table, TableExt, TablePairs, TableSequence};
+pub use crate::string_builder::StringBuilder;
+pub use crate::table::{
+    ArrayView, HashOptions, LenMode, MapView, MetaPairs, MetatableBuilder, RawTable, Table,
+    TableExt, TablePairs, TableSequence, TableSequenceRef, TableWithMeta, ToStringMode,
+};
+pub use crate::table_class::TableClassRegistrar;
 pub use crate::thread::{Thread, ThreadStatus};
-pub use crate::types::{AppDataRef, AppDataRefMut, Integer, LightUserData, Number, RegistryKey};
+pub use crate::types::{
+    AppDataRef, AppDataRefMut, FamilyFn, Integer, LightUserData, Number, RegistryKey,
+};
 pub use crate::userdata::{
-    AnyUserData, MetaMethod, UserData, UserDataFields, UserDataMetatable, UserDataMethods,
-    UserDataRef, UserDataRefMut,
+    AnyUserData, BorrowPolicy, LuaObject, MetaMethod, MethodDesc, UserData, UserDataFields,
+    UserDataMetatable, UserDataMethods, UserDataRef, UserDataRefMut, Visibility,
 };
 pub use crate::userdata_ext::AnyUserDataExt;
 pub use crate::userdata_impl::UserDataRegistrar;
-pub use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil, Value};
+pub use crate::value::{
+    FromLua, FromLuaMulti, HashableValue, IntoLua, IntoLuaMulti, MultiValue, Nil, Value,
+};
+pub use crate::weak::{Downgrade, WeakRef};
 
 #[cfg(not(feature = "luau"))]
-pub use crate::hook::HookTriggers;
+pub use crate::{
+    hook::{HookGuard, HookTriggers, StepControl, StepInfo},
+    thread::ThreadBuilder,
+};
 
 #[cfg(any(feature = "luau", doc))]
 #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+pub use crate::{chunk::Compiler, function::CoverageInfo, types::VmState};
+
+#[cfg(any(feature = "luau", feature = "vector", doc))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "luau", feature = "vector"))))]
+pub use crate::types::Vector;
+
+#[cfg(feature = "async")]
 pub use crate::{
-    chunk::Compiler,
-    function::CoverageInfo,
-    types::{Vector, VmState},
+    executor::{ExecutorSignal, LuaExecutorIntegration, TaskId},
+    thread::AsyncThread,
 };
 
-#[cfg(feature = "async")]
-pub use crate::thread::AsyncThread;
+#[cfg(feature = "tokio")]
+pub use crate::executor::TokioIntegration;
+
+#[cfg(feature = "async-std")]
+pub use crate::executor::AsyncStdIntegration;
+
+#[cfg(feature = "serialize")]
+pub use crate::error::PortableError;
 
 #[cfg(feature = "serialize")]
 #[doc(inline)]
 pub use crate::serde::{
-    de::Options as DeserializeOptions, ser::Options as SerializeOptions, LuaSerdeExt,
+    de::Options as DeserializeOptions, ser::Options as SerializeOptions, ArrayFormat, LuaSerdeExt,
+    SerdePolicy,
 };
 
 #[cfg(feature = "serialize")]
 #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
 pub mod serde;
 
+#[cfg(feature = "plugin")]
+#[cfg_attr(docsrs, doc(cfg(feature = "plugin")))]
+pub mod plugin;
+
+#[cfg(feature = "bindgen")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bindgen")))]
+pub use crate::bindgen::Bindgen;
+
 #[cfg(feature = "mlua_derive")]
 #[allow(unused_imports)]
 #[macro_use]
@@ -157,7 +238,8 @@ extern crate mlua_derive;
 // Unstable features
 #[cfg(feature = "unstable")]
 pub use crate::{
-    function::OwnedFunction, string::OwnedString, table::OwnedTable, userdata::OwnedAnyUserData,
+    function::OwnedFunction, string::OwnedString, table::OwnedTable, thread::OwnedThread,
+    userdata::OwnedAnyUserData,
 };
 
 /// Create a type that implements [`AsChunk`] and can capture Rust variables.
@@ -248,6 +330,61 @@ pub use mlua_derive::chunk;
 #[cfg_attr(docsrs, doc(cfg(feature = "module")))]
 pub use mlua_derive::lua_module;
 
+/// Derives [`UserData`] and [`LuaObject`] for a struct with named fields.
+///
+/// See [`LuaObject`] for the supported attributes.
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::LuaObject;
+
+/// Derives [`FromLua`] for a struct with named fields, converting from a table with a key per
+/// field.
+///
+/// The following field attributes are supported:
+///
+/// - `#[lua(rename = "name")]`: use a different table key.
+/// - `#[lua(default)]`: use [`Default`] if the key is missing (or `nil`).
+/// - `#[lua(skip)]`: do not read the field from the table, it's initialized using [`Default`].
+///
+/// Unlike the serde based conversion, fields can hold any type implementing [`FromLua`],
+/// including userdata and Lua handles.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{FromLua, IntoLua, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// #[derive(FromLua, IntoLua)]
+/// struct Config {
+///     name: String,
+///     #[lua(rename = "max_players", default)]
+///     limit: u32,
+///     #[lua(skip)]
+///     loaded: bool,
+/// }
+///
+/// let config: Config = lua.load("{name = 'lobby'}").eval()?;
+/// assert_eq!((config.name.as_str(), config.limit, config.loaded), ("lobby", 0, false));
+///
+/// lua.globals().set("config", Config { limit: 8, ..config })?;
+/// assert_eq!(lua.load("config.max_players").eval::<u32>()?, 8);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::FromLua;
+
+/// Derives [`IntoLua`] for a struct with named fields, converting to a table with a key per
+/// field.
+///
+/// Supports the `#[lua(rename = "name")]` and `#[lua(skip)]` field attributes, see
+/// [`FromLua`](derive@FromLua) for details.
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use mlua_derive::IntoLua;
+
 pub(crate) mod private {
     use super::*;
 
@@ -258,4 +395,6 @@ pub(crate) mod private {
     impl Sealed for Lua {}
     impl Sealed for Table<'_> {}
     impl Sealed for AnyUserData<'_> {}
+    impl Sealed for Function<'_> {}
+    impl Sealed for Thread<'_> {}
 }
@@ -0,0 +1,152 @@
+use crate::error::Result;
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+use crate::types::{Integer, Number};
+use crate::value::{MultiValue, Nil, Value};
+
+// Named registry value holding the original functions replaced by `LocalePolicy::CIndependent`
+const ORIGINALS_KEY: &str = "__mlua_locale_originals";
+
+/// Determines how locale-sensitive standard library functions behave
+/// (see [`Lua::set_locale_policy`]).
+///
+/// Depending on the Lua version, number parsing and formatting and string case conversion are
+/// implemented using the C library, so their results depend on the locale of the whole process
+/// (eg. `tostring(1.5)` returns `"1,5"` under a German locale).
+///
+/// [`Lua::set_locale_policy`]: crate::Lua::set_locale_policy
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LocalePolicy {
+    /// Standard library functions follow the process locale (the default).
+    #[default]
+    Process,
+    /// `tonumber`, `tostring`, `string.upper` and `string.lower` behave as in the "C" locale,
+    /// regardless of the process locale.
+    ///
+    /// Decimal numerals are parsed using `.` as the decimal separator, numbers are formatted
+    /// using `.` and case conversion only applies to ASCII letters.
+    /// Conversions performed implicitly by the Lua VM (eg. string concatenation of numbers) are
+    /// not affected.
+    CIndependent,
+}
+
+pub(crate) fn set_locale_policy(lua: &Lua, policy: LocalePolicy) -> Result<()> {
+    let globals = lua.globals();
+    let string = globals.raw_get::<_, Option<Table>>("string")?;
+    let originals = lua.named_registry_value::<Option<Table>>(ORIGINALS_KEY)?;
+
+    match (policy, originals) {
+        (LocalePolicy::Process, Some(originals)) => {
+            globals.raw_set("tonumber", originals.raw_get::<_, Value>("tonumber")?)?;
+            globals.raw_set("tostring", originals.raw_get::<_, Value>("tostring")?)?;
+            if let Some(string) = string {
+                string.raw_set("upper", originals.raw_get::<_, Value>("upper")?)?;
+                string.raw_set("lower", originals.raw_get::<_, Value>("lower")?)?;
+            }
+            lua.unset_named_registry_value(ORIGINALS_KEY)
+        }
+        (LocalePolicy::CIndependent, None) => {
+            let originals = lua.create_table()?;
+            if let Some(tonumber) = globals.raw_get::<_, Option<Function>>("tonumber")? {
+                originals.raw_set("tonumber", tonumber.clone())?;
+                globals.raw_set("tonumber", create_tonumber(lua, tonumber)?)?;
+            }
+            if let Some(tostring) = globals.raw_get::<_, Option<Function>>("tostring")? {
+                originals.raw_set("tostring", tostring.clone())?;
+                globals.raw_set("tostring", create_tostring(lua, tostring)?)?;
+            }
+            if let Some(string) = string {
+                originals.raw_set("upper", string.raw_get::<_, Value>("upper")?)?;
+                originals.raw_set("lower", string.raw_get::<_, Value>("lower")?)?;
+                let upper = lua.create_function(|lua, s: String| {
+                    lua.create_string(s.as_bytes().to_ascii_uppercase())
+                })?;
+                let lower = lua.create_function(|lua, s: String| {
+                    lua.create_string(s.as_bytes().to_ascii_lowercase())
+                })?;
+                string.raw_set("upper", upper)?;
+                string.raw_set("lower", lower)?;
+            }
+            lua.set_named_registry_value(ORIGINALS_KEY, originals)
+        }
+        // The policy is already set
+        _ => Ok(()),
+    }
+}
+
+fn create_tonumber<'lua>(lua: &'lua Lua, orig: Function) -> Result<Function<'lua>> {
+    let orig = lua.create_registry_value(orig)?;
+    lua.create_function(move |lua, args: MultiValue| {
+        // Only decimal numerals are affected by the locale
+        if let (Some(Value::String(s)), None | Some(Nil)) = (args.get(0), args.get(1)) {
+            let s = trim_space(s.as_bytes());
+            if !s.iter().any(|&c| c == b'x' || c == b'X') {
+                return Ok(parse_decimal(s).unwrap_or(Nil));
+            }
+        }
+        lua.registry_value::<Function>(&orig)?.call(args)
+    })
+}
+
+fn create_tostring<'lua>(lua: &'lua Lua, orig: Function) -> Result<Function<'lua>> {
+    let orig = lua.create_registry_value(orig)?;
+    lua.create_function(move |lua, args: MultiValue| {
+        let is_float = matches!(args.get(0), Some(Value::Number(_)));
+        let result = lua.registry_value::<Function>(&orig)?.call(args)?;
+        match result {
+            Value::String(s) if is_float => Ok(Value::String(
+                lua.create_string(normalize_number(s.as_bytes()))?,
+            )),
+            result => Ok(result),
+        }
+    })
+}
+
+// Trims whitespace as defined by `isspace` in the "C" locale
+fn trim_space(mut s: &[u8]) -> &[u8] {
+    let is_space = |c: &u8| b" \t\n\x0b\x0c\r".contains(c);
+    while let Some((c, rest)) = s.split_first() {
+        if !is_space(c) {
+            break;
+        }
+        s = rest;
+    }
+    while let Some((c, rest)) = s.split_last() {
+        if !is_space(c) {
+            break;
+        }
+        s = rest;
+    }
+    s
+}
+
+// Parses a decimal numeral using the Lua syntax and `.` as the decimal separator
+fn parse_decimal(s: &[u8]) -> Option<Value<'static>> {
+    // Rejects everything `str::parse` accepts besides Lua numerals (eg. "inf" or "nan")
+    if !s.iter().all(|c| b"0123456789.eE+-".contains(c)) {
+        return None;
+    }
+    let s = std::str::from_utf8(s).ok()?;
+    if let Ok(i) = s.parse::<Integer>() {
+        return Some(Value::Integer(i));
+    }
+    s.parse::<Number>().ok().map(Value::Number)
+}
+
+// Replaces a locale-specific decimal separator in a formatted number with `.`
+fn normalize_number(s: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(s.len());
+    let mut in_separator = false;
+    for &c in s {
+        if c.is_ascii_alphanumeric() || c == b'+' || c == b'-' {
+            result.push(c);
+            in_separator = false;
+        } else if !in_separator {
+            result.push(b'.');
+            in_separator = true;
+        }
+    }
+    result
+}
@@ -1,43 +1,67 @@
-use std::any::TypeId;
-use std::cell::{RefCell, UnsafeCell};
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell, UnsafeCell};
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::io::Read;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::os::raw::{c_char, c_int, c_void};
 use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe, Location};
+use std::path::PathBuf;
 use std::ptr::NonNull;
 use std::result::Result as StdResult;
 use std::sync::atomic::{AtomicPtr, Ordering};
-use std::sync::{Arc, Mutex};
-use std::{mem, ptr, str};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{mem, ptr, slice, str};
 
 use rustc_hash::FxHashMap;
 
+use crate::bit::{self, BitWidth};
 use crate::chunk::{AsChunk, Chunk, ChunkMode};
+use crate::conversion::{BoxedU64, ConversionLimits, IntegerOverflow, SetMode};
+use crate::docs::{self, ApiDoc, FnMeta};
+use crate::duplicate::{self, DuplicatePolicy};
+use crate::encoding::{self, Encoding};
 use crate::error::{Error, Result};
-use crate::function::Function;
-use crate::hook::Debug;
+use crate::expression::{self, ExpressionPolicy};
+use crate::function::{Function, ReentrancyGuard, ReentrancyPolicy};
+use crate::globals::{self, GlobalPolicy, GlobalsTransaction};
+use crate::heap::{self, HeapAnalysis};
+use crate::hook::{Debug, InstructionCounter};
+use crate::host::{self, Capabilities};
+use crate::locale::{self, LocalePolicy};
 use crate::memory::{MemoryState, ALLOCATOR};
+#[cfg(feature = "plugin")]
+use crate::plugin::{self, PluginEntry};
+use crate::sandbox::SandboxBuilder;
 use crate::scope::Scope;
+use crate::shared::SharedDataSegment;
+use crate::signal::Signal;
+use crate::source_map::{self, apply_source_maps, ChunkMap, SourceMap, SourceMaps};
 use crate::stdlib::StdLib;
 use crate::string::String;
+use crate::string_builder::StringBuilder;
 use crate::table::Table;
+use crate::table_class::{self, TableClassRegistrar};
 use crate::thread::Thread;
 use crate::types::{
-    AppData, AppDataRef, AppDataRefMut, Callback, CallbackUpvalue, DestructedUserdata, Integer,
-    LightUserData, LuaRef, MaybeSend, Number, RegistryKey,
+    AppData, AppDataRef, AppDataRefMut, Callback, CallbackUpvalue, Clock, CoercionFn,
+    DeferredConversion, DestructedUserdata, FamilyFn, Integer, LightUserData, LuaRef, MaybeSend,
+    MirrorSync, Number, Preprocessor, RegistryKey, SourceResolver, UnrefList,
 };
-use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataCell};
-use crate::userdata_impl::{UserDataProxy, UserDataRegistrar};
+use crate::userdata::{AnyUserData, BorrowPolicy, MetaMethod, UserData, UserDataCell};
+use crate::userdata_impl::{self, AppDataProxy, UserDataProxy, UserDataRegistrar};
 use crate::util::{
     self, assert_stack, check_stack, get_destructed_userdata_metatable, get_gc_metatable,
     get_gc_userdata, get_main_state, get_userdata, init_error_registry, init_gc_metatable,
     init_userdata_metatable, pop_error, push_gc_userdata, push_string, push_table, rawset_field,
     safe_pcall, safe_xpcall, short_type_name, StackGuard, WrappedFailure,
 };
-use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil, Value};
+use crate::value::{
+    FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Nil, PrettyValue, Value,
+};
 
 #[cfg(not(feature = "lua54"))]
 use crate::util::push_userdata;
@@ -45,7 +69,17 @@ use crate::util::push_userdata;
 use crate::{types::WarnCallback, userdata::USER_VALUE_MAXSLOT, util::push_userdata_uv};
 
 #[cfg(not(feature = "luau"))]
-use crate::{hook::HookTriggers, types::HookCallback};
+use {
+    crate::{
+        hook::{
+            check_thread_limits, is_call_event, is_return_event, HookGuard, HookSampler,
+            HookTriggers, ThreadLimits, ThreadLimitsGuard, THREAD_LIMITS_MASK,
+        },
+        thread::ThreadBuilder,
+        types::HookCallback,
+    },
+    std::sync::atomic::AtomicU64,
+};
 
 #[cfg(feature = "luau")]
 use crate::types::InterruptCallback;
@@ -57,6 +91,8 @@ use crate::{
 
 #[cfg(feature = "async")]
 use {
+    crate::executor::{self, AsyncTasks, LuaExecutorIntegration, TaskId},
+    crate::timers,
     crate::types::{AsyncCallback, AsyncCallbackUpvalue, AsyncPollUpvalue},
     futures_util::future::{self, Future},
     futures_util::task::{noop_waker_ref, Context, Poll, Waker},
@@ -85,15 +121,57 @@ pub(crate) struct ExtraData {
     registered_userdata: FxHashMap<TypeId, c_int>,
     registered_userdata_mt: FxHashMap<*const c_void, Option<TypeId>>,
     last_checked_userdata_mt: (*const c_void, Option<TypeId>),
+    // Weak tables with live instances of tracked userdata types
+    tracked_userdata: FxHashMap<TypeId, c_int>,
+    // Weak tables and key functions of userdata types with enabled reverse lookup
+    userdata_lookup: FxHashMap<TypeId, (c_int, Box<dyn Any + Send>)>,
+    // Userdata types with a non-default borrow policy
+    borrow_policies: FxHashMap<TypeId, BorrowPolicy>,
+    // Tables with host-only methods and fields of userdata types
+    host_only_members: FxHashMap<TypeId, c_int>,
 
     // When Lua instance dropped, setting `None` would prevent collecting `RegistryKey`s
-    registry_unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
+    registry_unref_list: Arc<UnrefList>,
 
     // Container to store arbitrary data (extensions)
     app_data: AppData,
+    // Attached `ValueMirror`s
+    mirrors: Vec<MirrorSync>,
+    traceback_options: Option<TracebackOptions>,
+
+    // Nesting level of Rust values being converted to Lua tables, and its limit
+    conversion_depth: usize,
+    max_conversion_depth: Option<usize>,
+    // Nested tables waiting to be filled by the outermost conversion, and the depth of the one
+    // being filled
+    conversion_queue: Vec<DeferredConversion>,
+    conversion_base: usize,
+    // Nesting depth of tables being converted into Rust values
+    from_conversion_depth: usize,
+    conversion_limits: ConversionLimits,
+    integer_overflow: IntegerOverflow,
+    set_mode: SetMode,
+    gc_stats: GcStats,
+    // Coercions registered by `Lua::register_coercion`, by target type
+    coercions: FxHashMap<TypeId, Vec<Coercion>>,
+    // Source maps attached to chunks by `Chunk::set_source_map`
+    source_maps: SourceMaps,
+    // Resolver of chunk sources set by `Lua::set_source_resolver`
+    source_resolver: Option<SourceResolver>,
+    // Source transformer set by `Lua::set_preprocessor`
+    preprocessor: Option<Preprocessor>,
+    // Clock set by `Lua::set_clock`
+    clock: Option<Clock>,
+    // Creation time of the state, the origin of the default clock
+    #[cfg(not(feature = "embedded"))]
+    created_at: std::time::Instant,
+    // Transformed sources of preprocessed chunks
+    preprocessed_sources: ChunkMap<std::string::String>,
 
     safe: bool,
     libs: StdLib,
+    // Options the state was created with, to create duplicates with the same options
+    options: LuaOptions,
     mem_state: Option<NonNull<MemoryState>>,
 
     ref_thread: *mut ffi::lua_State,
@@ -115,11 +193,59 @@ pub(crate) struct ExtraData {
     // Waker for polling futures
     #[cfg(feature = "async")]
     waker: NonNull<Waker>,
+    // Default number of futures polled per resume of an async thread
+    #[cfg(feature = "async")]
+    async_poll_budget: Option<usize>,
+    // Number of futures that can be polled until the end of the current resume
+    #[cfg(feature = "async")]
+    poll_budget_left: Option<usize>,
+    // Async tasks driven by `Lua::poll_pending_async`
+    #[cfg(feature = "async")]
+    async_tasks: AsyncTasks,
+    // Logical async task being polled
+    #[cfg(feature = "async")]
+    current_task: Option<TaskId>,
+    // Tasks waiting for a borrowed userdata to be released, by userdata address
+    #[cfg(feature = "async")]
+    borrow_waiters: FxHashMap<*const c_void, Vec<Waker>>,
+    // Userdata borrowed by the async methods being polled right now (innermost last)
+    #[cfg(feature = "async")]
+    polled_borrows: Vec<*const c_void>,
 
     #[cfg(not(feature = "luau"))]
     hook_callback: Option<HookCallback>,
     #[cfg(not(feature = "luau"))]
     hook_thread: *mut ffi::lua_State,
+    #[cfg(not(feature = "luau"))]
+    hook_triggers: HookTriggers,
+    // Whether the hook is called for all threads instead of `hook_thread` only
+    #[cfg(not(feature = "luau"))]
+    hook_all_threads: bool,
+    #[cfg(not(feature = "luau"))]
+    hook_sampler: HookSampler,
+    // Hooks replaced by `Lua::push_hook`, to restore when guards are dropped
+    #[cfg(not(feature = "luau"))]
+    hook_stack: Vec<SavedHook>,
+    #[cfg(not(feature = "luau"))]
+    hook_guard_id: usize,
+    // Number of VM instructions counted by `Lua::count_instructions`
+    instruction_count: u64,
+    // Number of active `InstructionCounter` guards
+    #[cfg(feature = "luau")]
+    instruction_counters: usize,
+    // Limit and number of interrupts counted by `Lua::with_instruction_limit`
+    #[cfg(feature = "luau")]
+    instruction_limit: Option<(u64, u64)>,
+    // Execution limits of threads created by `ThreadBuilder`, by thread address
+    #[cfg(not(feature = "luau"))]
+    thread_limits: FxHashMap<*mut ffi::lua_State, ThreadLimits>,
+    // Weak table holding guards that remove the limits of collected threads
+    #[cfg(not(feature = "luau"))]
+    thread_limits_guards: Option<c_int>,
+    #[cfg(not(feature = "luau"))]
+    thread_limits_id: u64,
+    // Backing table of protected global variables (see `Lua::protect_globals`)
+    protected_globals: Option<c_int>,
     #[cfg(feature = "lua54")]
     warn_callback: Option<WarnCallback>,
     #[cfg(feature = "luau")]
@@ -131,6 +257,26 @@ pub(crate) struct ExtraData {
     compiler: Option<Compiler>,
     #[cfg(feature = "luau-jit")]
     enable_jit: bool,
+    // Whether `Lua::load` detects binary chunks
+    allow_bytecode: bool,
+}
+
+// Coercion registered by `Lua::register_coercion`
+struct Coercion {
+    // Returns `None` if the value cannot be converted to the source type
+    convert: CoercionFn,
+    into_lua: for<'lua> fn(&'lua Lua, Box<dyn Any>) -> Result<Value<'lua>>,
+}
+
+// Hook replaced by `Lua::push_hook`
+#[cfg(not(feature = "luau"))]
+struct SavedHook {
+    // Id of the guard that replaced the hook
+    id: usize,
+    callback: Option<HookCallback>,
+    thread: *mut ffi::lua_State,
+    triggers: HookTriggers,
+    all_threads: bool,
 }
 
 /// Mode of the Lua garbage collector (GC).
@@ -150,6 +296,20 @@ pub enum GCMode {
     Generational,
 }
 
+/// Statistics of the Lua garbage collector (GC), returned by [`Lua::gc_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GcStats {
+    /// Memory (in bytes) currently allocated by the Lua state, same as [`Lua::used_memory`].
+    pub allocated_bytes: usize,
+    /// Number of steps performed with [`Lua::gc_step`] or [`Lua::gc_step_kbytes`].
+    pub steps: u64,
+    /// Number of collection cycles finished by [`Lua::gc_collect`] or by a step.
+    ///
+    /// Cycles finished by the automatic collector are not counted.
+    pub collections: u64,
+}
+
 /// Controls Lua interpreter behavior such as Rust panics handling.
 #[derive(Clone, Debug)]
 #[non_exhaustive]
@@ -218,12 +378,133 @@ impl LuaOptions {
     }
 }
 
+/// Controls formatting of stack tracebacks generated by mlua.
+///
+/// The options are applied to tracebacks attached to runtime errors, to [`Error::CallbackError`]
+/// and to error contexts added using [`LuaResultExt::with_lua_context`].
+///
+/// [`LuaResultExt::with_lua_context`]: crate::LuaResultExt::with_lua_context
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TracebackOptions {
+    /// Maximum number of frames to keep in a traceback.
+    ///
+    /// Remaining frames are replaced with a single line containing their count.
+    ///
+    /// Default: **None** (unlimited)
+    pub max_frames: Option<usize>,
+
+    /// Skip frames of anonymous C functions (`[C]: in ?`).
+    ///
+    /// These are mostly internal wrappers used by mlua (eg. protected calls and error handlers)
+    /// and rarely help to locate an error.
+    ///
+    /// Default: **false**
+    pub skip_internal: bool,
+
+    /// Keep only the file name of chunk sources (eg. `main.lua` instead of `scripts/app/main.lua`).
+    ///
+    /// Default: **false**
+    pub source_shortening: bool,
+}
+
+impl Default for TracebackOptions {
+    fn default() -> Self {
+        TracebackOptions::new()
+    }
+}
+
+impl TracebackOptions {
+    /// Returns a new instance of `TracebackOptions` with default parameters.
+    pub const fn new() -> Self {
+        TracebackOptions {
+            max_frames: None,
+            skip_internal: false,
+            source_shortening: false,
+        }
+    }
+
+    /// Sets [`max_frames`] option.
+    ///
+    /// [`max_frames`]: #structfield.max_frames
+    #[must_use]
+    pub const fn max_frames(mut self, max_frames: Option<usize>) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Sets [`skip_internal`] option.
+    ///
+    /// [`skip_internal`]: #structfield.skip_internal
+    #[must_use]
+    pub const fn skip_internal(mut self, enabled: bool) -> Self {
+        self.skip_internal = enabled;
+        self
+    }
+
+    /// Sets [`source_shortening`] option.
+    ///
+    /// [`source_shortening`]: #structfield.source_shortening
+    #[must_use]
+    pub const fn source_shortening(mut self, enabled: bool) -> Self {
+        self.source_shortening = enabled;
+        self
+    }
+
+    // Reformats frames of the (last) traceback in the string
+    fn apply(&self, traceback: &str) -> std::string::String {
+        const HEADER: &str = "stack traceback:";
+        let (head, frames) = match traceback.rfind(HEADER) {
+            Some(pos) => traceback.split_at(pos + HEADER.len()),
+            None => return traceback.to_string(),
+        };
+
+        let mut result = std::string::String::from(head);
+        let (mut count, mut skipped) = (0, 0);
+        for frame in frames.split('\n').map(|f| f.trim_start_matches('\t')) {
+            if frame.is_empty() || (self.skip_internal && frame == "[C]: in ?") {
+                continue;
+            }
+            if matches!(self.max_frames, Some(max) if count >= max) {
+                skipped += 1;
+                continue;
+            }
+            count += 1;
+            result.push_str("\n\t");
+            match frame.split_once(": in ") {
+                Some((source, rest)) if self.source_shortening && !source.starts_with('[') => {
+                    let (source, line) = match source.rsplit_once(':') {
+                        Some((source, line)) if line.bytes().all(|c| c.is_ascii_digit()) => {
+                            (source, Some(line))
+                        }
+                        _ => (source, None),
+                    };
+                    result.push_str(source.rsplit(['/', '\\']).next().unwrap_or(source));
+                    if let Some(line) = line {
+                        result.push(':');
+                        result.push_str(line);
+                    }
+                    result.push_str(": in ");
+                    result.push_str(rest);
+                }
+                _ => result.push_str(frame),
+            }
+        }
+        if skipped > 0 {
+            result.push_str(&format!("\n\t...({skipped} more frames)"));
+        }
+        result
+    }
+}
+
 #[cfg(feature = "async")]
 pub(crate) static ASYNC_POLL_PENDING: u8 = 0;
 pub(crate) static EXTRA_REGISTRY_KEY: u8 = 0;
 
 const WRAPPED_FAILURE_POOL_SIZE: usize = 64;
 const MULTIVALUE_POOL_SIZE: usize = 64;
+// Levels of nested tables converted recursively before deferring the rest to the work queue
+const INLINE_CONVERSION_DEPTH: usize = 32;
 
 /// Requires `feature = "send"`
 #[cfg(feature = "send")]
@@ -257,7 +538,7 @@ impl Drop for ExtraData {
             self.inner.assume_init_drop();
         }
 
-        *mlua_expect!(self.registry_unref_list.lock(), "unref list poisoned") = None;
+        self.registry_unref_list.close();
         if let Some(mem_state) = self.mem_state {
             drop(unsafe { Box::from_raw(mem_state.as_ptr()) });
         }
@@ -289,10 +570,16 @@ impl Lua {
     /// See [`StdLib`] documentation for a list of unsafe modules that cannot be loaded.
     ///
     /// [`StdLib`]: crate::StdLib
+    ///
+    /// With the `embedded` feature the `io` library is not loaded, as it requires a file system.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Lua {
+        #[cfg(all(feature = "embedded", not(feature = "luau")))]
+        let libs = StdLib::ALL_SAFE ^ StdLib::IO;
+        #[cfg(not(all(feature = "embedded", not(feature = "luau"))))]
+        let libs = StdLib::ALL_SAFE;
         mlua_expect!(
-            Self::new_with(StdLib::ALL_SAFE, LuaOptions::default()),
+            Self::new_with(libs, LuaOptions::default()),
             "Cannot create new safe Lua state"
         )
     }
@@ -334,7 +621,7 @@ impl Lua {
 
         #[cfg(not(feature = "luau"))]
         if libs.contains(StdLib::PACKAGE) {
-            mlua_expect!(lua.disable_c_modules(), "Error during disabling C modules");
+            lua.disable_c_modules()?;
         }
         unsafe { (*lua.extra.get()).safe = true };
 
@@ -404,6 +691,7 @@ impl Lua {
         let lua = Lua::init_from_ptr(state);
         let extra = lua.extra.get();
         (*extra).mem_state = NonNull::new(mem_state);
+        (*extra).options = options.clone();
 
         mlua_expect!(
             load_from_std_lib(state, libs),
@@ -411,6 +699,14 @@ impl Lua {
         );
         (*extra).libs |= libs;
 
+        #[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
+        if libs.contains(StdLib::STRING) {
+            mlua_expect!(
+                crate::pack::polyfill_string_library(&lua),
+                "Error during loading standard libraries"
+            );
+        }
+
         if !options.catch_rust_panics {
             mlua_expect!(
                 (|| -> Result<()> {
@@ -509,10 +805,34 @@ impl Lua {
             registered_userdata: FxHashMap::default(),
             registered_userdata_mt: FxHashMap::default(),
             last_checked_userdata_mt: (ptr::null(), None),
-            registry_unref_list: Arc::new(Mutex::new(Some(Vec::new()))),
+            tracked_userdata: FxHashMap::default(),
+            userdata_lookup: FxHashMap::default(),
+            borrow_policies: FxHashMap::default(),
+            host_only_members: FxHashMap::default(),
+            registry_unref_list: Arc::new(UnrefList::new()),
             app_data: AppData::default(),
+            mirrors: Vec::new(),
+            traceback_options: None,
+            conversion_depth: 0,
+            max_conversion_depth: None,
+            conversion_queue: Vec::new(),
+            conversion_base: 0,
+            from_conversion_depth: 0,
+            conversion_limits: ConversionLimits::new(),
+            integer_overflow: IntegerOverflow::default(),
+            set_mode: SetMode::default(),
+            gc_stats: GcStats::default(),
+            coercions: FxHashMap::default(),
+            source_maps: SourceMaps::default(),
+            source_resolver: None,
+            preprocessor: None,
+            clock: None,
+            #[cfg(not(feature = "embedded"))]
+            created_at: std::time::Instant::now(),
+            preprocessed_sources: ChunkMap::default(),
             safe: false,
             libs: StdLib::NONE,
+            options: LuaOptions::new(),
             mem_state: None,
             ref_thread,
             // We need 1 extra stack space to move values in and out of the ref stack.
@@ -526,10 +846,44 @@ impl Lua {
             wrapped_failure_mt_ptr,
             #[cfg(feature = "async")]
             waker: NonNull::from(noop_waker_ref()),
+            #[cfg(feature = "async")]
+            async_poll_budget: None,
+            #[cfg(feature = "async")]
+            poll_budget_left: None,
+            #[cfg(feature = "async")]
+            async_tasks: AsyncTasks::default(),
+            #[cfg(feature = "async")]
+            current_task: None,
+            #[cfg(feature = "async")]
+            borrow_waiters: FxHashMap::default(),
+            #[cfg(feature = "async")]
+            polled_borrows: Vec::new(),
             #[cfg(not(feature = "luau"))]
             hook_callback: None,
             #[cfg(not(feature = "luau"))]
             hook_thread: ptr::null_mut(),
+            #[cfg(not(feature = "luau"))]
+            hook_triggers: HookTriggers::new(),
+            #[cfg(not(feature = "luau"))]
+            hook_all_threads: false,
+            #[cfg(not(feature = "luau"))]
+            hook_sampler: HookSampler::default(),
+            #[cfg(not(feature = "luau"))]
+            hook_stack: Vec::new(),
+            #[cfg(not(feature = "luau"))]
+            hook_guard_id: 0,
+            instruction_count: 0,
+            #[cfg(feature = "luau")]
+            instruction_counters: 0,
+            #[cfg(feature = "luau")]
+            instruction_limit: None,
+            #[cfg(not(feature = "luau"))]
+            thread_limits: FxHashMap::default(),
+            #[cfg(not(feature = "luau"))]
+            thread_limits_guards: None,
+            #[cfg(not(feature = "luau"))]
+            thread_limits_id: 0,
+            protected_globals: None,
             #[cfg(feature = "lua54")]
             warn_callback: None,
             #[cfg(feature = "luau")]
@@ -540,6 +894,7 @@ impl Lua {
             compiler: None,
             #[cfg(feature = "luau-jit")]
             enable_jit: true,
+            allow_bytecode: true,
         }));
 
         // Store it in the registry
@@ -618,14 +973,58 @@ impl Lua {
         {
             let curr_libs = unsafe { (*self.extra.get()).libs };
             if is_safe && (curr_libs ^ (curr_libs | libs)).contains(StdLib::PACKAGE) {
-                mlua_expect!(self.disable_c_modules(), "Error during disabling C modules");
+                self.disable_c_modules()?;
             }
         }
         unsafe { (*self.extra.get()).libs |= libs };
 
+        #[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
+        if res.is_ok() && libs.contains(StdLib::STRING) {
+            crate::pack::polyfill_string_library(self)?;
+        }
+
         res
     }
 
+    /// Creates a new Lua state with the same standard libraries and [options](LuaOptions), and a
+    /// deep copy of the globals.
+    ///
+    /// This is useful to spin up variants of a state (eg. test fixtures or speculative
+    /// simulations) without rerunning initialization scripts. Tables keep their identity and
+    /// metatables, Lua functions are copied as bytecode (together with their upvalues) and the
+    /// standard library functions are taken from the new state. How other values are handled
+    /// (userdata, threads and Rust functions) is controlled by [`DuplicatePolicy`].
+    ///
+    /// Only the globals are copied. Other parts of the state (eg. registry values, app data,
+    /// hooks or memory limits) are not.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{DuplicatePolicy, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.load("counter = { value = 1 }; function bump() counter.value = counter.value + 1 end")
+    ///     .exec()?;
+    ///
+    /// let copy = lua.duplicate(DuplicatePolicy::new())?;
+    /// copy.load("bump()").exec()?;
+    /// assert_eq!(copy.load("counter.value").eval::<i32>()?, 2);
+    /// assert_eq!(lua.load("counter.value").eval::<i32>()?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn duplicate(&self, policy: DuplicatePolicy) -> Result<Lua> {
+        let extra = unsafe { &*self.extra.get() };
+        let (libs, options) = (extra.libs, extra.options.clone());
+        let lua = match extra.safe {
+            true => Lua::new_with(libs, options)?,
+            false => unsafe { Lua::unsafe_new_with(libs, options) },
+        };
+        duplicate::copy_globals(self, &lua, policy)?;
+        Ok(lua)
+    }
+
     /// Loads module `modname` into an existing Lua state using the specified entrypoint
     /// function.
     ///
@@ -840,6 +1239,7 @@ impl Lua {
     /// If you want to set a hook function for a thread (coroutine), use [`Thread::set_hook()`] instead.
     ///
     /// Please note you cannot have more than one hook function set at a time for this Lua instance.
+    /// Use [`Lua::push_hook()`] to temporarily replace the hook and restore it afterwards.
     ///
     /// # Example
     ///
@@ -877,164 +1277,498 @@ impl Lua {
         Ok(())
     }
 
-    /// Sets a 'hook' function for a thread (coroutine).
+    /// Installs a 'hook' function on top of the currently active one.
+    ///
+    /// Works like [`Lua::set_hook()`], but returns a [`HookGuard`]. Dropping the guard removes the
+    /// hook and restores the hook that was active before, so profilers, debuggers and watchdogs
+    /// can be used together without clobbering each other's hooks.
+    ///
+    /// Guards can be dropped in any order: dropping a guard of a hook that was replaced by a newer
+    /// one only updates the hook to restore when the newer guard is dropped.
+    ///
+    /// A hook set for a thread by [`Thread::set_hook()`] is restored only if it was not triggered
+    /// while a pushed hook was active.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use mlua::{Lua, HookTriggers, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_hook(HookTriggers::EVERY_LINE, |_lua, debug| {
+    ///     println!("line {}", debug.curr_line());
+    ///     Ok(())
+    /// })?;
+    ///
+    /// {
+    ///     let _guard = lua.push_hook(HookTriggers::ON_CALLS, |_lua, debug| {
+    ///         println!("call {:?}", debug.names().name);
+    ///         Ok(())
+    ///     })?;
+    ///     lua.load("print('calls are traced')").exec()?;
+    /// }
+    ///
+    /// // The line hook is active again
+    /// lua.load("local x = 1").exec()
+    /// # }
+    /// ```
+    ///
+    /// [`Thread::set_hook()`]: crate::Thread::set_hook
     #[cfg(not(feature = "luau"))]
-    pub(crate) unsafe fn set_thread_hook<F>(
-        &self,
-        state: *mut ffi::lua_State,
-        triggers: HookTriggers,
-        callback: F,
-    ) where
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn push_hook<F>(&self, triggers: HookTriggers, callback: F) -> Result<HookGuard>
+    where
         F: Fn(&Lua, Debug) -> Result<()> + MaybeSend + 'static,
     {
-        unsafe extern "C" fn hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
-            let extra = extra_data(state);
-            if (*extra).hook_thread != state {
-                // Hook was destined for a different thread, ignore
-                ffi::lua_sethook(state, None, 0, 0);
-                return;
-            }
-            callback_error_ext(state, extra, move |_| {
-                let hook_cb = (*extra).hook_callback.clone();
-                let hook_cb = mlua_expect!(hook_cb, "no hook callback set in hook_proc");
-                if Arc::strong_count(&hook_cb) > 2 {
-                    return Ok(()); // Don't allow recursion
-                }
-                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
-                let _guard = StateGuard::new(&lua.0, state);
-                let debug = Debug::new(lua, ar);
-                hook_cb(lua, debug)
-            })
-        }
-
-        (*self.extra.get()).hook_callback = Some(Arc::new(callback));
-        (*self.extra.get()).hook_thread = state; // Mark for what thread the hook is set
-        ffi::lua_sethook(state, Some(hook_proc), triggers.mask(), triggers.count());
+        self.push_hook_inner(triggers, false, callback)
     }
 
-    /// Removes any hook previously set by [`Lua::set_hook()`] or [`Thread::set_hook()`].
-    ///
-    /// This function has no effect if a hook was not previously set.
+    // Installs a hook on top of the currently active one.
+    //
+    // If `all_threads` is set, the hook is also called for the running thread and for the
+    // coroutines created while it's active (they inherit the hook from the creating thread).
     #[cfg(not(feature = "luau"))]
-    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
-    pub fn remove_hook(&self) {
+    pub(crate) fn push_hook_inner<F>(
+        &self,
+        triggers: HookTriggers,
+        all_threads: bool,
+        callback: F,
+    ) -> Result<HookGuard>
+    where
+        F: Fn(&Lua, Debug) -> Result<()> + MaybeSend + 'static,
+    {
         unsafe {
-            let state = self.state();
-            ffi::lua_sethook(state, None, 0, 0);
-            match get_main_state(self.main_state) {
-                Some(main_state) if !ptr::eq(state, main_state) => {
-                    // If main_state is different from state, remove hook from it too
-                    ffi::lua_sethook(main_state, None, 0, 0);
+            let state = get_main_state(self.main_state).ok_or(Error::MainThreadNotAvailable)?;
+            let extra = &mut *self.extra.get();
+            extra.hook_guard_id += 1;
+            let id = extra.hook_guard_id;
+            extra.hook_stack.push(SavedHook {
+                id,
+                callback: extra.hook_callback.take(),
+                thread: extra.hook_thread,
+                triggers: extra.hook_triggers,
+                all_threads: extra.hook_all_threads,
+            });
+            self.set_thread_hook(state, triggers, callback);
+            if all_threads {
+                extra.hook_all_threads = true;
+                let current = self.state();
+                if !ptr::eq(current, state) {
+                    let mask = ffi::lua_gethookmask(state);
+                    ffi::lua_sethook(current, Some(hook_proc), mask, triggers.count());
                 }
-                _ => {}
-            };
-            (*self.extra.get()).hook_callback = None;
-            (*self.extra.get()).hook_thread = ptr::null_mut();
+            }
+            Ok(HookGuard::new(self, id))
         }
     }
 
-    /// Sets an 'interrupt' function that will periodically be called by Luau VM.
+    /// Starts counting executed VM instructions, until the returned guard is dropped.
     ///
-    /// Any Luau code is guaranteed to call this handler "eventually"
-    /// (in practice this can happen at any function call or at any loop iteration).
+    /// The counter is updated every `interval` instructions by a hook installed using
+    /// [`Lua::push_hook()`], so the count is a multiple of `interval`. Small intervals give more
+    /// precise counts at a higher cost. Use [`Lua::instruction_count()`] to read the counter and
+    /// [`Lua::reset_instruction_count()`] to start accounting for a new script or request.
     ///
-    /// The provided interrupt function can error, and this error will be propagated through
-    /// the Luau code that was executing at the time the interrupt was triggered.
-    /// Also this can be used to implement continuous execution limits by instructing Luau VM to yield
-    /// by returning [`VmState::Yield`].
+    /// The hook is called for the running thread and for coroutines created while counting,
+    /// which inherit it from the creating thread. Coroutines created before counting has
+    /// started are not counted.
     ///
-    /// This is similar to [`Lua::set_hook`] but in more simplified form.
+    /// Counting pauses while a hook pushed later (using [`Lua::push_hook()`] or another counter) is
+    /// active, so nested counters do not count the same instructions twice. On LuaJIT,
+    /// instructions of JIT-compiled code are not counted.
     ///
-    /// # Example
+    /// On Luau, which has no instruction hooks, the counter is incremented at every interrupt
+    /// check (at function calls and loop iterations) of any thread and `interval` is ignored.
     ///
-    /// Periodically yield Luau VM to suspend execution.
+    /// # Examples
     ///
     /// ```
-    /// # use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
-    /// # use mlua::{Lua, Result, ThreadStatus, VmState};
+    /// # use mlua::{Lua, Result};
     /// # fn main() -> Result<()> {
     /// let lua = Lua::new();
-    /// let count = Arc::new(AtomicU64::new(0));
-    /// lua.set_interrupt(move |_| {
-    ///     if count.fetch_add(1, Ordering::Relaxed) % 2 == 0 {
-    ///         return Ok(VmState::Yield);
-    ///     }
-    ///     Ok(VmState::Continue)
-    /// });
-    ///
-    /// let co = lua.create_thread(
-    ///     lua.load(r#"
-    ///         local b = 0
-    ///         for _, x in ipairs({1, 2, 3}) do b += x end
-    ///     "#)
-    ///     .into_function()?,
-    /// )?;
-    /// while co.status() == ThreadStatus::Resumable {
-    ///     co.resume(())?;
-    /// }
+    /// let _guard = lua.count_instructions(1)?;
+    /// lua.load("for i = 1, 100 do end").exec()?;
+    /// let count = lua.reset_instruction_count();
+    /// assert!(count >= 100);
+    /// assert_eq!(lua.instruction_count(), 0);
     /// # Ok(())
     /// # }
     /// ```
-    #[cfg(any(feature = "luau", docsrs))]
-    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
-    pub fn set_interrupt<F>(&self, callback: F)
-    where
-        F: Fn(&Lua) -> Result<VmState> + MaybeSend + 'static,
-    {
-        unsafe extern "C" fn interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
-            if gc >= 0 {
-                // We don't support GC interrupts since they cannot survive Lua exceptions
-                return;
-            }
-            let extra = extra_data(state);
-            let result = callback_error_ext(state, extra, move |_| {
-                let interrupt_cb = (*extra).interrupt_callback.clone();
-                let interrupt_cb =
-                    mlua_expect!(interrupt_cb, "no interrupt callback set in interrupt_proc");
-                if Arc::strong_count(&interrupt_cb) > 2 {
-                    return Ok(VmState::Continue); // Don't allow recursion
-                }
-                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
-                let _guard = StateGuard::new(&lua.0, state);
-                interrupt_cb(lua)
-            });
-            match result {
-                VmState::Continue => {}
-                VmState::Yield => {
-                    ffi::lua_yield(state, 0);
-                }
-            }
+    pub fn count_instructions(&self, interval: u32) -> Result<InstructionCounter> {
+        #[cfg(not(feature = "luau"))]
+        {
+            let interval = interval.max(1);
+            let triggers = HookTriggers::new().every_nth_instruction(interval);
+            let guard = self.push_hook_inner(triggers, true, move |lua, _| {
+                unsafe { (*lua.extra.get()).instruction_count += interval as u64 };
+                Ok(())
+            })?;
+            Ok(InstructionCounter::new(guard))
         }
-
+        #[cfg(feature = "luau")]
         unsafe {
-            (*self.extra.get()).interrupt_callback = Some(Arc::new(callback));
+            let _ = interval;
+            (*self.extra.get()).instruction_counters += 1;
             (*ffi::lua_callbacks(self.main_state)).interrupt = Some(interrupt_proc);
+            Ok(InstructionCounter::new(self))
         }
     }
 
-    /// Removes any 'interrupt' previously set by `set_interrupt`.
-    ///
-    /// This function has no effect if an 'interrupt' was not previously set.
-    #[cfg(any(feature = "luau", docsrs))]
-    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
-    pub fn remove_interrupt(&self) {
+    // Called when an `InstructionCounter` is dropped
+    #[cfg(feature = "luau")]
+    pub(crate) fn stop_counting_instructions(&self) {
         unsafe {
-            (*self.extra.get()).interrupt_callback = None;
-            (*ffi::lua_callbacks(self.main_state)).interrupt = None;
+            let extra = &mut *self.extra.get();
+            extra.instruction_counters -= 1;
         }
+        self.release_interrupt();
     }
 
-    /// Sets the warning function to be used by Lua to emit warnings.
+    // Removes the interrupt once nothing (a callback, counter or limit) needs it anymore
+    #[cfg(feature = "luau")]
+    fn release_interrupt(&self) {
+        unsafe {
+            let extra = &*self.extra.get();
+            if extra.instruction_counters == 0
+                && extra.instruction_limit.is_none()
+                && extra.interrupt_callback.is_none()
+            {
+                (*ffi::lua_callbacks(self.main_state)).interrupt = None;
+            }
+        }
+    }
+
+    /// Returns the number of VM instructions counted since the last reset.
     ///
-    /// Requires `feature = "lua54"`
-    #[cfg(feature = "lua54")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
-    pub fn set_warning_function<F>(&self, callback: F)
-    where
-        F: 'static + MaybeSend + Fn(&Lua, &CStr, bool) -> Result<()>,
-    {
-        unsafe extern "C" fn warn_proc(ud: *mut c_void, msg: *const c_char, tocont: c_int) {
-            let extra = ud as *mut ExtraData;
+    /// See [`Lua::count_instructions()`].
+    pub fn instruction_count(&self) -> u64 {
+        unsafe { (*self.extra.get()).instruction_count }
+    }
+
+    /// Resets the instruction counter, returning the previous count.
+    ///
+    /// See [`Lua::count_instructions()`].
+    pub fn reset_instruction_count(&self) -> u64 {
+        unsafe { mem::take(&mut (*self.extra.get()).instruction_count) }
+    }
+
+    /// Calls `f`, aborting Lua code it runs with [`Error::InstructionLimitExceeded`] once `limit`
+    /// VM instructions are executed.
+    #[cfg(not(feature = "luau"))]
+    pub(crate) fn with_instruction_limit<R>(
+        &self,
+        limit: u64,
+        f: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        // Small limits are checked exactly, larger ones every 1000 instructions
+        let interval = limit.clamp(1, 1000);
+        let executed = Arc::new(AtomicU64::new(0));
+        let counter = executed.clone();
+        let triggers = HookTriggers::new().every_nth_instruction(interval as u32);
+        let guard = self.push_hook_inner(triggers, true, move |lua, _| {
+            if counter.fetch_add(interval, Ordering::Relaxed) + interval > limit {
+                // Raise the error on every instruction, so the script cannot catch it and continue
+                unsafe {
+                    let extra = &mut *lua.extra.get();
+                    let state = lua.state();
+                    extra.hook_triggers.every_nth_instruction = Some(1);
+                    let mask = ffi::lua_gethookmask(state) | ffi::LUA_MASKCOUNT;
+                    ffi::lua_sethook(state, ffi::lua_gethook(state), mask, 1);
+                }
+                return Err(Error::InstructionLimitExceeded(limit));
+            }
+            Ok(())
+        })?;
+        let result = f();
+        drop(guard);
+
+        // The error can be caught inside of a coroutine, report it anyway
+        match result {
+            _ if executed.load(Ordering::Relaxed) > limit => {
+                Err(Error::InstructionLimitExceeded(limit))
+            }
+            result => result,
+        }
+    }
+
+    /// Calls `f`, aborting Lua code it runs with [`Error::InstructionLimitExceeded`] once `limit`
+    /// interrupts (at function calls and loop iterations) are counted.
+    #[cfg(feature = "luau")]
+    pub(crate) fn with_instruction_limit<R>(
+        &self,
+        limit: u64,
+        f: impl FnOnce() -> Result<R>,
+    ) -> Result<R> {
+        struct LimitGuard<'a>(&'a Lua, Option<(u64, u64)>);
+
+        impl<'a> Drop for LimitGuard<'a> {
+            fn drop(&mut self) {
+                let lua = self.0;
+                unsafe {
+                    let extra = &mut *lua.extra.get();
+                    let executed = extra.instruction_limit.map_or(0, |(_, executed)| executed);
+                    // Instructions counted for a nested limit count for the outer one as well
+                    extra.instruction_limit = self.1.map(|(l, prev)| (l, prev + executed));
+                }
+                lua.release_interrupt();
+            }
+        }
+
+        let guard = unsafe {
+            let extra = &mut *self.extra.get();
+            let prev = extra.instruction_limit.replace((limit, 0));
+            (*ffi::lua_callbacks(self.main_state)).interrupt = Some(interrupt_proc);
+            LimitGuard(self, prev)
+        };
+        let result = f();
+        let executed = unsafe { (*self.extra.get()).instruction_limit.map_or(0, |(_, e)| e) };
+        drop(guard);
+
+        // The error can be caught inside of a coroutine, report it anyway
+        match result {
+            _ if executed > limit => Err(Error::InstructionLimitExceeded(limit)),
+            result => result,
+        }
+    }
+
+    /// Removes a hook installed by [`Lua::push_hook()`] and restores the previous one.
+    #[cfg(not(feature = "luau"))]
+    pub(crate) fn pop_hook(&self, id: usize) {
+        unsafe {
+            let extra = &mut *self.extra.get();
+            let index = match extra.hook_stack.iter().position(|saved| saved.id == id) {
+                Some(index) => index,
+                None => return,
+            };
+            let saved = extra.hook_stack.remove(index);
+            if let Some(next) = extra.hook_stack.get_mut(index) {
+                // The hook was replaced by a newer one, which will restore the saved hook instead
+                next.callback = saved.callback;
+                next.thread = saved.thread;
+                next.triggers = saved.triggers;
+                next.all_threads = saved.all_threads;
+                return;
+            }
+
+            let main_state = match get_main_state(self.main_state) {
+                Some(main_state) => main_state,
+                None => return,
+            };
+            ffi::lua_sethook(main_state, None, 0, 0);
+            match saved.callback {
+                Some(callback) => {
+                    if ptr::eq(saved.thread, main_state) {
+                        self.set_thread_hook_callback(main_state, saved.triggers, callback);
+                        extra.hook_all_threads = saved.all_threads;
+                    } else {
+                        // The thread hook is still installed (unless it has been triggered)
+                        extra.hook_callback = Some(callback);
+                        extra.hook_thread = saved.thread;
+                        extra.hook_triggers = saved.triggers;
+                        extra.hook_all_threads = false;
+                        extra.hook_sampler = HookSampler::default();
+                    }
+                }
+                None => {
+                    extra.hook_callback = None;
+                    extra.hook_thread = ptr::null_mut();
+                    extra.hook_triggers = HookTriggers::new();
+                    extra.hook_all_threads = false;
+                }
+            }
+        }
+    }
+
+    /// Sets a 'hook' function for a thread (coroutine).
+    #[cfg(not(feature = "luau"))]
+    pub(crate) unsafe fn set_thread_hook<F>(
+        &self,
+        state: *mut ffi::lua_State,
+        triggers: HookTriggers,
+        callback: F,
+    ) where
+        F: Fn(&Lua, Debug) -> Result<()> + MaybeSend + 'static,
+    {
+        self.set_thread_hook_callback(state, triggers, Arc::new(callback));
+    }
+
+    #[cfg(not(feature = "luau"))]
+    unsafe fn set_thread_hook_callback(
+        &self,
+        state: *mut ffi::lua_State,
+        triggers: HookTriggers,
+        callback: HookCallback,
+    ) {
+        let extra = &mut *self.extra.get();
+        extra.hook_callback = Some(callback);
+        extra.hook_thread = state; // Mark for what thread the hook is set
+        extra.hook_triggers = triggers;
+        extra.hook_all_threads = false;
+        extra.hook_sampler = HookSampler::default();
+        let mask = triggers.mask() | limits_mask(extra);
+        ffi::lua_sethook(state, Some(hook_proc), mask, triggers.count());
+    }
+
+    /// Sets execution limits for a thread (coroutine), enforced by a hook function.
+    #[cfg(not(feature = "luau"))]
+    pub(crate) unsafe fn set_thread_limits(
+        &self,
+        thread_state: *mut ffi::lua_State,
+        stack_limit: Option<usize>,
+        c_call_limit: Option<usize>,
+    ) -> Result<()> {
+        let state = self.state();
+        let _sg = StackGuard::new(state);
+        check_stack(state, 5)?;
+
+        let extra = self.extra.get();
+        let table_id = match (*extra).thread_limits_guards {
+            Some(table_id) => table_id,
+            None => {
+                let table_id = protect_lua!(state, 0, 0, |state| {
+                    ffi::lua_createtable(state, 0, 0);
+                    ffi::lua_createtable(state, 0, 1);
+                    ffi::lua_pushstring(state, cstr!("k"));
+                    ffi::lua_setfield(state, -2, cstr!("__mode"));
+                    ffi::lua_setmetatable(state, -2);
+                    ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+                })?;
+                (*extra).thread_limits_guards = Some(table_id);
+                table_id
+            }
+        };
+
+        // The guard lives as long as the thread and removes its limits when collected
+        (*extra).thread_limits_id += 1;
+        let id = (*extra).thread_limits_id;
+        let guard = self.make_any_userdata(UserDataCell::new(ThreadLimitsGuard {
+            extra: Arc::downgrade(&self.extra),
+            thread: thread_state,
+            id,
+        }))?;
+        ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, table_id as Integer);
+        ffi::lua_pushthread(thread_state);
+        ffi::lua_xmove(thread_state, state, 1);
+        self.push_ref(&guard.0);
+        protect_lua!(state, 3, 0, fn(state) ffi::lua_rawset(state, -3))?;
+
+        let limit = |limit: Option<usize>| limit.unwrap_or(0).min(c_int::MAX as usize) as c_int;
+        let limits = ThreadLimits {
+            stack_limit: limit(stack_limit),
+            c_call_limit: limit(c_call_limit),
+            depth: 0,
+            c_depth: 0,
+            id,
+        };
+        (*extra).thread_limits.insert(thread_state, limits);
+
+        // Keep the hook already set for the thread (if any), `hook_proc` checks the limits first
+        let mask = ffi::lua_gethookmask(thread_state) | THREAD_LIMITS_MASK;
+        let count = ffi::lua_gethookcount(thread_state);
+        ffi::lua_sethook(thread_state, Some(hook_proc), mask, count);
+        Ok(())
+    }
+
+    /// Removes any hook previously set by [`Lua::set_hook()`] or [`Thread::set_hook()`].
+    ///
+    /// This function has no effect if a hook was not previously set.
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn remove_hook(&self) {
+        unsafe {
+            let state = self.state();
+            ffi::lua_sethook(state, None, 0, 0);
+            match get_main_state(self.main_state) {
+                Some(main_state) if !ptr::eq(state, main_state) => {
+                    // If main_state is different from state, remove hook from it too
+                    ffi::lua_sethook(main_state, None, 0, 0);
+                }
+                _ => {}
+            };
+            (*self.extra.get()).hook_callback = None;
+            (*self.extra.get()).hook_thread = ptr::null_mut();
+            (*self.extra.get()).hook_triggers = HookTriggers::new();
+            (*self.extra.get()).hook_all_threads = false;
+        }
+    }
+
+    /// Sets an 'interrupt' function that will periodically be called by Luau VM.
+    ///
+    /// Any Luau code is guaranteed to call this handler "eventually"
+    /// (in practice this can happen at any function call or at any loop iteration).
+    ///
+    /// The provided interrupt function can error, and this error will be propagated through
+    /// the Luau code that was executing at the time the interrupt was triggered.
+    /// Also this can be used to implement continuous execution limits by instructing Luau VM to yield
+    /// by returning [`VmState::Yield`].
+    ///
+    /// This is similar to [`Lua::set_hook`] but in more simplified form.
+    ///
+    /// # Example
+    ///
+    /// Periodically yield Luau VM to suspend execution.
+    ///
+    /// ```
+    /// # use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+    /// # use mlua::{Lua, Result, ThreadStatus, VmState};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let count = Arc::new(AtomicU64::new(0));
+    /// lua.set_interrupt(move |_| {
+    ///     if count.fetch_add(1, Ordering::Relaxed) % 2 == 0 {
+    ///         return Ok(VmState::Yield);
+    ///     }
+    ///     Ok(VmState::Continue)
+    /// });
+    ///
+    /// let co = lua.create_thread(
+    ///     lua.load(r#"
+    ///         local b = 0
+    ///         for _, x in ipairs({1, 2, 3}) do b += x end
+    ///     "#)
+    ///     .into_function()?,
+    /// )?;
+    /// while co.status() == ThreadStatus::Resumable {
+    ///     co.resume(())?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn set_interrupt<F>(&self, callback: F)
+    where
+        F: Fn(&Lua) -> Result<VmState> + MaybeSend + 'static,
+    {
+        unsafe {
+            (*self.extra.get()).interrupt_callback = Some(Arc::new(callback));
+            (*ffi::lua_callbacks(self.main_state)).interrupt = Some(interrupt_proc);
+        }
+    }
+
+    /// Removes any 'interrupt' previously set by `set_interrupt`.
+    ///
+    /// This function has no effect if an 'interrupt' was not previously set.
+    #[cfg(any(feature = "luau", docsrs))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn remove_interrupt(&self) {
+        unsafe {
+            (*self.extra.get()).interrupt_callback = None;
+        }
+        self.release_interrupt();
+    }
+
+    /// Sets the warning function to be used by Lua to emit warnings.
+    ///
+    /// Requires `feature = "lua54"`
+    #[cfg(feature = "lua54")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lua54")))]
+    pub fn set_warning_function<F>(&self, callback: F)
+    where
+        F: 'static + MaybeSend + Fn(&Lua, &CStr, bool) -> Result<()>,
+    {
+        unsafe extern "C" fn warn_proc(ud: *mut c_void, msg: *const c_char, tocont: c_int) {
+            let extra = ud as *mut ExtraData;
             let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
             callback_error_ext(lua.state(), extra, |_| {
                 let cb = mlua_expect!(
@@ -1103,74 +1837,278 @@ impl Lua {
         }
     }
 
-    /// Returns the amount of memory (in bytes) currently used inside this Lua state.
-    pub fn used_memory(&self) -> usize {
+    /// Returns a traceback of the currently running Lua stack, or `None` if the stack is empty.
+    pub(crate) fn traceback(&self) -> Option<std::string::String> {
+        let state = self.state();
         unsafe {
-            match (*self.extra.get()).mem_state.map(|x| x.as_ref()) {
-                Some(mem_state) => mem_state.used_memory(),
-                None => {
-                    // Get data from the Lua GC
-                    let used_kbytes = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNT, 0);
-                    let used_kbytes_rem = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNTB, 0);
-                    (used_kbytes as usize) * 1024 + (used_kbytes_rem as usize)
-                }
+            let mut ar: ffi::lua_Debug = mem::zeroed();
+            #[cfg(not(feature = "luau"))]
+            if ffi::lua_getstack(state, 0, &mut ar) == 0 {
+                return None;
+            }
+            #[cfg(feature = "luau")]
+            if ffi::lua_getinfo(state, 0, cstr!(""), &mut ar) == 0 {
+                return None;
             }
+
+            let _sg = StackGuard::new(state);
+            check_stack(state, ffi::LUA_TRACEBACK_STACK + 2).ok()?;
+            // Skip the level of the protected call itself
+            protect_lua!(state, 0, 1, |state| {
+                ffi::luaL_traceback(state, state, ptr::null(), 1);
+                apply_traceback_options(state);
+            })
+            .ok()?;
+            Some(util::to_string(state, -1))
         }
     }
 
-    /// Sets a memory limit (in bytes) on this Lua state.
+    /// Sets formatting options for stack tracebacks generated by mlua.
     ///
-    /// Once an allocation occurs that would pass this memory limit,
-    /// a `Error::MemoryError` is generated instead.
-    /// Returns previous limit (zero means no limit).
+    /// # Examples
     ///
-    /// Does not work on module mode where Lua state is managed externally.
-    pub fn set_memory_limit(&self, limit: usize) -> Result<usize> {
-        unsafe {
-            match (*self.extra.get()).mem_state.map(|mut x| x.as_mut()) {
-                Some(mem_state) => Ok(mem_state.set_memory_limit(limit)),
-                None => Err(Error::MemoryLimitNotAvailable),
-            }
-        }
+    /// ```
+    /// # use mlua::{Lua, Result, TracebackOptions};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.set_traceback_options(TracebackOptions::new().max_frames(Some(10)).skip_internal(true));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_traceback_options(&self, options: TracebackOptions) {
+        unsafe { (*self.extra.get()).traceback_options = Some(options) };
     }
 
-    /// Returns true if the garbage collector is currently running automatically.
+    /// Sets a function resolving chunk sources into file paths.
     ///
-    /// Requires `feature = "lua54/lua53/lua52/luau"`
-    #[cfg(any(
-        feature = "lua54",
-        feature = "lua53",
-        feature = "lua52",
-        feature = "luau"
-    ))]
-    pub fn gc_is_running(&self) -> bool {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCISRUNNING, 0) != 0 }
-    }
-
-    /// Stop the Lua GC from running
-    pub fn gc_stop(&self) {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCSTOP, 0) };
+    /// The resolver is called with the source of a chunk (eg. `@scripts/main.lua` for chunks
+    /// loaded from files, or the chunk name) and returns the (absolute) path of the chunk, which
+    /// is reported by [`Function::info`] in [`FunctionInfo::resolved_source`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.set_source_resolver(|source| {
+    ///     let path = source.strip_prefix('@')?;
+    ///     Some(Path::new("/game/scripts").join(path))
+    /// });
+    /// let func = lua.load("return 1").set_name("@main.lua").into_function()?;
+    /// assert_eq!(func.info().resolved_source.unwrap(), Path::new("/game/scripts/main.lua"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`FunctionInfo::resolved_source`]: crate::FunctionInfo::resolved_source
+    pub fn set_source_resolver<F>(&self, resolver: F)
+    where
+        F: Fn(&str) -> Option<PathBuf> + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).source_resolver = Some(Box::new(resolver)) };
     }
 
-    /// Restarts the Lua GC if it is not running
-    pub fn gc_restart(&self) {
-        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCRESTART, 0) };
+    /// Removes the resolver set using [`Lua::set_source_resolver`].
+    pub fn remove_source_resolver(&self) {
+        unsafe { (*self.extra.get()).source_resolver = None };
     }
 
-    /// Perform a full garbage-collection cycle.
+    /// Sets a function transforming the source of every text chunk before it's loaded.
     ///
-    /// It may be necessary to call this function twice to collect all currently unreachable
-    /// objects. Once to finish the current gc cycle, and once to start and finish the next cycle.
-    pub fn gc_collect(&self) -> Result<()> {
-        unsafe {
-            check_stack(self.main_state, 2)?;
-            protect_lua!(self.main_state, 0, 0, fn(state) ffi::lua_gc(state, ffi::LUA_GCCOLLECT, 0))
-        }
-    }
-
-    /// Steps the garbage collector one indivisible step.
+    /// The preprocessor is applied to chunks created by [`Lua::load`] (unless overridden by
+    /// [`Chunk::set_preprocessor`]) or [`Lua::load_from_reader`] and to Lua modules loaded from
+    /// files by `require`, so it can be used to implement includes, constants or syntax sugar
+    /// uniformly. Binary chunks are not
+    /// preprocessed. Errors returned by the preprocessor are returned when the chunk is loaded.
     ///
-    /// Returns true if this has finished a collection cycle.
+    /// Line numbers in error messages and tracebacks refer to the transformed source, which can be
+    /// retrieved using [`Lua::preprocessed_source`].
+    ///
+    /// For Lua 5.x, `require` uses the preprocessor only if the `package` library is loaded when
+    /// it's set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.set_preprocessor(|source| Ok(source.replace("PI", "3.14")))?;
+    /// assert_eq!(lua.load("return PI * 2").eval::<f64>()?, 6.28);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Chunk::set_preprocessor`]: crate::Chunk::set_preprocessor
+    pub fn set_preprocessor<F>(&self, preprocessor: F) -> Result<()>
+    where
+        F: Fn(&str) -> Result<std::string::String> + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).preprocessor = Some(Arc::new(preprocessor)) };
+        #[cfg(not(feature = "luau"))]
+        self.set_preprocessing_searcher(true)?;
+        Ok(())
+    }
+
+    /// Removes the preprocessor set using [`Lua::set_preprocessor`].
+    pub fn remove_preprocessor(&self) -> Result<()> {
+        unsafe { (*self.extra.get()).preprocessor = None };
+        #[cfg(not(feature = "luau"))]
+        self.set_preprocessing_searcher(false)?;
+        Ok(())
+    }
+
+    /// Returns the transformed source of the last preprocessed chunk with the given name.
+    ///
+    /// The name is the chunk name as reported in error messages and tracebacks (eg.
+    /// `[string "main"]` or `scripts/main.lua`). Sources of the 256 most recently loaded chunks
+    /// are kept, loading a chunk replaces the source kept for a chunk with the same name (or
+    /// forgets it if the new chunk is not preprocessed).
+    ///
+    /// See [`Lua::set_preprocessor`] and [`Chunk::set_preprocessor`].
+    ///
+    /// [`Chunk::set_preprocessor`]: crate::Chunk::set_preprocessor
+    pub fn preprocessed_source(&self, name: &str) -> Option<std::string::String> {
+        let sources = unsafe { &(*self.extra.get()).preprocessed_sources };
+        sources.get_latest(name).cloned()
+    }
+
+    /// Sets the maximum nesting depth of Rust values converted to Lua tables.
+    ///
+    /// Converting deeper nested collections (eg. `Vec<Vec<...>>` or serialized values) fails
+    /// with [`Error::ConversionDepthExceeded`]. Returns the previous limit.
+    ///
+    /// Nested collections are converted using a work queue, so their depth is only limited by
+    /// the heap. Serialized values are converted recursively and it's recommended to set
+    /// a limit when serializing untrusted input.
+    ///
+    /// Default: **no limit**
+    pub fn set_max_conversion_depth(&self, depth: Option<usize>) -> Option<usize> {
+        unsafe { mem::replace(&mut (*self.extra.get()).max_conversion_depth, depth) }
+    }
+
+    /// Sets limits enforced when converting Lua values into Rust values (including the serde
+    /// bridge). Returns the previous limits.
+    ///
+    /// See [`ConversionLimits`] for details.
+    pub fn set_conversion_limits(&self, limits: ConversionLimits) -> ConversionLimits {
+        unsafe { mem::replace(&mut (*self.extra.get()).conversion_limits, limits) }
+    }
+
+    #[inline]
+    pub(crate) fn conversion_limits(&self) -> ConversionLimits {
+        unsafe { (*self.extra.get()).conversion_limits }
+    }
+
+    /// Sets the policy used to convert Rust integers exceeding the range of Lua integers
+    /// (eg. `u64` values above `i64::MAX`). Returns the previous policy.
+    ///
+    /// Default: [`IntegerOverflow::Float`]
+    pub fn set_integer_overflow(&self, policy: IntegerOverflow) -> IntegerOverflow {
+        unsafe { mem::replace(&mut (*self.extra.get()).integer_overflow, policy) }
+    }
+
+    #[inline]
+    pub(crate) fn integer_overflow(&self) -> IntegerOverflow {
+        unsafe { (*self.extra.get()).integer_overflow }
+    }
+
+    /// Sets the table representation of Rust sets converted into Lua values. Returns the
+    /// previous mode.
+    ///
+    /// Default: [`SetMode::Keys`]
+    pub fn set_set_mode(&self, mode: SetMode) -> SetMode {
+        unsafe { mem::replace(&mut (*self.extra.get()).set_mode, mode) }
+    }
+
+    #[inline]
+    pub(crate) fn set_mode(&self) -> SetMode {
+        unsafe { (*self.extra.get()).set_mode }
+    }
+
+    #[inline]
+    pub(crate) fn std_libs(&self) -> StdLib {
+        unsafe { (*self.extra.get()).libs }
+    }
+
+    /// Returns the amount of memory (in bytes) currently used inside this Lua state.
+    pub fn used_memory(&self) -> usize {
+        unsafe {
+            match (*self.extra.get()).mem_state.map(|x| x.as_ref()) {
+                Some(mem_state) => mem_state.used_memory(),
+                None => {
+                    // Get data from the Lua GC
+                    let used_kbytes = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNT, 0);
+                    let used_kbytes_rem = ffi::lua_gc(self.main_state, ffi::LUA_GCCOUNTB, 0);
+                    (used_kbytes as usize) * 1024 + (used_kbytes_rem as usize)
+                }
+            }
+        }
+    }
+
+    // Returns the memory state used by the allocator (`None` in module mode)
+    pub(crate) fn memory_state(&self) -> Option<NonNull<MemoryState>> {
+        unsafe { (*self.extra.get()).mem_state }
+    }
+
+    /// Sets a memory limit (in bytes) on this Lua state.
+    ///
+    /// Once an allocation occurs that would pass this memory limit,
+    /// a `Error::MemoryLimitExceeded` is generated instead.
+    /// Returns previous limit (zero means no limit).
+    ///
+    /// Does not work on module mode where Lua state is managed externally.
+    pub fn set_memory_limit(&self, limit: usize) -> Result<usize> {
+        unsafe {
+            match (*self.extra.get()).mem_state.map(|mut x| x.as_mut()) {
+                Some(mem_state) => Ok(mem_state.set_memory_limit(limit)),
+                None => Err(Error::MemoryLimitNotAvailable),
+            }
+        }
+    }
+
+    /// Returns true if the garbage collector is currently running automatically.
+    ///
+    /// Requires `feature = "lua54/lua53/lua52/luau"`
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luau"
+    ))]
+    pub fn gc_is_running(&self) -> bool {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCISRUNNING, 0) != 0 }
+    }
+
+    /// Stop the Lua GC from running
+    pub fn gc_stop(&self) {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCSTOP, 0) };
+    }
+
+    /// Restarts the Lua GC if it is not running
+    pub fn gc_restart(&self) {
+        unsafe { ffi::lua_gc(self.main_state, ffi::LUA_GCRESTART, 0) };
+    }
+
+    /// Perform a full garbage-collection cycle.
+    ///
+    /// It may be necessary to call this function twice to collect all currently unreachable
+    /// objects. Once to finish the current gc cycle, and once to start and finish the next cycle.
+    pub fn gc_collect(&self) -> Result<()> {
+        unsafe {
+            check_stack(self.main_state, 2)?;
+            self.expire_registry_values();
+            protect_lua!(self.main_state, 0, 0, fn(state) ffi::lua_gc(state, ffi::LUA_GCCOLLECT, 0))?;
+            (*self.extra.get()).gc_stats.collections += 1;
+        }
+        Ok(())
+    }
+
+    /// Steps the garbage collector one indivisible step.
+    ///
+    /// Returns true if this has finished a collection cycle.
     pub fn gc_step(&self) -> Result<bool> {
         self.gc_step_kbytes(0)
     }
@@ -1182,9 +2120,75 @@ impl Lua {
     pub fn gc_step_kbytes(&self, kbytes: c_int) -> Result<bool> {
         unsafe {
             check_stack(self.main_state, 3)?;
-            protect_lua!(self.main_state, 0, 0, |state| {
+            self.expire_registry_values();
+            let finished = protect_lua!(self.main_state, 0, 0, |state| {
                 ffi::lua_gc(state, ffi::LUA_GCSTEP, kbytes) != 0
-            })
+            })?;
+            let stats = &mut (*self.extra.get()).gc_stats;
+            stats.steps += 1;
+            stats.collections += finished as u64;
+            Ok(finished)
+        }
+    }
+
+    /// Advances the current garbage-collection cycle for at most `budget`.
+    ///
+    /// Performs collector steps until either the cycle is finished or the time budget is
+    /// exhausted. Returns true if a collection cycle has been finished. Frame-bound hosts can call
+    /// this once per frame (until it returns true) to free large amounts of script data without
+    /// stalling a single frame, unlike [`Lua::gc_collect`].
+    ///
+    /// At least one step is always performed. Each step is indivisible, so the budget can be
+    /// slightly exceeded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.load("data = {} for i = 1, 100000 do data[i] = {i} end data = nil").exec()?;
+    /// while !lua.gc_collect_incremental(Duration::from_millis(1))? {
+    ///     // Render a frame, etc.
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn gc_collect_incremental(&self, budget: Duration) -> Result<bool> {
+        let start = self.clock_now();
+        loop {
+            if self.gc_step()? {
+                return Ok(true);
+            }
+            match (start, self.clock_now()) {
+                (Some(start), Some(now)) if now.saturating_sub(start) < budget => {}
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    /// Returns statistics of the garbage collector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.gc_collect()?;
+    /// lua.gc_step()?;
+    /// let stats = lua.gc_stats();
+    /// assert_eq!(stats.steps, 1);
+    /// assert!(stats.collections >= 1);
+    /// assert_eq!(stats.allocated_bytes, lua.used_memory());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn gc_stats(&self) -> GcStats {
+        GcStats {
+            allocated_bytes: self.used_memory(),
+            ..unsafe { (*self.extra.get()).gc_stats }
         }
     }
 
@@ -1309,6 +2313,18 @@ impl Lua {
         unsafe { (*self.extra.get()).enable_jit = enable };
     }
 
+    /// Sets whether [`Lua::load`] accepts precompiled bytecode.
+    ///
+    /// By default the chunk mode (text or binary) is autodetected. Lua does not check the
+    /// consistency of the code inside binary chunks, so running maliciously crafted bytecode can
+    /// crash the interpreter. When bytecode is disallowed, all chunks are loaded as text, unless
+    /// the binary mode is requested explicitly with [`Chunk::set_mode`].
+    ///
+    /// [`Chunk::set_mode`]: crate::Chunk::set_mode
+    pub fn allow_bytecode(&self, allow: bool) {
+        unsafe { (*self.extra.get()).allow_bytecode = allow };
+    }
+
     /// Returns Lua source code as a `Chunk` builder type.
     ///
     /// In order to actually compile or run the resulting code, you must call [`Chunk::exec`] or
@@ -1319,17 +2335,197 @@ impl Lua {
     #[track_caller]
     pub fn load<'lua, 'a>(&'lua self, chunk: impl AsChunk<'a>) -> Chunk<'lua, 'a> {
         let caller = Location::caller();
+        let mode = match chunk.mode() {
+            None if unsafe { !(*self.extra.get()).allow_bytecode } => Some(ChunkMode::Text),
+            mode => mode,
+        };
         Chunk {
             lua: self,
             name: chunk.name().unwrap_or_else(|| caller.to_string()),
             env: chunk.environment(self),
-            mode: chunk.mode(),
+            mode,
             source: chunk.source(),
+            source_map: None,
+            preprocessor: self.preprocessor(),
+            preprocessed_source: None,
             #[cfg(feature = "luau")]
             compiler: unsafe { (*self.extra.get()).compiler.clone() },
         }
     }
 
+    /// Loads a chunk from a reader and returns it as a function, without running it.
+    ///
+    /// The source is passed to the Lua parser in buffered pieces as it is read, so large scripts
+    /// (eg. generated or decompressed on the fly) do not have to be kept in memory as a whole.
+    /// The `name` is used as the chunk name, similar to [`Chunk::set_name`].
+    ///
+    /// Binary chunks are accepted unless disabled by [`Lua::allow_bytecode`]. Errors returned by
+    /// the reader are converted to [`Error::ExternalError`].
+    ///
+    /// On Luau, or if a preprocessor is set using [`Lua::set_preprocessor`], the source must be
+    /// compiled (or preprocessed) as a whole, so it is read into memory first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let reader = Cursor::new("return 1 + 2");
+    /// let func = lua.load_from_reader(reader, "=script")?;
+    /// assert_eq!(func.call::<_, i32>(())?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Chunk::set_name`]: crate::Chunk::set_name
+    pub fn load_from_reader<'lua>(
+        &'lua self,
+        mut reader: impl Read,
+        name: &str,
+    ) -> Result<Function<'lua>> {
+        #[cfg(not(feature = "luau"))]
+        if self.preprocessor().is_none() {
+            struct ReaderState<'a> {
+                reader: &'a mut dyn Read,
+                buf: Vec<u8>,
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                text_only: bool,
+                error: Option<Error>,
+                panic: Option<Box<dyn Any + Send>>,
+            }
+
+            unsafe extern "C" fn read_proc(
+                _state: *mut ffi::lua_State,
+                data: *mut c_void,
+                size: *mut usize,
+            ) -> *const c_char {
+                let data = &mut *(data as *mut ReaderState);
+                *size = 0;
+                loop {
+                    let ReaderState { reader, buf, .. } = &mut *data;
+                    match catch_unwind(AssertUnwindSafe(|| reader.read(buf))) {
+                        Ok(Ok(0)) => return ptr::null(),
+                        // Lua 5.1 has no load mode, binary chunks start with the escape character
+                        #[cfg(any(feature = "lua51", feature = "luajit"))]
+                        Ok(Ok(_)) if mem::take(&mut data.text_only) && data.buf[0] == 0x1b => {
+                            data.error = Some(Error::SyntaxError {
+                                message: "attempt to load a binary chunk".to_string(),
+                                incomplete_input: false,
+                            });
+                        }
+                        Ok(Ok(n)) => {
+                            *size = n;
+                            return data.buf.as_ptr() as *const c_char;
+                        }
+                        Ok(Err(err)) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Ok(Err(err)) => data.error = Some(Error::external(err)),
+                        Err(panic) => data.panic = Some(panic),
+                    }
+                    return ptr::null();
+                }
+            }
+
+            let name = CString::new(name)
+                .map_err(|err| Error::RuntimeError(format!("invalid name: {err}")))?;
+            let allow_bytecode = unsafe { (*self.extra.get()).allow_bytecode };
+            let mut data = ReaderState {
+                reader: &mut reader,
+                buf: vec![0; 8192],
+                #[cfg(any(feature = "lua51", feature = "luajit"))]
+                text_only: !allow_bytecode,
+                error: None,
+                panic: None,
+            };
+
+            let state = self.state();
+            return unsafe {
+                let _sg = StackGuard::new(state);
+                check_stack(state, 1)?;
+
+                let data_ptr = &mut data as *mut ReaderState as *mut c_void;
+                let status = {
+                    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+                    {
+                        let mode_str = if allow_bytecode {
+                            cstr!("bt")
+                        } else {
+                            cstr!("t")
+                        };
+                        ffi::lua_load(state, read_proc, data_ptr, name.as_ptr(), mode_str)
+                    }
+                    #[cfg(any(feature = "lua51", feature = "luajit"))]
+                    {
+                        ffi::lua_load(state, read_proc, data_ptr, name.as_ptr())
+                    }
+                };
+
+                if let Some(panic) = data.panic {
+                    resume_unwind(panic);
+                }
+                if let Some(err) = data.error {
+                    return Err(err);
+                }
+                match status {
+                    ffi::LUA_OK => Ok(Function(self.pop_ref())),
+                    err => Err(pop_error(state, err)),
+                }
+            };
+        }
+
+        let mut source = Vec::new();
+        reader.read_to_end(&mut source).map_err(Error::external)?;
+        self.load(source).set_name(name).into_function()
+    }
+
+    /// Evaluates a single Lua expression in the given environment.
+    ///
+    /// Before compiling, the source is validated to contain exactly one expression: statements,
+    /// loops, function definitions and assignments are rejected with [`Error::SyntaxError`].
+    /// Further restrictions (eg. whether function calls are allowed) are set by `policy`.
+    ///
+    /// The expression can only access names defined in `env`, which makes this method suitable
+    /// for evaluating user-supplied formulas.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{ExpressionPolicy, Lua, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let env = lua.create_table_from([("price", 12.5), ("quantity", 4.0)])?;
+    /// let total: f64 = lua.eval_expression("price * quantity", env.clone(), ExpressionPolicy::new())?;
+    /// assert_eq!(total, 50.0);
+    ///
+    /// let res = lua.eval_expression::<Value>("while true do end", env, ExpressionPolicy::new());
+    /// assert!(res.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Error::SyntaxError`]: crate::Error::SyntaxError
+    #[track_caller]
+    pub fn eval_expression<'lua, R: FromLua<'lua>>(
+        &'lua self,
+        source: &str,
+        env: Table<'lua>,
+        policy: ExpressionPolicy,
+    ) -> Result<R> {
+        expression::validate(source.as_bytes(), &policy).map_err(|message| Error::SyntaxError {
+            message,
+            incomplete_input: false,
+        })?;
+        let mut chunk = self.load(format!("return {source}"));
+        // The source is already validated
+        chunk.preprocessor = None;
+        chunk
+            .set_name("=expression")
+            .set_environment(env)
+            .set_mode(ChunkMode::Text)
+            .call(())
+    }
+
     pub(crate) fn load_chunk<'lua>(
         &'lua self,
         name: Option<&CStr>,
@@ -1357,7 +2553,7 @@ impl Lua {
             ) {
                 ffi::LUA_OK => {
                     if let Some(env) = env {
-                        self.push_ref(&env.0);
+                        self.push_ref_checked(&env.0)?;
                         #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
                         ffi::lua_setupvalue(state, -2, 1);
                         #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
@@ -1394,6 +2590,31 @@ impl Lua {
         }
     }
 
+    // Creates a string of up to `len` bytes written by `fill` (which returns the number of bytes
+    // written) into a buffer allocated by Lua, so it's subject to the memory limit
+    pub(crate) fn create_string_with(
+        &self,
+        len: usize,
+        fill: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<String> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+            protect_lua!(state, 0, 1, |state| {
+                let buf = ffi::lua_newuserdata(state, len) as *mut u8;
+                ptr::write_bytes(buf, 0, len);
+            })?;
+            let buf = match len {
+                0 => &mut [],
+                _ => slice::from_raw_parts_mut(ffi::lua_touserdata(state, -1) as *mut u8, len),
+            };
+            let n = fill(buf)?;
+            push_string(state, &buf[..n], true)?;
+            Ok(String(self.pop_ref()))
+        }
+    }
+
     /// Creates and returns a new empty table.
     pub fn create_table(&self) -> Result<Table> {
         self.create_table_with_capacity(0, 0)
@@ -1425,8 +2646,22 @@ impl Lua {
         V: IntoLua<'lua>,
         I: IntoIterator<Item = (K, V)>,
     {
+        let depth = self.enter_conversion()?;
+        if depth.is_deferred() {
+            let table = self.create_table()?;
+            let t = table.clone();
+            let iter = iter.into_iter();
+            depth.defer(move || {
+                for (k, v) in iter {
+                    t.raw_set(k, v)?;
+                }
+                Ok(())
+            });
+            return Ok(table);
+        }
+
         let state = self.state();
-        unsafe {
+        let table = unsafe {
             let _sg = StackGuard::new(state);
             check_stack(state, 6)?;
 
@@ -1444,8 +2679,10 @@ impl Lua {
                 }
             }
 
-            Ok(Table(self.pop_ref()))
-        }
+            Table(self.pop_ref())
+        };
+        depth.finish()?;
+        Ok(table)
     }
 
     /// Creates a table from an iterator of values, using `1..` as the keys.
@@ -1454,8 +2691,22 @@ impl Lua {
         T: IntoLua<'lua>,
         I: IntoIterator<Item = T>,
     {
+        let depth = self.enter_conversion()?;
+        if depth.is_deferred() {
+            let table = self.create_table()?;
+            let t = table.clone();
+            let iter = iter.into_iter();
+            depth.defer(move || {
+                for (i, v) in iter.enumerate() {
+                    t.raw_set((i + 1) as Integer, v)?;
+                }
+                Ok(())
+            });
+            return Ok(table);
+        }
+
         let state = self.state();
-        unsafe {
+        let table = unsafe {
             let _sg = StackGuard::new(state);
             check_stack(state, 5)?;
 
@@ -1474,8 +2725,10 @@ impl Lua {
                 }
             }
 
-            Ok(Table(self.pop_ref()))
-        }
+            Table(self.pop_ref())
+        };
+        depth.finish()?;
+        Ok(table)
     }
 
     /// Wraps a Rust function or closure, creating a callable Lua function handle to it.
@@ -1534,10 +2787,104 @@ impl Lua {
         }))
     }
 
-    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    /// Wraps a Rust function or closure, attaching documentation to the created Lua function.
     ///
-    /// This is a version of [`create_function`] that accepts a FnMut argument. Refer to
-    /// [`create_function`] for more information about the implementation.
+    /// The documentation is returned by [`Lua::api_docs`] and shown by `help` (see
+    /// [`Lua::install_help`]). Refer to [`create_function`] for more information about the
+    /// implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{FnMeta, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let meta = FnMeta::new("Adds two numbers.")
+    ///     .param("a: number")
+    ///     .param("b: number")
+    ///     .returns("number");
+    /// let add = lua.create_function_with_meta(|_, (a, b): (f64, f64)| Ok(a + b), meta)?;
+    /// lua.globals().set("add", add)?;
+    ///
+    /// lua.install_help()?;
+    /// let help: String = lua.load("help(add)").eval()?;
+    /// assert_eq!(help, "add(a: number, b: number) -> number\n    Adds two numbers.");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_with_meta<'lua, A, R, F>(
+        &'lua self,
+        func: F,
+        meta: FnMeta,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        let func = self.create_function(func)?;
+        docs::set_meta(self, &func, None, meta)?;
+        Ok(func)
+    }
+
+    /// Wraps a Rust function or closure, checking the number of arguments it is called with.
+    ///
+    /// Calls with a number of arguments other than `arity` fail with [`Error::WrongArity`] before
+    /// any argument is converted, which gives clearer errors than conversion failures of missing
+    /// or extra arguments. Refer to [`create_function`] for more information about the
+    /// implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let add = lua.create_function_with_arity(2, |_, (a, b): (f64, f64)| Ok(a + b))?;
+    /// assert_eq!(add.call::<_, f64>((1, 2))?, 3.0);
+    ///
+    /// match add.call::<_, f64>((1, 2, 3)) {
+    ///     Err(Error::CallbackError { cause, .. }) => {
+    ///         assert!(matches!(*cause, Error::WrongArity { expected: 2, got: 3, .. }));
+    ///     }
+    ///     r => panic!("unexpected result: {r:?}"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_with_arity<'lua, A, R, F>(
+        &'lua self,
+        arity: usize,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        self.create_callback(Box::new(move |lua, args| {
+            if args.len() != arity {
+                // The running function is at level 0
+                let name = (lua.inspect_stack(0))
+                    .and_then(|ar| ar.names().name.map(|name| name.into_owned()));
+                return Err(Error::WrongArity {
+                    expected: arity,
+                    got: args.len(),
+                    name,
+                });
+            }
+            func(lua, A::from_lua_multi_args(args, 1, None, lua)?)?.into_lua_multi(lua)
+        }))
+    }
+
+    /// Wraps a Rust mutable closure, creating a callable Lua function handle to it.
+    ///
+    /// This is a version of [`create_function`] that accepts a FnMut argument. Refer to
+    /// [`create_function`] for more information about the implementation.
     ///
     /// [`create_function`]: #method.create_function
     pub fn create_function_mut<'lua, A, R, F>(&'lua self, func: F) -> Result<Function<'lua>>
@@ -1554,6 +2901,133 @@ impl Lua {
         })
     }
 
+    /// Wraps a Rust function or closure, applying `policy` to calls made while a previous call
+    /// to it is still running.
+    ///
+    /// This happens when the function calls into Lua, which calls the function again (directly
+    /// or through other host calls). Refer to [`create_function`] for more information about the
+    /// implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Function, Lua, ReentrancyPolicy, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let policy = ReentrancyPolicy::Error;
+    /// let notify = lua.create_function_with_reentrancy(policy, |lua, event: String| {
+    ///     let on_event: Function = lua.globals().get("on_event")?;
+    ///     on_event.call::<_, ()>(event)
+    /// })?;
+    /// lua.globals().set("notify", notify)?;
+    ///
+    /// let res = lua.load(r#"
+    ///     function on_event(event) notify("nested " .. event) end
+    ///     notify("changed")
+    /// "#).exec();
+    /// assert!(res.unwrap_err().to_string().contains("is not re-entrant"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`create_function`]: #method.create_function
+    pub fn create_function_with_reentrancy<'lua, A, R, F>(
+        &'lua self,
+        policy: ReentrancyPolicy,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        let guard = ReentrancyGuard::new(policy);
+        self.create_callback(Box::new(move |lua, args| {
+            guard.call(lua, args, |lua, args| {
+                func(lua, A::from_lua_multi_args(args, 1, None, lua)?)?.into_lua_multi(lua)
+            })
+        }))
+    }
+
+    /// Wraps a Rust mutable closure, applying `policy` to calls made while a previous call to it
+    /// is still running.
+    ///
+    /// This is a version of [`create_function_with_reentrancy`] that accepts a FnMut argument.
+    /// With [`ReentrancyPolicy::Allow`] re-entrant calls fail with
+    /// [`Error::RecursiveMutCallback`], as with [`create_function_mut`].
+    ///
+    /// [`create_function_with_reentrancy`]: #method.create_function_with_reentrancy
+    /// [`create_function_mut`]: #method.create_function_mut
+    pub fn create_function_mut_with_reentrancy<'lua, A, R, F>(
+        &'lua self,
+        policy: ReentrancyPolicy,
+        func: F,
+    ) -> Result<Function<'lua>>
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + FnMut(&'lua Lua, A) -> Result<R>,
+    {
+        let func = RefCell::new(func);
+        self.create_function_with_reentrancy(policy, move |lua, args| {
+            (*func
+                .try_borrow_mut()
+                .map_err(|_| Error::RecursiveMutCallback)?)(lua, args)
+        })
+    }
+
+    /// Creates a table of functions sharing the same Rust state.
+    ///
+    /// Each entry of `defs` becomes a function in the returned table under the given name.
+    /// All the functions share a single boxed dispatcher holding the `shared` state, instead of
+    /// a boxed closure (with its own copy of the state) per function, which makes it cheaper to
+    /// build module tables with many entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::sync::atomic::{AtomicI64, Ordering};
+    /// # use mlua::{IntoLuaMulti, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let counter = Arc::new(AtomicI64::new(0));
+    /// let module = lua.create_function_family(
+    ///     counter.clone(),
+    ///     &[
+    ///         ("incr", |lua, c, _| (c.fetch_add(1, Ordering::Relaxed) + 1).into_lua_multi(lua)),
+    ///         ("get", |lua, c, _| c.load(Ordering::Relaxed).into_lua_multi(lua)),
+    ///     ],
+    /// )?;
+    /// lua.globals().set("counter", module)?;
+    /// lua.load("counter.incr(); counter.incr()").exec()?;
+    /// assert_eq!(counter.load(Ordering::Relaxed), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_function_family<'lua, S>(
+        &'lua self,
+        shared: Arc<S>,
+        defs: &[(&str, FamilyFn<S>)],
+    ) -> Result<Table<'lua>>
+    where
+        S: 'static,
+        Arc<S>: MaybeSend,
+    {
+        let funcs = defs.iter().map(|&(_, func)| func).collect::<Vec<_>>();
+        let names = defs.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+        self.create_callback_family(
+            Box::new(move |lua, mut args| {
+                let index = match args.pop_front() {
+                    Some(Value::Integer(index)) => index as usize,
+                    _ => mlua_panic!("function family index is missing"),
+                };
+                funcs[index](lua, &shared, args)
+            }),
+            &names,
+        )
+    }
+
     /// Wraps a C function, creating a callable Lua function handle to it.
     ///
     /// # Safety
@@ -1624,213 +3098,1096 @@ impl Lua {
         }))
     }
 
-    /// Wraps a Lua function into a new thread (or coroutine).
+    /// Sets the default maximum number of futures polled per resume of an async thread.
     ///
-    /// Equivalent to `coroutine.create`.
-    pub fn create_thread<'lua>(&'lua self, func: Function) -> Result<Thread<'lua>> {
-        self.create_thread_inner(&func)
+    /// Async Rust functions returning a ready future do not yield, so Lua code calling them in a
+    /// loop can run for a long time within a single poll of [`AsyncThread`], starving other tasks
+    /// of the executor (eg. a frame loop). With a budget, once the given number of futures was
+    /// polled, the thread yields back to the executor and wakes itself to continue later.
+    ///
+    /// `None` (the default) means no limit. The budget is applied to async threads created
+    /// afterwards and can be overridden per thread using [`AsyncThread::set_poll_budget`].
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// [`AsyncThread`]: crate::AsyncThread
+    /// [`AsyncThread::set_poll_budget`]: crate::AsyncThread::set_poll_budget
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn set_async_poll_budget(&self, budget: Option<usize>) {
+        unsafe { (*self.extra.get()).async_poll_budget = budget.map(|n| n.max(1)) };
     }
 
-    /// Wraps a Lua function into a new thread (or coroutine).
+    /// Sets the integration notified when async tasks spawned using [`Lua::spawn_async`] must be
+    /// polled again.
     ///
-    /// Takes function by reference.
-    fn create_thread_inner<'lua>(&'lua self, func: &Function) -> Result<Thread<'lua>> {
-        let state = self.state();
+    /// The integration applies to tasks spawned afterwards.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn set_executor_integration<I: LuaExecutorIntegration>(&self, integration: Arc<I>) {
         unsafe {
-            let _sg = StackGuard::new(state);
-            check_stack(state, 3)?;
-
-            let thread_state = if self.unlikely_memory_error() {
-                ffi::lua_newthread(state)
-            } else {
-                protect_lua!(state, 0, 1, |state| ffi::lua_newthread(state))?
-            };
-            self.push_ref(&func.0);
-            ffi::lua_xmove(state, thread_state, 1);
-
-            Ok(Thread(self.pop_ref()))
-        }
+            (*self.extra.get())
+                .async_tasks
+                .set_integration(Some(integration))
+        };
     }
 
-    /// Wraps a Lua function into a new or recycled thread (coroutine).
+    /// Removes the integration set using [`Lua::set_executor_integration`].
+    ///
+    /// Requires `feature = "async"`
     #[cfg(feature = "async")]
-    pub(crate) fn create_recycled_thread<'lua>(
-        &'lua self,
-        func: &Function,
-    ) -> Result<Thread<'lua>> {
-        #[cfg(any(
-            feature = "lua54",
-            all(feature = "luajit", feature = "vendored"),
-            feature = "luau",
-        ))]
-        unsafe {
-            let state = self.state();
-            let _sg = StackGuard::new(state);
-            check_stack(state, 1)?;
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn remove_executor_integration(&self) {
+        unsafe { (*self.extra.get()).async_tasks.set_integration(None) };
+    }
 
-            if let Some(index) = (*self.extra.get()).thread_pool.pop() {
-                let thread_state = ffi::lua_tothread(self.ref_thread(), index);
-                self.push_ref(&func.0);
-                ffi::lua_xmove(state, thread_state, 1);
+    /// Spawns an async task calling the function with the given arguments.
+    ///
+    /// The task runs in a new thread (coroutine) driven by [`Lua::poll_pending_async`], which
+    /// returns its result when the task finishes. This allows hosts with custom schedulers (eg.
+    /// game engines) to drive async Lua code deterministically, without an executor.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::task::Poll;
+    /// # use std::time::Duration;
+    /// # use futures_util::future;
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// // Resolves on the first tick at or after `ms` milliseconds
+    /// let wait = lua.create_async_function(|lua, ms: u64| {
+    ///     let deadline = lua.async_now().unwrap_or_default() + Duration::from_millis(ms);
+    ///     future::poll_fn(move |cx| {
+    ///         if lua.async_now().unwrap_or_default() >= deadline {
+    ///             return Poll::Ready(Ok(()));
+    ///         }
+    ///         cx.waker().wake_by_ref();
+    ///         Poll::Pending
+    ///     })
+    /// })?;
+    /// lua.globals().set("wait", wait)?;
+    /// let func = lua.load("local ms = ... wait(ms) return ms").into_function()?;
+    /// let task = lua.spawn_async(func, 20)?;
+    ///
+    /// let mut tick = Duration::ZERO;
+    /// loop {
+    ///     if let Some((id, result)) = lua.poll_pending_async(tick).pop() {
+    ///         assert_eq!(id, task);
+    ///         assert_eq!(result?.len(), 1);
+    ///         break;
+    ///     }
+    ///     tick += Duration::from_millis(10);
+    /// }
+    /// assert_eq!(tick, Duration::from_millis(20));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn spawn_async<'lua, A>(&'lua self, func: Function<'lua>, args: A) -> Result<TaskId>
+    where
+        A: IntoLuaMulti<'lua>,
+    {
+        let thread = self.create_thread(func.bind(args)?)?;
+        let thread = self.create_registry_value(thread)?;
+        Ok(unsafe { (*self.extra.get()).async_tasks.insert(thread) })
+    }
 
-                #[cfg(feature = "luau")]
-                {
-                    // Inherit `LUA_GLOBALSINDEX` from the caller
-                    ffi::lua_xpush(state, thread_state, ffi::LUA_GLOBALSINDEX);
-                    ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
+    /// Polls async tasks that were woken (or spawned) since the last poll, and returns results of
+    /// the finished tasks.
+    ///
+    /// `now` is the current time of the host (eg. time of the current tick), returned by
+    /// [`Lua::async_now`] while the tasks are polled.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn poll_pending_async(&self, now: Duration) -> Vec<(TaskId, Result<MultiValue>)> {
+        let extra = self.extra.get();
+        let prev_now = unsafe { (*extra).async_tasks.set_now(Some(now)) };
+        let mut finished = Vec::new();
+        for id in unsafe { (*extra).async_tasks.take_woken() } {
+            let (thread, waker) = match unsafe { (*extra).async_tasks.task(self, id) } {
+                Some(task) => task,
+                None => continue,
+            };
+            if let Some(result) = executor::poll_thread(id, thread, &waker) {
+                if let Some(key) = unsafe { (*extra).async_tasks.remove(id) } {
+                    let _ = self.remove_registry_value(key);
                 }
-
-                return Ok(Thread(LuaRef::new(self, index)));
+                finished.push((id, result));
             }
-        };
-        self.create_thread_inner(func)
+        }
+        unsafe { (*extra).async_tasks.set_now(prev_now) };
+        finished
     }
 
-    /// Resets thread (coroutine) and returns to the pool for later use.
+    /// Returns the number of unfinished async tasks spawned using [`Lua::spawn_async`].
+    ///
+    /// Requires `feature = "async"`
     #[cfg(feature = "async")]
-    #[cfg(any(
-        feature = "lua54",
-        all(feature = "luajit", feature = "vendored"),
-        feature = "luau",
-    ))]
-    pub(crate) unsafe fn recycle_thread(&self, thread: &mut Thread) -> bool {
-        let extra = &mut *self.extra.get();
-        if extra.thread_pool.len() < extra.thread_pool.capacity() {
-            let thread_state = ffi::lua_tothread(extra.ref_thread, thread.0.index);
-            #[cfg(all(feature = "lua54", not(feature = "vendored")))]
-            let status = ffi::lua_resetthread(thread_state);
-            #[cfg(all(feature = "lua54", feature = "vendored"))]
-            let status = ffi::lua_closethread(thread_state, self.state());
-            #[cfg(feature = "lua54")]
-            if status != ffi::LUA_OK {
-                // Error object is on top, drop it
-                ffi::lua_settop(thread_state, 0);
-            }
-            #[cfg(all(feature = "luajit", feature = "vendored"))]
-            ffi::lua_resetthread(self.state(), thread_state);
-            #[cfg(feature = "luau")]
-            ffi::lua_resetthread(thread_state);
-            extra.thread_pool.push(thread.0.index);
-            thread.0.drop = false;
-            return true;
-        }
-        false
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn pending_async_tasks(&self) -> usize {
+        unsafe { (*self.extra.get()).async_tasks.len() }
     }
 
-    /// Creates a Lua userdata object from a custom userdata type.
+    /// Returns the time passed to [`Lua::poll_pending_async`] while polling async tasks, `None`
+    /// outside of it.
     ///
-    /// All userdata instances of the same type `T` shares the same metatable.
-    #[inline]
-    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
-    where
-        T: UserData + MaybeSend + 'static,
-    {
-        unsafe { self.make_userdata(UserDataCell::new(data)) }
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn async_now(&self) -> Option<Duration> {
+        unsafe { (*self.extra.get()).async_tasks.now() }
     }
 
-    /// Creates a Lua userdata object from a custom serializable userdata type.
+    /// Returns the id of the async task currently being polled, `None` outside of async code.
     ///
-    /// Requires `feature = "serialize"`
-    #[cfg(feature = "serialize")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
-    #[inline]
-    pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData>
-    where
-        T: UserData + Serialize + MaybeSend + 'static,
-    {
-        unsafe { self.make_userdata(UserDataCell::new_ser(data)) }
+    /// The id identifies a logical task across suspension points: it's preserved every time the
+    /// task is resumed, and async threads (eg. [`Function::call_async`]) awaited by the task
+    /// inherit it. Tracebacks generated while a task is polled include its id, so errors and logs
+    /// from interleaved coroutines can be grouped.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn current_task_id(&self) -> Option<TaskId> {
+        unsafe { (*self.extra.get()).current_task }
+    }
+
+    /// Creates a table with timer functions for use in async Lua code.
+    ///
+    /// The table has the following functions:
+    /// - `sleep(ms)` suspends the current coroutine for `ms` milliseconds.
+    /// - `interval(ms, func)` calls `func(n)` every `ms` milliseconds (`n` is the call number,
+    ///   starting from 1) until it returns `false`, then returns the number of calls.
+    /// - `timeout(ms, func, ...)` calls `func(...)` and returns its results, raising an error if
+    ///   the call does not finish within `ms` milliseconds.
+    ///
+    /// Waiting is delegated to the `sleep` function, which should return a future provided by
+    /// the host async runtime (eg. `tokio::time::sleep`). The timers are driven as part of the
+    /// calling coroutine, so the functions must be called from async Lua code
+    /// (see [`Function::call_async`]).
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mlua::{Lua, Result};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     lua.globals().set("timers", lua.create_timers(tokio::time::sleep)?)?;
+    ///     let ticks: u32 = lua.load(r#"
+    ///         timers.sleep(10)
+    ///         return timers.interval(10, function(n) return n < 3 end)
+    ///     "#).eval_async().await?;
+    ///     assert_eq!(ticks, 3);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub fn create_timers<'lua, F, FR>(&'lua self, sleep: F) -> Result<Table<'lua>>
+    where
+        F: Fn(std::time::Duration) -> FR + Clone + MaybeSend + 'static,
+        FR: Future<Output = ()> + 'lua,
+    {
+        timers::create_timers(self, sleep)
+    }
+
+    /// Loads the `bit` library with bitwise operations of the given width.
+    ///
+    /// The library provides the same set of functions on every Lua version (including Lua 5.1,
+    /// where `bit32` is absent, and LuaJIT, where the builtin `bit` module is replaced):
+    /// `tobit`, `bnot`, `band`, `bor`, `bxor`, `lshift`, `rshift`, `arshift`, `rol` and `ror`.
+    /// The semantics follow the LuaJIT `bit` module, see [`BitWidth`] for the Rust counterparts.
+    ///
+    /// The library is set as a global `bit` and registered in `package.loaded` (if available).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{BitWidth, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load_bit_library(BitWidth::Bits32)?;
+    /// let x: i64 = lua.load("bit.band(bit.lshift(1, 4), 0xff)").eval()?;
+    /// assert_eq!(x, BitWidth::Bits32.band(&[BitWidth::Bits32.lshift(1, 4), 0xff]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_bit_library(&self, width: BitWidth) -> Result<()> {
+        let bit = bit::create_bit_library(self, width)?;
+        if let Ok(Value::Table(loaded)) = self.named_registry_value("_LOADED") {
+            loaded.raw_set("bit", bit.clone())?;
+        }
+        self.globals().raw_set("bit", bit)
+    }
+
+    /// Loads the `encoding` library with binary-to-text codecs.
+    ///
+    /// The library provides `encode(data, name)` and `decode(data, name)` functions, where `name`
+    /// is the name of an [`Encoding`] (`"hex"` or `"base64"`). Decoding invalid data raises an
+    /// error.
+    ///
+    /// The library is set as a global `encoding` and registered in `package.loaded` (if
+    /// available).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.load_encoding_library()?;
+    /// let hex: String = lua.load(r#"encoding.encode("mlua", "hex")"#).eval()?;
+    /// assert_eq!(hex, "6d6c7561");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_encoding_library(&self) -> Result<()> {
+        let lib = encoding::create_encoding_library(self)?;
+        if let Ok(Value::Table(loaded)) = self.named_registry_value("_LOADED") {
+            loaded.raw_set("encoding", lib.clone())?;
+        }
+        self.globals().raw_set("encoding", lib)
+    }
+
+    /// Sets a global `inspect(value, [opts])` function returning a human readable representation
+    /// of any Lua value.
+    ///
+    /// Tables are expanded recursively with sorted keys, in the same format as the alternate
+    /// [`Debug`] representation of [`Value`]. Tables that were already printed (including cycles)
+    /// are shown as `table: 0x...`. The optional `opts` table accepts a `depth` field to limit
+    /// the depth of expanded nested tables.
+    ///
+    /// Userdata can provide a custom representation using
+    /// [`UserDataMethods::add_debug_fmt`] (or the `__inspect` metamethod).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.install_inspect()?;
+    /// let s: String = lua.load("inspect({ a = { b = { c = 1 } } }, { depth = 2 })").eval()?;
+    /// assert_eq!(s, "{\n  [\"a\"] = {\n    [\"b\"] = {...},\n  },\n}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Debug`]: std::fmt::Debug
+    /// [`UserDataMethods::add_debug_fmt`]: crate::UserDataMethods::add_debug_fmt
+    pub fn install_inspect(&self) -> Result<()> {
+        let inspect = self.create_function(|_, (value, opts): (Value, Option<Table>)| {
+            let depth = match opts {
+                Some(opts) => opts.get::<_, Option<usize>>("depth")?,
+                None => None,
+            };
+            Ok(PrettyValue(&value, depth).to_string())
+        })?;
+        self.globals().raw_set("inspect", inspect)
+    }
+
+    /// Returns documentation of all living functions created with attached [`FnMeta`].
+    ///
+    /// This includes functions created by [`Lua::create_function_with_meta`] and documented
+    /// userdata methods (see [`UserDataMethods::document_method`]). Entries are sorted by name.
+    ///
+    /// [`UserDataMethods::document_method`]: crate::UserDataMethods::document_method
+    pub fn api_docs(&self) -> Result<Vec<ApiDoc>> {
+        docs::api_docs(self)
+    }
+
+    /// Sets a global `help` function showing documentation attached to host functions.
+    ///
+    /// `help(f)` returns the signature and description of a documented function, `help(t)` of all
+    /// documented functions in a table, `help(ud)` of all documented methods of a userdata and
+    /// `help()` of everything returned by [`Lua::api_docs`].
+    pub fn install_help(&self) -> Result<()> {
+        let help = self.create_function(|lua, value: Value| docs::help(lua, value))?;
+        self.globals().raw_set("help", help)
+    }
+
+    /// Sets the policy for locale-sensitive standard library functions.
+    ///
+    /// With [`LocalePolicy::CIndependent`], `tonumber`, `tostring`, `string.upper` and
+    /// `string.lower` are replaced by locale-independent implementations, so that number parsing
+    /// and formatting and case conversion behave the same on every machine regardless of the
+    /// process locale. Setting [`LocalePolicy::Process`] restores the original functions.
+    ///
+    /// The functions are looked up in the current globals and the `string` table, so this method
+    /// should be called after the standard libraries have been loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, LocalePolicy, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.set_locale_policy(LocalePolicy::CIndependent)?;
+    /// assert_eq!(lua.load("tonumber('1.5')").eval::<f64>()?, 1.5);
+    /// assert_eq!(lua.load("tonumber('1,5')").eval::<Option<f64>>()?, None);
+    /// assert_eq!(lua.load("tostring(0.25)").eval::<String>()?, "0.25");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_locale_policy(&self, policy: LocalePolicy) -> Result<()> {
+        locale::set_locale_policy(self, policy)
+    }
+
+    /// Returns a builder for a restricted environment to run untrusted code in.
+    ///
+    /// See [`SandboxBuilder`] for details.
+    pub fn sandbox_builder(&self) -> SandboxBuilder {
+        SandboxBuilder::new(self)
+    }
+
+    // Replaces the metatable shared by all strings, returning the previous one
+    pub(crate) fn replace_string_metatable<'lua>(
+        &'lua self,
+        metatable: Option<Table<'lua>>,
+    ) -> Result<Option<Table<'lua>>> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            ffi::lua_pushstring(state, cstr!(""));
+            let prev = match ffi::lua_getmetatable(state, -1) {
+                0 => None,
+                _ => Some(Table(self.pop_ref())),
+            };
+            match metatable {
+                Some(metatable) => self.push_ref(&metatable.0),
+                None => ffi::lua_pushnil(state),
+            }
+            ffi::lua_setmetatable(state, -2);
+            Ok(prev)
+        }
+    }
+
+    /// Wraps a Lua function into a new thread (or coroutine).
+    ///
+    /// Equivalent to `coroutine.create`.
+    pub fn create_thread<'lua>(&'lua self, func: Function) -> Result<Thread<'lua>> {
+        self.create_thread_inner(&func)
+    }
+
+    /// Closes all suspended threads (coroutines) reachable from the Lua state.
+    ///
+    /// Threads that have yielded or stopped with an error are closed using [`Thread::close`],
+    /// which runs their pending to-be-closed variables (for Lua 5.4). Errors raised while
+    /// closing are ignored. This is useful to release resources held by abandoned coroutines,
+    /// eg. before reusing the state from a pool.
+    ///
+    /// Threads are found by traversing all values reachable from the registry (including values
+    /// held by Rust code). For Luau, local variables of suspended threads are traversed only if
+    /// the code is compiled with debug level 2 (see [`Compiler::set_debug_level`]).
+    /// Returns the number of closed threads.
+    ///
+    /// Requires `feature = "lua54"` OR `feature = "luajit,vendored"` OR `feature = "luau"`
+    ///
+    /// [`Thread::close`]: crate::Thread::close
+    /// [`Compiler::set_debug_level`]: crate::Compiler::set_debug_level
+    #[cfg(any(
+        feature = "lua54",
+        all(feature = "luajit", feature = "vendored"),
+        feature = "luau",
+    ))]
+    pub fn close_all_suspended_threads(&self) -> Result<usize> {
+        crate::thread::close_suspended_threads(self)
+    }
+
+    /// Returns a builder for a new thread (coroutine) with custom execution limits.
+    ///
+    /// See [`ThreadBuilder`] for details.
+    #[cfg(not(feature = "luau"))]
+    #[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+    pub fn thread_builder(&self) -> ThreadBuilder {
+        ThreadBuilder::new(self)
+    }
+
+    /// Wraps a Lua function into a new thread (or coroutine).
+    ///
+    /// Takes function by reference.
+    fn create_thread_inner<'lua>(&'lua self, func: &Function) -> Result<Thread<'lua>> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 3)?;
+
+            let thread_state = if self.unlikely_memory_error() {
+                ffi::lua_newthread(state)
+            } else {
+                protect_lua!(state, 0, 1, |state| ffi::lua_newthread(state))?
+            };
+            self.push_ref_checked(&func.0)?;
+            ffi::lua_xmove(state, thread_state, 1);
+
+            Ok(Thread(self.pop_ref()))
+        }
+    }
+
+    /// Wraps a Lua function into a new or recycled thread (coroutine).
+    #[cfg(feature = "async")]
+    pub(crate) fn create_recycled_thread<'lua>(
+        &'lua self,
+        func: &Function,
+    ) -> Result<Thread<'lua>> {
+        #[cfg(any(
+            feature = "lua54",
+            all(feature = "luajit", feature = "vendored"),
+            feature = "luau",
+        ))]
+        unsafe {
+            let state = self.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 1)?;
+
+            self.push_ref_checked(&func.0)?;
+            if let Some(index) = (*self.extra.get()).thread_pool.pop() {
+                let thread_state = ffi::lua_tothread(self.ref_thread(), index);
+                ffi::lua_xmove(state, thread_state, 1);
+
+                #[cfg(feature = "luau")]
+                {
+                    // Inherit `LUA_GLOBALSINDEX` from the caller
+                    ffi::lua_xpush(state, thread_state, ffi::LUA_GLOBALSINDEX);
+                    ffi::lua_replace(thread_state, ffi::LUA_GLOBALSINDEX);
+                }
+
+                return Ok(Thread(LuaRef::new(self, index)));
+            }
+        };
+        self.create_thread_inner(func)
+    }
+
+    /// Resets thread (coroutine) and returns to the pool for later use.
+    #[cfg(feature = "async")]
+    #[cfg(any(
+        feature = "lua54",
+        all(feature = "luajit", feature = "vendored"),
+        feature = "luau",
+    ))]
+    pub(crate) unsafe fn recycle_thread(&self, thread: &mut Thread) -> bool {
+        let extra = &mut *self.extra.get();
+        if extra.thread_pool.len() < extra.thread_pool.capacity() {
+            let thread_state = ffi::lua_tothread(extra.ref_thread, thread.0.index);
+            #[cfg(all(feature = "lua54", not(feature = "vendored")))]
+            let status = ffi::lua_resetthread(thread_state);
+            #[cfg(all(feature = "lua54", feature = "vendored"))]
+            let status = ffi::lua_closethread(thread_state, self.state());
+            #[cfg(feature = "lua54")]
+            if status != ffi::LUA_OK {
+                // Error object is on top, drop it
+                ffi::lua_settop(thread_state, 0);
+            }
+            #[cfg(all(feature = "luajit", feature = "vendored"))]
+            ffi::lua_resetthread(self.state(), thread_state);
+            #[cfg(feature = "luau")]
+            ffi::lua_resetthread(thread_state);
+            extra.thread_pool.push(thread.0.index);
+            thread.0.drop = false;
+            return true;
+        }
+        false
+    }
+
+    /// Creates a Lua userdata object from a custom userdata type.
+    ///
+    /// All userdata instances of the same type `T` shares the same metatable.
+    #[inline]
+    pub fn create_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: UserData + MaybeSend + 'static,
+    {
+        unsafe { self.make_userdata(UserDataCell::new(data)) }
+    }
+
+    /// Creates a Lua userdata object from a custom serializable userdata type.
+    ///
+    /// Requires `feature = "serialize"`
+    #[cfg(feature = "serialize")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serialize")))]
+    #[inline]
+    pub fn create_ser_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: UserData + Serialize + MaybeSend + 'static,
+    {
+        unsafe { self.make_userdata(UserDataCell::new_ser(data)) }
+    }
+
+    /// Creates a Lua userdata object from a custom Rust type.
+    ///
+    /// You can register the type using [`Lua::register_userdata_type()`] to add fields or methods
+    /// _before_ calling this method.
+    /// Otherwise, the userdata object will have an empty metatable.
+    ///
+    /// All userdata instances of the same type `T` shares the same metatable.
+    #[inline]
+    pub fn create_any_userdata<T>(&self, data: T) -> Result<AnyUserData>
+    where
+        T: MaybeSend + 'static,
+    {
+        unsafe { self.make_any_userdata(UserDataCell::new(data)) }
+    }
+
+    /// Creates a userdata holding a `u64` value without loss of precision.
+    ///
+    /// Unlike Lua integers (which are signed and might be 32-bit), the value can represent
+    /// any `u64` (eg. IDs above 2^63) regardless of the Lua version and [`IntegerOverflow`] policy.
+    /// It supports arithmetic (`+`, `-`, `*`, `/`, `//`, `%`) with other boxed values or
+    /// non-negative integers, comparison, and `tostring`. Arithmetic results are boxed values and
+    /// overflows raise an error.
+    ///
+    /// The value can be converted back into `u64` (and other integer types) using [`FromLua`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.globals().set("id", lua.create_u64(u64::MAX - 1)?)?;
+    /// let id: u64 = lua.load("id + 1").eval()?;
+    /// assert_eq!(id, u64::MAX);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_u64(&self, v: u64) -> Result<AnyUserData> {
+        self.create_userdata(BoxedU64(v))
+    }
+
+    /// Registers a custom Rust type in Lua to use in userdata objects.
+    ///
+    /// This methods provides a way to add fields or methods to userdata objects of a type `T`.
+    pub fn register_userdata_type<T: 'static>(
+        &self,
+        f: impl FnOnce(&mut UserDataRegistrar<T>),
+    ) -> Result<()> {
+        let mut registry = UserDataRegistrar::new();
+        f(&mut registry);
+
+        unsafe {
+            // Deregister the type if it already registered
+            let type_id = TypeId::of::<T>();
+            if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
+                ffi::luaL_unref(self.state(), ffi::LUA_REGISTRYINDEX, table_id);
+            }
+
+            // Register the type
+            self.register_userdata_metatable(registry)?;
+            Ok(())
+        }
+    }
+
+    /// Registers a class of plain Lua tables under the given name.
+    ///
+    /// This is the table-based counterpart of [`Lua::register_userdata_type`]: methods and
+    /// metamethods are registered once in a shared metatable, and instances are created using
+    /// [`Lua::new_class_instance`]. Instances are regular tables, so scripts can freely read and
+    /// add fields.
+    ///
+    /// Returns the metatable of the class, which can also be used from Lua to create instances
+    /// (eg. with `setmetatable`). Registering a class with an existing name replaces it, existing
+    /// instances keep the previous metatable.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, MetaMethod, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.register_table_class("Point", |class| {
+    ///     class.add_method("length", |_, this, ()| {
+    ///         let (x, y): (f64, f64) = (this.get("x")?, this.get("y")?);
+    ///         Ok((x * x + y * y).sqrt())
+    ///     });
+    ///     class.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+    ///         Ok(format!("Point({}, {})", this.get::<_, f64>("x")?, this.get::<_, f64>("y")?))
+    ///     });
+    /// })?;
+    ///
+    /// let point = lua.new_class_instance("Point", [("x", 3.0), ("y", 4.0)])?;
+    /// lua.globals().set("point", point)?;
+    /// assert_eq!(lua.load("point:length()").eval::<f64>()?, 5.0);
+    /// assert_eq!(lua.load("tostring(point)").eval::<String>()?, "Point(3, 4)");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn register_table_class<'lua>(
+        &'lua self,
+        name: &str,
+        f: impl FnOnce(&mut TableClassRegistrar<'lua>),
+    ) -> Result<Table<'lua>> {
+        table_class::register_table_class(self, name, f)
+    }
+
+    /// Creates an instance of a table class registered using [`Lua::register_table_class`].
+    ///
+    /// The instance is a new table holding the given fields, with the class metatable attached.
+    /// Returns an error if no class with the given name is registered.
+    pub fn new_class_instance<'lua, K, V, I>(
+        &'lua self,
+        name: &str,
+        fields: I,
+    ) -> Result<Table<'lua>>
+    where
+        K: IntoLua<'lua>,
+        V: IntoLua<'lua>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let metatable = table_class::class_metatable(self, name)?;
+        let instance = self.create_table_from(fields)?;
+        instance.set_metatable(Some(metatable));
+        Ok(instance)
+    }
+
+    /// Returns an iterator over all live userdata instances of type `T`.
+    ///
+    /// Instance tracking is opt-in: the type must be registered using
+    /// [`Lua::register_userdata_type()`] with [`UserDataRegistrar::track_instances()`] enabled
+    /// before creating the instances. For untracked types the iterator is empty.
+    ///
+    /// The instances are held weakly, so tracking does not prevent them from being collected.
+    /// Destructed instances (eg. taken using [`AnyUserData::take()`]) are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Player(String);
+    ///
+    /// impl UserData for Player {}
+    ///
+    /// lua.register_userdata_type::<Player>(|reg| {
+    ///     Player::add_fields(reg);
+    ///     Player::add_methods(reg);
+    ///     reg.track_instances();
+    /// })?;
+    ///
+    /// let _alice = lua.create_userdata(Player("alice".into()))?;
+    /// let _bob = lua.create_userdata(Player("bob".into()))?;
+    /// let mut names = lua
+    ///     .iter_userdata::<Player>()
+    ///     .map(|ud| Ok(ud.borrow::<Player>()?.0.clone()))
+    ///     .collect::<Result<Vec<_>>>()?;
+    /// names.sort();
+    /// assert_eq!(names, ["alice", "bob"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`AnyUserData::take()`]: crate::AnyUserData::take
+    pub fn iter_userdata<T: 'static>(&self) -> impl Iterator<Item = AnyUserData> {
+        let table_id = unsafe { (*self.extra.get()).tracked_userdata.get(&TypeId::of::<T>()) };
+        let instances = match table_id {
+            Some(&table_id) => unsafe { userdata_impl::tracked_instances::<T>(self, table_id) },
+            None => Vec::new(),
+        };
+        instances.into_iter()
+    }
+
+    /// Returns the userdata wrapping the given Rust value, if it is exposed to Lua.
+    ///
+    /// Reverse lookup is opt-in: the type must be registered using
+    /// [`Lua::register_userdata_type()`] with [`UserDataRegistrar::reverse_lookup()`] or
+    /// [`UserDataRegistrar::reverse_lookup_by()`] enabled before creating the instances.
+    /// This allows re-exposing the same Rust object as the identical Lua object, preserving
+    /// equality and any state attached to it on the Lua side.
+    ///
+    /// The instances are held weakly. If several instances have the same key, the latest created
+    /// one is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use mlua::{Lua, Result, UserData};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Player;
+    ///
+    /// impl UserData for Player {}
+    ///
+    /// lua.register_userdata_type::<Arc<Player>>(|reg| {
+    ///     reg.reverse_lookup_by(|player| Arc::as_ptr(player) as usize);
+    /// })?;
+    ///
+    /// let player = Arc::new(Player);
+    /// let ud = lua.create_any_userdata(player.clone())?;
+    /// assert_eq!(lua.userdata_for(&player), Some(ud));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn userdata_for<T: 'static>(&self, value: &T) -> Option<AnyUserData> {
+        unsafe {
+            let (table_id, key) = (*self.extra.get())
+                .userdata_lookup
+                .get(&TypeId::of::<T>())?;
+            let key = key.downcast_ref::<fn(&T) -> usize>()?(value);
+
+            let state = self.state();
+            let _sg = StackGuard::new(state);
+            assert_stack(state, 2);
+
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, *table_id as Integer);
+            ffi::lua_pushinteger(state, key as Integer);
+            if ffi::lua_rawget(state, -2) != ffi::LUA_TUSERDATA {
+                return None;
+            }
+            let ud = AnyUserData(self.pop_ref());
+            match ud.inspect(|_: &UserDataCell<T>| Ok(())) {
+                Ok(()) => Some(ud),
+                Err(_) => None,
+            }
+        }
+    }
+
+    /// Create a Lua userdata "proxy" object from a custom userdata type.
+    ///
+    /// Proxy object is an empty userdata object that has `T` metatable attached.
+    /// The main purpose of this object is to provide access to static fields and functions
+    /// without creating an instance of type `T`.
+    ///
+    /// You can get or set uservalues on this object but you cannot borrow any Rust type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataFields, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct MyUserData(i32);
+    ///
+    /// impl UserData for MyUserData {
+    ///     fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+    ///         fields.add_field_method_get("val", |_, this| Ok(this.0));
+    ///     }
+    ///
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_function("new", |_, value: i32| Ok(MyUserData(value)));
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("MyUserData", lua.create_proxy::<MyUserData>()?)?;
+    ///
+    /// lua.load("assert(MyUserData.new(321).val == 321)").exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn create_proxy<T>(&self) -> Result<AnyUserData>
+    where
+        T: 'static + UserData,
+    {
+        unsafe { self.make_userdata(UserDataCell::new(UserDataProxy::<T>(PhantomData))) }
+    }
+
+    /// Returns a handle to the global environment.
+    pub fn globals(&self) -> Table {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            assert_stack(state, 1);
+            // Lua code sees a proxy of the global table (see `Lua::protect_globals`)
+            if let Some(globals_id) = (*self.extra.get()).protected_globals {
+                ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, globals_id as Integer);
+                return Table(self.pop_ref());
+            }
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
+            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+            ffi::lua_pushvalue(state, ffi::LUA_GLOBALSINDEX);
+            Table(self.pop_ref())
+        }
+    }
+
+    /// Restricts writes to global variables by Lua code according to the given policy.
+    ///
+    /// Lua code loaded afterwards gets an empty proxy of the global table as its environment (and
+    /// as `_G`), with a protected metatable checking writes to existing global variables (except
+    /// [`writable`] ones) and creation of new global variables. Global variables created by Lua
+    /// code can be modified by it afterwards. Forbidden writes are reported to
+    /// [`GlobalPolicy::on_violation`] and rejected with a runtime error.
+    ///
+    /// [`Lua::globals`] still returns the global table itself, so Rust code can access and
+    /// iterate over global variables without restrictions. In Lua, `pairs(_G)` iterates over the
+    /// global table on Lua 5.2+ (and Luau generalized iteration), while raw access functions
+    /// (`rawget`, `rawset`, `next`) operate on the proxy.
+    ///
+    /// This is a hardening measure against accidental writes rather than a security boundary:
+    /// functions loaded (and on Lua 5.1, LuaJIT and Luau, coroutines created) before this call
+    /// keep using the global table directly, and `rawset(_G, ...)` bypasses the checks.
+    ///
+    /// Returns an error if the global table already has a metatable or globals are already
+    /// protected.
+    ///
+    /// [`writable`]: crate::GlobalPolicy::writable
+    ///
+    /// [`GlobalPolicy::on_violation`]: crate::GlobalPolicy::on_violation
+    pub fn protect_globals(&self, policy: GlobalPolicy) -> Result<()> {
+        globals::protect_globals(self, policy)
+    }
+
+    /// Updates several global variables at once.
+    ///
+    /// Changes made by the function `f` using the provided [`GlobalsTransaction`] are staged and
+    /// applied only if it returns `Ok`, all together, so Lua code never observes a partially
+    /// applied update. If `f` returns an error, global variables are left unchanged.
+    ///
+    /// Global variables are written without invoking metamethods, so the policy of
+    /// [protected](Lua::protect_globals) global variables does not apply.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// lua.update_globals(|tx| {
+    ///     tx.set("width", 3)?;
+    ///     tx.set("area", tx.get::<i64>("width")? * 2)?;
+    ///     Ok(())
+    /// })?;
+    /// assert_eq!(lua.load("area").eval::<i64>()?, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`GlobalsTransaction`]: crate::GlobalsTransaction
+    pub fn update_globals<'lua, F, R>(&'lua self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut GlobalsTransaction<'lua>) -> Result<R>,
+    {
+        globals::update_globals(self, f)
+    }
+
+    /// Replaces the global `print` function with one passing its output to the given handler.
+    ///
+    /// Arguments are converted to strings (using `__tostring` metamethods if present) and
+    /// joined with tabs, as in the standard `print`. The handler receives the resulting line
+    /// without the trailing newline.
+    ///
+    /// This is useful on targets without a console (eg. embedded devices), or to capture output
+    /// of scripts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::sync::{Arc, Mutex};
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let output = Arc::new(Mutex::new(Vec::new()));
+    /// let output2 = output.clone();
+    /// lua.set_print_handler(move |_, line| {
+    ///     output2.lock().unwrap().push(line.to_string());
+    ///     Ok(())
+    /// })?;
+    /// lua.load("print('answer:', 42)").exec()?;
+    /// assert_eq!(*output.lock().unwrap(), vec!["answer:\t42"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_print_handler<F>(&self, handler: F) -> Result<()>
+    where
+        F: Fn(&Lua, &str) -> Result<()> + MaybeSend + 'static,
+    {
+        host::set_print_handler(self, handler)
+    }
+
+    /// Sets the clock used to measure time, and replaces the `os.clock` function with one
+    /// reading it.
+    ///
+    /// The clock returns the time elapsed since an arbitrary fixed point (eg. a monotonic timer
+    /// of the device). Besides `os.clock`, it's used by [`HookTriggers::every`] and
+    /// [`Lua::gc_collect_incremental`], which otherwise use the system clock. With the `embedded`
+    /// feature the system clock is never used: `every` is ignored and `gc_collect_incremental`
+    /// performs a single step until a clock is set.
+    ///
+    /// If the `os` library is not loaded, an `os` table with only the `clock` function is
+    /// created.
+    ///
+    /// [`HookTriggers::every`]: crate::HookTriggers::every
+    pub fn set_clock<F>(&self, clock: F) -> Result<()>
+    where
+        F: Fn() -> Duration + MaybeSend + 'static,
+    {
+        unsafe { (*self.extra.get()).clock = Some(Box::new(clock)) };
+        host::set_clock(self)
+    }
+
+    #[inline]
+    pub(crate) fn clock_now(&self) -> Option<Duration> {
+        unsafe { (*self.extra.get()).clock_now() }
+    }
+
+    /// Returns the features of this Lua state: Lua version, loaded standard libraries,
+    /// registered modules and async support.
+    ///
+    /// Modules are the entries of `package.loaded` other than standard libraries, eg. modules
+    /// loaded by scripts or set by the host.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        host::capabilities(self)
+    }
+
+    /// Installs a read-only `host.capabilities` table describing the features of this Lua
+    /// state, so scripts can feature-detect the embedding.
+    ///
+    /// The table has `version`, `mlua_version` and `async` fields and `std_libs` and `modules`
+    /// sets (tables with `true` values) and is a snapshot of [`Lua::capabilities`] at the time of
+    /// the call. The global `host` table is created if it does not exist.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// lua.expose_capabilities()?;
+    /// let has_string: bool = lua.load("host.capabilities.std_libs.string == true").eval()?;
+    /// assert!(has_string);
+    /// assert!(lua.load("host.capabilities.version = 1").exec().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expose_capabilities(&self) -> Result<()> {
+        host::expose_capabilities(self)
+    }
+
+    // Makes `proxy` the global environment of Lua code, keeping the global table for Rust code
+    pub(crate) fn set_protected_globals(&self, globals: &Table, proxy: &Table) -> Result<()> {
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 2)?;
+            self.push_ref(&globals.0);
+            let globals_id = protect_lua!(state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+            (*self.extra.get()).protected_globals = Some(globals_id);
+
+            self.push_ref(&proxy.0);
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+            ffi::lua_rawseti(state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
+            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
+            {
+                // Each thread has its own environment, new threads inherit it
+                let main_state = self.main_state;
+                if main_state != state {
+                    check_stack(main_state, 1)?;
+                    ffi::lua_pushvalue(state, -1);
+                    ffi::lua_xmove(state, main_state, 1);
+                    ffi::lua_replace(main_state, ffi::LUA_GLOBALSINDEX);
+                }
+                ffi::lua_replace(state, ffi::LUA_GLOBALSINDEX);
+            }
+            Ok(())
+        }
     }
 
-    /// Creates a Lua userdata object from a custom Rust type.
-    ///
-    /// You can register the type using [`Lua::register_userdata_type()`] to add fields or methods
-    /// _before_ calling this method.
-    /// Otherwise, the userdata object will have an empty metatable.
-    ///
-    /// All userdata instances of the same type `T` shares the same metatable.
     #[inline]
-    pub fn create_any_userdata<T>(&self, data: T) -> Result<AnyUserData>
-    where
-        T: MaybeSend + 'static,
-    {
-        unsafe { self.make_any_userdata(UserDataCell::new(data)) }
+    pub(crate) fn has_protected_globals(&self) -> bool {
+        unsafe { (*self.extra.get()).protected_globals.is_some() }
     }
 
-    /// Registers a custom Rust type in Lua to use in userdata objects.
-    ///
-    /// This methods provides a way to add fields or methods to userdata objects of a type `T`.
-    pub fn register_userdata_type<T: 'static>(
-        &self,
-        f: impl FnOnce(&mut UserDataRegistrar<T>),
-    ) -> Result<()> {
-        let mut registry = UserDataRegistrar::new();
-        f(&mut registry);
-
+    // Returns a handle to the registry table
+    pub(crate) fn registry_table(&self) -> Result<Table> {
+        let state = self.state();
         unsafe {
-            // Deregister the type if it already registered
-            let type_id = TypeId::of::<T>();
-            if let Some(&table_id) = (*self.extra.get()).registered_userdata.get(&type_id) {
-                ffi::luaL_unref(self.state(), ffi::LUA_REGISTRYINDEX, table_id);
-            }
-
-            // Register the type
-            self.register_userdata_metatable(registry)?;
-            Ok(())
+            let _sg = StackGuard::new(state);
+            check_stack(state, 1)?;
+            ffi::lua_pushvalue(state, ffi::LUA_REGISTRYINDEX);
+            Ok(Table(self.pop_ref()))
         }
     }
 
-    /// Create a Lua userdata "proxy" object from a custom userdata type.
+    /// Walks all tables reachable from the global environment and the registry and reports
+    /// potential memory savings.
     ///
-    /// Proxy object is an empty userdata object that has `T` metatable attached.
-    /// The main purpose of this object is to provide access to static fields and functions
-    /// without creating an instance of type `T`.
+    /// The report includes string keys repeated across tables, groups of structurally identical
+    /// tables and the tables retaining the largest amount of data. It's intended to help reduce
+    /// memory usage of states holding large amounts of configuration data.
     ///
-    /// You can get or set uservalues on this object but you cannot borrow any Rust type.
+    /// Metatables, upvalues and userdata user values are not inspected. The walk visits every
+    /// reachable table, so it can be slow for big states.
     ///
     /// # Examples
     ///
     /// ```
-    /// # use mlua::{Lua, Result, UserData, UserDataFields, UserDataMethods};
+    /// # use mlua::{Lua, Result};
     /// # fn main() -> Result<()> {
     /// # let lua = Lua::new();
-    /// struct MyUserData(i32);
-    ///
-    /// impl UserData for MyUserData {
-    ///     fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
-    ///         fields.add_field_method_get("val", |_, this| Ok(this.0));
-    ///     }
-    ///
-    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
-    ///         methods.add_function("new", |_, value: i32| Ok(MyUserData(value)));
+    /// lua.load(r#"
+    ///     config = {
+    ///         servers = {
+    ///             { port = 80, tls = false },
+    ///             { port = 80, tls = false },
+    ///         },
     ///     }
-    /// }
-    ///
-    /// lua.globals().set("MyUserData", lua.create_proxy::<MyUserData>()?)?;
-    ///
-    /// lua.load("assert(MyUserData.new(321).val == 321)").exec()?;
+    /// "#).exec()?;
+    /// let analysis = lua.heap_analysis()?;
+    /// let group = &analysis.duplicate_tables[0];
+    /// assert_eq!(group.paths, ["_G.config.servers[1]", "_G.config.servers[2]"]);
     /// # Ok(())
     /// # }
     /// ```
-    #[inline]
-    pub fn create_proxy<T>(&self) -> Result<AnyUserData>
+    pub fn heap_analysis(&self) -> Result<HeapAnalysis> {
+        heap::analyze(self)
+    }
+
+    /// Loads a plugin using the FFI-safe [`plugin`] interface.
+    ///
+    /// Calls the plugin `entry` point, which registers functions and userdata types, and returns
+    /// a table with the registered functions.
+    ///
+    /// # Safety
+    ///
+    /// The entry point and all functions registered by the plugin must follow the [`plugin`]
+    /// interface, and must stay valid (ie. the plugin library must stay loaded) while this `Lua`
+    /// instance exists. With the `send` feature, the `Lua` instance can be moved to another
+    /// thread, so plugin functions, their data and plugin userdata (including destructors) must be
+    /// usable from any thread.
+    ///
+    /// [`plugin`]: crate::plugin
+    #[cfg(feature = "plugin")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "plugin")))]
+    pub unsafe fn load_plugin(&self, entry: PluginEntry) -> Result<Table> {
+        plugin::load(self, entry)
+    }
+
+    /// Creates a new [`Signal`] with no connected handlers.
+    ///
+    /// `A` is the type of arguments passed to handlers when the signal is emitted from Rust.
+    /// The signal can be passed to Lua where handlers can be connected and the signal emitted
+    /// using `connect`, `disconnect` and `emit` methods.
+    pub fn create_signal<'lua, A>(&'lua self) -> Result<Signal<'lua, A>>
     where
-        T: 'static + UserData,
+        A: IntoLuaMulti<'lua>,
     {
-        unsafe { self.make_userdata(UserDataCell::new(UserDataProxy::<T>(PhantomData))) }
+        Signal::new(self)
     }
 
-    /// Returns a handle to the global environment.
-    pub fn globals(&self) -> Table {
-        let state = self.state();
-        unsafe {
-            let _sg = StackGuard::new(state);
-            assert_stack(state, 1);
-            #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
-            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, ffi::LUA_RIDX_GLOBALS);
-            #[cfg(any(feature = "lua51", feature = "luajit", feature = "luau"))]
-            ffi::lua_pushvalue(state, ffi::LUA_GLOBALSINDEX);
-            Table(self.pop_ref())
-        }
+    /// Creates a new empty [`StringBuilder`].
+    ///
+    /// The builder can be passed to Lua where strings are accumulated using the `append` method,
+    /// and the result is produced by `build` (or drained from Rust by [`StringBuilder::take`]).
+    pub fn create_string_builder(&self) -> Result<StringBuilder> {
+        StringBuilder::new(self)
     }
 
     /// Returns a handle to the active `Thread`. For calls to `Lua` this will be the main Lua thread,
@@ -1875,6 +4232,68 @@ impl Lua {
         f(&Scope::new(self))
     }
 
+    /// An asynchronous version of [`scope`] that allows to hold scoped values across awaits.
+    ///
+    /// The `Scope` is passed to `f` by value and lives in the returned future, so functions and
+    /// userdata created through it stay valid while the future runs (including when calling Lua
+    /// code asynchronously) and are invalidated when the future completes or is dropped.
+    ///
+    /// Requires `feature = "async"`
+    ///
+    /// # Safety
+    ///
+    /// The returned future must be either polled to completion or dropped. Leaking it (eg. using
+    /// [`std::mem::forget`]) leaves the scoped values accessible from Lua after the borrowed
+    /// data has gone away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// struct Counter(u32);
+    ///
+    /// impl UserData for Counter {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method_mut("incr", |_, this, ()| {
+    ///             this.0 += 1;
+    ///             Ok(())
+    ///         });
+    ///     }
+    /// }
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let run = lua
+    ///     .load("local counter = ...; counter:incr(); coroutine.yield(); counter:incr()")
+    ///     .into_function()?;
+    ///
+    /// let mut counter = Counter(0);
+    /// let counter_ref = &mut counter;
+    /// unsafe {
+    ///     lua.async_scope(|scope| async move {
+    ///         let counter = scope.create_userdata_ref_mut(counter_ref)?;
+    ///         run.call_async::<_, ()>(counter).await
+    ///     })
+    ///     .await?;
+    /// }
+    /// assert_eq!(counter.0, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`scope`]: #method.scope
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub unsafe fn async_scope<'lua, 'scope, R, F, FR>(&'lua self, f: F) -> FR
+    where
+        'lua: 'scope,
+        F: FnOnce(Scope<'lua, 'scope>) -> FR,
+        FR: Future<Output = Result<R>> + 'scope,
+    {
+        f(Scope::new(self))
+    }
+
     /// Attempts to coerce a Lua value into a String in a manner consistent with Lua's internal
     /// behavior.
     ///
@@ -1979,6 +4398,203 @@ impl Lua {
         T::from_lua_multi(value, self)
     }
 
+    /// Registers a coercion from values of type `F` to `T`.
+    ///
+    /// Coercions are applied only by [`UserDataRef`] when the value is not a userdata of type `T`
+    /// (the coerced value is then wrapped into a new userdata), and by [`Lua::coerce`]. Other
+    /// [`FromLua`] implementations don't use them, unless they call [`Lua::coerce`] themselves.
+    /// [`UserDataRefMut`] never coerces, as changes made through it would be lost with the
+    /// temporary userdata.
+    ///
+    /// Coercions to the same type are tried in registration order, using the first one for which
+    /// the value can be converted to `F`. The target type must be convertible to Lua, so that
+    /// the coerced value can be passed back to Lua as a userdata.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Lua, Result, UserData, UserDataRef};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// #[derive(Clone, Copy)]
+    /// struct Vec2(f64, f64);
+    ///
+    /// impl UserData for Vec2 {}
+    ///
+    /// lua.register_coercion(|_, [x, y]: [f64; 2]| Ok(Vec2(x, y)))?;
+    /// lua.register_coercion(|_, s: String| {
+    ///     let err = || Error::RuntimeError("expected `x,y`".to_string());
+    ///     let (x, y) = s.split_once(',').ok_or_else(err)?;
+    ///     let parse = |v: &str| v.trim().parse::<f64>().map_err(Error::external);
+    ///     Ok(Vec2(parse(x)?, parse(y)?))
+    /// })?;
+    ///
+    /// let len = lua.create_function(|_, v: UserDataRef<Vec2>| Ok(v.0.hypot(v.1)))?;
+    /// lua.globals().set("len", len)?;
+    /// assert_eq!(lua.load("len({3, 4}) + len('6, 8')").eval::<f64>()?, 15.0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`UserDataRef`]: crate::UserDataRef
+    /// [`UserDataRefMut`]: crate::UserDataRefMut
+    pub fn register_coercion<F, T, C>(&self, func: C) -> Result<()>
+    where
+        F: for<'lua> FromLua<'lua>,
+        T: for<'lua> IntoLua<'lua> + 'static,
+        C: Fn(&Lua, F) -> Result<T> + MaybeSend + 'static,
+    {
+        fn into_lua<'lua, T: for<'a> IntoLua<'a> + 'static>(
+            lua: &'lua Lua,
+            value: Box<dyn Any>,
+        ) -> Result<Value<'lua>> {
+            match value.downcast::<T>() {
+                Ok(value) => value.into_lua(lua),
+                Err(_) => unreachable!(),
+            }
+        }
+
+        let coercion = Coercion {
+            convert: Box::new(move |lua, value| {
+                let value = F::from_lua(value, lua).ok()?;
+                Some(func(lua, value).map(|value| Box::new(value) as Box<dyn Any>))
+            }),
+            into_lua: into_lua::<T>,
+        };
+        let coercions = unsafe { &mut (*self.extra.get()).coercions };
+        coercions
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(coercion);
+        Ok(())
+    }
+
+    /// Converts a value to `T` using coercions registered by [`Lua::register_coercion`].
+    ///
+    /// Returns [`Error::FromLuaConversionError`] if no coercion to `T` applies to the value.
+    pub fn coerce<'lua, T: 'static>(&'lua self, value: Value<'lua>) -> Result<T> {
+        match self.apply_coercions::<T, _>(value.clone(), |_, value| Ok(value)) {
+            Some(value) => Ok(*value?.downcast::<T>().unwrap()),
+            None => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: std::any::type_name::<T>(),
+                message: Some("no coercion applies".to_string()),
+            }),
+        }
+    }
+
+    // Converts a value to `T` using registered coercions and then back to a Lua value
+    pub(crate) fn coerce_to_lua<'lua, T: 'static>(
+        &'lua self,
+        value: Value<'lua>,
+    ) -> Option<Result<Value<'lua>>> {
+        self.apply_coercions::<T, _>(value, |coercion, value| (coercion.into_lua)(self, value))
+    }
+
+    fn apply_coercions<'lua, T: 'static, R>(
+        &'lua self,
+        value: Value<'lua>,
+        map: impl Fn(&Coercion, Box<dyn Any>) -> Result<R>,
+    ) -> Option<Result<R>> {
+        let type_id = TypeId::of::<T>();
+        // Coercions are taken out for the duration of the call, as they can run Lua code
+        let coercions = unsafe { (*self.extra.get()).coercions.get_mut(&type_id) };
+        let coercions = mem::take(coercions?);
+        let result = (coercions.iter()).find_map(|coercion| {
+            let value = (coercion.convert)(self, value.clone())?;
+            Some(value.and_then(|value| map(coercion, value)))
+        });
+        let slot = unsafe { (*self.extra.get()).coercions.entry(type_id).or_default() };
+        let registered = mem::replace(slot, coercions);
+        slot.extend(registered);
+        result
+    }
+
+    /// Packs values into a binary string according to the format string `fmt`.
+    ///
+    /// Follows the semantics of the Lua 5.4 [`string.pack`] function on every backend, including
+    /// the ones that do not provide it natively. Use [`Lua::string_unpack`] for the reverse
+    /// operation.
+    ///
+    /// On Lua 5.1, Lua 5.2 and LuaJIT the same implementation is installed as `string.pack` and
+    /// `string.unpack` when the `string` library is loaded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let bytes = lua.string_pack("<i4z", (7, "abc"))?;
+    /// assert_eq!(bytes, b"\x07\0\0\0abc\0");
+    /// let (n, s): (i32, String) = lua.string_unpack("<i4z", &bytes)?;
+    /// assert_eq!((n, s.as_str()), (7, "abc"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`string.pack`]: https://www.lua.org/manual/5.4/manual.html#pdf-string.pack
+    pub fn string_pack<'lua>(
+        &'lua self,
+        fmt: &str,
+        values: impl IntoLuaMulti<'lua>,
+    ) -> Result<Vec<u8>> {
+        crate::pack::pack(self, fmt.as_bytes(), values.into_lua_multi(self)?)
+    }
+
+    /// Unpacks values from a binary string according to the format string `fmt`.
+    ///
+    /// Follows the semantics of the Lua 5.4 [`string.unpack`] function, except that the position
+    /// of the first unread byte is not returned.
+    ///
+    /// [`string.unpack`]: https://www.lua.org/manual/5.4/manual.html#pdf-string.unpack
+    pub fn string_unpack<'lua, R: FromLuaMulti<'lua>>(
+        &'lua self,
+        fmt: &str,
+        data: impl AsRef<[u8]>,
+    ) -> Result<R> {
+        R::from_lua_multi(
+            crate::pack::unpack(self, fmt.as_bytes(), data.as_ref())?,
+            self,
+        )
+    }
+
+    /// Encodes binary data as text, returning the result as a Lua string.
+    ///
+    /// Use [`Lua::decode`] for the reverse operation. The same conversions are available to Lua
+    /// code through the `encoding` library (see [`Lua::load_encoding_library`]).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Encoding, Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let text = lua.encode(b"\x00\xffmlua", Encoding::Base64)?;
+    /// assert_eq!(text, "AP9tbHVh");
+    /// assert_eq!(lua.decode(text.as_bytes(), Encoding::Base64)?, b"\x00\xffmlua"[..]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encode<'lua>(
+        &'lua self,
+        data: impl AsRef<[u8]>,
+        encoding: Encoding,
+    ) -> Result<String<'lua>> {
+        encoding::encode(self, data.as_ref(), encoding)
+    }
+
+    /// Decodes text produced by [`Lua::encode`] back into binary data.
+    ///
+    /// Returns an error if `data` is not valid for the encoding.
+    pub fn decode<'lua>(
+        &'lua self,
+        data: impl AsRef<[u8]>,
+        encoding: Encoding,
+    ) -> Result<String<'lua>> {
+        encoding::decode(self, data.as_ref(), encoding)
+    }
+
     /// Set a value in the Lua registry based on a string name.
     ///
     /// This value will be available to rust from all `Lua` instances which share the same main
@@ -2056,12 +4672,9 @@ impl Lua {
 
             self.push_value(t)?;
 
-            // Try to reuse previously allocated slot
+            // Try to reuse the slot of a dropped key
             let unref_list = (*self.extra.get()).registry_unref_list.clone();
-            let free_registry_id = mlua_expect!(unref_list.lock(), "unref list poisoned")
-                .as_mut()
-                .and_then(|x| x.pop());
-            if let Some(registry_id) = free_registry_id {
+            if let Some(registry_id) = unref_list.pop() {
                 // It must be safe to replace the value without triggering memory error
                 ffi::lua_rawseti(state, ffi::LUA_REGISTRYINDEX, registry_id as Integer);
                 return Ok(RegistryKey::new(registry_id, unref_list));
@@ -2181,19 +4794,34 @@ impl Lua {
 
     /// Remove any registry values whose `RegistryKey`s have all been dropped.
     ///
-    /// Unlike normal handle values, `RegistryKey`s do not automatically remove themselves on Drop,
-    /// but you can call this method to remove any unreachable registry values not manually removed
-    /// by `Lua::remove_registry_value`.
+    /// This is done automatically when the garbage collector is run (slots of dropped keys are
+    /// also reused by [`create_registry_value`]), but you can call this method to release the
+    /// values immediately.
+    ///
+    /// [`create_registry_value`]: #method.create_registry_value
     pub fn expire_registry_values(&self) {
+        let unref_list = unsafe { &(*self.extra.get()).registry_unref_list };
+        if unref_list.is_empty() {
+            return;
+        }
+        let mut ids = Vec::new();
+        unref_list.drain(|id| ids.push(id));
+
         let state = self.state();
-        unsafe {
-            let mut unref_list = mlua_expect!(
-                (*self.extra.get()).registry_unref_list.lock(),
-                "unref list poisoned"
-            );
-            let unref_list = mem::replace(&mut *unref_list, Some(Vec::new()));
-            for id in mlua_expect!(unref_list, "unref list not set") {
-                ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id);
+        let released = Cell::new(0);
+        let res = unsafe {
+            // `luaL_unref` can allocate when updating the free list (Lua 5.1-5.3)
+            protect_lua!(state, 0, 0, |state| {
+                for &id in &ids {
+                    ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id);
+                    released.set(released.get() + 1);
+                }
+            })
+        };
+        if res.is_err() {
+            // Retry the rest on the next call
+            for &id in &ids[released.get()..] {
+                unref_list.push(id);
             }
         }
     }
@@ -2267,18 +4895,122 @@ impl Lua {
     #[track_caller]
     pub fn app_data_mut<T: 'static>(&self) -> Option<AppDataRefMut<T>> {
         let extra = unsafe { &*self.extra.get() };
-        extra.app_data.borrow_mut()
+        extra.app_data.borrow_mut()
+    }
+
+    /// Removes an application data of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the app data container is currently borrowed.
+    #[track_caller]
+    pub fn remove_app_data<T: 'static>(&self) -> Option<T> {
+        let extra = unsafe { &*self.extra.get() };
+        extra.app_data.remove()
+    }
+
+    /// Applies pending updates of all [`ValueMirror`]s attached to this Lua state.
+    ///
+    /// This is the "safe point" at which changes made by [`ValueMirror::update`] become visible
+    /// to Lua code. Mirrors that have not changed since the last call are skipped.
+    ///
+    /// All mirrors are synchronized even if some of them fail; the first error is returned.
+    ///
+    /// [`ValueMirror`]: crate::ValueMirror
+    /// [`ValueMirror::update`]: crate::ValueMirror::update
+    pub fn sync_mirrors(&self) -> Result<()> {
+        let extra = self.extra.get();
+        // Take the list out to allow attaching new mirrors from the callbacks
+        let mut mirrors = mem::take(unsafe { &mut (*extra).mirrors });
+        let mut result = Ok(());
+        for sync in &mut mirrors {
+            if let Err(err) = sync(self) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        unsafe {
+            let attached = mem::replace(&mut (*extra).mirrors, mirrors);
+            (*extra).mirrors.extend(attached);
+        }
+        result
+    }
+
+    /// Maps a [`SharedDataSegment`] into this Lua state.
+    ///
+    /// Returns a read-only proxy of the segment root table. The data itself is not copied into
+    /// the state, so the same segment can be attached to many states at a low memory cost.
+    ///
+    /// [`SharedDataSegment`]: crate::SharedDataSegment
+    pub fn attach_shared_data(&self, segment: &SharedDataSegment) -> Result<AnyUserData> {
+        crate::shared::attach_shared_data(self, segment)
+    }
+
+    pub(crate) fn add_mirror_sync(&self, sync: MirrorSync) {
+        unsafe { (*self.extra.get()).mirrors.push(sync) };
+    }
+
+    /// Exposes an application data object of type `T` as a global userdata named `name`.
+    ///
+    /// The global does not hold a copy of the data. Instead, its fields and methods (as defined by
+    /// the [`UserData`] implementation of `T`) borrow the object stored using
+    /// [`Lua::set_app_data()`] on every access, so Rust and Lua always share the same value.
+    ///
+    /// Accessing the global when the app data object is not set (or already borrowed in an
+    /// incompatible way) returns an error. Async methods are not supported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct World {
+    ///     tick: u64,
+    /// }
+    ///
+    /// impl UserData for World {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method_mut("advance", |_, this, ()| {
+    ///             this.tick += 1;
+    ///             Ok(this.tick)
+    ///         });
+    ///     }
+    /// }
+    ///
+    /// lua.set_app_data(World { tick: 0 });
+    /// lua.expose_app_data_as_global::<World>("world")?;
+    ///
+    /// lua.load("world:advance(); world:advance()").exec()?;
+    /// assert_eq!(lua.app_data_ref::<World>().unwrap().tick, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expose_app_data_as_global<T: UserData + 'static>(&self, name: &str) -> Result<()> {
+        let proxy =
+            unsafe { self.make_userdata(UserDataCell::new(AppDataProxy::<T>(PhantomData)))? };
+        self.globals().raw_set(name, proxy)
+    }
+
+    /// Tries to borrow app data of type `T`, returning `Ok(None)` if no such data is set.
+    pub(crate) fn try_app_data_ref<T: 'static>(&self) -> Result<Option<AppDataRef<T>>> {
+        let extra = unsafe { &*self.extra.get() };
+        match extra.app_data.try_borrow() {
+            Some(Ok(data)) => Ok(Some(data)),
+            Some(Err(_)) => Err(Error::UserDataBorrowError),
+            None => Ok(None),
+        }
     }
 
-    /// Removes an application data of type `T`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the app data container is currently borrowed.
-    #[track_caller]
-    pub fn remove_app_data<T: 'static>(&self) -> Option<T> {
+    /// Tries to mutably borrow app data of type `T`, returning `Ok(None)` if no such data is set.
+    pub(crate) fn try_app_data_mut<T: 'static>(&self) -> Result<Option<AppDataRefMut<T>>> {
         let extra = unsafe { &*self.extra.get() };
-        extra.app_data.remove()
+        match extra.app_data.try_borrow_mut() {
+            Some(Ok(data)) => Ok(Some(data)),
+            Some(Err(_)) => Err(Error::UserDataBorrowMutError),
+            None => Ok(None),
+        }
     }
 
     // Uses 2 stack spaces, does not call checkstack
@@ -2314,23 +5046,23 @@ impl Lua {
             }
 
             Value::String(s) => {
-                self.push_ref(&s.0);
+                self.push_ref_checked(&s.0)?;
             }
 
             Value::Table(t) => {
-                self.push_ref(&t.0);
+                self.push_ref_checked(&t.0)?;
             }
 
             Value::Function(f) => {
-                self.push_ref(&f.0);
+                self.push_ref_checked(&f.0)?;
             }
 
             Value::Thread(t) => {
-                self.push_ref(&t.0);
+                self.push_ref_checked(&t.0)?;
             }
 
             Value::UserData(ud) => {
-                self.push_ref(&ud.0);
+                self.push_ref_checked(&ud.0)?;
             }
 
             Value::Error(err) => {
@@ -2437,9 +5169,17 @@ impl Lua {
             ffi::LUA_TCDATA => {
                 ffi::lua_pop(state, 1);
                 // TODO: Fix this in a next major release
+                #[cfg(feature = "strict-no-panic")]
+                return Value::Error(Error::Internal(
+                    "cdata objects cannot be handled by mlua yet".to_string(),
+                ));
+                #[cfg(not(feature = "strict-no-panic"))]
                 panic!("cdata objects cannot be handled by mlua yet");
             }
 
+            #[cfg(feature = "strict-no-panic")]
+            _ => Value::Error(Error::Internal("LUA_TNONE in pop_value".to_string())),
+            #[cfg(not(feature = "strict-no-panic"))]
             _ => mlua_panic!("LUA_TNONE in pop_value"),
         }
     }
@@ -2453,6 +5193,17 @@ impl Lua {
         ffi::lua_xpush(self.ref_thread(), self.state(), lref.index);
     }
 
+    // Same as `push_ref`, but returns `Error::StateMismatch` instead of panicking if the reference
+    // belongs to a different Lua state (with `strict-no-panic` feature)
+    pub(crate) unsafe fn push_ref_checked(&self, lref: &LuaRef) -> Result<()> {
+        #[cfg(feature = "strict-no-panic")]
+        if !Arc::ptr_eq(&lref.lua.0, &self.0) {
+            return Err(Error::StateMismatch);
+        }
+        self.push_ref(lref);
+        Ok(())
+    }
+
     // Pops the topmost element of the stack and stores a reference to it. This pins the object,
     // preventing garbage collection until the returned `LuaRef` is dropped.
     //
@@ -2505,14 +5256,120 @@ impl Lua {
         LuaRef::new(self, index)
     }
 
+    // Returns a host-only method, field getter or field setter (`kind`) of the userdata
+    pub(crate) fn userdata_host_member<'lua>(
+        &'lua self,
+        ud: &AnyUserData<'lua>,
+        kind: &str,
+        name: &str,
+    ) -> Result<Option<Function<'lua>>> {
+        let type_id = match ud.type_id()? {
+            Some(type_id) => type_id,
+            None => return Ok(None),
+        };
+        let members = unsafe {
+            let id = match (*self.extra.get()).host_only_members.get(&type_id) {
+                Some(&id) => id,
+                None => return Ok(None),
+            };
+            let state = self.state();
+            let _sg = StackGuard::new(state);
+            check_stack(state, 1)?;
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, id as Integer);
+            Table(self.pop_ref())
+        };
+        members.raw_get::<_, Table>(kind)?.raw_get(name)
+    }
+
     unsafe fn register_userdata_metatable<'lua, T: 'static>(
         &'lua self,
         mut registry: UserDataRegistrar<'lua, T>,
     ) -> Result<Integer> {
+        registry.check_conflicts()?;
+
         let state = self.state();
         let _sg = StackGuard::new(state);
         check_stack(state, 13)?;
 
+        // Create (or drop) the weak table with live instances
+        let type_id = TypeId::of::<T>();
+        let tracked_id = (*self.extra.get()).tracked_userdata.get(&type_id).copied();
+        match (registry.track_instances, tracked_id) {
+            (true, None) => {
+                let id = userdata_impl::create_tracking_table(state)?;
+                (*self.extra.get()).tracked_userdata.insert(type_id, id);
+            }
+            (false, Some(id)) => {
+                (*self.extra.get()).tracked_userdata.remove(&type_id);
+                ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id);
+            }
+            _ => {}
+        }
+
+        let borrow_policies = &mut (*self.extra.get()).borrow_policies;
+        match registry.borrow_policy {
+            BorrowPolicy::Error => borrow_policies.remove(&type_id),
+            policy => borrow_policies.insert(type_id, policy),
+        };
+
+        // Move host-only members to a separate table, hidden from Lua
+        if let Some(id) = (*self.extra.get()).host_only_members.remove(&type_id) {
+            ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id);
+        }
+        if !registry.host_only.is_empty() {
+            let host_only = registry.take_host_only();
+            let (methods, getters, setters) = (
+                self.create_table()?,
+                self.create_table()?,
+                self.create_table()?,
+            );
+            for (k, m) in host_only.methods {
+                methods.raw_set(k, self.create_callback(m)?)?;
+            }
+            #[cfg(feature = "async")]
+            for (k, m) in host_only.async_methods {
+                methods.raw_set(k, self.create_async_callback(m)?)?;
+            }
+            for (k, m) in host_only.getters {
+                getters.raw_set(k, self.create_callback(m)?)?;
+            }
+            for (k, m) in host_only.setters {
+                setters.raw_set(k, self.create_callback(m)?)?;
+            }
+            let members = self.create_table_from([
+                ("methods", methods),
+                ("getters", getters),
+                ("setters", setters),
+            ])?;
+            self.push_ref(&members.0);
+            let id = protect_lua!(state, 1, 0, |state| {
+                ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+            })?;
+            (*self.extra.get()).host_only_members.insert(type_id, id);
+        }
+
+        // Create (or drop) the weak table for reverse lookups
+        let lookup = (*self.extra.get()).userdata_lookup.remove(&type_id);
+        match (registry.lookup_key, lookup) {
+            (Some(key), lookup) => {
+                let id = match lookup {
+                    Some((id, _)) => id,
+                    None => protect_lua!(state, 0, 0, |state| {
+                        ffi::lua_createtable(state, 0, 0);
+                        ffi::lua_createtable(state, 0, 1);
+                        ffi::lua_pushstring(state, cstr!("v"));
+                        ffi::lua_setfield(state, -2, cstr!("__mode"));
+                        ffi::lua_setmetatable(state, -2);
+                        ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+                    })?,
+                };
+                let lookup = (id, Box::new(key) as Box<dyn Any + Send>);
+                (*self.extra.get()).userdata_lookup.insert(type_id, lookup);
+            }
+            (None, Some((id, _))) => ffi::luaL_unref(state, ffi::LUA_REGISTRYINDEX, id),
+            (None, None) => {}
+        }
+
         // Prepare metatable, add meta methods first and then meta fields
         let metatable_nrec = registry.meta_methods.len() + registry.meta_fields.len();
         #[cfg(feature = "async")]
@@ -2541,6 +5398,16 @@ impl Lua {
         }
         let metatable_index = ffi::lua_absindex(state, -1);
 
+        // Documented methods are named after the `__name` field
+        let mut method_docs = FxHashMap::default();
+        if !registry.method_docs.is_empty() {
+            ffi::lua_getfield(state, metatable_index, cstr!("__name"));
+            let type_name = self.pop_value().to_string()?;
+            for (k, meta) in registry.method_docs {
+                method_docs.insert(k.clone(), (format!("{type_name}:{k}"), meta));
+            }
+        }
+
         let mut extra_tables_count = 0;
 
         let fields_nrec = registry.fields.len();
@@ -2609,12 +5476,20 @@ impl Lua {
                 }
             }
             for (k, m) in registry.methods {
-                self.push_value(Value::Function(self.create_callback(m)?))?;
+                let func = self.create_callback(m)?;
+                if let Some((name, meta)) = method_docs.remove(&k) {
+                    docs::set_meta(self, &func, Some(&name), meta)?;
+                }
+                self.push_value(Value::Function(func))?;
                 rawset_field(state, -2, &k)?;
             }
             #[cfg(feature = "async")]
             for (k, m) in registry.async_methods {
-                self.push_value(Value::Function(self.create_async_callback(m)?))?;
+                let func = self.create_async_callback(m)?;
+                if let Some((name, meta)) = method_docs.remove(&k) {
+                    docs::set_meta(self, &func, Some(&name), meta)?;
+                }
+                self.push_value(Value::Function(func))?;
                 rawset_field(state, -2, &k)?;
             }
             match index_type {
@@ -2647,7 +5522,6 @@ impl Lua {
             ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
         })?;
 
-        let type_id = TypeId::of::<T>();
         (*self.extra.get()).registered_userdata.insert(type_id, id);
         (*self.extra.get())
             .registered_userdata_mt
@@ -2785,6 +5659,65 @@ impl Lua {
         }
     }
 
+    // Creates a table of functions sharing the same callback.
+    // The callback receives the function index (in `names`) as the first argument.
+    pub(crate) fn create_callback_family<'lua>(
+        &'lua self,
+        func: Callback<'lua, 'static>,
+        names: &[&str],
+    ) -> Result<Table<'lua>> {
+        unsafe extern "C" fn call_family_callback(state: *mut ffi::lua_State) -> c_int {
+            let upvalue = get_userdata::<CallbackUpvalue>(state, ffi::lua_upvalueindex(1));
+            let extra = (*upvalue).extra.get();
+            callback_error_ext(state, extra, |nargs| {
+                // Lua ensures that `LUA_MINSTACK` stack spaces are available (after pushing arguments)
+                let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+                let _guard = StateGuard::new(&lua.0, state);
+
+                let mut args = MultiValue::new_or_pooled(lua);
+                args.reserve(nargs as usize + 1);
+                for _ in 0..nargs {
+                    args.push_front(lua.pop_value());
+                }
+                let index = ffi::lua_tointeger(state, ffi::lua_upvalueindex(2));
+                args.push_front(Value::Integer(index));
+
+                let func = &*(*upvalue).data;
+                let mut results = func(lua, args)?;
+                let nresults = results.len() as c_int;
+
+                check_stack(state, nresults)?;
+                for r in results.drain_all() {
+                    lua.push_value(r)?;
+                }
+                MultiValue::return_to_pool(results, lua);
+
+                Ok(nresults)
+            })
+        }
+
+        let table = self.create_table_with_capacity(0, names.len() as c_int)?;
+        let state = self.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            let func = mem::transmute(func);
+            let extra = Arc::clone(&self.extra);
+            let protect = !self.unlikely_memory_error();
+            push_gc_userdata(state, CallbackUpvalue { data: func, extra }, protect)?;
+            for (index, name) in names.iter().enumerate() {
+                ffi::lua_pushvalue(state, -1);
+                ffi::lua_pushinteger(state, index as Integer);
+                protect_lua!(state, 2, 1, fn(state) {
+                    ffi::lua_pushcclosure(state, call_family_callback, 2);
+                })?;
+                table.raw_set(*name, Function(self.pop_ref()))?;
+            }
+        }
+        Ok(table)
+    }
+
     #[cfg(feature = "async")]
     pub(crate) fn create_async_callback<'lua>(
         &'lua self,
@@ -2843,6 +5776,15 @@ impl Lua {
                 let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
                 let _guard = StateGuard::new(&lua.0, state);
 
+                if let Some(left) = &mut (*extra).poll_budget_left {
+                    if *left == 0 {
+                        // Budget is exhausted, give control back to the executor
+                        lua.waker().wake_by_ref();
+                        return Ok(0);
+                    }
+                    *left -= 1;
+                }
+
                 let fut = &mut (*upvalue).data;
                 let mut ctx = Context::from_waker(lua.waker());
                 match fut.as_mut().poll(&mut ctx) {
@@ -2952,6 +5894,68 @@ impl Lua {
         mem::replace(&mut (*self.extra.get()).waker, waker)
     }
 
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) fn async_poll_budget(&self) -> Option<usize> {
+        unsafe { (*self.extra.get()).async_poll_budget }
+    }
+
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) unsafe fn set_poll_budget_left(&self, budget: Option<usize>) -> Option<usize> {
+        mem::replace(&mut (*self.extra.get()).poll_budget_left, budget)
+    }
+
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) fn next_task_id(&self) -> TaskId {
+        unsafe { (*self.extra.get()).async_tasks.next_id() }
+    }
+
+    // Wakes `waker` once the userdata at `ud` is released by a method or a `UserDataRef(Mut)`
+    #[cfg(feature = "async")]
+    pub(crate) fn add_borrow_waiter(&self, ud: *const c_void, waker: &Waker) {
+        let waiters = unsafe { &mut (*self.extra.get()).borrow_waiters };
+        let waiters = waiters.entry(ud).or_default();
+        if !waiters.iter().any(|w| w.will_wake(waker)) {
+            waiters.push(waker.clone());
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn notify_borrow_released(&self, ud: *const c_void) {
+        let waiters = unsafe { &mut (*self.extra.get()).borrow_waiters };
+        if waiters.is_empty() {
+            return;
+        }
+        for waker in waiters.remove(&ud).into_iter().flatten() {
+            waker.wake();
+        }
+    }
+
+    // Returns true if the userdata at `ud` is borrowed by an async method that is being polled,
+    // so whatever is polled now runs on its behalf and can never see it released.
+    #[cfg(feature = "async")]
+    pub(crate) fn is_borrow_polled(&self, ud: *const c_void) -> bool {
+        unsafe { (*self.extra.get()).polled_borrows.contains(&ud) }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn push_polled_borrow(&self, ud: *const c_void) {
+        unsafe { (*self.extra.get()).polled_borrows.push(ud) };
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn pop_polled_borrow(&self) {
+        unsafe { (*self.extra.get()).polled_borrows.pop() };
+    }
+
+    #[cfg(feature = "async")]
+    #[inline]
+    pub(crate) unsafe fn set_current_task(&self, task: Option<TaskId>) -> Option<TaskId> {
+        mem::replace(&mut (*self.extra.get()).current_task, task)
+    }
+
     pub(crate) unsafe fn make_userdata<T>(&self, data: UserDataCell<T>) -> Result<AnyUserData>
     where
         T: UserData + 'static,
@@ -2989,7 +5993,7 @@ impl Lua {
         })
     }
 
-    unsafe fn make_userdata_with_metatable<T>(
+    unsafe fn make_userdata_with_metatable<T: 'static>(
         &self,
         data: UserDataCell<T>,
         get_metatable_id: impl FnOnce() -> Result<Integer>,
@@ -3001,6 +6005,15 @@ impl Lua {
         // We push metatable first to ensure having correct metatable with `__gc` method
         ffi::lua_pushnil(state);
         ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, get_metatable_id()?);
+
+        // The wrapped value is boxed, so the lookup key can be calculated before moving it
+        let lookup = match (*self.extra.get()).userdata_lookup.get(&TypeId::of::<T>()) {
+            Some((table_id, key)) => match key.downcast_ref::<fn(&T) -> usize>() {
+                Some(key) => Some((*table_id, key(&*data.try_borrow()?))),
+                None => None,
+            },
+            None => None,
+        };
         let protect = !self.unlikely_memory_error();
         #[cfg(not(feature = "lua54"))]
         push_userdata(state, data, protect)?;
@@ -3021,6 +6034,25 @@ impl Lua {
             ffi::lua_setuservalue(state, -2);
         }
 
+        // Add the instance to the weak table if the type is tracked
+        if let Some(&table_id) = (*self.extra.get()).tracked_userdata.get(&TypeId::of::<T>()) {
+            userdata_impl::track_instance(state, table_id, protect)?;
+        }
+
+        // Add the instance to the reverse lookup table
+        if let Some((table_id, key)) = lookup {
+            check_stack(state, 5)?;
+            ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, table_id as Integer);
+            ffi::lua_pushinteger(state, key as Integer);
+            ffi::lua_pushvalue(state, -3);
+            if protect {
+                protect_lua!(state, 3, 0, fn(state) ffi::lua_rawset(state, -3))?;
+            } else {
+                ffi::lua_rawset(state, -3);
+                ffi::lua_pop(state, 1);
+            }
+        }
+
         Ok(AnyUserData(self.pop_ref()))
     }
 
@@ -3051,6 +6083,67 @@ impl Lua {
         Ok(())
     }
 
+    // Replaces the searcher of Lua modules with one loading them using `Lua::load`, so they are
+    // preprocessed. The original searcher is restored when `enable` is false.
+    #[cfg(not(feature = "luau"))]
+    fn set_preprocessing_searcher(&self, enable: bool) -> Result<()> {
+        const ORIGINAL_SEARCHER_KEY: &str = "__mlua_original_lua_searcher";
+
+        let package = match self.globals().raw_get::<_, Option<Table>>("package")? {
+            Some(package) => package,
+            None => return Ok(()),
+        };
+        #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+        let searchers: Table = package.raw_get("searchers")?;
+        #[cfg(any(feature = "lua51", feature = "luajit"))]
+        let searchers: Table = package.raw_get("loaders")?;
+
+        let original = self.named_registry_value::<Option<Function>>(ORIGINAL_SEARCHER_KEY)?;
+        match (enable, original) {
+            (true, None) => {
+                let searcher = self.create_function(|lua, name: std::string::String| {
+                    let package: Table = lua.globals().raw_get("package")?;
+                    let path: std::string::String = package.raw_get("path")?;
+                    let file_name = name.replace('.', std::path::MAIN_SEPARATOR_STR);
+                    let mut not_found = Vec::new();
+                    for template in path.split(';').filter(|t| !t.is_empty()) {
+                        let path = template.replace('?', &file_name);
+                        let source = match std::fs::read(&path) {
+                            Ok(source) => source,
+                            Err(_) => {
+                                not_found.push(format!("no file '{path}'"));
+                                continue;
+                            }
+                        };
+                        let loader = (lua.load(source).set_name(format!("@{path}")))
+                            .into_function()
+                            .map_err(|err| {
+                                Error::RuntimeError(format!(
+                                    "error loading module '{name}' from file '{path}':\n\t{err}"
+                                ))
+                            })?;
+                        return (loader, path).into_lua_multi(lua);
+                    }
+                    #[cfg(feature = "lua54")]
+                    let message = not_found.join("\n\t");
+                    #[cfg(not(feature = "lua54"))]
+                    let message: std::string::String =
+                        not_found.iter().map(|s| format!("\n\t{s}")).collect();
+                    message.into_lua_multi(lua)
+                })?;
+                let original: Function = searchers.raw_get(2)?;
+                self.set_named_registry_value(ORIGINAL_SEARCHER_KEY, original)?;
+                searchers.raw_set(2, searcher)
+            }
+            (false, Some(original)) => {
+                searchers.raw_set(2, original)?;
+                self.unset_named_registry_value(ORIGINAL_SEARCHER_KEY)
+            }
+            // The searcher is already set
+            _ => Ok(()),
+        }
+    }
+
     pub(crate) unsafe fn try_from_ptr(state: *mut ffi::lua_State) -> Option<Self> {
         let extra = extra_data(state);
         if extra.is_null() {
@@ -3092,6 +6185,84 @@ impl LuaInner {
         unsafe { (*self.extra.get()).ref_thread }
     }
 
+    pub(crate) fn preprocessor(&self) -> Option<Preprocessor> {
+        unsafe { (*self.extra.get()).preprocessor.clone() }
+    }
+
+    // Keeps the transformed source of the chunk that created the function, or forgets the source
+    // of a previously loaded chunk with the same name if it was not preprocessed
+    pub(crate) fn set_preprocessed_source(
+        &self,
+        func: &Function,
+        source: Option<std::string::String>,
+    ) {
+        let sources = unsafe { &mut (*self.extra.get()).preprocessed_sources };
+        match source {
+            Some(source) => {
+                let info = func.info();
+                let short_src = info.short_src.unwrap_or_default();
+                sources.insert(info.source.unwrap_or_default(), short_src, source);
+            }
+            None if !sources.is_empty() => {
+                sources.remove(func.info().source.as_deref().unwrap_or_default())
+            }
+            None => {}
+        }
+    }
+
+    // Attaches a source map to the chunk that created the function
+    pub(crate) fn set_source_map(&self, func: &Function, map: SourceMap) {
+        let info = func.info();
+        let source_maps = unsafe { &mut (*self.extra.get()).source_maps };
+        let short_src = info.short_src.unwrap_or_default();
+        source_maps.insert(info.source.unwrap_or_default(), short_src, Arc::new(map));
+    }
+
+    pub(crate) fn resolve_source(&self, source: &str) -> Option<PathBuf> {
+        let resolver = unsafe { (*self.extra.get()).source_resolver.as_ref()? };
+        resolver(source)
+    }
+
+    pub(crate) fn source_map(&self, source: &str) -> Option<Arc<SourceMap>> {
+        unsafe { (*self.extra.get()).source_maps.get(source).cloned() }
+    }
+
+    pub(crate) fn has_coercions<T: 'static>(&self) -> bool {
+        let coercions = unsafe { &(*self.extra.get()).coercions };
+        matches!(coercions.get(&TypeId::of::<T>()), Some(c) if !c.is_empty())
+    }
+
+    // Increments the conversion depth until the returned guard is dropped
+    pub(crate) fn enter_conversion(&self) -> Result<ConversionGuard<'_>> {
+        let extra = unsafe { &mut *self.extra.get() };
+        if let Some(max_depth) = extra.max_conversion_depth {
+            if extra.conversion_depth >= max_depth {
+                return Err(Error::ConversionDepthExceeded(max_depth));
+            }
+        }
+        extra.conversion_depth += 1;
+        Ok(ConversionGuard(self, extra.conversion_depth))
+    }
+
+    // Increments the nesting depth of tables converted into Rust values until the returned guard
+    // is dropped
+    pub(crate) fn enter_from_conversion(&self) -> Result<FromConversionGuard<'_>> {
+        let extra = unsafe { &mut *self.extra.get() };
+        if let Some(max_depth) = extra.conversion_limits.max_depth {
+            if extra.from_conversion_depth >= max_depth {
+                return Err(Error::ConversionDepthExceeded(max_depth));
+            }
+        }
+        extra.from_conversion_depth += 1;
+        Ok(FromConversionGuard(self))
+    }
+
+    #[inline]
+    pub(crate) fn borrow_policy<T: 'static>(&self) -> BorrowPolicy {
+        let borrow_policies = unsafe { &(*self.extra.get()).borrow_policies };
+        (borrow_policies.get(&TypeId::of::<T>()).copied()).unwrap_or_default()
+    }
+
     #[inline]
     pub(crate) fn new_multivalue_from_pool(&self) -> MultiValue {
         let extra = unsafe { &mut *self.extra.get() };
@@ -3111,10 +6282,30 @@ impl LuaInner {
 }
 
 impl ExtraData {
-    #[cfg(feature = "luau")]
+    // Returns the time of the clock set by `Lua::set_clock`, or the time since the state creation.
+    // With the `embedded` feature the system clock is not used, so it's `None` if no clock is set.
+    pub(crate) fn clock_now(&self) -> Option<Duration> {
+        if let Some(clock) = &self.clock {
+            return Some(clock());
+        }
+        #[cfg(not(feature = "embedded"))]
+        return Some(self.created_at.elapsed());
+        #[cfg(feature = "embedded")]
+        None
+    }
+
+    // Returns `None` if the Rust allocator is not installed
+    #[inline]
+    pub(crate) fn mem_state(&self) -> Option<NonNull<MemoryState>> {
+        self.mem_state
+    }
+
+    #[cfg(not(feature = "luau"))]
     #[inline]
-    pub(crate) fn mem_state(&self) -> NonNull<MemoryState> {
-        self.mem_state.unwrap()
+    pub(crate) fn thread_limits_mut(
+        &mut self,
+    ) -> &mut FxHashMap<*mut ffi::lua_State, ThreadLimits> {
+        &mut self.thread_limits
     }
 }
 
@@ -3133,13 +6324,164 @@ impl<'a> Drop for StateGuard<'a> {
     }
 }
 
+pub(crate) struct ConversionGuard<'a>(&'a LuaInner, usize);
+
+impl<'a> ConversionGuard<'a> {
+    // Returns `true` if the table at this level should be filled later from the work queue
+    pub(crate) fn is_deferred(&self) -> bool {
+        let base = unsafe { (*self.0.extra.get()).conversion_base };
+        self.1 >= base + INLINE_CONVERSION_DEPTH
+    }
+
+    // Queues filling of the table at this level.
+    // The outermost conversion runs the queue before returning, so the captured values are alive.
+    pub(crate) fn defer(&self, f: impl FnOnce() -> Result<()>) {
+        let conversion = unsafe { DeferredConversion::new(self.1, f) };
+        unsafe { (*self.0.extra.get()).conversion_queue.push(conversion) };
+    }
+
+    // Runs the queued conversions if this is the outermost level
+    pub(crate) fn finish(&self) -> Result<()> {
+        if self.1 != 1 {
+            return Ok(());
+        }
+        let extra = self.0.extra.get();
+        while let Some(conversion) = unsafe { (*extra).conversion_queue.pop() } {
+            unsafe { (*extra).conversion_depth = conversion.depth };
+            unsafe { (*extra).conversion_base = conversion.depth };
+            let res = conversion.run();
+            unsafe { (*extra).conversion_depth = 1 };
+            unsafe { (*extra).conversion_base = 0 };
+            res?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for ConversionGuard<'a> {
+    fn drop(&mut self) {
+        let extra = self.0.extra.get();
+        unsafe { (*extra).conversion_depth = self.1 - 1 };
+        if self.1 == 1 {
+            // Discard conversions left after an error
+            unsafe { (*extra).conversion_base = 0 };
+            drop(unsafe { mem::take(&mut (*extra).conversion_queue) });
+        }
+    }
+}
+
+pub(crate) struct FromConversionGuard<'a>(&'a LuaInner);
+
+impl<'a> Drop for FromConversionGuard<'a> {
+    fn drop(&mut self) {
+        unsafe { (*self.0.extra.get()).from_conversion_depth -= 1 };
+    }
+}
+
+#[cfg(feature = "luau")]
+unsafe extern "C" fn interrupt_proc(state: *mut ffi::lua_State, gc: c_int) {
+    if gc >= 0 {
+        // We don't support GC interrupts since they cannot survive Lua exceptions
+        return;
+    }
+    let extra = extra_data(state);
+    if (*extra).instruction_counters > 0 {
+        (*extra).instruction_count += 1;
+    }
+    if let Some((limit, executed)) = (*extra).instruction_limit.as_mut() {
+        // Keep raising the error, so the script cannot catch it and continue
+        *executed += 1;
+        if *executed > *limit {
+            let limit = *limit;
+            return callback_error_ext(state, extra, move |_| {
+                Err(Error::InstructionLimitExceeded(limit))
+            });
+        }
+    }
+    if (*extra).interrupt_callback.is_none() {
+        return;
+    }
+    let result = callback_error_ext(state, extra, move |_| {
+        let interrupt_cb = (*extra).interrupt_callback.clone();
+        let interrupt_cb =
+            mlua_expect!(interrupt_cb, "no interrupt callback set in interrupt_proc");
+        if Arc::strong_count(&interrupt_cb) > 2 {
+            return Ok(VmState::Continue); // Don't allow recursion
+        }
+        let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+        let _guard = StateGuard::new(&lua.0, state);
+        interrupt_cb(lua)
+    });
+    match result {
+        VmState::Continue => {}
+        VmState::Yield => {
+            ffi::lua_yield(state, 0);
+        }
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+unsafe extern "C" fn hook_proc(state: *mut ffi::lua_State, ar: *mut ffi::lua_Debug) {
+    let extra = extra_data(state);
+    let limited = !(*extra).thread_limits.is_empty()
+        && check_thread_limits(state, &mut (*extra).thread_limits, ar);
+    if !(*extra).hook_all_threads && (*extra).hook_thread != state {
+        // Hook was destined for a different thread, keep only the thread limits (if any)
+        if limited {
+            ffi::lua_sethook(state, Some(hook_proc), THREAD_LIMITS_MASK, 0);
+        } else {
+            ffi::lua_sethook(state, None, 0, 0);
+        }
+        return;
+    }
+    let triggers = (*extra).hook_triggers;
+    if (*extra).hook_all_threads {
+        // The hook could be set for the thread before, or for the limits only
+        let mask = triggers.mask() | if limited { THREAD_LIMITS_MASK } else { 0 };
+        if ffi::lua_gethookmask(state) != mask {
+            ffi::lua_sethook(state, Some(hook_proc), mask, triggers.count());
+        }
+    }
+    if (is_call_event((*ar).event) && !triggers.on_calls)
+        || (is_return_event((*ar).event) && !triggers.on_returns)
+    {
+        // Call and return events are requested to check the thread limits
+        return;
+    }
+    if !(*extra)
+        .hook_sampler
+        .sample(&triggers, (*ar).event, || (*extra).clock_now())
+    {
+        return;
+    }
+    callback_error_ext(state, extra, move |_| {
+        let hook_cb = (*extra).hook_callback.clone();
+        let hook_cb = mlua_expect!(hook_cb, "no hook callback set in hook_proc");
+        if Arc::strong_count(&hook_cb) > 2 {
+            return Ok(()); // Don't allow recursion
+        }
+        let lua: &Lua = mem::transmute((*extra).inner.assume_init_ref());
+        let _guard = StateGuard::new(&lua.0, state);
+        let debug = Debug::new(lua, ar);
+        hook_cb(lua, debug)
+    })
+}
+
+#[cfg(not(feature = "luau"))]
+fn limits_mask(extra: &ExtraData) -> c_int {
+    match extra.thread_limits.is_empty() {
+        false => THREAD_LIMITS_MASK,
+        true => 0,
+    }
+}
+
 #[cfg(feature = "luau")]
-unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
+pub(crate) unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
     (*ffi::lua_callbacks(state)).userdata as *mut ExtraData
 }
 
 #[cfg(not(feature = "luau"))]
-unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
+pub(crate) unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
     let extra_key = &EXTRA_REGISTRY_KEY as *const u8 as *const c_void;
     if ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, extra_key) != ffi::LUA_TUSERDATA {
         // `ExtraData` can be null only when Lua state is foreign.
@@ -3152,6 +6494,34 @@ unsafe fn extra_data(state: *mut ffi::lua_State) -> *mut ExtraData {
     (*extra_ptr).get()
 }
 
+// Applies source maps and traceback options (if set) to the traceback string on top of the stack
+pub(crate) unsafe fn apply_traceback_options(state: *mut ffi::lua_State) {
+    let extra = extra_data(state);
+    if extra.is_null() {
+        return;
+    }
+    let options = (*extra).traceback_options;
+    #[cfg(feature = "async")]
+    let task = (*extra).current_task;
+    #[cfg(not(feature = "async"))]
+    let task: Option<std::convert::Infallible> = None;
+    if options.is_none() && (*extra).source_maps.is_empty() && task.is_none() {
+        return;
+    }
+    source_map::rewrite_traceback(state, |mut traceback| {
+        if !(*extra).source_maps.is_empty() {
+            traceback = apply_source_maps(&(*extra).source_maps, &traceback);
+        }
+        if let Some(options) = options {
+            traceback = options.apply(&traceback);
+        }
+        if let Some(task) = task {
+            traceback.push_str(&format!("\n[{task}]"));
+        }
+        traceback
+    })
+}
+
 // Creates required entries in the metatable cache (see `util::METATABLE_CACHE`)
 pub(crate) fn init_metatable_cache(cache: &mut FxHashMap<TypeId, u8>) {
     cache.insert(TypeId::of::<Arc<UnsafeCell<ExtraData>>>(), 0);
@@ -3268,6 +6638,7 @@ where
             // Build `CallbackError` with traceback
             let traceback = if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
                 ffi::luaL_traceback(state, state, ptr::null(), 0);
+                apply_traceback_options(state);
                 let traceback = util::to_string(state, -1);
                 ffi::lua_pop(state, 1);
                 traceback
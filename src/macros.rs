@@ -95,6 +95,84 @@ macro_rules! require_module_feature {
     };
 }
 
+/// Converts the arguments of a callback into local variables, in order.
+///
+/// `lua_args!(lua, args => a: i64, b: &str, opts: Option<Table>)` pops the values from the
+/// `args` [`MultiValue`] one by one and converts each of them using [`FromLua`], without the
+/// intermediate tuple of the generic [`FromLuaMulti`] path. Missing arguments are `nil`, extra
+/// arguments are ignored. A `&str` argument borrows a Lua string.
+///
+/// A failed conversion returns (using `?`) an [`Error::BadArgument`] with the position and name
+/// of the argument, so the macro must be used in a function returning [`Result`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{lua_args, Lua, MultiValue, Result, Table};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let f = lua.create_function(|lua, args: MultiValue| {
+///     lua_args!(lua, args => n: i64, sep: &str, opts: Option<Table>);
+///     let count = match opts {
+///         Some(opts) => opts.get::<_, Option<usize>>("count")?.unwrap_or(1),
+///         None => 1,
+///     };
+///     Ok(vec![n.to_string(); count].join(sep))
+/// })?;
+/// assert_eq!(f.call::<_, String>((3, ",", lua.create_table_from([("count", 2)])?))?, "3,3");
+///
+/// let err = f.call::<_, String>((3, true)).unwrap_err();
+/// assert!(err.to_string().contains("bad argument `sep`"));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`MultiValue`]: crate::MultiValue
+/// [`FromLua`]: crate::FromLua
+/// [`FromLuaMulti`]: crate::FromLuaMulti
+/// [`Error::BadArgument`]: crate::Error::BadArgument
+/// [`Result`]: crate::Result
+#[macro_export]
+macro_rules! lua_args {
+    ($lua:expr, $args:expr => $($rest:tt)*) => {
+        let lua: &$crate::Lua = $lua;
+        let mut args: $crate::MultiValue = $args;
+        $crate::lua_args!(@arg lua, args, 1usize; $($rest)*);
+    };
+
+    (@arg $lua:ident, $args:ident, $pos:expr; $(,)?) => {
+        let _ = (&$lua, &$args);
+    };
+
+    (@arg $lua:ident, $args:ident, $pos:expr; $name:ident : &str $(, $($rest:tt)*)?) => {
+        let $name = $crate::lua_args!(@convert $lua, $args, $pos, $name, $crate::String);
+        let $name: &str = $name
+            .to_str()
+            .map_err(|err| $crate::lua_args!(@error $pos, $name, err))?;
+        $crate::lua_args!(@arg $lua, $args, $pos + 1; $($($rest)*)?);
+    };
+
+    (@arg $lua:ident, $args:ident, $pos:expr; $name:ident : $ty:ty $(, $($rest:tt)*)?) => {
+        let $name = $crate::lua_args!(@convert $lua, $args, $pos, $name, $ty);
+        $crate::lua_args!(@arg $lua, $args, $pos + 1; $($($rest)*)?);
+    };
+
+    (@convert $lua:ident, $args:ident, $pos:expr, $name:ident, $ty:ty) => {{
+        let value = $args.pop_front().unwrap_or($crate::Value::Nil);
+        <$ty as $crate::FromLua>::from_lua(value, $lua)
+            .map_err(|err| $crate::lua_args!(@error $pos, $name, err))?
+    }};
+
+    (@error $pos:expr, $name:ident, $err:expr) => {
+        $crate::Error::BadArgument {
+            to: None,
+            pos: $pos,
+            name: Some(stringify!($name).to_string()),
+            cause: ::std::sync::Arc::new($err),
+        }
+    };
+}
+
 macro_rules! protect_lua {
     ($state:expr, $nargs:expr, $nresults:expr, $f:expr) => {
         crate::util::protect_lua_closure($state, $nargs, $nresults, $f)
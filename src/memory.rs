@@ -1,7 +1,8 @@
 use std::alloc::{self, Layout};
 use std::os::raw::c_void;
-use std::ptr;
+use std::{mem, ptr};
 
+use crate::lua::extra_data;
 #[cfg(feature = "luau")]
 use crate::lua::ExtraData;
 
@@ -17,6 +18,9 @@ pub(crate) struct MemoryState {
     // Indicates that the memory limit was reached on the last allocation.
     #[cfg(feature = "luau")]
     limit_reached: bool,
+    // Indicates that an allocation was refused because of the memory limit.
+    // Unlike `limit_reached`, it stays set until the error is reported.
+    limit_exceeded: bool,
 }
 
 impl MemoryState {
@@ -37,6 +41,22 @@ impl MemoryState {
         prev_limit as usize
     }
 
+    // Accounts memory allocated outside of Lua on behalf of Lua values (eg. string builders).
+    // Returns `false` if it would pass the memory limit.
+    pub(crate) fn try_charge(&mut self, size: usize) -> bool {
+        let new_used_memory = self.used_memory.saturating_add(size as isize);
+        if self.memory_limit > 0 && new_used_memory > self.memory_limit {
+            return false;
+        }
+        self.used_memory = new_used_memory;
+        true
+    }
+
+    // Releases memory accounted using `try_charge`
+    pub(crate) fn release(&mut self, size: usize) {
+        self.used_memory -= size as isize;
+    }
+
     // This function is used primarily for calling `lua_pushcfunction` in lua5.1/jit
     // to bypass the memory limit (if set).
     #[cfg(any(feature = "lua51", feature = "luajit"))]
@@ -61,7 +81,7 @@ impl MemoryState {
         if extra.is_null() {
             return f();
         }
-        let mem_state = (*extra).mem_state();
+        let mem_state = (*extra).mem_state().unwrap();
         (*mem_state.as_ptr()).ignore_limit = true;
         f();
         (*mem_state.as_ptr()).ignore_limit = false;
@@ -74,6 +94,21 @@ impl MemoryState {
         f();
     }
 
+    // Returns the memory limit if an allocation was refused because of it, and resets the flag.
+    // Used to tell apart the memory limit errors from other `LUA_ERRMEM` errors.
+    pub(crate) unsafe fn take_limit_exceeded(state: *mut ffi::lua_State) -> Option<usize> {
+        // Look up the state through `ExtraData` to not compare allocator function pointers
+        let extra = extra_data(state);
+        let mem_state = match extra.is_null() {
+            false => &mut *(*extra).mem_state()?.as_ptr(),
+            true => return None,
+        };
+        if mem::take(&mut mem_state.limit_exceeded) {
+            return Some(mem_state.memory_limit());
+        }
+        None
+    }
+
     // Returns `true` if the memory limit was reached on the last memory operation
     #[cfg(feature = "luau")]
     pub(crate) unsafe fn limit_reached(state: *mut ffi::lua_State) -> bool {
@@ -81,7 +116,7 @@ impl MemoryState {
         if extra.is_null() {
             return false;
         }
-        (*(*extra).mem_state().as_ptr()).limit_reached
+        (*(*extra).mem_state().unwrap().as_ptr()).limit_reached
     }
 }
 
@@ -125,6 +160,7 @@ unsafe extern "C" fn allocator(
         {
             mem_state.limit_reached = true;
         }
+        mem_state.limit_exceeded = true;
         return ptr::null_mut();
     }
     mem_state.used_memory += mem_diff;
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::string::String as StdString;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::types::MaybeSend;
+use crate::value::IntoLua;
+
+/// A Rust value shared between multiple Lua states.
+///
+/// The value is registered once and then attached to any number of [`Lua`] instances, either as
+/// a global variable ([`ValueMirror::attach`]) or as an on-change callback
+/// ([`ValueMirror::attach_with`]).
+///
+/// Calling [`ValueMirror::update`] does not touch any Lua state. Instead, every attached state
+/// picks up the new value at its next safe point, which is the next call to
+/// [`Lua::sync_mirrors`] made by the thread owning that state. This makes it possible to share
+/// configuration across a state-per-core server design, where each state is driven by its own
+/// event loop.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, ValueMirror};
+/// # fn main() -> Result<()> {
+/// let mirror = ValueMirror::new(10);
+///
+/// let (lua1, lua2) = (Lua::new(), Lua::new());
+/// mirror.attach(&lua1, "max_players")?;
+/// mirror.attach(&lua2, "max_players")?;
+///
+/// mirror.update(20);
+/// assert_eq!(lua1.globals().get::<_, i32>("max_players")?, 10);
+///
+/// lua1.sync_mirrors()?;
+/// lua2.sync_mirrors()?;
+/// assert_eq!(lua1.globals().get::<_, i32>("max_players")?, 20);
+/// assert_eq!(lua2.globals().get::<_, i32>("max_players")?, 20);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ValueMirror<T> {
+    inner: Arc<MirrorInner<T>>,
+}
+
+struct MirrorInner<T> {
+    value: Mutex<Arc<T>>,
+    version: AtomicU64,
+}
+
+impl<T> MirrorInner<T> {
+    fn snapshot(&self) -> (u64, Arc<T>) {
+        let value = self.value.lock().unwrap_or_else(|err| err.into_inner());
+        (self.version.load(Ordering::Acquire), value.clone())
+    }
+}
+
+impl<T> ValueMirror<T>
+where
+    T: for<'lua> IntoLua<'lua> + Clone + Send + Sync + 'static,
+{
+    /// Creates a new mirror holding the given value.
+    pub fn new(value: T) -> Self {
+        ValueMirror {
+            inner: Arc::new(MirrorInner {
+                value: Mutex::new(Arc::new(value)),
+                version: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns a copy of the current value.
+    pub fn get(&self) -> T {
+        T::clone(&self.inner.snapshot().1)
+    }
+
+    /// Replaces the value.
+    ///
+    /// Attached Lua states receive the new value on their next call to [`Lua::sync_mirrors`].
+    pub fn update(&self, value: T) {
+        let mut current = self
+            .inner
+            .value
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        *current = Arc::new(value);
+        self.inner.version.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Attaches the mirror to a Lua state as a global variable `name`.
+    ///
+    /// The global is set immediately and then reassigned to a freshly converted copy of the value
+    /// every time the value changes.
+    pub fn attach(&self, lua: &Lua, name: &str) -> Result<()> {
+        let name = StdString::from(name);
+        self.attach_with(lua, move |lua, value| {
+            lua.globals().set(name.as_str(), value)
+        })
+    }
+
+    /// Attaches the mirror to a Lua state with an on-change callback.
+    ///
+    /// The callback is called immediately with the current value and then every time the value
+    /// changes, from [`Lua::sync_mirrors`].
+    pub fn attach_with<F>(&self, lua: &Lua, func: F) -> Result<()>
+    where
+        F: Fn(&Lua, T) -> Result<()> + MaybeSend + 'static,
+    {
+        let inner = self.inner.clone();
+        let (version, value) = inner.snapshot();
+        func(lua, T::clone(&value))?;
+
+        let mut last_version = version;
+        lua.add_mirror_sync(Box::new(move |lua| {
+            if inner.version.load(Ordering::Acquire) == last_version {
+                return Ok(());
+            }
+            let (version, value) = inner.snapshot();
+            func(lua, T::clone(&value))?;
+            last_version = version;
+            Ok(())
+        }));
+        Ok(())
+    }
+}
+
+impl<T> Clone for ValueMirror<T> {
+    fn clone(&self) -> Self {
+        ValueMirror {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ValueMirror<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (version, value) = self.inner.snapshot();
+        f.debug_struct("ValueMirror")
+            .field("value", &value)
+            .field("version", &version)
+            .finish()
+    }
+}
@@ -0,0 +1,522 @@
+//! Implementation of the `string.pack` / `string.unpack` binary format (Lua 5.4 semantics).
+//!
+//! The implementation is backend-independent, so the same format specification can be used
+//! on every supported Lua version, including the ones without native `string.pack`.
+
+use std::mem;
+use std::os::raw::{c_int, c_long, c_short};
+use std::sync::Arc;
+
+use crate::conversion::{CheckedConversion, ConversionPolicy};
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::string::String;
+#[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
+use crate::table::Table;
+use crate::types::{Integer, Number};
+use crate::value::{FromLua, IntoLua, MultiValue, Value};
+
+const NO_INTEGER_REP: &str = "number has no integer representation";
+
+// Maximum size for the binary representation of an integer
+const MAX_INT_SIZE: usize = 16;
+
+// Size of the integer used for packing and unpacking
+const SZINT: usize = mem::size_of::<i64>();
+
+// Maximum size of a packed result (`MAXSIZE` of `lstrlib.c`)
+const MAX_SIZE: usize = c_int::MAX as usize;
+
+// Maximum alignment used by the native `!` option
+const MAX_ALIGN: usize = {
+    let (a, b) = (mem::align_of::<f64>(), mem::align_of::<*const ()>());
+    if a > b {
+        a
+    } else {
+        b
+    }
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KOption {
+    Int,       // signed integers
+    Uint,      // unsigned integers
+    Float,     // single-precision floating-point numbers
+    Number,    // Lua "native" floating-point numbers
+    Double,    // double-precision floating-point numbers
+    Char,      // fixed-length strings
+    String,    // strings with prefixed length
+    Zstr,      // zero-terminated strings
+    Padding,   // padding
+    PaddAlign, // padding for alignment
+    Nop,       // no-op (configuration or spaces)
+}
+
+struct Header {
+    little: bool,
+    max_align: usize,
+}
+
+impl Header {
+    fn new() -> Self {
+        Header {
+            little: cfg!(target_endian = "little"),
+            max_align: 1,
+        }
+    }
+}
+
+fn format_error(fname: &'static str, msg: impl Into<std::string::String>) -> Error {
+    bad_argument(fname, 1, msg)
+}
+
+fn bad_argument(fname: &'static str, pos: usize, msg: impl Into<std::string::String>) -> Error {
+    Error::BadArgument {
+        to: Some(fname.to_string()),
+        pos,
+        name: None,
+        cause: Arc::new(Error::RuntimeError(msg.into())),
+    }
+}
+
+fn get_num(fmt: &mut &[u8], default: usize) -> usize {
+    match fmt.first() {
+        Some(c) if c.is_ascii_digit() => {
+            let mut n = 0usize;
+            while let Some(c @ b'0'..=b'9') = fmt.first() {
+                if n >= (usize::MAX / 2 - 9) / 10 {
+                    break;
+                }
+                n = n * 10 + (c - b'0') as usize;
+                *fmt = &fmt[1..];
+            }
+            n
+        }
+        _ => default,
+    }
+}
+
+fn get_num_limit(fname: &'static str, fmt: &mut &[u8], default: usize) -> Result<usize> {
+    let size = get_num(fmt, default);
+    if size > MAX_INT_SIZE || size == 0 {
+        return Err(format_error(
+            fname,
+            format!("integral size ({size}) out of limits [1,{MAX_INT_SIZE}]"),
+        ));
+    }
+    Ok(size)
+}
+
+// Reads an option from the format string, returning its kind and size
+fn get_option(fname: &'static str, h: &mut Header, fmt: &mut &[u8]) -> Result<(KOption, usize)> {
+    let opt = fmt[0];
+    *fmt = &fmt[1..];
+    let kind = match opt {
+        b'b' => (KOption::Int, 1),
+        b'B' => (KOption::Uint, 1),
+        b'h' => (KOption::Int, mem::size_of::<c_short>()),
+        b'H' => (KOption::Uint, mem::size_of::<c_short>()),
+        b'l' => (KOption::Int, mem::size_of::<c_long>()),
+        b'L' => (KOption::Uint, mem::size_of::<c_long>()),
+        b'j' => (KOption::Int, mem::size_of::<Integer>()),
+        b'J' => (KOption::Uint, mem::size_of::<Integer>()),
+        b'T' => (KOption::Uint, mem::size_of::<usize>()),
+        b'f' => (KOption::Float, mem::size_of::<f32>()),
+        b'n' => (KOption::Number, mem::size_of::<Number>()),
+        b'd' => (KOption::Double, mem::size_of::<f64>()),
+        b'i' => (
+            KOption::Int,
+            get_num_limit(fname, fmt, mem::size_of::<c_int>())?,
+        ),
+        b'I' => (
+            KOption::Uint,
+            get_num_limit(fname, fmt, mem::size_of::<c_int>())?,
+        ),
+        b's' => (
+            KOption::String,
+            get_num_limit(fname, fmt, mem::size_of::<usize>())?,
+        ),
+        b'c' => match get_num(fmt, usize::MAX) {
+            usize::MAX => {
+                return Err(format_error(fname, "missing size for format option 'c'"));
+            }
+            size => (KOption::Char, size),
+        },
+        b'z' => (KOption::Zstr, 0),
+        b'x' => (KOption::Padding, 1),
+        b'X' => (KOption::PaddAlign, 0),
+        b' ' => (KOption::Nop, 0),
+        b'<' => {
+            h.little = true;
+            (KOption::Nop, 0)
+        }
+        b'>' => {
+            h.little = false;
+            (KOption::Nop, 0)
+        }
+        b'=' => {
+            h.little = cfg!(target_endian = "little");
+            (KOption::Nop, 0)
+        }
+        b'!' => {
+            h.max_align = get_num_limit(fname, fmt, MAX_ALIGN)?;
+            (KOption::Nop, 0)
+        }
+        c => {
+            let msg = format!("invalid format option '{}'", c as char);
+            return Err(format_error(fname, msg));
+        }
+    };
+    Ok(kind)
+}
+
+// Reads an option and computes the number of padding bytes needed to align it
+fn get_details(
+    fname: &'static str,
+    h: &mut Header,
+    total_size: usize,
+    fmt: &mut &[u8],
+) -> Result<(KOption, usize, usize)> {
+    let (opt, size) = get_option(fname, h, fmt)?;
+    let mut align = size;
+    if opt == KOption::PaddAlign {
+        let invalid_next = || format_error(fname, "invalid next option for option 'X'");
+        if fmt.is_empty() {
+            return Err(invalid_next());
+        }
+        match get_option(fname, h, fmt)? {
+            (KOption::Char, _) | (_, 0) => return Err(invalid_next()),
+            (_, size) => align = size,
+        }
+    }
+    if align <= 1 || opt == KOption::Char {
+        return Ok((opt, size, 0));
+    }
+    if align > h.max_align {
+        align = h.max_align;
+    }
+    if !align.is_power_of_two() {
+        return Err(format_error(
+            fname,
+            "format asks for alignment not power of 2",
+        ));
+    }
+    Ok((
+        opt,
+        size,
+        (align - (total_size & (align - 1))) & (align - 1),
+    ))
+}
+
+// Reserves `n` more bytes in the result, failing if its size would exceed `MAX_SIZE`
+fn reserve(buf: &mut Vec<u8>, n: usize, too_large: impl FnOnce() -> Error) -> Result<()> {
+    if n > MAX_SIZE - buf.len() {
+        return Err(too_large());
+    }
+    (buf.try_reserve(n)).map_err(|_| Error::MemoryError("not enough memory".to_string()))
+}
+
+fn pack_int(buf: &mut Vec<u8>, n: u64, little: bool, size: usize, neg: bool) {
+    let start = buf.len();
+    let bytes = n.to_le_bytes();
+    for i in 0..size {
+        buf.push(match bytes.get(i) {
+            Some(&b) => b,
+            // Sign-extend the value
+            None if neg => 0xff,
+            None => 0,
+        });
+    }
+    if !little {
+        buf[start..].reverse();
+    }
+}
+
+fn unpack_int(
+    fname: &'static str,
+    data: &[u8],
+    little: bool,
+    size: usize,
+    signed: bool,
+) -> Result<i64> {
+    let byte = |i: usize| if little { data[i] } else { data[size - 1 - i] };
+    let limit = size.min(SZINT);
+    let mut res = 0u64;
+    for i in (0..limit).rev() {
+        res = (res << 8) | byte(i) as u64;
+    }
+    if size < SZINT {
+        if signed {
+            let mask = 1u64 << (size * 8 - 1);
+            res = (res ^ mask).wrapping_sub(mask);
+        }
+    } else if size > SZINT {
+        let mask = if !signed || (res as i64) >= 0 {
+            0
+        } else {
+            0xff
+        };
+        if (limit..size).any(|i| byte(i) != mask) {
+            let msg = format!("{size}-byte integer does not fit into Lua Integer");
+            return Err(bad_argument(fname, 2, msg));
+        }
+    }
+    Ok(res as i64)
+}
+
+// Converts an integer argument, rejecting numbers with a fractional part like Lua 5.4 does
+fn check_integer(fname: &'static str, value: Value, pos: usize) -> Result<i64> {
+    i64::checked_from_value(value, ConversionPolicy::Exact).map_err(|err| {
+        let message = err.conversion_context().and_then(|ctx| ctx.message);
+        if message == Some(NO_INTEGER_REP) {
+            return bad_argument(fname, pos, NO_INTEGER_REP);
+        }
+        Error::BadArgument {
+            to: Some(fname.to_string()),
+            pos,
+            name: None,
+            cause: Arc::new(err),
+        }
+    })
+}
+
+pub(crate) fn pack<'lua>(
+    lua: &'lua Lua,
+    mut fmt: &[u8],
+    args: MultiValue<'lua>,
+) -> Result<Vec<u8>> {
+    const FNAME: &str = "pack";
+
+    let mut h = Header::new();
+    let mut args = args.into_iter();
+    let mut buf = Vec::new();
+    let mut arg_pos = 1;
+
+    let mut next_arg = |arg_pos: &mut usize| {
+        *arg_pos += 1;
+        args.next().unwrap_or(Value::Nil)
+    };
+    let arg_error = |arg_pos, err| Error::BadArgument {
+        to: Some(FNAME.to_string()),
+        pos: arg_pos,
+        name: None,
+        cause: Arc::new(err),
+    };
+
+    while !fmt.is_empty() {
+        let (opt, size, ntoalign) = get_details(FNAME, &mut h, buf.len(), &mut fmt)?;
+        // Sizes in the format are checked before growing the result, eg. for `c2000000000`
+        reserve(&mut buf, ntoalign + size, || {
+            format_error(FNAME, "result too large")
+        })?;
+        buf.resize(buf.len() + ntoalign, 0);
+        match opt {
+            KOption::Int => {
+                let value = next_arg(&mut arg_pos);
+                let n = check_integer(FNAME, value, arg_pos)?;
+                if size < SZINT {
+                    let lim = 1i64 << (size * 8 - 1);
+                    if !(-lim <= n && n < lim) {
+                        return Err(bad_argument(FNAME, arg_pos, "integer overflow"));
+                    }
+                }
+                pack_int(&mut buf, n as u64, h.little, size, n < 0);
+            }
+            KOption::Uint => {
+                let value = next_arg(&mut arg_pos);
+                let n = check_integer(FNAME, value, arg_pos)?;
+                if size < SZINT && (n as u64) >= 1u64 << (size * 8) {
+                    return Err(bad_argument(FNAME, arg_pos, "unsigned overflow"));
+                }
+                pack_int(&mut buf, n as u64, h.little, size, false);
+            }
+            KOption::Float => {
+                let value = next_arg(&mut arg_pos);
+                let n = f32::from_lua(value, lua).map_err(|err| arg_error(arg_pos, err))?;
+                buf.extend(if h.little {
+                    n.to_le_bytes()
+                } else {
+                    n.to_be_bytes()
+                });
+            }
+            KOption::Number | KOption::Double => {
+                let value = next_arg(&mut arg_pos);
+                let n = f64::from_lua(value, lua).map_err(|err| arg_error(arg_pos, err))?;
+                buf.extend(if h.little {
+                    n.to_le_bytes()
+                } else {
+                    n.to_be_bytes()
+                });
+            }
+            KOption::Char => {
+                let value = next_arg(&mut arg_pos);
+                let s = String::from_lua(value, lua).map_err(|err| arg_error(arg_pos, err))?;
+                let s = s.as_bytes();
+                if s.len() > size {
+                    return Err(bad_argument(
+                        FNAME,
+                        arg_pos,
+                        "string longer than given size",
+                    ));
+                }
+                buf.extend_from_slice(s);
+                buf.resize(buf.len() + (size - s.len()), 0);
+            }
+            KOption::String => {
+                let value = next_arg(&mut arg_pos);
+                let s = String::from_lua(value, lua).map_err(|err| arg_error(arg_pos, err))?;
+                let s = s.as_bytes();
+                if size < SZINT && (s.len() as u64) >= 1u64 << (size * 8) {
+                    return Err(bad_argument(
+                        FNAME,
+                        arg_pos,
+                        "string length does not fit in given size",
+                    ));
+                }
+                reserve(&mut buf, size + s.len(), || {
+                    bad_argument(FNAME, arg_pos, "result too large")
+                })?;
+                pack_int(&mut buf, s.len() as u64, h.little, size, false);
+                buf.extend_from_slice(s);
+            }
+            KOption::Zstr => {
+                let value = next_arg(&mut arg_pos);
+                let s = String::from_lua(value, lua).map_err(|err| arg_error(arg_pos, err))?;
+                let s = s.as_bytes();
+                if s.contains(&0) {
+                    return Err(bad_argument(FNAME, arg_pos, "string contains zeros"));
+                }
+                reserve(&mut buf, s.len() + 1, || {
+                    bad_argument(FNAME, arg_pos, "result too large")
+                })?;
+                buf.extend_from_slice(s);
+                buf.push(0);
+            }
+            KOption::Padding => buf.push(0),
+            KOption::PaddAlign | KOption::Nop => {}
+        }
+    }
+
+    Ok(buf)
+}
+
+pub(crate) fn unpack<'lua>(lua: &'lua Lua, fmt: &[u8], data: &[u8]) -> Result<MultiValue<'lua>> {
+    let (results, _) = unpack_from(lua, fmt, data, 0)?;
+    Ok(MultiValue::from_vec(results))
+}
+
+// Unpacks values starting at byte `pos`, returning them with the position after the last one
+fn unpack_from<'lua>(
+    lua: &'lua Lua,
+    mut fmt: &[u8],
+    data: &[u8],
+    mut pos: usize,
+) -> Result<(Vec<Value<'lua>>, usize)> {
+    const FNAME: &str = "unpack";
+
+    let mut h = Header::new();
+    let mut results = Vec::new();
+
+    let too_short = || bad_argument(FNAME, 2, "data string too short");
+
+    while !fmt.is_empty() {
+        let (opt, size, ntoalign) = get_details(FNAME, &mut h, pos, &mut fmt)?;
+        if ntoalign + size > data.len() - pos {
+            return Err(too_short());
+        }
+        pos += ntoalign;
+        match opt {
+            KOption::Int | KOption::Uint => {
+                let signed = opt == KOption::Int;
+                let n = unpack_int(FNAME, &data[pos..], h.little, size, signed)?;
+                results.push(n.into_lua(lua)?);
+            }
+            KOption::Float => {
+                let bytes = data[pos..pos + size].try_into().unwrap();
+                let n = if h.little {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                };
+                results.push(Value::Number(n as Number));
+            }
+            KOption::Number | KOption::Double => {
+                let bytes = data[pos..pos + size].try_into().unwrap();
+                let n = if h.little {
+                    f64::from_le_bytes(bytes)
+                } else {
+                    f64::from_be_bytes(bytes)
+                };
+                results.push(Value::Number(n as Number));
+            }
+            KOption::Char => {
+                let s = lua.create_string(&data[pos..pos + size])?;
+                results.push(Value::String(s));
+            }
+            KOption::String => {
+                let len = unpack_int(FNAME, &data[pos..], h.little, size, false)? as u64;
+                if len > (data.len() - pos - size) as u64 {
+                    return Err(too_short());
+                }
+                let len = len as usize;
+                let s = lua.create_string(&data[pos + size..pos + size + len])?;
+                results.push(Value::String(s));
+                pos += len;
+            }
+            KOption::Zstr => {
+                let len = match data[pos..].iter().position(|&b| b == 0) {
+                    Some(len) => len,
+                    None => return Err(bad_argument(FNAME, 2, "unfinished string for format 'z'")),
+                };
+                let s = lua.create_string(&data[pos..pos + len])?;
+                results.push(Value::String(s));
+                pos += len + 1;
+            }
+            KOption::Padding | KOption::PaddAlign | KOption::Nop => {}
+        }
+        pos += size;
+    }
+
+    Ok((results, pos))
+}
+
+// Registers `string.pack` and `string.unpack` on Lua versions without native support
+#[cfg(any(feature = "lua52", feature = "lua51", feature = "luajit"))]
+pub(crate) fn polyfill_string_library(lua: &Lua) -> Result<()> {
+    let string = match lua.globals().raw_get::<_, Option<Table>>("string")? {
+        Some(string) => string,
+        None => return Ok(()),
+    };
+
+    if let Value::Nil = string.raw_get::<_, Value>("pack")? {
+        let func = lua.create_function(|lua, (fmt, args): (String, MultiValue)| {
+            lua.create_string(pack(lua, fmt.as_bytes(), args)?)
+        })?;
+        string.raw_set("pack", func)?;
+    }
+
+    if let Value::Nil = string.raw_get::<_, Value>("unpack")? {
+        let func = lua.create_function(
+            |lua, (fmt, data, init): (String, String, Option<Integer>)| {
+                let data = data.as_bytes();
+                // Translate a relative initial position like `posrelatI` of `lstrlib.c`
+                let len = data.len() as i64;
+                #[allow(clippy::useless_conversion)]
+                let pos = match init.map_or(1, i64::from) {
+                    pos if pos > 0 => pos - 1,
+                    pos if pos == 0 || pos < -len => 0,
+                    pos => len + pos,
+                };
+                if pos > len {
+                    return Err(bad_argument("unpack", 3, "initial position out of string"));
+                }
+                let (mut results, pos) = unpack_from(lua, fmt.as_bytes(), data, pos as usize)?;
+                results.push((pos as Integer + 1).into_lua(lua)?);
+                Ok(MultiValue::from_vec(results))
+            },
+        )?;
+        string.raw_set("unpack", func)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,492 @@
+//! FFI-safe interface for plugins registering functions and userdata types.
+//!
+//! Plugins compiled separately from the host (possibly with a different compiler version) cannot
+//! exchange Rust types with it, as the Rust ABI is not stable. This module defines a small
+//! `#[repr(C)]` interface which plugins use instead: a plugin exports a [`PluginEntry`] function,
+//! the host loads it (eg. from a dynamic library) and passes it to [`Lua::load_plugin`], which
+//! calls the entry with a [`PluginApi`] table to register functions and userdata types.
+//!
+//! Plugin functions receive and return [`FfiValue`]s, so plugins don't need to link against Lua.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::os::raw::{c_int, c_void};
+//! use mlua::plugin::{FfiCall, FfiValue, PluginApi, PLUGIN_ABI_VERSION};
+//! # use mlua::{Lua, Result};
+//!
+//! unsafe extern "C" fn add(call: *const FfiCall, _data: *mut c_void) -> c_int {
+//!     let call = &*call;
+//!     let args = std::slice::from_raw_parts(call.args, call.nargs);
+//!     match args {
+//!         [FfiValue::Integer(a), FfiValue::Integer(b)] => {
+//!             (call.push_return)(call.ret, &FfiValue::Integer(a + b));
+//!             0
+//!         }
+//!         _ => 1,
+//!     }
+//! }
+//!
+//! unsafe extern "C" fn plugin_entry(api: *const PluginApi) -> c_int {
+//!     let api = &*api;
+//!     if api.abi_version != PLUGIN_ABI_VERSION {
+//!         return 1;
+//!     }
+//!     let name = "add";
+//!     (api.register_function)(api.ctx, name.as_ptr(), name.len(), add, std::ptr::null_mut())
+//! }
+//!
+//! # fn main() -> Result<()> {
+//! let lua = Lua::new();
+//! let plugin = unsafe { lua.load_plugin(plugin_entry)? };
+//! lua.globals().set("plugin", plugin)?;
+//! assert_eq!(lua.load("plugin.add(1, 2)").eval::<i64>()?, 3);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Lua::load_plugin`]: crate::Lua::load_plugin
+
+use std::os::raw::{c_int, c_void};
+use std::slice;
+use std::string::String as StdString;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::string::String;
+use crate::table::Table;
+use crate::types::LightUserData;
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+use crate::value::{MultiValue, Value};
+
+/// Version of the plugin ABI.
+///
+/// Incremented on every incompatible change of the types in this module. Plugins must check
+/// [`PluginApi::abi_version`] before using the API.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Entry point of a plugin.
+///
+/// Returns `0` on success.
+pub type PluginEntry = unsafe extern "C" fn(api: *const PluginApi) -> c_int;
+
+/// A function implemented by a plugin.
+///
+/// `data` is the pointer provided when the function was registered. Returns `0` on success.
+/// On failure, the first returned value (if it's a string) is used as the error message.
+pub type FfiFunction = unsafe extern "C" fn(call: *const FfiCall, data: *mut c_void) -> c_int;
+
+/// Destructor of plugin userdata.
+pub type FfiDrop = unsafe extern "C" fn(data: *mut c_void);
+
+/// A value passed between the host and plugins.
+///
+/// Only values listed here can be passed to plugin functions, other Lua values (eg. tables)
+/// result in a conversion error.
+#[repr(C, u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FfiValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    /// A byte string, valid only for the duration of the call.
+    String {
+        ptr: *const u8,
+        len: usize,
+    },
+    /// Plugin userdata owned by Lua, valid only for the duration of the call.
+    ///
+    /// Cannot be returned from plugin functions.
+    UserData {
+        type_id: u32,
+        data: *mut c_void,
+    },
+    /// Returns new plugin userdata of the registered type, transferring ownership to Lua.
+    ///
+    /// `data` is freed by the type destructor once collected, or immediately if the call fails
+    /// (eg. a previously returned value could not be converted). If `type_id` is not a
+    /// registered type, there is no destructor to call and `data` is leaked.
+    NewUserData {
+        type_id: u32,
+        data: *mut c_void,
+    },
+}
+
+/// Arguments and return values of a [`FfiFunction`] call.
+#[repr(C)]
+pub struct FfiCall {
+    /// Arguments of the call (for methods the first argument is the userdata).
+    pub args: *const FfiValue,
+    /// Number of arguments.
+    pub nargs: usize,
+    /// Opaque pointer passed to `push_return`.
+    pub ret: *mut c_void,
+    /// Appends a return value. Strings are copied before the function returns.
+    pub push_return: unsafe extern "C" fn(ret: *mut c_void, value: *const FfiValue),
+}
+
+/// A method of a plugin userdata type.
+#[repr(C)]
+pub struct FfiMethod {
+    /// Method name (UTF-8).
+    pub name: *const u8,
+    pub name_len: usize,
+    pub func: FfiFunction,
+    /// Pointer passed to `func`.
+    pub data: *mut c_void,
+}
+
+/// Description of a plugin userdata type.
+#[repr(C)]
+pub struct FfiUserDataType {
+    /// Type name (UTF-8).
+    pub name: *const u8,
+    pub name_len: usize,
+    pub methods: *const FfiMethod,
+    pub methods_len: usize,
+    /// Called once the userdata is collected.
+    pub drop: Option<FfiDrop>,
+}
+
+/// Functions provided by the host to a plugin entry point.
+///
+/// All pointers are valid only for the duration of the entry point call.
+#[repr(C)]
+pub struct PluginApi {
+    /// Version of the ABI used by the host (see [`PLUGIN_ABI_VERSION`]).
+    pub abi_version: u32,
+    /// Opaque pointer passed to the functions below.
+    pub ctx: *mut c_void,
+    /// Registers a function in the plugin table. Returns `0` on success.
+    pub register_function: unsafe extern "C" fn(
+        ctx: *mut c_void,
+        name: *const u8,
+        name_len: usize,
+        func: FfiFunction,
+        data: *mut c_void,
+    ) -> c_int,
+    /// Registers a userdata type. Returns the type id, or `0` on failure.
+    pub register_userdata_type:
+        unsafe extern "C" fn(ctx: *mut c_void, ty: *const FfiUserDataType) -> u32,
+}
+
+// Registry key of the table with registered userdata types, indexed by type id
+const TYPES_REGISTRY_KEY: &str = "__mlua_plugin_types";
+
+struct PluginContext<'lua> {
+    lua: &'lua Lua,
+    exports: Table<'lua>,
+    error: Option<Error>,
+}
+
+struct CallState<'lua> {
+    lua: &'lua Lua,
+    values: Vec<Value<'lua>>,
+    error: Option<Error>,
+}
+
+// Plugin userdata owned by Lua
+struct PluginObject {
+    type_id: u32,
+    data: *mut c_void,
+    drop: Option<FfiDrop>,
+}
+
+// SAFETY: with the `send` feature, Lua userdata must be `Send`. The pointer is only passed back
+// to the plugin (in method calls and to the destructor), so it's sound as long as the plugin data
+// can be used and dropped from any thread, which is a requirement of the plugin interface when
+// the host uses the `send` feature (see `Lua::load_plugin`).
+unsafe impl Send for PluginObject {}
+
+impl Drop for PluginObject {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            unsafe { drop(self.data) };
+        }
+    }
+}
+
+impl UserData for PluginObject {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_function(MetaMethod::Index, |_, (ud, key): (AnyUserData, Value)| {
+            ud.get_user_value::<Table>()?
+                .raw_get::<_, Table>("methods")?
+                .raw_get::<_, Value>(key)
+        });
+        methods.add_meta_function(MetaMethod::ToString, |_, ud: AnyUserData| {
+            let name = ud.get_user_value::<Table>()?.raw_get::<_, String>("name")?;
+            let data = ud.borrow::<PluginObject>()?.data;
+            Ok(format!("{}: {data:?}", name.to_str()?))
+        });
+    }
+}
+
+pub(crate) unsafe fn load<'lua>(lua: &'lua Lua, entry: PluginEntry) -> Result<Table<'lua>> {
+    let mut ctx = PluginContext {
+        lua,
+        exports: lua.create_table()?,
+        error: None,
+    };
+    let api = PluginApi {
+        abi_version: PLUGIN_ABI_VERSION,
+        ctx: &mut ctx as *mut PluginContext as *mut c_void,
+        register_function,
+        register_userdata_type,
+    };
+    let code = entry(&api);
+    if let Some(err) = ctx.error {
+        return Err(err);
+    }
+    if code != 0 {
+        let msg = format!("plugin initialization failed with code {code}");
+        return Err(Error::RuntimeError(msg));
+    }
+    Ok(ctx.exports)
+}
+
+unsafe extern "C" fn register_function(
+    ctx: *mut c_void,
+    name: *const u8,
+    name_len: usize,
+    func: FfiFunction,
+    data: *mut c_void,
+) -> c_int {
+    let ctx = &mut *(ctx as *mut PluginContext);
+    let result = (|| {
+        let name = ctx
+            .lua
+            .create_string(slice::from_raw_parts(name, name_len))?;
+        let func = create_ffi_function(ctx.lua, func, data, None)?;
+        ctx.exports.raw_set(name, func)
+    })();
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            ctx.error.get_or_insert(err);
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn register_userdata_type(ctx: *mut c_void, ty: *const FfiUserDataType) -> u32 {
+    let ctx = &mut *(ctx as *mut PluginContext);
+    let ty = &*ty;
+    let result = (|| {
+        let lua = ctx.lua;
+        let types = match lua.named_registry_value::<Option<Table>>(TYPES_REGISTRY_KEY)? {
+            Some(types) => types,
+            None => {
+                let types = lua.create_table()?;
+                lua.set_named_registry_value(TYPES_REGISTRY_KEY, types.clone())?;
+                types
+            }
+        };
+        let type_id = types.raw_len() as u32 + 1;
+        let type_name = lua.create_string(slice::from_raw_parts(ty.name, ty.name_len))?;
+
+        let methods = lua.create_table_with_capacity(0, ty.methods_len as c_int)?;
+        for method in slice::from_raw_parts(ty.methods, ty.methods_len) {
+            let name = lua.create_string(slice::from_raw_parts(method.name, method.name_len))?;
+            let self_type = SelfType {
+                type_id,
+                method: format!("{}.{}", type_name.to_string_lossy(), name.to_string_lossy()),
+            };
+            let func = create_ffi_function(lua, method.func, method.data, Some(self_type))?;
+            methods.raw_set(name, func)?;
+        }
+        let info = lua.create_table_with_capacity(0, 3)?;
+        info.raw_set("name", type_name)?;
+        info.raw_set("methods", methods)?;
+        if let Some(drop) = ty.drop {
+            info.raw_set("drop", LightUserData(drop as *mut c_void))?;
+        }
+
+        types.raw_push(info)?;
+        Ok(type_id)
+    })();
+    match result {
+        Ok(type_id) => type_id,
+        Err(err) => {
+            ctx.error.get_or_insert(err);
+            0
+        }
+    }
+}
+
+// Userdata type expected as the first argument of a plugin method
+struct SelfType {
+    type_id: u32,
+    method: StdString,
+}
+
+fn create_ffi_function<'lua>(
+    lua: &'lua Lua,
+    func: FfiFunction,
+    data: *mut c_void,
+    self_type: Option<SelfType>,
+) -> Result<Function<'lua>> {
+    // SAFETY: same as for `PluginObject`, data of plugin functions must be usable from any thread
+    // when the host uses the `send` feature
+    struct FfiData(*mut c_void);
+    unsafe impl Send for FfiData {}
+    impl FfiData {
+        fn get(&self) -> *mut c_void {
+            self.0
+        }
+    }
+
+    let data = FfiData(data);
+    lua.create_function(move |lua, args: MultiValue| {
+        // Keep `args` alive while the plugin has pointers to them
+        let ffi_args = (args.iter())
+            .map(to_ffi_value)
+            .collect::<Result<Vec<_>>>()?;
+        // Plugins downcast `self` without checking its type
+        if let Some(self_type) = &self_type {
+            match ffi_args.first() {
+                Some(FfiValue::UserData { type_id, .. }) if *type_id == self_type.type_id => {}
+                _ => {
+                    return Err(Error::BadArgument {
+                        to: Some(self_type.method.clone()),
+                        pos: 1,
+                        name: Some("self".to_string()),
+                        cause: Arc::new(Error::UserDataTypeMismatch),
+                    })
+                }
+            }
+        }
+        let mut state = CallState {
+            lua,
+            values: Vec::new(),
+            error: None,
+        };
+        let call = FfiCall {
+            args: ffi_args.as_ptr(),
+            nargs: ffi_args.len(),
+            ret: &mut state as *mut CallState as *mut c_void,
+            push_return,
+        };
+        let code = unsafe { func(&call, data.get()) };
+        if let Some(err) = state.error {
+            return Err(err);
+        }
+        if code != 0 {
+            return Err(match state.values.first() {
+                Some(Value::String(msg)) => Error::RuntimeError(msg.to_string_lossy().into_owned()),
+                _ => Error::RuntimeError(format!("plugin function failed with code {code}")),
+            });
+        }
+        Ok(MultiValue::from_vec(state.values))
+    })
+}
+
+fn to_ffi_value(value: &Value) -> Result<FfiValue> {
+    Ok(match value {
+        Value::Nil => FfiValue::Nil,
+        Value::Boolean(b) => FfiValue::Boolean(*b),
+        #[allow(clippy::useless_conversion)]
+        Value::Integer(i) => FfiValue::Integer((*i).into()),
+        Value::Number(n) => FfiValue::Number(*n),
+        Value::String(s) => {
+            let bytes = s.as_bytes();
+            FfiValue::String {
+                ptr: bytes.as_ptr(),
+                len: bytes.len(),
+            }
+        }
+        Value::UserData(ud) if ud.is::<PluginObject>() => {
+            let obj = ud.borrow::<PluginObject>()?;
+            FfiValue::UserData {
+                type_id: obj.type_id,
+                data: obj.data,
+            }
+        }
+        _ => {
+            return Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "FfiValue",
+                message: Some("value cannot be passed to a plugin".to_string()),
+            })
+        }
+    })
+}
+
+unsafe extern "C" fn push_return(ret: *mut c_void, value: *const FfiValue) {
+    let state = &mut *(ret as *mut CallState);
+    if state.error.is_some() {
+        // The value is discarded, but the plugin still transferred its ownership
+        discard_ffi_value(state.lua, *value);
+        return;
+    }
+    match from_ffi_value(state.lua, *value) {
+        Ok(value) => state.values.push(value),
+        Err(err) => state.error = Some(err),
+    }
+}
+
+unsafe fn from_ffi_value<'lua>(lua: &'lua Lua, value: FfiValue) -> Result<Value<'lua>> {
+    Ok(match value {
+        FfiValue::Nil => Value::Nil,
+        FfiValue::Boolean(b) => Value::Boolean(b),
+        FfiValue::Integer(i) => Value::Integer(i as _),
+        FfiValue::Number(n) => Value::Number(n as _),
+        FfiValue::String { ptr, len } => {
+            Value::String(lua.create_string(slice::from_raw_parts(ptr, len))?)
+        }
+        FfiValue::UserData { .. } => {
+            let msg = "borrowed plugin userdata cannot be returned".to_string();
+            return Err(Error::RuntimeError(msg));
+        }
+        FfiValue::NewUserData { type_id, data } => {
+            let (info, drop) = match userdata_type(lua, type_id) {
+                Ok(Some(ty)) => ty,
+                Ok(None) => {
+                    let msg = format!("unknown plugin userdata type id {type_id}");
+                    return Err(Error::RuntimeError(msg));
+                }
+                Err(err) => {
+                    discard_ffi_value(lua, value);
+                    return Err(err);
+                }
+            };
+            // From now on `data` is dropped together with `PluginObject`, even on errors
+            let ud = lua.create_userdata(PluginObject {
+                type_id,
+                data,
+                drop,
+            })?;
+            ud.set_user_value(info)?;
+            Value::UserData(ud)
+        }
+    })
+}
+
+// Frees a value returned by a plugin function that is not passed to Lua
+unsafe fn discard_ffi_value(lua: &Lua, value: FfiValue) {
+    if let FfiValue::NewUserData { type_id, data } = value {
+        if let Ok(Some((_, Some(drop)))) = userdata_type(lua, type_id) {
+            drop(data);
+        }
+    }
+}
+
+// Returns the description and destructor of a registered userdata type
+unsafe fn userdata_type<'lua>(
+    lua: &'lua Lua,
+    type_id: u32,
+) -> Result<Option<(Table<'lua>, Option<FfiDrop>)>> {
+    let types = match lua.named_registry_value::<Option<Table>>(TYPES_REGISTRY_KEY)? {
+        Some(types) => types,
+        None => return Ok(None),
+    };
+    let info = match types.raw_get::<_, Option<Table>>(type_id)? {
+        Some(info) => info,
+        None => return Ok(None),
+    };
+    let drop = (info.raw_get::<_, Option<LightUserData>>("drop")?)
+        .map(|LightUserData(drop)| std::mem::transmute::<*mut c_void, FfiDrop>(drop));
+    Ok(Some((info, drop)))
+}
@@ -2,37 +2,74 @@
 
 #[doc(no_inline)]
 pub use crate::{
-    AnyUserData as LuaAnyUserData, AnyUserDataExt as LuaAnyUserDataExt, Chunk as LuaChunk,
-    Error as LuaError, ErrorContext as LuaErrorContext, ExternalError as LuaExternalError,
-    ExternalResult as LuaExternalResult, FromLua, FromLuaMulti, Function as LuaFunction,
-    FunctionInfo as LuaFunctionInfo, GCMode as LuaGCMode, Integer as LuaInteger, IntoLua,
-    IntoLuaMulti, LightUserData as LuaLightUserData, Lua, LuaOptions, MetaMethod as LuaMetaMethod,
-    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, RegistryKey as LuaRegistryKey,
-    Result as LuaResult, StdLib as LuaStdLib, String as LuaString, Table as LuaTable,
-    TableExt as LuaTableExt, TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
-    Thread as LuaThread, ThreadStatus as LuaThreadStatus, UserData as LuaUserData,
+    AnyUserData as LuaAnyUserData, AnyUserDataExt as LuaAnyUserDataExt, ApiDoc as LuaApiDoc,
+    ArrayView as LuaArrayView, BitWidth as LuaBitWidth, BorrowPolicy as LuaBorrowPolicy,
+    Capabilities as LuaCapabilities, CheckedConversion as LuaCheckedConversion, Chunk as LuaChunk,
+    ConversionContext as LuaConversionContext, ConversionLimits as LuaConversionLimits,
+    ConversionPolicy as LuaConversionPolicy, Downgrade as LuaDowngrade,
+    DuplicatePolicy as LuaDuplicatePolicy, Encoding as LuaEncoding, Error as LuaError,
+    ErrorContext as LuaErrorContext, ExpressionPolicy as LuaExpressionPolicy,
+    ExternalError as LuaExternalError, ExternalResult as LuaExternalResult,
+    FamilyFn as LuaFamilyFn, FnMeta as LuaFnMeta, FromLua, FromLuaMulti, Function as LuaFunction,
+    FunctionInfo as LuaFunctionInfo, FunctionKind as LuaFunctionKind, GCMode as LuaGCMode,
+    GcStats as LuaGcStats, GlobalPolicy as LuaGlobalPolicy, GlobalViolation as LuaGlobalViolation,
+    GlobalsTransaction as LuaGlobalsTransaction, HashOptions as LuaHashOptions,
+    HashableValue as LuaHashableValue, HeapAnalysis as LuaHeapAnalysis,
+    InstructionCounter as LuaInstructionCounter, Integer as LuaInteger,
+    IntegerOverflow as LuaIntegerOverflow, IntoLua, IntoLuaMulti, LenMode as LuaLenMode,
+    LightUserData as LuaLightUserData, LocalePolicy as LuaLocalePolicy, Lua, LuaObject, LuaOptions,
+    LuaResultExt, MapView as LuaMapView, MappedLocation as LuaMappedLocation,
+    MetaMethod as LuaMetaMethod, MetaPairs as LuaMetaPairs,
+    MetatableBuilder as LuaMetatableBuilder, MethodDesc as LuaMethodDesc,
+    MultiValue as LuaMultiValue, Nil as LuaNil, Number as LuaNumber, RawTable as LuaRawTable,
+    ReentrancyPolicy as LuaReentrancyPolicy, RegistryKey as LuaRegistryKey, Result as LuaResult,
+    SetMode as LuaSetMode, SharedDataSegment as LuaSharedDataSegment, Signal as LuaSignal,
+    SourceMap as LuaSourceMap, StdLib as LuaStdLib, String as LuaString,
+    StringBuilder as LuaStringBuilder, Table as LuaTable,
+    TableClassRegistrar as LuaTableClassRegistrar, TableExt as LuaTableExt,
+    TablePairs as LuaTablePairs, TableSequence as LuaTableSequence,
+    TableSequenceRef as LuaTableSequenceRef, TableWithMeta as LuaTableWithMeta,
+    Thread as LuaThread, ThreadStatus as LuaThreadStatus, ToStringMode as LuaToStringMode,
+    TracebackOptions as LuaTracebackOptions, UserData as LuaUserData,
     UserDataFields as LuaUserDataFields, UserDataMetatable as LuaUserDataMetatable,
     UserDataMethods as LuaUserDataMethods, UserDataRef as LuaUserDataRef,
     UserDataRefMut as LuaUserDataRefMut, UserDataRegistrar as LuaUserDataRegistrar,
-    Value as LuaValue,
+    Value as LuaValue, ValueMirror as LuaValueMirror, Visibility as LuaVisibility,
+    WeakRef as LuaWeakRef,
 };
 
 #[cfg(not(feature = "luau"))]
 #[doc(no_inline)]
-pub use crate::HookTriggers as LuaHookTriggers;
+pub use crate::{
+    HookGuard as LuaHookGuard, HookTriggers as LuaHookTriggers, Sandbox as LuaSandbox,
+    SandboxBuilder as LuaSandboxBuilder, StepControl as LuaStepControl, StepInfo as LuaStepInfo,
+    ThreadBuilder as LuaThreadBuilder,
+};
 
 #[cfg(feature = "luau")]
 #[doc(no_inline)]
-pub use crate::{CoverageInfo as LuaCoverageInfo, Vector as LuaVector, VmState as LuaVmState};
+pub use crate::{CoverageInfo as LuaCoverageInfo, VmState as LuaVmState};
+
+#[cfg(any(feature = "luau", feature = "vector"))]
+#[doc(no_inline)]
+pub use crate::Vector as LuaVector;
 
 #[cfg(feature = "async")]
 #[doc(no_inline)]
-pub use crate::AsyncThread as LuaAsyncThread;
+pub use crate::{
+    AsyncThread as LuaAsyncThread, ExecutorSignal as LuaExecutorSignal, LuaExecutorIntegration,
+    TaskId as LuaTaskId,
+};
+
+#[cfg(feature = "bindgen")]
+#[doc(no_inline)]
+pub use crate::Bindgen as LuaBindgen;
 
 #[cfg(feature = "serialize")]
 #[doc(no_inline)]
 pub use crate::{
-    DeserializeOptions as LuaDeserializeOptions, LuaSerdeExt,
+    ArrayFormat as LuaArrayFormat, DeserializeOptions as LuaDeserializeOptions, LuaSerdeExt,
+    PortableError as LuaPortableError, SerdePolicy as LuaSerdePolicy,
     SerializeOptions as LuaSerializeOptions,
 };
 
@@ -40,5 +77,5 @@ pub use crate::{
 #[doc(no_inline)]
 pub use crate::{
     OwnedAnyUserData as LuaOwnedAnyUserData, OwnedFunction as LuaOwnedFunction,
-    OwnedString as LuaOwnedString, OwnedTable as LuaOwnedTable,
+    OwnedString as LuaOwnedString, OwnedTable as LuaOwnedTable, OwnedThread as LuaOwnedThread,
 };
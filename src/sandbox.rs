@@ -0,0 +1,302 @@
+use std::string::String as StdString;
+
+use crate::chunk::{AsChunk, Chunk, ChunkMode};
+use crate::error::Result;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::value::{FromLuaMulti, Value};
+
+/// Global variables available in a sandbox by default.
+pub const DEFAULT_SANDBOX_GLOBALS: &[&str] = &[
+    "_VERSION",
+    "assert",
+    "error",
+    "getmetatable",
+    "ipairs",
+    "next",
+    "pairs",
+    "pcall",
+    "print",
+    "rawequal",
+    "rawget",
+    "rawlen",
+    "rawset",
+    "select",
+    "setmetatable",
+    "tonumber",
+    "tostring",
+    "type",
+    "unpack",
+    "xpcall",
+    "bit32",
+    "coroutine",
+    "math",
+    "os",
+    "string",
+    "table",
+    "utf8",
+];
+
+/// Global variables and library functions blocked in a sandbox by default.
+pub const DEFAULT_SANDBOX_BLOCKED: &[&str] = &[
+    "collectgarbage",
+    "dofile",
+    "load",
+    "loadfile",
+    "loadstring",
+    "require",
+    "io",
+    "debug",
+    "package",
+    "os.execute",
+    "os.exit",
+    "os.getenv",
+    "os.remove",
+    "os.rename",
+    "os.setlocale",
+    "os.tmpname",
+    "string.dump",
+];
+
+/// Builder for a restricted environment to run untrusted Lua code in.
+///
+/// Created by [`Lua::sandbox_builder`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let sandbox = lua
+///     .sandbox_builder()
+///     .allow_globals(["print", "string", "math"])
+///     .memory_limit(1024 * 1024)
+///     .build()?;
+///
+/// sandbox.exec("x = math.max(1, 2)")?;
+/// assert!(sandbox.exec("io.write('hello')").is_err());
+/// // Every chunk gets its own environment
+/// assert_eq!(sandbox.eval::<Option<i64>>("return x")?, None);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::sandbox_builder`]: crate::Lua::sandbox_builder
+#[must_use = "`SandboxBuilder` does nothing until `build` is called"]
+pub struct SandboxBuilder<'lua> {
+    lua: &'lua Lua,
+    globals: Vec<StdString>,
+    blocked: Vec<StdString>,
+    instruction_limit: Option<u64>,
+    memory_limit: Option<usize>,
+}
+
+/// A restricted environment to run untrusted Lua code in.
+///
+/// Created by [`SandboxBuilder::build`].
+pub struct Sandbox<'lua> {
+    lua: &'lua Lua,
+    template: Table<'lua>,
+    instruction_limit: Option<u64>,
+    memory_limit: Option<usize>,
+}
+
+impl<'lua> SandboxBuilder<'lua> {
+    pub(crate) fn new(lua: &'lua Lua) -> Self {
+        SandboxBuilder {
+            lua,
+            globals: DEFAULT_SANDBOX_GLOBALS.iter().map(|&s| s.into()).collect(),
+            blocked: DEFAULT_SANDBOX_BLOCKED.iter().map(|&s| s.into()).collect(),
+            instruction_limit: None,
+            memory_limit: None,
+        }
+    }
+
+    /// Sets the global variables copied into the sandbox environment, replacing the
+    /// [default ones](DEFAULT_SANDBOX_GLOBALS).
+    ///
+    /// Global variables missing from the Lua state are ignored.
+    pub fn allow_globals<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<StdString>,
+    {
+        self.globals = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Blocks global variables or library functions (eg. `"os.execute"`) in addition to the
+    /// [default ones](DEFAULT_SANDBOX_BLOCKED).
+    ///
+    /// Blocking takes precedence over allowing.
+    pub fn block<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<StdString>,
+    {
+        self.blocked.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Limits the number of VM instructions a single chunk can execute.
+    ///
+    /// Limits larger than 1000 are checked every 1000 instructions, so they are approximate.
+    /// On Luau, which has no instruction hooks, interrupt checks (at function calls and loop
+    /// iterations) are counted instead of instructions.
+    pub fn instruction_limit(mut self, limit: u64) -> Self {
+        self.instruction_limit = Some(limit);
+        self
+    }
+
+    /// Limits the amount of memory (in bytes) a single chunk can allocate.
+    ///
+    /// The limit is applied on top of the memory already used by the Lua state, using
+    /// [`Lua::set_memory_limit`].
+    ///
+    /// [`Lua::set_memory_limit`]: crate::Lua::set_memory_limit
+    pub fn memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    /// Builds the sandbox.
+    ///
+    /// Allowed global variables are captured at this point, later changes to the globals table are
+    /// not visible in the sandbox.
+    pub fn build(self) -> Result<Sandbox<'lua>> {
+        let lua = self.lua;
+        let template = lua.create_table()?;
+        let globals = lua.globals();
+        for name in &self.globals {
+            if self.blocked.contains(name) {
+                continue;
+            }
+            let value = match globals.raw_get::<_, Value>(name.as_str())? {
+                Value::Nil => continue,
+                Value::Table(lib) => {
+                    let prefix = format!("{name}.");
+                    let lib = copy_table(lua, &lib)?;
+                    for field in self.blocked.iter().filter_map(|b| b.strip_prefix(&prefix)) {
+                        lib.raw_set(field, Value::Nil)?;
+                    }
+                    Value::Table(lib)
+                }
+                value => value,
+            };
+            template.raw_set(name.as_str(), value)?;
+        }
+
+        Ok(Sandbox {
+            lua,
+            template,
+            instruction_limit: self.instruction_limit,
+            memory_limit: self.memory_limit,
+        })
+    }
+}
+
+impl<'lua> Sandbox<'lua> {
+    /// Creates a new environment table populated with the allowed global variables.
+    ///
+    /// Library tables are copied, so changes made to them are not visible outside of the
+    /// environment.
+    pub fn environment(&self) -> Result<Table<'lua>> {
+        let env = self.lua.create_table()?;
+        for pair in self.template.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            let value = match value {
+                Value::Table(lib) => Value::Table(copy_table(self.lua, &lib)?),
+                value => value,
+            };
+            env.raw_set(key, value)?;
+        }
+        env.raw_set("_G", env.clone())?;
+        Ok(env)
+    }
+
+    /// Loads a text chunk with a new [environment](Sandbox::environment).
+    ///
+    /// The instruction and memory limits and the string metatable isolation are not applied when
+    /// the chunk is run directly, use [`Sandbox::exec`] or [`Sandbox::eval`] instead.
+    #[track_caller]
+    pub fn load<'a>(&self, chunk: impl AsChunk<'a>) -> Chunk<'lua, 'a> {
+        let mut chunk = self.lua.load(chunk).set_mode(ChunkMode::Text);
+        chunk.env = self.environment().map(Some);
+        chunk
+    }
+
+    // Creates a locked string metatable that indexes the `string` library of the environment
+    fn string_metatable(&self, env: &Table<'lua>) -> Result<Table<'lua>> {
+        let metatable = self.lua.create_table()?;
+        metatable.raw_set("__index", env.raw_get::<_, Value>("string")?)?;
+        metatable.raw_set("__metatable", false)?;
+        Ok(metatable)
+    }
+
+    /// Loads and executes a chunk in a new environment, applying the sandbox limits.
+    #[track_caller]
+    pub fn exec<'a>(&self, chunk: impl AsChunk<'a>) -> Result<()> {
+        self.eval(chunk)
+    }
+
+    /// Loads and evaluates a chunk in a new environment, applying the sandbox limits.
+    ///
+    /// Returns [`Error::InstructionLimitExceeded`] or [`Error::MemoryLimitExceeded`] if the
+    /// corresponding limit is exceeded. The instruction limit applies to coroutines created by the
+    /// chunk as well.
+    ///
+    /// While the chunk runs, string methods (eg. `("abc"):upper()`) are looked up in the `string`
+    /// library of the environment, and the string metatable is hidden from `getmetatable`, so the
+    /// chunk cannot reach the host `string` library or modify it.
+    ///
+    /// [`Error::InstructionLimitExceeded`]: crate::Error::InstructionLimitExceeded
+    /// [`Error::MemoryLimitExceeded`]: crate::Error::MemoryLimitExceeded
+    #[track_caller]
+    pub fn eval<'a, R: FromLuaMulti<'lua>>(&self, chunk: impl AsChunk<'a>) -> Result<R> {
+        let env = self.environment()?;
+        let string_mt = self.string_metatable(&env)?;
+        let chunk = self
+            .lua
+            .load(chunk)
+            .set_mode(ChunkMode::Text)
+            .set_environment(env);
+
+        let prev_memory_limit = match self.memory_limit {
+            Some(limit) => {
+                let limit = self.lua.used_memory().saturating_add(limit);
+                let prev = self.lua.set_memory_limit(limit)?;
+                if prev != 0 && prev < limit {
+                    self.lua.set_memory_limit(prev)?;
+                }
+                Some(prev)
+            }
+            None => None,
+        };
+
+        let result = self
+            .lua
+            .replace_string_metatable(Some(string_mt))
+            .and_then(|prev_mt| {
+                let result = match self.instruction_limit {
+                    Some(limit) => self.lua.with_instruction_limit(limit, || chunk.eval()),
+                    None => chunk.eval(),
+                };
+                self.lua.replace_string_metatable(prev_mt)?;
+                result
+            });
+        if let Some(prev) = prev_memory_limit {
+            self.lua.set_memory_limit(prev)?;
+        }
+        result
+    }
+}
+
+fn copy_table<'lua>(lua: &'lua Lua, table: &Table<'lua>) -> Result<Table<'lua>> {
+    let copy = lua.create_table()?;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        copy.raw_set(key, value)?;
+    }
+    Ok(copy)
+}
@@ -12,9 +12,9 @@ use crate::function::Function;
 use crate::lua::Lua;
 use crate::types::{Callback, CallbackUpvalue, LuaRef, MaybeSend};
 use crate::userdata::{
-    AnyUserData, MetaMethod, UserData, UserDataCell, UserDataFields, UserDataMethods,
+    AnyUserData, MetaMethod, UserData, UserDataCell, UserDataFields, UserDataMethods, Visibility,
 };
-use crate::userdata_impl::UserDataRegistrar;
+use crate::userdata_impl::{check_member_conflicts, member_names, UserDataRegistrar};
 use crate::util::{
     assert_stack, check_stack, get_userdata, init_userdata_metatable, push_table, rawset_field,
     take_userdata, StackGuard,
@@ -362,6 +362,26 @@ impl<'lua, 'scope> Scope<'lua, 'scope> {
         T::add_fields(&mut ud_fields);
         T::add_methods(&mut ud_methods);
 
+        if ud_methods.deny_overrides {
+            let getters = member_names(&ud_fields.fields);
+            let getters = getters.chain(member_names(&ud_fields.field_getters));
+            let meta = member_names(&ud_fields.meta_fields);
+            let meta = meta.chain(member_names(&ud_methods.meta_methods));
+            let setters = member_names(&ud_fields.field_setters);
+            let methods = member_names(&ud_methods.methods);
+            check_member_conflicts::<T>(getters, setters, methods, meta)?;
+        }
+
+        // Non-static userdata cannot be accessed from Rust by type, so host-only members are dropped
+        let host_only = [&ud_fields.host_only[..], &ud_methods.host_only[..]].concat();
+        ud_fields
+            .field_getters
+            .retain(|(k, _)| !host_only.contains(k));
+        ud_fields
+            .field_setters
+            .retain(|(k, _)| !host_only.contains(k));
+        ud_methods.methods.retain(|(k, _)| !host_only.contains(k));
+
         let lua = self.lua;
         let state = lua.state();
         unsafe {
@@ -574,6 +594,8 @@ enum NonStaticMethod<'lua, T> {
 struct NonStaticUserDataMethods<'lua, T: UserData> {
     methods: Vec<(String, NonStaticMethod<'lua, T>)>,
     meta_methods: Vec<(String, NonStaticMethod<'lua, T>)>,
+    host_only: Vec<String>,
+    deny_overrides: bool,
 }
 
 impl<'lua, T: UserData> Default for NonStaticUserDataMethods<'lua, T> {
@@ -581,11 +603,21 @@ impl<'lua, T: UserData> Default for NonStaticUserDataMethods<'lua, T> {
         NonStaticUserDataMethods {
             methods: Vec::new(),
             meta_methods: Vec::new(),
+            host_only: Vec::new(),
+            deny_overrides: false,
         }
     }
 }
 
 impl<'lua, T: UserData> UserDataMethods<'lua, T> for NonStaticUserDataMethods<'lua, T> {
+    fn set_visibility(&mut self, name: impl AsRef<str>, visibility: Visibility) {
+        set_visibility(&mut self.host_only, name.as_ref(), visibility);
+    }
+
+    fn deny_overrides(&mut self) {
+        self.deny_overrides = true;
+    }
+
     fn add_method<M, A, R>(&mut self, name: impl AsRef<str>, method: M)
     where
         M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
@@ -774,6 +806,7 @@ struct NonStaticUserDataFields<'lua, T: UserData> {
     field_getters: Vec<(String, NonStaticMethod<'lua, T>)>,
     field_setters: Vec<(String, NonStaticMethod<'lua, T>)>,
     meta_fields: Vec<(String, Callback<'lua, 'static>)>,
+    host_only: Vec<String>,
 }
 
 impl<'lua, T: UserData> Default for NonStaticUserDataFields<'lua, T> {
@@ -783,11 +816,16 @@ impl<'lua, T: UserData> Default for NonStaticUserDataFields<'lua, T> {
             field_getters: Vec::new(),
             field_setters: Vec::new(),
             meta_fields: Vec::new(),
+            host_only: Vec::new(),
         }
     }
 }
 
 impl<'lua, T: UserData> UserDataFields<'lua, T> for NonStaticUserDataFields<'lua, T> {
+    fn set_visibility(&mut self, name: impl AsRef<str>, visibility: Visibility) {
+        set_visibility(&mut self.host_only, name.as_ref(), visibility);
+    }
+
     fn add_field<V>(&mut self, name: impl AsRef<str>, value: V)
     where
         V: IntoLua<'lua> + Clone + 'static,
@@ -871,3 +909,10 @@ impl<'lua, T: UserData> UserDataFields<'lua, T> for NonStaticUserDataFields<'lua
         ));
     }
 }
+
+fn set_visibility(host_only: &mut Vec<String>, name: &str, visibility: Visibility) {
+    host_only.retain(|k| k != name);
+    if visibility == Visibility::HostOnly {
+        host_only.push(name.to_string());
+    }
+}
@@ -2,13 +2,17 @@ use std::cell::RefCell;
 use std::convert::TryInto;
 use std::os::raw::c_void;
 use std::rc::Rc;
+use std::str;
 use std::string::String as StdString;
 
 use rustc_hash::FxHashSet;
 use serde::de::{self, IntoDeserializer};
 
+use crate::conversion::ConversionLimits;
 use crate::error::{Error, Result};
+use crate::serde::{push_borrowed_strings_table, SerdePolicy};
 use crate::table::{Table, TablePairs, TableSequence};
+use crate::types::Integer;
 use crate::userdata::AnyUserData;
 use crate::value::Value;
 
@@ -18,6 +22,18 @@ pub struct Deserializer<'lua> {
     value: Value<'lua>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    borrow: BorrowMode<'lua>,
+}
+
+// Defines how strings are passed to visitors
+#[derive(Debug, Clone)]
+enum BorrowMode<'lua> {
+    // Strings are copied
+    Owned,
+    // Strings are borrowed, the only reachable string is the root value itself
+    Root,
+    // Strings are borrowed and kept alive by storing them in a table anchored to the root value
+    Anchored(Table<'lua>),
 }
 
 /// A struct with options to change default deserializer behavior.
@@ -42,6 +58,47 @@ pub struct Options {
     ///
     /// Default: **true**
     pub deny_recursive_tables: bool,
+
+    /// Policy for converting table keys and array-like tables.
+    ///
+    /// Default: **[`SerdePolicy::new()`]**
+    pub policy: SerdePolicy,
+
+    /// If true, [`Value::NULL`] is deserialized as `None` into `Option` types, like `nil`.
+    /// Otherwise it's deserialized as `Some` of a unit value, which keeps the nil/null distinction
+    /// (eg. `Option<serde_json::Value>` becomes `Some(Null)`).
+    ///
+    /// Default: **true**
+    ///
+    /// [`Value::NULL`]: crate::Value::NULL
+    pub null_as_none: bool,
+
+    /// If true, empty tables are deserialized as `None` into `Option` types.
+    ///
+    /// Default: **false**
+    pub empty_table_as_none: bool,
+
+    /// If true, an attempt to deserialize a struct from a table missing some of its fields will
+    /// cause an error, even for `Option` fields (which have to be set to [`Value::NULL`]).
+    /// Otherwise missing `Option` fields are deserialized as `None`.
+    ///
+    /// Default: **false**
+    ///
+    /// [`Value::NULL`]: crate::Value::NULL
+    pub deny_missing_fields: bool,
+
+    /// If true, an attempt to deserialize a struct from a table with keys that are not fields of
+    /// the struct will cause an error.
+    /// Otherwise unknown keys are ignored.
+    ///
+    /// Default: **false**
+    pub deny_unknown_fields: bool,
+
+    /// Maximum nesting depth of tables. Deserializing more deeply nested tables will cause an
+    /// error.
+    ///
+    /// Default: **none**
+    pub max_depth: Option<usize>,
 }
 
 impl Default for Options {
@@ -56,6 +113,12 @@ impl Options {
         Options {
             deny_unsupported_types: true,
             deny_recursive_tables: true,
+            policy: SerdePolicy::new(),
+            null_as_none: true,
+            empty_table_as_none: false,
+            deny_missing_fields: false,
+            deny_unknown_fields: false,
+            max_depth: None,
         }
     }
 
@@ -76,6 +139,60 @@ impl Options {
         self.deny_recursive_tables = enabled;
         self
     }
+
+    /// Sets [`policy`] option.
+    ///
+    /// [`policy`]: #structfield.policy
+    #[must_use]
+    pub const fn policy(mut self, policy: SerdePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets [`null_as_none`] option.
+    ///
+    /// [`null_as_none`]: #structfield.null_as_none
+    #[must_use]
+    pub const fn null_as_none(mut self, enabled: bool) -> Self {
+        self.null_as_none = enabled;
+        self
+    }
+
+    /// Sets [`empty_table_as_none`] option.
+    ///
+    /// [`empty_table_as_none`]: #structfield.empty_table_as_none
+    #[must_use]
+    pub const fn empty_table_as_none(mut self, enabled: bool) -> Self {
+        self.empty_table_as_none = enabled;
+        self
+    }
+
+    /// Sets [`deny_missing_fields`] option.
+    ///
+    /// [`deny_missing_fields`]: #structfield.deny_missing_fields
+    #[must_use]
+    pub const fn deny_missing_fields(mut self, enabled: bool) -> Self {
+        self.deny_missing_fields = enabled;
+        self
+    }
+
+    /// Sets [`deny_unknown_fields`] option.
+    ///
+    /// [`deny_unknown_fields`]: #structfield.deny_unknown_fields
+    #[must_use]
+    pub const fn deny_unknown_fields(mut self, enabled: bool) -> Self {
+        self.deny_unknown_fields = enabled;
+        self
+    }
+
+    /// Sets [`max_depth`] option.
+    ///
+    /// [`max_depth`]: #structfield.max_depth
+    #[must_use]
+    pub const fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
 }
 
 impl<'lua> Deserializer<'lua> {
@@ -90,18 +207,62 @@ impl<'lua> Deserializer<'lua> {
             value,
             options,
             visited: Rc::new(RefCell::new(FxHashSet::default())),
+            borrow: BorrowMode::Owned,
         }
     }
 
+    /// Creates a new Lua Deserializer that passes borrowed strings to visitors.
+    ///
+    /// Borrowed strings reachable from the `value` table are anchored to it (in a weak-keyed
+    /// registry table), so they stay alive for as long as the table. The anchors are not dropped
+    /// when deserialization finishes, as the borrowed strings can outlive it. It's sound as long
+    /// as the caller ties the deserializer lifetime `'de` to the borrow of `value`.
+    pub(crate) fn new_borrowed(value: &Value<'lua>, options: Options) -> Result<Self> {
+        let borrow = match value {
+            Value::Table(t) => {
+                let lua = t.0.lua;
+                let anchors = unsafe {
+                    push_borrowed_strings_table(lua.ref_thread());
+                    Table(lua.pop_ref_thread())
+                };
+                let strings = match anchors.raw_get::<_, Value>(t.clone())? {
+                    Value::Table(strings) => strings,
+                    _ => {
+                        let strings = lua.create_table()?;
+                        anchors.raw_set(t.clone(), strings.clone())?;
+                        strings
+                    }
+                };
+                BorrowMode::Anchored(strings)
+            }
+            _ => BorrowMode::Root,
+        };
+        Ok(Deserializer {
+            value: value.clone(),
+            options,
+            visited: Rc::new(RefCell::new(FxHashSet::default())),
+            borrow,
+        })
+    }
+
+    // Copies strings instead of borrowing them, for visitors that would copy them anyway, so
+    // they are not anchored needlessly
+    fn into_owned(mut self) -> Self {
+        self.borrow = BorrowMode::Owned;
+        self
+    }
+
     fn from_parts(
         value: Value<'lua>,
         options: Options,
         visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+        borrow: BorrowMode<'lua>,
     ) -> Self {
         Deserializer {
             value,
             options,
             visited,
+            borrow,
         }
     }
 }
@@ -125,12 +286,31 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
             Value::Number(n) => visitor.visit_f64(n.into()),
             #[cfg(feature = "luau")]
             Value::Vector(_) => self.deserialize_seq(visitor),
-            Value::String(s) => match s.to_str() {
-                Ok(s) => visitor.visit_str(s),
-                Err(_) => visitor.visit_bytes(s.as_bytes()),
+            Value::String(s) => {
+                (s.0.lua.conversion_limits())
+                    .check_string_len(s.as_bytes().len())
+                    .map_err(<Error as de::Error>::custom)?;
+                if let BorrowMode::Owned = self.borrow {
+                    return match s.to_str() {
+                        Ok(s) => visitor.visit_str(s),
+                        Err(_) => visitor.visit_bytes(s.as_bytes()),
+                    };
+                }
+                if let BorrowMode::Anchored(strings) = &self.borrow {
+                    strings.raw_set(s.clone(), true)?;
+                }
+                // SAFETY: Lua strings are immutable and the string is kept alive by the root
+                // value (or anchored to it), which is borrowed for `'de`
+                let bytes = unsafe { &*(s.as_bytes() as *const [u8]) };
+                match str::from_utf8(bytes) {
+                    Ok(s) => visitor.visit_borrowed_str(s),
+                    Err(_) => visitor.visit_borrowed_bytes(bytes),
+                }
+            }
+            Value::Table(ref t) => match self.options.policy.sequence_len(t)? {
+                Some(_) => self.deserialize_seq(visitor),
+                None => self.deserialize_map(visitor),
             },
-            Value::Table(ref t) if t.raw_len() > 0 || t.is_array() => self.deserialize_seq(visitor),
-            Value::Table(_) => self.deserialize_map(visitor),
             Value::LightUserData(ud) if ud.0.is_null() => visitor.visit_none(),
             Value::UserData(ud) if ud.is_serializable() => {
                 serde_userdata(ud, |value| value.deserialize_any(visitor))
@@ -159,7 +339,12 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     {
         match self.value {
             Value::Nil => visitor.visit_none(),
-            Value::LightUserData(ud) if ud.0.is_null() => visitor.visit_none(),
+            Value::LightUserData(ud) if ud.0.is_null() && self.options.null_as_none => {
+                visitor.visit_none()
+            }
+            Value::Table(ref t) if self.options.empty_table_as_none && t.is_empty() => {
+                visitor.visit_none()
+            }
             _ => visitor.visit_some(self),
         }
     }
@@ -176,7 +361,7 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     {
         let (variant, value, _guard) = match self.value {
             Value::Table(table) => {
-                let _guard = RecursionGuard::new(&table, &self.visited);
+                let _guard = RecursionGuard::new(&table, self.options, &self.visited)?;
 
                 let mut iter = table.pairs::<StdString, Value>();
                 let (variant, value) = match iter.next() {
@@ -213,6 +398,7 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
             value,
             options: self.options,
             visited: self.visited,
+            borrow: self.borrow,
         })
     }
 
@@ -233,13 +419,23 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
                 visitor.visit_seq(&mut deserializer)
             }
             Value::Table(t) => {
-                let _guard = RecursionGuard::new(&t, &self.visited);
-
-                let len = t.raw_len() as usize;
+                let _guard = RecursionGuard::new(&t, self.options, &self.visited)?;
+                let lua = t.0.lua;
+                let _depth = lua.enter_from_conversion()?;
+
+                let (len, seq) = match self.options.policy.sequence_len(&t)? {
+                    // Sparse sequences have holes, so iterate by length
+                    Some(len) => (len, t.sequence_values_by_len(Some(len as Integer))),
+                    None => (t.raw_len() as usize, t.sequence_values()),
+                };
+                (lua.conversion_limits())
+                    .check_table_entries(len)
+                    .map_err(<Error as de::Error>::custom)?;
                 let mut deserializer = SeqDeserializer {
-                    seq: t.sequence_values(),
+                    seq,
                     options: self.options,
                     visited: self.visited,
+                    borrow: self.borrow,
                 };
                 let seq = visitor.visit_seq(&mut deserializer)?;
                 if deserializer.seq.count() == 0 {
@@ -289,13 +485,17 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     {
         match self.value {
             Value::Table(t) => {
-                let _guard = RecursionGuard::new(&t, &self.visited);
+                let _guard = RecursionGuard::new(&t, self.options, &self.visited)?;
+                let lua = t.0.lua;
+                let _depth = lua.enter_from_conversion()?;
 
                 let mut deserializer = MapDeserializer {
+                    limits: lua.conversion_limits(),
                     pairs: t.pairs(),
                     value: None,
                     options: self.options,
                     visited: self.visited,
+                    borrow: self.borrow,
                     processed: 0,
                 };
                 let map = visitor.visit_map(&mut deserializer)?;
@@ -323,12 +523,15 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
     fn deserialize_struct<V>(
         self,
         _name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        if let Value::Table(ref t) = self.value {
+            check_struct_fields(t, fields, self.options)?;
+        }
         self.deserialize_map(visitor)
     }
 
@@ -367,9 +570,48 @@ impl<'lua, 'de> serde::Deserializer<'de> for Deserializer<'lua> {
         }
     }
 
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_owned().deserialize_any(visitor)
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_owned().deserialize_any(visitor)
+    }
+
+    #[inline]
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_owned().deserialize_any(visitor)
+    }
+
+    #[inline]
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_owned().deserialize_any(visitor)
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.into_owned().deserialize_any(visitor)
+    }
+
     serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
-        byte_buf identifier ignored_any
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 str bytes
     }
 }
 
@@ -377,6 +619,7 @@ struct SeqDeserializer<'lua> {
     seq: TableSequence<'lua, Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    borrow: BorrowMode<'lua>,
 }
 
 impl<'lua, 'de> de::SeqAccess<'de> for SeqDeserializer<'lua> {
@@ -394,7 +637,9 @@ impl<'lua, 'de> de::SeqAccess<'de> for SeqDeserializer<'lua> {
                         continue;
                     }
                     let visited = Rc::clone(&self.visited);
-                    let deserializer = Deserializer::from_parts(value, self.options, visited);
+                    let borrow = self.borrow.clone();
+                    let deserializer =
+                        Deserializer::from_parts(value, self.options, visited, borrow);
                     return seed.deserialize(deserializer).map(Some);
                 }
                 None => return Ok(None),
@@ -430,8 +675,12 @@ impl<'de> de::SeqAccess<'de> for VecDeserializer {
             Some(&n) => {
                 self.next += 1;
                 let visited = Rc::clone(&self.visited);
-                let deserializer =
-                    Deserializer::from_parts(Value::Number(n as _), self.options, visited);
+                let deserializer = Deserializer::from_parts(
+                    Value::Number(n as _),
+                    self.options,
+                    visited,
+                    BorrowMode::Owned,
+                );
                 seed.deserialize(deserializer).map(Some)
             }
             None => Ok(None),
@@ -444,10 +693,12 @@ impl<'de> de::SeqAccess<'de> for VecDeserializer {
 }
 
 struct MapDeserializer<'lua> {
+    limits: ConversionLimits,
     pairs: TablePairs<'lua, Value<'lua>, Value<'lua>>,
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    borrow: BorrowMode<'lua>,
     processed: usize,
 }
 
@@ -468,9 +719,20 @@ impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
                         continue;
                     }
                     self.processed += 1;
+                    (self.limits)
+                        .check_table_entries(self.processed)
+                        .map_err(<Error as de::Error>::custom)?;
                     self.value = Some(value);
+                    let key = self.options.policy.map_key(key);
+                    if let (true, Value::Integer(i)) =
+                        (self.options.policy.stringify_integer_keys, &key)
+                    {
+                        let key_de = IntoDeserializer::<Error>::into_deserializer(i.to_string());
+                        return seed.deserialize(key_de).map(Some);
+                    }
                     let visited = Rc::clone(&self.visited);
-                    let key_de = Deserializer::from_parts(key, self.options, visited);
+                    let borrow = self.borrow.clone();
+                    let key_de = Deserializer::from_parts(key, self.options, visited, borrow);
                     return seed.deserialize(key_de).map(Some);
                 }
                 None => return Ok(None),
@@ -485,7 +747,13 @@ impl<'lua, 'de> de::MapAccess<'de> for MapDeserializer<'lua> {
         match self.value.take() {
             Some(value) => {
                 let visited = Rc::clone(&self.visited);
-                seed.deserialize(Deserializer::from_parts(value, self.options, visited))
+                let borrow = self.borrow.clone();
+                seed.deserialize(Deserializer::from_parts(
+                    value,
+                    self.options,
+                    visited,
+                    borrow,
+                ))
             }
             None => Err(de::Error::custom("value is missing")),
         }
@@ -504,6 +772,7 @@ struct EnumDeserializer<'lua> {
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    borrow: BorrowMode<'lua>,
 }
 
 impl<'lua, 'de> de::EnumAccess<'de> for EnumDeserializer<'lua> {
@@ -519,6 +788,7 @@ impl<'lua, 'de> de::EnumAccess<'de> for EnumDeserializer<'lua> {
             value: self.value,
             options: self.options,
             visited: self.visited,
+            borrow: self.borrow,
         };
         seed.deserialize(variant).map(|v| (v, variant_access))
     }
@@ -528,6 +798,7 @@ struct VariantDeserializer<'lua> {
     value: Option<Value<'lua>>,
     options: Options,
     visited: Rc<RefCell<FxHashSet<*const c_void>>>,
+    borrow: BorrowMode<'lua>,
 }
 
 impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
@@ -549,7 +820,9 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
     {
         match self.value {
             Some(value) => {
-                seed.deserialize(Deserializer::from_parts(value, self.options, self.visited))
+                let deserializer =
+                    Deserializer::from_parts(value, self.options, self.visited, self.borrow);
+                seed.deserialize(deserializer)
             }
             None => Err(de::Error::invalid_type(
                 de::Unexpected::UnitVariant,
@@ -564,7 +837,7 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
     {
         match self.value {
             Some(value) => serde::Deserializer::deserialize_seq(
-                Deserializer::from_parts(value, self.options, self.visited),
+                Deserializer::from_parts(value, self.options, self.visited, self.borrow),
                 visitor,
             ),
             None => Err(de::Error::invalid_type(
@@ -574,13 +847,16 @@ impl<'lua, 'de> de::VariantAccess<'de> for VariantDeserializer<'lua> {
         }
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
+        if let Some(Value::Table(ref t)) = self.value {
+            check_struct_fields(t, fields, self.options)?;
+        }
         match self.value {
             Some(value) => serde::Deserializer::deserialize_map(
-                Deserializer::from_parts(value, self.options, self.visited),
+                Deserializer::from_parts(value, self.options, self.visited, self.borrow),
                 visitor,
             ),
             None => Err(de::Error::invalid_type(
@@ -600,11 +876,23 @@ struct RecursionGuard {
 
 impl RecursionGuard {
     #[inline]
-    fn new(table: &Table, visited: &Rc<RefCell<FxHashSet<*const c_void>>>) -> Self {
+    fn new(
+        table: &Table,
+        options: Options,
+        visited: &Rc<RefCell<FxHashSet<*const c_void>>>,
+    ) -> Result<Self> {
+        // Tables being deserialized are tracked until finished, so this is the nesting depth
+        if let Some(max_depth) = options.max_depth {
+            if visited.borrow().len() >= max_depth {
+                return Err(de::Error::custom(format!(
+                    "maximum nesting depth ({max_depth}) exceeded"
+                )));
+            }
+        }
         let visited = Rc::clone(visited);
         let ptr = table.to_pointer();
         visited.borrow_mut().insert(ptr);
-        RecursionGuard { ptr, visited }
+        Ok(RecursionGuard { ptr, visited })
     }
 }
 
@@ -614,6 +902,37 @@ impl Drop for RecursionGuard {
     }
 }
 
+// Checks table keys against struct `fields` if required by `options`
+fn check_struct_fields(
+    table: &Table,
+    fields: &'static [&'static str],
+    options: Options,
+) -> Result<()> {
+    if options.deny_unknown_fields {
+        for pair in table.clone().pairs::<Value, Value>() {
+            let key = match pair?.0 {
+                Value::String(s) => s.to_string_lossy().into_owned(),
+                Value::Integer(i) if options.policy.stringify_integer_keys => i.to_string(),
+                key => {
+                    let msg = format!("unexpected `{}` key in a struct table", key.type_name());
+                    return Err(de::Error::custom(msg));
+                }
+            };
+            if !fields.contains(&key.as_str()) {
+                return Err(de::Error::unknown_field(&key, fields));
+            }
+        }
+    }
+    if options.deny_missing_fields {
+        for &field in fields {
+            if table.raw_get::<_, Value>(field)? == Value::Nil {
+                return Err(de::Error::missing_field(field));
+            }
+        }
+    }
+    Ok(())
+}
+
 // Checks `options` and decides should we emit an error or skip next element
 fn check_value_if_skip(
     value: &Value,
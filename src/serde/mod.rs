@@ -2,7 +2,10 @@
 
 use std::os::raw::c_void;
 
-use serde::{de::DeserializeOwned, ser::Serialize};
+use serde::{
+    de::{Deserialize, DeserializeOwned},
+    ser::Serialize,
+};
 
 use crate::error::Result;
 use crate::lua::Lua;
@@ -191,6 +194,49 @@ pub trait LuaSerdeExt: Sealed {
     #[allow(clippy::wrong_self_convention)]
     fn from_value_with<T: DeserializeOwned>(&self, value: Value, options: de::Options)
         -> Result<T>;
+
+    /// Deserializes a borrowed [`Value`] into a serde deserializable object that can borrow
+    /// strings from it.
+    ///
+    /// Unlike [`from_value`], strings are not copied and can be deserialized into `&str` or
+    /// `&[u8]` (eg. fields of `#[serde(borrow)]` structs). To make it possible, every string
+    /// borrowed from a deserialized table is anchored to that table and stays alive for as
+    /// long as the table itself, even if it's removed from the table later. Strings deserialized
+    /// into owned types (eg. `String` fields or struct field names) are copied and not anchored.
+    ///
+    /// The anchors are kept after deserialization finishes, so repeatedly deserializing a
+    /// long-lived table whose strings are replaced retains the old strings as well. Use
+    /// [`from_value`] for such tables if borrowing is not needed.
+    ///
+    /// Requires `feature = "serialize"`
+    ///
+    /// [`Value`]: crate::Value
+    /// [`from_value`]: #tymethod.from_value
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use mlua::{Lua, Result, LuaSerdeExt, Value};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize, Debug, PartialEq)]
+    /// struct User<'a> {
+    ///     name: &'a str,
+    ///     age: u8,
+    /// }
+    ///
+    /// fn main() -> Result<()> {
+    ///     let lua = Lua::new();
+    ///     let val: Value = lua.load(r#"{name = "John Smith", age = 20}"#).eval()?;
+    ///     let u: User = lua.from_value_ref(&val)?;
+    ///
+    ///     assert_eq!(u, User { name: "John Smith", age: 20 });
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::wrong_self_convention)]
+    fn from_value_ref<'de, T: Deserialize<'de>>(&self, value: &'de Value) -> Result<T>;
 }
 
 impl LuaSerdeExt for Lua {
@@ -232,11 +278,21 @@ impl LuaSerdeExt for Lua {
     {
         T::deserialize(de::Deserializer::new_with_options(value, options))
     }
+
+    fn from_value_ref<'de, T>(&self, value: &'de Value) -> Result<T>
+    where
+        T: Deserialize<'de>,
+    {
+        T::deserialize(de::Deserializer::new_borrowed(
+            value,
+            de::Options::default(),
+        )?)
+    }
 }
 
-// Uses 2 stack spaces and calls checkstack.
+// Uses 3 stack spaces and calls checkstack.
 pub(crate) unsafe fn init_metatables(state: *mut ffi::lua_State) -> Result<()> {
-    check_stack(state, 2)?;
+    check_stack(state, 3)?;
     protect_lua!(state, 0, 0, fn(state) {
         ffi::lua_createtable(state, 0, 1);
 
@@ -246,6 +302,16 @@ pub(crate) unsafe fn init_metatables(state: *mut ffi::lua_State) -> Result<()> {
 
         let array_metatable_key = &ARRAY_METATABLE_REGISTRY_KEY as *const u8 as *const c_void;
         ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, array_metatable_key);
+
+        // Weak-keyed table to keep strings borrowed by `from_value_ref` alive
+        ffi::lua_createtable(state, 0, 0);
+        ffi::lua_createtable(state, 0, 1);
+        ffi::lua_pushstring(state, cstr!("k"));
+        ffi::lua_setfield(state, -2, cstr!("__mode"));
+        ffi::lua_setmetatable(state, -2);
+
+        let borrowed_strings_key = &BORROWED_STRINGS_REGISTRY_KEY as *const u8 as *const c_void;
+        ffi::lua_rawsetp(state, ffi::LUA_REGISTRYINDEX, borrowed_strings_key);
     })
 }
 
@@ -254,12 +320,20 @@ pub(crate) unsafe fn push_array_metatable(state: *mut ffi::lua_State) {
     ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, array_metatable_key);
 }
 
+pub(crate) unsafe fn push_borrowed_strings_table(state: *mut ffi::lua_State) {
+    let borrowed_strings_key = &BORROWED_STRINGS_REGISTRY_KEY as *const u8 as *const c_void;
+    ffi::lua_rawgetp(state, ffi::LUA_REGISTRYINDEX, borrowed_strings_key);
+}
+
 static ARRAY_METATABLE_REGISTRY_KEY: u8 = 0;
+static BORROWED_STRINGS_REGISTRY_KEY: u8 = 0;
 
 pub mod de;
+mod policy;
 pub mod ser;
 
 #[doc(inline)]
 pub use de::Deserializer;
+pub use policy::{ArrayFormat, SerdePolicy};
 #[doc(inline)]
 pub use ser::Serializer;
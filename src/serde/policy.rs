@@ -0,0 +1,191 @@
+use crate::error::Result;
+use crate::table::Table;
+use crate::types::Integer;
+use crate::value::Value;
+
+/// Defines which Lua tables are converted to serde sequences (arrays).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArrayFormat {
+    /// Tables with a non-zero length (`#t > 0`) or with the [`array_metatable`] are sequences.
+    ///
+    /// Keys outside of the sequence are ignored, so tables with integer keys (eg. ID-keyed maps)
+    /// can lose entries. This is the legacy behavior.
+    ///
+    /// [`array_metatable`]: crate::LuaSerdeExt::array_metatable
+    Border,
+    /// Only tables with keys exactly `1..=n` (or empty tables with the [`array_metatable`]) are
+    /// sequences. Any other table is a map.
+    ///
+    /// [`array_metatable`]: crate::LuaSerdeExt::array_metatable
+    Strict,
+    /// Like [`ArrayFormat::Strict`], but tables with only positive integer keys and at most
+    /// `max_holes` missing keys are also sequences, with `nil` in place of missing values.
+    PreserveSparse { max_holes: usize },
+}
+
+/// Policy for converting table keys and array-like tables between Lua and serde formats.
+///
+/// Set per conversion using [`DeserializeOptions::policy`] and [`SerializeOptions::policy`].
+///
+/// [`DeserializeOptions::policy`]: crate::DeserializeOptions::policy
+/// [`SerializeOptions::policy`]: crate::SerializeOptions::policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SerdePolicy {
+    /// Defines which Lua tables are converted to sequences.
+    ///
+    /// Default: **[`ArrayFormat::Border`]**
+    pub array_format: ArrayFormat,
+
+    /// If true, float keys with an exact integer representation (eg. `1.0`) are converted to
+    /// integers. Lua versions without an integer subtype (Lua 5.1, LuaJIT and Luau) store all
+    /// numeric keys as floats.
+    ///
+    /// Default: **true**
+    pub normalize_float_keys: bool,
+
+    /// If true, integer keys of Lua tables are passed as strings (eg. `"42"`), for formats
+    /// supporting only string keys.
+    ///
+    /// Default: **false**
+    pub stringify_integer_keys: bool,
+
+    /// If true, map keys which are canonical integers written as strings (eg. `"42"`, but not
+    /// `"042"`) are converted to integer keys in Lua tables. Reverts `stringify_integer_keys`.
+    ///
+    /// Default: **false**
+    pub parse_integer_keys: bool,
+}
+
+impl Default for SerdePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SerdePolicy {
+    /// Returns a new instance of [`SerdePolicy`] with default parameters.
+    pub const fn new() -> Self {
+        SerdePolicy {
+            array_format: ArrayFormat::Border,
+            normalize_float_keys: true,
+            stringify_integer_keys: false,
+            parse_integer_keys: false,
+        }
+    }
+
+    /// Returns a policy to preserve integer keys when round-tripping through formats supporting
+    /// only string keys (such as JSON).
+    ///
+    /// Uses [`ArrayFormat::Strict`] and enables `stringify_integer_keys` and
+    /// `parse_integer_keys`.
+    pub const fn string_keys() -> Self {
+        SerdePolicy {
+            array_format: ArrayFormat::Strict,
+            normalize_float_keys: true,
+            stringify_integer_keys: true,
+            parse_integer_keys: true,
+        }
+    }
+
+    /// Sets [`array_format`] option.
+    ///
+    /// [`array_format`]: #structfield.array_format
+    #[must_use]
+    pub const fn array_format(mut self, format: ArrayFormat) -> Self {
+        self.array_format = format;
+        self
+    }
+
+    /// Sets [`normalize_float_keys`] option.
+    ///
+    /// [`normalize_float_keys`]: #structfield.normalize_float_keys
+    #[must_use]
+    pub const fn normalize_float_keys(mut self, enabled: bool) -> Self {
+        self.normalize_float_keys = enabled;
+        self
+    }
+
+    /// Sets [`stringify_integer_keys`] option.
+    ///
+    /// [`stringify_integer_keys`]: #structfield.stringify_integer_keys
+    #[must_use]
+    pub const fn stringify_integer_keys(mut self, enabled: bool) -> Self {
+        self.stringify_integer_keys = enabled;
+        self
+    }
+
+    /// Sets [`parse_integer_keys`] option.
+    ///
+    /// [`parse_integer_keys`]: #structfield.parse_integer_keys
+    #[must_use]
+    pub const fn parse_integer_keys(mut self, enabled: bool) -> Self {
+        self.parse_integer_keys = enabled;
+        self
+    }
+
+    // Returns the sequence length if the table must be converted to a sequence
+    pub(crate) fn sequence_len(&self, table: &Table) -> Result<Option<usize>> {
+        let max_holes = match self.array_format {
+            ArrayFormat::Border => {
+                let len = table.raw_len() as usize;
+                return Ok((len > 0 || table.is_array()).then_some(len));
+            }
+            ArrayFormat::Strict => 0,
+            ArrayFormat::PreserveSparse { max_holes } => max_holes,
+        };
+
+        let (mut count, mut max_key) = (0, 0);
+        for key in table.clone().pairs::<Value, Value>() {
+            match integer_key(&key?.0) {
+                Some(i) if i > 0 => {
+                    count += 1;
+                    max_key = max_key.max(i as usize);
+                }
+                _ => return Ok(None),
+            }
+        }
+        if count == 0 {
+            return Ok(table.is_array().then_some(0));
+        }
+        Ok((max_key - count <= max_holes).then_some(max_key))
+    }
+
+    // Applies the policy to a table key passed to serde
+    pub(crate) fn map_key<'lua>(&self, key: Value<'lua>) -> Value<'lua> {
+        match key {
+            Value::Number(_) if self.normalize_float_keys => match integer_key(&key) {
+                Some(i) => Value::Integer(i),
+                None => key,
+            },
+            _ => key,
+        }
+    }
+
+    // Converts a string key received from serde to an integer key if enabled
+    pub(crate) fn parse_key<'lua>(&self, key: Value<'lua>) -> Value<'lua> {
+        if let (true, Value::String(s)) = (self.parse_integer_keys, &key) {
+            if let Some(i) = (s.to_str().ok())
+                .and_then(|s| s.parse::<Integer>().ok().filter(|i| i.to_string() == s))
+            {
+                return Value::Integer(i);
+            }
+        }
+        key
+    }
+}
+
+// Returns the integer value of a key if it has an exact integer representation
+fn integer_key(key: &Value) -> Option<Integer> {
+    match *key {
+        Value::Integer(i) => Some(i),
+        #[allow(clippy::unnecessary_cast)]
+        Value::Number(n)
+            if n.fract() == 0.0 && n >= Integer::MIN as f64 && n < -(Integer::MIN as f64) =>
+        {
+            Some(n as Integer)
+        }
+        _ => None,
+    }
+}
@@ -2,11 +2,12 @@ use std::os::raw::c_int;
 
 use serde::{ser, Serialize};
 
-use super::LuaSerdeExt;
+use super::{LuaSerdeExt, SerdePolicy};
 use crate::error::{Error, Result};
 use crate::lua::Lua;
 use crate::string::String;
 use crate::table::Table;
+use crate::types::{Integer, Number};
 use crate::value::{IntoLua, Value};
 
 /// A struct for serializing Rust values into Lua values.
@@ -45,6 +46,28 @@ pub struct Options {
     /// [`null`]: crate::LuaSerdeExt::null
     /// [`Nil`]: crate::Value::Nil
     pub serialize_unit_to_null: bool,
+
+    /// If true, maps whose keys are exactly the integers `1..=n` (eg. `BTreeMap<i64, T>`) are
+    /// serialized to a sequence table, with the [`array_metatable`] attached if
+    /// [`set_array_metatable`] is enabled. Otherwise they are serialized to a table with the
+    /// entries in its hash part.
+    ///
+    /// Default: **false**
+    ///
+    /// [`array_metatable`]: crate::LuaSerdeExt::array_metatable
+    /// [`set_array_metatable`]: #structfield.set_array_metatable
+    pub dense_integer_keys: bool,
+
+    /// If true, Rust integers (including integer `serde_json::Value::Number`s) are serialized to
+    /// Lua integers when they fit. Otherwise all numbers are serialized to Lua floats.
+    ///
+    /// Default: **true**
+    pub preserve_integers: bool,
+
+    /// Policy for converting map keys.
+    ///
+    /// Default: **[`SerdePolicy::new()`]**
+    pub policy: SerdePolicy,
 }
 
 impl Default for Options {
@@ -60,6 +83,9 @@ impl Options {
             set_array_metatable: true,
             serialize_none_to_null: true,
             serialize_unit_to_null: true,
+            dense_integer_keys: false,
+            preserve_integers: true,
+            policy: SerdePolicy::new(),
         }
     }
 
@@ -89,6 +115,33 @@ impl Options {
         self.serialize_unit_to_null = enabled;
         self
     }
+
+    /// Sets [`dense_integer_keys`] option.
+    ///
+    /// [`dense_integer_keys`]: #structfield.dense_integer_keys
+    #[must_use]
+    pub const fn dense_integer_keys(mut self, enabled: bool) -> Self {
+        self.dense_integer_keys = enabled;
+        self
+    }
+
+    /// Sets [`preserve_integers`] option.
+    ///
+    /// [`preserve_integers`]: #structfield.preserve_integers
+    #[must_use]
+    pub const fn preserve_integers(mut self, enabled: bool) -> Self {
+        self.preserve_integers = enabled;
+        self
+    }
+
+    /// Sets [`policy`] option.
+    ///
+    /// [`policy`]: #structfield.policy
+    #[must_use]
+    pub const fn policy(mut self, policy: SerdePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
 }
 
 impl<'lua> Serializer<'lua> {
@@ -112,6 +165,18 @@ macro_rules! lua_serialize_number {
     };
 }
 
+macro_rules! lua_serialize_integer {
+    ($name:ident, $t:ty) => {
+        #[inline]
+        fn $name(self, value: $t) -> Result<Value<'lua>> {
+            if !self.options.preserve_integers {
+                return Ok(Value::Number(value as Number));
+            }
+            value.into_lua(self.lua)
+        }
+    };
+}
+
 impl<'lua> ser::Serializer for Serializer<'lua> {
     type Ok = Value<'lua>;
     type Error = Error;
@@ -131,16 +196,16 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
         Ok(Value::Boolean(value))
     }
 
-    lua_serialize_number!(serialize_i8, i8);
-    lua_serialize_number!(serialize_u8, u8);
-    lua_serialize_number!(serialize_i16, i16);
-    lua_serialize_number!(serialize_u16, u16);
-    lua_serialize_number!(serialize_i32, i32);
-    lua_serialize_number!(serialize_u32, u32);
-    lua_serialize_number!(serialize_i64, i64);
-    lua_serialize_number!(serialize_u64, u64);
-    lua_serialize_number!(serialize_i128, i128);
-    lua_serialize_number!(serialize_u128, u128);
+    lua_serialize_integer!(serialize_i8, i8);
+    lua_serialize_integer!(serialize_u8, u8);
+    lua_serialize_integer!(serialize_i16, i16);
+    lua_serialize_integer!(serialize_u16, u16);
+    lua_serialize_integer!(serialize_i32, i32);
+    lua_serialize_integer!(serialize_u32, u32);
+    lua_serialize_integer!(serialize_i64, i64);
+    lua_serialize_integer!(serialize_u64, u64);
+    lua_serialize_integer!(serialize_i128, i128);
+    lua_serialize_integer!(serialize_u128, u128);
 
     lua_serialize_number!(serialize_f32, f32);
     lua_serialize_number!(serialize_f64, f64);
@@ -226,6 +291,7 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
     {
         let table = self.lua.create_table()?;
         let variant = self.lua.create_string(variant)?;
+        let _depth = self.lua.enter_conversion()?;
         let value = self.lua.to_value_with(value, self.options)?;
         table.raw_set(variant, value)?;
         Ok(Value::Table(table))
@@ -281,6 +347,9 @@ impl<'lua> ser::Serializer for Serializer<'lua> {
         Ok(SerializeMap {
             key: None,
             table: self.lua.create_table_with_capacity(0, len)?,
+            len: 0,
+            max_key: 0,
+            dense: self.options.dense_integer_keys,
             options: self.options,
         })
     }
@@ -348,6 +417,7 @@ impl<'lua> ser::SerializeSeq for SerializeSeq<'lua> {
     where
         T: Serialize + ?Sized,
     {
+        let _depth = self.lua.enter_conversion()?;
         let value = self.lua.to_value_with(value, self.options)?;
         let table = self.table.as_ref().unwrap();
         table.raw_seti(self.next + 1, value)?;
@@ -420,6 +490,7 @@ impl<'lua> ser::SerializeTupleVariant for SerializeTupleVariant<'lua> {
         T: Serialize + ?Sized,
     {
         let lua = self.table.0.lua;
+        let _depth = lua.enter_conversion()?;
         self.table.raw_push(lua.to_value_with(value, self.options)?)
     }
 
@@ -435,6 +506,11 @@ impl<'lua> ser::SerializeTupleVariant for SerializeTupleVariant<'lua> {
 pub struct SerializeMap<'lua> {
     table: Table<'lua>,
     key: Option<Value<'lua>>,
+    // Number of entries and the largest key, to detect maps with keys `1..=len`
+    len: usize,
+    max_key: Integer,
+    // Whether all keys so far are positive integers (and `dense_integer_keys` is enabled)
+    dense: bool,
     options: Options,
 }
 
@@ -447,7 +523,15 @@ impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
         T: Serialize + ?Sized,
     {
         let lua = self.table.0.lua;
-        self.key = Some(lua.to_value_with(key, self.options)?);
+        let key = lua.to_value_with(key, self.options)?;
+        let key = self.options.policy.parse_key(key);
+        if self.dense {
+            match key {
+                Value::Integer(i) if i > 0 => self.max_key = self.max_key.max(i),
+                _ => self.dense = false,
+            }
+        }
+        self.key = Some(key);
         Ok(())
     }
 
@@ -460,12 +544,26 @@ impl<'lua> ser::SerializeMap for SerializeMap<'lua> {
             self.key.take(),
             "serialize_value called before serialize_key"
         );
+        let _depth = lua.enter_conversion()?;
         let value = lua.to_value_with(value, self.options)?;
+        self.len += 1;
         self.table.raw_set(key, value)
     }
 
     fn end(self) -> Result<Value<'lua>> {
-        Ok(Value::Table(self.table))
+        // Keys are distinct, so `len` positive keys not greater than `len` are exactly `1..=len`
+        if !self.dense || self.len == 0 || self.max_key as usize != self.len {
+            return Ok(Value::Table(self.table));
+        }
+        let lua = self.table.0.lua;
+        let table = lua.create_table_with_capacity(self.len as c_int, 0)?;
+        for i in 1..=self.len {
+            table.raw_seti(i, self.table.raw_get::<_, Value>(i)?)?;
+        }
+        if self.options.set_array_metatable {
+            table.set_metatable(Some(lua.array_metatable()));
+        }
+        Ok(Value::Table(table))
     }
 }
 
@@ -502,6 +600,7 @@ impl<'lua> ser::SerializeStructVariant for SerializeStructVariant<'lua> {
         T: Serialize + ?Sized,
     {
         let lua = self.table.0.lua;
+        let _depth = lua.enter_conversion()?;
         self.table
             .raw_set(key, lua.to_value_with(value, self.options)?)?;
         Ok(())
@@ -0,0 +1,325 @@
+use std::fmt;
+use std::os::raw::c_void;
+use std::string::String as StdString;
+use std::sync::Arc;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::error::{Error, Result};
+#[cfg(any(
+    feature = "lua54",
+    feature = "lua53",
+    feature = "lua52",
+    feature = "luajit52",
+    feature = "luau"
+))]
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::{Integer, Number};
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods, UserDataRef};
+use crate::value::{Nil, Value};
+
+/// Immutable tree of Lua data shared between multiple Lua states.
+///
+/// A segment is created once from a Lua table ([`SharedDataSegment::from_table`]) and stored on
+/// the Rust side. It can then be attached to any number of Lua states using
+/// [`Lua::attach_shared_data`], which exposes the data through lightweight read-only proxies
+/// instead of copying it into every state. This is useful for large static data (eg. item or
+/// level definitions in games) used by a pool of Lua states.
+///
+/// Nested tables are exposed as proxies created on access, strings are created in the accessing
+/// state when read. Proxies support indexing, the length operator, `pairs` (on Lua 5.2+),
+/// generalized iteration (on Luau) and equality. Any attempt to modify them raises an error.
+///
+/// Only booleans, numbers, strings and tables can be shared. Table keys must be booleans,
+/// integers or strings.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, SharedDataSegment, Table};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let items: Table = lua.load(r#"{sword = {damage = 10}, shield = {armor = 5}}"#).eval()?;
+/// let segment = SharedDataSegment::from_table(&items)?;
+///
+/// let (lua1, lua2) = (Lua::new(), Lua::new());
+/// lua1.globals().set("items", lua1.attach_shared_data(&segment)?)?;
+/// lua2.globals().set("items", lua2.attach_shared_data(&segment)?)?;
+///
+/// assert_eq!(lua1.load("items.sword.damage").eval::<i32>()?, 10);
+/// assert_eq!(lua2.load("items.shield.armor").eval::<i32>()?, 5);
+/// assert!(lua2.load("items.sword.damage = 100").exec().is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Lua::attach_shared_data`]: crate::Lua::attach_shared_data
+#[derive(Clone)]
+pub struct SharedDataSegment {
+    root: Arc<SharedTable>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum SharedKey {
+    Boolean(bool),
+    Integer(Integer),
+    String(Box<[u8]>),
+}
+
+#[derive(Clone)]
+enum SharedValue {
+    Boolean(bool),
+    Integer(Integer),
+    Number(Number),
+    String(Box<[u8]>),
+    Table(Arc<SharedTable>),
+}
+
+#[derive(Default)]
+struct SharedTable {
+    // Values with keys `1..=n`
+    array: Vec<SharedValue>,
+    // Other entries, in a stable order used for iteration
+    entries: Vec<(SharedKey, SharedValue)>,
+    index: FxHashMap<SharedKey, usize>,
+}
+
+impl SharedDataSegment {
+    /// Creates a new segment from a deep copy of the given table.
+    ///
+    /// Tables referenced multiple times are copied once. Returns an error if the table is
+    /// recursive or contains values that cannot be shared (eg. functions or userdata).
+    pub fn from_table(table: &Table) -> Result<Self> {
+        let mut builder = SegmentBuilder::default();
+        let root = builder.copy_table(table)?;
+        Ok(SharedDataSegment { root })
+    }
+}
+
+impl fmt::Debug for SharedDataSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedDataSegment")
+            .field("array_len", &self.root.array.len())
+            .field("entries", &self.root.entries.len())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct SegmentBuilder {
+    copied: FxHashMap<*const c_void, Arc<SharedTable>>,
+    in_progress: FxHashSet<*const c_void>,
+}
+
+impl SegmentBuilder {
+    fn copy_table(&mut self, table: &Table) -> Result<Arc<SharedTable>> {
+        let ptr = table.to_pointer();
+        if let Some(copy) = self.copied.get(&ptr) {
+            return Ok(copy.clone());
+        }
+        if !self.in_progress.insert(ptr) {
+            return Err(share_error("table", "recursive tables cannot be shared"));
+        }
+
+        let mut entries = FxHashMap::default();
+        for pair in table.clone().pairs::<Value, Value>() {
+            let (key, value) = pair?;
+            let key = match shared_key(&key) {
+                Some(key) => key,
+                None => {
+                    let msg = "table keys must be booleans, integers or strings";
+                    return Err(share_error(key.type_name(), msg));
+                }
+            };
+            entries.insert(key, self.copy_value(value)?);
+        }
+
+        let mut shared = SharedTable::default();
+        while let Some(value) =
+            entries.remove(&SharedKey::Integer(shared.array.len() as Integer + 1))
+        {
+            shared.array.push(value);
+        }
+        shared.entries = entries.into_iter().collect();
+        shared.index = (shared.entries.iter().enumerate())
+            .map(|(i, (key, _))| (key.clone(), i))
+            .collect();
+
+        let shared = Arc::new(shared);
+        self.in_progress.remove(&ptr);
+        self.copied.insert(ptr, shared.clone());
+        Ok(shared)
+    }
+
+    fn copy_value(&mut self, value: Value) -> Result<SharedValue> {
+        Ok(match value {
+            Value::Boolean(b) => SharedValue::Boolean(b),
+            Value::Integer(i) => SharedValue::Integer(i),
+            Value::Number(n) => SharedValue::Number(n),
+            Value::String(s) => SharedValue::String(s.as_bytes().into()),
+            Value::Table(t) => SharedValue::Table(self.copy_table(&t)?),
+            value => {
+                let msg = format!("{} values cannot be shared", value.type_name());
+                return Err(share_error(value.type_name(), msg));
+            }
+        })
+    }
+}
+
+fn share_error(from: &'static str, message: impl Into<StdString>) -> Error {
+    Error::FromLuaConversionError {
+        from,
+        to: "SharedDataSegment",
+        message: Some(message.into()),
+    }
+}
+
+fn shared_key(key: &Value) -> Option<SharedKey> {
+    match *key {
+        Value::Boolean(b) => Some(SharedKey::Boolean(b)),
+        Value::Integer(i) => Some(SharedKey::Integer(i)),
+        Value::Number(n)
+            if n.fract() == 0.0 && n >= Integer::MIN as Number && n < Integer::MAX as Number =>
+        {
+            Some(SharedKey::Integer(n as Integer))
+        }
+        Value::String(ref s) => Some(SharedKey::String(s.as_bytes().into())),
+        _ => None,
+    }
+}
+
+impl SharedTable {
+    fn get(&self, key: &SharedKey) -> Option<&SharedValue> {
+        if let SharedKey::Integer(i) = *key {
+            if i >= 1 && i <= self.array.len() as Integer {
+                return Some(&self.array[i as usize - 1]);
+            }
+        }
+        self.index.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    // Returns the entry following `key` in the iteration order (array part first)
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luajit52",
+        feature = "luau"
+    ))]
+    fn next(&self, key: Option<&SharedKey>) -> Option<(SharedKey, &SharedValue)> {
+        let pos = match key {
+            None => 0,
+            Some(&SharedKey::Integer(i)) if i >= 1 && i <= self.array.len() as Integer => {
+                i as usize
+            }
+            Some(key) => self.array.len() + self.index.get(key)? + 1,
+        };
+        if pos < self.array.len() {
+            return Some((SharedKey::Integer(pos as Integer + 1), &self.array[pos]));
+        }
+        let (key, value) = self.entries.get(pos - self.array.len())?;
+        Some((key.clone(), value))
+    }
+}
+
+pub(crate) fn attach_shared_data<'lua>(
+    lua: &'lua Lua,
+    segment: &SharedDataSegment,
+) -> Result<AnyUserData<'lua>> {
+    lua.create_userdata(SharedTableProxy(segment.root.clone()))
+}
+
+// Read-only view of a shared table in a Lua state
+struct SharedTableProxy(Arc<SharedTable>);
+
+impl SharedTableProxy {
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luajit52",
+        feature = "luau"
+    ))]
+    fn push_key<'lua>(lua: &'lua Lua, key: SharedKey) -> Result<Value<'lua>> {
+        Ok(match key {
+            SharedKey::Boolean(b) => Value::Boolean(b),
+            SharedKey::Integer(i) => Value::Integer(i),
+            SharedKey::String(s) => Value::String(lua.create_string(&s)?),
+        })
+    }
+
+    fn push_value<'lua>(lua: &'lua Lua, value: &SharedValue) -> Result<Value<'lua>> {
+        Ok(match *value {
+            SharedValue::Boolean(b) => Value::Boolean(b),
+            SharedValue::Integer(i) => Value::Integer(i),
+            SharedValue::Number(n) => Value::Number(n),
+            SharedValue::String(ref s) => Value::String(lua.create_string(s)?),
+            SharedValue::Table(ref t) => {
+                Value::UserData(lua.create_userdata(SharedTableProxy(t.clone()))?)
+            }
+        })
+    }
+
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luajit52",
+        feature = "luau"
+    ))]
+    fn create_iterator<'lua>(lua: &'lua Lua) -> Result<Function<'lua>> {
+        lua.create_function(|lua, (this, key): (UserDataRef<Self>, Value)| {
+            let key = match key {
+                Value::Nil => None,
+                key => Some(
+                    shared_key(&key)
+                        .ok_or_else(|| Error::RuntimeError("invalid key to 'next'".to_string()))?,
+                ),
+            };
+            match this.0.next(key.as_ref()) {
+                Some((key, value)) => {
+                    Ok((Self::push_key(lua, key)?, Self::push_value(lua, value)?))
+                }
+                None => Ok((Nil, Nil)),
+            }
+        })
+    }
+}
+
+impl UserData for SharedTableProxy {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(
+            MetaMethod::Index,
+            |lua, this, key: Value| match shared_key(&key).and_then(|key| this.0.get(&key)) {
+                Some(value) => Self::push_value(lua, value),
+                None => Ok(Nil),
+            },
+        );
+        methods.add_meta_method(MetaMethod::NewIndex, |_, _, _: (Value, Value)| {
+            Err::<(), _>(Error::RuntimeError(
+                "attempt to modify shared data".to_string(),
+            ))
+        });
+        methods.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.0.array.len()));
+        methods.add_meta_function(
+            MetaMethod::Eq,
+            |_, (a, b): (UserDataRef<Self>, UserDataRef<Self>)| Ok(Arc::ptr_eq(&a.0, &b.0)),
+        );
+
+        #[cfg(any(
+            feature = "lua54",
+            feature = "lua53",
+            feature = "lua52",
+            feature = "luajit52"
+        ))]
+        methods.add_meta_function(MetaMethod::Pairs, |lua, this: AnyUserData| {
+            Ok((Self::create_iterator(lua)?, this, Nil))
+        });
+        #[cfg(feature = "luau")]
+        methods.add_meta_function(MetaMethod::Iter, |lua, this: AnyUserData| {
+            Ok((Self::create_iterator(lua)?, this, Nil))
+        });
+    }
+}
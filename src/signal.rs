@@ -0,0 +1,260 @@
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+use crate::value::{FromLua, IntoLua, IntoLuaMulti, MultiValue, Value};
+
+/// A signal (in the signals and slots sense) shared between Rust and Lua.
+///
+/// Handlers (Lua functions) connected to the signal are called in connection order when the
+/// signal is emitted. The signal is a userdata, so it can be passed to Lua where it provides
+/// the same operations as methods:
+///
+/// - `signal:connect(handler, [opts])` returns a connection id. If `opts.weak` is true, the
+///   handler is referenced weakly and disconnected automatically once collected.
+/// - `signal:disconnect(id)` returns `true` if the connection existed.
+/// - `signal:emit(...)` calls all handlers with the given arguments.
+/// - `signal:emit_async(...)` (requires `feature = "async"`) awaits handlers calling async
+///   functions.
+///
+/// `A` is the type of arguments passed by [`Signal::emit`].
+///
+/// Created by [`Lua::create_signal`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let on_damage = lua.create_signal::<(String, u32)>()?;
+/// lua.globals().set("on_damage", on_damage.clone())?;
+/// lua.load(r#"
+///     health = 100
+///     on_damage:connect(function(source, amount) health = health - amount end)
+/// "#).exec()?;
+///
+/// on_damage.emit(("trap".to_string(), 30))?;
+/// assert_eq!(lua.globals().get::<_, u32>("health")?, 70);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Signal<'lua, A> {
+    ud: AnyUserData<'lua>,
+    _phantom: PhantomData<fn(A)>,
+}
+
+impl<'lua, A> Clone for Signal<'lua, A> {
+    fn clone(&self) -> Self {
+        Signal {
+            ud: self.ud.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<'lua, A: IntoLuaMulti<'lua>> Signal<'lua, A> {
+    pub(crate) fn new(lua: &'lua Lua) -> Result<Self> {
+        let ud = lua.create_userdata(SignalData {
+            slots: RefCell::new(Vec::new()),
+            next_id: Cell::new(1),
+        })?;
+        let weak = lua.create_table()?;
+        let weak_mt = lua.create_table_from([("__mode", "v")])?;
+        weak.set_metatable(Some(weak_mt));
+        ud.set_user_value(lua.create_sequence_from([lua.create_table()?, weak])?)?;
+        Ok(Signal {
+            ud,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Connects a handler to the signal.
+    ///
+    /// Returns the connection id which can be used to disconnect the handler.
+    pub fn connect(&self, handler: Function<'lua>) -> Result<u64> {
+        connect(&self.ud, handler, false)
+    }
+
+    /// Connects a handler to the signal, holding only a weak reference to it.
+    ///
+    /// The handler is disconnected automatically once it's garbage collected.
+    pub fn connect_weak(&self, handler: Function<'lua>) -> Result<u64> {
+        connect(&self.ud, handler, true)
+    }
+
+    /// Disconnects a handler. Returns `true` if the connection existed.
+    pub fn disconnect(&self, id: u64) -> Result<bool> {
+        disconnect(&self.ud, id)
+    }
+
+    /// Returns the number of connected handlers.
+    pub fn len(&self) -> Result<usize> {
+        Ok(handlers(&self.ud)?.len())
+    }
+
+    /// Returns `true` if there are no connected handlers.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Calls all connected handlers with the given arguments.
+    ///
+    /// Stops at (and returns) the first error raised by a handler.
+    pub fn emit(&self, args: A) -> Result<()> {
+        emit(&self.ud, args.into_lua_multi(self.ud.0.lua)?)
+    }
+
+    /// Asynchronously calls all connected handlers with the given arguments.
+    ///
+    /// Handlers are called one by one, each as an async Lua function (see
+    /// [`Function::call_async`]).
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    pub async fn emit_async(&self, args: A) -> Result<()> {
+        emit_async(&self.ud, args.into_lua_multi(self.ud.0.lua)?).await
+    }
+
+    /// Returns the underlying userdata.
+    pub fn as_userdata(&self) -> &AnyUserData<'lua> {
+        &self.ud
+    }
+}
+
+impl<'lua, A> IntoLua<'lua> for Signal<'lua, A> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::UserData(self.ud))
+    }
+}
+
+impl<'lua, A> FromLua<'lua> for Signal<'lua, A> {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::UserData(ud) if ud.is::<SignalData>() => Ok(Signal {
+                ud,
+                _phantom: PhantomData,
+            }),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "Signal",
+                message: None,
+            }),
+        }
+    }
+}
+
+struct SignalData {
+    // Connections in order, with a flag whether the handler is held weakly
+    slots: RefCell<Vec<(u64, bool)>>,
+    next_id: Cell<u64>,
+}
+
+impl UserData for SignalData {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function(
+            "connect",
+            |_, (ud, handler, opts): (AnyUserData, Function, Option<Table>)| {
+                let weak = match opts {
+                    Some(opts) => opts.get::<_, Option<bool>>("weak")?.unwrap_or(false),
+                    None => false,
+                };
+                connect(&ud, handler, weak)
+            },
+        );
+        methods.add_function("disconnect", |_, (ud, id): (AnyUserData, u64)| {
+            disconnect(&ud, id)
+        });
+        methods.add_function("emit", |_, (ud, args): (AnyUserData, MultiValue)| {
+            emit(&ud, args)
+        });
+        #[cfg(feature = "async")]
+        methods.add_async_function(
+            "emit_async",
+            |_, (ud, args): (AnyUserData, MultiValue)| async move { emit_async(&ud, args).await },
+        );
+        methods.add_meta_function(MetaMethod::Len, |_, ud: AnyUserData| {
+            Ok(handlers(&ud)?.len())
+        });
+    }
+}
+
+// Returns the tables holding strong and weak handlers
+fn handler_tables<'lua>(ud: &AnyUserData<'lua>) -> Result<(Table<'lua>, Table<'lua>)> {
+    let tables = ud.get_user_value::<Table>()?;
+    Ok((tables.raw_get(1)?, tables.raw_get(2)?))
+}
+
+fn connect<'lua>(ud: &AnyUserData<'lua>, handler: Function<'lua>, weak: bool) -> Result<u64> {
+    let (strong_handlers, weak_handlers) = handler_tables(ud)?;
+    let data = ud.borrow::<SignalData>()?;
+    let id = data.next_id.get();
+    data.next_id.set(id + 1);
+    match weak {
+        true => weak_handlers.raw_set(id, handler)?,
+        false => strong_handlers.raw_set(id, handler)?,
+    }
+    data.slots.borrow_mut().push((id, weak));
+    Ok(id)
+}
+
+fn disconnect(ud: &AnyUserData, id: u64) -> Result<bool> {
+    let (strong_handlers, weak_handlers) = handler_tables(ud)?;
+    let data = ud.borrow::<SignalData>()?;
+    let mut slots = data.slots.borrow_mut();
+    match slots.iter().position(|&(slot_id, _)| slot_id == id) {
+        Some(pos) => {
+            let (_, weak) = slots.remove(pos);
+            match weak {
+                true => weak_handlers.raw_set(id, Value::Nil)?,
+                false => strong_handlers.raw_set(id, Value::Nil)?,
+            }
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// Returns a snapshot of connected handlers (dropping collected weak ones)
+fn handlers<'lua>(ud: &AnyUserData<'lua>) -> Result<Vec<Function<'lua>>> {
+    let (strong_handlers, weak_handlers) = handler_tables(ud)?;
+    let data = ud.borrow::<SignalData>()?;
+    let mut slots = data.slots.borrow_mut();
+    let mut handlers = Vec::with_capacity(slots.len());
+    let mut collected = Vec::new();
+    for &(id, weak) in slots.iter() {
+        let handler = match weak {
+            true => weak_handlers.raw_get::<_, Option<Function>>(id)?,
+            false => strong_handlers.raw_get::<_, Option<Function>>(id)?,
+        };
+        match handler {
+            Some(handler) => handlers.push(handler),
+            None => collected.push(id),
+        }
+    }
+    if !collected.is_empty() {
+        slots.retain(|(id, _)| !collected.contains(id));
+    }
+    Ok(handlers)
+}
+
+fn emit<'lua>(ud: &AnyUserData<'lua>, args: MultiValue<'lua>) -> Result<()> {
+    for handler in handlers(ud)? {
+        handler.call::<_, ()>(args.clone())?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+async fn emit_async<'lua>(ud: &AnyUserData<'lua>, args: MultiValue<'lua>) -> Result<()> {
+    for handler in handlers(ud)? {
+        handler.call_async::<_, ()>(args.clone()).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::os::raw::{c_char, c_int, c_void};
+use std::string::String as StdString;
+use std::sync::Arc;
+
+use rustc_hash::FxHashSet;
+
+use crate::types::Integer;
+use crate::util;
+
+/// Maps lines of a generated Lua chunk to positions in its original source.
+///
+/// Attached to a chunk using [`Chunk::set_source_map`]. Errors and tracebacks raised by code of
+/// the chunk report positions in the original source, and [`Debug::mapped_location`] returns the
+/// original position of a running function.
+///
+/// Lines without a mapping are mapped to the same line as the closest preceding mapped line (so
+/// a single mapping covers all Lua lines generated from one line of the original source).
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result, SourceMap};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// // Lua code generated from lines 10 and 11 of `rules.dsl`
+/// let source = "local x = 1\nerror('boom')";
+/// let map = SourceMap::new("rules.dsl").map_line(1, 10).map_line(2, 11);
+/// let err = lua.load(source).set_name("=gen").set_source_map(map).exec().unwrap_err();
+/// assert!(err.to_string().starts_with("runtime error: rules.dsl:11: boom"));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Chunk::set_source_map`]: crate::Chunk::set_source_map
+/// [`Debug::mapped_location`]: crate::Debug::mapped_location
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceMap {
+    source: StdString,
+    lines: BTreeMap<usize, usize>,
+}
+
+/// Position in the original source of a chunk, see [`SourceMap`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedLocation {
+    /// Name of the original source.
+    pub source: StdString,
+    /// Line in the original source.
+    pub line: usize,
+}
+
+impl SourceMap {
+    /// Creates an empty source map for the original source with the given name.
+    pub fn new(source: impl Into<StdString>) -> Self {
+        SourceMap {
+            source: source.into(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a source map from original line numbers of each generated line (starting from 1).
+    pub fn from_lines(
+        source: impl Into<StdString>,
+        lines: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        SourceMap {
+            source: source.into(),
+            lines: (1..).zip(lines).collect(),
+        }
+    }
+
+    /// Maps a generated line to a line of the original source.
+    #[must_use]
+    pub fn map_line(mut self, generated: usize, original: usize) -> Self {
+        self.lines.insert(generated, original);
+        self
+    }
+
+    /// Returns the name of the original source.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the original line of a generated line, `None` if the line precedes all mappings.
+    pub fn lookup(&self, line: usize) -> Option<usize> {
+        let (_, &original) = self.lines.range(..=line).next_back()?;
+        Some(original)
+    }
+
+    pub(crate) fn location(&self, line: usize) -> Option<MappedLocation> {
+        Some(MappedLocation {
+            source: self.source.clone(),
+            line: self.lookup(line)?,
+        })
+    }
+}
+
+// Maximum number of chunks with attached data kept, older ones are evicted first
+const MAX_CHUNKS: usize = 256;
+
+// Data attached to loaded chunks, by chunk source name. Error messages only have the short
+// source (truncated by Lua), so chunks sharing it are told apart in text by load order: the most
+// recently loaded one is used.
+pub(crate) struct ChunkMap<T> {
+    entries: VecDeque<(StdString, StdString, T)>,
+}
+
+impl<T> Default for ChunkMap<T> {
+    fn default() -> Self {
+        ChunkMap {
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> ChunkMap<T> {
+    // Attaches data to a chunk, replacing data of a previously loaded chunk with the same source
+    pub(crate) fn insert(&mut self, source: StdString, short_src: StdString, value: T) {
+        self.entries.retain(|(s, ..)| *s != source);
+        if self.entries.len() >= MAX_CHUNKS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((source, short_src, value));
+    }
+
+    pub(crate) fn get(&self, source: &str) -> Option<&T> {
+        let mut entries = self.entries.iter();
+        entries.find(|(s, ..)| s == source).map(|(.., value)| value)
+    }
+
+    // Returns data of the most recently loaded chunk with the given short source
+    pub(crate) fn get_latest(&self, short_src: &str) -> Option<&T> {
+        let mut entries = self.entries.iter().rev();
+        entries
+            .find(|(_, s, _)| s == short_src)
+            .map(|(.., value)| value)
+    }
+
+    pub(crate) fn remove(&mut self, source: &str) {
+        self.entries.retain(|(s, ..)| s != source);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Returns the most recently loaded chunk of each short source
+    fn latest(&self) -> impl Iterator<Item = (&str, &T)> {
+        let mut seen = FxHashSet::default();
+        (self.entries.iter().rev())
+            .filter(move |(_, short_src, _)| seen.insert(short_src.as_str()))
+            .map(|(_, short_src, value)| (short_src.as_str(), value))
+    }
+}
+
+pub(crate) type SourceMaps = ChunkMap<Arc<SourceMap>>;
+
+// Replaces generated positions (`chunk:line`) in an error message or traceback with positions in
+// the original sources. Traceback frames keep the generated position at the end.
+pub(crate) fn apply_source_maps(maps: &SourceMaps, text: &str) -> StdString {
+    let mut result = StdString::with_capacity(text.len());
+    let mut in_traceback = false;
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            result.push('\n');
+        }
+        in_traceback = in_traceback || line.starts_with("stack traceback:");
+        let (mapped, raw) = map_line(maps, line);
+        result.push_str(&mapped);
+        if let (true, Some(raw)) = (in_traceback && line.starts_with('\t'), raw) {
+            let _ = write!(result, " (at {raw})");
+        }
+    }
+    result
+}
+
+// Maps positions in a single line, returning the first replaced generated position.
+// Only whole `<chunk>:<line>:` tokens are replaced, so chunk names inside other words (eg. `main`
+// in `domain:80:`) are left alone.
+fn map_line(maps: &SourceMaps, line: &str) -> (StdString, Option<StdString>) {
+    let mut line = line.to_string();
+    let mut first_raw = None;
+    for (short_src, map) in maps.latest() {
+        let mut pos = 0;
+        while let Some(found) = line[pos..].find(short_src) {
+            let start = pos + found;
+            let digits_start = start + short_src.len() + 1;
+            let digits_len = match line[start + short_src.len()..].strip_prefix(':') {
+                Some(rest) => rest.bytes().take_while(u8::is_ascii_digit).count(),
+                None => 0,
+            };
+            let end = digits_start + digits_len;
+            let prev = line[..start].chars().next_back();
+            let at_boundary = digits_len > 0
+                && !matches!(prev, Some(c) if !is_separator(c))
+                && line[end..].starts_with(':');
+            let mapped = at_boundary
+                .then(|| line[digits_start..end].parse().ok())
+                .flatten()
+                .and_then(|n| map.lookup(n));
+            match mapped {
+                Some(original) => {
+                    let replacement = format!("{}:{original}", map.source);
+                    if first_raw.is_none() {
+                        first_raw = Some(line[start..end].to_string());
+                    }
+                    line.replace_range(start..end, &replacement);
+                    pos = start + replacement.len();
+                }
+                None => pos = start + short_src.len(),
+            }
+        }
+    }
+    (line, first_raw)
+}
+
+// Returns true if the character cannot be a part of a chunk name preceding a position
+fn is_separator(c: char) -> bool {
+    !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '/' | '\\' | ']' | '"'))
+}
+
+// Replaces the traceback string on top of the stack with the result of `rewrite`.
+//
+// Called from error handlers, so Lua errors must not unwind through live Rust values: the new
+// traceback is pushed in a protected call and the original one is kept if that fails.
+pub(crate) unsafe fn rewrite_traceback(
+    state: *mut ffi::lua_State,
+    rewrite: impl FnOnce(StdString) -> StdString,
+) {
+    unsafe extern "C" fn push_traceback(state: *mut ffi::lua_State) -> c_int {
+        let data = ffi::lua_touserdata(state, 1) as *const c_char;
+        let len = ffi::lua_tointeger(state, 2) as usize;
+        ffi::lua_pushlstring(state, data, len);
+        1
+    }
+
+    if ffi::lua_checkstack(state, 3) == 0 {
+        return;
+    }
+    // Can raise a memory error, so must be done before allocating the new traceback
+    ffi::lua_pushcfunction(state, push_traceback);
+
+    let traceback = rewrite(util::to_string(state, -2));
+    ffi::lua_pushlightuserdata(state, traceback.as_ptr() as *mut c_void);
+    ffi::lua_pushinteger(state, traceback.len() as Integer);
+    if ffi::lua_pcall(state, 2, 1, 0) == ffi::LUA_OK {
+        ffi::lua_replace(state, -2);
+    } else {
+        ffi::lua_pop(state, 1);
+    }
+}
@@ -71,6 +71,40 @@ impl StdLib {
     pub fn contains(self, lib: Self) -> bool {
         (self & lib).0 != 0
     }
+
+    // Names of the libraries in the set available for the current Lua version
+    pub(crate) fn names(self) -> Vec<&'static str> {
+        let libs = [
+            #[cfg(any(
+                feature = "lua54",
+                feature = "lua53",
+                feature = "lua52",
+                feature = "luau"
+            ))]
+            (StdLib::COROUTINE, ffi::LUA_COLIBNAME),
+            (StdLib::TABLE, ffi::LUA_TABLIBNAME),
+            #[cfg(not(feature = "luau"))]
+            (StdLib::IO, ffi::LUA_IOLIBNAME),
+            (StdLib::OS, ffi::LUA_OSLIBNAME),
+            (StdLib::STRING, ffi::LUA_STRLIBNAME),
+            #[cfg(any(feature = "lua54", feature = "lua53", feature = "luau"))]
+            (StdLib::UTF8, ffi::LUA_UTF8LIBNAME),
+            #[cfg(any(feature = "lua52", feature = "luajit", feature = "luau"))]
+            (StdLib::BIT, ffi::LUA_BITLIBNAME),
+            (StdLib::MATH, ffi::LUA_MATHLIBNAME),
+            #[cfg(not(feature = "luau"))]
+            (StdLib::PACKAGE, ffi::LUA_LOADLIBNAME),
+            #[cfg(feature = "luajit")]
+            (StdLib::JIT, ffi::LUA_JITLIBNAME),
+            #[cfg(feature = "luajit")]
+            (StdLib::FFI, ffi::LUA_FFILIBNAME),
+            (StdLib::DEBUG, ffi::LUA_DBLIBNAME),
+        ];
+        (libs.into_iter())
+            .filter(|&(lib, _)| self.contains(lib))
+            .map(|(_, name)| name)
+            .collect()
+    }
 }
 
 impl BitAnd for StdLib {
@@ -12,6 +12,7 @@ use {
 
 use crate::error::{Error, Result};
 use crate::types::LuaRef;
+use crate::value::Value;
 
 /// Handle to an internal Lua string.
 ///
@@ -61,10 +62,13 @@ impl<'lua> String<'lua> {
     /// ```
     #[inline]
     pub fn to_str(&self) -> Result<&str> {
-        str::from_utf8(self.as_bytes()).map_err(|e| Error::FromLuaConversionError {
-            from: "string",
-            to: "&str",
-            message: Some(e.to_string()),
+        str::from_utf8(self.as_bytes()).map_err(|e| {
+            Error::FromLuaConversionError {
+                from: "string",
+                to: "&str",
+                message: Some(e.to_string()),
+            }
+            .with_value_preview(Value::String(self.clone()).preview())
         })
     }
 
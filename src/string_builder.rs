@@ -0,0 +1,197 @@
+use std::mem;
+use std::ptr::NonNull;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::memory::MemoryState;
+use crate::multi::Variadic;
+use crate::string::String;
+use crate::userdata::{AnyUserData, MetaMethod, UserData, UserDataMethods};
+use crate::value::{FromLua, IntoLua, Value};
+
+/// A mutable string buffer shared between Rust and Lua.
+///
+/// Appending to the builder is amortized O(1), which avoids quadratic cost of building large
+/// strings (eg. logs or reports) using repeated concatenation of immutable Lua strings.
+/// The builder is a userdata, so it can be passed to Lua where it provides the following methods:
+///
+/// - `builder:append(...)` appends strings (or numbers) and returns the builder for chaining.
+/// - `builder:len()` (and `#builder`) returns the length of the buffer in bytes.
+/// - `builder:build()` (and `tostring(builder)`) returns the buffer contents as a string.
+/// - `builder:clear()` empties the buffer.
+///
+/// The buffer memory counts towards the [memory limit] of the Lua state, appending beyond it
+/// fails with [`Error::MemoryLimitExceeded`].
+///
+/// Created by [`Lua::create_string_builder`].
+///
+/// [memory limit]: crate::Lua::set_memory_limit
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let report = lua.create_string_builder()?;
+/// lua.globals().set("report", report.clone())?;
+/// lua.load(r#"
+///     for i = 1, 3 do
+///         report:append("line ", i, "\n")
+///     end
+/// "#).exec()?;
+///
+/// assert_eq!(report.take()?, b"line 1\nline 2\nline 3\n");
+/// assert!(report.is_empty()?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct StringBuilder<'lua>(AnyUserData<'lua>);
+
+impl<'lua> StringBuilder<'lua> {
+    pub(crate) fn new(lua: &'lua Lua) -> Result<Self> {
+        Ok(StringBuilder(lua.create_userdata(StringBuilderData {
+            buf: Vec::new(),
+            mem_state: lua.memory_state(),
+            charged: 0,
+        })?))
+    }
+
+    /// Appends bytes to the buffer.
+    pub fn append(&self, s: impl AsRef<[u8]>) -> Result<()> {
+        self.0.borrow_mut::<StringBuilderData>()?.append(s.as_ref())
+    }
+
+    /// Returns the length of the buffer in bytes.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.0.borrow::<StringBuilderData>()?.buf.len())
+    }
+
+    /// Returns `true` if the buffer is empty.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Creates a Lua string from the buffer contents, leaving the buffer unchanged.
+    pub fn build(&self) -> Result<String<'lua>> {
+        build(&self.0)
+    }
+
+    /// Takes the buffer contents without copying, leaving the buffer empty.
+    pub fn take(&self) -> Result<Vec<u8>> {
+        let mut data = self.0.borrow_mut::<StringBuilderData>()?;
+        data.release();
+        Ok(mem::take(&mut data.buf))
+    }
+
+    /// Empties the buffer.
+    pub fn clear(&self) -> Result<()> {
+        self.0.borrow_mut::<StringBuilderData>()?.buf.clear();
+        Ok(())
+    }
+
+    /// Returns the underlying userdata.
+    pub fn as_userdata(&self) -> &AnyUserData<'lua> {
+        &self.0
+    }
+}
+
+impl<'lua> IntoLua<'lua> for StringBuilder<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(Value::UserData(self.0))
+    }
+}
+
+impl<'lua> FromLua<'lua> for StringBuilder<'lua> {
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        match value {
+            Value::UserData(ud) if ud.is::<StringBuilderData>() => Ok(StringBuilder(ud)),
+            _ => Err(Error::FromLuaConversionError {
+                from: value.type_name(),
+                to: "StringBuilder",
+                message: None,
+            }),
+        }
+    }
+}
+
+struct StringBuilderData {
+    buf: Vec<u8>,
+    // Memory state of the Lua instance, charged with the buffer capacity
+    mem_state: Option<NonNull<MemoryState>>,
+    charged: usize,
+}
+
+// SAFETY: the memory state is only accessed while the Lua instance is used (in methods or when the
+// userdata is collected), so it's never accessed concurrently
+unsafe impl Send for StringBuilderData {}
+
+impl StringBuilderData {
+    fn append(&mut self, bytes: &[u8]) -> Result<()> {
+        let len = self.buf.len() + bytes.len();
+        if len > self.buf.capacity() {
+            // Grow at least twice to keep appends amortized O(1)
+            let capacity = len.max(self.buf.capacity() * 2);
+            self.charge(capacity)?;
+            self.buf.reserve_exact(capacity - self.buf.len());
+        }
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    // Accounts `capacity` bytes in total in the memory state
+    fn charge(&mut self, capacity: usize) -> Result<()> {
+        if let Some(mut mem_state) = self.mem_state {
+            let mem_state = unsafe { mem_state.as_mut() };
+            if !mem_state.try_charge(capacity - self.charged) {
+                return Err(Error::MemoryLimitExceeded(mem_state.memory_limit()));
+            }
+            self.charged = capacity;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        if let Some(mut mem_state) = self.mem_state {
+            unsafe { mem_state.as_mut() }.release(mem::take(&mut self.charged));
+        }
+    }
+}
+
+impl Drop for StringBuilderData {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl UserData for StringBuilderData {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_function(
+            "append",
+            |_, (ud, parts): (AnyUserData, Variadic<String>)| {
+                {
+                    let mut data = ud.borrow_mut::<StringBuilderData>()?;
+                    for part in parts.iter() {
+                        data.append(part.as_bytes())?;
+                    }
+                }
+                Ok(ud)
+            },
+        );
+        methods.add_method("len", |_, this, ()| Ok(this.buf.len()));
+        methods.add_function("build", |_, ud: AnyUserData| build(&ud));
+        methods.add_method_mut("clear", |_, this, ()| {
+            this.buf.clear();
+            Ok(())
+        });
+        methods.add_meta_method(MetaMethod::Len, |_, this, ()| Ok(this.buf.len()));
+        methods.add_meta_function(MetaMethod::ToString, |_, ud: AnyUserData| build(&ud));
+    }
+}
+
+fn build<'lua>(ud: &AnyUserData<'lua>) -> Result<String<'lua>> {
+    let data = ud.borrow::<StringBuilderData>()?;
+    ud.0.lua.create_string(&data.buf)
+}
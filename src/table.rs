@@ -1,7 +1,14 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+#[cfg(any(feature = "luau", doc))]
 use std::collections::HashSet;
 use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::marker::PhantomData;
-use std::os::raw::c_void;
+use std::ops::{Bound, RangeBounds};
+use std::os::raw::{c_int, c_void};
+use std::string::String as StdString;
+use std::{ptr, slice, str};
 
 #[cfg(feature = "serialize")]
 use {
@@ -12,10 +19,12 @@ use {
 
 use crate::error::{Error, Result};
 use crate::function::Function;
+use crate::lua::Lua;
 use crate::private::Sealed;
-use crate::types::{Integer, LuaRef};
-use crate::util::{assert_stack, check_stack, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Nil, Value};
+use crate::types::{Integer, LuaRef, MaybeSend};
+use crate::userdata::MetaMethod;
+use crate::util::{assert_stack, check_stack, push_string, StackGuard};
+use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Nil, PrettyContext, Value};
 
 #[cfg(feature = "async")]
 use futures_util::future::{self, LocalBoxFuture};
@@ -36,6 +45,100 @@ pub struct Table<'lua>(pub(crate) LuaRef<'lua>);
 #[derive(Clone, Debug)]
 pub struct OwnedTable(pub(crate) crate::types::LuaOwnedRef);
 
+/// Strategy used by [`Table::len_with`] to compute the length of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LenMode {
+    /// The result of the `#` operator without invoking the `__len` metamethod.
+    ///
+    /// For tables with holes this can be *any* border of the table.
+    Raw,
+    /// The result of the `#` operator, invoking the `__len` metamethod if present.
+    Metamethod,
+    /// The number of contiguous non-nil values starting from index `1`.
+    ///
+    /// See [`Table::sequence_len`].
+    SequenceScan,
+}
+
+/// Defines how [`Table::concat_str`] converts sequence elements to strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToStringMode {
+    /// Only strings and numbers are accepted, like in Lua `table.concat`.
+    Strict,
+    /// Any value is converted as by the Lua `tostring` function, invoking the `__tostring`
+    /// metamethod if present.
+    ToString,
+}
+
+/// Options for [`Table::structural_hash`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct HashOptions {
+    /// If true, the metatables of tables are hashed together with their contents.
+    /// Otherwise metatables are ignored.
+    ///
+    /// Default: **false**
+    pub include_metatables: bool,
+
+    /// If true, an attempt to hash a table that contains functions, threads, userdata, light
+    /// userdata or errors will cause an error.
+    /// Otherwise such values are hashed by identity (their pointer).
+    ///
+    /// Default: **false**
+    pub deny_unsupported_types: bool,
+
+    /// If true, an attempt to hash a recursive table (table that refers to itself) will cause an
+    /// error.
+    /// Otherwise recursive references are hashed as a marker of their nesting depth.
+    ///
+    /// Default: **true**
+    pub deny_recursive_tables: bool,
+}
+
+impl Default for HashOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashOptions {
+    /// Returns a new instance of `HashOptions` with default parameters.
+    pub const fn new() -> Self {
+        HashOptions {
+            include_metatables: false,
+            deny_unsupported_types: false,
+            deny_recursive_tables: true,
+        }
+    }
+
+    /// Sets [`include_metatables`] option.
+    ///
+    /// [`include_metatables`]: #structfield.include_metatables
+    #[must_use]
+    pub const fn include_metatables(mut self, enabled: bool) -> Self {
+        self.include_metatables = enabled;
+        self
+    }
+
+    /// Sets [`deny_unsupported_types`] option.
+    ///
+    /// [`deny_unsupported_types`]: #structfield.deny_unsupported_types
+    #[must_use]
+    pub const fn deny_unsupported_types(mut self, enabled: bool) -> Self {
+        self.deny_unsupported_types = enabled;
+        self
+    }
+
+    /// Sets [`deny_recursive_tables`] option.
+    ///
+    /// [`deny_recursive_tables`]: #structfield.deny_recursive_tables
+    #[must_use]
+    pub const fn deny_recursive_tables(mut self, enabled: bool) -> Self {
+        self.deny_recursive_tables = enabled;
+        self
+    }
+}
+
 #[cfg(feature = "unstable")]
 impl OwnedTable {
     /// Get borrowed handle to the underlying Lua table.
@@ -148,6 +251,201 @@ impl<'lua> Table<'lua> {
         V::from_lua(value, lua)
     }
 
+    /// Gets the value at a dot-separated `path` of nested keys (eg. `"config.server.port"`).
+    ///
+    /// Every segment of the path is a string key looked up using [`get`], so this might invoke
+    /// the `__index` metamethod. If an intermediate value is `nil`, the result is converted from
+    /// `nil` (eg. `Option<T>` returns `None`). Indexing a value that is not a table is an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config: Table = lua.load("{ server = { port = 8080 } }").eval()?;
+    /// assert_eq!(config.get_path::<u16>("server.port")?, 8080);
+    /// assert_eq!(config.get_path::<Option<String>>("client.host")?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`get`]: #method.get
+    pub fn get_path<V: FromLua<'lua>>(&self, path: &str) -> Result<V> {
+        let lua = self.0.lua;
+        let (parent, key) = match path.rsplit_once('.') {
+            Some((parent_path, key)) => (self.path_table(parent_path, false)?, key),
+            None => (Some(self.clone()), path),
+        };
+        let value = match parent {
+            Some(parent) => parent.get::<_, Value>(key)?,
+            None => Value::Nil,
+        };
+        V::from_lua(value, lua).map_err(|err| err.with_conversion_path(|| path_segments(path)))
+    }
+
+    /// Sets the value at a dot-separated `path` of nested keys (eg. `"config.server.port"`).
+    ///
+    /// Every segment of the path is a string key set using [`set`], so this might invoke
+    /// the `__index` and `__newindex` metamethods. Missing (`nil`) intermediate tables are created.
+    /// Indexing a value that is not a table is an error.
+    ///
+    /// [`set`]: #method.set
+    pub fn set_path<V: IntoLua<'lua>>(&self, path: &str, value: V) -> Result<()> {
+        match path.rsplit_once('.') {
+            Some((parent_path, key)) => {
+                let parent = self.path_table(parent_path, true)?;
+                parent.expect("missing tables are created").set(key, value)
+            }
+            None => self.set(path, value),
+        }
+    }
+
+    // Walks the dot-separated `path` of nested tables, optionally creating missing ones.
+    // Returns `None` if a table is missing (and not created).
+    fn path_table(&self, path: &str, create: bool) -> Result<Option<Table<'lua>>> {
+        let lua = self.0.lua;
+        let mut table = self.clone();
+        let mut end = 0;
+        for key in path.split('.') {
+            end += key.len();
+            table = match table.get::<_, Value>(key)? {
+                Value::Table(t) => t,
+                Value::Nil if create => {
+                    let t = lua.create_table()?;
+                    table.set(key, t.clone())?;
+                    t
+                }
+                Value::Nil => return Ok(None),
+                value => {
+                    return Err(Error::RuntimeError(format!(
+                        "cannot index '{}' (a {} value)",
+                        &path[..end],
+                        value.type_name()
+                    )))
+                }
+            };
+            end += 1;
+        }
+        Ok(Some(table))
+    }
+
+    /// Gets the integer value associated to `key`, converting it directly from the Lua stack.
+    ///
+    /// This is a faster equivalent of `get::<_, i64>(key)` (with the same conversion rules), as
+    /// it does not construct an intermediate [`Value`].
+    ///
+    /// This might invoke the `__index` metamethod.
+    pub fn get_i64<K: IntoLua<'lua>>(&self, key: K) -> Result<i64> {
+        let lua = self.0.lua;
+        self.get_on_stack(key, |state| unsafe {
+            let mut isint = 0;
+            let i = ffi::lua_tointegerx(state, -1, &mut isint);
+            if isint != 0 {
+                #[allow(clippy::unnecessary_cast)]
+                return Ok(i as i64);
+            }
+            i64::from_lua(lua.pop_value(), lua)
+        })
+    }
+
+    /// Gets the number value associated to `key`, converting it directly from the Lua stack.
+    ///
+    /// This is a faster equivalent of `get::<_, f64>(key)` (with the same conversion rules), as
+    /// it does not construct an intermediate [`Value`].
+    ///
+    /// This might invoke the `__index` metamethod.
+    pub fn get_f64<K: IntoLua<'lua>>(&self, key: K) -> Result<f64> {
+        let lua = self.0.lua;
+        self.get_on_stack(key, |state| unsafe {
+            if ffi::lua_type(state, -1) == ffi::LUA_TNUMBER {
+                #[allow(clippy::unnecessary_cast)]
+                return Ok(ffi::lua_tonumber(state, -1) as f64);
+            }
+            f64::from_lua(lua.pop_value(), lua)
+        })
+    }
+
+    /// Gets the boolean value associated to `key`, converting it directly from the Lua stack.
+    ///
+    /// As in Lua (and `get::<_, bool>(key)`), only `nil` and `false` are converted to `false`.
+    ///
+    /// This might invoke the `__index` metamethod.
+    pub fn get_bool<K: IntoLua<'lua>>(&self, key: K) -> Result<bool> {
+        self.get_on_stack(key, |state| unsafe {
+            Ok(ffi::lua_toboolean(state, -1) != 0)
+        })
+    }
+
+    /// Gets the string value associated to `key` and passes it to `f`, without copying the
+    /// string or constructing an intermediate [`Value`].
+    ///
+    /// Numbers are converted to strings, as in `get::<_, String>(key)`. Returns an error if the
+    /// value is not a valid UTF-8 string.
+    ///
+    /// This might invoke the `__index` metamethod.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let config = lua.load("{mode = 'fast', retries = 3}").eval::<mlua::Table>()?;
+    /// assert!(config.get_str_with("mode", |mode| mode == "fast")?);
+    /// assert_eq!(config.get_i64("retries")?, 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_str_with<K, R, F>(&self, key: K, f: F) -> Result<R>
+    where
+        K: IntoLua<'lua>,
+        F: FnOnce(&str) -> R,
+    {
+        let lua = self.0.lua;
+        self.get_on_stack(key, |state| unsafe {
+            if ffi::lua_type(state, -1) == ffi::LUA_TSTRING {
+                let mut size = 0;
+                let data = ffi::lua_tolstring(state, -1, &mut size);
+                let bytes = slice::from_raw_parts(data as *const u8, size);
+                return match str::from_utf8(bytes) {
+                    Ok(s) => Ok(f(s)),
+                    Err(err) => Err(Error::FromLuaConversionError {
+                        from: "string",
+                        to: "&str",
+                        message: Some(err.to_string()),
+                    }),
+                };
+            }
+            let s = StdString::from_lua(lua.pop_value(), lua)?;
+            Ok(f(&s))
+        })
+    }
+
+    // Pushes the value associated to `key` onto the stack and calls `f` to convert it
+    fn get_on_stack<K, R, F>(&self, key: K, f: F) -> Result<R>
+    where
+        K: IntoLua<'lua>,
+        F: FnOnce(*mut ffi::lua_State) -> Result<R>,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        let key = key.into_lua(lua)?;
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            lua.push_ref(&self.0);
+            lua.push_value(key)?;
+            if self.has_metatable() {
+                protect_lua!(state, 2, 1, fn(state) ffi::lua_gettable(state, -2))?;
+            } else {
+                ffi::lua_rawget(state, -2);
+            }
+            f(state)
+        }
+    }
+
     /// Checks whether the table contains a non-nil value for `key`.
     ///
     /// This might invoke the `__index` metamethod.
@@ -469,6 +767,85 @@ impl<'lua> Table<'lua> {
         Ok(())
     }
 
+    /// Removes up to `budget_entries` entries from the table, without invoking metamethods.
+    ///
+    /// Returns `true` once the table is empty. Calling this method repeatedly (eg. once per frame)
+    /// clears huge tables in bounded slices instead of stalling in [`Table::clear`]. The table
+    /// can be modified between calls, each call continues where the previous one stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let t: Table = lua.load("local t = {} for i = 1, 1000 do t[i] = i end return t").eval()?;
+    /// let mut calls = 1;
+    /// while !t.clear_incremental(100)? {
+    ///     calls += 1;
+    /// }
+    /// assert_eq!(calls, 10);
+    /// assert!(t.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn clear_incremental(&self, budget_entries: usize) -> Result<bool> {
+        #[cfg(feature = "luau")]
+        self.check_readonly_write()?;
+
+        let lua = self.0.lua;
+        let cursors = clear_cursors_table(lua)?;
+        let cursor = cursors.raw_get::<_, Value>(self.clone())?;
+
+        let state = lua.state();
+        let next_key = unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+
+            lua.push_ref(&self.0);
+            // Continue from the key we stopped at, unless it has been removed since
+            let mut from_cursor = false;
+            if cursor != Nil {
+                lua.push_value(cursor)?;
+                ffi::lua_pushvalue(state, -1);
+                from_cursor = ffi::lua_rawget(state, -3) != ffi::LUA_TNIL;
+                ffi::lua_pop(state, 1);
+                if !from_cursor {
+                    ffi::lua_pop(state, 1);
+                }
+            }
+            if !from_cursor {
+                ffi::lua_pushnil(state);
+            }
+
+            let mut removed = 0;
+            loop {
+                if ffi::lua_next(state, -2) == 0 {
+                    // Keys can be added before the cursor between calls, so restart once
+                    if from_cursor {
+                        from_cursor = false;
+                        ffi::lua_pushnil(state);
+                        continue;
+                    }
+                    break Nil;
+                }
+                ffi::lua_pop(state, 1);
+                if removed == budget_entries {
+                    break lua.pop_value();
+                }
+                // Removing the current key during traversal is allowed
+                ffi::lua_pushvalue(state, -1);
+                ffi::lua_pushnil(state);
+                ffi::lua_rawset(state, -4);
+                removed += 1;
+            }
+        };
+
+        let done = next_key == Nil;
+        cursors.raw_set(self.clone(), next_key)?;
+        Ok(done)
+    }
+
     /// Returns the result of the Lua `#` operator.
     ///
     /// This might invoke the `__len` metamethod. Use the [`raw_len`] method if that is not desired.
@@ -497,6 +874,125 @@ impl<'lua> Table<'lua> {
         unsafe { ffi::lua_rawlen(ref_thread, self.0.index) as Integer }
     }
 
+    /// Returns the length of the table according to the given [`LenMode`].
+    pub fn len_with(&self, mode: LenMode) -> Result<Integer> {
+        match mode {
+            LenMode::Raw => Ok(self.raw_len()),
+            LenMode::Metamethod => self.len(),
+            LenMode::SequenceScan => Ok(self.sequence_len()),
+        }
+    }
+
+    /// Returns the number of contiguous non-nil values starting from index `1`, without invoking
+    /// metamethods.
+    ///
+    /// Unlike the `#` operator, the result is well-defined for tables with holes: it's always the
+    /// first border of the table (the same length [`sequence_values`] would iterate over).
+    /// This method scans the array, so it's `O(n)`.
+    ///
+    /// [`sequence_values`]: #method.sequence_values
+    pub fn sequence_len(&self) -> Integer {
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            assert_stack(state, 2);
+
+            lua.push_ref(&self.0);
+            let mut len = 0;
+            while ffi::lua_rawgeti(state, -1, len + 1) != ffi::LUA_TNIL {
+                ffi::lua_pop(state, 1);
+                len += 1;
+            }
+            len as Integer
+        }
+    }
+
+    /// Joins the sequence elements in `range` with `sep`, returning a Rust string.
+    ///
+    /// The elements are converted to strings according to `mode` and joined in a single call to
+    /// Lua. An unbounded range ends at the result of the `#` operator (see [`len`]), as in Lua
+    /// `table.concat`. Elements are read with the `__index` metamethod.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, ToStringMode};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let list = lua.load("{'a', 2, true, 'd'}").eval::<mlua::Table>()?;
+    /// assert_eq!(list.concat_str(", ", 1..=2, ToStringMode::Strict)?, "a, 2");
+    /// assert_eq!(list.concat_str("", .., ToStringMode::ToString)?, "a2trued");
+    /// assert!(list.concat_str("", .., ToStringMode::Strict).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`len`]: #method.len
+    pub fn concat_str<R: RangeBounds<Integer>>(
+        &self,
+        sep: &str,
+        range: R,
+        mode: ToStringMode,
+    ) -> Result<StdString> {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.saturating_add(1),
+            Bound::Unbounded => 1,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.saturating_sub(1),
+            Bound::Unbounded => self.len()?,
+        };
+
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 4)?;
+
+            lua.push_ref(&self.0);
+            push_string(state, sep.as_bytes(), true)?;
+            let invalid = protect_lua!(state, 2, 1, |state| {
+                ffi::luaL_checkstack(state, CONCAT_STACK_LIMIT + 3, ptr::null());
+                for i in start..=end {
+                    if i > start {
+                        ffi::lua_pushvalue(state, 2);
+                        adjust_concat_stack(state, 2);
+                    }
+                    ffi::lua_geti(state, 1, i);
+                    match mode {
+                        ToStringMode::Strict => match ffi::lua_type(state, -1) {
+                            ffi::LUA_TSTRING | ffi::LUA_TNUMBER => {
+                                ffi::lua_tolstring(state, -1, ptr::null_mut());
+                            }
+                            _ => return Some(i),
+                        },
+                        ToStringMode::ToString => {
+                            ffi::luaL_tolstring(state, -1, ptr::null_mut());
+                            ffi::lua_remove(state, -2);
+                        }
+                    }
+                    adjust_concat_stack(state, 2);
+                }
+                ffi::lua_concat(state, ffi::lua_gettop(state) - 2);
+                None
+            })?;
+
+            if let Some(i) = invalid {
+                let value = self.get::<_, Value>(i)?;
+                return Err(Error::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "string",
+                    message: Some(format!("invalid value (at index {i}) to concatenate")),
+                }
+                .with_value_preview(value.preview()));
+            }
+            Ok(crate::string::String(lua.pop_ref()).to_str()?.to_string())
+        }
+    }
+
     /// Returns `true` if the table is empty, without invoking metamethods.
     ///
     /// It checks both the array part and the hash part.
@@ -546,27 +1042,93 @@ impl<'lua> Table<'lua> {
     ///
     /// If `metatable` is `None`, the metatable is removed (if no metatable is set, this does
     /// nothing).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `metatable` was created by a different Lua state, or if this table is readonly
+    /// (Luau). Use [`Table::try_set_metatable`] to get an error instead.
     pub fn set_metatable(&self, metatable: Option<Table<'lua>>) {
-        // Workaround to throw readonly error without returning Result
+        if let Err(err) = self.try_set_metatable(metatable) {
+            panic!("{err}");
+        }
+    }
+
+    /// Sets or removes the metatable of this table, returning an error on failure.
+    ///
+    /// Same as [`Table::set_metatable`], but returns an error if this table is readonly (Luau)
+    /// or if `metatable` was created by a different Lua state (with `strict-no-panic` feature).
+    pub fn try_set_metatable(&self, metatable: Option<Table<'lua>>) -> Result<()> {
         #[cfg(feature = "luau")]
         if self.is_readonly() {
-            panic!("attempt to modify a readonly table");
+            return Err(Error::RuntimeError(
+                "attempt to modify a readonly table".to_string(),
+            ));
         }
 
         let lua = self.0.lua;
         let state = lua.state();
         unsafe {
             let _sg = StackGuard::new(state);
-            assert_stack(state, 2);
+            check_stack(state, 2)?;
 
             lua.push_ref(&self.0);
             if let Some(metatable) = metatable {
-                lua.push_ref(&metatable.0);
+                lua.push_ref_checked(&metatable.0)?;
             } else {
                 ffi::lua_pushnil(state);
             }
             ffi::lua_setmetatable(state, -2);
         }
+        Ok(())
+    }
+
+    /// Creates a new metatable, configures it using the given closure and attaches it to this
+    /// table (replacing any existing one).
+    ///
+    /// The [`MetatableBuilder`] has a typed setter for each common metamethod, so there is no
+    /// need to deal with raw `"__index"` keys. Returns the created metatable, which can be shared
+    /// with other tables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let counter = lua.create_table()?;
+    /// counter.set("n", 0)?;
+    /// counter.set_metatable_with(|mt| {
+    ///     mt.on_call(|_, this, step: Option<i64>| {
+    ///         let n = this.get::<_, i64>("n")? + step.unwrap_or(1);
+    ///         this.set("n", n)?;
+    ///         Ok(n)
+    ///     });
+    ///     mt.on_tostring(|_, this| Ok(format!("counter({})", this.get::<_, i64>("n")?)));
+    /// })?;
+    ///
+    /// lua.globals().set("counter", counter)?;
+    /// lua.load("counter() counter(10)").exec()?;
+    /// assert_eq!(lua.load("tostring(counter)").eval::<String>()?, "counter(11)");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_metatable_with<F>(&self, f: F) -> Result<Table<'lua>>
+    where
+        F: FnOnce(&mut MetatableBuilder<'lua>),
+    {
+        #[cfg(feature = "luau")]
+        self.check_readonly_write()?;
+
+        let mut builder = MetatableBuilder {
+            metatable: self.0.lua.create_table()?,
+            error: None,
+        };
+        f(&mut builder);
+        if let Some(err) = builder.error {
+            return Err(err);
+        }
+        self.set_metatable(Some(builder.metatable.clone()));
+        Ok(builder.metatable)
     }
 
     /// Returns true if the table has metatable attached.
@@ -609,25 +1171,125 @@ impl<'lua> Table<'lua> {
         unsafe { ffi::lua_getreadonly(ref_thread, self.0.index) != 0 }
     }
 
-    /// Converts the table to a generic C pointer.
+    /// Sets `readonly` attribute on the table and on every table reachable from it (as a key or
+    /// a value), recursively.
     ///
-    /// Different tables will give different pointers.
-    /// There is no way to convert the pointer back to its original value.
+    /// Metatables are not frozen.
     ///
-    /// Typically this function is used only for hashing and debug information.
-    #[inline]
-    pub fn to_pointer(&self) -> *const c_void {
-        let ref_thread = self.0.lua.ref_thread();
-        unsafe { ffi::lua_topointer(ref_thread, self.0.index) }
+    /// Other Lua versions have no read-only tables. A metatable based proxy cannot freeze a table
+    /// in place and is bypassed by raw access (`rawset`, `next`), so no fallback is provided.
+    ///
+    /// Requires `feature = "luau"`
+    #[cfg(any(feature = "luau", doc))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+    pub fn deep_freeze(&self) -> Result<()> {
+        let mut visited = HashSet::new();
+        let mut queue = vec![self.clone()];
+        while let Some(table) = queue.pop() {
+            if !visited.insert(table.to_pointer()) {
+                continue;
+            }
+            for pair in table.clone().pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                for v in [key, value] {
+                    if let Value::Table(t) = v {
+                        queue.push(t);
+                    }
+                }
+            }
+            table.set_readonly(true);
+        }
+        Ok(())
     }
 
-    /// Convert this handle to owned version.
-    #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
-    #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
-    #[inline]
-    pub fn into_owned(self) -> OwnedTable {
-        OwnedTable(self.0.into_owned())
-    }
+    /// Computes a hash of the table contents.
+    ///
+    /// Tables with equal contents produce the same hash regardless of their identity or the order
+    /// in which their keys were inserted. Nested tables are hashed by content, numbers are hashed
+    /// by value (so `1` and `1.0` are the same) and strings by their bytes.
+    /// No metamethods are invoked.
+    ///
+    /// This is useful for caching results keyed by configuration tables, which scripts often
+    /// rebuild from scratch. The hash is stable within a process, but not guaranteed to be stable
+    /// across mlua versions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{HashOptions, Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let a: Table = lua.load("{ width = 800, height = 600, tags = {'a', 'b'} }").eval()?;
+    /// let b: Table = lua.load("{ tags = {'a', 'b'}, height = 600, width = 800 }").eval()?;
+    /// assert_eq!(
+    ///     a.structural_hash(HashOptions::new())?,
+    ///     b.structural_hash(HashOptions::new())?,
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn structural_hash(&self, options: HashOptions) -> Result<u64> {
+        let mut stack = Vec::new();
+        self.structural_hash_inner(options, &mut stack)
+    }
+
+    fn structural_hash_inner(
+        &self,
+        options: HashOptions,
+        stack: &mut Vec<*const c_void>,
+    ) -> Result<u64> {
+        let ptr = self.to_pointer();
+        if let Some(depth) = stack.iter().rposition(|&p| p == ptr) {
+            if options.deny_recursive_tables {
+                return Err(Error::RuntimeError(
+                    "cannot hash recursive table".to_string(),
+                ));
+            }
+            return Ok(hash_of(&(HASH_TAG_RECURSIVE, stack.len() - depth)));
+        }
+
+        stack.push(ptr);
+        let res = (|| {
+            // Combine pair hashes with a commutative operation to not depend on traversal order
+            let (mut combined, mut count) = (0u64, 0u64);
+            for pair in self.clone().pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                let key_hash = hash_value(&key, options, stack)?;
+                let value_hash = hash_value(&value, options, stack)?;
+                combined = combined.wrapping_add(hash_of(&(key_hash, value_hash)));
+                count += 1;
+            }
+            let mt_hash = match self.get_metatable() {
+                Some(mt) if options.include_metatables => {
+                    Some(mt.structural_hash_inner(options, stack)?)
+                }
+                _ => None,
+            };
+            Ok(hash_of(&(HASH_TAG_TABLE, combined, count, mt_hash)))
+        })();
+        stack.pop();
+        res
+    }
+
+    /// Converts the table to a generic C pointer.
+    ///
+    /// Different tables will give different pointers.
+    /// There is no way to convert the pointer back to its original value.
+    ///
+    /// Typically this function is used only for hashing and debug information.
+    #[inline]
+    pub fn to_pointer(&self) -> *const c_void {
+        let ref_thread = self.0.lua.ref_thread();
+        unsafe { ffi::lua_topointer(ref_thread, self.0.index) }
+    }
+
+    /// Convert this handle to owned version.
+    #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+    #[inline]
+    pub fn into_owned(self) -> OwnedTable {
+        OwnedTable(self.0.into_owned())
+    }
 
     /// Consume this table and return an iterator over the pairs of the table.
     ///
@@ -717,7 +1379,6 @@ impl<'lua> Table<'lua> {
         }
     }
 
-    #[cfg(feature = "serialize")]
     pub(crate) fn sequence_values_by_len<V: FromLua<'lua>>(
         self,
         len: Option<Integer>,
@@ -731,6 +1392,271 @@ impl<'lua> Table<'lua> {
         }
     }
 
+    /// Returns a lending iterator over the sequence part of the table, yielding references to the
+    /// values.
+    ///
+    /// Unlike [`sequence_values`], this does not create a new reference for every string, table,
+    /// function or thread: the current element is kept in a single reserved slot that is reused
+    /// for the next one. This makes walking large array-like tables considerably cheaper.
+    /// Clone a value to keep it beyond the next call to [`TableSequenceRef::next`].
+    ///
+    /// Access is raw (without invoking metamethods).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table, Value};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load(r#"{"a", "bb", "ccc"}"#).eval()?;
+    ///
+    /// let mut total_len = 0;
+    /// let mut values = table.sequence_values_by_ref();
+    /// while let Some(value) = values.next() {
+    ///     if let Value::String(s) = value? {
+    ///         total_len += s.as_bytes().len();
+    ///     }
+    /// }
+    /// assert_eq!(total_len, 6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`sequence_values`]: #method.sequence_values
+    pub fn sequence_values_by_ref(&self) -> TableSequenceRef<'lua> {
+        let lua = self.0.lua;
+        let slot = unsafe {
+            ffi::lua_pushnil(lua.ref_thread());
+            lua.pop_ref_thread()
+        };
+        TableSequenceRef {
+            table: self.0.clone(),
+            slot,
+            index: 1,
+            current: Nil,
+        }
+    }
+
+    /// Returns a lazy typed view over the sequence part of the table.
+    ///
+    /// Values are converted on access, so large tables can be walked on demand without
+    /// converting the whole structure upfront.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{10, 20, 30}").eval()?;
+    /// let array = table.as_array::<u32>();
+    /// assert_eq!(array.len(), 3);
+    /// assert_eq!(array.get(2)?, Some(20));
+    /// assert_eq!(array.iter().collect::<Result<Vec<_>>>()?, [10, 20, 30]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn as_array<V: FromLua<'lua>>(&self) -> ArrayView<'lua, V> {
+        ArrayView {
+            table: self.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a lazy typed view over the key-value pairs of the table.
+    ///
+    /// Keys and values are converted on access, so large tables can be walked on demand without
+    /// converting the whole structure upfront.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ alice = 10, bob = 20 }").eval()?;
+    /// let scores = table.as_map::<String, u32>();
+    /// assert_eq!(scores.get("bob")?, Some(20));
+    /// assert_eq!(scores.get("carol")?, None);
+    /// assert_eq!(scores.len()?, 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn as_map<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> MapView<'lua, K, V> {
+        MapView {
+            table: self.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns a view of the table where all operations are raw (without invoking metamethods).
+    ///
+    /// Unlike mixing [`raw_get`] and [`get`] calls on the same handle, the view encodes the access
+    /// mode in its type, so it can be passed around without losing the intent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua
+    ///     .load("setmetatable({}, {__index = function() return 'default' end})")
+    ///     .eval()?;
+    /// assert_eq!(table.with_meta().get::<_, String>("key")?, "default");
+    /// assert_eq!(table.raw().get::<_, Option<String>>("key")?, None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`raw_get`]: #method.raw_get
+    /// [`get`]: #method.get
+    pub fn raw(&self) -> RawTable<'lua> {
+        RawTable {
+            table: self.clone(),
+        }
+    }
+
+    /// Returns a view of the table where all operations invoke metamethods.
+    ///
+    /// See [`raw`] for the raw counterpart.
+    ///
+    /// [`raw`]: #method.raw
+    pub fn with_meta(&self) -> TableWithMeta<'lua> {
+        TableWithMeta {
+            table: self.clone(),
+        }
+    }
+
+    /// Converts the sequence part of the table into a `Vec`.
+    ///
+    /// This is equivalent to collecting [`sequence_values`], but all values are read in a single
+    /// pass over the table, which is faster for large tables. Access is raw (without invoking
+    /// metamethods).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{1, 2, 3, nil, 5}").eval()?;
+    /// assert_eq!(table.to_vec::<i32>()?, [1, 2, 3]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`sequence_values`]: #method.sequence_values
+    pub fn to_vec<V: FromLua<'lua>>(&self) -> Result<Vec<V>> {
+        let lua = self.0.lua;
+        let state = lua.state();
+        let _depth = lua.enter_from_conversion()?;
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 2)?;
+
+            lua.push_ref(&self.0);
+            let mut vec = Vec::with_capacity(ffi::lua_rawlen(state, -1));
+            for index in 1.. {
+                if ffi::lua_rawgeti(state, -1, index) == ffi::LUA_TNIL {
+                    break;
+                }
+                check_table_entries(lua, "Vec", index as usize)?;
+                let value = V::from_lua(lua.pop_value(), lua)
+                    .map_err(|err| err.with_conversion_path(|| format!("[{index}]")))?;
+                vec.push(value);
+            }
+            Ok(vec)
+        }
+    }
+
+    /// Converts all key-value pairs of the table into a `HashMap`.
+    ///
+    /// This is equivalent to collecting [`pairs`], but all pairs are read in a single traversal of
+    /// the table, which is faster for large tables.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table: Table = lua.load("{ alice = 10, bob = 20 }").eval()?;
+    /// let scores: HashMap<String, u32> = table.to_hashmap()?;
+    /// assert_eq!(scores["bob"], 20);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`pairs`]: #method.pairs
+    pub fn to_hashmap<K, V, S>(&self) -> Result<HashMap<K, V, S>>
+    where
+        K: Eq + Hash + FromLua<'lua>,
+        V: FromLua<'lua>,
+        S: BuildHasher + Default,
+    {
+        let lua = self.0.lua;
+        let state = lua.state();
+        let _depth = lua.enter_from_conversion()?;
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 6)?;
+
+            let mut map = HashMap::default();
+            lua.push_ref(&self.0);
+            ffi::lua_pushnil(state);
+            // Stack: table, key
+            loop {
+                ffi::lua_pushvalue(state, -2);
+                ffi::lua_pushvalue(state, -2);
+                let next = protect_lua!(state, 2, ffi::LUA_MULTRET, |state| {
+                    ffi::lua_next(state, -2)
+                })?;
+                // Stack: table, key, table[, next_key, value]
+                if next == 0 {
+                    break;
+                }
+                check_table_entries(lua, "HashMap", map.len() + 1)?;
+                let value = lua.pop_value();
+                ffi::lua_pushvalue(state, -1);
+                let key = lua.pop_value();
+                ffi::lua_replace(state, -3);
+                ffi::lua_pop(state, 1);
+
+                let value = V::from_lua(value, lua)
+                    .map_err(|err| err.with_conversion_path(|| path_segment(&key)))?;
+                map.insert(K::from_lua(key, lua)?, value);
+            }
+            Ok(map)
+        }
+    }
+
+    /// Creates a table from a slice of values, using `1..` as the keys.
+    ///
+    /// This is the reverse of [`to_vec`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Table};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let table = Table::from_slice(&lua, &["a", "b", "c"])?;
+    /// assert_eq!(table.raw_len(), 3);
+    /// assert_eq!(table.to_vec::<String>()?, ["a", "b", "c"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`to_vec`]: #method.to_vec
+    pub fn from_slice<T>(lua: &'lua Lua, values: &[T]) -> Result<Table<'lua>>
+    where
+        T: IntoLua<'lua> + Clone,
+    {
+        lua.create_sequence_from(values.iter().cloned())
+    }
+
     /// Sets element value at position `idx` without invoking metamethods.
     #[allow(dead_code)]
     pub(crate) fn raw_seti<V: IntoLua<'lua>>(&self, idx: usize, value: V) -> Result<()> {
@@ -789,9 +1715,9 @@ impl<'lua> Table<'lua> {
         &self,
         fmt: &mut fmt::Formatter,
         ident: usize,
-        visited: &mut HashSet<*const c_void>,
+        ctx: &mut PrettyContext,
     ) -> fmt::Result {
-        visited.insert(self.to_pointer());
+        ctx.visited.insert(self.to_pointer());
 
         let t = self.clone();
         // Collect key/value pairs into a vector so we can sort them
@@ -804,9 +1730,9 @@ impl<'lua> Table<'lua> {
         writeln!(fmt, "{{")?;
         for (key, value) in pairs {
             write!(fmt, "{}[", " ".repeat(ident + 2))?;
-            key.fmt_pretty(fmt, false, ident + 2, visited)?;
+            key.fmt_pretty(fmt, false, ident + 2, ctx)?;
             write!(fmt, "] = ")?;
-            value.fmt_pretty(fmt, true, ident + 2, visited)?;
+            value.fmt_pretty(fmt, true, ident + 2, ctx)?;
             writeln!(fmt, ",")?;
         }
         write!(fmt, "{}}}", " ".repeat(ident))
@@ -816,7 +1742,7 @@ impl<'lua> Table<'lua> {
 impl fmt::Debug for Table<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         if fmt.alternate() {
-            return self.fmt_pretty(fmt, 0, &mut HashSet::new());
+            return self.fmt_pretty(fmt, 0, &mut PrettyContext::new());
         }
         fmt.write_fmt(format_args!("Table({:?})", self.0))
     }
@@ -885,6 +1811,30 @@ where
     }
 }
 
+// Maximum number of pieces kept on the stack by `Table::concat_str`
+const CONCAT_STACK_LIMIT: c_int = 32;
+
+// Concatenates pieces on top of the stack (above `base`), keeping their number bounded.
+// Shorter pieces are merged first to avoid copying long strings repeatedly (like `luaL_Buffer`
+// in Lua 5.1).
+unsafe fn adjust_concat_stack(state: *mut ffi::lua_State, base: c_int) {
+    let levels = ffi::lua_gettop(state) - base;
+    let mut toget = 1;
+    let mut toplen = ffi::lua_rawlen(state, -1);
+    while toget < levels {
+        let len = ffi::lua_rawlen(state, -(toget + 1));
+        if levels - toget + 1 >= CONCAT_STACK_LIMIT || toplen > len {
+            toplen += len;
+            toget += 1;
+        } else {
+            break;
+        }
+    }
+    if toget > 1 {
+        ffi::lua_concat(state, toget);
+    }
+}
+
 /// An extension trait for `Table`s that provides a variety of convenient functionality.
 pub trait TableExt<'lua>: Sealed {
     /// Calls the table as function assuming it has `__call` metamethod.
@@ -1084,6 +2034,39 @@ impl<'lua> Serialize for Table<'lua> {
     }
 }
 
+const HASH_TAG_TABLE: u8 = 5;
+const HASH_TAG_RECURSIVE: u8 = 6;
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn hash_value(value: &Value, options: HashOptions, stack: &mut Vec<*const c_void>) -> Result<u64> {
+    Ok(match value {
+        Value::Nil => hash_of(&0u8),
+        Value::Boolean(b) => hash_of(&(1u8, b)),
+        Value::Integer(i) => hash_of(&(2u8, i)),
+        // Floats with an exact integer representation must hash as integers (`1 == 1.0` in Lua)
+        Value::Number(n)
+            if n.fract() == 0.0 && *n >= Integer::MIN as f64 && *n < -(Integer::MIN as f64) =>
+        {
+            hash_of(&(2u8, *n as Integer))
+        }
+        Value::Number(n) => hash_of(&(3u8, n.to_bits())),
+        #[cfg(feature = "luau")]
+        Value::Vector(v) => hash_of(&(8u8, v.0.map(f32::to_bits))),
+        Value::String(s) => hash_of(&(4u8, s.as_bytes())),
+        Value::Table(t) => t.structural_hash_inner(options, stack)?,
+        _ if options.deny_unsupported_types => {
+            let err = format!("cannot hash value of type {}", value.type_name());
+            return Err(Error::RuntimeError(err));
+        }
+        _ => hash_of(&(7u8, value.type_name(), value.to_pointer() as usize)),
+    })
+}
+
 /// An iterator over the pairs of a Lua table.
 ///
 /// This struct is created by the [`Table::pairs`] method.
@@ -1120,11 +2103,10 @@ where
                 if next != 0 {
                     let value = lua.pop_value();
                     let key = lua.pop_value();
-                    Ok(Some((
-                        key.clone(),
-                        K::from_lua(key, lua)?,
-                        V::from_lua(value, lua)?,
-                    )))
+                    let ret_key = K::from_lua(key.clone(), lua)?;
+                    let value = V::from_lua(value, lua)
+                        .map_err(|err| err.with_conversion_path(|| path_segment(&key)))?;
+                    Ok(Some((key, ret_key, value)))
                 } else {
                     Ok(None)
                 }
@@ -1181,7 +2163,10 @@ where
             match res {
                 Ok(Some((index, r))) => {
                     self.index = Some(index + 1);
-                    Some(V::from_lua(r, lua))
+                    Some(
+                        V::from_lua(r, lua)
+                            .map_err(|err| err.with_conversion_path(|| format!("[{index}]"))),
+                    )
                 }
                 Ok(None) => None,
                 Err(err) => Some(Err(err)),
@@ -1192,6 +2177,588 @@ where
     }
 }
 
+/// A lending iterator over the sequence part of a Lua table.
+///
+/// This struct is created by the [`Table::sequence_values_by_ref`] method.
+///
+/// [`Table::sequence_values_by_ref`]: crate::Table::sequence_values_by_ref
+pub struct TableSequenceRef<'lua> {
+    table: LuaRef<'lua>,
+    // Reserved reference slot holding the current element
+    slot: LuaRef<'lua>,
+    index: Integer,
+    current: Value<'lua>,
+}
+
+impl<'lua> TableSequenceRef<'lua> {
+    /// Advances the iterator and returns a reference to the next value.
+    ///
+    /// Returns `None` when the first `nil` value is reached.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<&Value<'lua>>> {
+        let lua = self.table.lua;
+        let state = lua.state();
+
+        // The current value may point to the slot, release it before overwriting the slot
+        self.current = Nil;
+        let res = unsafe {
+            (|| {
+                let _sg = StackGuard::new(state);
+                check_stack(state, 2)?;
+
+                lua.push_ref(&self.table);
+                let borrowed = |index| LuaRef {
+                    lua,
+                    index,
+                    drop: false,
+                };
+                Ok(match ffi::lua_rawgeti(state, -1, self.index) {
+                    ffi::LUA_TNIL => None,
+                    ty @ (ffi::LUA_TSTRING
+                    | ffi::LUA_TTABLE
+                    | ffi::LUA_TFUNCTION
+                    | ffi::LUA_TTHREAD) => {
+                        let ref_thread = lua.ref_thread();
+                        ffi::lua_xmove(state, ref_thread, 1);
+                        ffi::lua_replace(ref_thread, self.slot.index);
+                        let r = borrowed(self.slot.index);
+                        Some(match ty {
+                            ffi::LUA_TSTRING => Value::String(crate::string::String(r)),
+                            ffi::LUA_TTABLE => Value::Table(Table(r)),
+                            ffi::LUA_TFUNCTION => Value::Function(Function(r)),
+                            _ => Value::Thread(crate::thread::Thread(r)),
+                        })
+                    }
+                    _ => Some(lua.pop_value()),
+                })
+            })()
+        };
+
+        match res {
+            Ok(Some(value)) => {
+                self.index += 1;
+                self.current = value;
+                Some(Ok(&self.current))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// A lazy typed view over the sequence part of a Lua table.
+///
+/// Access is raw (without invoking metamethods) and indices start at 1, as in Lua.
+///
+/// This struct is created by the [`Table::as_array`] method.
+///
+/// [`Table::as_array`]: crate::Table::as_array
+pub struct ArrayView<'lua, V> {
+    table: Table<'lua>,
+    _phantom: PhantomData<V>,
+}
+
+impl<'lua, V: FromLua<'lua>> ArrayView<'lua, V> {
+    /// Returns the value at position `index`, or `None` if the index is out of bounds.
+    pub fn get(&self, index: usize) -> Result<Option<V>> {
+        if index == 0 || index > self.len() {
+            return Ok(None);
+        }
+        let lua = self.table.0.lua;
+        let value = self.table.raw_get::<_, Value>(index)?;
+        V::from_lua(value, lua)
+            .map(Some)
+            .map_err(|err| err.with_conversion_path(|| format!("[{index}]")))
+    }
+
+    /// Returns the length of the sequence (as returned by [`Table::raw_len`]).
+    ///
+    /// [`Table::raw_len`]: crate::Table::raw_len
+    pub fn len(&self) -> usize {
+        self.table.raw_len() as usize
+    }
+
+    /// Returns `true` if the sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the sequence values, converting them on access.
+    pub fn iter(&self) -> TableSequence<'lua, V> {
+        self.table.clone().sequence_values_by_len(None)
+    }
+
+    /// Returns the underlying table.
+    pub fn table(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
+/// A lazy typed view over the key-value pairs of a Lua table.
+///
+/// Access is raw (without invoking metamethods).
+///
+/// This struct is created by the [`Table::as_map`] method.
+///
+/// [`Table::as_map`]: crate::Table::as_map
+pub struct MapView<'lua, K, V> {
+    table: Table<'lua>,
+    _phantom: PhantomData<(K, V)>,
+}
+
+impl<'lua, K: FromLua<'lua>, V: FromLua<'lua>> MapView<'lua, K, V> {
+    /// Returns the value associated with `key`, or `None` if the key is absent.
+    pub fn get<Q: IntoLua<'lua>>(&self, key: Q) -> Result<Option<V>> {
+        let lua = self.table.0.lua;
+        let key = key.into_lua(lua)?;
+        match self.table.raw_get::<_, Value>(key.clone())? {
+            Value::Nil => Ok(None),
+            value => V::from_lua(value, lua)
+                .map(Some)
+                .map_err(|err| err.with_conversion_path(|| path_segment(&key))),
+        }
+    }
+
+    /// Returns `true` if the table contains a non-nil value for `key`.
+    pub fn contains_key<Q: IntoLua<'lua>>(&self, key: Q) -> Result<bool> {
+        Ok(self.table.raw_get::<_, Value>(key)? != Value::Nil)
+    }
+
+    /// Returns the number of key-value pairs in the table.
+    ///
+    /// This walks the whole table.
+    pub fn len(&self) -> Result<usize> {
+        let mut len = 0;
+        for pair in self.table.clone().pairs::<Value, Value>() {
+            pair?;
+            len += 1;
+        }
+        Ok(len)
+    }
+
+    /// Returns `true` if the table has no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Returns an iterator over the key-value pairs, converting them on access.
+    pub fn iter(&self) -> TablePairs<'lua, K, V> {
+        self.table.clone().pairs()
+    }
+
+    /// Returns the underlying table.
+    pub fn table(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
+/// A view of a Lua table where all operations are raw (without invoking metamethods).
+///
+/// This struct is created by the [`Table::raw`] method.
+///
+/// [`Table::raw`]: crate::Table::raw
+#[derive(Clone, Debug)]
+pub struct RawTable<'lua> {
+    table: Table<'lua>,
+}
+
+impl<'lua> RawTable<'lua> {
+    /// Gets the value associated to `key`, see [`Table::raw_get`].
+    ///
+    /// [`Table::raw_get`]: crate::Table::raw_get
+    pub fn get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        self.table.raw_get(key)
+    }
+
+    /// Sets a key-value pair, see [`Table::raw_set`].
+    ///
+    /// [`Table::raw_set`]: crate::Table::raw_set
+    pub fn set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        self.table.raw_set(key, value)
+    }
+
+    /// Returns the result of the `#` operator, see [`Table::raw_len`].
+    ///
+    /// [`Table::raw_len`]: crate::Table::raw_len
+    pub fn len(&self) -> Integer {
+        self.table.raw_len()
+    }
+
+    /// Returns `true` if the table is empty, see [`Table::is_empty`].
+    ///
+    /// [`Table::is_empty`]: crate::Table::is_empty
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Returns an iterator over the pairs of the table, see [`Table::pairs`].
+    ///
+    /// [`Table::pairs`]: crate::Table::pairs
+    pub fn pairs<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> TablePairs<'lua, K, V> {
+        self.table.clone().pairs()
+    }
+
+    /// Returns the underlying table.
+    pub fn table(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
+/// A view of a Lua table where all operations invoke metamethods.
+///
+/// This struct is created by the [`Table::with_meta`] method.
+///
+/// [`Table::with_meta`]: crate::Table::with_meta
+#[derive(Clone, Debug)]
+pub struct TableWithMeta<'lua> {
+    table: Table<'lua>,
+}
+
+impl<'lua> TableWithMeta<'lua> {
+    /// Gets the value associated to `key`, invoking the `__index` metamethod, see [`Table::get`].
+    ///
+    /// [`Table::get`]: crate::Table::get
+    pub fn get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        self.table.get(key)
+    }
+
+    /// Sets a key-value pair, invoking the `__newindex` metamethod, see [`Table::set`].
+    ///
+    /// [`Table::set`]: crate::Table::set
+    pub fn set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        self.table.set(key, value)
+    }
+
+    /// Returns the result of the `#` operator, invoking the `__len` metamethod, see
+    /// [`Table::len`].
+    ///
+    /// [`Table::len`]: crate::Table::len
+    pub fn len(&self) -> Result<Integer> {
+        self.table.len()
+    }
+
+    /// Returns `true` if the `#` operator returns zero.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns an iterator over the pairs of the table, invoking the `__pairs` metamethod if
+    /// present (on Lua 5.2+), like the Lua `pairs` function.
+    pub fn pairs<K: FromLua<'lua>, V: FromLua<'lua>>(&self) -> Result<MetaPairs<'lua, K, V>> {
+        #[cfg(any(
+            feature = "lua54",
+            feature = "lua53",
+            feature = "lua52",
+            feature = "luajit52"
+        ))]
+        if let Some(mt) = self.table.get_metatable() {
+            if let Some(pairs) = mt.raw_get::<_, Option<Function>>("__pairs")? {
+                let (next, state, control) = pairs.call(self.table.clone())?;
+                return Ok(MetaPairs(MetaPairsInner::Metamethod {
+                    next,
+                    state,
+                    control: Some(control),
+                    _phantom: PhantomData,
+                }));
+            }
+        }
+        Ok(MetaPairs(MetaPairsInner::Raw(self.table.clone().pairs())))
+    }
+
+    /// Returns the underlying table.
+    pub fn table(&self) -> &Table<'lua> {
+        &self.table
+    }
+}
+
+/// An iterator over the pairs of a Lua table, invoking the `__pairs` metamethod.
+///
+/// This struct is created by the [`TableWithMeta::pairs`] method.
+pub struct MetaPairs<'lua, K, V>(MetaPairsInner<'lua, K, V>);
+
+enum MetaPairsInner<'lua, K, V> {
+    Raw(TablePairs<'lua, K, V>),
+    #[cfg(any(
+        feature = "lua54",
+        feature = "lua53",
+        feature = "lua52",
+        feature = "luajit52"
+    ))]
+    Metamethod {
+        next: Function<'lua>,
+        state: Value<'lua>,
+        // `None` when the iteration is finished
+        control: Option<Value<'lua>>,
+        _phantom: PhantomData<(K, V)>,
+    },
+}
+
+impl<'lua, K, V> Iterator for MetaPairs<'lua, K, V>
+where
+    K: FromLua<'lua>,
+    V: FromLua<'lua>,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0 {
+            MetaPairsInner::Raw(ref mut pairs) => pairs.next(),
+            #[cfg(any(
+                feature = "lua54",
+                feature = "lua53",
+                feature = "lua52",
+                feature = "luajit52"
+            ))]
+            MetaPairsInner::Metamethod {
+                ref next,
+                ref state,
+                ref mut control,
+                ..
+            } => {
+                let lua = next.0.lua;
+                let prev = control.take()?;
+                let res = (|| {
+                    let (key, value) = next.call::<_, (Value, Value)>((state.clone(), prev))?;
+                    if key == Nil {
+                        return Ok(None);
+                    }
+                    let ret_key = K::from_lua(key.clone(), lua)?;
+                    let value = V::from_lua(value, lua)
+                        .map_err(|err| err.with_conversion_path(|| path_segment(&key)))?;
+                    Ok(Some((key, ret_key, value)))
+                })();
+                match res {
+                    Ok(Some((key, ret_key, value))) => {
+                        *control = Some(key);
+                        Some(Ok((ret_key, value)))
+                    }
+                    Ok(None) => None,
+                    Err(err) => Some(Err(err)),
+                }
+            }
+        }
+    }
+}
+
+/// A builder of a metatable, with typed setters for metamethods.
+///
+/// This struct is created by the [`Table::set_metatable_with`] method.
+///
+/// [`Table::set_metatable_with`]: crate::Table::set_metatable_with
+pub struct MetatableBuilder<'lua> {
+    metatable: Table<'lua>,
+    // First error raised while building, returned by `Table::set_metatable_with`
+    error: Option<Error>,
+}
+
+macro_rules! binary_metamethod {
+    ($(#[$meta:meta])* $name:ident, $method:expr) => {
+        $(#[$meta])*
+        pub fn $name<R, F>(&mut self, func: F) -> &mut Self
+        where
+            R: IntoLua<'lua>,
+            F: 'static + MaybeSend + Fn(&'lua Lua, Value<'lua>, Value<'lua>) -> Result<R>,
+        {
+            self.set_function($method, move |lua, (a, b)| func(lua, a, b))
+        }
+    };
+}
+
+impl<'lua> MetatableBuilder<'lua> {
+    /// Sets the `__index` metamethod to a fallback table for missing keys.
+    pub fn index_table(&mut self, table: Table<'lua>) -> &mut Self {
+        self.set(MetaMethod::Index, table)
+    }
+
+    /// Sets the `__index` metamethod, called with the table and a missing key.
+    pub fn on_index<K, R, F>(&mut self, func: F) -> &mut Self
+    where
+        K: FromLua<'lua>,
+        R: IntoLua<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>, K) -> Result<R>,
+    {
+        self.set_function(MetaMethod::Index, move |lua, (t, k)| func(lua, t, k))
+    }
+
+    /// Sets the `__newindex` metamethod, called with the table, a missing key and the value
+    /// being assigned.
+    pub fn on_newindex<K, V, F>(&mut self, func: F) -> &mut Self
+    where
+        K: FromLua<'lua>,
+        V: FromLua<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>, K, V) -> Result<()>,
+    {
+        self.set_function(MetaMethod::NewIndex, move |lua, (t, k, v)| {
+            func(lua, t, k, v)
+        })
+    }
+
+    /// Sets the `__call` metamethod, called with the table and the call arguments.
+    pub fn on_call<A, R, F>(&mut self, func: F) -> &mut Self
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>, A) -> Result<R>,
+    {
+        self.set_function(MetaMethod::Call, move |lua, (t, args)| func(lua, t, args))
+    }
+
+    /// Sets the `__tostring` metamethod.
+    pub fn on_tostring<F>(&mut self, func: F) -> &mut Self
+    where
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>) -> Result<StdString>,
+    {
+        self.set_function(MetaMethod::ToString, func)
+    }
+
+    /// Sets the `__len` metamethod.
+    ///
+    /// Lua 5.1 and LuaJIT (without 5.2 compatibility) do not call it for tables.
+    pub fn on_len<R, F>(&mut self, func: F) -> &mut Self
+    where
+        R: IntoLua<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>) -> Result<R>,
+    {
+        self.set_function(MetaMethod::Len, func)
+    }
+
+    /// Sets the `__eq` metamethod, called when comparing two different tables.
+    pub fn on_eq<F>(&mut self, func: F) -> &mut Self
+    where
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>, Table<'lua>) -> Result<bool>,
+    {
+        self.set_function(MetaMethod::Eq, move |lua, (a, b)| func(lua, a, b))
+    }
+
+    /// Sets the `__lt` metamethod (`<` operator).
+    pub fn on_lt<F>(&mut self, func: F) -> &mut Self
+    where
+        F: 'static + MaybeSend + Fn(&'lua Lua, Value<'lua>, Value<'lua>) -> Result<bool>,
+    {
+        self.set_function(MetaMethod::Lt, move |lua, (a, b)| func(lua, a, b))
+    }
+
+    /// Sets the `__le` metamethod (`<=` operator).
+    pub fn on_le<F>(&mut self, func: F) -> &mut Self
+    where
+        F: 'static + MaybeSend + Fn(&'lua Lua, Value<'lua>, Value<'lua>) -> Result<bool>,
+    {
+        self.set_function(MetaMethod::Le, move |lua, (a, b)| func(lua, a, b))
+    }
+
+    /// Sets the `__unm` metamethod (unary `-` operator).
+    pub fn on_unm<R, F>(&mut self, func: F) -> &mut Self
+    where
+        R: IntoLua<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, Table<'lua>) -> Result<R>,
+    {
+        self.set_function(MetaMethod::Unm, func)
+    }
+
+    binary_metamethod!(
+        /// Sets the `__add` metamethod (`+` operator).
+        on_add,
+        MetaMethod::Add
+    );
+    binary_metamethod!(
+        /// Sets the `__sub` metamethod (`-` operator).
+        on_sub,
+        MetaMethod::Sub
+    );
+    binary_metamethod!(
+        /// Sets the `__mul` metamethod (`*` operator).
+        on_mul,
+        MetaMethod::Mul
+    );
+    binary_metamethod!(
+        /// Sets the `__div` metamethod (`/` operator).
+        on_div,
+        MetaMethod::Div
+    );
+    binary_metamethod!(
+        /// Sets the `__mod` metamethod (`%` operator).
+        on_mod,
+        MetaMethod::Mod
+    );
+    binary_metamethod!(
+        /// Sets the `__pow` metamethod (`^` operator).
+        on_pow,
+        MetaMethod::Pow
+    );
+    binary_metamethod!(
+        /// Sets the `__concat` metamethod (`..` operator).
+        on_concat,
+        MetaMethod::Concat
+    );
+
+    /// Sets any metamethod (or metatable field) to the given value.
+    ///
+    /// This is useful for metamethods without a dedicated setter, eg. `__pairs` or `__close`.
+    pub fn set(&mut self, method: MetaMethod, value: impl IntoLua<'lua>) -> &mut Self {
+        if self.error.is_none() {
+            if let Err(err) = self.metatable.raw_set(method.name(), value) {
+                self.error = Some(err);
+            }
+        }
+        self
+    }
+
+    fn set_function<A, R, F>(&mut self, method: MetaMethod, func: F) -> &mut Self
+    where
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+        F: 'static + MaybeSend + Fn(&'lua Lua, A) -> Result<R>,
+    {
+        match self.metatable.0.lua.create_function(func) {
+            Ok(func) => self.set(method, func),
+            Err(err) => {
+                self.error.get_or_insert(err);
+                self
+            }
+        }
+    }
+}
+
+// Formats a dot-separated path of string keys as a conversion error path
+fn path_segments(path: &str) -> std::string::String {
+    (path.split('.'))
+        .map(|key| format!("[\"{}\"]", key.escape_debug()))
+        .collect()
+}
+
+// Checks the number of table entries converted into a Rust collection against `ConversionLimits`
+fn check_table_entries(lua: &Lua, to: &'static str, n: usize) -> Result<()> {
+    (lua.conversion_limits().check_table_entries(n)).map_err(|message| {
+        Error::FromLuaConversionError {
+            from: "table",
+            to,
+            message: Some(message),
+        }
+    })
+}
+
+// Formats a table key as a segment of a conversion error path
+fn path_segment(key: &Value) -> std::string::String {
+    match key.preview() {
+        Some(key) => format!("[{key}]"),
+        None => format!("[{}]", key.type_name()),
+    }
+}
+
+// Registry key of the weak-keyed table holding the next key to remove by
+// `Table::clear_incremental`
+const CLEAR_CURSORS_REGISTRY_KEY: &str = "__mlua_clear_cursors";
+
+fn clear_cursors_table<'lua>(lua: &'lua Lua) -> Result<Table<'lua>> {
+    if let Some(cursors) = lua.named_registry_value::<Option<Table>>(CLEAR_CURSORS_REGISTRY_KEY)? {
+        return Ok(cursors);
+    }
+    let cursors = lua.create_table()?;
+    cursors.set_metatable(Some(lua.create_table_from([("__mode", "k")])?));
+    lua.set_named_registry_value(CLEAR_CURSORS_REGISTRY_KEY, cursors.clone())?;
+    Ok(cursors)
+}
+
 #[cfg(test)]
 mod assertions {
     use super::*;
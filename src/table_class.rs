@@ -0,0 +1,175 @@
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::userdata::MetaMethod;
+use crate::value::{FromLuaMulti, IntoLua, IntoLuaMulti, Value};
+
+// Registry key of the table mapping class names to their metatables
+const TABLE_CLASSES_REGISTRY_KEY: &str = "__mlua_table_classes";
+
+/// Registrar of methods for a table-based class.
+///
+/// This struct is passed to the closure given to [`Lua::register_table_class`]. Instances of the
+/// class are plain Lua tables holding the fields, methods are looked up in a shared metatable.
+///
+/// [`Lua::register_table_class`]: crate::Lua::register_table_class
+pub struct TableClassRegistrar<'lua> {
+    lua: &'lua Lua,
+    methods: Table<'lua>,
+    metatable: Table<'lua>,
+    // User-defined `__index` metamethod, called for keys not found in `methods`
+    index: Option<Function<'lua>>,
+    // First error raised while registering, returned by `Lua::register_table_class`
+    error: Option<Error>,
+}
+
+impl<'lua> TableClassRegistrar<'lua> {
+    /// Adds a field shared by all instances of the class, eg. a constant.
+    ///
+    /// Instance fields with the same name take precedence.
+    pub fn add_field<V: IntoLua<'lua>>(&mut self, name: impl AsRef<str>, value: V) {
+        let res = self.methods.raw_set(name.as_ref(), value);
+        self.check(res);
+    }
+
+    /// Adds a regular method which accepts the instance table as the first parameter.
+    ///
+    /// The method can be called from Lua using `instance:method(...)`.
+    pub fn add_method<M, A, R>(&mut self, name: impl AsRef<str>, method: M)
+    where
+        M: Fn(&'lua Lua, Table<'lua>, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.add_function(name, move |lua, (this, args)| method(lua, this, args));
+    }
+
+    /// Adds a regular method as a function which accepts generic arguments.
+    pub fn add_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let res = (self.lua.create_function(function))
+            .and_then(|func| self.methods.raw_set(name.as_ref(), func));
+        self.check(res);
+    }
+
+    /// Adds a metamethod which accepts the instance table as the first parameter.
+    ///
+    /// An `__index` metamethod is called only for keys that are neither instance fields nor
+    /// methods of the class.
+    ///
+    /// # Note
+    ///
+    /// This can cause an error with certain binary metamethods that can trigger if only the right
+    /// side has a metatable. To prevent this, use [`add_meta_function`].
+    ///
+    /// [`add_meta_function`]: #method.add_meta_function
+    pub fn add_meta_method<M, A, R>(&mut self, name: impl AsRef<str>, method: M)
+    where
+        M: Fn(&'lua Lua, Table<'lua>, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.add_meta_function(name, move |lua, (this, args)| method(lua, this, args));
+    }
+
+    /// Adds a metamethod as a function which accepts generic arguments.
+    pub fn add_meta_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let res = (|| {
+            let name = MetaMethod::validate(name.as_ref())?;
+            let func = self.lua.create_function(function)?;
+            if name == MetaMethod::Index.name() {
+                self.index = Some(func);
+                return Ok(());
+            }
+            self.metatable.raw_set(name, func)
+        })();
+        self.check(res);
+    }
+
+    fn check(&mut self, res: Result<()>) {
+        if let Err(err) = res {
+            self.error.get_or_insert(err);
+        }
+    }
+}
+
+fn table_classes<'lua>(lua: &'lua Lua) -> Result<Table<'lua>> {
+    if let Some(classes) = lua.named_registry_value::<Option<Table>>(TABLE_CLASSES_REGISTRY_KEY)? {
+        return Ok(classes);
+    }
+    let classes = lua.create_table()?;
+    lua.set_named_registry_value(TABLE_CLASSES_REGISTRY_KEY, classes.clone())?;
+    Ok(classes)
+}
+
+pub(crate) fn register_table_class<'lua>(
+    lua: &'lua Lua,
+    name: &str,
+    f: impl FnOnce(&mut TableClassRegistrar<'lua>),
+) -> Result<Table<'lua>> {
+    let mut registrar = TableClassRegistrar {
+        lua,
+        methods: lua.create_table()?,
+        metatable: lua.create_table()?,
+        index: None,
+        error: None,
+    };
+    f(&mut registrar);
+    if let Some(err) = registrar.error {
+        return Err(err);
+    }
+
+    let TableClassRegistrar {
+        methods,
+        metatable,
+        index,
+        ..
+    } = registrar;
+    let index = match index {
+        None => Value::Table(methods),
+        Some(index) => {
+            // Look up methods first, then fall back to the user-defined metamethod
+            let chunk = r#"
+                local methods, index = ...
+                return function(self, key)
+                    local value = methods[key]
+                    if value ~= nil then
+                        return value
+                    end
+                    return index(self, key)
+                end
+            "#;
+            let make_index = lua
+                .load(chunk)
+                .set_name("=__mlua_table_class")
+                .into_function()?;
+            Value::Function(make_index.call((methods, index))?)
+        }
+    };
+    metatable.raw_set(MetaMethod::Index.name(), index)?;
+    metatable.raw_set("__name", name)?;
+
+    table_classes(lua)?.raw_set(name, metatable.clone())?;
+    Ok(metatable)
+}
+
+// Returns the metatable of a registered class
+pub(crate) fn class_metatable<'lua>(lua: &'lua Lua, name: &str) -> Result<Table<'lua>> {
+    match table_classes(lua)?.raw_get::<_, Option<Table>>(name)? {
+        Some(metatable) => Ok(metatable),
+        None => Err(Error::RuntimeError(format!(
+            "table class '{name}' is not registered"
+        ))),
+    }
+}
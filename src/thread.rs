@@ -8,11 +8,6 @@ use crate::types::LuaRef;
 use crate::util::{check_stack, error_traceback_thread, pop_error, StackGuard};
 use crate::value::{FromLuaMulti, IntoLuaMulti};
 
-#[cfg(any(
-    feature = "lua54",
-    all(feature = "luajit", feature = "vendored"),
-    feature = "luau",
-))]
 use crate::function::Function;
 
 #[cfg(not(feature = "luau"))]
@@ -24,6 +19,7 @@ use crate::{
 #[cfg(feature = "async")]
 use {
     crate::{
+        executor::TaskId,
         lua::ASYNC_POLL_PENDING,
         value::{MultiValue, Value},
     },
@@ -56,6 +52,62 @@ pub enum ThreadStatus {
 #[derive(Clone, Debug)]
 pub struct Thread<'lua>(pub(crate) LuaRef<'lua>);
 
+/// Owned handle to an internal Lua thread (or coroutine).
+///
+/// The owned handle holds a *strong* reference to the current Lua instance.
+/// Be warned, if you place it into a Lua type (eg. [`UserData`] or a Rust callback), it is *very easy*
+/// to accidentally cause reference cycles that would prevent destroying Lua instance.
+///
+/// [`UserData`]: crate::UserData
+#[cfg(feature = "unstable")]
+#[cfg_attr(docsrs, doc(cfg(feature = "unstable")))]
+#[derive(Clone, Debug)]
+pub struct OwnedThread(pub(crate) crate::types::LuaOwnedRef);
+
+#[cfg(feature = "unstable")]
+impl OwnedThread {
+    /// Get borrowed handle to the underlying Lua thread.
+    #[cfg_attr(feature = "send", allow(unused))]
+    pub const fn to_ref(&self) -> Thread {
+        Thread(self.0.to_ref())
+    }
+}
+
+/// Builder for a Lua thread (coroutine) with custom execution limits.
+///
+/// Limits are enforced using a hook function that is called every time the thread calls a
+/// function. Once a limit is exceeded, the call fails with a [`RuntimeError`] that can be caught
+/// by Lua code (using `pcall`) or by the host, instead of exhausting the Lua (or host C) stack.
+///
+/// The limits are checked by the same hook dispatcher as [`Lua::set_hook()`] and
+/// [`Thread::set_hook()`], so hooks set for the thread (or for the main thread) are called as usual
+/// and do not remove the limits. Coroutines created by the thread do not inherit the limits.
+///
+/// Created by [`Lua::thread_builder()`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Error, Function, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let f: Function = lua.load("local function f(n) return n == 0 and 0 or 1 + f(n - 1) end; return f").eval()?;
+/// let thread = lua.thread_builder().stack_limit(100).build(f)?;
+/// assert!(matches!(thread.resume::<_, i32>(1000), Err(Error::RuntimeError(_))));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`RuntimeError`]: crate::Error::RuntimeError
+#[cfg(not(feature = "luau"))]
+#[cfg_attr(docsrs, doc(cfg(not(feature = "luau"))))]
+#[must_use = "`ThreadBuilder` does nothing until `build` is called"]
+pub struct ThreadBuilder<'lua> {
+    lua: &'lua Lua,
+    stack_limit: Option<usize>,
+    c_call_limit: Option<usize>,
+}
+
 /// Thread (coroutine) representation as an async [`Future`] or [`Stream`].
 ///
 /// Requires `feature = "async"`
@@ -70,6 +122,8 @@ pub struct AsyncThread<'lua, R> {
     args0: Option<Result<MultiValue<'lua>>>,
     ret: PhantomData<R>,
     recycle: bool,
+    poll_budget: Option<usize>,
+    task_id: TaskId,
 }
 
 impl<'lua> Thread<'lua> {
@@ -88,6 +142,10 @@ impl<'lua> Thread<'lua> {
     /// If the thread calls `coroutine.yield`, returns the values passed to `yield`. If the thread
     /// `return`s values from its main function, returns those.
     ///
+    /// If the thread raises an error, the returned error includes a traceback of the coroutine's
+    /// own stack: it's appended to the message of [`Error::RuntimeError`] for Lua errors, and
+    /// stored in [`Error::CallbackError`] for errors returned by Rust callbacks.
+    ///
     /// # Examples
     ///
     /// ```
@@ -232,21 +290,9 @@ impl<'lua> Thread<'lua> {
 
             lua.push_ref(&self.0);
             let thread_state = ffi::lua_tothread(state, -1);
+            close_thread(state, thread_state)?;
 
-            #[cfg(all(feature = "lua54", not(feature = "vendored")))]
-            let status = ffi::lua_resetthread(thread_state);
-            #[cfg(all(feature = "lua54", feature = "vendored"))]
-            let status = ffi::lua_closethread(thread_state, state);
-            #[cfg(feature = "lua54")]
-            if status != ffi::LUA_OK {
-                return Err(pop_error(thread_state, status));
-            }
-            #[cfg(all(feature = "luajit", feature = "vendored"))]
-            ffi::lua_resetthread(state, thread_state);
-            #[cfg(feature = "luau")]
-            ffi::lua_resetthread(thread_state);
-
-            lua.push_ref(&func.0);
+            lua.push_ref_checked(&func.0)?;
             ffi::lua_xmove(state, thread_state, 1);
 
             #[cfg(feature = "luau")]
@@ -260,6 +306,59 @@ impl<'lua> Thread<'lua> {
         }
     }
 
+    /// Closes a thread, making it dead.
+    ///
+    /// This is equivalent to `coroutine.close` in Lua 5.4. For Lua 5.4 pending to-be-closed
+    /// variables of a suspended thread are closed, and the error that stopped the thread (or
+    /// raised while closing the variables) is returned. In LuaJIT and Luau the thread is reset
+    /// to an empty state.
+    ///
+    /// Running threads (including those which resumed another thread) cannot be closed.
+    ///
+    /// Requires `feature = "lua54"` OR `feature = "luajit,vendored"` OR `feature = "luau"`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, Thread, ThreadStatus};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// let thread: Thread = lua.load(r#"
+    ///     coroutine.create(function()
+    ///         coroutine.yield(1)
+    ///         coroutine.yield(2)
+    ///     end)
+    /// "#).eval()?;
+    ///
+    /// assert_eq!(thread.resume::<_, i32>(())?, 1);
+    /// thread.close()?;
+    /// assert_eq!(thread.status(), ThreadStatus::Unresumable);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(any(
+        feature = "lua54",
+        all(feature = "luajit", feature = "vendored"),
+        feature = "luau",
+    ))]
+    pub fn close(&self) -> Result<()> {
+        let lua = self.0.lua;
+        let state = lua.state();
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 1)?;
+
+            lua.push_ref(&self.0);
+            let thread_state = ffi::lua_tothread(state, -1);
+            if ffi::lua_status(thread_state) == ffi::LUA_OK && has_frames(thread_state) {
+                return Err(Error::RuntimeError(
+                    "cannot close a running coroutine".to_string(),
+                ));
+            }
+            close_thread(state, thread_state)
+        }
+    }
+
     /// Converts Thread to an AsyncThread which implements [`Future`] and [`Stream`] traits.
     ///
     /// `args` are passed as arguments to the thread function for first call.
@@ -314,6 +413,8 @@ impl<'lua> Thread<'lua> {
     {
         let args = args.into_lua_multi(self.0.lua);
         AsyncThread {
+            poll_budget: self.0.lua.async_poll_budget(),
+            task_id: self.0.lua.next_task_id(),
             thread: self,
             args0: Some(args),
             ret: PhantomData,
@@ -365,6 +466,212 @@ impl<'lua> Thread<'lua> {
             protect_lua!(state, 0, 0, |_| ffi::luaL_sandboxthread(thread))
         }
     }
+
+    /// Convert this handle to owned version.
+    #[cfg(all(feature = "unstable", any(not(feature = "send"), doc)))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "unstable", not(feature = "send")))))]
+    #[inline]
+    pub fn into_owned(self) -> OwnedThread {
+        OwnedThread(self.0.into_owned())
+    }
+}
+
+// Closes pending to-be-closed variables of the thread and resets it
+#[cfg(any(
+    feature = "lua54",
+    all(feature = "luajit", feature = "vendored"),
+    feature = "luau",
+))]
+unsafe fn close_thread(
+    state: *mut ffi::lua_State,
+    thread_state: *mut ffi::lua_State,
+) -> Result<()> {
+    #[cfg(all(feature = "lua54", not(feature = "vendored")))]
+    let status = ffi::lua_resetthread(thread_state);
+    #[cfg(all(feature = "lua54", feature = "vendored"))]
+    let status = ffi::lua_closethread(thread_state, state);
+    #[cfg(feature = "lua54")]
+    if status != ffi::LUA_OK {
+        return Err(pop_error(thread_state, status));
+    }
+    #[cfg(all(feature = "luajit", feature = "vendored"))]
+    ffi::lua_resetthread(state, thread_state);
+    #[cfg(feature = "luau")]
+    {
+        let _ = state;
+        ffi::lua_resetthread(thread_state);
+    }
+    Ok(())
+}
+
+// Checks whether the thread has active call frames (it's running or resumed another thread)
+unsafe fn has_frames(thread_state: *mut ffi::lua_State) -> bool {
+    #[cfg(not(feature = "luau"))]
+    {
+        let mut ar: ffi::lua_Debug = std::mem::zeroed();
+        ffi::lua_getstack(thread_state, 0, &mut ar) != 0
+    }
+    #[cfg(feature = "luau")]
+    {
+        ffi::lua_stackdepth(thread_state) > 0
+    }
+}
+
+// Closes all suspended (yielded or failed) threads reachable from the registry.
+//
+// Errors raised while closing threads are ignored. Returns the number of closed threads.
+#[cfg(any(
+    feature = "lua54",
+    all(feature = "luajit", feature = "vendored"),
+    feature = "luau",
+))]
+pub(crate) fn close_suspended_threads(lua: &Lua) -> Result<usize> {
+    let state = lua.state();
+    let mut closed = 0;
+    for thread in reachable_threads(lua)? {
+        unsafe {
+            let thread_state = ffi::lua_tothread(lua.ref_thread(), thread.0.index);
+            if ffi::lua_status(thread_state) != ffi::LUA_OK {
+                let _sg = StackGuard::new(state);
+                let _ = close_thread(state, thread_state);
+                closed += 1;
+            }
+        }
+    }
+    Ok(closed)
+}
+
+// Walks all objects reachable from the registry and returns the found threads
+#[cfg(any(
+    feature = "lua54",
+    all(feature = "luajit", feature = "vendored"),
+    feature = "luau",
+))]
+fn reachable_threads<'lua>(lua: &'lua Lua) -> Result<Vec<Thread<'lua>>> {
+    use crate::value::Value;
+    use rustc_hash::FxHashSet;
+
+    let state = lua.state();
+    let mut visited = FxHashSet::default();
+    let mut queue = Vec::new();
+    let mut threads = Vec::new();
+
+    unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 1)?;
+        ffi::lua_pushvalue(state, ffi::LUA_REGISTRYINDEX);
+        queue.push(lua.pop_value());
+    }
+
+    // Pushes the value on top of the stack to the queue, if it can reference other objects
+    let mut enqueue = |queue: &mut Vec<Value<'lua>>| unsafe {
+        match ffi::lua_type(state, -1) {
+            ffi::LUA_TTABLE | ffi::LUA_TFUNCTION | ffi::LUA_TUSERDATA | ffi::LUA_TTHREAD
+                if visited.insert(ffi::lua_topointer(state, -1)) =>
+            {
+                queue.push(lua.pop_value())
+            }
+            _ => ffi::lua_pop(state, 1),
+        }
+    };
+
+    while let Some(value) = queue.pop() {
+        unsafe {
+            let _sg = StackGuard::new(state);
+            check_stack(state, 5)?;
+            lua.push_value(value)?;
+
+            if ffi::lua_getmetatable(state, -1) != 0 {
+                enqueue(&mut queue);
+            }
+            match ffi::lua_type(state, -1) {
+                ffi::LUA_TTABLE => {
+                    ffi::lua_pushnil(state);
+                    while ffi::lua_next(state, -2) != 0 {
+                        ffi::lua_pushvalue(state, -2);
+                        enqueue(&mut queue);
+                        enqueue(&mut queue);
+                    }
+                }
+                ffi::LUA_TFUNCTION => {
+                    for n in 1.. {
+                        if ffi::lua_getupvalue(state, -1, n).is_null() {
+                            break;
+                        }
+                        enqueue(&mut queue);
+                    }
+                    #[cfg(any(feature = "luajit", feature = "luau"))]
+                    {
+                        ffi::lua_getfenv(state, -1);
+                        enqueue(&mut queue);
+                    }
+                }
+                ffi::LUA_TUSERDATA => {
+                    #[cfg(feature = "lua54")]
+                    for n in 1.. {
+                        if ffi::lua_getiuservalue(state, -1, n) == ffi::LUA_TNONE {
+                            ffi::lua_pop(state, 1);
+                            break;
+                        }
+                        enqueue(&mut queue);
+                    }
+                    #[cfg(feature = "luajit")]
+                    {
+                        ffi::lua_getfenv(state, -1);
+                        enqueue(&mut queue);
+                    }
+                }
+                ffi::LUA_TTHREAD => {
+                    let thread_state = ffi::lua_tothread(state, -1);
+                    check_stack(thread_state, 2)?;
+                    // Values on the stack and local variables of the active frames
+                    for i in 1..=ffi::lua_gettop(thread_state) {
+                        ffi::lua_pushvalue(thread_state, i);
+                        ffi::lua_xmove(thread_state, state, 1);
+                        enqueue(&mut queue);
+                    }
+                    for level in 0.. {
+                        #[cfg(not(feature = "luau"))]
+                        {
+                            let mut ar: ffi::lua_Debug = std::mem::zeroed();
+                            if ffi::lua_getstack(thread_state, level, &mut ar) == 0 {
+                                break;
+                            }
+                            ffi::lua_getinfo(thread_state, cstr!("f"), &mut ar);
+                            ffi::lua_xmove(thread_state, state, 1);
+                            enqueue(&mut queue);
+                            for n in 1.. {
+                                if ffi::lua_getlocal(thread_state, &ar, n).is_null() {
+                                    break;
+                                }
+                                ffi::lua_xmove(thread_state, state, 1);
+                                enqueue(&mut queue);
+                            }
+                        }
+                        #[cfg(feature = "luau")]
+                        {
+                            let mut ar: ffi::lua_Debug = std::mem::zeroed();
+                            if ffi::lua_getinfo(thread_state, level, cstr!("f"), &mut ar) == 0 {
+                                break;
+                            }
+                            ffi::lua_xmove(thread_state, state, 1);
+                            enqueue(&mut queue);
+                            for n in 1.. {
+                                if ffi::lua_getlocal(thread_state, level, n).is_null() {
+                                    break;
+                                }
+                                ffi::lua_xmove(thread_state, state, 1);
+                                enqueue(&mut queue);
+                            }
+                        }
+                    }
+                    threads.push(Thread(lua.pop_ref()));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(threads)
 }
 
 impl<'lua> PartialEq for Thread<'lua> {
@@ -373,8 +680,91 @@ impl<'lua> PartialEq for Thread<'lua> {
     }
 }
 
+// Additional shortcuts
+#[cfg(feature = "unstable")]
+impl OwnedThread {
+    /// Resumes execution of this thread.
+    ///
+    /// This is a shortcut for [`Thread::resume()`].
+    #[inline]
+    pub fn resume<'lua, A, R>(&'lua self, args: A) -> Result<R>
+    where
+        A: IntoLuaMulti<'lua>,
+        R: FromLuaMulti<'lua>,
+    {
+        self.to_ref().resume(args)
+    }
+
+    /// Gets the status of the thread.
+    ///
+    /// This is a shortcut for [`Thread::status()`].
+    #[inline]
+    pub fn status(&self) -> ThreadStatus {
+        self.to_ref().status()
+    }
+}
+
+#[cfg(not(feature = "luau"))]
+impl<'lua> ThreadBuilder<'lua> {
+    pub(crate) fn new(lua: &'lua Lua) -> Self {
+        ThreadBuilder {
+            lua,
+            stack_limit: None,
+            c_call_limit: None,
+        }
+    }
+
+    /// Sets the maximum depth of the thread call stack (the number of active function calls).
+    pub fn stack_limit(mut self, limit: usize) -> Self {
+        self.stack_limit = Some(limit);
+        self
+    }
+
+    /// Sets the maximum number of nested C (including Rust) function calls on the thread call
+    /// stack.
+    ///
+    /// It's useful to prevent scripts from recursing through Rust callbacks, as every such call
+    /// consumes the host C stack.
+    pub fn c_call_limit(mut self, limit: usize) -> Self {
+        self.c_call_limit = Some(limit);
+        self
+    }
+
+    /// Wraps a Lua function into a new thread (or coroutine) with the configured limits.
+    pub fn build(self, func: Function<'lua>) -> Result<Thread<'lua>> {
+        let lua = self.lua;
+        let thread = lua.create_thread(func)?;
+        if self.stack_limit.is_some() || self.c_call_limit.is_some() {
+            unsafe {
+                let thread_state = ffi::lua_tothread(lua.ref_thread(), thread.0.index);
+                lua.set_thread_limits(thread_state, self.stack_limit, self.c_call_limit)?;
+            }
+        }
+        Ok(thread)
+    }
+}
+
 #[cfg(feature = "async")]
 impl<'lua, R> AsyncThread<'lua, R> {
+    /// Sets the maximum number of futures polled per resume of this thread.
+    ///
+    /// Overrides the default set by [`Lua::set_async_poll_budget`].
+    pub fn set_poll_budget(&mut self, budget: Option<usize>) {
+        self.poll_budget = budget.map(|n| n.max(1));
+    }
+
+    /// Returns the id of the task this thread runs, unless it's polled within another task.
+    ///
+    /// See [`Lua::current_task_id`].
+    pub fn task_id(&self) -> TaskId {
+        self.task_id
+    }
+
+    #[inline]
+    pub(crate) fn set_task_id(&mut self, id: TaskId) {
+        self.task_id = id;
+    }
+
     #[inline]
     pub(crate) fn set_recyclable(&mut self, recyclable: bool) {
         self.recycle = recyclable;
@@ -423,7 +813,7 @@ where
             _ => return Poll::Ready(None),
         };
 
-        let _wg = WakerGuard::new(lua, cx.waker());
+        let _wg = WakerGuard::new(lua, cx.waker(), self.poll_budget, self.task_id);
 
         // This is safe as we are not moving the whole struct
         let this = unsafe { self.get_unchecked_mut() };
@@ -457,7 +847,7 @@ where
             _ => return Poll::Ready(Err(Error::CoroutineInactive)),
         };
 
-        let _wg = WakerGuard::new(lua, cx.waker());
+        let _wg = WakerGuard::new(lua, cx.waker(), self.poll_budget, self.task_id);
 
         // This is safe as we are not moving the whole struct
         let this = unsafe { self.get_unchecked_mut() };
@@ -496,18 +886,30 @@ fn is_poll_pending(val: &MultiValue) -> bool {
 struct WakerGuard<'lua, 'a> {
     lua: &'lua Lua,
     prev: NonNull<Waker>,
+    prev_budget: Option<usize>,
+    prev_task: Option<TaskId>,
     _phantom: PhantomData<&'a ()>,
 }
 
 #[cfg(feature = "async")]
 impl<'lua, 'a> WakerGuard<'lua, 'a> {
     #[inline]
-    pub fn new(lua: &'lua Lua, waker: &'a Waker) -> Result<WakerGuard<'lua, 'a>> {
+    pub fn new(
+        lua: &'lua Lua,
+        waker: &'a Waker,
+        budget: Option<usize>,
+        task: TaskId,
+    ) -> Result<WakerGuard<'lua, 'a>> {
         unsafe {
             let prev = lua.set_waker(NonNull::from(waker));
+            let prev_budget = lua.set_poll_budget_left(budget);
+            // Nested async threads belong to the task being polled
+            let prev_task = lua.set_current_task(Some(lua.current_task_id().unwrap_or(task)));
             Ok(WakerGuard {
                 lua,
                 prev,
+                prev_budget,
+                prev_task,
                 _phantom: PhantomData,
             })
         }
@@ -519,6 +921,8 @@ impl<'lua, 'a> Drop for WakerGuard<'lua, 'a> {
     fn drop(&mut self) {
         unsafe {
             self.lua.set_waker(self.prev);
+            self.lua.set_poll_budget_left(self.prev_budget);
+            self.lua.set_current_task(self.prev_task);
         }
     }
 }
@@ -528,4 +932,7 @@ mod assertions {
     use super::*;
 
     static_assertions::assert_not_impl_any!(Thread: Send);
+
+    #[cfg(all(feature = "unstable", not(feature = "send")))]
+    static_assertions::assert_not_impl_any!(OwnedThread: Send);
 }
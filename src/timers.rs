@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use futures_util::future::{self, Either, Future};
+
+use crate::error::{Error, Result};
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::table::Table;
+use crate::types::MaybeSend;
+use crate::value::{MultiValue, Value};
+
+/// Creates a table with `sleep`, `interval` and `timeout` async functions.
+///
+/// All waiting is delegated to the `sleep` future provided by the host, the functions
+/// themselves are driven by the coroutine (or future) calling them.
+pub(crate) fn create_timers<'lua, F, FR>(lua: &'lua Lua, sleep: F) -> Result<Table<'lua>>
+where
+    F: Fn(Duration) -> FR + Clone + MaybeSend + 'static,
+    FR: Future<Output = ()> + 'lua,
+{
+    let timers = lua.create_table_with_capacity(0, 3)?;
+
+    let sleep_fn = sleep.clone();
+    let func = lua.create_async_function(move |_, ms: u64| {
+        let delay = sleep_fn(Duration::from_millis(ms));
+        async move {
+            delay.await;
+            Ok(())
+        }
+    })?;
+    timers.raw_set("sleep", func)?;
+
+    let sleep_fn = sleep.clone();
+    let func = lua.create_async_function(move |_, (ms, func): (u64, Function)| {
+        let sleep_fn = sleep_fn.clone();
+        async move {
+            let mut count = 0;
+            loop {
+                sleep_fn(Duration::from_millis(ms)).await;
+                count += 1;
+                if let Value::Boolean(false) = func.call_async::<_, Value>(count).await? {
+                    return Ok(count);
+                }
+            }
+        }
+    })?;
+    timers.raw_set("interval", func)?;
+
+    let func =
+        lua.create_async_function(move |_, (ms, func, args): (u64, Function, MultiValue)| {
+            let delay = sleep(Duration::from_millis(ms));
+            async move {
+                let call = func.call_async::<_, MultiValue>(args);
+                futures_util::pin_mut!(delay, call);
+                match future::select(call, delay).await {
+                    Either::Left((res, _)) => res,
+                    Either::Right(_) => Err(Error::RuntimeError(format!(
+                        "operation timed out after {ms}ms"
+                    ))),
+                }
+            }
+        })?;
+    timers.raw_set("timeout", func)?;
+
+    Ok(timers)
+}
@@ -3,9 +3,11 @@ use std::cell::{Cell, Ref, RefCell, RefMut, UnsafeCell};
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
 use std::result::Result as StdResult;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use std::{fmt, mem, ptr};
 
 #[cfg(feature = "lua54")]
@@ -17,11 +19,12 @@ use rustc_hash::FxHashMap;
 use futures_util::future::LocalBoxFuture;
 
 use crate::error::Result;
+use crate::globals::GlobalViolation;
 #[cfg(not(feature = "luau"))]
 use crate::hook::Debug;
 use crate::lua::{ExtraData, Lua};
 use crate::util::{assert_stack, StackGuard};
-use crate::value::MultiValue;
+use crate::value::{MultiValue, Value};
 
 #[cfg(feature = "unstable")]
 use {crate::lua::LuaInner, std::marker::PhantomData};
@@ -38,6 +41,11 @@ pub type Number = ffi::lua_Number;
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct LightUserData(pub *mut c_void);
 
+/// Function of a family created by [`Lua::create_function_family`].
+///
+/// [`Lua::create_function_family`]: crate::Lua::create_function_family
+pub type FamilyFn<S> = for<'lua> fn(&'lua Lua, &S, MultiValue<'lua>) -> Result<MultiValue<'lua>>;
+
 pub(crate) type Callback<'lua, 'a> =
     Box<dyn Fn(&'lua Lua, MultiValue<'lua>) -> Result<MultiValue<'lua>> + 'a>;
 
@@ -84,6 +92,44 @@ pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &CStr, bool) -> Result<()> + Sen
 #[cfg(all(not(feature = "send"), feature = "lua54"))]
 pub(crate) type WarnCallback = Box<dyn Fn(&Lua, &CStr, bool) -> Result<()>>;
 
+#[cfg(feature = "send")]
+pub(crate) type CoercionFn =
+    Box<dyn for<'lua> Fn(&'lua Lua, Value<'lua>) -> Option<Result<Box<dyn Any>>> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type CoercionFn =
+    Box<dyn for<'lua> Fn(&'lua Lua, Value<'lua>) -> Option<Result<Box<dyn Any>>>>;
+
+#[cfg(feature = "send")]
+pub(crate) type ViolationCallback = Box<dyn Fn(&Lua, &GlobalViolation) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type ViolationCallback = Box<dyn Fn(&Lua, &GlobalViolation) -> Result<()>>;
+
+#[cfg(feature = "send")]
+pub(crate) type SourceResolver = Box<dyn Fn(&str) -> Option<PathBuf> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type SourceResolver = Box<dyn Fn(&str) -> Option<PathBuf>>;
+
+#[cfg(feature = "send")]
+pub(crate) type Clock = Box<dyn Fn() -> Duration + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type Clock = Box<dyn Fn() -> Duration>;
+
+#[cfg(feature = "send")]
+pub(crate) type Preprocessor = Arc<dyn Fn(&str) -> Result<String> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type Preprocessor = Arc<dyn Fn(&str) -> Result<String>>;
+
+#[cfg(feature = "send")]
+pub(crate) type MirrorSync = Box<dyn FnMut(&Lua) -> Result<()> + Send>;
+
+#[cfg(not(feature = "send"))]
+pub(crate) type MirrorSync = Box<dyn FnMut(&Lua) -> Result<()>>;
+
 #[cfg(feature = "send")]
 pub trait MaybeSend: Send {}
 #[cfg(feature = "send")]
@@ -98,12 +144,17 @@ impl<T> MaybeSend for T {}
 ///
 /// By default vectors are 3-dimensional, but can be 4-dimensional
 /// if the `luau-vector4` feature is enabled.
-#[cfg(any(feature = "luau", doc))]
-#[cfg_attr(docsrs, doc(cfg(feature = "luau")))]
+///
+/// Other Lua versions get 3-dimensional vectors with the `vector` feature. They are represented
+/// as small userdata values with arithmetic (`+`, `-`, `*`, `/`, unary `-`), equality,
+/// `tostring` and component access (`v.x`, `v.y`, `v.z`) implemented natively, without going
+/// through Rust callbacks.
+#[cfg(any(feature = "luau", feature = "vector", doc))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "luau", feature = "vector"))))]
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Vector(pub(crate) [f32; Self::SIZE]);
 
-#[cfg(feature = "luau")]
+#[cfg(any(feature = "luau", feature = "vector"))]
 impl fmt::Display for Vector {
     #[rustfmt::skip]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -114,7 +165,7 @@ impl fmt::Display for Vector {
     }
 }
 
-#[cfg(feature = "luau")]
+#[cfg(any(feature = "luau", feature = "vector"))]
 impl Vector {
     pub(crate) const SIZE: usize = if cfg!(feature = "luau-vector4") { 4 } else { 3 };
 
@@ -172,7 +223,7 @@ impl Serialize for Vector {
     }
 }
 
-#[cfg(feature = "luau")]
+#[cfg(any(feature = "luau", feature = "vector"))]
 impl PartialEq<[f32; Self::SIZE]> for Vector {
     #[inline]
     fn eq(&self, other: &[f32; Self::SIZE]) -> bool {
@@ -180,13 +231,54 @@ impl PartialEq<[f32; Self::SIZE]> for Vector {
     }
 }
 
+#[cfg(any(feature = "luau", feature = "vector"))]
+impl From<[f32; Self::SIZE]> for Vector {
+    #[inline]
+    fn from(v: [f32; Self::SIZE]) -> Self {
+        Vector(v)
+    }
+}
+
+#[cfg(any(feature = "luau", feature = "vector"))]
+impl From<Vector> for [f32; Vector::SIZE] {
+    #[inline]
+    fn from(v: Vector) -> Self {
+        v.0
+    }
+}
+
+#[cfg(all(
+    feature = "glam",
+    any(feature = "luau", feature = "vector"),
+    not(feature = "luau-vector4")
+))]
+impl From<glam::Vec3> for Vector {
+    #[inline]
+    fn from(v: glam::Vec3) -> Self {
+        Vector(v.to_array())
+    }
+}
+
+#[cfg(all(
+    feature = "glam",
+    any(feature = "luau", feature = "vector"),
+    not(feature = "luau-vector4")
+))]
+impl From<Vector> for glam::Vec3 {
+    #[inline]
+    fn from(v: Vector) -> Self {
+        glam::Vec3::from_array(v.0)
+    }
+}
+
 pub(crate) struct DestructedUserdata;
 
 /// An auto generated key into the Lua registry.
 ///
-/// This is a handle to a value stored inside the Lua registry. It is not automatically
-/// garbage collected on Drop, but it can be removed with [`Lua::remove_registry_value`],
-/// and instances not manually removed can be garbage collected with [`Lua::expire_registry_values`].
+/// This is a handle to a value stored inside the Lua registry. Slots of dropped keys are reused
+/// for new registry values, and their values are removed from the registry when the garbage
+/// collector is run; it can also be done explicitly with [`Lua::remove_registry_value`] or
+/// [`Lua::expire_registry_values`].
 ///
 /// Be warned, If you place this into Lua via a [`UserData`] type or a rust callback, it is *very
 /// easy* to accidentally cause reference cycles that the Lua garbage collector cannot resolve.
@@ -202,7 +294,7 @@ pub(crate) struct DestructedUserdata;
 pub struct RegistryKey {
     pub(crate) registry_id: c_int,
     pub(crate) is_nil: AtomicBool,
-    pub(crate) unref_list: Arc<Mutex<Option<Vec<c_int>>>>,
+    pub(crate) unref_list: Arc<UnrefList>,
 }
 
 impl fmt::Debug for RegistryKey {
@@ -229,17 +321,14 @@ impl Drop for RegistryKey {
     fn drop(&mut self) {
         // We don't need to collect nil slot
         if self.registry_id > ffi::LUA_REFNIL {
-            let mut unref_list = mlua_expect!(self.unref_list.lock(), "unref list poisoned");
-            if let Some(list) = unref_list.as_mut() {
-                list.push(self.registry_id);
-            }
+            self.unref_list.push(self.registry_id);
         }
     }
 }
 
 impl RegistryKey {
     // Creates a new instance of `RegistryKey`
-    pub(crate) const fn new(id: c_int, unref_list: Arc<Mutex<Option<Vec<c_int>>>>) -> Self {
+    pub(crate) const fn new(id: c_int, unref_list: Arc<UnrefList>) -> Self {
         RegistryKey {
             registry_id: id,
             is_nil: AtomicBool::new(id == ffi::LUA_REFNIL),
@@ -273,6 +362,136 @@ impl RegistryKey {
     }
 }
 
+// Lock-free list of registry ids of dropped `RegistryKey`s, waiting to be released.
+// Keys can be dropped from any thread, the ids are released by the Lua owning thread.
+pub(crate) struct UnrefList {
+    head: AtomicPtr<UnrefNode>,
+    // Set when the Lua state is closed, ids are no longer collected
+    closed: AtomicBool,
+}
+
+struct UnrefNode {
+    id: c_int,
+    next: *mut UnrefNode,
+}
+
+impl UnrefList {
+    pub(crate) const fn new() -> Self {
+        UnrefList {
+            head: AtomicPtr::new(ptr::null_mut()),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn push(&self, id: c_int) {
+        if self.closed.load(Ordering::Acquire) {
+            return;
+        }
+        let node = Box::into_raw(Box::new(UnrefNode {
+            id,
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*node).next = head };
+            match (self.head).compare_exchange_weak(
+                head,
+                node,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(new_head) => head = new_head,
+            }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Relaxed).is_null()
+    }
+
+    // Takes a single pending id.
+    // Only the thread owning the Lua state pops ids, so a node cannot be freed and pushed again
+    // while being popped (no ABA problem).
+    pub(crate) fn pop(&self) -> Option<c_int> {
+        let mut head = self.head.load(Ordering::Acquire);
+        while !head.is_null() {
+            let next = unsafe { (*head).next };
+            match (self.head).compare_exchange_weak(
+                head,
+                next,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(unsafe { Box::from_raw(head) }.id),
+                Err(new_head) => head = new_head,
+            }
+        }
+        None
+    }
+
+    // Takes all pending ids at once
+    pub(crate) fn drain(&self, mut f: impl FnMut(c_int)) {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::Acquire);
+        while !node.is_null() {
+            let UnrefNode { id, next } = *unsafe { Box::from_raw(node) };
+            f(id);
+            node = next;
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.drain(|_| {});
+    }
+}
+
+impl Drop for UnrefList {
+    fn drop(&mut self) {
+        self.drain(|_| {});
+    }
+}
+
+// Filling of a nested table, deferred to keep the host stack bounded when converting deeply
+// nested Rust values. The closure is type-erased without requiring it to be `'static`.
+pub(crate) struct DeferredConversion {
+    pub(crate) depth: usize,
+    data: *mut c_void,
+    run: unsafe fn(*mut c_void) -> Result<()>,
+    drop: unsafe fn(*mut c_void),
+}
+
+impl DeferredConversion {
+    // Safety: the conversion must be run or dropped while the data captured by `f` is alive
+    pub(crate) unsafe fn new<F: FnOnce() -> Result<()>>(depth: usize, f: F) -> Self {
+        unsafe fn run<F: FnOnce() -> Result<()>>(data: *mut c_void) -> Result<()> {
+            Box::from_raw(data as *mut F)()
+        }
+        unsafe fn drop<F>(data: *mut c_void) {
+            mem::drop(Box::from_raw(data as *mut F));
+        }
+
+        DeferredConversion {
+            depth,
+            data: Box::into_raw(Box::new(f)) as *mut c_void,
+            run: run::<F>,
+            drop: drop::<F>,
+        }
+    }
+
+    pub(crate) fn run(self) -> Result<()> {
+        let this = mem::ManuallyDrop::new(self);
+        unsafe { (this.run)(this.data) }
+    }
+}
+
+impl Drop for DeferredConversion {
+    fn drop(&mut self) {
+        unsafe { (self.drop)(self.data) }
+    }
+}
+
 pub(crate) struct LuaRef<'lua> {
     pub(crate) lua: &'lua Lua,
     pub(crate) index: c_int,
@@ -321,6 +540,11 @@ impl<'lua> Drop for LuaRef<'lua> {
 impl<'lua> PartialEq for LuaRef<'lua> {
     fn eq(&self, other: &Self) -> bool {
         let lua = self.lua;
+        // Values from different Lua states are never equal
+        #[cfg(feature = "strict-no-panic")]
+        if lua.ref_thread() != other.lua.ref_thread() {
+            return false;
+        }
         let state = lua.state();
         unsafe {
             let _sg = StackGuard::new(state);
@@ -432,6 +656,38 @@ impl AppData {
         })
     }
 
+    pub(crate) fn try_borrow<T: 'static>(&self) -> Option<StdResult<AppDataRef<T>, ()>> {
+        let data = match unsafe { &*self.container.get() }
+            .get(&TypeId::of::<T>())?
+            .try_borrow()
+        {
+            Ok(data) => data,
+            Err(_) => return Some(Err(())),
+        };
+        let data = Ref::filter_map(data, |data| data.downcast_ref()).ok()?;
+        self.borrow.set(self.borrow.get() + 1);
+        Some(Ok(AppDataRef {
+            data,
+            borrow: &self.borrow,
+        }))
+    }
+
+    pub(crate) fn try_borrow_mut<T: 'static>(&self) -> Option<StdResult<AppDataRefMut<T>, ()>> {
+        let data = match unsafe { &*self.container.get() }
+            .get(&TypeId::of::<T>())?
+            .try_borrow_mut()
+        {
+            Ok(data) => data,
+            Err(_) => return Some(Err(())),
+        };
+        let data = RefMut::filter_map(data, |data| data.downcast_mut()).ok()?;
+        self.borrow.set(self.borrow.get() + 1);
+        Some(Ok(AppDataRefMut {
+            data,
+            borrow: &self.borrow,
+        }))
+    }
+
     #[track_caller]
     pub(crate) fn remove<T: 'static>(&self) -> Option<T> {
         if self.borrow.get() != 0 {
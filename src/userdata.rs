@@ -2,7 +2,7 @@ use std::any::{type_name, TypeId};
 use std::cell::{Ref, RefCell, RefMut};
 use std::fmt;
 use std::hash::Hash;
-use std::mem;
+use std::mem::{self, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
 use std::os::raw::{c_char, c_int};
 use std::string::String as StdString;
@@ -16,19 +16,24 @@ use {
     std::result::Result as StdResult,
 };
 
+use crate::docs::FnMeta;
 use crate::error::{Error, Result};
 use crate::function::Function;
 use crate::lua::Lua;
 use crate::string::String;
 use crate::table::{Table, TablePairs};
 use crate::types::{LuaRef, MaybeSend};
+use crate::userdata_ext::AnyUserDataExt;
 use crate::util::{check_stack, get_userdata, take_userdata, StackGuard};
-use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Value};
+use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Value};
 use crate::UserDataRegistrar;
 
 #[cfg(feature = "lua54")]
 pub(crate) const USER_VALUE_MAXSLOT: usize = 8;
 
+// Name of the user value holding properties of `UserDataMethods::add_dynamic_fields_storage`
+const DYNAMIC_FIELDS_KEY: &str = "__mlua_dynamic_fields";
+
 /// Kinds of metamethods that can be overridden.
 ///
 /// Currently, this mechanism does not allow overriding the `__gc` metamethod, since there is
@@ -132,7 +137,12 @@ pub enum MetaMethod {
     ///
     /// Executed when a variable, that marked as to-be-closed, goes out of scope.
     ///
-    /// More information about to-be-closed variabled can be found in the Lua 5.4
+    /// The metamethod receives the value and the error object that caused the scope exit (or
+    /// `nil` if the scope exited normally), eg. `|_, this, err: Value| { ... }` added with
+    /// [`UserDataMethods::add_meta_method_mut`]. It's run deterministically on scope exit, which
+    /// makes it suitable for releasing resources (files, locks, transactions) held by userdata.
+    ///
+    /// More information about to-be-closed variables can be found in the Lua 5.4
     /// [documentation][lua_doc].
     ///
     /// Requires `feature = "lua54"`
@@ -215,6 +225,58 @@ impl MetaMethod {
         }
     }
 
+    // All metamethods supported by the enabled Lua version
+    const ALL: &'static [MetaMethod] = &[
+        MetaMethod::Add,
+        MetaMethod::Sub,
+        MetaMethod::Mul,
+        MetaMethod::Div,
+        MetaMethod::Mod,
+        MetaMethod::Pow,
+        MetaMethod::Unm,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::IDiv,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::BAnd,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::BOr,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::BXor,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::BNot,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::Shl,
+        #[cfg(any(feature = "lua54", feature = "lua53"))]
+        MetaMethod::Shr,
+        MetaMethod::Concat,
+        MetaMethod::Len,
+        MetaMethod::Eq,
+        MetaMethod::Lt,
+        MetaMethod::Le,
+        MetaMethod::Index,
+        MetaMethod::NewIndex,
+        MetaMethod::Call,
+        MetaMethod::ToString,
+        #[cfg(any(
+            feature = "lua54",
+            feature = "lua53",
+            feature = "lua52",
+            feature = "luajit52"
+        ))]
+        MetaMethod::Pairs,
+        #[cfg(any(feature = "lua52", feature = "luajit52"))]
+        MetaMethod::IPairs,
+        #[cfg(feature = "luau")]
+        MetaMethod::Iter,
+        #[cfg(feature = "lua54")]
+        MetaMethod::Close,
+    ];
+
+    // Returns `true` if `name` is the name of a metamethod or a restricted metatable field
+    pub(crate) fn is_reserved(name: &str) -> bool {
+        MetaMethod::ALL.iter().any(|m| m.name() == name) || MetaMethod::validate(name).is_err()
+    }
+
     pub(crate) fn validate(name: &str) -> Result<&str> {
         match name {
             "__gc" => Err(Error::MetaMethodRestricted(name.to_string())),
@@ -231,6 +293,124 @@ impl AsRef<str> for MetaMethod {
     }
 }
 
+/// Static description of a userdata method, consumed by
+/// [`UserDataMethods::add_methods_from_descriptors`].
+///
+/// Methods are plain function pointers receiving the arguments as [`MultiValue`], so descriptors
+/// can be created in `const` context.
+pub struct MethodDesc<T> {
+    pub(crate) name: &'static str,
+    pub(crate) meta: bool,
+    pub(crate) func: MethodDescFn<T>,
+}
+
+pub(crate) enum MethodDescFn<T> {
+    Ref(for<'lua> fn(&'lua Lua, &T, MultiValue<'lua>) -> Result<MultiValue<'lua>>),
+    Mut(for<'lua> fn(&'lua Lua, &mut T, MultiValue<'lua>) -> Result<MultiValue<'lua>>),
+}
+
+impl<T> MethodDesc<T> {
+    /// Describes a regular method which accepts a `&T` as the first parameter.
+    pub const fn method(
+        name: &'static str,
+        func: for<'lua> fn(&'lua Lua, &T, MultiValue<'lua>) -> Result<MultiValue<'lua>>,
+    ) -> Self {
+        MethodDesc {
+            name,
+            meta: false,
+            func: MethodDescFn::Ref(func),
+        }
+    }
+
+    /// Describes a regular method which accepts a `&mut T` as the first parameter.
+    pub const fn method_mut(
+        name: &'static str,
+        func: for<'lua> fn(&'lua Lua, &mut T, MultiValue<'lua>) -> Result<MultiValue<'lua>>,
+    ) -> Self {
+        MethodDesc {
+            name,
+            meta: false,
+            func: MethodDescFn::Mut(func),
+        }
+    }
+
+    /// Describes a metamethod which accepts a `&T` as the first parameter.
+    pub const fn meta_method(
+        meta: MetaMethod,
+        func: for<'lua> fn(&'lua Lua, &T, MultiValue<'lua>) -> Result<MultiValue<'lua>>,
+    ) -> Self {
+        MethodDesc {
+            name: meta.name(),
+            meta: true,
+            func: MethodDescFn::Ref(func),
+        }
+    }
+
+    /// Describes a metamethod which accepts a `&mut T` as the first parameter.
+    pub const fn meta_method_mut(
+        meta: MetaMethod,
+        func: for<'lua> fn(&'lua Lua, &mut T, MultiValue<'lua>) -> Result<MultiValue<'lua>>,
+    ) -> Self {
+        MethodDesc {
+            name: meta.name(),
+            meta: true,
+            func: MethodDescFn::Mut(func),
+        }
+    }
+
+    /// Returns the method (or metamethod) name.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Defines how userdata methods behave when called while the userdata is already borrowed,
+/// eg. by a pending async method.
+///
+/// Set for a type using [`UserDataMethods::set_borrow_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum BorrowPolicy {
+    /// Fail with [`Error::UserDataBorrowError`] or [`Error::UserDataBorrowMutError`] (as the
+    /// cause of a bad `self` argument).
+    #[default]
+    Error,
+    /// Fail with a runtime error naming the method and the conflicting borrow, which Lua code
+    /// receives as a plain string message.
+    LuaError,
+    /// Async methods wait until the userdata can be borrowed. The waiting task is woken when an
+    /// async method or a [`UserDataRef`]/[`UserDataRefMut`] holding the conflicting borrow
+    /// releases it. Other methods fail as with [`BorrowPolicy::Error`].
+    ///
+    /// A method called (directly or through Lua code) from an async method that holds the
+    /// conflicting borrow fails as with [`BorrowPolicy::Error`] instead, as the borrow cannot be
+    /// released before it returns. Borrows held by Rust through [`AnyUserData::borrow`] or
+    /// [`AnyUserData::borrow_mut`] don't wake waiting tasks when dropped.
+    ///
+    /// Requires `feature = "async"`
+    #[cfg(feature = "async")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+    Wait,
+}
+
+/// Defines who can access a userdata method or field.
+///
+/// Set for a member using [`UserDataMethods::set_visibility`] or
+/// [`UserDataFields::set_visibility`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Visibility {
+    /// The member is accessible from both Lua and Rust.
+    #[default]
+    Public,
+    /// The member is hidden from Lua code and can only be accessed from Rust, using
+    /// [`AnyUserDataExt`] methods (eg. [`AnyUserDataExt::call_method`]).
+    ///
+    /// [`AnyUserDataExt`]: crate::AnyUserDataExt
+    /// [`AnyUserDataExt::call_method`]: crate::AnyUserDataExt::call_method
+    HostOnly,
+}
+
 /// Method registry for [`UserData`] implementors.
 ///
 /// [`UserData`]: crate::UserData
@@ -441,6 +621,218 @@ pub trait UserDataMethods<'lua, T> {
         FR: Future<Output = Result<R>> + 'lua,
         R: IntoLuaMulti<'lua>;
 
+    /// Forbids fields, methods and metamethods of this type from overriding each other.
+    ///
+    /// By default, registering the same name twice silently replaces the earlier registration.
+    /// With this option, creating a userdata fails with [`Error::RegistrationConflict`] if its
+    /// type registers the same name twice, a field and a method with the same name, or a regular
+    /// method named like a metamethod.
+    ///
+    /// [`Error::RegistrationConflict`]: crate::Error::RegistrationConflict
+    fn deny_overrides(&mut self) {}
+
+    /// Sets how methods, metamethods and fields of this type behave when the userdata is already
+    /// borrowed by another call.
+    ///
+    /// Default: **[`BorrowPolicy::Error`]**
+    fn set_borrow_policy(&mut self, _policy: BorrowPolicy) {}
+
+    /// Sets the visibility of a method (or field) of this type.
+    ///
+    /// Members with [`Visibility::HostOnly`] are not visible to Lua code, so internal operations
+    /// can be exposed to the host without being callable by untrusted scripts. They are available
+    /// from Rust through [`AnyUserDataExt`] methods. Metamethods are always public.
+    ///
+    /// Host-only members of non-static userdata created in a [`Scope`] are not registered at all.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{AnyUserDataExt, Lua, Result, UserData, UserDataMethods, Visibility};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Cache(Vec<i32>);
+    ///
+    /// impl UserData for Cache {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_method("len", |_, this, ()| Ok(this.0.len()));
+    ///         methods.add_method_mut("clear", |_, this, ()| Ok(this.0.clear()));
+    ///         methods.set_visibility("clear", Visibility::HostOnly);
+    ///     }
+    /// }
+    ///
+    /// let cache = lua.create_userdata(Cache(vec![1, 2, 3]))?;
+    /// lua.globals().set("cache", cache.clone())?;
+    /// assert!(lua.load("cache:clear()").exec().is_err());
+    ///
+    /// cache.call_method::<_, ()>("clear", ())?;
+    /// assert_eq!(lua.load("cache:len()").eval::<usize>()?, 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`AnyUserDataExt`]: crate::AnyUserDataExt
+    /// [`Scope`]: crate::Scope
+    fn set_visibility(&mut self, _name: impl AsRef<str>, _visibility: Visibility) {}
+
+    /// Sets a custom formatter used by `inspect` (see [`Lua::install_inspect`]).
+    ///
+    /// The formatter is registered as the `__inspect` metamethod.
+    ///
+    /// [`Lua::install_inspect`]: crate::Lua::install_inspect
+    fn add_debug_fmt<F>(&mut self, formatter: F)
+    where
+        F: Fn(&'lua Lua, &T) -> Result<StdString> + MaybeSend + 'static,
+    {
+        self.add_meta_method("__inspect", move |lua, this, ()| formatter(lua, this));
+    }
+
+    /// Gives each instance of this type a table of properties defined by Lua code.
+    ///
+    /// Reading a key that is not a field or method of the userdata returns the stored property
+    /// (or `nil`), and assigning to such a key stores the property. From Rust the properties are
+    /// accessed using [`AnyUserData::dynamic_get`] and [`AnyUserData::dynamic_set`].
+    ///
+    /// Fields without a setter and methods are read-only. This registers the `__index` and
+    /// `__newindex` metamethods, so it cannot be combined with custom ones.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Lua, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Entity;
+    ///
+    /// impl UserData for Entity {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_dynamic_fields_storage();
+    ///     }
+    /// }
+    ///
+    /// let entity = lua.create_userdata(Entity)?;
+    /// lua.load("local e = ... e.tag = 'enemy'").call::<_, ()>(entity.clone())?;
+    /// assert_eq!(entity.dynamic_get::<_, String>("tag")?, "enemy");
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn add_dynamic_fields_storage(&mut self) {
+        self.add_meta_function(MetaMethod::Index, |_, (ud, key): (AnyUserData, Value)| {
+            ud.dynamic_get::<_, Value>(key)
+        });
+        self.add_meta_function(
+            MetaMethod::NewIndex,
+            |_, (ud, key, value): (AnyUserData, Value, Value)| {
+                // Fields and methods cannot be shadowed by properties
+                if matches!(ud.dynamic_get(key.clone())?, Value::Nil)
+                    && !matches!(AnyUserDataExt::get(&ud, key.clone())?, Value::Nil)
+                {
+                    let key = key.to_string()?;
+                    return Err(Error::RuntimeError(format!("field '{key}' is read-only")));
+                }
+                ud.dynamic_set(key, value)
+            },
+        );
+    }
+
+    /// Adds methods and metamethods described by a list of [`MethodDesc`].
+    ///
+    /// Descriptors can be placed in a `static` (eg. generated by a build script or a macro),
+    /// which avoids creating a distinct closure type per method.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{FromLuaMulti, IntoLuaMulti, Lua, MetaMethod, MethodDesc, Result, UserData, UserDataMethods};
+    /// # fn main() -> Result<()> {
+    /// # let lua = Lua::new();
+    /// struct Counter(i64);
+    ///
+    /// static METHODS: &[MethodDesc<Counter>] = &[
+    ///     MethodDesc::method("get", |lua, this, _| this.0.into_lua_multi(lua)),
+    ///     MethodDesc::method_mut("add", |lua, this, args| {
+    ///         this.0 += i64::from_lua_multi(args, lua)?;
+    ///         ().into_lua_multi(lua)
+    ///     }),
+    ///     MethodDesc::meta_method(MetaMethod::ToString, |lua, this, _| {
+    ///         format!("Counter({})", this.0).into_lua_multi(lua)
+    ///     }),
+    /// ];
+    ///
+    /// impl UserData for Counter {
+    ///     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+    ///         methods.add_methods_from_descriptors(METHODS);
+    ///     }
+    /// }
+    ///
+    /// lua.globals().set("counter", Counter(1))?;
+    /// lua.load(r#"
+    ///     counter:add(2)
+    ///     assert(counter:get() == 3)
+    ///     assert(tostring(counter) == "Counter(3)")
+    /// "#).exec()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn add_methods_from_descriptors(&mut self, descriptors: &[MethodDesc<T>])
+    where
+        T: 'static,
+    {
+        for desc in descriptors {
+            match (desc.meta, &desc.func) {
+                (false, MethodDescFn::Ref(f)) => self.add_method(desc.name, *f),
+                (false, MethodDescFn::Mut(f)) => self.add_method_mut(desc.name, *f),
+                (true, MethodDescFn::Ref(f)) => self.add_meta_method(desc.name, *f),
+                (true, MethodDescFn::Mut(f)) => self.add_meta_method_mut(desc.name, *f),
+            }
+        }
+    }
+
+    /// Attaches documentation to the regular method (or function) registered as `name`.
+    ///
+    /// The documentation is returned by [`Lua::api_docs`] and shown by `help` (see
+    /// [`Lua::install_help`]) under the name `TypeName:name`.
+    ///
+    /// [`Lua::api_docs`]: crate::Lua::api_docs
+    /// [`Lua::install_help`]: crate::Lua::install_help
+    fn document_method(&mut self, _name: impl AsRef<str>, _meta: FnMeta) {}
+
+    /// Add a regular method which accepts a `&T` as the first parameter, with attached
+    /// documentation.
+    ///
+    /// See [`add_method`] and [`document_method`].
+    ///
+    /// [`add_method`]: #method.add_method
+    /// [`document_method`]: #method.document_method
+    fn add_method_with_meta<M, A, R>(&mut self, name: impl AsRef<str>, meta: FnMeta, method: M)
+    where
+        M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let name = name.as_ref();
+        self.add_method(name, method);
+        self.document_method(name, meta);
+    }
+
+    /// Add a regular method as a function which accepts generic arguments, with attached
+    /// documentation.
+    ///
+    /// See [`add_function`] and [`document_method`].
+    ///
+    /// [`add_function`]: #method.add_function
+    /// [`document_method`]: #method.document_method
+    fn add_function_with_meta<F, A, R>(&mut self, name: impl AsRef<str>, meta: FnMeta, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> Result<R> + MaybeSend + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let name = name.as_ref();
+        self.add_function(name, function);
+        self.document_method(name, meta);
+    }
+
     //
     // Below are internal methods used in generated code
     //
@@ -539,6 +931,16 @@ pub trait UserDataFields<'lua, T> {
         F: Fn(&'lua Lua) -> Result<R> + MaybeSend + 'static,
         R: IntoLua<'lua>;
 
+    /// Sets the visibility of a field (or method) of this type.
+    ///
+    /// Fields with [`Visibility::HostOnly`] can be accessed only from Rust, using
+    /// [`AnyUserDataExt::get`] and [`AnyUserDataExt::set`].
+    /// See [`UserDataMethods::set_visibility`] for details.
+    ///
+    /// [`AnyUserDataExt::get`]: crate::AnyUserDataExt::get
+    /// [`AnyUserDataExt::set`]: crate::AnyUserDataExt::set
+    fn set_visibility(&mut self, _name: impl AsRef<str>, _visibility: Visibility) {}
+
     //
     // Below are internal methods used in generated code
     //
@@ -624,6 +1026,68 @@ pub trait UserData: Sized {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {}
 }
 
+/// Userdata type that can be also converted to and from a plain Lua table.
+///
+/// Usually implemented using `#[derive(LuaObject)]` (requires `feature = "macros"`), which
+/// generates both the [`UserData`] implementation (a field accessor per struct field) and the
+/// table conversion from the same field list. Tables use the same layout as serde would produce,
+/// so they can be used for persistence.
+///
+/// The derive macro supports the following attributes:
+///
+/// - `#[lua(methods = path)]` on the struct: a function `fn<'lua, M: UserDataMethods<'lua, Self>>(&mut M)`
+///   to add custom methods.
+/// - `#[lua(rename = "name")]` on a field: use a different name in Lua.
+/// - `#[lua(readonly)]` on a field: do not generate a setter.
+/// - `#[lua(skip)]` on a field: do not expose the field to Lua. The value is initialized
+///   using [`Default`] when converting from a table.
+///
+/// Exposed field types must implement [`Clone`], [`IntoLua`] and [`FromLua`].
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Lua, LuaObject, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// #[derive(LuaObject)]
+/// struct Player {
+///     name: String,
+///     #[lua(rename = "hp")]
+///     health: u32,
+///     #[lua(skip)]
+///     session: Option<u64>,
+/// }
+///
+/// let player = Player { name: "alice".into(), health: 100, session: None };
+/// lua.globals().set("player", player)?;
+/// lua.load("player.hp = player.hp - 10").exec()?;
+///
+/// let player = lua.globals().get::<_, mlua::AnyUserData>("player")?;
+/// let saved = player.borrow::<Player>()?.to_table(&lua)?;
+/// assert_eq!(saved.get::<_, u32>("hp")?, 90);
+///
+/// let restored = Player::from_table(saved)?;
+/// assert_eq!(restored.name, "alice");
+/// assert_eq!(restored.session, None);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`IntoLua`]: crate::IntoLua
+/// [`FromLua`]: crate::FromLua
+pub trait LuaObject: UserData {
+    /// Names of the fields exposed to Lua, in declaration order.
+    const FIELDS: &'static [&'static str];
+
+    /// Converts the object to a table with a key per exposed field.
+    fn to_table<'lua>(&self, lua: &'lua Lua) -> Result<Table<'lua>>;
+
+    /// Creates a new object from a table produced by [`LuaObject::to_table`] (or with the same
+    /// layout).
+    fn from_table(table: Table) -> Result<Self>;
+}
+
 // Wraps UserData in a way to always implement `serde::Serialize` trait.
 pub(crate) struct UserDataCell<T>(RefCell<UserDataVariant<T>>);
 
@@ -673,6 +1137,25 @@ impl<T> UserDataCell<T> {
             })
     }
 
+    // Immutably borrows the wrapped value, failing with `UserDataWouldBlock` if it's mutably
+    // borrowed.
+    #[inline]
+    pub(crate) fn borrow_if_free(&self) -> Result<Ref<T>> {
+        let r = self.0.try_borrow().map_err(|_| Error::UserDataWouldBlock)?;
+        Ok(Ref::map(r, |r| r.deref()))
+    }
+
+    // Mutably borrows the wrapped value, failing with `UserDataWouldBlock` if it's already
+    // borrowed.
+    #[inline]
+    pub(crate) fn borrow_mut_if_free(&self) -> Result<RefMut<T>> {
+        let r = self
+            .0
+            .try_borrow_mut()
+            .map_err(|_| Error::UserDataWouldBlock)?;
+        RefMut::filter_map(r, |r| r.try_deref_mut().ok()).map_err(|_| Error::UserDataBorrowMutError)
+    }
+
     // Consumes this `UserDataCell`, returning the wrapped value.
     #[inline]
     fn into_inner(self) -> Result<T> {
@@ -837,6 +1320,40 @@ impl<'lua> AnyUserData<'lua> {
         self.inspect(|cell| cell.try_borrow_mut())
     }
 
+    /// Tries to borrow this userdata immutably if it is of type `T`.
+    ///
+    /// Unlike [`borrow`], a conflicting borrow (eg. by a pending async method) is reported with a
+    /// dedicated error, so the caller can try again later.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `UserDataWouldBlock` if the userdata is currently mutably borrowed.
+    /// Returns a `UserDataTypeMismatch` if the userdata is not of type `T`.
+    ///
+    /// [`borrow`]: #method.borrow
+    #[inline]
+    pub fn try_borrow<T: 'static>(&self) -> Result<Ref<T>> {
+        self.inspect(|cell| cell.borrow_if_free())
+    }
+
+    /// Tries to borrow this userdata mutably if it is of type `T`.
+    ///
+    /// Unlike [`borrow_mut`], a conflicting borrow (eg. by a pending async method) is reported
+    /// with a dedicated error, so the caller can try again later.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `UserDataWouldBlock` if the userdata is currently borrowed.
+    /// Returns a `UserDataBorrowMutError` if the userdata can never be mutably borrowed (eg. it
+    /// was created from a shared reference). Returns a `UserDataTypeMismatch` if the userdata is
+    /// not of type `T`.
+    ///
+    /// [`borrow_mut`]: #method.borrow_mut
+    #[inline]
+    pub fn try_borrow_mut<T: 'static>(&self) -> Result<RefMut<T>> {
+        self.inspect(|cell| cell.borrow_mut_if_free())
+    }
+
     /// Takes the value out of this userdata.
     /// Sets the special "destructed" metatable that prevents any further operations with this userdata.
     ///
@@ -1057,6 +1574,30 @@ impl<'lua> AnyUserData<'lua> {
         }
     }
 
+    /// Returns a property stored by [`UserDataMethods::add_dynamic_fields_storage`].
+    ///
+    /// Missing properties are `nil`.
+    pub fn dynamic_get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        match self.get_named_user_value::<Option<Table>>(DYNAMIC_FIELDS_KEY)? {
+            Some(fields) => fields.raw_get(key),
+            None => V::from_lua(Value::Nil, self.0.lua),
+        }
+    }
+
+    /// Stores a property accessible by Lua code with
+    /// [`UserDataMethods::add_dynamic_fields_storage`].
+    pub fn dynamic_set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        let fields = match self.get_named_user_value::<Option<Table>>(DYNAMIC_FIELDS_KEY)? {
+            Some(fields) => fields,
+            None => {
+                let fields = self.0.lua.create_table()?;
+                self.set_named_user_value(DYNAMIC_FIELDS_KEY, fields.clone())?;
+                fields
+            }
+        };
+        fields.raw_set(key, value)
+    }
+
     /// Returns a metatable of this `UserData`.
     ///
     /// Returned [`UserDataMetatable`] object wraps the original metatable and
@@ -1096,6 +1637,13 @@ impl<'lua> AnyUserData<'lua> {
         unsafe { self.0.lua.get_userdata_type_id(&self.0) }
     }
 
+    /// Formats the userdata using its `__inspect` metamethod (if any).
+    pub(crate) fn fmt_inspect(&self) -> Option<StdString> {
+        let mt = self.get_raw_metatable().ok()?;
+        let func = mt.raw_get::<_, Option<Function>>("__inspect").ok()??;
+        func.call::<_, StdString>(self.clone()).ok()
+    }
+
     /// Returns a type name of this `UserData` (from `__name` metatable field).
     pub(crate) fn type_name(&self) -> Result<Option<StdString>> {
         let lua = self.0.lua;
@@ -1162,7 +1710,18 @@ impl<'lua> AnyUserData<'lua> {
         is_serializable().unwrap_or(false)
     }
 
-    fn inspect<'a, T, F, R>(&'a self, func: F) -> Result<R>
+    // Wakes async methods waiting for this userdata to be released (see `BorrowPolicy::Wait`).
+    // Without async support there is nobody to wake.
+    pub(crate) fn notify_borrow_released(&self) {
+        #[cfg(feature = "async")]
+        {
+            let lua = self.0.lua;
+            let ptr = unsafe { ffi::lua_topointer(lua.ref_thread(), self.0.index) };
+            lua.notify_borrow_released(ptr);
+        }
+    }
+
+    pub(crate) fn inspect<'a, T, F, R>(&'a self, func: F) -> Result<R>
     where
         T: 'static,
         F: FnOnce(&'a UserDataCell<T>) -> Result<R>,
@@ -1339,7 +1898,9 @@ impl<'lua> Serialize for AnyUserData<'lua> {
 /// A wrapper type for an immutably borrowed value from a `AnyUserData`.
 ///
 /// It implements [`FromLua`] and can be used to receive a typed userdata from Lua.
-pub struct UserDataRef<'lua, T: 'static>(AnyUserData<'lua>, Ref<'lua, T>);
+/// Values of other types are converted using the coercions registered for `T` by
+/// [`Lua::register_coercion`].
+pub struct UserDataRef<'lua, T: 'static>(AnyUserData<'lua>, ManuallyDrop<Ref<'lua, T>>);
 
 impl<'lua, T: 'static> Deref for UserDataRef<'lua, T> {
     type Target = T;
@@ -1354,14 +1915,21 @@ impl<'lua, T: 'static> UserDataRef<'lua, T> {
         let ud = try_value_to_userdata::<T>(value)?;
         // It's safe to lift lifetime of `Ref<T>` to `'lua` as long as we hold AnyUserData to it.
         let this = unsafe { mem::transmute(ud.borrow::<T>()?) };
-        Ok(UserDataRef(ud, this))
+        Ok(UserDataRef(ud, ManuallyDrop::new(this)))
+    }
+}
+
+impl<'lua, T: 'static> Drop for UserDataRef<'lua, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.1) };
+        self.0.notify_borrow_released();
     }
 }
 
 /// A wrapper type for a mutably borrowed value from a `AnyUserData`.
 ///
 /// It implements [`FromLua`] and can be used to receive a typed userdata from Lua.
-pub struct UserDataRefMut<'lua, T: 'static>(AnyUserData<'lua>, RefMut<'lua, T>);
+pub struct UserDataRefMut<'lua, T: 'static>(AnyUserData<'lua>, ManuallyDrop<RefMut<'lua, T>>);
 
 impl<'lua, T: 'static> Deref for UserDataRefMut<'lua, T> {
     type Target = T;
@@ -1382,7 +1950,14 @@ impl<'lua, T: 'static> UserDataRefMut<'lua, T> {
         let ud = try_value_to_userdata::<T>(value)?;
         // It's safe to lift lifetime of `RefMut<T>` to `'lua` as long as we hold AnyUserData to it.
         let this = unsafe { mem::transmute(ud.borrow_mut::<T>()?) };
-        Ok(UserDataRefMut(ud, this))
+        Ok(UserDataRefMut(ud, ManuallyDrop::new(this)))
+    }
+}
+
+impl<'lua, T: 'static> Drop for UserDataRefMut<'lua, T> {
+    fn drop(&mut self) {
+        unsafe { ManuallyDrop::drop(&mut self.1) };
+        self.0.notify_borrow_released();
     }
 }
 
@@ -1393,7 +1968,8 @@ fn try_value_to_userdata<T>(value: Value) -> Result<AnyUserData> {
             from: value.type_name(),
             to: "userdata",
             message: Some(format!("expected userdata of type {}", type_name::<T>())),
-        }),
+        }
+        .with_value_preview(value.preview())),
     }
 }
 
@@ -88,6 +88,17 @@ pub trait AnyUserDataExt<'lua>: Sealed {
 
 impl<'lua> AnyUserDataExt<'lua> for AnyUserData<'lua> {
     fn get<K: IntoLua<'lua>, V: FromLua<'lua>>(&self, key: K) -> Result<V> {
+        let lua = self.0.lua;
+        let key = key.into_lua(lua)?;
+        if let Some(name) = host_member_name(&key) {
+            if let Some(method) = lua.userdata_host_member(self, "methods", name)? {
+                return V::from_lua(Value::Function(method), lua);
+            }
+            if let Some(getter) = lua.userdata_host_member(self, "getters", name)? {
+                return getter.call(self.clone());
+            }
+        }
+
         let metatable = self.get_metatable()?;
         match metatable.get::<Value>(MetaMethod::Index)? {
             Value::Table(table) => table.raw_get(key),
@@ -99,6 +110,14 @@ impl<'lua> AnyUserDataExt<'lua> for AnyUserData<'lua> {
     }
 
     fn set<K: IntoLua<'lua>, V: IntoLua<'lua>>(&self, key: K, value: V) -> Result<()> {
+        let lua = self.0.lua;
+        let key = key.into_lua(lua)?;
+        if let Some(name) = host_member_name(&key) {
+            if let Some(setter) = lua.userdata_host_member(self, "setters", name)? {
+                return setter.call((self.clone(), value));
+            }
+        }
+
         let metatable = self.get_metatable()?;
         match metatable.get::<Value>(MetaMethod::NewIndex)? {
             Value::Table(table) => table.raw_set(key, value),
@@ -211,3 +230,11 @@ impl<'lua> AnyUserDataExt<'lua> for AnyUserData<'lua> {
         }
     }
 }
+
+// Name of a host-only member that can be referred to by the key
+fn host_member_name<'a>(key: &'a Value) -> Option<&'a str> {
+    match key {
+        Value::String(s) => s.to_str().ok(),
+        _ => None,
+    }
+}
@@ -7,13 +7,17 @@ use std::os::raw::c_int;
 use std::string::String as StdString;
 use std::sync::{Arc, Mutex, RwLock};
 
+use rustc_hash::FxHashSet;
+
+use crate::docs::FnMeta;
 use crate::error::{Error, Result};
 use crate::lua::Lua;
-use crate::types::{Callback, MaybeSend};
+use crate::types::{Callback, Integer, MaybeSend};
 use crate::userdata::{
-    AnyUserData, MetaMethod, UserData, UserDataCell, UserDataFields, UserDataMethods,
+    AnyUserData, BorrowPolicy, MetaMethod, MethodDesc, MethodDescFn, UserData, UserDataCell,
+    UserDataFields, UserDataMethods, Visibility,
 };
-use crate::util::{get_userdata, short_type_name};
+use crate::util::{assert_stack, check_stack, get_userdata, short_type_name, StackGuard};
 use crate::value::{FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, MultiValue, Value};
 
 #[cfg(not(feature = "send"))]
@@ -24,8 +28,19 @@ use {
     crate::types::AsyncCallback,
     futures_util::future::{self, TryFutureExt},
     std::future::Future,
+    std::os::raw::c_void,
+    std::task::Poll,
 };
 
+// Fields and methods of a userdata type accessible only from Rust
+pub(crate) struct HostOnlyMembers<'lua> {
+    pub(crate) methods: Vec<(String, Callback<'lua, 'static>)>,
+    #[cfg(feature = "async")]
+    pub(crate) async_methods: Vec<(String, AsyncCallback<'lua, 'static>)>,
+    pub(crate) getters: Vec<(String, Callback<'lua, 'static>)>,
+    pub(crate) setters: Vec<(String, Callback<'lua, 'static>)>,
+}
+
 pub struct UserDataRegistrar<'lua, T: 'static> {
     // Fields
     pub(crate) fields: Vec<(String, Callback<'lua, 'static>)>,
@@ -40,6 +55,14 @@ pub struct UserDataRegistrar<'lua, T: 'static> {
     pub(crate) meta_methods: Vec<(String, Callback<'lua, 'static>)>,
     #[cfg(feature = "async")]
     pub(crate) async_meta_methods: Vec<(String, AsyncCallback<'lua, 'static>)>,
+    pub(crate) method_docs: Vec<(String, FnMeta)>,
+
+    pub(crate) track_instances: bool,
+    pub(crate) lookup_key: Option<fn(&T) -> usize>,
+    pub(crate) deny_overrides: bool,
+    pub(crate) borrow_policy: BorrowPolicy,
+    // Names of members with `Visibility::HostOnly`
+    pub(crate) host_only: Vec<String>,
 
     _type: PhantomData<T>,
 }
@@ -57,10 +80,94 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
             meta_methods: Vec::new(),
             #[cfg(feature = "async")]
             async_meta_methods: Vec::new(),
+            method_docs: Vec::new(),
+            track_instances: false,
+            lookup_key: None,
+            deny_overrides: false,
+            borrow_policy: BorrowPolicy::Error,
+            host_only: Vec::new(),
             _type: PhantomData,
         }
     }
 
+    /// Enables tracking of live instances of `T`.
+    ///
+    /// Tracked instances can be enumerated using [`Lua::iter_userdata()`].
+    pub fn track_instances(&mut self) {
+        self.track_instances = true;
+    }
+
+    /// Enables reverse lookup of instances of `T` by the address of the wrapped value.
+    ///
+    /// Instances can be looked up using [`Lua::userdata_for()`].
+    pub fn reverse_lookup(&mut self) {
+        self.lookup_key = Some(|data| data as *const T as usize);
+    }
+
+    /// Enables reverse lookup of instances of `T` by a custom key.
+    ///
+    /// The key function receives the wrapped value and must return the same key for values
+    /// representing the same Rust object, for example the address of the data behind a smart
+    /// pointer or a unique id. Instances can be looked up using [`Lua::userdata_for()`].
+    pub fn reverse_lookup_by(&mut self, key: fn(&T) -> usize) {
+        self.lookup_key = Some(key);
+    }
+
+    fn set_member_visibility(&mut self, name: &str, visibility: Visibility) {
+        self.host_only.retain(|k| k != name);
+        if visibility == Visibility::HostOnly {
+            self.host_only.push(name.to_string());
+        }
+    }
+
+    /// Moves fields and methods with `Visibility::HostOnly` out of the registrar.
+    pub(crate) fn take_host_only(&mut self) -> HostOnlyMembers<'lua> {
+        let host_only = &self.host_only;
+        let take = |members: &mut Vec<(String, Callback<'lua, 'static>)>| {
+            let (hidden, public) = members.drain(..).partition(|(k, _)| host_only.contains(k));
+            *members = public;
+            hidden
+        };
+        // Static fields are exposed as getters ignoring their arguments
+        let mut getters = take(&mut self.fields);
+        getters.extend(take(&mut self.field_getters));
+        let members = HostOnlyMembers {
+            methods: take(&mut self.methods),
+            getters,
+            setters: take(&mut self.field_setters),
+            #[cfg(feature = "async")]
+            async_methods: {
+                let (hidden, public) =
+                    (self.async_methods.drain(..)).partition(|(k, _)| host_only.contains(k));
+                self.async_methods = public;
+                hidden
+            },
+        };
+        self.method_docs.retain(|(k, _)| !host_only.contains(k));
+        members
+    }
+
+    /// Checks that registered fields, methods and metamethods do not shadow each other, if
+    /// requested by the type.
+    pub(crate) fn check_conflicts(&self) -> Result<()> {
+        if !self.deny_overrides {
+            return Ok(());
+        }
+
+        let methods = member_names(&self.methods);
+        #[cfg(feature = "async")]
+        let methods = methods.chain(member_names(&self.async_methods));
+        let meta = member_names(&self.meta_fields).chain(member_names(&self.meta_methods));
+        #[cfg(feature = "async")]
+        let meta = meta.chain(member_names(&self.async_meta_methods));
+        check_member_conflicts::<T>(
+            member_names(&self.fields).chain(member_names(&self.field_getters)),
+            member_names(&self.field_setters),
+            methods,
+            meta,
+        )
+    }
+
     fn box_method<M, A, R>(name: &str, method: M) -> Callback<'lua, 'static>
     where
         M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
@@ -68,74 +175,12 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = get_function_name::<T>(name);
-        macro_rules! try_self_arg {
-            ($res:expr) => {
-                $res.map_err(|err| Error::bad_self_argument(&name, err))?
-            };
-            ($res:expr, $err:expr) => {
-                $res.map_err(|_| Error::bad_self_argument(&name, $err))?
-            };
-        }
-
-        Box::new(move |lua, mut args| {
-            let front = args
-                .pop_front()
-                .ok_or_else(|| Error::from_lua_conversion("missing argument", "userdata", None));
-            let front = try_self_arg!(front);
-            let call = |ud| {
+        Box::new(move |lua, args| {
+            Self::call_with_self(lua, &name, args, |ud, args| {
                 // Self was at index 1, so we pass 2 here
                 let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
                 method(lua, ud, args)?.into_lua_multi(lua)
-            };
-
-            let userdata = try_self_arg!(AnyUserData::from_lua(front, lua));
-            let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
-            match try_self_arg!(userdata.type_id()) {
-                Some(id) if id == TypeId::of::<T>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<T>(ref_thread, index));
-                    call(&ud)
-                },
-                #[cfg(not(feature = "send"))]
-                Some(id) if id == TypeId::of::<Rc<T>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<Rc<T>>(ref_thread, index));
-                    call(&ud)
-                },
-                #[cfg(not(feature = "send"))]
-                Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<Rc<RefCell<T>>>(ref_thread, index));
-                    let ud = try_self_arg!(ud.try_borrow(), Error::UserDataBorrowError);
-                    call(&ud)
-                },
-                Some(id) if id == TypeId::of::<Arc<T>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<Arc<T>>(ref_thread, index));
-                    call(&ud)
-                },
-                Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<Arc<Mutex<T>>>(ref_thread, index));
-                    let ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowError);
-                    call(&ud)
-                },
-                #[cfg(feature = "parking_lot")]
-                Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
-                    let ud = get_userdata_ref::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
-                    let ud = try_self_arg!(ud);
-                    let ud = try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowError));
-                    call(&ud)
-                },
-                Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_ref::<Arc<RwLock<T>>>(ref_thread, index));
-                    let ud = try_self_arg!(ud.try_read(), Error::UserDataBorrowError);
-                    call(&ud)
-                },
-                #[cfg(feature = "parking_lot")]
-                Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
-                    let ud = get_userdata_ref::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
-                    let ud = try_self_arg!(ud);
-                    let ud = try_self_arg!(ud.try_read().ok_or(Error::UserDataBorrowError));
-                    call(&ud)
-                },
-                _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
-            }
+            })
         })
     }
 
@@ -146,73 +191,179 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
         R: IntoLuaMulti<'lua>,
     {
         let name = get_function_name::<T>(name);
-        macro_rules! try_self_arg {
-            ($res:expr) => {
-                $res.map_err(|err| Error::bad_self_argument(&name, err))?
-            };
-            ($res:expr, $err:expr) => {
-                $res.map_err(|_| Error::bad_self_argument(&name, $err))?
-            };
-        }
-
         let method = RefCell::new(method);
-        Box::new(move |lua, mut args| {
+        Box::new(move |lua, args| {
             let mut method = method
                 .try_borrow_mut()
                 .map_err(|_| Error::RecursiveMutCallback)?;
-            let front = args
-                .pop_front()
-                .ok_or_else(|| Error::from_lua_conversion("missing argument", "userdata", None));
-            let front = try_self_arg!(front);
-            let call = |ud| {
+            Self::call_with_self_mut(lua, &name, args, |ud, args| {
                 // Self was at index 1, so we pass 2 here
                 let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
                 method(lua, ud, args)?.into_lua_multi(lua)
+            })
+        })
+    }
+
+    // Boxes a method of a `MethodDesc`, passing the arguments to its function pointer unchanged
+    fn box_method_desc(desc: &MethodDesc<T>) -> Callback<'lua, 'static> {
+        let name = get_function_name::<T>(desc.name);
+        match desc.func {
+            MethodDescFn::Ref(func) => Box::new(move |lua, args| {
+                Self::call_with_self(lua, &name, args, |ud, args| func(lua, ud, args))
+            }),
+            MethodDescFn::Mut(func) => Box::new(move |lua, args| {
+                Self::call_with_self_mut(lua, &name, args, |ud, args| func(lua, ud, args))
+            }),
+        }
+    }
+
+    // Borrows `self` from the first argument and calls `call` with it and the rest of arguments
+    fn call_with_self<R>(
+        lua: &'lua Lua,
+        name: &str,
+        mut args: MultiValue<'lua>,
+        call: impl FnOnce(&T, MultiValue<'lua>) -> Result<R>,
+    ) -> Result<R> {
+        macro_rules! try_self_arg {
+            ($res:expr) => {
+                $res.map_err(|err| self_arg_error::<T>(lua, name, err))?
             };
+            ($res:expr, $err:expr) => {
+                $res.map_err(|_| self_arg_error::<T>(lua, name, $err))?
+            };
+        }
+
+        let front = args
+            .pop_front()
+            .ok_or_else(|| Error::from_lua_conversion("missing argument", "userdata", None));
+        let front = try_self_arg!(front);
+        let call = |ud| call(ud, args);
 
-            let userdata = try_self_arg!(AnyUserData::from_lua(front, lua));
-            let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
-            match try_self_arg!(userdata.type_id()) {
-                Some(id) if id == TypeId::of::<T>() => unsafe {
-                    let mut ud = try_self_arg!(get_userdata_mut::<T>(ref_thread, index));
-                    call(&mut ud)
-                },
-                #[cfg(not(feature = "send"))]
-                Some(id) if id == TypeId::of::<Rc<T>>() => Err(Error::UserDataBorrowMutError),
-                #[cfg(not(feature = "send"))]
-                Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_mut::<Rc<RefCell<T>>>(ref_thread, index));
-                    let mut ud = try_self_arg!(ud.try_borrow_mut(), Error::UserDataBorrowMutError);
-                    call(&mut ud)
-                },
-                Some(id) if id == TypeId::of::<Arc<T>>() => Err(Error::UserDataBorrowMutError),
-                Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_mut::<Arc<Mutex<T>>>(ref_thread, index));
-                    let mut ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowMutError);
-                    call(&mut ud)
-                },
-                #[cfg(feature = "parking_lot")]
-                Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
-                    let ud = get_userdata_mut::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
-                    let ud = try_self_arg!(ud);
-                    let mut ud = try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowMutError));
-                    call(&mut ud)
-                },
-                Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
-                    let ud = try_self_arg!(get_userdata_mut::<Arc<RwLock<T>>>(ref_thread, index));
-                    let mut ud = try_self_arg!(ud.try_write(), Error::UserDataBorrowMutError);
-                    call(&mut ud)
-                },
-                #[cfg(feature = "parking_lot")]
-                Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
-                    let ud = get_userdata_mut::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
-                    let ud = try_self_arg!(ud);
-                    let mut ud = try_self_arg!(ud.try_write().ok_or(Error::UserDataBorrowMutError));
-                    call(&mut ud)
-                },
-                _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
+        let userdata = try_self_arg!(AnyUserData::from_lua(front, lua));
+        let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
+        match try_self_arg!(userdata.type_id()) {
+            Some(id) if id == TypeId::of::<T>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<T>(ref_thread, index));
+                call(&ud)
+            },
+            #[cfg(not(feature = "send"))]
+            Some(id) if id == TypeId::of::<Rc<T>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<Rc<T>>(ref_thread, index));
+                call(&ud)
+            },
+            #[cfg(not(feature = "send"))]
+            Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<Rc<RefCell<T>>>(ref_thread, index));
+                let ud = try_self_arg!(ud.try_borrow(), Error::UserDataBorrowError);
+                call(&ud)
+            },
+            Some(id) if id == TypeId::of::<Arc<T>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<Arc<T>>(ref_thread, index));
+                call(&ud)
+            },
+            Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<Arc<Mutex<T>>>(ref_thread, index));
+                let ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowError);
+                call(&ud)
+            },
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
+                let ud = get_userdata_ref::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
+                let ud = try_self_arg!(ud);
+                let ud = try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowError));
+                call(&ud)
+            },
+            Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_ref::<Arc<RwLock<T>>>(ref_thread, index));
+                let ud = try_self_arg!(ud.try_read(), Error::UserDataBorrowError);
+                call(&ud)
+            },
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
+                let ud = get_userdata_ref::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
+                let ud = try_self_arg!(ud);
+                let ud = try_self_arg!(ud.try_read().ok_or(Error::UserDataBorrowError));
+                call(&ud)
+            },
+            Some(id) if id == TypeId::of::<AppDataProxy<T>>() => {
+                let data = try_self_arg!(lua.try_app_data_ref::<T>());
+                let data = try_self_arg!(data.ok_or_else(app_data_missing::<T>));
+                call(&data)
             }
-        })
+            _ => Err(Error::bad_self_argument(name, Error::UserDataTypeMismatch)),
+        }
+    }
+
+    // Mutably borrows `self` from the first argument and calls `call` with it and the rest of
+    // arguments
+    fn call_with_self_mut<R>(
+        lua: &'lua Lua,
+        name: &str,
+        mut args: MultiValue<'lua>,
+        call: impl FnOnce(&mut T, MultiValue<'lua>) -> Result<R>,
+    ) -> Result<R> {
+        macro_rules! try_self_arg {
+            ($res:expr) => {
+                $res.map_err(|err| self_arg_error::<T>(lua, name, err))?
+            };
+            ($res:expr, $err:expr) => {
+                $res.map_err(|_| self_arg_error::<T>(lua, name, $err))?
+            };
+        }
+
+        let front = args
+            .pop_front()
+            .ok_or_else(|| Error::from_lua_conversion("missing argument", "userdata", None));
+        let front = try_self_arg!(front);
+        let call = |ud| call(ud, args);
+
+        let userdata = try_self_arg!(AnyUserData::from_lua(front, lua));
+        let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
+        match try_self_arg!(userdata.type_id()) {
+            Some(id) if id == TypeId::of::<T>() => unsafe {
+                let mut ud = try_self_arg!(get_userdata_mut::<T>(ref_thread, index));
+                call(&mut ud)
+            },
+            #[cfg(not(feature = "send"))]
+            Some(id) if id == TypeId::of::<Rc<T>>() => Err(Error::UserDataBorrowMutError),
+            #[cfg(not(feature = "send"))]
+            Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_mut::<Rc<RefCell<T>>>(ref_thread, index));
+                let mut ud = try_self_arg!(ud.try_borrow_mut(), Error::UserDataBorrowMutError);
+                call(&mut ud)
+            },
+            Some(id) if id == TypeId::of::<Arc<T>>() => Err(Error::UserDataBorrowMutError),
+            Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_mut::<Arc<Mutex<T>>>(ref_thread, index));
+                let mut ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowMutError);
+                call(&mut ud)
+            },
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
+                let ud = get_userdata_mut::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
+                let ud = try_self_arg!(ud);
+                let mut ud = try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowMutError));
+                call(&mut ud)
+            },
+            Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
+                let ud = try_self_arg!(get_userdata_mut::<Arc<RwLock<T>>>(ref_thread, index));
+                let mut ud = try_self_arg!(ud.try_write(), Error::UserDataBorrowMutError);
+                call(&mut ud)
+            },
+            #[cfg(feature = "parking_lot")]
+            Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
+                let ud = get_userdata_mut::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
+                let ud = try_self_arg!(ud);
+                let mut ud = try_self_arg!(ud.try_write().ok_or(Error::UserDataBorrowMutError));
+                call(&mut ud)
+            },
+            Some(id) if id == TypeId::of::<AppDataProxy<T>>() => {
+                let data = try_self_arg!(lua.try_app_data_mut::<T>());
+                let mut data = try_self_arg!(data.ok_or_else(app_data_missing::<T>));
+                call(&mut data)
+            }
+            _ => Err(Error::bad_self_argument(name, Error::UserDataTypeMismatch)),
+        }
     }
 
     #[cfg(feature = "async")]
@@ -233,10 +384,7 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
             let method = method.clone();
             macro_rules! try_self_arg {
                 ($res:expr) => {
-                    $res.map_err(|err| Error::bad_self_argument(&name, err))?
-                };
-                ($res:expr, $err:expr) => {
-                    $res.map_err(|_| Error::bad_self_argument(&name, $err))?
+                    $res.map_err(|err| self_arg_error::<T>(lua, &name, err))?
                 };
             }
 
@@ -247,71 +395,117 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
                 let front = try_self_arg!(front);
                 let userdata: AnyUserData = try_self_arg!(AnyUserData::from_lua(front, lua));
                 let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
-                match try_self_arg!(userdata.type_id()) {
-                    Some(id) if id == TypeId::of::<T>() => unsafe {
-                        let ud = try_self_arg!(get_userdata_ref::<T>(ref_thread, index));
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        // Self was at index 1, so we pass 2 here
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(not(feature = "send"))]
-                    Some(id) if id == TypeId::of::<Rc<T>>() => unsafe {
-                        let ud = try_self_arg!(get_userdata_ref::<Rc<T>>(ref_thread, index));
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(not(feature = "send"))]
-                    Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_ref::<Rc<RefCell<T>>>(ref_thread, index));
-                        let ud = try_self_arg!(ud.try_borrow(), Error::UserDataBorrowError);
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    Some(id) if id == TypeId::of::<Arc<T>>() => unsafe {
-                        let ud = try_self_arg!(get_userdata_ref::<Arc<T>>(ref_thread, index));
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_ref::<Arc<Mutex<T>>>(ref_thread, index));
-                        let ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowError);
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(feature = "parking_lot")]
-                    Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
-                        let ud = get_userdata_ref::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
-                        let ud = try_self_arg!(ud);
-                        let ud = try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowError));
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_ref::<Arc<RwLock<T>>>(ref_thread, index));
-                        let ud = try_self_arg!(ud.try_read(), Error::UserDataBorrowError);
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(feature = "parking_lot")]
-                    Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
-                        let ud = get_userdata_ref::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
-                        let ud = try_self_arg!(ud);
-                        let ud = try_self_arg!(ud.try_read().ok_or(Error::UserDataBorrowError));
-                        let ud = std::mem::transmute::<&T, &T>(&ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
+                let ptr = unsafe { ffi::lua_topointer(ref_thread, index) };
+                // Borrows `self`, retrying if it's borrowed and the borrow policy is to wait
+                macro_rules! borrow_self_arg {
+                    ($res:expr) => {
+                        match $res {
+                            Ok(ud) => ud,
+                            Err(err) if waits_for_borrow::<T>(lua, ptr, &err) => continue,
+                            Err(err) => return Err(self_arg_error::<T>(lua, &name, err)),
+                        }
+                    };
+                    ($res:expr, $err:expr) => {
+                        borrow_self_arg!($res.map_err(|_| $err))
+                    };
+                }
+                let type_id = try_self_arg!(userdata.type_id());
+                let mut retry = false;
+                loop {
+                    if retry {
+                        wait_for_release(lua, ptr).await;
+                    }
+                    retry = true;
+                    return match type_id {
+                        Some(id) if id == TypeId::of::<T>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_ref::<T>(ref_thread, index));
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            // Self was at index 1, so we pass 2 here
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(not(feature = "send"))]
+                        Some(id) if id == TypeId::of::<Rc<T>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_ref::<Rc<T>>(ref_thread, index));
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(not(feature = "send"))]
+                        Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_ref::<Rc<RefCell<T>>>(
+                                ref_thread, index
+                            ));
+                            let ud = borrow_self_arg!(ud.try_borrow(), Error::UserDataBorrowError);
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        Some(id) if id == TypeId::of::<Arc<T>>() => unsafe {
+                            let ud =
+                                borrow_self_arg!(get_userdata_ref::<Arc<T>>(ref_thread, index));
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_ref::<Arc<Mutex<T>>>(
+                                ref_thread, index
+                            ));
+                            let ud = borrow_self_arg!(ud.try_lock(), Error::UserDataBorrowError);
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(feature = "parking_lot")]
+                        Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
+                            let ud =
+                                get_userdata_ref::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
+                            let ud = borrow_self_arg!(ud);
+                            let ud =
+                                borrow_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowError));
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_ref::<Arc<RwLock<T>>>(
+                                ref_thread, index
+                            ));
+                            let ud = borrow_self_arg!(ud.try_read(), Error::UserDataBorrowError);
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(feature = "parking_lot")]
+                        Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
+                            let ud =
+                                get_userdata_ref::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
+                            let ud = borrow_self_arg!(ud);
+                            let ud =
+                                borrow_self_arg!(ud.try_read().ok_or(Error::UserDataBorrowError));
+                            let ud = std::mem::transmute::<&T, &T>(&ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
+                    };
                 }
             })
         })
@@ -335,10 +529,7 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
             let method = method.clone();
             macro_rules! try_self_arg {
                 ($res:expr) => {
-                    $res.map_err(|err| Error::bad_self_argument(&name, err))?
-                };
-                ($res:expr, $err:expr) => {
-                    $res.map_err(|_| Error::bad_self_argument(&name, $err))?
+                    $res.map_err(|err| self_arg_error::<T>(lua, &name, err))?
                 };
             }
 
@@ -349,67 +540,114 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
                 let front = try_self_arg!(front);
                 let userdata: AnyUserData = try_self_arg!(AnyUserData::from_lua(front, lua));
                 let (ref_thread, index) = (lua.ref_thread(), userdata.0.index);
-                match try_self_arg!(userdata.type_id()) {
-                    Some(id) if id == TypeId::of::<T>() => unsafe {
-                        let mut ud = try_self_arg!(get_userdata_mut::<T>(ref_thread, index));
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        // Self was at index 1, so we pass 2 here
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(not(feature = "send"))]
-                    Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => {
-                        Err(Error::UserDataBorrowMutError)
+                let ptr = unsafe { ffi::lua_topointer(ref_thread, index) };
+                // Borrows `self`, retrying if it's borrowed and the borrow policy is to wait
+                macro_rules! borrow_self_arg {
+                    ($res:expr) => {
+                        match $res {
+                            Ok(ud) => ud,
+                            Err(err) if waits_for_borrow::<T>(lua, ptr, &err) => continue,
+                            Err(err) => return Err(self_arg_error::<T>(lua, &name, err)),
+                        }
+                    };
+                    ($res:expr, $err:expr) => {
+                        borrow_self_arg!($res.map_err(|_| $err))
+                    };
+                }
+                let type_id = try_self_arg!(userdata.type_id());
+                let mut retry = false;
+                loop {
+                    if retry {
+                        wait_for_release(lua, ptr).await;
                     }
-                    #[cfg(not(feature = "send"))]
-                    Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_mut::<Rc<RefCell<T>>>(ref_thread, index));
-                        let mut ud =
-                            try_self_arg!(ud.try_borrow_mut(), Error::UserDataBorrowMutError);
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(not(feature = "send"))]
-                    Some(id) if id == TypeId::of::<Arc<T>>() => Err(Error::UserDataBorrowMutError),
-                    Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_mut::<Arc<Mutex<T>>>(ref_thread, index));
-                        let mut ud = try_self_arg!(ud.try_lock(), Error::UserDataBorrowMutError);
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(feature = "parking_lot")]
-                    Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
-                        let ud = get_userdata_mut::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
-                        let ud = try_self_arg!(ud);
-                        let mut ud =
-                            try_self_arg!(ud.try_lock().ok_or(Error::UserDataBorrowMutError));
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
-                        let ud =
-                            try_self_arg!(get_userdata_mut::<Arc<RwLock<T>>>(ref_thread, index));
-                        let mut ud = try_self_arg!(ud.try_write(), Error::UserDataBorrowMutError);
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    #[cfg(feature = "parking_lot")]
-                    Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
-                        let ud = get_userdata_mut::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
-                        let ud = try_self_arg!(ud);
-                        let mut ud =
-                            try_self_arg!(ud.try_write().ok_or(Error::UserDataBorrowMutError));
-                        let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
-                        let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
-                        method(lua, ud, args).await?.into_lua_multi(lua)
-                    },
-                    _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
+                    retry = true;
+                    return match type_id {
+                        Some(id) if id == TypeId::of::<T>() => unsafe {
+                            let mut ud = borrow_self_arg!(get_userdata_mut::<T>(ref_thread, index));
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            // Self was at index 1, so we pass 2 here
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(not(feature = "send"))]
+                        Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => {
+                            Err(Error::UserDataBorrowMutError)
+                        }
+                        #[cfg(not(feature = "send"))]
+                        Some(id) if id == TypeId::of::<Rc<RefCell<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_mut::<Rc<RefCell<T>>>(
+                                ref_thread, index
+                            ));
+                            let mut ud = borrow_self_arg!(
+                                ud.try_borrow_mut(),
+                                Error::UserDataBorrowMutError
+                            );
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(not(feature = "send"))]
+                        Some(id) if id == TypeId::of::<Arc<T>>() => {
+                            Err(Error::UserDataBorrowMutError)
+                        }
+                        Some(id) if id == TypeId::of::<Arc<Mutex<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_mut::<Arc<Mutex<T>>>(
+                                ref_thread, index
+                            ));
+                            let mut ud =
+                                borrow_self_arg!(ud.try_lock(), Error::UserDataBorrowMutError);
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(feature = "parking_lot")]
+                        Some(id) if id == TypeId::of::<Arc<parking_lot::Mutex<T>>>() => unsafe {
+                            let ud =
+                                get_userdata_mut::<Arc<parking_lot::Mutex<T>>>(ref_thread, index);
+                            let ud = borrow_self_arg!(ud);
+                            let mut ud = borrow_self_arg!(ud
+                                .try_lock()
+                                .ok_or(Error::UserDataBorrowMutError));
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        Some(id) if id == TypeId::of::<Arc<RwLock<T>>>() => unsafe {
+                            let ud = borrow_self_arg!(get_userdata_mut::<Arc<RwLock<T>>>(
+                                ref_thread, index
+                            ));
+                            let mut ud =
+                                borrow_self_arg!(ud.try_write(), Error::UserDataBorrowMutError);
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        #[cfg(feature = "parking_lot")]
+                        Some(id) if id == TypeId::of::<Arc<parking_lot::RwLock<T>>>() => unsafe {
+                            let ud =
+                                get_userdata_mut::<Arc<parking_lot::RwLock<T>>>(ref_thread, index);
+                            let ud = borrow_self_arg!(ud);
+                            let mut ud = borrow_self_arg!(ud
+                                .try_write()
+                                .ok_or(Error::UserDataBorrowMutError));
+                            let ud = std::mem::transmute::<&mut T, &mut T>(&mut ud);
+                            let args = A::from_lua_multi_args(args, 2, Some(&name), lua)?;
+                            hold_borrow(lua, ptr, method(lua, ud, args))
+                                .await?
+                                .into_lua_multi(lua)
+                        },
+                        _ => Err(Error::bad_self_argument(&name, Error::UserDataTypeMismatch)),
+                    };
                 }
             })
         })
@@ -488,6 +726,77 @@ impl<'lua, T: 'static> UserDataRegistrar<'lua, T> {
     }
 }
 
+// Converts a failure to use `self` according to the borrow policy of `T`
+fn self_arg_error<T: 'static>(lua: &Lua, name: &str, err: Error) -> Error {
+    match (lua.borrow_policy::<T>(), &err) {
+        (BorrowPolicy::LuaError, Error::UserDataBorrowError) => {
+            Error::RuntimeError(format!("`{name}`: userdata is already mutably borrowed"))
+        }
+        (BorrowPolicy::LuaError, Error::UserDataBorrowMutError) => {
+            Error::RuntimeError(format!("`{name}`: userdata is already borrowed"))
+        }
+        _ => Error::bad_self_argument(name, err),
+    }
+}
+
+// Returns true if a method must wait until the userdata at `ptr` can be borrowed.
+// Waiting is pointless (and would hang) when the borrow is held by a method this call is nested
+// in, so the borrow error is returned in that case.
+#[cfg(feature = "async")]
+fn waits_for_borrow<T: 'static>(lua: &Lua, ptr: *const c_void, err: &Error) -> bool {
+    lua.borrow_policy::<T>() == BorrowPolicy::Wait
+        && matches!(
+            err,
+            Error::UserDataBorrowError | Error::UserDataBorrowMutError
+        )
+        && !lua.is_borrow_polled(ptr)
+}
+
+// Waits until the userdata at `ptr` is released
+#[cfg(feature = "async")]
+async fn wait_for_release(lua: &Lua, ptr: *const c_void) {
+    let mut registered = false;
+    future::poll_fn(|cx| {
+        if registered {
+            return Poll::Ready(());
+        }
+        registered = true;
+        lua.add_borrow_waiter(ptr, cx.waker());
+        Poll::Pending
+    })
+    .await
+}
+
+// Runs a method future holding a borrow of the userdata at `ptr`, waking the tasks waiting for it
+// once the future completes or is dropped
+#[cfg(feature = "async")]
+async fn hold_borrow<F: Future>(lua: &Lua, ptr: *const c_void, fut: F) -> F::Output {
+    struct Release<'a>(&'a Lua, *const c_void);
+
+    impl Drop for Release<'_> {
+        fn drop(&mut self) {
+            self.0.notify_borrow_released(self.1);
+        }
+    }
+
+    struct Polling<'a>(&'a Lua);
+
+    impl Drop for Polling<'_> {
+        fn drop(&mut self) {
+            self.0.pop_polled_borrow();
+        }
+    }
+
+    let _release = Release(lua, ptr);
+    futures_util::pin_mut!(fut);
+    future::poll_fn(|cx| {
+        lua.push_polled_borrow(ptr);
+        let _polling = Polling(lua);
+        fut.as_mut().poll(cx)
+    })
+    .await
+}
+
 // Returns function name for the type `T`, without the module path
 fn get_function_name<T>(name: &str) -> StdString {
     format!("{}.{name}", short_type_name::<T>())
@@ -572,7 +881,12 @@ impl<'lua, T: 'static> UserDataFields<'lua, T> for UserDataRegistrar<'lua, T> {
 
     // Below are internal methods
 
+    fn set_visibility(&mut self, name: impl AsRef<str>, visibility: Visibility) {
+        self.set_member_visibility(name.as_ref(), visibility);
+    }
+
     fn append_fields_from<S>(&mut self, other: UserDataRegistrar<'lua, S>) {
+        self.host_only.extend(other.host_only);
         self.fields.extend(other.fields);
         self.field_getters.extend(other.field_getters);
         self.field_setters.extend(other.field_setters);
@@ -581,6 +895,16 @@ impl<'lua, T: 'static> UserDataFields<'lua, T> for UserDataRegistrar<'lua, T> {
 }
 
 impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistrar<'lua, T> {
+    fn add_methods_from_descriptors(&mut self, descriptors: &[MethodDesc<T>]) {
+        for desc in descriptors {
+            let method = (desc.name.into(), Self::box_method_desc(desc));
+            match desc.meta {
+                false => self.methods.push(method),
+                true => self.meta_methods.push(method),
+            }
+        }
+    }
+
     fn add_method<M, A, R>(&mut self, name: impl AsRef<str>, method: M)
     where
         M: Fn(&'lua Lua, &T, A) -> Result<R> + MaybeSend + 'static,
@@ -757,16 +1081,100 @@ impl<'lua, T: 'static> UserDataMethods<'lua, T> for UserDataRegistrar<'lua, T> {
 
     // Below are internal methods used in generated code
 
+    fn deny_overrides(&mut self) {
+        self.deny_overrides = true;
+    }
+
+    fn set_borrow_policy(&mut self, policy: BorrowPolicy) {
+        self.borrow_policy = policy;
+    }
+
+    fn set_visibility(&mut self, name: impl AsRef<str>, visibility: Visibility) {
+        self.set_member_visibility(name.as_ref(), visibility);
+    }
+
+    fn document_method(&mut self, name: impl AsRef<str>, meta: FnMeta) {
+        self.method_docs.push((name.as_ref().to_string(), meta));
+    }
+
     fn append_methods_from<S>(&mut self, other: UserDataRegistrar<'lua, S>) {
+        self.deny_overrides |= other.deny_overrides;
+        self.host_only.extend(other.host_only);
+        if other.borrow_policy != BorrowPolicy::Error {
+            self.borrow_policy = other.borrow_policy;
+        }
         self.methods.extend(other.methods);
         #[cfg(feature = "async")]
         self.async_methods.extend(other.async_methods);
         self.meta_methods.extend(other.meta_methods);
         #[cfg(feature = "async")]
         self.async_meta_methods.extend(other.async_meta_methods);
+        self.method_docs.extend(other.method_docs);
     }
 }
 
+// Returns names of the registered members
+pub(crate) fn member_names<V>(members: &[(String, V)]) -> impl Iterator<Item = &str> {
+    members.iter().map(|(name, _)| name.as_str())
+}
+
+/// Checks that the given member names of the userdata type `T` do not shadow each other.
+pub(crate) fn check_member_conflicts<'a, T: ?Sized>(
+    getters: impl Iterator<Item = &'a str>,
+    setters: impl Iterator<Item = &'a str>,
+    methods: impl Iterator<Item = &'a str>,
+    meta: impl Iterator<Item = &'a str>,
+) -> Result<()> {
+    let conflict = |message: StdString| {
+        let type_name = short_type_name::<T>();
+        Err(Error::RegistrationConflict(format!(
+            "{type_name}: {message}"
+        )))
+    };
+
+    let mut getter_names = FxHashSet::default();
+    for name in getters {
+        if !getter_names.insert(name) {
+            return conflict(format!("field `{name}` is registered more than once"));
+        }
+    }
+
+    let mut setter_names = FxHashSet::default();
+    for name in setters {
+        if !setter_names.insert(name) {
+            return conflict(format!(
+                "field `{name}` setter is registered more than once"
+            ));
+        }
+    }
+
+    let mut method_names = FxHashSet::default();
+    for name in methods {
+        if !method_names.insert(name) {
+            return conflict(format!("method `{name}` is registered more than once"));
+        }
+        if getter_names.contains(name) {
+            return conflict(format!(
+                "field `{name}` shadows the method with the same name"
+            ));
+        }
+        if MetaMethod::is_reserved(name) {
+            return conflict(format!(
+                "method `{name}` is named like a metamethod (use `add_meta_method` instead)"
+            ));
+        }
+    }
+
+    let mut meta_names = FxHashSet::default();
+    for name in meta {
+        if !meta_names.insert(name) {
+            return conflict(format!("metamethod `{name}` is registered more than once"));
+        }
+    }
+
+    Ok(())
+}
+
 #[inline]
 unsafe fn get_userdata_ref<'a, T>(state: *mut ffi::lua_State, index: c_int) -> Result<Ref<'a, T>> {
     (*get_userdata::<UserDataCell<T>>(state, index)).try_borrow()
@@ -815,3 +1223,67 @@ lua_userdata_impl!(Arc<parking_lot::RwLock<T>>);
 pub(crate) struct UserDataProxy<T>(pub(crate) PhantomData<T>);
 
 lua_userdata_impl!(UserDataProxy<T>);
+
+// A special proxy object for UserData stored as app data
+pub(crate) struct AppDataProxy<T>(pub(crate) PhantomData<T>);
+
+lua_userdata_impl!(AppDataProxy<T>);
+
+fn app_data_missing<T>() -> Error {
+    let type_name = short_type_name::<T>();
+    Error::RuntimeError(format!("app data of type `{type_name}` is not set"))
+}
+
+// Creates a weak table for live instances of a tracked userdata type, returning its registry id
+pub(crate) unsafe fn create_tracking_table(state: *mut ffi::lua_State) -> Result<c_int> {
+    protect_lua!(state, 0, 0, |state| {
+        ffi::lua_createtable(state, 0, 0);
+        ffi::lua_createtable(state, 0, 1);
+        ffi::lua_pushstring(state, cstr!("k"));
+        ffi::lua_setfield(state, -2, cstr!("__mode"));
+        ffi::lua_setmetatable(state, -2);
+        ffi::luaL_ref(state, ffi::LUA_REGISTRYINDEX)
+    })
+}
+
+// Adds the userdata on top of the stack to the table of live instances
+pub(crate) unsafe fn track_instance(
+    state: *mut ffi::lua_State,
+    table_id: c_int,
+    protect: bool,
+) -> Result<()> {
+    check_stack(state, 5)?;
+    ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, table_id as Integer);
+    ffi::lua_pushvalue(state, -2);
+    ffi::lua_pushboolean(state, 1);
+    if protect {
+        protect_lua!(state, 3, 0, fn(state) ffi::lua_rawset(state, -3))?;
+    } else {
+        ffi::lua_rawset(state, -3);
+        ffi::lua_pop(state, 1);
+    }
+    Ok(())
+}
+
+// Returns live (not destructed) instances of `T` from the table of live instances
+pub(crate) unsafe fn tracked_instances<T: 'static>(
+    lua: &Lua,
+    table_id: c_int,
+) -> Vec<AnyUserData<'_>> {
+    let state = lua.state();
+    let _sg = StackGuard::new(state);
+    assert_stack(state, 3);
+
+    let mut instances = Vec::new();
+    ffi::lua_rawgeti(state, ffi::LUA_REGISTRYINDEX, table_id as Integer);
+    ffi::lua_pushnil(state);
+    while ffi::lua_next(state, -2) != 0 {
+        ffi::lua_pop(state, 1);
+        ffi::lua_pushvalue(state, -1);
+        let ud = AnyUserData(lua.pop_ref());
+        if ud.inspect(|_: &UserDataCell<T>| Ok(())).is_ok() {
+            instances.push(ud);
+        }
+    }
+    instances
+}
@@ -12,6 +12,7 @@ use once_cell::sync::Lazy;
 use rustc_hash::FxHashMap;
 
 use crate::error::{Error, Result};
+use crate::lua::apply_traceback_options;
 use crate::memory::MemoryState;
 
 pub(crate) use short_names::short_type_name;
@@ -225,7 +226,10 @@ pub unsafe fn pop_error(state: *mut ffi::lua_State, err_code: c_int) -> Error {
                     // runtime errors, so we handle them the same way.
                     Error::RuntimeError(err_string)
                 }
-                ffi::LUA_ERRMEM => Error::MemoryError(err_string),
+                ffi::LUA_ERRMEM => match MemoryState::take_limit_exceeded(state) {
+                    Some(limit) => Error::MemoryLimitExceeded(limit),
+                    None => Error::MemoryError(err_string),
+                },
                 #[cfg(any(feature = "lua53", feature = "lua52"))]
                 ffi::LUA_ERRGCMM => Error::GarbageCollectorError(err_string),
                 _ => mlua_panic!("unrecognized lua error code"),
@@ -659,6 +663,7 @@ where
             // Build `CallbackError` with traceback
             let traceback = if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
                 ffi::luaL_traceback(state, state, ptr::null(), 0);
+                apply_traceback_options(state);
                 let traceback = to_string(state, -1);
                 ffi::lua_pop(state, 1);
                 traceback
@@ -702,6 +707,7 @@ pub unsafe extern "C" fn error_traceback(state: *mut ffi::lua_State) -> c_int {
         if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
             ffi::luaL_traceback(state, state, s, 0);
             ffi::lua_remove(state, -2);
+            apply_traceback_options(state);
         }
     }
 
@@ -718,6 +724,7 @@ pub unsafe fn error_traceback_thread(state: *mut ffi::lua_State, thread: *mut ff
         if ffi::lua_checkstack(state, ffi::LUA_TRACEBACK_STACK) != 0 {
             ffi::luaL_traceback(state, thread, s, 0);
             ffi::lua_remove(state, -2);
+            apply_traceback_options(state);
         }
     }
 }
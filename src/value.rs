@@ -1,11 +1,12 @@
 use std::cmp::Ordering;
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::iter::{self, FromIterator};
 use std::ops::Index;
 use std::os::raw::c_void;
 use std::string::String as StdString;
 use std::sync::Arc;
-use std::{fmt, ptr, slice, str, vec};
+use std::{fmt, mem, ptr, slice, str, vec};
 
 #[cfg(feature = "serialize")]
 use {
@@ -163,6 +164,139 @@ impl<'lua> Value<'lua> {
         }
     }
 
+    /// Feeds the value into the given [`Hasher`].
+    ///
+    /// The hash is consistent with equality (`==`) of values: booleans, numbers and strings
+    /// are hashed by content (an integer and a float with the same numeric value hash equally),
+    /// and tables, functions, threads and userdata are hashed by reference.
+    /// Metamethods are not invoked.
+    ///
+    /// See [`HashableValue`] for using values as keys in Rust collections.
+    #[allow(clippy::should_implement_trait)]
+    pub fn hash<H: Hasher>(&self, state: &mut H) {
+        // Normalizes -0.0 to 0.0 so that equal numbers hash equally
+        fn hash_num<H: Hasher>(n: f64, state: &mut H) {
+            let n = if n == 0.0 { 0.0 } else { n };
+            n.to_bits().hash(state);
+        }
+
+        mem::discriminant(&self.hash_kind()).hash(state);
+        match self {
+            Value::Nil | Value::Error(_) => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::LightUserData(ud) => ud.0.hash(state),
+            // Integers are hashed as floats to match `Integer(a) == Number(b)` comparison
+            Value::Integer(i) => hash_num(*i as f64, state),
+            Value::Number(n) => hash_num(*n, state),
+            #[cfg(feature = "luau")]
+            Value::Vector(v) => (v.0.iter()).for_each(|&n| hash_num(n as f64, state)),
+            Value::String(s) => s.as_bytes().hash(state),
+            Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_) => {
+                self.to_pointer().hash(state)
+            }
+        }
+    }
+
+    /// Interprets values returned by a Lua function following the `(nil, errmsg)` or
+    /// `(false, errmsg)` convention, used by the standard library (eg. `io.open`) and many
+    /// third-party Lua libraries to report errors.
+    ///
+    /// Returns `Err` if the first value is `nil` or `false` and it is followed by a non-nil error
+    /// message, otherwise returns the first value (or `Nil` if there are no values).
+    ///
+    /// The error message is converted to [`Error::RuntimeError`], unless it's already an
+    /// [`Error`] value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mlua::{Error, Function, Lua, MultiValue, Result, Value};
+    /// # fn main() -> Result<()> {
+    /// let lua = Lua::new();
+    /// let f: Function = lua.load("function(ok) if ok then return 42 end return nil, 'boom' end").eval()?;
+    ///
+    /// let value = Value::from_lua_result_convention(f.call::<_, MultiValue>(true)?)?;
+    /// assert_eq!(value, Value::Integer(42));
+    ///
+    /// let err = Value::from_lua_result_convention(f.call::<_, MultiValue>(false)?).unwrap_err();
+    /// assert!(matches!(err, Error::RuntimeError(msg) if msg == "boom"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_lua_result_convention(multi: MultiValue<'lua>) -> Result<Value<'lua>> {
+        let mut values = multi.into_iter();
+        let value = values.next().unwrap_or(Value::Nil);
+        if let Value::Nil | Value::Boolean(false) = value {
+            match values.next() {
+                None | Some(Value::Nil) => {}
+                Some(Value::Error(err)) => return Err(err),
+                Some(Value::String(msg)) => {
+                    return Err(Error::RuntimeError(msg.to_string_lossy().into_owned()))
+                }
+                Some(msg) => return Err(Error::RuntimeError(msg.to_string()?)),
+            }
+        }
+        Ok(value)
+    }
+
+    /// Converts a Rust result into values following the `(nil, errmsg)` convention.
+    ///
+    /// This is the reverse of [`Value::from_lua_result_convention`]: `Ok(value)` is returned as
+    /// a single value, and `Err(err)` as `nil` followed by the error message.
+    /// For [`Error::RuntimeError`] the message is passed as is.
+    pub fn into_lua_result_convention(
+        lua: &'lua Lua,
+        result: Result<Value<'lua>>,
+    ) -> Result<MultiValue<'lua>> {
+        match result {
+            Ok(value) => Ok(MultiValue::from_vec(vec![value])),
+            Err(err) => {
+                let msg = match err {
+                    Error::RuntimeError(msg) => lua.create_string(msg)?,
+                    err => lua.create_string(err.to_string())?,
+                };
+                Ok(MultiValue::from_vec(vec![Value::Nil, Value::String(msg)]))
+            }
+        }
+    }
+
+    // Returns a type tag shared by values that can be equal
+    fn hash_kind(&self) -> Option<&'static str> {
+        match self {
+            Value::Integer(_) | Value::Number(_) => Some("number"),
+            Value::Error(_) => None,
+            _ => Some(self.type_name()),
+        }
+    }
+
+    // Returns a short preview of the value to include in error messages.
+    // Only primitive values (booleans, numbers and strings) have a preview.
+    pub(crate) fn preview(&self) -> Option<StdString> {
+        const MAX_LEN: usize = 32;
+        match self {
+            Value::Boolean(b) => Some(b.to_string()),
+            Value::Integer(i) => Some(i.to_string()),
+            Value::Number(n) => {
+                let mut s = n.to_string();
+                if s.len() > MAX_LEN {
+                    s = format!("{n:e}");
+                }
+                Some(s)
+            }
+            Value::String(s) => {
+                let s = s.to_string_lossy();
+                let mut preview = StdString::from("\"");
+                preview.extend(s.chars().take(MAX_LEN).flat_map(char::escape_debug));
+                preview.push('"');
+                if s.chars().nth(MAX_LEN).is_some() {
+                    preview.push_str("...");
+                }
+                Some(preview)
+            }
+            _ => None,
+        }
+    }
+
     // Compares two values.
     // Used to sort values for Debug printing.
     pub(crate) fn cmp(&self, other: &Self) -> Ordering {
@@ -208,7 +342,7 @@ impl<'lua> Value<'lua> {
         fmt: &mut fmt::Formatter,
         recursive: bool,
         ident: usize,
-        visited: &mut HashSet<*const c_void>,
+        ctx: &mut PrettyContext,
     ) -> fmt::Result {
         match self {
             Value::Nil => write!(fmt, "nil"),
@@ -220,12 +354,24 @@ impl<'lua> Value<'lua> {
             #[cfg(feature = "luau")]
             Value::Vector(v) => write!(fmt, "{v}"),
             Value::String(s) => write!(fmt, "{s:?}"),
-            Value::Table(t) if recursive && !visited.contains(&t.to_pointer()) => {
-                t.fmt_pretty(fmt, ident, visited)
+            Value::Table(t) if recursive && !ctx.visited.contains(&t.to_pointer()) => {
+                match ctx.max_depth {
+                    Some(max_depth) if ident / 2 >= max_depth => write!(fmt, "{{...}}"),
+                    _ => t.fmt_pretty(fmt, ident, ctx),
+                }
             }
             t @ Value::Table(_) => write!(fmt, "table: {:?}", t.to_pointer()),
             f @ Value::Function(_) => write!(fmt, "function: {:?}", f.to_pointer()),
             t @ Value::Thread(_) => write!(fmt, "thread: {:?}", t.to_pointer()),
+            Value::UserData(ud) if ctx.use_inspect => match ud.fmt_inspect() {
+                Some(s) => write!(fmt, "{s}"),
+                None => {
+                    ctx.use_inspect = false;
+                    let res = self.fmt_pretty(fmt, recursive, ident, ctx);
+                    ctx.use_inspect = true;
+                    res
+                }
+            },
             u @ Value::UserData(ud) => {
                 let name = ud.type_name().ok().flatten();
                 let name = name.unwrap_or_else(|| "userdata".to_string());
@@ -237,10 +383,47 @@ impl<'lua> Value<'lua> {
     }
 }
 
+// State of the pretty formatting (`{:#?}` and `inspect`) of values
+pub(crate) struct PrettyContext {
+    // Tables that were already formatted (to detect cycles)
+    pub(crate) visited: HashSet<*const c_void>,
+    // Maximum depth of nested tables to expand
+    max_depth: Option<usize>,
+    // Use `__inspect` metamethods of userdata
+    use_inspect: bool,
+}
+
+impl PrettyContext {
+    pub(crate) fn new() -> Self {
+        PrettyContext {
+            visited: HashSet::new(),
+            max_depth: None,
+            use_inspect: false,
+        }
+    }
+
+    pub(crate) fn inspect(max_depth: Option<usize>) -> Self {
+        PrettyContext {
+            visited: HashSet::new(),
+            max_depth,
+            use_inspect: true,
+        }
+    }
+}
+
+// Formats a value using `fmt_pretty` with the given context
+pub(crate) struct PrettyValue<'a, 'lua>(pub(crate) &'a Value<'lua>, pub(crate) Option<usize>);
+
+impl fmt::Display for PrettyValue<'_, '_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        (self.0).fmt_pretty(fmt, true, 0, &mut PrettyContext::inspect(self.1))
+    }
+}
+
 impl fmt::Debug for Value<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         if fmt.alternate() {
-            return self.fmt_pretty(fmt, true, 0, &mut HashSet::new());
+            return self.fmt_pretty(fmt, true, 0, &mut PrettyContext::new());
         }
         match self {
             Value::Nil => write!(fmt, "Nil"),
@@ -282,6 +465,76 @@ impl<'lua> PartialEq for Value<'lua> {
     }
 }
 
+/// A wrapper around [`Value`] implementing [`Eq`] and [`Hash`], to use values as keys in Rust
+/// collections (eg. [`HashMap`] or [`HashSet`]).
+///
+/// Values are compared and hashed without invoking metamethods, see [`Value::hash`].
+/// Unlike [`Value`], `NaN` numbers are considered equal to each other.
+///
+/// # Examples
+///
+/// ```
+/// # use std::collections::HashMap;
+/// # use mlua::{HashableValue, Lua, Result};
+/// # fn main() -> Result<()> {
+/// # let lua = Lua::new();
+/// let map: HashMap<HashableValue, i32> = lua.load("{ [1] = 10, x = 20, [true] = 30 }").eval()?;
+/// assert_eq!(map[&HashableValue::from(mlua::Value::Number(1.0))], 10);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`HashMap`]: std::collections::HashMap
+#[derive(Clone, Debug)]
+pub struct HashableValue<'lua>(pub Value<'lua>);
+
+impl<'lua> HashableValue<'lua> {
+    /// Returns the wrapped value.
+    #[inline]
+    pub fn into_inner(self) -> Value<'lua> {
+        self.0
+    }
+}
+
+impl<'lua> From<Value<'lua>> for HashableValue<'lua> {
+    #[inline]
+    fn from(value: Value<'lua>) -> Self {
+        HashableValue(value)
+    }
+}
+
+impl<'lua> PartialEq for HashableValue<'lua> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Number(a), Value::Number(b)) if a.is_nan() && b.is_nan() => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl<'lua> Eq for HashableValue<'lua> {}
+
+impl<'lua> Hash for HashableValue<'lua> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<'lua> IntoLua<'lua> for HashableValue<'lua> {
+    #[inline]
+    fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+        Ok(self.0)
+    }
+}
+
+impl<'lua> FromLua<'lua> for HashableValue<'lua> {
+    #[inline]
+    fn from_lua(value: Value<'lua>, _: &'lua Lua) -> Result<Self> {
+        Ok(HashableValue(value))
+    }
+}
+
 impl<'lua> AsRef<Value<'lua>> for Value<'lua> {
     #[inline]
     fn as_ref(&self) -> &Self {
@@ -0,0 +1,207 @@
+use std::io::Write;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+use crate::error::{Error, Result};
+use crate::lua::Lua;
+use crate::types::Vector;
+use crate::userdata::AnyUserData;
+use crate::util::{check_stack, StackGuard};
+use crate::value::Value;
+
+// Name of the (shared) metatable of packed vectors in the registry
+const VECTOR_METATABLE: *const c_char = cstr!("__mlua_vector");
+
+// Vectors are stored as plain (non mlua) userdata holding 3 packed floats. All metamethods are
+// implemented as C functions, so operations on vectors do not go through Rust callbacks.
+type Packed = [f32; 3];
+
+// Uses 4 stack spaces, does not call checkstack.
+pub(crate) unsafe fn push_vector(state: *mut ffi::lua_State, v: Vector) -> Result<()> {
+    protect_lua!(state, 0, 1, |state| {
+        let ud = ffi::lua_newuserdata(state, std::mem::size_of::<Packed>()) as *mut Packed;
+        ptr::write_unaligned(ud, v.0);
+        if ffi::luaL_newmetatable(state, VECTOR_METATABLE) != 0 {
+            init_vector_metatable(state);
+        }
+        ffi::lua_setmetatable(state, -2);
+    })
+}
+
+// Returns the vector at `index` if it's a packed vector.
+// Uses 2 stack spaces, does not call checkstack.
+pub(crate) unsafe fn to_vector(state: *mut ffi::lua_State, index: c_int) -> Option<Vector> {
+    let ud = ffi::lua_touserdata(state, index) as *const Packed;
+    if ud.is_null() || ffi::lua_getmetatable(state, index) == 0 {
+        return None;
+    }
+    ffi::luaL_getmetatable(state, VECTOR_METATABLE);
+    let is_vector = ffi::lua_rawequal(state, -1, -2) != 0;
+    ffi::lua_pop(state, 2);
+    is_vector.then(|| Vector(ptr::read_unaligned(ud)))
+}
+
+pub(crate) fn create_vector<'lua>(lua: &'lua Lua, v: Vector) -> Result<Value<'lua>> {
+    let state = lua.state();
+    unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 4)?;
+
+        push_vector(state, v)?;
+        Ok(lua.pop_value())
+    }
+}
+
+pub(crate) fn userdata_to_vector(ud: &AnyUserData) -> Option<Vector> {
+    let lua = ud.0.lua;
+    let state = lua.state();
+    unsafe {
+        let _sg = StackGuard::new(state);
+        check_stack(state, 3).ok()?;
+
+        lua.push_ref(&ud.0);
+        to_vector(state, -1)
+    }
+}
+
+pub(crate) fn vector_conversion_error(value: &Value) -> Error {
+    Error::FromLuaConversionError {
+        from: value.type_name(),
+        to: "vector",
+        message: None,
+    }
+    .with_value_preview(value.preview())
+}
+
+unsafe fn init_vector_metatable(state: *mut ffi::lua_State) {
+    let methods: [(*const c_char, ffi::lua_CFunction); 8] = [
+        (cstr!("__index"), vector_index),
+        (cstr!("__add"), vector_add),
+        (cstr!("__sub"), vector_sub),
+        (cstr!("__mul"), vector_mul),
+        (cstr!("__div"), vector_div),
+        (cstr!("__unm"), vector_unm),
+        (cstr!("__eq"), vector_eq),
+        (cstr!("__tostring"), vector_tostring),
+    ];
+    for (name, func) in methods {
+        ffi::lua_pushcfunction(state, func);
+        ffi::lua_setfield(state, -2, name);
+    }
+    // Protect the shared metatable from scripts
+    ffi::lua_pushboolean(state, 0);
+    ffi::lua_setfield(state, -2, cstr!("__metatable"));
+}
+
+// Operand of an arithmetic metamethod
+enum Operand {
+    Vector(Packed),
+    Number(f32),
+}
+
+unsafe fn check_operand(state: *mut ffi::lua_State, index: c_int) -> Operand {
+    if ffi::lua_type(state, index) == ffi::LUA_TNUMBER {
+        return Operand::Number(ffi::lua_tonumber(state, index) as f32);
+    }
+    match to_vector(state, index) {
+        Some(v) => Operand::Vector(v.0),
+        None => ffi::luaL_error(
+            state,
+            cstr!("attempt to perform arithmetic on a vector and a %s value"),
+            ffi::luaL_typename(state, index),
+        ),
+    }
+}
+
+unsafe fn check_vector(state: *mut ffi::lua_State, index: c_int) -> Packed {
+    match check_operand(state, index) {
+        Operand::Vector(v) => v,
+        Operand::Number(_) => ffi::luaL_error(
+            state,
+            cstr!("attempt to perform arithmetic on a vector and a number value"),
+        ),
+    }
+}
+
+unsafe fn return_vector(state: *mut ffi::lua_State, v: Packed) -> c_int {
+    let ud = ffi::lua_newuserdata(state, std::mem::size_of::<Packed>()) as *mut Packed;
+    ptr::write_unaligned(ud, v);
+    ffi::luaL_getmetatable(state, VECTOR_METATABLE);
+    ffi::lua_setmetatable(state, -2);
+    1
+}
+
+unsafe extern "C" fn vector_index(state: *mut ffi::lua_State) -> c_int {
+    let v = check_vector(state, 1);
+    let mut len = 0;
+    let key = ffi::lua_tolstring(state, 2, &mut len);
+    let i = match (key.is_null(), len) {
+        (false, 1) => match *key as u8 {
+            b'x' | b'X' => 0,
+            b'y' | b'Y' => 1,
+            b'z' | b'Z' => 2,
+            _ => return 0,
+        },
+        _ => return 0,
+    };
+    ffi::lua_pushnumber(state, v[i] as ffi::lua_Number);
+    1
+}
+
+macro_rules! vector_binop {
+    ($name:ident, $op:tt) => {
+        unsafe extern "C" fn $name(state: *mut ffi::lua_State) -> c_int {
+            let v = match (check_operand(state, 1), check_operand(state, 2)) {
+                (Operand::Vector(a), Operand::Vector(b)) => {
+                    [a[0] $op b[0], a[1] $op b[1], a[2] $op b[2]]
+                }
+                (Operand::Vector(a), Operand::Number(n)) => [a[0] $op n, a[1] $op n, a[2] $op n],
+                (Operand::Number(n), Operand::Vector(b)) => [n $op b[0], n $op b[1], n $op b[2]],
+                (Operand::Number(_), Operand::Number(_)) => {
+                    ffi::luaL_error(state, cstr!("vector expected"))
+                }
+            };
+            return_vector(state, v)
+        }
+    };
+}
+
+// Addition and subtraction are only defined for two vectors
+macro_rules! vector_vector_binop {
+    ($name:ident, $op:tt) => {
+        unsafe extern "C" fn $name(state: *mut ffi::lua_State) -> c_int {
+            let (a, b) = (check_vector(state, 1), check_vector(state, 2));
+            return_vector(state, [a[0] $op b[0], a[1] $op b[1], a[2] $op b[2]])
+        }
+    };
+}
+
+vector_vector_binop!(vector_add, +);
+vector_vector_binop!(vector_sub, -);
+vector_binop!(vector_mul, *);
+vector_binop!(vector_div, /);
+
+unsafe extern "C" fn vector_unm(state: *mut ffi::lua_State) -> c_int {
+    let v = check_vector(state, 1);
+    return_vector(state, [-v[0], -v[1], -v[2]])
+}
+
+unsafe extern "C" fn vector_eq(state: *mut ffi::lua_State) -> c_int {
+    let eq = match (to_vector(state, 1), to_vector(state, 2)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    };
+    ffi::lua_pushboolean(state, eq as c_int);
+    1
+}
+
+unsafe extern "C" fn vector_tostring(state: *mut ffi::lua_State) -> c_int {
+    let v = Vector(check_vector(state, 1));
+    // Format on the stack to not leak memory if Lua raises an error
+    let mut buf = [0u8; 256];
+    let mut cursor = &mut buf[..];
+    let _ = write!(cursor, "{v}");
+    let len = 256 - cursor.len();
+    ffi::lua_pushlstring(state, buf.as_ptr() as *const c_char, len);
+    1
+}
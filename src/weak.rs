@@ -0,0 +1,122 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::error::Result;
+use crate::function::Function;
+use crate::lua::Lua;
+use crate::private::Sealed;
+use crate::table::Table;
+use crate::thread::Thread;
+use crate::types::Integer;
+use crate::userdata::AnyUserData;
+use crate::value::{FromLua, IntoLua, Nil, Value};
+
+/// Weak reference to a Lua object that does not prevent it from being garbage collected.
+///
+/// Created by [`Downgrade::downgrade`]. This is useful for Rust-side caches of script objects
+/// which should be collected once scripts no longer use them.
+///
+/// # Examples
+///
+/// ```
+/// # use mlua::{Downgrade, Lua, Result};
+/// # fn main() -> Result<()> {
+/// let lua = Lua::new();
+/// let table = lua.create_table()?;
+/// let weak = table.downgrade()?;
+/// assert_eq!(weak.upgrade(), Some(table.clone()));
+///
+/// drop(table);
+/// lua.gc_collect()?;
+/// lua.gc_collect()?;
+/// assert_eq!(weak.upgrade(), None);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WeakRef<'lua, T> {
+    lua: &'lua Lua,
+    id: Integer,
+    _type: PhantomData<T>,
+}
+
+/// Lua objects that can be referenced weakly, see [`WeakRef`].
+///
+/// Implemented for [`Table`], [`Function`], [`AnyUserData`] and [`Thread`].
+pub trait Downgrade<'lua>: Sealed + Sized {
+    /// Creates a weak reference to this object.
+    fn downgrade(&self) -> Result<WeakRef<'lua, Self>>;
+}
+
+// Registry key of the weak-valued table holding objects referenced by `WeakRef`s
+const WEAK_REFS_REGISTRY_KEY: &str = "__mlua_weak_refs";
+
+// Key of the last allocated id in the weak table. Ids are never reused, so a collected object
+// cannot be replaced by another one.
+const LAST_ID_KEY: &str = "last_id";
+
+fn weak_refs_table<'lua>(lua: &'lua Lua) -> Result<Table<'lua>> {
+    if let Some(refs) = lua.named_registry_value::<Option<Table>>(WEAK_REFS_REGISTRY_KEY)? {
+        return Ok(refs);
+    }
+    let refs = lua.create_table()?;
+    refs.set_metatable(Some(lua.create_table_from([("__mode", "v")])?));
+    lua.set_named_registry_value(WEAK_REFS_REGISTRY_KEY, refs.clone())?;
+    Ok(refs)
+}
+
+impl<'lua, T> WeakRef<'lua, T> {
+    fn new(lua: &'lua Lua, value: Value<'lua>) -> Result<Self> {
+        let refs = weak_refs_table(lua)?;
+        let id = refs
+            .raw_get::<_, Option<Integer>>(LAST_ID_KEY)?
+            .unwrap_or(0)
+            + 1;
+        refs.raw_set(LAST_ID_KEY, id)?;
+        refs.raw_set(id, value)?;
+        Ok(WeakRef {
+            lua,
+            id,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<'lua, T: FromLua<'lua>> WeakRef<'lua, T> {
+    /// Returns the referenced object, or `None` if it has been garbage collected.
+    pub fn upgrade(&self) -> Option<T> {
+        let refs = weak_refs_table(self.lua).ok()?;
+        match refs.raw_get::<_, Value>(self.id).ok()? {
+            Value::Nil => None,
+            value => T::from_lua(value, self.lua).ok(),
+        }
+    }
+}
+
+impl<'lua, T> fmt::Debug for WeakRef<'lua, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("WeakRef").field(&self.id).finish()
+    }
+}
+
+impl<'lua, T> Drop for WeakRef<'lua, T> {
+    fn drop(&mut self) {
+        if let Ok(refs) = weak_refs_table(self.lua) {
+            let _ = refs.raw_set(self.id, Nil);
+        }
+    }
+}
+
+macro_rules! impl_downgrade {
+    ($($t:ident),*) => {
+        $(
+            impl<'lua> Downgrade<'lua> for $t<'lua> {
+                fn downgrade(&self) -> Result<WeakRef<'lua, Self>> {
+                    let lua = self.0.lua;
+                    WeakRef::new(lua, self.clone().into_lua(lua)?)
+                }
+            }
+        )*
+    };
+}
+
+impl_downgrade!(Table, Function, AnyUserData, Thread);
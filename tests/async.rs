@@ -1,14 +1,19 @@
 #![cfg(feature = "async")]
 
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::task::Context;
 use std::time::Duration;
 
+use futures::task::ArcWake;
+
 use futures_timer::Delay;
 use futures_util::stream::TryStreamExt;
 
 use mlua::{
-    AnyUserDataExt, Error, Function, Lua, LuaOptions, Result, StdLib, Table, TableExt, UserData,
-    UserDataMethods, Value,
+    AnyUserDataExt, BorrowPolicy, Error, ExecutorSignal, Function, Lua, LuaOptions, Result, StdLib,
+    Table, TableExt, UserData, UserDataMethods, UserDataRefMut, Value,
 };
 
 #[tokio::test]
@@ -460,6 +465,42 @@ async fn test_async_userdata() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_async_timers() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals()
+        .set("timers", lua.create_timers(Delay::new)?)?;
+
+    let start = std::time::Instant::now();
+    lua.load("timers.sleep(50)").exec_async().await?;
+    assert!(start.elapsed() >= Duration::from_millis(50));
+
+    let ticks = lua
+        .load("return timers.interval(10, function(n) return n < 5 end)")
+        .eval_async::<i64>()
+        .await?;
+    assert_eq!(ticks, 5);
+
+    let res = lua
+        .load("return timers.timeout(100, function(a, b) timers.sleep(10); return a + b end, 1, 2)")
+        .eval_async::<i64>()
+        .await?;
+    assert_eq!(res, 3);
+
+    match lua
+        .load("timers.timeout(10, timers.sleep, 1000)")
+        .exec_async()
+        .await
+    {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(cause.to_string().contains("timed out after 10ms"))
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_async_thread_error() -> Result<()> {
     struct MyUserData;
@@ -523,3 +564,439 @@ async fn test_async_terminate() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_async_poll_budget() -> Result<()> {
+    use futures_util::future::poll_fn;
+    use std::future::Future;
+    use std::pin::pin;
+
+    let lua = Lua::new();
+
+    let ready = lua.create_async_function(|_, n: i64| async move { Ok(n) })?;
+    lua.globals().set("ready", ready)?;
+    let func = lua
+        .load("local sum = 0; for i = 1, 10 do sum = sum + ready(i) end; return sum")
+        .into_function()?;
+
+    // Returns the result and the number of polls of the thread
+    let run = |budget: Option<Option<usize>>| {
+        let mut thread = lua
+            .create_thread(func.clone())
+            .unwrap()
+            .into_async::<_, i64>(());
+        if let Some(budget) = budget {
+            thread.set_poll_budget(budget);
+        }
+        let mut polls = 0;
+        async move {
+            let mut thread = pin!(thread);
+            let res = poll_fn(|cx| {
+                polls += 1;
+                thread.as_mut().poll(cx)
+            })
+            .await;
+            (res, polls)
+        }
+    };
+
+    let (res, polls) = run(None).await;
+    assert_eq!(res?, 55);
+    assert_eq!(polls, 1);
+
+    lua.set_async_poll_budget(Some(3));
+    let (res, polls) = run(None).await;
+    assert_eq!(res?, 55);
+    assert_eq!(polls, 4);
+
+    // Per thread override
+    let (res, polls) = run(Some(None)).await;
+    assert_eq!(res?, 55);
+    assert_eq!(polls, 1);
+    let (res, polls) = run(Some(Some(5))).await;
+    assert_eq!(res?, 55);
+    assert_eq!(polls, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_signal() -> Result<()> {
+    let lua = Lua::new();
+
+    let sleep = lua.create_async_function(|_, n: u64| async move {
+        Delay::new(Duration::from_millis(n)).await;
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+
+    let signal = lua.create_signal::<u64>()?;
+    lua.globals().set("signal", signal.clone())?;
+    lua.load(
+        r#"
+        total = 0
+        signal:connect(function(n) sleep(n); total = total + n end)
+        signal:connect(function(n) total = total * 2 end)
+    "#,
+    )
+    .exec()?;
+
+    signal.emit_async(10).await?;
+    assert_eq!(lua.globals().get::<_, u64>("total")?, 20);
+
+    lua.load("signal:emit_async(5)").exec_async().await?;
+    assert_eq!(lua.globals().get::<_, u64>("total")?, 50);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_userdata_borrow_policy() -> Result<()> {
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.set_borrow_policy(BorrowPolicy::Wait);
+            methods.add_async_method_mut("add", |_, this, n: i64| async move {
+                let value = this.0;
+                Delay::new(Duration::from_millis(10)).await;
+                this.0 = value + n;
+                Ok(())
+            });
+            methods.add_async_method_mut("apply", |_, this, f: Function| async move {
+                f.call_async::<_, ()>(()).await?;
+                this.0 += 1;
+                Ok(())
+            });
+            methods.add_method("get", |_, this, ()| Ok(this.0));
+        }
+    }
+
+    struct Busy(i64);
+
+    impl UserData for Busy {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.set_borrow_policy(BorrowPolicy::LuaError);
+            methods.add_async_method_mut("add", |_, this, n: i64| async move {
+                Delay::new(Duration::from_millis(10)).await;
+                this.0 += n;
+                Ok(())
+            });
+        }
+    }
+
+    let lua = Lua::new();
+
+    // Concurrent calls wait for each other
+    let counter = lua.create_userdata(Counter(0))?;
+    let add = counter.get::<_, Function>("add")?;
+    futures_util::try_join!(
+        add.call_async::<_, ()>((counter.clone(), 1)),
+        add.call_async::<_, ()>((counter.clone(), 2)),
+        add.call_async::<_, ()>((counter.clone(), 3)),
+    )?;
+    assert_eq!(counter.call_method::<_, i64>("get", ())?, 6);
+
+    // Waiting calls are woken when a `UserDataRefMut` is dropped
+    struct Flag(AtomicBool);
+    impl ArcWake for Flag {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.store(true, Ordering::SeqCst);
+        }
+    }
+    let flag = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = futures::task::waker(flag.clone());
+    let mut cx = Context::from_waker(&waker);
+    let guard = lua.unpack::<UserDataRefMut<Counter>>(Value::UserData(counter.clone()))?;
+    let fut = add.call_async::<_, ()>((counter.clone(), 4));
+    futures_util::pin_mut!(fut);
+    assert!(fut.as_mut().poll(&mut cx).is_pending());
+    assert!(!flag.0.load(Ordering::SeqCst));
+    drop(guard);
+    assert!(flag.0.load(Ordering::SeqCst));
+    fut.await?;
+    assert_eq!(counter.call_method::<_, i64>("get", ())?, 10);
+
+    // A call nested in a method holding the borrow fails instead of waiting forever
+    lua.globals().set("counter", counter.clone())?;
+    let res = (lua.load("counter:apply(function() counter:add(1) end)"))
+        .exec_async()
+        .await;
+    match res {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::CallbackError { cause, .. } => match cause.as_ref() {
+                Error::BadArgument { cause, .. } => {
+                    assert!(matches!(*cause.as_ref(), Error::UserDataBorrowMutError))
+                }
+                err => panic!("expected BadArgument, got {err:?}"),
+            },
+            err => panic!("expected CallbackError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert_eq!(counter.call_method::<_, i64>("get", ())?, 10);
+
+    let busy = lua.create_userdata(Busy(0))?;
+    let add = busy.get::<_, Function>("add")?;
+    let res = futures_util::try_join!(
+        add.call_async::<_, ()>((busy.clone(), 1)),
+        add.call_async::<_, ()>((busy.clone(), 2)),
+    );
+    match res {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => {
+                assert_eq!(msg, "`Busy.add`: userdata is already borrowed")
+            }
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_async_manual_driver() -> Result<()> {
+    let lua = Lua::new();
+
+    // Sleeps until the host time reaches the deadline, waking the task on every poll
+    let sleep = lua.create_async_function(|lua, ms: u64| {
+        let deadline = lua.async_now().unwrap() + Duration::from_millis(ms);
+        futures_util::future::poll_fn(move |cx| {
+            if lua.async_now().unwrap() >= deadline {
+                return std::task::Poll::Ready(Ok(()));
+            }
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        })
+    })?;
+    lua.globals().set("sleep", sleep)?;
+
+    let func = lua
+        .load("local name, ms = ... sleep(ms) return name")
+        .into_function()?;
+    let slow = lua.spawn_async(func.clone(), ("slow", 30))?;
+    let fast = lua.spawn_async(func.clone(), ("fast", 10))?;
+    let failing = lua.spawn_async(lua.load("error('boom')").into_function()?, ())?;
+    assert_ne!(slow, fast);
+    assert_eq!(lua.pending_async_tasks(), 3);
+    assert_eq!(lua.async_now(), None);
+
+    let mut finished = Vec::new();
+    for tick in 0..5 {
+        for (id, result) in lua.poll_pending_async(Duration::from_millis(tick * 10)) {
+            match result {
+                Ok(mut ret) => {
+                    let name = ret.pop_front().unwrap().to_string()?;
+                    finished.push((tick, id, name));
+                }
+                Err(_) => finished.push((tick, id, "error".to_string())),
+            }
+        }
+    }
+    assert_eq!(
+        finished,
+        vec![
+            (0, failing, "error".to_string()),
+            (1, fast, "fast".to_string()),
+            (3, slow, "slow".to_string()),
+        ]
+    );
+    assert_eq!(lua.pending_async_tasks(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_executor_integration() -> Result<()> {
+    let lua = Lua::new();
+    let signal = Arc::new(ExecutorSignal::new());
+    lua.set_executor_integration(signal.clone());
+
+    let sleep = lua.create_async_function(|_, ms: u64| async move {
+        Delay::new(Duration::from_millis(ms)).await;
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+    let func = lua
+        .load("local n = ... sleep(n) return n * 2")
+        .into_function()?;
+    lua.spawn_async(func.clone(), 20)?;
+    lua.spawn_async(func, 10)?;
+
+    let mut results = Vec::new();
+    while lua.pending_async_tasks() > 0 {
+        signal.wait().await;
+        for (_, result) in lua.poll_pending_async(Duration::ZERO) {
+            results.push(lua.unpack::<i64>(result?.pop_front().unwrap())?);
+        }
+    }
+    assert_eq!(results, vec![20, 40]);
+
+    // Closures can be used as integrations
+    let count = Arc::new(Mutex::new(0));
+    let count2 = count.clone();
+    lua.set_executor_integration(Arc::new(move |_| *count2.lock().unwrap() += 1));
+    lua.spawn_async(lua.load("return 1").into_function()?, ())?;
+    assert_eq!(*count.lock().unwrap(), 1);
+    assert_eq!(lua.poll_pending_async(Duration::ZERO).len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_task_id() -> Result<()> {
+    let lua = Lua::new();
+    assert_eq!(lua.current_task_id(), None);
+
+    // Records the current task id before and after suspending
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log2 = log.clone();
+    let trace = lua.create_async_function(move |lua, name: String| {
+        let log = log2.clone();
+        async move {
+            let before = lua.current_task_id().unwrap();
+            Delay::new(Duration::from_millis(10)).await;
+            let after = lua.current_task_id().unwrap();
+            log.lock().unwrap().push((name, before, after));
+            Ok(())
+        }
+    })?;
+    lua.globals().set("trace", trace)?;
+
+    let func = lua
+        .load("local name = ... trace(name) trace(name)")
+        .into_function()?;
+    let fut1 = func.call_async::<_, ()>("a");
+    let fut2 = func.call_async::<_, ()>("b");
+    futures_util::try_join!(fut1, fut2)?;
+    assert_eq!(lua.current_task_id(), None);
+
+    let log = log.lock().unwrap().clone();
+    assert_eq!(log.len(), 4);
+    let id_of = |name: &str| {
+        let ids = (log.iter())
+            .filter(|(n, _, _)| n == name)
+            .flat_map(|(_, before, after)| [*before, *after])
+            .collect::<Vec<_>>();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        ids[0]
+    };
+    assert_ne!(id_of("a"), id_of("b"));
+
+    // Nested async calls inherit the task id
+    let outer = lua.create_async_function(|lua, inner: Function| async move {
+        let id = lua.current_task_id();
+        let inner_id = inner.call_async::<_, u64>(()).await?;
+        Ok(id.map(|id| id.as_u64()) == Some(inner_id))
+    })?;
+    let inner = lua.create_function(|lua, ()| Ok(lua.current_task_id().unwrap().as_u64()))?;
+    assert!(outer.call_async::<_, bool>(inner).await?);
+
+    // Tracebacks include the task id
+    let thread = lua.create_thread(lua.load("error('boom')").into_function()?)?;
+    let fut = thread.into_async::<_, ()>(());
+    let task_id = fut.task_id();
+    match fut.await {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains(&format!("[{task_id}]"))),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_scope() -> Result<()> {
+    let lua = Lua::new();
+
+    let sleep = lua.create_async_function(|_, n: u64| async move {
+        Delay::new(Duration::from_millis(n)).await;
+        Ok(())
+    })?;
+    lua.globals().set("sleep", sleep)?;
+    let run = lua
+        .load(
+            r#"
+            local log = ...
+            log("start")
+            sleep(10)
+            log("end")
+            return log
+        "#,
+        )
+        .into_function()?;
+
+    let mut messages = Vec::new();
+    let messages_ref = &mut messages;
+    let log = unsafe {
+        lua.async_scope(|scope| async move {
+            let log = scope.create_function_mut(|_, msg: String| {
+                messages_ref.push(msg);
+                Ok(())
+            })?;
+            run.call_async::<_, Function>(log).await
+        })
+        .await?
+    };
+    assert_eq!(messages, vec!["start", "end"]);
+
+    // Scoped values are invalidated at the end of the scope
+    match log.call::<_, ()>("late") {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::CallbackDestructed => {}
+            err => panic!("expected CallbackDestructed, got {err:?}"),
+        },
+        r => panic!("improper return for destructed function: {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "current_thread")]
+async fn test_async_tokio_integration() -> Result<()> {
+    let lua = Lua::new();
+    let integration = Arc::new(mlua::TokioIntegration::new());
+    lua.set_executor_integration(integration.clone());
+
+    let sleep = lua.create_async_function(|lua, ms: u64| async move {
+        tokio::time::sleep(Duration::from_millis(ms)).await;
+        Ok(lua.async_now().map(|now| now.as_millis() as u64))
+    })?;
+    let t1 = lua.spawn_async(sleep.clone(), 20)?;
+    let t2 = lua.spawn_async(sleep, 5)?;
+
+    let finished = integration.run(&lua).await;
+    let ids = finished.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![t2, t1]);
+    for (_, result) in finished {
+        let now = result?.pop_front().unwrap();
+        assert!(lua.unpack::<u64>(now)? >= 5);
+    }
+    assert_eq!(lua.pending_async_tasks(), 0);
+
+    Ok(())
+}
+
+#[cfg(feature = "async-std")]
+#[test]
+fn test_async_async_std_integration() -> Result<()> {
+    let lua = Lua::new();
+    let integration = Arc::new(mlua::AsyncStdIntegration::new());
+    lua.set_executor_integration(integration.clone());
+
+    let sleep = lua.create_async_function(|_, ms: u64| async move {
+        async_std::task::sleep(Duration::from_millis(ms)).await;
+        Ok(ms)
+    })?;
+    lua.spawn_async(sleep.clone(), 20)?;
+    lua.spawn_async(sleep, 5)?;
+
+    let finished = async_std::task::block_on(integration.run(&lua));
+    let results = (finished.into_iter())
+        .map(|(_, result)| lua.unpack::<u64>(result?.pop_front().unwrap()))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(results, vec![5, 20]);
+
+    Ok(())
+}
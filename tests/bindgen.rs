@@ -0,0 +1,70 @@
+#![cfg(feature = "bindgen")]
+
+use std::collections::HashMap;
+
+use mlua::{Bindgen, Error, Lua, Result, UserData, UserDataFields, Variadic};
+
+struct Player {
+    name: String,
+}
+
+impl UserData for Player {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("name", |_, this| Ok(this.name.clone()));
+    }
+}
+
+mod api {
+    use super::Player;
+
+    include!("bindgen/api.rs");
+}
+
+#[test]
+fn test_bindgen() -> Result<()> {
+    let code = Bindgen::new("ScriptApi")
+        .definitions(include_str!("bindgen/api.d.luau"))
+        .generate()?;
+    assert_eq!(code, include_str!("bindgen/api.rs"));
+
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        function onPlayerJoin(player, level)
+            return "Hello, " .. player.name .. " (" .. tostring(level) .. ")"
+        end
+
+        function sum(...)
+            local s = 0
+            for _, n in ipairs({...}) do s = s + n end
+            return s
+        end
+
+        game = {
+            scores = function() return { alice = 10 } end,
+            reset = function(force) assert(force) end,
+        }
+    "#,
+    )
+    .exec()?;
+
+    let api = api::ScriptApi::new(lua.globals());
+    let player = Player {
+        name: "alice".into(),
+    };
+    assert_eq!(api.on_player_join(player, None)?, "Hello, alice (nil)");
+    assert_eq!(api.sum(Variadic::from_iter([1, 2, 3]))?, 6);
+    assert_eq!(api.game_scores()?, HashMap::from([("alice".into(), 10)]));
+    api.game_reset(true)?;
+    assert!(api.configure(lua.create_table()?, mlua::Nil).is_err());
+
+    match Bindgen::new("Api")
+        .definitions("declare function f(a: number")
+        .generate()
+    {
+        Err(Error::SyntaxError { message, .. }) => assert!(message.starts_with("line 1:")),
+        r => panic!("expected SyntaxError, got {r:?}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Generated by `mlua::Bindgen`, do not edit.
+
+/// Typed wrappers for functions defined by Lua scripts.
+pub struct ScriptApi<'lua> {
+    env: ::mlua::Table<'lua>,
+}
+
+impl<'lua> ScriptApi<'lua> {
+    /// Creates wrappers for functions stored in the environment table `env`.
+    pub fn new(env: ::mlua::Table<'lua>) -> Self {
+        ScriptApi { env }
+    }
+
+    /// Called when a player joins the game.
+    ///
+    /// Returns a greeting.
+    pub fn on_player_join(&self, player: Player, level: Option<::mlua::Number>) -> ::mlua::Result<::std::string::String> {
+        let func: ::mlua::Function<'lua> = self.env.get("onPlayerJoin")?;
+        func.call((player, level,))
+    }
+
+    pub fn sum(&self, args: ::mlua::Variadic<::mlua::Integer>) -> ::mlua::Result<::mlua::Integer> {
+        let func: ::mlua::Function<'lua> = self.env.get("sum")?;
+        func.call((args,))
+    }
+
+    /// Returns the current scores
+    pub fn game_scores(&self) -> ::mlua::Result<::std::collections::HashMap<::std::string::String, ::mlua::Integer>> {
+        let func: ::mlua::Function<'lua> = self.env.get::<_, ::mlua::Table<'lua>>("game")?.get("scores")?;
+        func.call(())
+    }
+
+    pub fn game_reset(&self, force: bool) -> ::mlua::Result<()> {
+        let func: ::mlua::Function<'lua> = self.env.get::<_, ::mlua::Table<'lua>>("game")?.get("reset")?;
+        func.call((force,))
+    }
+
+    pub fn configure(&self, config: ::mlua::Table<'lua>, r#type: ::mlua::Value<'lua>) -> ::mlua::Result<()> {
+        let func: ::mlua::Function<'lua> = self.env.get("configure")?;
+        func.call((config, r#type,))
+    }
+}
@@ -1,7 +1,11 @@
 use std::fs;
 use std::io;
 
-use mlua::{Lua, Result};
+use std::sync::{Arc, Mutex};
+
+use mlua::{
+    ChunkMode, Error, ExpressionPolicy, Lua, MappedLocation, Result, SourceMap, Table, Value,
+};
 
 #[test]
 fn test_chunk_path() -> Result<()> {
@@ -51,3 +55,310 @@ fn test_chunk_macro() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_chunk_bytecode() -> Result<()> {
+    let lua = Lua::new();
+
+    let bytecode = lua
+        .load("local a, b = ... return a * b")
+        .set_name("mul")
+        .into_bytecode()?;
+    assert_eq!(lua.load(&bytecode).call::<_, i32>((6, 7))?, 42);
+    // Binary chunks are returned unchanged
+    assert_eq!(lua.load(&bytecode).into_bytecode()?, bytecode);
+
+    match lua.load("return +").into_bytecode() {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {r:?}"),
+    }
+
+    lua.allow_bytecode(false);
+    match lua.load(&bytecode).exec() {
+        Err(Error::SyntaxError { .. }) => {}
+        r => panic!("expected SyntaxError, got {r:?}"),
+    }
+    // Text chunks and explicitly requested binary chunks are still loaded
+    assert_eq!(lua.load("return 1").eval::<i32>()?, 1);
+    let mul = lua.load(&bytecode).set_mode(ChunkMode::Binary);
+    assert_eq!(mul.call::<_, i32>((2, 3))?, 6);
+
+    lua.allow_bytecode(true);
+    assert_eq!(lua.load(&bytecode).call::<_, i32>((2, 5))?, 10);
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_expression() -> Result<()> {
+    let lua = Lua::new();
+
+    let env = lua.create_table()?;
+    env.set("x", 3)?;
+    env.set("name", "mlua")?;
+    env.set("math", lua.globals().get::<_, Table>("math")?)?;
+    let policy = ExpressionPolicy::new();
+
+    assert_eq!(
+        lua.eval_expression::<i64>("x * 2 + 1", env.clone(), policy)?,
+        7
+    );
+    assert_eq!(
+        lua.eval_expression::<i64>("math.max(x, 10)", env.clone(), policy)?,
+        10
+    );
+    assert_eq!(
+        lua.eval_expression::<String>("name:upper() .. '!'", env.clone(), policy)?,
+        "MLUA!"
+    );
+    assert!(lua.eval_expression::<bool>("x >= 3 and x ~= 4 -- comment", env.clone(), policy)?);
+    assert_eq!(
+        lua.eval_expression::<String>("[[while]] .. \"=end\"", env.clone(), policy)?,
+        "while=end"
+    );
+    // Globals are not available
+    assert!(lua
+        .eval_expression::<Option<Table>>("string", env.clone(), policy)?
+        .is_none());
+
+    #[track_caller]
+    fn check_rejected(lua: &Lua, src: &str, env: &Table, policy: ExpressionPolicy) {
+        match lua.eval_expression::<Value>(src, env.clone(), policy) {
+            Err(Error::SyntaxError { .. }) => {}
+            r => panic!("expected SyntaxError for `{src}`, got {r:?}"),
+        }
+    }
+
+    for src in [
+        "",
+        "x = 1",
+        "x; x",
+        "x, x",
+        "function() end",
+        "(function() while true do end end)()",
+        "x end",
+        "x\nlocal y = 1",
+        "{}",
+    ] {
+        check_rejected(&lua, src, &env, policy);
+    }
+
+    let no_calls = ExpressionPolicy::new().allow_function_calls(false);
+    check_rejected(&lua, "math.max(1, 2)", &env, no_calls);
+    check_rejected(&lua, "name:upper()", &env, no_calls);
+    check_rejected(&lua, "math.abs 'x'", &env, no_calls);
+    assert_eq!(
+        lua.eval_expression::<i64>("(x + 1) * 2", env.clone(), no_calls)?,
+        8
+    );
+
+    let tables = ExpressionPolicy::new().allow_table_constructors(true);
+    let t: Table = lua.eval_expression("{ x, y = x + 1, [1 + 1] = 5; 6 }", env.clone(), tables)?;
+    assert_eq!(t.get::<_, i64>("y")?, 4);
+    check_rejected(&lua, "{ (x = 1) }", &env, tables);
+
+    check_rejected(&lua, "x + x", &env, ExpressionPolicy::new().max_length(3));
+
+    Ok(())
+}
+
+#[test]
+fn test_chunk_source_map() -> Result<()> {
+    let lua = Lua::new();
+
+    // Lua code generated from `rules.dsl`: lines 1-2 from line 5, line 4 from line 7
+    let source = r#"
+        local function check(v)
+            if v > 10 then error("too big") end
+        end
+        check(...)
+    "#;
+    let map = SourceMap::new("rules.dsl")
+        .map_line(2, 5)
+        .map_line(4, 7)
+        .map_line(5, 8);
+    let check = lua
+        .load(source)
+        .set_name("=generated")
+        .set_source_map(map)
+        .into_function()?;
+    check.call::<_, ()>(5)?;
+    match check.call::<_, ()>(20) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.starts_with("rules.dsl:5: too big"), "{msg}");
+            assert!(msg.contains("\n\trules.dsl:5: in "), "{msg}");
+            assert!(msg.contains("(at generated:3)"), "{msg}");
+            assert!(msg.contains("\n\trules.dsl:8: in main chunk (at generated:5)"));
+        }
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Callback errors
+    let locations = Arc::new(Mutex::new(Vec::new()));
+    let locations2 = locations.clone();
+    let fail = lua.create_function(move |lua, ()| -> Result<()> {
+        let debug = lua.inspect_stack(1).unwrap();
+        locations2
+            .lock()
+            .unwrap()
+            .push((debug.mapped_location(), debug.curr_line()));
+        Err(Error::RuntimeError("failed".into()))
+    })?;
+    lua.globals().set("fail", fail)?;
+    let map = SourceMap::from_lines("script.dsl", [1, 1, 2]);
+    let chunk = lua.load("local x = 1\n\nfail()").set_name("=gen2");
+    match chunk.set_source_map(map).exec() {
+        Err(Error::CallbackError { traceback, .. }) => {
+            assert!(traceback.contains("script.dsl:2: in main chunk (at gen2:3)"));
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    let location = MappedLocation {
+        source: "script.dsl".into(),
+        line: 2,
+    };
+    assert_eq!(*locations.lock().unwrap(), vec![(Some(location), 3)]);
+
+    // Chunks without source map are not affected
+    match lua.load("error('x')").set_name("=gen3").exec() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("gen3:1: x")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Only whole positions are mapped
+    let map = SourceMap::new("main.dsl").map_line(1, 10);
+    lua.load("local x = 1")
+        .set_name("=main")
+        .set_source_map(map)
+        .exec()?;
+    match lua
+        .load("error('main:1: domain:1: main:1 main')")
+        .set_name("=gen4")
+        .exec()
+    {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(
+                msg.starts_with("gen4:1: main.dsl:10: domain:1: main:1 main"),
+                "{msg}"
+            )
+        }
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Maps of reloaded chunks are replaced
+    for line in [20, 30] {
+        let map = SourceMap::new("main.dsl").map_line(1, line);
+        lua.load("local x = 1")
+            .set_name("=main")
+            .set_source_map(map)
+            .exec()?;
+    }
+    match lua.load("error('main:1: x')").set_name("=gen5").exec() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("gen5:1: main.dsl:30: x")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_chunk_preprocessor() -> Result<()> {
+    let lua = Lua::new();
+
+    // `--#include name` comments are replaced by predefined code
+    lua.set_preprocessor(|source| {
+        let mut result = String::new();
+        for line in source.lines() {
+            match line.strip_prefix("--#include ") {
+                Some("consts") => result.push_str("local MAX = 10"),
+                Some(name) => return Err(Error::RuntimeError(format!("unknown include '{name}'"))),
+                None => result.push_str(line),
+            }
+            result.push('\n');
+        }
+        Ok(result)
+    })?;
+
+    assert_eq!(
+        lua.load("--#include consts\nreturn MAX * 2")
+            .eval::<i32>()?,
+        20
+    );
+    match lua.load("--#include other").exec() {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "unknown include 'other'"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Errors refer to the transformed source, which is retained
+    let chunk = lua
+        .load("--#include consts\nerror('boom')")
+        .set_name("main");
+    match chunk.exec() {
+        Err(Error::RuntimeError(msg)) => assert!(msg.starts_with("[string \"main\"]:2: boom")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    assert_eq!(
+        lua.preprocessed_source("[string \"main\"]").as_deref(),
+        Some("local MAX = 10\nerror('boom')\n")
+    );
+    assert_eq!(lua.preprocessed_source("[string \"other\"]"), None);
+
+    // Reloading a chunk with the same name replaces (or forgets) its transformed source
+    lua.load("--#include consts\nreturn MAX")
+        .set_name("main")
+        .exec()?;
+    assert_eq!(
+        lua.preprocessed_source("[string \"main\"]").as_deref(),
+        Some("local MAX = 10\nreturn MAX\n")
+    );
+    lua.load("return 1")
+        .set_name("main")
+        .set_preprocessor(|s| Ok(s.to_string()))
+        .exec()?;
+    assert_eq!(
+        lua.preprocessed_source("[string \"main\"]").as_deref(),
+        Some("return 1")
+    );
+
+    // Chunk preprocessor replaces the state-wide one
+    let chunk = lua
+        .load("return a ++ b")
+        .set_preprocessor(|s| Ok(s.replace("++", "+")));
+    lua.globals().set("a", 1)?;
+    lua.globals().set("b", 2)?;
+    assert_eq!(chunk.eval::<i32>()?, 3);
+
+    // Modules loaded by `require` are preprocessed
+    #[cfg(not(feature = "luau"))]
+    {
+        let temp_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            temp_dir.path().join("prep_module.lua"),
+            "--#include consts\nreturn MAX + 1",
+        )?;
+        let package: Table = lua.globals().get("package")?;
+        package.set("path", format!("{}/?.lua", temp_dir.path().display()))?;
+        assert_eq!(lua.load("return require('prep_module')").eval::<i32>()?, 11);
+        match lua.load("require('missing_module')").exec() {
+            Err(Error::RuntimeError(msg)) => assert!(msg.contains("missing_module.lua'"), "{msg}"),
+            r => panic!("expected RuntimeError, got {r:?}"),
+        }
+
+        // The original searcher is restored
+        lua.remove_preprocessor()?;
+        fs::write(
+            temp_dir.path().join("prep_module2.lua"),
+            "--#include consts\nreturn MAX + 1",
+        )?;
+        assert!(lua.load("require('prep_module2')").exec().is_err());
+    }
+
+    lua.remove_preprocessor()?;
+    let value = lua.load("--#include consts\nreturn MAX").eval::<Value>()?;
+    assert_eq!(value, Value::Nil);
+
+    lua.load("return 1").set_name("main").exec()?;
+    assert_eq!(lua.preprocessed_source("[string \"main\"]"), None);
+
+    Ok(())
+}
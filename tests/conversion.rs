@@ -1,9 +1,13 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::ffi::{CStr, CString};
 
 use maplit::{btreemap, btreeset, hashmap, hashset};
-use mlua::{Error, Lua, Result};
+use mlua::{
+    CheckedConversion, ConversionLimits, ConversionPolicy, Error, FromLua, Integer,
+    IntegerOverflow, IntoLua, Lua, Result, SetMode, Table, UserData, UserDataFields, UserDataRef,
+    UserDataRefMut, Value,
+};
 
 #[test]
 fn test_conv_vec() -> Result<()> {
@@ -71,6 +75,53 @@ fn test_conv_btreeset() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_conv_set_mode() -> Result<()> {
+    let lua = Lua::new();
+
+    let set = btreeset! {"a".to_string(), "b".to_string()};
+    assert_eq!(lua.set_set_mode(SetMode::Array), SetMode::Keys);
+    lua.globals().set("set", set.clone())?;
+    lua.globals().set("hset", hashset! {1, 2, 3})?;
+    lua.load(r#"assert(set[1] == "a" and set[2] == "b" and set.a == nil and #hset == 3)"#)
+        .exec()?;
+    assert_eq!(lua.globals().get::<_, BTreeSet<String>>("set")?, set);
+    assert_eq!(
+        lua.globals().get::<_, HashSet<i32>>("hset")?,
+        hashset! {1, 2, 3}
+    );
+
+    assert_eq!(lua.set_set_mode(SetMode::Keys), SetMode::Array);
+    lua.globals().set("set", set.clone())?;
+    lua.load(r#"assert(set.a == true and set.b == true and #set == 0)"#)
+        .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_vecdeque_binaryheap() -> Result<()> {
+    let lua = Lua::new();
+
+    let deque = VecDeque::from(vec![1, 2, 3]);
+    lua.globals().set("deque", deque.clone())?;
+    let deque2: VecDeque<i32> = lua.globals().get("deque")?;
+    assert_eq!(deque, deque2);
+
+    let heap = BinaryHeap::from(vec![3, 1, 2]);
+    lua.globals().set("heap", heap)?;
+    lua.load("assert(heap[1] == 1 and heap[2] == 2 and heap[3] == 3)")
+        .exec()?;
+    let mut heap2 = lua.load("{5, 9, 7}").eval::<BinaryHeap<i32>>()?;
+    assert_eq!(heap2.pop(), Some(9));
+    assert_eq!(heap2.into_sorted_vec(), vec![5, 7]);
+
+    assert!(lua.unpack::<VecDeque<i32>>(Value::Boolean(true)).is_err());
+    assert!(lua.unpack::<BinaryHeap<i32>>(Value::Integer(1)).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_conv_cstring() -> Result<()> {
     let lua = Lua::new();
@@ -138,3 +189,365 @@ fn test_conv_array() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_conv_checked_integer() -> Result<()> {
+    let lua = Lua::new();
+
+    let n = Value::Number(2.5);
+    assert_eq!(
+        Integer::checked_from_value(n.clone(), ConversionPolicy::Trunc)?,
+        2
+    );
+    assert_eq!(
+        Integer::checked_from_value(n.clone(), ConversionPolicy::Round)?,
+        3
+    );
+    let err = Integer::checked_from_value(n, ConversionPolicy::Exact).unwrap_err();
+    let ctx = err.conversion_context().unwrap();
+    assert_eq!(ctx.message, Some("number has no integer representation"));
+    assert_eq!(ctx.value, Some("2.5"));
+    assert_eq!(i32::checked_from_number(-3.0, ConversionPolicy::Exact)?, -3);
+
+    // Strings are coerced to numbers
+    let s = Value::String(lua.create_string("42")?);
+    assert_eq!(u8::checked_from_value(s, ConversionPolicy::Exact)?, 42);
+    let s = Value::String(lua.create_string("abc")?);
+    assert!(u8::checked_from_value(s, ConversionPolicy::Trunc).is_err());
+
+    // Same errors as `FromLua`
+    let err1 = u8::checked_from_value(Value::Integer(256), ConversionPolicy::Trunc).unwrap_err();
+    let err2 = lua.unpack::<u8>(Value::Integer(256)).unwrap_err();
+    assert_eq!(err1.to_string(), err2.to_string());
+    assert_eq!(lua.unpack::<i32>(Value::Number(-2.7))?, -2);
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_max_depth() -> Result<()> {
+    struct Nested(Vec<Nested>);
+
+    impl<'lua> IntoLua<'lua> for Nested {
+        fn into_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+            self.0.into_lua(lua)
+        }
+    }
+
+    // Returns a value converted to `levels` nested tables
+    fn nested(levels: usize) -> Nested {
+        (1..levels).fold(Nested(Vec::new()), |inner, _| Nested(vec![inner]))
+    }
+
+    let lua = Lua::new();
+
+    // No limit by default, depth is bounded only by the heap
+    let value = nested(100_000).into_lua(&lua)?;
+    let mut depth = 0;
+    let mut table = lua.unpack::<Option<Table>>(value)?;
+    while let Some(t) = table {
+        depth += 1;
+        table = t.get::<_, Option<Table>>(1)?;
+    }
+    assert_eq!(depth, 100_000);
+
+    assert_eq!(lua.set_max_conversion_depth(Some(200)), None);
+    nested(200).into_lua(&lua)?;
+    match nested(201).into_lua(&lua) {
+        Err(Error::ConversionDepthExceeded(200)) => {}
+        r => panic!("expected ConversionDepthExceeded, got {r:?}"),
+    }
+    // Errors from deferred tables are reported and the state stays usable
+    match nested(1000).into_lua(&lua) {
+        Err(Error::ConversionDepthExceeded(200)) => {}
+        r => panic!("expected ConversionDepthExceeded, got {r:?}"),
+    }
+    nested(100).into_lua(&lua)?;
+
+    assert_eq!(lua.set_max_conversion_depth(Some(10)), Some(200));
+    nested(10).into_lua(&lua)?;
+    assert!(nested(11).into_lua(&lua).is_err());
+    let map = hashmap! { "a" => vec![nested(9)] };
+    assert!(map.into_lua(&lua).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_limits() -> Result<()> {
+    let lua = Lua::new();
+
+    let limits = ConversionLimits::new()
+        .max_depth(3)
+        .max_table_entries(10)
+        .max_string_len(5);
+    assert_eq!(
+        lua.set_conversion_limits(limits),
+        ConversionLimits::default()
+    );
+
+    let nested = lua.load("{{{1}}}").eval::<Value>()?;
+    lua.unpack::<Vec<Vec<Vec<i32>>>>(nested)?;
+    let nested = lua.load("{{{{1}}}}").eval::<Value>()?;
+    match lua.unpack::<Vec<Vec<Vec<Vec<i32>>>>>(nested) {
+        Err(Error::ConversionDepthExceeded(3)) => {}
+        r => panic!("expected ConversionDepthExceeded, got {r:?}"),
+    }
+
+    let seq = lua
+        .load("{1, 2, 3, 4, 5, 6, 7, 8, 9, 10}")
+        .eval::<Value>()?;
+    assert_eq!(lua.unpack::<Vec<i32>>(seq)?.len(), 10);
+    let seq = lua
+        .load("{1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11}")
+        .eval::<Value>()?;
+    for err in [
+        lua.unpack::<Vec<i32>>(seq.clone()).unwrap_err(),
+        lua.unpack::<HashSet<i32>>(seq.clone()).unwrap_err(),
+        lua.unpack::<HashMap<i32, i32>>(seq).unwrap_err(),
+    ] {
+        match err {
+            Error::FromLuaConversionError { message, .. } => {
+                assert_eq!(message.unwrap(), "table has more than 10 entries")
+            }
+            err => panic!("expected FromLuaConversionError, got {err:?}"),
+        }
+    }
+
+    assert_eq!(lua.unpack::<String>(lua.load("'hello'").eval()?)?, "hello");
+    match lua.unpack::<String>(lua.load("'hello!'").eval()?) {
+        Err(Error::FromLuaConversionError { message, .. }) => {
+            assert_eq!(message.unwrap(), "string is longer than 5 bytes")
+        }
+        r => panic!("expected FromLuaConversionError, got {r:?}"),
+    }
+
+    lua.set_conversion_limits(ConversionLimits::new());
+    lua.unpack::<String>(lua.load("'hello!'").eval()?)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_coercion() -> Result<()> {
+    #[derive(Debug, PartialEq)]
+    struct Pixels(u32);
+
+    impl<'lua> FromLua<'lua> for Pixels {
+        fn from_lua(value: Value<'lua>, lua: &'lua Lua) -> Result<Self> {
+            match value {
+                Value::Integer(i) => Ok(Pixels(i as u32)),
+                _ => lua.coerce(value),
+            }
+        }
+    }
+
+    impl<'lua> IntoLua<'lua> for Pixels {
+        fn into_lua(self, _: &'lua Lua) -> Result<Value<'lua>> {
+            Ok(Value::Integer(self.0 as Integer))
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Vec2(f32, f32);
+
+    impl UserData for Vec2 {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field_method_get("x", |_, v| Ok(v.0));
+        }
+    }
+
+    let lua = Lua::new();
+
+    lua.register_coercion(|_, s: String| match s.strip_suffix("px") {
+        Some(n) => Ok(Pixels(n.parse().map_err(Error::external)?)),
+        None => Err(Error::RuntimeError(format!("invalid pixels `{s}`"))),
+    })?;
+    assert_eq!(lua.unpack::<Pixels>(Value::Integer(7))?, Pixels(7));
+    assert_eq!(lua.load("'42px'").eval::<Pixels>()?, Pixels(42));
+    match lua.load("'42em'").eval::<Pixels>() {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "invalid pixels `42em`"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    match lua.load("true").eval::<Pixels>() {
+        Err(Error::FromLuaConversionError {
+            from: "boolean", ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {r:?}"),
+    }
+
+    // Coercions to userdata
+    lua.register_coercion(|_, [x, y]: [f32; 2]| Ok(Vec2(x, y)))?;
+    lua.register_coercion(|_, t: HashMap<String, f32>| Ok(Vec2(t["x"], t["y"])))?;
+    let length = lua.create_function(|_, v: UserDataRef<Vec2>| Ok(v.0.hypot(v.1)))?;
+    lua.globals().set("length", length)?;
+    lua.globals().set("v", Vec2(6.0, 8.0))?;
+    lua.load(
+        r#"
+        assert(length(v) == 10)
+        assert(length({3, 4}) == 5)
+        assert(length({x = 5, y = 12}) == 13)
+        assert(not pcall(length, "3,4"))
+    "#,
+    )
+    .exec()?;
+    let v = lua.load("{1, 2}").eval::<UserDataRef<Vec2>>()?;
+    assert_eq!(*v, Vec2(1.0, 2.0));
+    // Mutable references are never coerced
+    match lua.load("{1, 2}").eval::<UserDataRefMut<Vec2>>() {
+        Err(Error::FromLuaConversionError { from: "table", .. }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r.map(|v| *v)),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_integer_overflow() -> Result<()> {
+    let lua = Lua::new();
+    let big = u64::MAX - 1;
+
+    // Float by default
+    assert!(matches!(lua.pack(big)?, Value::Number(_)));
+    assert!(matches!(lua.pack(5u64)?, Value::Integer(5)));
+
+    assert_eq!(
+        lua.set_integer_overflow(IntegerOverflow::Error),
+        IntegerOverflow::Float
+    );
+    assert!(matches!(
+        lua.pack(big),
+        Err(Error::ToLuaConversionError { .. })
+    ));
+    assert!(matches!(lua.pack(5usize)?, Value::Integer(5)));
+
+    // Values round-trip through negative integers
+    lua.set_integer_overflow(IntegerOverflow::Wrap);
+    if Integer::BITS == 64 {
+        assert!(matches!(lua.pack(big)?, Value::Integer(-2)));
+        assert_eq!(lua.unpack::<u64>(Value::Integer(-2))?, big);
+        assert!(lua.pack(u128::MAX).is_err());
+    }
+    assert_eq!(lua.unpack::<i64>(Value::Integer(-2))?, -2);
+    assert!(lua.unpack::<u8>(Value::Integer(-2)).is_err());
+    lua.set_integer_overflow(IntegerOverflow::Error);
+    assert!(lua.unpack::<u64>(Value::Integer(-2)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_conv_boxed_u64() -> Result<()> {
+    let lua = Lua::new();
+
+    let id = lua.create_u64(u64::MAX - 10)?;
+    lua.globals().set("id", id.clone())?;
+    assert_eq!(
+        lua.unpack::<u64>(Value::UserData(id.clone()))?,
+        u64::MAX - 10
+    );
+    assert!(lua.unpack::<i64>(Value::UserData(id)).is_err());
+
+    assert_eq!(lua.load("id + 10").eval::<u64>()?, u64::MAX);
+    assert_eq!(lua.load("id - id").eval::<u64>()?, 0);
+    assert_eq!(lua.load("id / 2").eval::<u64>()?, (u64::MAX - 10) / 2);
+    assert_eq!(lua.load("id % 10").eval::<u64>()?, (u64::MAX - 10) % 10);
+    assert_eq!(
+        lua.load("tostring(id * 1)").eval::<String>()?,
+        (u64::MAX - 10).to_string()
+    );
+    assert!(lua.load("id == id + 0").eval::<bool>()?);
+    assert!(lua.load("id < id + 1 and id <= id").eval::<bool>()?);
+
+    // Errors
+    assert!(lua.load("id + 11").exec().is_err());
+    assert!(lua.load("id / 0").exec().is_err());
+    assert!(lua.load("id - (id + 1)").exec().is_err());
+
+    Ok(())
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_conv_derive_table() -> Result<()> {
+    use mlua::AnyUserData;
+
+    #[derive(Clone)]
+    struct Handle(u32);
+
+    impl UserData for Handle {}
+
+    #[derive(FromLua, IntoLua)]
+    struct Entity<'lua> {
+        name: String,
+        #[lua(rename = "hp", default)]
+        health: u32,
+        tags: Vec<String>,
+        handle: AnyUserData<'lua>,
+        #[lua(skip)]
+        dirty: bool,
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("handle", Handle(7))?;
+
+    let entity: Entity = lua
+        .load(r#"{name = "orc", tags = {"hostile"}, handle = handle, dirty = true}"#)
+        .eval()?;
+    assert_eq!(entity.name, "orc");
+    assert_eq!(entity.health, 0);
+    assert_eq!(entity.tags, vec!["hostile"]);
+    assert_eq!(entity.handle.borrow::<Handle>()?.0, 7);
+    assert!(!entity.dirty);
+
+    let table: Table = lua.unpack(entity.into_lua(&lua)?)?;
+    assert_eq!(table.get::<_, String>("name")?, "orc");
+    assert_eq!(table.get::<_, u32>("hp")?, 0);
+    assert_eq!(table.get::<_, Value>("health")?, Value::Nil);
+    assert_eq!(table.get::<_, Value>("dirty")?, Value::Nil);
+    assert_eq!(table.get::<_, UserDataRef<Handle>>("handle")?.0, 7);
+
+    // Missing required field
+    let result = lua.load(r#"{name = "orc"}"#).eval::<Entity>();
+    assert!(result.is_err());
+    match lua.unpack::<Entity>(Value::Integer(1)) {
+        Err(Error::FromLuaConversionError {
+            from: "integer",
+            to: "Entity",
+            ..
+        }) => {}
+        r => panic!("expected FromLuaConversionError, got {:?}", r.map(|_| ())),
+    }
+    let err = lua
+        .load(r#"{name = "orc", tags = {"hostile", {}}, handle = handle}"#)
+        .eval::<Entity>()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.conversion_context().unwrap().path,
+        Some(r#"["tags"][2]"#)
+    );
+
+    // Generic structs
+    #[derive(FromLua, IntoLua)]
+    struct Pair<T> {
+        first: T,
+        second: T,
+    }
+
+    let pair: Pair<i64> = lua.load("{first = 1, second = 2}").eval()?;
+    assert_eq!((pair.first, pair.second), (1, 2));
+    let table: Table = lua.unpack(pair.into_lua(&lua)?)?;
+    assert_eq!(table.get::<_, i64>("second")?, 2);
+    let err = lua
+        .load(r#"{first = 1, second = "x"}"#)
+        .eval::<Pair<i64>>()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.conversion_context().unwrap().path,
+        Some(r#"["second"]"#)
+    );
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
+use std::collections::HashMap;
 use std::io;
 
-use mlua::{Error, ErrorContext, Lua, Result};
+use mlua::{Error, ErrorContext, Lua, LuaResultExt, Result, TracebackOptions, Value};
 
 #[test]
 fn test_error_context() -> Result<()> {
@@ -48,3 +49,131 @@ fn test_error_context() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_conversion_context() -> Result<()> {
+    let lua = Lua::new();
+
+    let err = lua.unpack::<u8>(Value::Integer(300)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "error converting Lua integer 300 to u8 (out of range)"
+    );
+    let ctx = err.conversion_context().unwrap();
+    assert_eq!((ctx.from, ctx.to), ("integer", "u8"));
+    assert_eq!(ctx.value, Some("300"));
+    assert_eq!(ctx.path, None);
+    match err {
+        Error::FromLuaConversionDetails { cause, .. } => {
+            assert!(matches!(*cause, Error::FromLuaConversionError { .. }))
+        }
+        err => panic!("expected FromLuaConversionDetails, got {err:?}"),
+    }
+
+    // Nested path
+    let t = lua.load(r#"{ {1, 2}, {3, "four"} }"#).eval::<Value>()?;
+    let err = lua.unpack::<Vec<Vec<i32>>>(t).unwrap_err();
+    let ctx = err.conversion_context().unwrap();
+    assert_eq!(ctx.value, Some("\"four\""));
+    assert_eq!(ctx.path, Some("[2][2]"));
+
+    let t = lua
+        .load(r#"{ a = {x = 1}, b = {x = true} }"#)
+        .eval::<Value>()?;
+    let err = lua
+        .unpack::<HashMap<String, HashMap<String, i32>>>(t)
+        .unwrap_err();
+    assert_eq!(
+        err.conversion_context().unwrap().path,
+        Some(r#"["b"]["x"]"#)
+    );
+
+    // Long strings are truncated
+    let s = Value::String(lua.create_string("x".repeat(100))?);
+    let err = lua.unpack::<i32>(s).unwrap_err();
+    let value = err.conversion_context().unwrap().value.unwrap();
+    assert!(value.len() < 40 && value.ends_with("..."));
+
+    // Function arguments
+    let func = lua.create_function(|_, (_, _): (i32, i32)| Ok(()))?;
+    let err = func.call::<_, ()>((1, "x")).unwrap_err();
+    let ctx = err.conversion_context().unwrap();
+    assert_eq!(ctx.argument, Some((2, None)));
+    assert_eq!(ctx.value, Some("\"x\""));
+
+    assert!(Error::RuntimeError("error".into())
+        .conversion_context()
+        .is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_with_lua_context() -> Result<()> {
+    let lua = Lua::new();
+
+    let load_quest = lua.create_function(|lua, id: u32| {
+        Err::<(), _>(Error::RuntimeError("quest not found".into()))
+            .with_lua_context(lua, format!("loading quest {id}"))
+    })?;
+    lua.globals().set("load_quest", load_quest)?;
+
+    let err = lua
+        .load("local function start() load_quest(42) end\nstart()")
+        .set_name("quests")
+        .exec()
+        .unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("loading quest 42\nstack traceback:"), "{msg}");
+    assert!(msg.contains("[string \"quests\"]:1: in "), "{msg}");
+    assert!(msg.contains("quest not found"), "{msg}");
+
+    // No Lua code is running, so no traceback is captured
+    let res: Result<()> = Err(Error::RuntimeError("oops".into()));
+    match res.with_lua_context(&lua, "host") {
+        Err(Error::WithContext { context, .. }) => {
+            assert!(context.starts_with("host"));
+            assert!(!context.contains("stack traceback"));
+        }
+        r => panic!("expected WithContext, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_traceback_options() -> Result<()> {
+    let lua = Lua::new();
+
+    let func = lua
+        .load(
+            r#"
+            local function f(n)
+                if n == 0 then error("boom") end
+                f(n - 1)
+            end
+            f(5)
+        "#,
+        )
+        .set_name("@scripts/app/main.lua")
+        .into_function()?;
+    let err = func.call::<_, ()>(()).unwrap_err().to_string();
+    assert!(err.contains("[C]: in ?"));
+    assert!(err.contains("scripts/app/main.lua:3:"));
+
+    lua.set_traceback_options(
+        TracebackOptions::new()
+            .max_frames(Some(3))
+            .skip_internal(true)
+            .source_shortening(true),
+    );
+    let err = func.call::<_, ()>(()).unwrap_err().to_string();
+    let traceback = err.split("stack traceback:").nth(1).unwrap();
+    assert!(!traceback.contains("[C]: in ?"));
+    assert!(!traceback.contains("scripts/app/"));
+    assert!(traceback.contains("\tmain.lua:4:"));
+    assert_eq!(traceback.lines().filter(|l| !l.is_empty()).count(), 4);
+    assert!(traceback.trim_end().ends_with("more frames)"));
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use mlua::{Function, Lua, Result, String, Table};
+use std::string::String as StdString;
+
+use mlua::{
+    lua_args, Error, Function, FunctionKind, Lua, MultiValue, Result, String, Table, Variadic,
+};
 
 #[test]
 fn test_function() -> Result<()> {
@@ -56,6 +60,99 @@ fn test_bind() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_call_fast() -> Result<()> {
+    let lua = Lua::new();
+
+    let concat: Function = lua
+        .load("function(a, b) return a .. b, #a + #b end")
+        .eval()?;
+    let (s, len) = concat.call_fast::<_, (String, i64)>(("foo", "bar"))?;
+    assert_eq!((s.to_str()?, len), ("foobar", 6));
+
+    // Lua errors have no traceback attached
+    let fail: Function = lua.load("function() error('oops') end").eval()?;
+    match fail.call_fast::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.ends_with("oops"));
+            assert!(!msg.contains("stack traceback"));
+        }
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Rust errors are still wrapped
+    let rust_fail =
+        lua.create_function(|_, ()| Err::<(), _>(Error::RuntimeError("rust".into())))?;
+    match rust_fail.call_fast::<_, ()>(()) {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(&*cause, Error::RuntimeError(msg) if msg == "rust"))
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_call_spread() -> Result<()> {
+    let lua = Lua::new();
+
+    let f: Function = lua
+        .load("function(...) return select('#', ...), ... end")
+        .eval()?;
+
+    let args = lua.create_sequence_from(["a", "b", "c"])?;
+    let (n, a, c) = f.call_spread::<(i32, String, Variadic<String>)>(args)?;
+    assert_eq!((n, a.to_str()?), (3, "a"));
+    assert_eq!(c.len(), 2);
+
+    // `n` field is honored, including trailing nils
+    let packed: Table = lua.load("{ n = 3, 1 }").eval()?;
+    assert_eq!(f.call_spread::<i32>(packed)?, 3);
+    let args = lua.create_table_from([("n", 1)])?;
+    args.raw_set(1, "x")?;
+    args.raw_set(2, "y")?;
+    assert_eq!(f.call_spread::<(i32, String)>(args)?.0, 1);
+
+    // Empty table
+    assert_eq!(f.call_spread::<i32>(lua.create_table()?)?, 0);
+
+    // Errors are propagated
+    let err_fn: Function = lua.load("function(msg) error(msg) end").eval()?;
+    let args = lua.create_sequence_from(["boom"])?;
+    assert!(err_fn.call_spread::<()>(args).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_compose() -> Result<()> {
+    let lua = Lua::new();
+
+    let split: Function = lua
+        .load(r#"function(s) return s:sub(1, 1), s:sub(2) end"#)
+        .eval()?;
+    let swap: Function = lua.load(r#"function(a, b) return b, a end"#).eval()?;
+    let concat: Function = lua.load(r#"function(a, b) return a .. b end"#).eval()?;
+
+    let f = split.then(&swap)?.then(&concat)?;
+    assert_eq!(f.call::<_, String>("abc")?, "bca");
+
+    let f = Function::compose(&[split.clone(), swap, concat.clone()])?;
+    assert_eq!(f.call::<_, String>("xyz")?, "yzx");
+
+    let f = split.and_then_rust(|_, (a, b): (String, String)| {
+        Ok(format!("{}-{}", a.to_str()?, b.to_str()?))
+    })?;
+    assert_eq!(f.call::<_, String>("abc")?, "a-bc");
+
+    // Single function is returned as is
+    assert_eq!(Function::compose(std::slice::from_ref(&concat))?, concat);
+    assert!(Function::compose(&[]).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_rust_function() -> Result<()> {
     let lua = Lua::new();
@@ -114,6 +211,54 @@ fn test_dump() -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_function_clone_into() -> Result<()> {
+    use mlua::Value;
+
+    let lua = Lua::new();
+    let worker = Lua::new();
+
+    lua.globals().set("prefix", "main")?;
+    worker.globals().set("prefix", "worker")?;
+    let func = lua
+        .load(
+            r#"
+        local sep, count = ":", 0
+        return function(s)
+            count = count + 1
+            return prefix .. sep .. s .. sep .. count
+        end
+    "#,
+        )
+        .eval::<Function>()?;
+
+    let mut names = Vec::new();
+    let copy = func.clone_into(&worker, |name, value| {
+        names.push(name.to_string());
+        match value {
+            Value::String(s) => Ok(Value::String(worker.create_string(s.as_bytes())?)),
+            Value::Integer(i) => Ok(Value::Integer(i + 10)),
+            _ => Ok(Value::Nil),
+        }
+    })?;
+    names.sort();
+    assert_eq!(names, ["count", "sep"]);
+
+    assert_eq!(copy.call::<_, String>("a")?, "worker:a:11");
+    assert_eq!(copy.call::<_, String>("b")?, "worker:b:12");
+    // Upvalues are not shared
+    assert_eq!(func.call::<_, String>("a")?, "main:a:1");
+
+    let rust_func = lua.create_function(|_, ()| Ok(()))?;
+    match rust_func.clone_into(&worker, |_, _| Ok(Value::Nil)) {
+        Err(Error::RuntimeError(_)) => {}
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_function_environment() -> Result<()> {
     let lua = Lua::new();
@@ -231,6 +376,62 @@ fn test_function_info() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_function_info_ext() -> Result<()> {
+    let lua = Lua::new();
+
+    let globals = lua.globals();
+    lua.load(
+        r#"
+        function function1(a, b)
+            local x = a
+            return function(...) return x end
+        end
+    "#,
+    )
+    .set_name("source1")
+    .exec()?;
+
+    let function1 = globals.get::<_, Function>("function1")?;
+    let function2 = function1.call::<_, Function>(())?;
+    let function3 = lua.create_function(|_, ()| Ok(()))?;
+
+    let function1_info = function1.info();
+    assert_eq!(function1_info.kind, FunctionKind::Lua);
+    assert_eq!(function1_info.resolved_source, None);
+    #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+    {
+        assert_eq!(function1_info.num_params, Some(2));
+        assert_eq!(function1_info.is_vararg, Some(false));
+    }
+
+    let function2_info = function2.info();
+    assert_eq!(function2_info.kind, FunctionKind::Lua);
+    assert_eq!(function2_info.num_upvalues, 1);
+    #[cfg(not(any(feature = "lua51", feature = "luajit")))]
+    {
+        assert_eq!(function2_info.num_params, Some(0));
+        assert_eq!(function2_info.is_vararg, Some(true));
+    }
+
+    assert_eq!(function3.info().kind, FunctionKind::C);
+
+    // Sources are resolved using the registered resolver
+    lua.set_source_resolver(|source| match source {
+        "source1" => Some(std::path::PathBuf::from("/scripts/source1.lua")),
+        _ => None,
+    });
+    assert_eq!(
+        function1.info().resolved_source.as_deref(),
+        Some(std::path::Path::new("/scripts/source1.lua"))
+    );
+    assert_eq!(function3.info().resolved_source, None);
+    lua.remove_source_resolver();
+    assert_eq!(function1.info().resolved_source, None);
+
+    Ok(())
+}
+
 #[test]
 fn test_function_wrap() -> Result<()> {
     use mlua::Error;
@@ -304,3 +505,207 @@ fn test_owned_function_drop() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_function_family() -> Result<()> {
+    use mlua::{FromLuaMulti, IntoLuaMulti, MultiValue};
+    use std::string::String as StdString;
+    use std::sync::Arc;
+
+    struct Config {
+        prefix: &'static str,
+        scale: i64,
+    }
+
+    let lua = Lua::new();
+    let config = Arc::new(Config {
+        prefix: "id-",
+        scale: 3,
+    });
+    let module = lua.create_function_family(
+        config.clone(),
+        &[
+            ("tag", |lua, config, args| {
+                let name = StdString::from_lua_multi(args, lua)?;
+                format!("{}{name}", config.prefix).into_lua_multi(lua)
+            }),
+            ("scale", |lua, config, args| {
+                let values = Variadic::<i64>::from_lua_multi(args, lua)?;
+                (values.iter().map(|v| v * config.scale))
+                    .collect::<Variadic<_>>()
+                    .into_lua_multi(lua)
+            }),
+            ("count", |lua, _, args: MultiValue| {
+                args.len().into_lua_multi(lua)
+            }),
+        ],
+    )?;
+    lua.globals().set("m", module)?;
+
+    lua.load(
+        r#"
+        assert(m.tag("x") == "id-x")
+        local a, b = m.scale(1, 2)
+        assert(a == 3 and b == 6)
+        assert(m.count() == 0)
+        assert(m.count(nil, nil) == 2)
+    "#,
+    )
+    .exec()?;
+
+    // Shared state is released together with the Lua instance
+    drop(lua);
+    assert_eq!(Arc::strong_count(&config), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_function_meta() -> Result<()> {
+    use mlua::{FnMeta, UserData, UserDataMethods};
+
+    struct Counter(i64);
+
+    impl UserData for Counter {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_with_meta(
+                "get",
+                FnMeta::new("Returns the counter value.").returns("integer"),
+                |_, this, ()| Ok(this.0),
+            );
+            methods.add_method_mut("increment", |_, this, ()| {
+                this.0 += 1;
+                Ok(())
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    lua.install_help()?;
+
+    let meta = FnMeta {
+        doc: "Greets somebody.\nReturns the greeting.".into(),
+        params: vec!["name: string".into()],
+        returns: vec!["string".into()],
+    };
+    let greet = lua
+        .create_function_with_meta(|_, name: String| Ok(format!("hi {}", name.to_str()?)), meta)?;
+    let module = lua.create_table()?;
+    module.set("greet", greet)?;
+    module.set("undocumented", lua.create_function(|_, ()| Ok(()))?)?;
+    lua.globals().set("mymod", module)?;
+    lua.globals().set("counter", Counter(0))?;
+
+    let docs = lua.api_docs()?;
+    let names = docs.iter().map(|d| d.name.as_deref()).collect::<Vec<_>>();
+    assert_eq!(names, vec![Some("Counter:get"), Some("mymod.greet")]);
+    assert_eq!(docs[1].meta.params, vec!["name: string"]);
+
+    let help = |code: &str| lua.load(code).eval::<std::string::String>();
+    assert_eq!(
+        help("help(mymod.greet)")?,
+        "mymod.greet(name: string) -> string\n    Greets somebody.\n    Returns the greeting."
+    );
+    assert_eq!(help("help(mymod)")?, help("help(mymod.greet)")?);
+    assert_eq!(
+        help("help(counter)")?,
+        "Counter:get() -> integer\n    Returns the counter value."
+    );
+    assert_eq!(
+        help("help(mymod.undocumented)")?,
+        "no documentation available"
+    );
+    assert_eq!(
+        help("help()")?,
+        format!("{}\n{}", help("help(counter)")?, help("help(mymod)")?)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_function_arity() -> Result<()> {
+    let lua = Lua::new();
+
+    let concat = lua.create_function_with_arity(
+        2,
+        |_, (a, b): (std::string::String, std::string::String)| Ok(format!("{a}{b}")),
+    )?;
+    lua.globals().set("concat", concat.clone())?;
+    assert_eq!(concat.call::<_, std::string::String>(("a", "b"))?, "ab");
+
+    // The argument count is checked before converting arguments
+    match lua.load("concat({}, {}, {})").exec() {
+        Err(Error::CallbackError { cause, .. }) => match *cause {
+            Error::WrongArity {
+                expected: 2,
+                got: 3,
+                ref name,
+            } => assert_eq!(name.as_deref(), Some("concat")),
+            ref err => panic!("expected WrongArity, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    match concat.call::<_, ()>("a") {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(
+                *cause,
+                Error::WrongArity {
+                    expected: 2,
+                    got: 1,
+                    ..
+                }
+            ));
+            assert_eq!(
+                cause.to_string(),
+                "wrong number of arguments (expected 2, got 1)"
+            );
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_lua_args() -> Result<()> {
+    let lua = Lua::new();
+
+    let f = lua.create_function(|lua, args: MultiValue| {
+        lua_args!(lua, args => a: i64, b: &str, opts: Option<Table>,);
+        let suffix = match opts {
+            Some(opts) => opts.get::<_, StdString>("suffix")?,
+            None => StdString::new(),
+        };
+        Ok(format!("{a}{b}{suffix}"))
+    })?;
+
+    assert_eq!(f.call::<_, StdString>((1, "x"))?, "1x");
+    let opts = lua.create_table_from([("suffix", "!")])?;
+    assert_eq!(f.call::<_, StdString>((2, "y", opts, "extra"))?, "2y!");
+    // Numbers are coerced to strings
+    assert_eq!(f.call::<_, StdString>((3, 4))?, "34");
+
+    match f.call::<_, StdString>((1, "x", 5)) {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { pos, name, .. } => {
+                assert_eq!(*pos, 3);
+                assert_eq!(name.as_deref(), Some("opts"));
+            }
+            err => panic!("expected BadArgument, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    match f.call::<_, StdString>(()) {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { pos, name, .. } => {
+                assert_eq!(*pos, 1);
+                assert_eq!(name.as_deref(), Some("a"));
+            }
+            err => panic!("expected BadArgument, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    Ok(())
+}
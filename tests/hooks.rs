@@ -4,8 +4,9 @@ use std::cell::RefCell;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicI64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use mlua::{DebugEvent, Error, HookTriggers, Lua, Result, Value};
+use mlua::{DebugEvent, Error, HookTriggers, Lua, Result, StepControl, Value};
 
 #[test]
 fn test_hook_triggers() {
@@ -18,6 +19,63 @@ fn test_hook_triggers() {
     assert_eq!(trigger.every_nth_instruction, Some(5));
 }
 
+#[test]
+fn test_hook_sampling() -> Result<()> {
+    let lua = Lua::new();
+    // The `embedded` profile doesn't read the system clock
+    #[cfg(feature = "embedded")]
+    {
+        let start = std::time::Instant::now();
+        lua.set_clock(move || start.elapsed())?;
+    }
+
+    let lines = Arc::new(AtomicI64::new(0));
+    let hook_lines = lines.clone();
+    lua.set_hook(HookTriggers::new().every_nth_line(3), move |_lua, debug| {
+        assert_eq!(debug.event(), DebugEvent::Line);
+        hook_lines.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    })?;
+    lua.load("for i = 1, 30 do local _ = i end").exec()?;
+    let count = lines.load(Ordering::Relaxed);
+    // The loop body produces 30 line events (plus a few for the loop itself)
+    assert!((10..=12).contains(&count), "unexpected count {count}");
+
+    let calls = Arc::new(AtomicI64::new(0));
+    let hook_calls = calls.clone();
+    lua.set_hook(
+        HookTriggers::new().every(Duration::from_secs(3600)),
+        move |_lua, _debug| {
+            hook_calls.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        },
+    )?;
+    lua.load("for i = 1, 100000 do local _ = i end").exec()?;
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // Time-based sampling combined with another trigger
+    calls.store(0, Ordering::Relaxed);
+    let hook_calls = calls.clone();
+    let triggers = HookTriggers::ON_CALLS.every(Duration::from_millis(1));
+    lua.set_hook(triggers, move |_lua, _debug| {
+        hook_calls.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    })?;
+    lua.load(
+        r#"
+        local function f() end
+        local start = os.clock()
+        while os.clock() - start < 0.02 do f() end
+    "#,
+    )
+    .exec()?;
+    lua.remove_hook();
+    let count = calls.load(Ordering::Relaxed);
+    assert!((2..=40).contains(&count), "unexpected count {count}");
+
+    Ok(())
+}
+
 #[test]
 fn test_line_counts() -> Result<()> {
     let output = Arc::new(Mutex::new(Vec::new()));
@@ -262,3 +320,216 @@ fn test_hook_threads() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hook_stacking() -> Result<()> {
+    let lua = Lua::new();
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let make_hook = |name: &'static str| {
+        let output = output.clone();
+        move |_: &Lua, _: mlua::Debug| {
+            output.lock().unwrap().push(name);
+            Ok(())
+        }
+    };
+    let run = || -> Result<Vec<&'static str>> {
+        output.lock().unwrap().clear();
+        lua.load("local x = 1").exec()?;
+        let mut output = output.lock().unwrap().clone();
+        output.dedup();
+        Ok(output)
+    };
+
+    lua.set_hook(HookTriggers::EVERY_LINE, make_hook("base"))?;
+    let profiler = lua.push_hook(HookTriggers::EVERY_LINE, make_hook("profiler"))?;
+    assert_eq!(run()?, ["profiler"]);
+
+    let watchdog = lua.push_hook(HookTriggers::EVERY_LINE, make_hook("watchdog"))?;
+    assert_eq!(run()?, ["watchdog"]);
+
+    drop(watchdog);
+    assert_eq!(run()?, ["profiler"]);
+    drop(profiler);
+    assert_eq!(run()?, ["base"]);
+
+    // Out of order removal
+    let profiler = lua.push_hook(HookTriggers::EVERY_LINE, make_hook("profiler"))?;
+    let watchdog = lua.push_hook(HookTriggers::EVERY_LINE, make_hook("watchdog"))?;
+    drop(profiler);
+    assert_eq!(run()?, ["watchdog"]);
+    drop(watchdog);
+    assert_eq!(run()?, ["base"]);
+
+    lua.remove_hook();
+    let guard = lua.push_hook(HookTriggers::EVERY_LINE, make_hook("profiler"))?;
+    drop(guard);
+    assert!(run()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_instruction_count() -> Result<()> {
+    let lua = Lua::new();
+    let code = "local n = 0; for i = 1, 1000 do n = n + i end";
+
+    // Nothing is counted without a guard
+    lua.load(code).exec()?;
+    assert_eq!(lua.instruction_count(), 0);
+
+    let guard = lua.count_instructions(1)?;
+    lua.load(code).exec()?;
+    let exact = lua.reset_instruction_count();
+    assert!(exact >= 1000);
+
+    // Counts are proportional to the executed code
+    lua.load(code).exec()?;
+    lua.load(code).exec()?;
+    assert_eq!(lua.instruction_count(), exact * 2);
+    drop(guard);
+
+    lua.reset_instruction_count();
+    let _guard = lua.count_instructions(100)?;
+    lua.load(code).exec()?;
+    let count = lua.instruction_count();
+    assert_eq!(count % 100, 0);
+    assert!(count <= exact);
+    drop(_guard);
+
+    // Instructions executed by coroutines are counted too
+    lua.reset_instruction_count();
+    let _guard = lua.count_instructions(1)?;
+    let wrapped = format!("coroutine.wrap(function() {code} end)()");
+    lua.load(&wrapped).exec()?;
+    assert!(lua.reset_instruction_count() >= exact);
+
+    Ok(())
+}
+
+#[test]
+fn test_exec_stepped() -> Result<()> {
+    let lua = Lua::new();
+
+    let chunk = r#"
+        local function double(n)
+            return n * 2
+        end
+        local x = 1
+        x = double(x)
+        result = x
+    "#;
+
+    // Step through everything, recording lines and locals
+    let mut steps = Vec::new();
+    let completed = lua.load(chunk).set_name("tutorial").exec_stepped(|step| {
+        let locals = (step.locals.iter())
+            .map(|(name, value)| format!("{name}={}", value.to_string().unwrap()))
+            .collect::<Vec<_>>();
+        steps.push((step.line, step.function, locals));
+        StepControl::Continue
+    })?;
+    assert!(completed);
+    assert_eq!(lua.globals().get::<_, i64>("result")?, 2);
+    let lines = steps.iter().map(|(line, ..)| *line).collect::<Vec<_>>();
+    assert_eq!(lines, [4, 5, 6, 3, 7]);
+    assert_eq!(steps[2].1, None);
+    assert!(steps[2].2.contains(&"x=1".to_string()));
+    assert_eq!(steps[3].1.as_deref(), Some("double"));
+    assert_eq!(steps[3].2, ["n=1"]);
+    assert!(steps[4].2.contains(&"x=2".to_string()));
+
+    // Finish runs to the end without stepping
+    let mut count = 0;
+    let completed = lua.load(chunk).exec_stepped(|_| {
+        count += 1;
+        StepControl::Finish
+    })?;
+    assert!(completed);
+    assert_eq!(count, 1);
+
+    // Abort stops execution, even if the script catches errors
+    lua.globals().set("result", 0)?;
+    let completed = lua
+        .load("pcall(function() x = 1 end)\nresult = 1")
+        .exec_stepped(|_| StepControl::Abort)?;
+    assert!(!completed);
+    assert_eq!(lua.globals().get::<_, i64>("result")?, 0);
+
+    // Script errors are propagated
+    match lua
+        .load("error('boom')")
+        .exec_stepped(|_| StepControl::Continue)
+    {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("boom")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // The previous hook is restored
+    lua.load("x = 1").exec_stepped(|_| StepControl::Continue)?;
+    let mut count = 0;
+    lua.load("x = 1").exec_stepped(|_| {
+        count += 1;
+        StepControl::Continue
+    })?;
+    assert_eq!(count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_exec_with_budget() -> Result<()> {
+    let lua = Lua::new();
+
+    let hits = Arc::new(AtomicI64::new(0));
+    let hits2 = hits.clone();
+    lua.set_hook(HookTriggers::ON_CALLS, move |_, _| {
+        hits2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    })?;
+
+    let chunk = "local x = 0 for i = 1, 100 do x = x + i end";
+    lua.load(chunk).exec_with_budget(10_000)?;
+    match lua.load(chunk).exec_with_budget(50) {
+        Err(Error::InstructionLimitExceeded(50)) => {}
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+
+    // The script cannot catch the error to keep running
+    let chunk = "while true do pcall(function() while true do end end) end";
+    match lua.load(chunk).exec_with_budget(100_000) {
+        Err(Error::InstructionLimitExceeded(100_000)) => {}
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+
+    // Coroutines created by the chunk are limited too
+    for chunk in [
+        "coroutine.wrap(function() while true do end end)()",
+        "local co = coroutine.create(function() while true do end end) coroutine.resume(co)",
+        "while true do coroutine.resume(coroutine.create(function() end)) end",
+    ] {
+        match lua.load(chunk).exec_with_budget(10_000) {
+            Err(Error::InstructionLimitExceeded(10_000)) => {}
+            r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+        }
+    }
+
+    // The budget applies when the chunk is run from a coroutine
+    let run =
+        lua.create_function(|lua, chunk: String| lua.load(&chunk).exec_with_budget(10_000))?;
+    let thread = lua.create_thread(run)?;
+    match thread.resume::<_, ()>("while true do end") {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(*cause, Error::InstructionLimitExceeded(10_000)))
+        }
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+
+    // The budget is reset between calls and the previous hook is restored
+    lua.load("local x = 1").exec_with_budget(10)?;
+    hits.store(0, Ordering::Relaxed);
+    lua.load("(function() end)()").exec()?;
+    assert!(hits.load(Ordering::Relaxed) > 0);
+
+    Ok(())
+}
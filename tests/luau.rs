@@ -155,6 +155,7 @@ fn test_readonly_table() -> Result<()> {
     check_readonly_error(t.pop::<Value>());
     check_readonly_error(t.raw_push("value"));
     check_readonly_error(t.raw_pop::<Value>());
+    check_readonly_error(t.try_set_metatable(None));
 
     // Special case
     match catch_unwind(AssertUnwindSafe(|| t.set_metatable(None))) {
@@ -165,6 +166,31 @@ fn test_readonly_table() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_deep_freeze() -> Result<()> {
+    let lua = Lua::new();
+
+    let t = lua
+        .load("local t = { a = { b = {} } }; t.a.parent = t; t[{}] = 1; return t")
+        .eval::<Table>()?;
+    t.deep_freeze()?;
+
+    assert!(t.is_readonly());
+    let a = t.get::<_, Table>("a")?;
+    assert!(a.is_readonly());
+    assert!(a.get::<_, Table>("b")?.is_readonly());
+    for pair in t.clone().pairs::<Value, Value>() {
+        if let (Value::Table(key), _) = pair? {
+            assert!(key.is_readonly());
+        }
+    }
+
+    lua.globals().set("t", t)?;
+    assert!(lua.load("t.a.b.c = 1").exec().is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_sandbox() -> Result<()> {
     let lua = Lua::new();
@@ -223,6 +249,42 @@ fn test_sandbox_threads() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_instruction_count() -> Result<()> {
+    let lua = Lua::new();
+    let code = "local n = 0; for i = 1, 1000 do n += i end";
+
+    lua.load(code).exec()?;
+    assert_eq!(lua.instruction_count(), 0);
+
+    // Interrupt checks are counted in any thread, together with an interrupt callback
+    let interrupts = Arc::new(AtomicU64::new(0));
+    let interrupts2 = interrupts.clone();
+    lua.set_interrupt(move |_| {
+        interrupts2.fetch_add(1, Ordering::Relaxed);
+        Ok(VmState::Continue)
+    });
+    let guard = lua.count_instructions(1)?;
+    lua.load(code).exec()?;
+    let count = lua.reset_instruction_count();
+    assert!(count >= 1000);
+    lua.load(&format!("coroutine.wrap(function() {code} end)()"))
+        .exec()?;
+    assert!(lua.reset_instruction_count() >= count);
+    assert!(interrupts.load(Ordering::Relaxed) >= 2 * count);
+
+    // Removing the callback keeps counting, dropping the guard keeps the callback
+    lua.remove_interrupt();
+    lua.load(code).exec()?;
+    assert!(lua.reset_instruction_count() >= 1000);
+    lua.set_interrupt(move |_| Ok(VmState::Continue));
+    drop(guard);
+    lua.load(code).exec()?;
+    assert_eq!(lua.instruction_count(), 0);
+
+    Ok(())
+}
+
 #[test]
 fn test_interrupts() -> Result<()> {
     let lua = Lua::new();
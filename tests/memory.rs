@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use mlua::{Error, GCMode, Lua, Result, UserData};
 
@@ -28,7 +29,7 @@ fn test_memory_limit() -> Result<()> {
 
     lua.set_memory_limit(initial_memory + 10000)?;
     match f.call::<_, ()>(()) {
-        Err(Error::MemoryError(_)) => {}
+        Err(Error::MemoryLimitExceeded(_)) => {}
         something_else => panic!("did not trigger memory error: {:?}", something_else),
     };
 
@@ -54,7 +55,7 @@ fn test_memory_limit_thread() -> Result<()> {
     lua.set_memory_limit(lua.used_memory() + 10000)?;
     let thread = lua.create_thread(f)?;
     match thread.resume::<_, ()>(()) {
-        Err(Error::MemoryError(_)) => {}
+        Err(Error::MemoryLimitExceeded(_)) => {}
         something_else => panic!("did not trigger memory error: {:?}", something_else),
     };
 
@@ -103,6 +104,55 @@ fn test_gc_control() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_gc_stats() -> Result<()> {
+    let lua = Lua::new();
+    assert_eq!(lua.gc_stats().steps, 0);
+    assert_eq!(lua.gc_stats().collections, 0);
+
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(lua.gc_stats().collections, 2);
+
+    lua.gc_stop();
+    let mut finished = false;
+    for _ in 0..1000 {
+        if lua.gc_step()? {
+            finished = true;
+            break;
+        }
+    }
+    assert!(finished);
+    let stats = lua.gc_stats();
+    assert!(stats.steps >= 1);
+    assert_eq!(stats.collections, 3);
+
+    let _data = lua.create_string(vec![0; 1024 * 1024])?;
+    assert!(lua.gc_stats().allocated_bytes >= stats.allocated_bytes + 1024 * 1024);
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_collect_incremental() -> Result<()> {
+    let lua = Lua::new();
+    lua.gc_stop();
+
+    lua.load("data = {} for i = 1, 100000 do data[i] = {i} end")
+        .exec()?;
+    lua.load("data = nil").exec()?;
+    let used = lua.used_memory();
+
+    while !lua.gc_collect_incremental(Duration::from_micros(100))? {}
+    assert_eq!(lua.gc_stats().collections, 1);
+
+    // Finish the next cycle to collect everything unreachable
+    while !lua.gc_collect_incremental(Duration::from_millis(10))? {}
+    assert!(lua.used_memory() < used / 2);
+
+    Ok(())
+}
+
 #[cfg(any(feature = "lua53", feature = "lua52"))]
 #[test]
 fn test_gc_error() {
@@ -0,0 +1,245 @@
+#![cfg(feature = "plugin")]
+
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::{ptr, slice};
+
+use mlua::plugin::{FfiCall, FfiMethod, FfiUserDataType, FfiValue, PluginApi, PLUGIN_ABI_VERSION};
+use mlua::{Error, Lua, Result};
+
+static COUNTER_TYPE: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe fn args<'a>(call: *const FfiCall) -> &'a [FfiValue] {
+    slice::from_raw_parts((*call).args, (*call).nargs)
+}
+
+unsafe fn push(call: *const FfiCall, value: FfiValue) {
+    ((*call).push_return)((*call).ret, &value);
+}
+
+unsafe fn fail(call: *const FfiCall, msg: &str) -> c_int {
+    push(
+        call,
+        FfiValue::String {
+            ptr: msg.as_ptr(),
+            len: msg.len(),
+        },
+    );
+    1
+}
+
+unsafe extern "C" fn new_counter(call: *const FfiCall, _: *mut c_void) -> c_int {
+    let start = match args(call) {
+        [FfiValue::Integer(start)] => *start,
+        _ => return fail(call, "integer expected"),
+    };
+    let data = Box::into_raw(Box::new(start)) as *mut c_void;
+    let type_id = COUNTER_TYPE.load(Ordering::Relaxed);
+    push(call, FfiValue::NewUserData { type_id, data });
+    0
+}
+
+// Returns an unregistered userdata type followed by a valid counter
+unsafe extern "C" fn bad_counters(call: *const FfiCall, _: *mut c_void) -> c_int {
+    push(
+        call,
+        FfiValue::NewUserData {
+            type_id: 999,
+            data: ptr::null_mut(),
+        },
+    );
+    let data = Box::into_raw(Box::new(0i64)) as *mut c_void;
+    let type_id = COUNTER_TYPE.load(Ordering::Relaxed);
+    push(call, FfiValue::NewUserData { type_id, data });
+    0
+}
+
+unsafe extern "C" fn counter_add(call: *const FfiCall, _: *mut c_void) -> c_int {
+    match args(call) {
+        [FfiValue::UserData { type_id, data }, FfiValue::Integer(n)]
+            if *type_id == COUNTER_TYPE.load(Ordering::Relaxed) =>
+        {
+            *(*data as *mut i64) += n;
+            0
+        }
+        _ => fail(call, "bad arguments to 'add'"),
+    }
+}
+
+unsafe extern "C" fn counter_get(call: *const FfiCall, _: *mut c_void) -> c_int {
+    match args(call) {
+        [FfiValue::UserData { data, .. }] => {
+            push(call, FfiValue::Integer(*(*data as *mut i64)));
+            0
+        }
+        _ => fail(call, "bad arguments to 'get'"),
+    }
+}
+
+unsafe extern "C" fn counter_drop(data: *mut c_void) {
+    drop(Box::from_raw(data as *mut i64));
+    DROPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+unsafe extern "C" fn greet(call: *const FfiCall, data: *mut c_void) -> c_int {
+    let prefix = &*(data as *const &str);
+    match args(call) {
+        [FfiValue::String { ptr, len }] => {
+            let name = String::from_utf8_lossy(slice::from_raw_parts(*ptr, *len));
+            let greeting = format!("{prefix}, {name}!");
+            push(
+                call,
+                FfiValue::String {
+                    ptr: greeting.as_ptr(),
+                    len: greeting.len(),
+                },
+            );
+            0
+        }
+        _ => fail(call, "string expected"),
+    }
+}
+
+static GREETING: &str = "Hello";
+
+unsafe extern "C" fn plugin_entry(api: *const PluginApi) -> c_int {
+    let api = &*api;
+    if api.abi_version != PLUGIN_ABI_VERSION {
+        return 1;
+    }
+
+    let methods = [
+        FfiMethod {
+            name: "add".as_ptr(),
+            name_len: 3,
+            func: counter_add,
+            data: ptr::null_mut(),
+        },
+        FfiMethod {
+            name: "get".as_ptr(),
+            name_len: 3,
+            func: counter_get,
+            data: ptr::null_mut(),
+        },
+    ];
+    let counter = FfiUserDataType {
+        name: "Counter".as_ptr(),
+        name_len: 7,
+        methods: methods.as_ptr(),
+        methods_len: methods.len(),
+        drop: Some(counter_drop),
+    };
+    let type_id = (api.register_userdata_type)(api.ctx, &counter);
+    if type_id == 0 {
+        return 2;
+    }
+    COUNTER_TYPE.store(type_id, Ordering::Relaxed);
+
+    let name = "new_counter";
+    if (api.register_function)(
+        api.ctx,
+        name.as_ptr(),
+        name.len(),
+        new_counter,
+        ptr::null_mut(),
+    ) != 0
+    {
+        return 3;
+    }
+    let name = "bad_counters";
+    if (api.register_function)(
+        api.ctx,
+        name.as_ptr(),
+        name.len(),
+        bad_counters,
+        ptr::null_mut(),
+    ) != 0
+    {
+        return 4;
+    }
+    let name = "greet";
+    let data = &GREETING as *const &str as *mut c_void;
+    (api.register_function)(api.ctx, name.as_ptr(), name.len(), greet, data)
+}
+
+unsafe extern "C" fn failing_entry(_: *const PluginApi) -> c_int {
+    42
+}
+
+#[test]
+fn test_plugin() -> Result<()> {
+    let lua = Lua::new();
+
+    let plugin = unsafe { lua.load_plugin(plugin_entry)? };
+    lua.globals().set("plugin", plugin)?;
+
+    assert_eq!(
+        lua.load(r#"plugin.greet("plugin")"#).eval::<String>()?,
+        "Hello, plugin!"
+    );
+
+    lua.load(
+        r#"
+        local counter = plugin.new_counter(10)
+        counter:add(5)
+        counter:add(-3)
+        assert(counter:get() == 12)
+        assert(tostring(counter):find("^Counter: "))
+    "#,
+    )
+    .exec()?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+
+    // Values returned after a failed one are dropped
+    match lua.load("plugin.bad_counters()").exec() {
+        Err(Error::CallbackError { cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => assert!(msg.contains("unknown plugin userdata type")),
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+
+    // Errors reported by plugin functions
+    match lua.load("plugin.greet(1)").exec() {
+        Err(Error::CallbackError { cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => assert_eq!(msg, "string expected"),
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    // Unsupported values
+    match lua.load("plugin.greet({})").exec() {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(
+                cause.as_ref(),
+                Error::FromLuaConversionError { .. }
+            ))
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // Methods check the type of `self`
+    match lua
+        .load("local c = plugin.new_counter(1); c.get('x')")
+        .exec()
+    {
+        Err(Error::CallbackError { cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { to, pos: 1, .. } => {
+                assert_eq!(to.as_deref(), Some("Counter.get"))
+            }
+            err => panic!("expected BadArgument, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    match unsafe { lua.load_plugin(failing_entry) } {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("code 42")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    Ok(())
+}
@@ -4,8 +4,8 @@ use std::collections::HashMap;
 use std::error::Error as StdError;
 
 use mlua::{
-    DeserializeOptions, Error, Lua, LuaSerdeExt, Result as LuaResult, SerializeOptions, UserData,
-    Value,
+    ArrayFormat, ConversionLimits, DeserializeOptions, Error, ErrorContext, Lua, LuaSerdeExt,
+    PortableError, Result as LuaResult, SerdePolicy, SerializeOptions, UserData, Value,
 };
 use serde::{Deserialize, Serialize};
 
@@ -325,6 +325,29 @@ fn test_to_value_with_options() -> Result<(), Box<dyn StdError>> {
     )
     .exec()?;
 
+    // dense_integer_keys
+    let map: std::collections::BTreeMap<i64, &str> = [(1, "a"), (2, "b"), (3, "c")].into();
+    let options = SerializeOptions::new().dense_integer_keys(true);
+    let data4 = lua.unpack::<mlua::Table>(lua.to_value_with(&map, options)?)?;
+    assert_eq!(data4.raw_len(), 3);
+    assert_eq!(data4.get_metatable(), Some(lua.array_metatable()));
+    let sparse: std::collections::BTreeMap<i64, &str> = [(1, "a"), (3, "c")].into();
+    let data5 = lua.unpack::<mlua::Table>(lua.to_value_with(&sparse, options)?)?;
+    assert_eq!(data5.get::<_, Option<String>>(2)?, None);
+    assert_eq!(data5.get::<_, String>(3)?, "c");
+    assert_eq!(data5.get_metatable(), None);
+    let data6 = lua.unpack::<mlua::Table>(lua.to_value(&map)?)?;
+    assert_eq!(data6.get_metatable(), None);
+
+    // preserve_integers
+    let json = serde_json::json!({"int": 1, "float": 1.5});
+    let table = lua.unpack::<mlua::Table>(lua.to_value(&json)?)?;
+    assert_eq!(table.get::<_, Value>("int")?, Value::Integer(1));
+    assert_eq!(table.get::<_, Value>("float")?, Value::Number(1.5));
+    let options = SerializeOptions::new().preserve_integers(false);
+    let table = lua.unpack::<mlua::Table>(lua.to_value_with(&json, options)?)?;
+    assert_eq!(table.get::<_, Value>("int")?, Value::Number(1.0));
+
     Ok(())
 }
 
@@ -553,6 +576,195 @@ fn test_from_value_with_options() -> Result<(), Box<dyn StdError>> {
     Ok(())
 }
 
+#[test]
+fn test_from_value_option_options() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    lua.globals().set("null", lua.null())?;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        name: String,
+        extra: Option<serde_json::Value>,
+    }
+
+    // Nil/null distinction
+    let null_value = lua.load("{name = 'a', extra = null}").eval::<Value>()?;
+    let config: Config = lua.from_value(null_value.clone())?;
+    assert_eq!(config.extra, None);
+    let options = DeserializeOptions::new().null_as_none(false);
+    let config: Config = lua.from_value_with(null_value.clone(), options)?;
+    assert_eq!(config.extra, Some(serde_json::Value::Null));
+    let config: Config = lua.from_value_with(lua.load("{name = 'a'}").eval()?, options)?;
+    assert_eq!(config.extra, None);
+
+    // Empty tables
+    let empty_value = lua.load("{name = 'a', extra = {}}").eval::<Value>()?;
+    let config: Config = lua.from_value(empty_value.clone())?;
+    assert!(config.extra.is_some());
+    let options = DeserializeOptions::new().empty_table_as_none(true);
+    let config: Config = lua.from_value_with(empty_value, options)?;
+    assert_eq!(config.extra, None);
+
+    // Missing fields
+    let options = DeserializeOptions::new().deny_missing_fields(true);
+    match lua.from_value_with::<Config>(lua.load("{name = 'a'}").eval()?, options) {
+        Err(Error::DeserializeError(err)) => assert_eq!(err, "missing field `extra`"),
+        r => panic!("expected DeserializeError, got {r:?}"),
+    }
+    assert!(lua.from_value_with::<Config>(null_value, options).is_ok());
+
+    // Unknown fields
+    let value = lua.load("{name = 'a', nmae = 'b'}").eval::<Value>()?;
+    assert!(lua.from_value::<Config>(value.clone()).is_ok());
+    let options = DeserializeOptions::new().deny_unknown_fields(true);
+    match lua.from_value_with::<Config>(value, options) {
+        Err(Error::DeserializeError(err)) => assert!(err.contains("unknown field `nmae`")),
+        r => panic!("expected DeserializeError, got {r:?}"),
+    }
+    let value = lua.load("{name = 'a', [1] = true}").eval::<Value>()?;
+    assert!(lua.from_value_with::<Config>(value, options).is_err());
+
+    // Depth limit
+    let value = lua.load("{{{{}}}}").eval::<Value>()?;
+    let options = DeserializeOptions::new().max_depth(Some(3));
+    match lua.from_value_with::<serde_json::Value>(value.clone(), options) {
+        Err(Error::DeserializeError(err)) => {
+            assert_eq!(err, "maximum nesting depth (3) exceeded")
+        }
+        r => panic!("expected DeserializeError, got {r:?}"),
+    }
+    let options = DeserializeOptions::new().max_depth(Some(4));
+    assert!(lua
+        .from_value_with::<serde_json::Value>(value, options)
+        .is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_serde_policy() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+
+    let ids = lua
+        .load("{[1] = 'a', [2] = 'b', [7] = 'c'}")
+        .eval::<Value>()?;
+    let sparse = lua.load("{[1] = 'a', [3] = 'c'}").eval::<Value>()?;
+
+    // Legacy behavior uses the table border
+    let value = lua.from_value::<serde_json::Value>(ids.clone())?;
+    assert_eq!(value, serde_json::json!(["a", "b"]));
+
+    // Integer keys are preserved
+    let options =
+        DeserializeOptions::new().policy(SerdePolicy::new().array_format(ArrayFormat::Strict));
+    let map = lua.from_value_with::<HashMap<i64, String>>(ids.clone(), options)?;
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&7], "c");
+    assert_eq!(
+        lua.from_value_with::<Vec<String>>(lua.load("{'x', 'y'}").eval()?, options)?,
+        vec!["x", "y"]
+    );
+
+    // Round-trip through a format supporting only string keys
+    let policy = SerdePolicy::string_keys();
+    let options = DeserializeOptions::new().policy(policy);
+    let json = lua.from_value_with::<serde_json::Value>(ids.clone(), options)?;
+    assert_eq!(json, serde_json::json!({"1": "a", "2": "b", "7": "c"}));
+    let value = lua.to_value_with(&json, SerializeOptions::new().policy(policy))?;
+    let table = lua.unpack::<mlua::Table>(value)?;
+    assert_eq!(table.raw_get::<_, String>(7)?, "c");
+    assert_eq!(table.raw_get::<_, String>(1)?, "a");
+    assert_eq!(table.clone().pairs::<Value, Value>().count(), 3);
+    // Non-canonical integers stay strings
+    let value = lua.to_value_with(
+        &serde_json::json!({"007": true, "-5": true}),
+        SerializeOptions::new().policy(policy),
+    )?;
+    let table = lua.unpack::<mlua::Table>(value)?;
+    assert!(table.raw_get::<_, bool>("007")?);
+    assert!(table.raw_get::<_, bool>(-5)?);
+
+    // Sparse arrays
+    let policy = SerdePolicy::new().array_format(ArrayFormat::PreserveSparse { max_holes: 1 });
+    let options = DeserializeOptions::new().policy(policy);
+    let value = lua.from_value_with::<serde_json::Value>(sparse.clone(), options)?;
+    assert_eq!(value, serde_json::json!(["a", null, "c"]));
+    let value = lua.from_value_with::<Vec<Option<String>>>(sparse, options)?;
+    assert_eq!(value, vec![Some("a".into()), None, Some("c".into())]);
+    // Too many holes
+    let value = lua.from_value_with::<HashMap<i64, String>>(ids, options)?;
+    assert_eq!(value.len(), 3);
+
+    // Float keys with integer values
+    let value = lua.load("{[2^53] = 'big'}").eval::<Value>()?;
+    let map = lua.from_value::<HashMap<i64, String>>(value)?;
+    assert_eq!(map[&(1 << 53)], "big");
+
+    Ok(())
+}
+
+#[test]
+fn test_from_value_ref() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Item<'a> {
+        name: &'a str,
+        data: &'a [u8],
+        tags: Vec<&'a str>,
+    }
+
+    let value: Value = lua
+        .load(r#"{name = "item", data = "\255\0", tags = {"a", "b"}}"#)
+        .eval()?;
+    let item: Item = lua.from_value_ref(&value)?;
+    assert_eq!(
+        item,
+        Item {
+            name: "item",
+            data: b"\xff\x00",
+            tags: vec!["a", "b"],
+        }
+    );
+
+    // Borrowed strings stay valid after removing them from the table
+    let table = match &value {
+        Value::Table(table) => table,
+        _ => unreachable!(),
+    };
+    table.set("name", Value::Nil)?;
+    table.set("tags", Value::Nil)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(item.name, "item");
+    assert_eq!(item.tags, ["a", "b"]);
+
+    // Map keys and root strings
+    let value: Value = lua.load(r#"{key = "value"}"#).eval()?;
+    let map: HashMap<&str, &str> = lua.from_value_ref(&value)?;
+    assert_eq!(map["key"], "value");
+    let value = Value::String(lua.create_string("root")?);
+    assert_eq!(lua.from_value_ref::<&str>(&value)?, "root");
+
+    // Owned strings are copied
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Mixed<'a> {
+        name: &'a str,
+        owner: String,
+        initial: char,
+    }
+    let value: Value = lua
+        .load(r#"{name = "item", owner = "me", initial = "m"}"#)
+        .eval()?;
+    let mixed: Mixed = lua.from_value_ref(&value)?;
+    assert_eq!(
+        (mixed.name, mixed.owner.as_str(), mixed.initial),
+        ("item", "me", 'm')
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_from_value_userdata() -> Result<(), Box<dyn StdError>> {
     let lua = Lua::new();
@@ -611,3 +823,192 @@ fn test_from_value_userdata() -> Result<(), Box<dyn StdError>> {
 
     Ok(())
 }
+
+#[test]
+fn test_serialize_max_depth() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+    lua.set_max_conversion_depth(Some(200));
+
+    // Deeply nested values fail cleanly instead of overflowing the stack
+    let value = (0..1000).fold(serde_json::json!([]), |inner, _| serde_json::json!([inner]));
+    match lua.to_value(&value) {
+        Err(Error::ConversionDepthExceeded(200)) => {}
+        r => panic!("expected ConversionDepthExceeded, got {r:?}"),
+    }
+    let value = (1..200).fold(
+        serde_json::json!({}),
+        |inner, _| serde_json::json!({ "a": inner }),
+    );
+    lua.to_value(&value)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_limits() -> LuaResult<()> {
+    let lua = Lua::new();
+    lua.set_conversion_limits(
+        ConversionLimits::new()
+            .max_depth(2)
+            .max_table_entries(3)
+            .max_string_len(4),
+    );
+
+    let value = lua.load("{ a = { 1, 2, 3 }, b = 'abcd' }").eval()?;
+    lua.from_value::<serde_json::Value>(value)?;
+
+    let value = lua.load("{ a = { { 1 } } }").eval()?;
+    match lua.from_value::<serde_json::Value>(value) {
+        Err(Error::ConversionDepthExceeded(2)) => {}
+        r => panic!("expected ConversionDepthExceeded, got {r:?}"),
+    }
+
+    for (code, message) in [
+        ("{ 1, 2, 3, 4 }", "table has more than 3 entries"),
+        (
+            "{ a = 1, b = 2, c = 3, d = 4 }",
+            "table has more than 3 entries",
+        ),
+        ("{ 'abcde' }", "string is longer than 4 bytes"),
+    ] {
+        let value = lua.load(code).eval()?;
+        match lua.from_value::<serde_json::Value>(value) {
+            Err(Error::DeserializeError(msg)) => assert_eq!(msg, message),
+            r => panic!("expected DeserializeError, got {r:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_portable_error() -> Result<(), Box<dyn StdError>> {
+    let lua = Lua::new();
+
+    let func = lua.create_function(|_, ()| -> LuaResult<()> {
+        let err = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+        Err(Error::external(err).context("saving state"))
+    })?;
+    lua.globals().set("save", func)?;
+    let err = lua
+        .load("local function run() save() end run()")
+        .exec()
+        .unwrap_err();
+
+    let json = serde_json::to_string(&err.to_portable())?;
+    let portable: PortableError = serde_json::from_str(&json)?;
+    assert_eq!(portable, err.to_portable());
+    assert_eq!(portable.kind, "CallbackError");
+    assert!(portable
+        .traceback
+        .iter()
+        .any(|f| f.contains("in function 'save'")));
+    let context = portable.cause.as_deref().unwrap();
+    assert_eq!(
+        (context.kind.as_str(), context.message.as_str()),
+        ("WithContext", "saving state")
+    );
+    let external = context.cause.as_deref().unwrap();
+    assert_eq!(
+        (external.kind.as_str(), external.message.as_str()),
+        ("ExternalError", "disk full")
+    );
+
+    // Structure is restored
+    let restored = Error::from_portable(portable);
+    match restored {
+        Error::CallbackError {
+            ref traceback,
+            ref cause,
+        } => {
+            assert!(traceback.starts_with("stack traceback:\n\t"));
+            assert!(
+                matches!(**cause, Error::WithContext { ref context, .. } if context == "saving state")
+            );
+        }
+        ref err => panic!("expected CallbackError, got {err:?}"),
+    }
+    assert_eq!(restored.to_string(), err.to_string());
+    assert_eq!(restored.to_portable(), err.to_portable());
+
+    // Errors which cannot be restored are wrapped
+    let err = Error::ToLuaConversionError {
+        from: "Foo",
+        to: "table",
+        message: None,
+    };
+    let restored = Error::from_portable(err.to_portable());
+    let portable = restored.downcast_ref::<PortableError>().unwrap();
+    assert_eq!(portable.kind, "ToLuaConversionError");
+    assert_eq!(restored.to_string(), "error converting Foo to Lua table");
+
+    Ok(())
+}
+
+// Serializes the error through json and restores it
+fn portable_roundtrip(err: &Error) -> Result<(PortableError, Error), Box<dyn StdError>> {
+    let json = serde_json::to_string(&err.to_portable())?;
+    let portable: PortableError = serde_json::from_str(&json)?;
+    let restored = Error::from_portable(portable.clone());
+    assert_eq!(restored.to_string(), err.to_string());
+    Ok((portable, restored))
+}
+
+#[test]
+fn test_portable_error_memory_limit() -> Result<(), Box<dyn StdError>> {
+    let (portable, restored) = portable_roundtrip(&Error::MemoryLimitExceeded(1024))?;
+    assert_eq!(portable.limit, Some(1024));
+    assert!(matches!(restored, Error::MemoryLimitExceeded(1024)));
+    Ok(())
+}
+
+#[test]
+fn test_portable_error_instruction_limit() -> Result<(), Box<dyn StdError>> {
+    let (portable, restored) = portable_roundtrip(&Error::InstructionLimitExceeded(u64::MAX))?;
+    assert_eq!(portable.limit, Some(u64::MAX));
+    assert!(matches!(
+        restored,
+        Error::InstructionLimitExceeded(u64::MAX)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_portable_error_conversion_depth() -> Result<(), Box<dyn StdError>> {
+    let (portable, restored) = portable_roundtrip(&Error::ConversionDepthExceeded(128))?;
+    assert_eq!(portable.limit, Some(128));
+    assert!(matches!(restored, Error::ConversionDepthExceeded(128)));
+    Ok(())
+}
+
+#[test]
+fn test_portable_error_wrong_arity() -> Result<(), Box<dyn StdError>> {
+    let err = Error::WrongArity {
+        expected: 2,
+        got: 3,
+        name: Some("add".into()),
+    };
+    let (portable, restored) = portable_roundtrip(&err)?;
+    assert_eq!(
+        (
+            portable.expected,
+            portable.got,
+            portable.function.as_deref()
+        ),
+        (Some(2), Some(3), Some("add"))
+    );
+    match restored {
+        Error::WrongArity {
+            expected: 2,
+            got: 3,
+            ref name,
+        } => assert_eq!(name.as_deref(), Some("add")),
+        ref err => panic!("expected WrongArity, got {err:?}"),
+    }
+
+    // Without structured fields (eg. serialized by an older version) the error is wrapped
+    let json = r#"{"kind":"WrongArity","message":"wrong number of arguments","traceback":[],"cause":null}"#;
+    let restored = Error::from_portable(serde_json::from_str(json)?);
+    assert!(restored.downcast_ref::<PortableError>().is_some());
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
 
-use mlua::{Lua, Result, String};
+use mlua::{Error, Lua, Result, String};
 
 #[test]
 fn test_string_compare() {
@@ -117,3 +117,133 @@ fn test_owned_string() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_string_pack_unpack() -> Result<()> {
+    let lua = Lua::new();
+
+    let bytes = lua.string_pack(">I2 i3 z s1 d", (0x1234, -2, "hi", "abc", 1.5))?;
+    let (a, b, c, d, e): (u16, i32, std::string::String, std::string::String, f64) =
+        lua.string_unpack(">I2 i3 z s1 d", &bytes)?;
+    assert_eq!(
+        (a, b, c, d, e),
+        (0x1234, -2, "hi".into(), "abc".into(), 1.5)
+    );
+    assert_eq!(&bytes[..5], b"\x12\x34\xff\xff\xfe");
+
+    // Alignment
+    let bytes = lua.string_pack("<!4 b i4", (1, 2))?;
+    assert_eq!(bytes, b"\x01\0\0\0\x02\0\0\0");
+
+    // Errors
+    assert!(lua.string_pack("b", 200).is_err());
+    assert!(lua.string_pack("q", ()).is_err());
+    assert!(lua.string_unpack::<i32>("i4", b"\0\0").is_err());
+
+    // The result size is checked before allocating
+    for fmt in ["c4000000000", "i8 c2147483640"] {
+        match lua.string_pack(fmt, (1, "")) {
+            Err(Error::BadArgument { pos: 1, cause, .. }) => {
+                assert_eq!(cause.to_string(), "runtime error: result too large")
+            }
+            r => panic!("expected BadArgument, got {r:?}"),
+        }
+    }
+
+    // Numbers with a fractional part are not truncated
+    match lua.string_pack("i4", 2.5) {
+        Err(Error::BadArgument { pos: 2, cause, .. }) => assert_eq!(
+            cause.to_string(),
+            "runtime error: number has no integer representation"
+        ),
+        r => panic!("expected BadArgument, got {r:?}"),
+    }
+
+    // Available to scripts on every backend
+    let (n, s, next): (i32, std::string::String, i64) = lua
+        .load(r#"string.unpack("<i4z", string.pack("<i4z", 7, "abc"))"#)
+        .eval()?;
+    assert_eq!((n, s.as_str(), next), (7, "abc", 9));
+    let (n, next): (i32, i64) = lua.load(r#"string.unpack("<i2", "xx\7\0", -2)"#).eval()?;
+    assert_eq!((n, next), (7, 5));
+
+    // Compatible with the builtin implementation
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    {
+        let fmt = "<!8 j b h T d z c5 s2 x Xi8 J";
+        let args = (-7, 3, -300, 1_000_000, 3.25, "zstr", "ab", "s2", 5);
+        let packed = lua.string_pack(fmt, args)?;
+        let native = lua
+            .load("return string.pack(...)")
+            .call::<_, String>((fmt, -7, 3, -300, 1_000_000, 3.25, "zstr", "ab", "s2", 5))?;
+        assert_eq!(native, packed);
+        let values = lua.string_unpack::<mlua::MultiValue>(fmt, &packed)?;
+        let native_values = lua
+            .load("local t = table.pack(string.unpack(...)); return table.unpack(t, 1, t.n - 1)")
+            .call::<_, mlua::MultiValue>((fmt, native))?;
+        assert_eq!(values.len(), native_values.len());
+        for (v1, v2) in values.iter().zip(native_values.iter()) {
+            assert!(v1.equals(v2)?);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_string_builder() -> Result<()> {
+    let lua = Lua::new();
+
+    let builder = lua.create_string_builder()?;
+    builder.append("rust;")?;
+    lua.globals().set("builder", builder.clone())?;
+    lua.load(
+        r#"
+        for i = 1, 3 do
+            builder:append("n", i, ";")
+        end
+        assert(builder:append("x"):len() == 15)
+        assert(#builder == 15)
+        assert(tostring(builder) == builder:build())
+    "#,
+    )
+    .exec()?;
+
+    assert_eq!(builder.len()?, 15);
+    assert_eq!(builder.build()?, "rust;n1;n2;n3;x");
+    assert_eq!(builder.take()?, b"rust;n1;n2;n3;x");
+    assert!(builder.is_empty()?);
+    assert_eq!(lua.load("builder:build()").eval::<String>()?, "");
+
+    builder.append("a")?;
+    lua.load("builder:clear()").exec()?;
+    assert!(builder.is_empty()?);
+
+    // Only strings and numbers can be appended
+    assert!(lua.load("builder:append({})").exec().is_err());
+
+    // Conversion from other userdata fails
+    let other = lua.create_any_userdata(0i32)?;
+    assert!(lua
+        .unpack::<mlua::StringBuilder>(mlua::Value::UserData(other))
+        .is_err());
+
+    // The buffer counts towards the memory limit
+    assert!(lua.used_memory() < 1 << 20);
+    builder.append(vec![b'x'; 1 << 20])?;
+    assert!(lua.used_memory() > 1 << 20);
+    lua.set_memory_limit(lua.used_memory() + (1 << 20))?;
+    match lua
+        .load("for i = 1, 64 do builder:append(string.rep('x', 65536)) end")
+        .exec()
+    {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(*cause, Error::MemoryLimitExceeded(_)))
+        }
+        r => panic!("expected MemoryLimitExceeded, got {r:?}"),
+    }
+    assert!(builder.take()?.len() >= 1 << 20);
+    assert!(lua.used_memory() < 1 << 20);
+
+    Ok(())
+}
@@ -1,4 +1,10 @@
-use mlua::{Error, Lua, Nil, Result, Table, TableExt, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+
+use mlua::{
+    ConversionLimits, Error, FromLua, HashOptions, LenMode, Lua, MetaMethod, Nil, Result, Table,
+    TableExt, ToStringMode, Value,
+};
 
 #[test]
 fn test_globals_set_get() -> Result<()> {
@@ -204,6 +210,298 @@ fn test_table_clear() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_table_clear_incremental() -> Result<()> {
+    let lua = Lua::new();
+
+    let t: Table = lua
+        .load(
+            r#"
+        local t = setmetatable({}, { __newindex = function() error("newindex error") end })
+        for i = 1, 100 do rawset(t, i, i) end
+        for i = 1, 100 do rawset(t, "k" .. i, i) end
+        return t
+    "#,
+        )
+        .eval()?;
+
+    // Zero budget only checks whether the table is empty
+    assert!(!t.clear_incremental(0)?);
+    assert_eq!(t.raw_len(), 100);
+
+    assert!(!t.clear_incremental(50)?);
+    assert_eq!(t.clone().pairs::<Value, Value>().count(), 150);
+
+    // Modify the table between calls
+    for pair in t.clone().pairs::<Value, Value>().take(10) {
+        t.raw_set(pair?.0, Nil)?;
+    }
+    t.raw_set("new", true)?;
+
+    let mut calls = 0;
+    while !t.clear_incremental(50)? {
+        calls += 1;
+        assert!(calls < 10);
+    }
+    assert!(t.is_empty());
+    assert_eq!(t.clone().pairs::<Value, Value>().count(), 0);
+    assert_ne!(t.get_metatable(), None);
+
+    // Empty tables are cleared right away
+    assert!(lua.create_table()?.clear_incremental(10)?);
+
+    #[cfg(feature = "luau")]
+    {
+        let t = lua.create_table()?;
+        t.set_readonly(true);
+        assert!(t.clear_incremental(10).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_table_class() -> Result<()> {
+    let lua = Lua::new();
+
+    let class = lua.register_table_class("Point", |class| {
+        class.add_field("dims", 2);
+        class.add_method("move_by", |_, this, (dx, dy): (i64, i64)| {
+            this.set("x", this.get::<_, i64>("x")? + dx)?;
+            this.set("y", this.get::<_, i64>("y")? + dy)?;
+            Ok(this)
+        });
+        class.add_function("origin", |lua, ()| {
+            lua.new_class_instance("Point", [("x", 0), ("y", 0)])
+        });
+        class.add_meta_function(MetaMethod::Add, |lua, (a, b): (Table, Table)| {
+            let x = a.get::<_, i64>("x")? + b.get::<_, i64>("x")?;
+            let y = a.get::<_, i64>("y")? + b.get::<_, i64>("y")?;
+            lua.new_class_instance("Point", [("x", x), ("y", y)])
+        });
+        class.add_meta_method(MetaMethod::Index, |_, _, key: String| {
+            Ok(format!("missing {key}"))
+        });
+    })?;
+
+    let p = lua.new_class_instance("Point", [("x", 1), ("y", 2)])?;
+    assert_eq!(p.get_metatable(), Some(class.clone()));
+    lua.globals().set("p", p)?;
+    lua.globals().set("Point", class)?;
+
+    assert_eq!(lua.load("p:move_by(1, 1).x").eval::<i64>()?, 2);
+    assert_eq!(lua.load("p.dims").eval::<i64>()?, 2);
+    assert_eq!(lua.load("p.z").eval::<String>()?, "missing z");
+    assert_eq!(lua.load("(p + p:origin()).y").eval::<i64>()?, 3);
+    // Instances are plain tables
+    lua.load("p.dims = 3").exec()?;
+    assert_eq!(lua.load("rawget(p, 'dims')").eval::<i64>()?, 3);
+    // The metatable can be used from Lua
+    let q: Table = lua.load("setmetatable({x = 5, y = 5}, Point)").eval()?;
+    assert_eq!(
+        q.call_method::<_, _, Table>("move_by", (1, 1))?
+            .get::<_, i64>("y")?,
+        6
+    );
+
+    match lua.new_class_instance("Vector", [("x", 1)]) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("'Vector' is not registered")),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    let res = lua.register_table_class("Broken", |class| {
+        class.add_meta_function("__gc", |_, ()| Ok(()));
+    });
+    assert!(matches!(res, Err(Error::MetaMethodRestricted(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_table_len_with() -> Result<()> {
+    let lua = Lua::new();
+
+    let t = lua
+        .load(
+            r#"
+        local t = {1, 2, nil, 4, 5}
+        return setmetatable(t, { __len = function() return 10 end })
+    "#,
+        )
+        .eval::<Table>()?;
+
+    assert_eq!(t.len_with(LenMode::Metamethod)?, 10);
+    assert_eq!(t.len_with(LenMode::Raw)?, t.raw_len());
+    assert_eq!(t.len_with(LenMode::SequenceScan)?, 2);
+    assert_eq!(t.sequence_len(), 2);
+
+    let t = lua.create_sequence_from([1, 2, 3])?;
+    assert_eq!(t.sequence_len(), 3);
+    assert_eq!(lua.create_table()?.sequence_len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_primitive_getters() -> Result<()> {
+    let lua = Lua::new();
+
+    let t = lua
+        .load(
+            r#"
+        return setmetatable({
+            int = 42, float = 1.5, int_float = 3.0, num_str = "7",
+            yes = true, no = false, name = "mlua", bad_utf8 = "\255",
+        }, {__index = function(_, k) return k == "dynamic" and 10 or nil end})
+    "#,
+        )
+        .eval::<Table>()?;
+
+    assert_eq!(t.get_i64("int")?, 42);
+    assert_eq!(t.get_i64("int_float")?, 3);
+    assert_eq!(t.get_i64("num_str")?, 7);
+    assert_eq!(t.get_i64("float")?, t.get::<_, i64>("float")?);
+    assert_eq!(t.get_i64("dynamic")?, 10);
+    assert!(t.get_i64("name").is_err());
+    assert!(t.get_i64("missing").is_err());
+
+    assert_eq!(t.get_f64("float")?, 1.5);
+    assert_eq!(t.get_f64("int")?, 42.0);
+    assert_eq!(t.get_f64("num_str")?, 7.0);
+    assert!(t.get_f64("yes").is_err());
+
+    assert!(t.get_bool("yes")?);
+    assert!(!t.get_bool("no")?);
+    assert!(!t.get_bool("missing")?);
+    assert!(t.get_bool("int")?);
+
+    assert_eq!(t.get_str_with("name", |s| s.len())?, 4);
+    assert_eq!(t.get_str_with("int", |s| s.to_string())?, "42");
+    assert!(t.get_str_with("bad_utf8", |_| ()).is_err());
+    assert!(t.get_str_with("missing", |_| ()).is_err());
+
+    // Raw access for tables without metatable
+    let t = lua.create_table_from([("a", 1)])?;
+    assert_eq!(t.get_i64("a")?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_concat_str() -> Result<()> {
+    let lua = Lua::new();
+
+    let t = lua
+        .load(
+            r#"
+        local mt = {__tostring = function(p) return "P(" .. p.x .. ")" end}
+        return {"a", 1, 2.5, setmetatable({x = 4}, mt), false}
+    "#,
+        )
+        .eval::<Table>()?;
+
+    assert_eq!(t.concat_str(", ", ..=3, ToStringMode::Strict)?, "a, 1, 2.5");
+    assert_eq!(t.concat_str("", 2..4, ToStringMode::Strict)?, "12.5");
+    assert_eq!(
+        t.concat_str("-", .., ToStringMode::ToString)?,
+        "a-1-2.5-P(4)-false"
+    );
+    assert_eq!(t.concat_str("-", 4..4, ToStringMode::Strict)?, "");
+    match t.concat_str(",", .., ToStringMode::Strict) {
+        Err(Error::FromLuaConversionError {
+            from: "table",
+            message,
+            ..
+        }) => {
+            assert_eq!(
+                message.unwrap(),
+                "invalid value (at index 4) to concatenate"
+            );
+        }
+        r => panic!("expected FromLuaConversionError, got {r:?}"),
+    }
+
+    // Errors in `__tostring` are propagated
+    #[cfg(not(feature = "luau"))]
+    {
+        let bad = lua
+            .load("setmetatable({}, {__tostring = error})")
+            .eval::<Table>()?;
+        t.set(6, bad)?;
+        assert!(t.concat_str("", 5..=6, ToStringMode::ToString).is_err());
+    }
+
+    // Long sequences
+    let t = lua.create_sequence_from((1..=1000).map(|i| i.to_string()))?;
+    let expected = (1..=1000)
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(t.concat_str(" ", .., ToStringMode::Strict)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_structural_hash() -> Result<()> {
+    let lua = Lua::new();
+
+    let hash = |code: &str| -> Result<u64> {
+        let t = lua.load(code).eval::<Table>()?;
+        t.structural_hash(HashOptions::new())
+    };
+
+    // Key order and table identity do not matter
+    let h = hash("{ a = 1, b = { 'x', 'y' }, [10] = true }")?;
+    assert_eq!(h, hash("{ [10] = true, b = { 'x', 'y' }, a = 1 }")?);
+    assert_eq!(
+        h,
+        hash("local t = {}; t.b = {'x', 'y'}; t[10] = true; t.a = 1.0; return t")?
+    );
+
+    // Contents do
+    assert_ne!(h, hash("{ a = 1, b = { 'y', 'x' }, [10] = true }")?);
+    assert_ne!(h, hash("{ a = '1', b = { 'x', 'y' }, [10] = true }")?);
+    assert_ne!(h, hash("{ a = 1, b = { 'x', 'y' } }")?);
+    assert_ne!(hash("{ {} }")?, hash("{ { {} } }")?);
+
+    // Metatables
+    let t = lua
+        .load("setmetatable({ 1 }, { __index = { 2 } })")
+        .eval::<Table>()?;
+    assert_eq!(t.structural_hash(HashOptions::new())?, hash("{ 1 }")?);
+    let options = HashOptions::new().include_metatables(true);
+    assert_ne!(t.structural_hash(options)?, hash("{ 1 }")?);
+
+    // Unsupported types
+    let f = lua.create_function(|_, ()| Ok(()))?;
+    let t = lua.create_table_from([("f", f)])?;
+    assert_eq!(
+        t.structural_hash(HashOptions::new())?,
+        t.structural_hash(HashOptions::new())?
+    );
+    let options = HashOptions::new().deny_unsupported_types(true);
+    assert!(matches!(
+        t.structural_hash(options),
+        Err(Error::RuntimeError(_))
+    ));
+
+    // Recursive tables
+    let t = lua
+        .load("local t = { 1 }; t.self = t; return t")
+        .eval::<Table>()?;
+    assert!(matches!(
+        t.structural_hash(HashOptions::new()),
+        Err(Error::RuntimeError(_))
+    ));
+    let options = HashOptions::new().deny_recursive_tables(false);
+    let t2 = lua
+        .load("local t = { 1 }; t.self = t; return t")
+        .eval::<Table>()?;
+    assert_eq!(t.structural_hash(options)?, t2.structural_hash(options)?);
+
+    Ok(())
+}
+
 #[test]
 fn test_table_sequence_from() -> Result<()> {
     let lua = Lua::new();
@@ -267,6 +565,81 @@ fn test_metatable() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_set_metatable_with() -> Result<()> {
+    let lua = Lua::new();
+
+    let defaults = lua.create_table()?;
+    defaults.set("color", "red")?;
+
+    let point = lua.create_table()?;
+    point.set("x", 1)?;
+    point.set("y", 2)?;
+    let mt = point.set_metatable_with(|mt| {
+        mt.index_table(defaults.clone());
+        mt.on_newindex(|_, this, key: String, value: Value| {
+            if key == "z" {
+                return Err(Error::RuntimeError("point is 2d".to_string()));
+            }
+            this.raw_set(key, value)
+        });
+        mt.on_call(|_, this, scale: f64| {
+            Ok((
+                this.get::<_, f64>("x")? * scale,
+                this.get::<_, f64>("y")? * scale,
+            ))
+        });
+        mt.on_tostring(|_, this| {
+            Ok(format!(
+                "({}, {})",
+                this.get::<_, i64>("x")?,
+                this.get::<_, i64>("y")?
+            ))
+        });
+        mt.on_eq(|_, a, b| Ok(a.get::<_, i64>("x")? == b.get::<_, i64>("x")?));
+        mt.on_add(|lua, a, b| {
+            let (a, b) = (Table::from_lua(a, lua)?, Table::from_lua(b, lua)?);
+            let sum = lua.create_table()?;
+            sum.set("x", a.get::<_, i64>("x")? + b.get::<_, i64>("x")?)?;
+            Ok(sum)
+        });
+    })?;
+    assert_eq!(point.get_metatable(), Some(mt.clone()));
+
+    let other = lua.create_table()?;
+    other.set("x", 1)?;
+    other.set_metatable(Some(mt));
+
+    lua.globals().set("p", point)?;
+    lua.globals().set("q", other)?;
+    assert_eq!(lua.load("p.color").eval::<String>()?, "red");
+    assert_eq!(lua.load("p(2)").eval::<(f64, f64)>()?, (2.0, 4.0));
+    assert_eq!(lua.load("tostring(p)").eval::<String>()?, "(1, 2)");
+    assert!(lua.load("p == q").eval::<bool>()?);
+    assert_eq!(lua.load("(p + q).x").eval::<i64>()?, 2);
+    lua.load("p.w = 3").exec()?;
+    assert_eq!(lua.load("rawget(p, 'w')").eval::<i64>()?, 3);
+    match lua.load("p.z = 3").exec() {
+        Err(Error::CallbackError { cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => assert_eq!(msg, "point is 2d"),
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // Generic setter
+    let t = lua.create_table()?;
+    t.set_metatable_with(|mt| {
+        mt.set(MetaMethod::Index, lua.globals());
+    })?;
+    assert_eq!(
+        t.get::<_, String>("_VERSION")?,
+        lua.globals().get::<_, String>("_VERSION")?
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_table_eq() -> Result<()> {
     let lua = Lua::new();
@@ -391,3 +764,211 @@ fn test_owned_table() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_table_views() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(r#"{ 1, 2, "x", 4, name = "config", [10] = true }"#)
+        .eval()?;
+
+    let array = table.as_array::<i64>();
+    assert_eq!(array.len(), 4);
+    assert!(!array.is_empty());
+    assert_eq!(array.get(1)?, Some(1));
+    assert_eq!(array.get(4)?, Some(4));
+    assert_eq!(array.get(0)?, None);
+    assert_eq!(array.get(5)?, None);
+    match array.get(3) {
+        Err(err) => assert!(err.to_string().contains("[3]"), "{err}"),
+        r => panic!("expected conversion error, got {r:?}"),
+    }
+    let values = array.iter().take(2).collect::<Result<Vec<_>>>()?;
+    assert_eq!(values, [1, 2]);
+    assert!(array.iter().collect::<Result<Vec<_>>>().is_err());
+
+    let map = table.as_map::<Value, Value>();
+    assert_eq!(map.len()?, 6);
+    assert!(map.contains_key("name")?);
+    assert!(!map.contains_key("missing")?);
+    assert_eq!(map.get(10)?, Some(Value::Boolean(true)));
+
+    let strings = table.as_map::<String, String>();
+    assert_eq!(strings.get("name")?.unwrap(), "config");
+    assert_eq!(strings.get("missing")?, None);
+    assert!(strings.get(10).is_err());
+
+    let empty = lua.create_table()?;
+    assert!(empty.as_array::<i64>().is_empty());
+    assert!(empty.as_map::<Value, Value>().is_empty());
+    assert_eq!(empty.as_map::<Value, Value>().iter().count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_raw_and_meta_views() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(
+            r#"
+            local data = {1, 2, 3}
+            local proxy = {"x"}
+            return setmetatable(proxy, {
+                __index = data,
+                __newindex = function(_, k, v) rawset(data, k, v * 10) end,
+                __len = function() return #data end,
+                __pairs = function() return next, data, nil end,
+            })
+        "#,
+        )
+        .eval()?;
+
+    let raw = table.raw();
+    assert_eq!(raw.get::<_, String>(1)?, "x");
+    assert_eq!(raw.get::<_, Option<i64>>(2)?, None);
+    assert_eq!(raw.len(), 1);
+    raw.set(2, "y")?;
+    assert_eq!(raw.pairs::<i64, String>().count(), 2);
+
+    let meta = table.with_meta();
+    assert_eq!(meta.get::<_, i64>(3)?, 3);
+    meta.set(4, 4)?;
+    assert_eq!(meta.get::<_, i64>(4)?, 40);
+    assert_eq!(meta.len()?, 4);
+    let pairs = meta.pairs::<i64, Value>()?.collect::<Result<Vec<_>>>()?;
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    assert_eq!(pairs.len(), 4);
+    #[cfg(not(any(feature = "lua54", feature = "lua53", feature = "lua52")))]
+    assert_eq!(pairs.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_sequence_by_ref() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua
+        .load(r#"{1, "two", {3}, print, 5.5, [7] = "gap"}"#)
+        .eval()?;
+
+    let mut values = Vec::new();
+    let mut iter = table.sequence_values_by_ref();
+    while let Some(value) = iter.next() {
+        // Cloned values must stay valid after the slot is reused
+        values.push(value?.clone());
+    }
+    assert_eq!(values.len(), 5);
+    assert_eq!(values[0], Value::Integer(1));
+    assert!(matches!(&values[1], Value::String(s) if s == "two"));
+    assert!(matches!(&values[2], Value::Table(t) if t.raw_get::<_, i32>(1)? == 3));
+    assert!(matches!(&values[3], Value::Function(_)));
+    assert_eq!(values[4], Value::Number(5.5));
+    assert!(iter.next().is_none());
+    drop(iter);
+
+    // Iterating a large table does not exhaust the reference stack
+    let big = lua.create_sequence_from((0..100_000).map(|i| i.to_string()))?;
+    let mut sum = 0;
+    let mut iter = big.sequence_values_by_ref();
+    while let Some(value) = iter.next() {
+        if let Value::String(s) = value? {
+            sum += s.as_bytes().len();
+        }
+    }
+    assert_eq!(sum, (0..100_000).map(|i: i32| i.to_string().len()).sum());
+
+    Ok(())
+}
+
+#[test]
+fn test_table_path() -> Result<()> {
+    let lua = Lua::new();
+
+    let config: Table = lua
+        .load(r#"{ server = { port = 8080, host = "localhost" }, debug = true }"#)
+        .eval()?;
+
+    assert_eq!(config.get_path::<u16>("server.port")?, 8080);
+    assert_eq!(config.get_path::<String>("server.host")?, "localhost");
+    assert!(config.get_path::<bool>("debug")?);
+    assert_eq!(config.get_path::<Option<i64>>("client.timeout")?, None);
+    assert_eq!(config.get_path::<Option<i64>>("server.timeout")?, None);
+
+    // Conversion errors include the full path
+    match config.get_path::<i64>("server.host") {
+        Err(err) => assert!(err.to_string().contains(r#"["server"]["host"]"#), "{err}"),
+        r => panic!("expected conversion error, got {r:?}"),
+    }
+    // Indexing a non-table value is an error
+    match config.get_path::<Value>("server.port.x") {
+        Err(err) => assert!(err.to_string().contains("server.port"), "{err}"),
+        r => panic!("expected error, got {r:?}"),
+    }
+
+    // Missing tables are created
+    config.set_path("server.port", 9090)?;
+    config.set_path("client.retry.count", 3)?;
+    config.set_path("name", "app")?;
+    lua.globals().set("config", config.clone())?;
+    lua.load(
+        r#"
+        assert(config.server.port == 9090)
+        assert(config.client.retry.count == 3)
+        assert(config.name == "app")
+    "#,
+    )
+    .exec()?;
+    assert!(config.set_path("debug.level", 1).is_err());
+
+    // Metamethods are respected
+    let proxy: Table = lua
+        .load(r#"setmetatable({}, { __index = { nested = { value = 42 } } })"#)
+        .eval()?;
+    assert_eq!(proxy.get_path::<i64>("nested.value")?, 42);
+
+    Ok(())
+}
+
+#[test]
+fn test_table_bulk_conversion() -> Result<()> {
+    let lua = Lua::new();
+
+    let table: Table = lua.load("{1, 2, 3, nil, 5, x = 6}").eval()?;
+    assert_eq!(table.to_vec::<i64>()?, vec![1, 2, 3]);
+    assert_eq!(lua.create_table()?.to_vec::<i64>()?, Vec::<i64>::new());
+
+    let table = Table::from_slice(&lua, &[10, 20, 30])?;
+    assert_eq!(table.raw_len(), 3);
+    assert_eq!(table.to_vec::<i64>()?, vec![10, 20, 30]);
+
+    let table: Table = lua.load("{a = 1, b = 2, [3] = 3}").eval()?;
+    let map: HashMap<String, i64> = table.to_hashmap()?;
+    assert_eq!(map.len(), 3);
+    assert_eq!((map["a"], map["b"], map["3"]), (1, 2, 3));
+    let map: HashMap<i64, Value> = lua.create_table()?.to_hashmap()?;
+    assert!(map.is_empty());
+
+    // Conversion errors include the path
+    let table: Table = lua.load(r#"{1, "x"}"#).eval()?;
+    match table.to_vec::<i64>() {
+        Err(err) => assert!(err.to_string().contains("[2]"), "{err}"),
+        r => panic!("expected conversion error, got {r:?}"),
+    }
+    let table: Table = lua.load(r#"{a = {}}"#).eval()?;
+    match table.to_hashmap::<String, i64, RandomState>() {
+        Err(err) => assert!(err.to_string().contains(r#"["a"]"#), "{err}"),
+        r => panic!("expected conversion error, got {r:?}"),
+    }
+
+    // Conversion limits are respected
+    lua.set_conversion_limits(ConversionLimits::new().max_table_entries(2));
+    let table = Table::from_slice(&lua, &[1, 2, 3])?;
+    assert!(table.to_vec::<i64>().is_err());
+    assert!(table.to_hashmap::<i64, i64, RandomState>().is_err());
+
+    Ok(())
+}
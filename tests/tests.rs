@@ -3,12 +3,13 @@ use std::iter::FromIterator;
 use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::string::String as StdString;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
-use std::{error, f32, f64, fmt};
+use std::sync::{Arc, Mutex};
+use std::{error, f32, f64, fmt, io};
 
 use mlua::{
-    ChunkMode, Error, ExternalError, Function, Lua, LuaOptions, Nil, Result, StdLib, String, Table,
-    UserData, Value, Variadic,
+    BitWidth, ChunkMode, Downgrade, DuplicatePolicy, Encoding, Error, ExternalError, Function,
+    GlobalPolicy, LocalePolicy, Lua, LuaOptions, Nil, ReentrancyPolicy, Result, SharedDataSegment,
+    StdLib, String, Table, UserData, UserDataFields, UserDataMethods, Value, ValueMirror, Variadic,
 };
 
 #[cfg(not(feature = "luau"))]
@@ -174,6 +175,188 @@ fn test_load_mode() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_load_from_reader() -> Result<()> {
+    // Returns the source in small pieces
+    struct SlowReader<'a>(&'a [u8]);
+
+    impl io::Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(7);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let lua = Lua::new();
+
+    let mut source = StdString::from("local sum = 0\n");
+    for i in 1..=1000 {
+        source.push_str(&format!("sum = sum + {i}\n"));
+    }
+    source.push_str("return sum");
+    let func = lua.load_from_reader(SlowReader(source.as_bytes()), "=generated")?;
+    assert_eq!(func.call::<_, i64>(())?, 500500);
+
+    match lua.load_from_reader(SlowReader(b"return +"), "=broken") {
+        Err(Error::SyntaxError { message, .. }) => assert!(message.starts_with("broken:")),
+        r => panic!("expected SyntaxError, got {r:?}"),
+    }
+
+    #[cfg(not(feature = "luau"))]
+    {
+        let bytecode = lua.load("return 1").into_function()?.dump(true);
+        let func = lua.load_from_reader(SlowReader(&bytecode), "=bytecode")?;
+        assert_eq!(func.call::<_, i32>(())?, 1);
+        lua.allow_bytecode(false);
+        match lua.load_from_reader(SlowReader(&bytecode), "=bytecode") {
+            Err(Error::SyntaxError { message, .. }) => {
+                assert!(message.contains("attempt to load a binary chunk"))
+            }
+            r => panic!("expected SyntaxError, got {r:?}"),
+        }
+    }
+
+    struct FailingReader;
+
+    impl io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "read failed"))
+        }
+    }
+
+    match lua.load_from_reader(FailingReader, "=failing") {
+        Err(Error::ExternalError(err)) => assert_eq!(err.to_string(), "read failed"),
+        r => panic!("expected ExternalError, got {r:?}"),
+    }
+
+    // The preprocessor is applied to the whole source
+    lua.set_preprocessor(|source| Ok(source.replace("PI", "3")))?;
+    let func = lua.load_from_reader(SlowReader(b"return PI * 2"), "=prep")?;
+    assert_eq!(func.call::<_, i32>(())?, 6);
+    assert_eq!(
+        lua.preprocessed_source("prep").as_deref(),
+        Some("return 3 * 2")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_duplicate() -> Result<()> {
+    let lua = Lua::new();
+    lua.load(
+        r#"
+        local shared = { 1, 2, 3 }
+        config = { items = shared, same = shared, name = "test" }
+        setmetatable(config, { __index = function(_, k) return "default_" .. k end })
+        function string.shout(s) return s:upper() .. "!" end
+        local count = 0
+        function inc() count = count + 1; return count end
+        native = function() end
+        "#,
+    )
+    .exec()?;
+    lua.globals()
+        .set("native", lua.create_function(|_, ()| Ok(()))?)?;
+    lua.load("inc()").exec()?;
+
+    let copy = lua.duplicate(DuplicatePolicy::new())?;
+    let globals = copy.globals();
+    assert!(copy.load("config.items == config.same").eval::<bool>()?);
+    assert_eq!(
+        copy.load("string.format('%d', 5)").eval::<StdString>()?,
+        "5"
+    );
+    // Rust functions are skipped
+    assert_eq!(globals.get::<_, Value>("native")?, Nil);
+
+    // Lua functions cannot be copied on Luau
+    #[cfg(not(feature = "luau"))]
+    {
+        assert_eq!(
+            copy.load("config.missing").eval::<StdString>()?,
+            "default_missing"
+        );
+        assert_eq!(copy.load("('hi'):shout()").eval::<StdString>()?, "HI!");
+
+        // Upvalues are copied by value
+        assert_eq!(copy.load("inc()").eval::<i32>()?, 2);
+        assert_eq!(copy.load("inc()").eval::<i32>()?, 3);
+        assert_eq!(lua.load("inc()").eval::<i32>()?, 2);
+    }
+
+    // Changes do not affect the source state
+    copy.load("config.name = 'copy'").exec()?;
+    assert_eq!(lua.load("config.name").eval::<StdString>()?, "test");
+
+    match lua.duplicate(DuplicatePolicy::new().skip_unsupported(false)) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("cannot copy function value"))
+        }
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    // Options are carried over
+    let lua = Lua::new_with(StdLib::ALL_SAFE, LuaOptions::new().catch_rust_panics(false))?;
+    let copy = lua.duplicate(DuplicatePolicy::new())?;
+    let panic_fn = copy.create_function(|_, ()| -> Result<()> { panic!("duplicate panic") })?;
+    copy.globals().set("panic_fn", panic_fn)?;
+    match catch_unwind(AssertUnwindSafe(|| copy.load("pcall(panic_fn)").exec())) {
+        Ok(r) => panic!("no panic was detected: {r:?}"),
+        Err(p) => assert_eq!(*p.downcast::<&str>().unwrap(), "duplicate panic"),
+    }
+
+    Ok(())
+}
+
+#[cfg(all(feature = "vector", not(feature = "luau")))]
+#[test]
+fn test_vector() -> Result<()> {
+    use mlua::Vector;
+
+    let lua = Lua::new();
+    let globals = lua.globals();
+    globals.set("a", Vector::new(1.0, 2.0, 3.0))?;
+    globals.set("b", Vector::from([4.0, 5.0, 6.0]))?;
+
+    assert_eq!(lua.load("a + b").eval::<Vector>()?, [5.0, 7.0, 9.0]);
+    assert_eq!(lua.load("b - a").eval::<Vector>()?, [3.0, 3.0, 3.0]);
+    assert_eq!(lua.load("a * b").eval::<Vector>()?, [4.0, 10.0, 18.0]);
+    assert_eq!(lua.load("a * 2").eval::<Vector>()?, [2.0, 4.0, 6.0]);
+    assert_eq!(lua.load("6 / a").eval::<Vector>()?, [6.0, 3.0, 2.0]);
+    assert_eq!(lua.load("-a").eval::<Vector>()?, [-1.0, -2.0, -3.0]);
+    assert_eq!(lua.load("a.x + a.y + a.z").eval::<f32>()?, 6.0);
+    assert_eq!(lua.load("a.w").eval::<Value>()?, Nil);
+    assert!(lua.load("a == a * 1").eval::<bool>()?);
+    assert!(lua.load("a ~= b").eval::<bool>()?);
+    assert_eq!(
+        lua.load("tostring(a)").eval::<StdString>()?,
+        "vector(1, 2, 3)"
+    );
+    assert_eq!(lua.load("getmetatable(a)").eval::<bool>()?, false);
+
+    // Invalid operations
+    assert!(lua.load("a + 1").exec().is_err());
+    assert!(lua.load("a * {}").exec().is_err());
+    assert!(lua.load("a.x = 1").exec().is_err());
+
+    // Other values are not vectors
+    assert!(lua.load("{1, 2, 3}").eval::<Vector>().is_err());
+    assert!(lua.load("io.stdout").eval::<Vector>().is_err());
+
+    #[cfg(feature = "glam")]
+    {
+        let v: glam::Vec3 = lua.load("a + b").eval()?;
+        assert_eq!(v, glam::Vec3::new(5.0, 7.0, 9.0));
+        globals.set("c", glam::Vec3::ONE)?;
+        assert_eq!(lua.load("(c * 2).y").eval::<f32>()?, 2.0);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_lua_multi() -> Result<()> {
     let lua = Lua::new();
@@ -713,6 +896,113 @@ fn test_recursive_mut_callback_error() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_reentrancy_policy() -> Result<()> {
+    let lua = Lua::new();
+
+    // Error: re-entrant calls fail with an explanatory message
+    let f = lua.create_function_mut_with_reentrancy(ReentrancyPolicy::Error, |lua, n: u32| {
+        if n > 0 {
+            lua.load(format!("f({})", n - 1)).exec()?;
+        }
+        Ok(())
+    })?;
+    lua.globals().set("f", f)?;
+    let err = lua.load("f(1)").exec().unwrap_err().to_string();
+    assert!(err.contains("function 'f' is not re-entrant"), "{err}");
+    lua.load("f(0)").exec()?;
+
+    // Queue: re-entrant calls are deferred until the running call finishes
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let log2 = log.clone();
+    let f =
+        lua.create_function_mut_with_reentrancy(ReentrancyPolicy::Queue, move |lua, n: u32| {
+            log2.lock().unwrap().push(format!("start {n}"));
+            if n > 0 {
+                let res = lua
+                    .globals()
+                    .get::<_, Function>("f")?
+                    .call::<_, Option<u32>>(n - 1)?;
+                assert_eq!(res, None);
+            }
+            log2.lock().unwrap().push(format!("end {n}"));
+            Ok(n)
+        })?;
+    lua.globals().set("f", f)?;
+    assert_eq!(lua.load("f(2)").eval::<u32>()?, 2);
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["start 2", "end 2", "start 1", "end 1", "start 0", "end 0"]
+    );
+
+    // Deferred calls receive all arguments, including nils
+    let f = lua.create_function_with_reentrancy(
+        ReentrancyPolicy::Queue,
+        |lua, (nested, a, b): (bool, Value, Value)| {
+            if nested {
+                let f = lua.globals().get::<_, Function>("g")?;
+                f.call::<_, ()>((false, Value::Nil, 2))?;
+            } else {
+                assert_eq!(a, Value::Nil);
+                assert_eq!(b, Value::Integer(2));
+                lua.globals().set("deferred_called", true)?;
+            }
+            Ok(())
+        },
+    )?;
+    lua.globals().set("g", f)?;
+    lua.load("g(true)").exec()?;
+    assert!(lua.globals().get::<_, bool>("deferred_called")?);
+
+    // Allow: re-entrant calls of non-mutable callbacks run as usual
+    let f = lua.create_function_with_reentrancy(ReentrancyPolicy::Allow, |lua, n: u32| {
+        if n == 0 {
+            return Ok(0);
+        }
+        let res = lua
+            .globals()
+            .get::<_, Function>("h")?
+            .call::<_, u32>(n - 1)?;
+        Ok(res + n)
+    })?;
+    lua.globals().set("h", f)?;
+    assert_eq!(lua.load("h(3)").eval::<u32>()?, 6);
+
+    Ok(())
+}
+
+#[test]
+fn test_capabilities() -> Result<()> {
+    let lua = Lua::new_with(StdLib::MATH | StdLib::STRING, LuaOptions::default())?;
+    let loaded = lua.named_registry_value::<Table>("_LOADED")?;
+    loaded.set("mymod", lua.create_table()?)?;
+
+    let caps = lua.capabilities()?;
+    assert!(caps.has("math") && caps.has("string") && caps.has("mymod"));
+    assert!(!caps.has("os") && !caps.has("_G"));
+    assert_eq!(caps.modules, vec!["mymod"]);
+    assert_eq!(caps.async_support, cfg!(feature = "async"));
+
+    lua.globals()
+        .set("host", lua.create_table_from([("name", "test")])?)?;
+    lua.expose_capabilities()?;
+    lua.load(
+        r#"
+        local caps = host.capabilities
+        assert(host.name == "test")
+        assert(caps.version == _VERSION)
+        assert(type(caps.mlua_version) == "string")
+        assert(caps.std_libs.math and caps.std_libs.string and not caps.std_libs.os)
+        assert(caps.modules.mymod and not caps.modules.math)
+        assert(not pcall(function() caps.version = "Lua 6.0" end))
+        assert(not pcall(function() caps.modules.other = true end))
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
 #[test]
 fn test_set_metatable_nil() -> Result<()> {
     let lua = Lua::new();
@@ -789,6 +1079,38 @@ fn test_drop_registry_value() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_drop_registry_value_auto_expire() -> Result<()> {
+    struct MyUserdata(Arc<()>);
+
+    impl UserData for MyUserdata {}
+
+    let lua = Lua::new();
+    let rc = Arc::new(());
+
+    let r = lua.create_registry_value(MyUserdata(rc.clone()))?;
+    assert_eq!(Arc::strong_count(&rc), 2);
+
+    // Keys can be dropped from other threads
+    std::thread::spawn(move || drop(r)).join().unwrap();
+
+    // Value is released without calling `expire_registry_values`
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&rc), 1);
+
+    // Slot of a dropped key is reused and the old value is replaced
+    let r = lua.create_registry_value(MyUserdata(rc.clone()))?;
+    drop(r);
+    let r = lua.create_registry_value("hello")?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(Arc::strong_count(&rc), 1);
+    assert_eq!(lua.registry_value::<String>(&r)?, "hello");
+
+    Ok(())
+}
+
 #[test]
 fn test_replace_registry_value() -> Result<()> {
     let lua = Lua::new();
@@ -858,6 +1180,63 @@ fn test_mismatched_registry_key() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "strict-no-panic")]
+fn test_state_mismatch() -> Result<()> {
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+
+    let t = lua1.create_table()?;
+    match lua2.globals().set("t", t.clone()) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+
+    let f = lua1.create_function(|_, ()| Ok(()))?;
+    match lua2.create_thread(f.clone()) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+
+    let mt = lua2.create_table()?;
+    match t.try_set_metatable(Some(mt)) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+
+    assert_ne!(t, lua2.create_table()?);
+
+    // Handles passed to other fallible APIs
+    let f2 = lua2.create_function(|_, ()| Ok(()))?;
+    match f2.call::<_, ()>(t.clone()) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+    match lua2.create_table()?.raw_set(1, t.clone()) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+    #[cfg(any(
+        feature = "lua54",
+        all(feature = "luajit", feature = "vendored"),
+        feature = "luau",
+    ))]
+    match lua2.create_thread(f2.clone())?.reset(f) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+    match lua2.load("return 1").set_environment(t.clone()).exec() {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+    match lua2.create_registry_value(t) {
+        Err(Error::StateMismatch) => {}
+        r => panic!("expected StateMismatch error, got {:?}", r),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_registry_value_reuse() -> Result<()> {
     let lua = Lua::new();
@@ -880,6 +1259,40 @@ fn test_registry_value_reuse() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_weak_ref() -> Result<()> {
+    let lua = Lua::new();
+
+    let table = lua.create_table()?;
+    table.set("a", 1)?;
+    let func = lua.create_function(|_, ()| Ok(()))?;
+    let weak_table = table.downgrade()?;
+    let weak_func = func.downgrade()?;
+    let weak_ud = lua.create_any_userdata(123)?.downgrade()?;
+
+    // Objects are kept alive by strong handles (or Lua references)
+    lua.globals().set("func", func)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(weak_table.upgrade().unwrap().get::<_, i32>("a")?, 1);
+    assert!(weak_func.upgrade().is_some());
+    assert!(weak_ud.upgrade().is_none());
+
+    drop(table);
+    lua.globals().set("func", Nil)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(weak_table.upgrade().is_none());
+    assert!(weak_func.upgrade().is_none());
+
+    // Ids of collected objects are not reused
+    let table2 = lua.create_table()?;
+    let _weak_table2 = table2.downgrade()?;
+    assert!(weak_table.upgrade().is_none());
+
+    Ok(())
+}
+
 #[test]
 fn test_application_data() -> Result<()> {
     let lua = Lua::new();
@@ -939,6 +1352,329 @@ fn test_application_data() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_app_data_global() -> Result<()> {
+    struct Game {
+        score: i64,
+    }
+
+    impl UserData for Game {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field_method_get("score", |_, this| Ok(this.score));
+        }
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("add", |_, this, n: i64| {
+                this.score += n;
+                Ok(this.score)
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    lua.set_app_data(Game { score: 1 });
+    lua.expose_app_data_as_global::<Game>("game")?;
+
+    // Changes made from Lua are visible in Rust and vice versa
+    assert_eq!(lua.load("game:add(2)").eval::<i64>()?, 3);
+    assert_eq!(lua.app_data_ref::<Game>().unwrap().score, 3);
+    lua.app_data_mut::<Game>().unwrap().score = 10;
+    assert_eq!(lua.load("game.score").eval::<i64>()?, 10);
+
+    // Borrowing rules are enforced
+    let game = lua.app_data_ref::<Game>().unwrap();
+    assert_eq!(lua.load("game.score").eval::<i64>()?, 10);
+    match lua.load("game:add(1)").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { cause, .. } => {
+                assert!(matches!(cause.as_ref(), Error::UserDataBorrowMutError))
+            }
+            e => panic!("expected BadArgument, got {e:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    drop(game);
+
+    // Accessing removed app data is an error
+    lua.remove_app_data::<Game>();
+    match lua.load("return game.score").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::BadArgument { cause, .. } => match cause.as_ref() {
+                Error::RuntimeError(msg) => assert!(msg.contains("is not set")),
+                e => panic!("expected RuntimeError, got {e:?}"),
+            },
+            e => panic!("expected BadArgument, got {e:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    // The app data container is not left borrowed after a failed access
+    lua.set_app_data(Game { score: 5 });
+    assert_eq!(lua.load("game.score").eval::<i64>()?, 5);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_mirror() -> Result<()> {
+    let mirror = ValueMirror::new(vec!["a".to_string()]);
+
+    let lua1 = Lua::new();
+    let lua2 = Lua::new();
+    mirror.attach(&lua1, "tags")?;
+    let calls = Arc::new(AtomicU32::new(0));
+    let calls2 = calls.clone();
+    mirror.attach_with(&lua2, move |lua, tags| {
+        calls2.fetch_add(1, Ordering::Relaxed);
+        lua.globals().set("ntags", tags.len())
+    })?;
+    assert_eq!(lua1.load("tags[1]").eval::<String>()?, "a");
+    assert_eq!(lua2.globals().get::<_, usize>("ntags")?, 1);
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    // Updates are applied on the next sync only
+    mirror.update(vec!["b".to_string(), "c".to_string()]);
+    assert_eq!(lua1.load("#tags").eval::<usize>()?, 1);
+    lua1.sync_mirrors()?;
+    lua2.sync_mirrors()?;
+    assert_eq!(lua1.load("tags[2]").eval::<String>()?, "c");
+    assert_eq!(lua2.globals().get::<_, usize>("ntags")?, 2);
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+    // Unchanged mirrors are skipped
+    lua2.sync_mirrors()?;
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    assert_eq!(mirror.get(), vec!["b", "c"]);
+
+    // Errors are propagated
+    let lua3 = Lua::new();
+    mirror.attach_with(&lua3, |_, tags| {
+        if tags.is_empty() {
+            return Err(Error::RuntimeError("no tags".into()));
+        }
+        Ok(())
+    })?;
+    mirror.update(Vec::new());
+    assert!(matches!(lua3.sync_mirrors(), Err(Error::RuntimeError(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_data_segment() -> Result<()> {
+    let source = Lua::new();
+    let data = source
+        .load(
+            r#"
+        local common = {kind = "weapon"}
+        return {
+            items = {
+                {name = "sword", damage = 10.5, meta = common},
+                {name = "axe", damage = 12, meta = common},
+            },
+            [true] = "yes",
+            [10] = "ten",
+        }
+    "#,
+        )
+        .eval::<Table>()?;
+    let segment = SharedDataSegment::from_table(&data)?;
+    drop(data);
+    drop(source);
+
+    let (lua1, lua2) = (Lua::new(), Lua::new());
+    for lua in [&lua1, &lua2] {
+        lua.globals()
+            .set("data", lua.attach_shared_data(&segment)?)?;
+        lua.load(
+            r#"
+            assert(#data.items == 2)
+            assert(data.items[1].name == "sword" and data.items[1].damage == 10.5)
+            assert(data.items[2].meta.kind == "weapon")
+            assert(data.items[1].meta == data.items[2].meta)
+            assert(data.items[1] ~= data.items[2])
+            assert(data[true] == "yes" and data[10] == "ten" and data[10.0] == "ten")
+            assert(data.missing == nil and data.items[3] == nil)
+            assert(not pcall(function() data.items[1].name = "bow" end))
+        "#,
+        )
+        .exec()?;
+    }
+
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    lua1.load(
+        r#"
+        local names, keys = {}, 0
+        for i, item in pairs(data.items) do names[i] = item.name end
+        for _ in pairs(data) do keys = keys + 1 end
+        assert(names[1] == "sword" and names[2] == "axe" and keys == 3)
+    "#,
+    )
+    .exec()?;
+    #[cfg(feature = "luau")]
+    lua1.load(
+        r#"
+        local names, keys = {}, 0
+        for i, item in data.items do names[i] = item.name end
+        for _ in data do keys = keys + 1 end
+        assert(names[1] == "sword" and names[2] == "axe" and keys == 3)
+    "#,
+    )
+    .exec()?;
+
+    // Values that cannot be shared
+    let lua = Lua::new();
+    let recursive = lua.load("local t = {} t.t = t return t").eval::<Table>()?;
+    assert!(SharedDataSegment::from_table(&recursive).is_err());
+    let with_function = lua.load("{f = print}").eval::<Table>()?;
+    assert!(SharedDataSegment::from_table(&with_function).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_protect_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("score", 1)?;
+
+    let violations = Arc::new(Mutex::new(Vec::new()));
+    let violations2 = violations.clone();
+    let policy = GlobalPolicy::new()
+        .allow_new(false)
+        .writable(["score"])
+        .on_violation(move |_, violation| {
+            violations2.lock().unwrap().push(violation.clone());
+            Ok(())
+        });
+    lua.protect_globals(policy)?;
+
+    lua.load("score = score + 1").exec()?;
+    assert_eq!(lua.globals().get::<_, i32>("score")?, 2);
+    match lua.load("print = nil").set_name("=script").exec() {
+        Err(Error::CallbackError { cause, .. }) => {
+            let msg = "attempt to modify protected global 'print'";
+            assert!(matches!(&*cause, Error::RuntimeError(m) if m == msg));
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert!(lua.load("level = 2").exec().is_err());
+    #[cfg(any(feature = "lua54", feature = "lua53"))]
+    assert!(lua.load("table.insert(_G, 1)").exec().is_err());
+    {
+        let violations = violations.lock().unwrap();
+        assert_eq!(
+            violations.len(),
+            if cfg!(any(feature = "lua54", feature = "lua53")) {
+                3
+            } else {
+                2
+            }
+        );
+        assert_eq!(violations[0].key, "print");
+        assert!(!violations[0].new);
+        assert!((violations[0].traceback.as_ref().unwrap()).contains("script"));
+        assert_eq!(violations[1].key, "level");
+        assert!(violations[1].new);
+    }
+
+    // Reads and writes from Rust are not restricted
+    lua.globals().set("level", 3)?;
+    let set_level = lua.create_function(|lua, level: i32| lua.globals().set("level", level))?;
+    lua.globals().set("set_level", set_level)?;
+    lua.load("set_level(4)").exec()?;
+    assert_eq!(lua.load("level").eval::<i32>()?, 4);
+    assert_eq!(lua.globals().get::<_, i32>("level")?, 4);
+    assert!(lua.load("type(print) == 'function'").eval::<bool>()?);
+    assert!(lua.load("setmetatable(_G, nil)").exec().is_err());
+    #[cfg(any(feature = "lua54", feature = "lua53", feature = "lua52"))]
+    assert!(
+        lua.load(
+            "local n = 0 for k in pairs(_G) do if k == 'score' then n = n + 1 end end return n"
+        )
+        .eval::<i32>()?
+            == 1
+    );
+
+    // Rust code accesses the global table itself
+    assert!(lua.globals().raw_get::<_, Function>("print").is_ok());
+    let names = (lua.globals().pairs::<String, Value>())
+        .map(|pair| pair.map(|(name, _)| name))
+        .collect::<Result<Vec<_>>>()?;
+    assert!(names.iter().any(|name| name == "print"));
+    lua.globals().raw_set("print", Nil)?;
+    assert!(lua.load("print == nil").eval::<bool>()?);
+    assert!(lua.load("print = 1").exec().is_err());
+
+    assert!(lua.load("level = 5").exec().is_err());
+    lua.install_inspect()?;
+    assert_eq!(lua.load("inspect(1)").eval::<String>()?, "1");
+
+    // Errors of the violation handler are propagated
+    let lua = Lua::new();
+    lua.protect_globals(GlobalPolicy::new().on_violation(|_, violation| {
+        Err(Error::RuntimeError(format!("denied: {}", violation.key)))
+    }))?;
+    lua.load("x = 1; x = 2").exec()?;
+    assert_eq!(lua.globals().raw_get::<_, i32>("x")?, 2);
+    match lua.load("string = nil").exec() {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(matches!(&*cause, Error::RuntimeError(m) if m == "denied: string"))
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+    assert!(lua.protect_globals(GlobalPolicy::new()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_update_globals() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("stale", true)?;
+
+    let n = lua.update_globals(|tx| {
+        tx.set("a", 1)?;
+        tx.set("b", tx.get::<i64>("a")? + 1)?;
+        tx.remove("stale")?;
+        assert_eq!(tx.get::<Option<bool>>("stale")?, None);
+        // Nothing is applied until the function returns
+        assert_eq!(lua.globals().get::<_, Option<i64>>("a")?, None);
+        Ok(2)
+    })?;
+    assert_eq!(n, 2);
+    assert_eq!(lua.load("a + b").eval::<i64>()?, 3);
+    assert_eq!(lua.globals().get::<_, Option<bool>>("stale")?, None);
+
+    // All or nothing
+    let result = lua.update_globals(|tx| {
+        tx.set("a", 10)?;
+        Err::<(), _>(Error::RuntimeError("failed".into()))
+    });
+    assert!(result.is_err());
+    assert_eq!(lua.globals().get::<_, i64>("a")?, 1);
+
+    // Protected globals are updated
+    lua.protect_globals(GlobalPolicy::new().allow_new(false))?;
+    lua.update_globals(|tx| {
+        tx.set("a", 2)?;
+        tx.set("c", 3)
+    })?;
+    assert_eq!(lua.load("a + b + c").eval::<i64>()?, 7);
+    assert!(lua.load("a = 1").exec().is_err());
+    assert!(lua.load("d = 4").exec().is_err());
+
+    // A look-alike metatable does not make globals protected
+    let lua = Lua::new();
+    let mt = lua.create_table()?;
+    mt.set("__index", lua.create_table()?)?;
+    mt.set("__metatable", "protected")?;
+    lua.globals().set_metatable(Some(mt));
+    lua.update_globals(|tx| tx.set("a", 1))?;
+    assert_eq!(lua.globals().raw_get::<_, i64>("a")?, 1);
+
+    Ok(())
+}
+
 #[test]
 fn test_recursion() -> Result<()> {
     let lua = Lua::new();
@@ -1346,3 +2082,388 @@ fn test_send() {
     .join()
     .unwrap();
 }
+
+#[test]
+fn test_locale_policy() -> Result<()> {
+    let lua = Lua::new();
+
+    let orig_tonumber: Function = lua.globals().get("tonumber")?;
+    lua.set_locale_policy(LocalePolicy::CIndependent)?;
+    // Setting the policy twice keeps the original functions
+    lua.set_locale_policy(LocalePolicy::CIndependent)?;
+
+    lua.load(
+        r#"
+        assert(tonumber("10") == 10)
+        assert(tonumber(" 1.5\n") == 1.5)
+        assert(tonumber("-2e3") == -2000)
+        assert(tonumber("1,5") == nil)
+        assert(tonumber("inf") == nil)
+        assert(tonumber("0x10") == 16)
+        assert(tonumber("ff", 16) == 255)
+        assert(tonumber(7) == 7)
+        assert(tostring(0.5) == "0.5")
+        assert(tostring(nil) == "nil")
+        assert(string.upper("abc") == "ABC")
+        assert(("ABC\xc9"):lower() == "abc\xc9")
+    "#,
+    )
+    .exec()?;
+
+    lua.set_locale_policy(LocalePolicy::Process)?;
+    let tonumber: Function = lua.globals().get("tonumber")?;
+    assert_eq!(tonumber, orig_tonumber);
+
+    Ok(())
+}
+
+#[test]
+fn test_sandbox_builder() -> Result<()> {
+    let lua = Lua::new();
+    lua.globals().set("secret", 42)?;
+
+    let sandbox = lua
+        .sandbox_builder()
+        .allow_globals(["string", "math", "os", "io", "load", "pcall"])
+        .block(["math.random"])
+        .build()?;
+
+    // Only whitelisted globals are available and blocked functions are removed
+    assert_eq!(sandbox.eval::<Option<i64>>("return secret")?, None);
+    assert_eq!(
+        sandbox.eval::<StdString>("return string.upper('abc')")?,
+        "ABC"
+    );
+    assert!(sandbox.eval::<bool>("return os.execute == nil and os.time ~= nil")?);
+    assert!(sandbox.eval::<bool>("return io == nil and load == nil")?);
+    assert!(sandbox.eval::<bool>("return math.random == nil")?);
+    assert!(lua.load("return math.random ~= nil").eval::<bool>()?);
+
+    // Every chunk runs in its own environment
+    sandbox.exec("x = 1; string.custom = true")?;
+    assert_eq!(sandbox.eval::<Option<i64>>("return x")?, None);
+    assert_eq!(sandbox.eval::<Option<bool>>("return string.custom")?, None);
+    assert_eq!(lua.globals().get::<_, Option<i64>>("x")?, None);
+    let env = sandbox.environment()?;
+    sandbox.load("y = 2").set_environment(env.clone()).exec()?;
+    assert_eq!(env.get::<_, i64>("y")?, 2);
+
+    // The string metatable is hidden and its methods come from the sandbox `string` library
+    let sandbox = lua.sandbox_builder().build()?;
+    assert!(sandbox.eval::<bool>("return getmetatable('') == false")?);
+    assert!(sandbox.eval::<bool>("return ('').dump == nil")?);
+    assert_eq!(sandbox.eval::<StdString>("return ('abc'):upper()")?, "ABC");
+    sandbox
+        .exec("string.upper = function() return 'pwned' end; assert(('a'):upper() == 'pwned')")?;
+    sandbox.exec("assert(pcall(setmetatable, '', {}) == false)")?;
+    assert_eq!(
+        lua.load("return ('abc'):upper()").eval::<StdString>()?,
+        "ABC"
+    );
+    assert!(lua
+        .load("return getmetatable('').__index == string")
+        .eval::<bool>()?);
+    let sandbox2 = lua.sandbox_builder().allow_globals(["pcall"]).build()?;
+    assert!(sandbox2.eval::<bool>("return not pcall(function() return ('a'):upper() end)")?);
+
+    // Limits
+    let sandbox = lua.sandbox_builder().memory_limit(64 * 1024).build()?;
+    match sandbox.exec("local t = {} for i = 1, 1e6 do t[i] = i end") {
+        Err(Error::MemoryLimitExceeded(_)) => {}
+        r => panic!("expected MemoryLimitExceeded, got {r:?}"),
+    }
+    sandbox.exec("local t = {} for i = 1, 100 do t[i] = i end")?;
+    assert_eq!(lua.set_memory_limit(0)?, 0);
+
+    let sandbox = lua.sandbox_builder().instruction_limit(10_000).build()?;
+    match sandbox.exec("while true do end") {
+        Err(Error::InstructionLimitExceeded(10_000)) => {}
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+    sandbox.exec("local x = 0 for i = 1, 10 do x = x + i end")?;
+    match sandbox.exec("coroutine.wrap(function() while true do end end)()") {
+        Err(Error::InstructionLimitExceeded(10_000)) => {}
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+    match sandbox.exec("while true do pcall(function() while true do end end) end") {
+        Err(Error::InstructionLimitExceeded(10_000)) => {}
+        r => panic!("expected InstructionLimitExceeded, got {r:?}"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bit_library() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load_bit_library(BitWidth::Bits32)?;
+    let results: Vec<i64> = lua
+        .load(
+            r#"
+            local bit = require("bit")
+            return {
+                bit.band(0xff, 0x0f, 0x3c), bit.bor(1, 2, 4), bit.bxor(5, 3), bit.bnot(0),
+                bit.lshift(1, 31), bit.rshift(-1, 28), bit.arshift(-256, 4),
+                bit.rol(0x12345678, 8), bit.ror(0x12345678, 8), bit.tobit(0xffffffff + 2),
+            }
+        "#,
+        )
+        .eval()?;
+    let w = BitWidth::Bits32;
+    assert_eq!(
+        results,
+        [
+            w.band(&[0xff, 0x0f, 0x3c]),
+            w.bor(&[1, 2, 4]),
+            w.bxor(&[5, 3]),
+            w.bnot(0),
+            w.lshift(1, 31),
+            w.rshift(-1, 28),
+            w.arshift(-256, 4),
+            w.rol(0x12345678, 8),
+            w.ror(0x12345678, 8),
+            w.tobit(0xffffffff + 2),
+        ]
+    );
+    assert_eq!(
+        results,
+        [
+            0x0c,
+            7,
+            6,
+            -1,
+            -2147483648,
+            0xf,
+            -16,
+            0x34567812,
+            0x78123456,
+            1
+        ]
+    );
+
+    match lua.load("bit.band(1.5)").exec() {
+        Err(Error::CallbackError { cause, .. }) => {
+            assert!(cause.to_string().contains("no integer representation"))
+        }
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    lua.load_bit_library(BitWidth::Bits64)?;
+    assert_eq!(lua.load("bit.rshift(-1, 60)").eval::<i64>()?, 0xf);
+    assert_eq!(lua.load("bit.lshift(1, 40)").eval::<i64>()?, 1 << 40);
+
+    Ok(())
+}
+
+#[test]
+fn test_encoding() -> Result<()> {
+    let lua = Lua::new();
+
+    let cases: &[(&[u8], &str, &str)] = &[
+        (b"", "", ""),
+        (b"f", "66", "Zg=="),
+        (b"fo", "666f", "Zm8="),
+        (b"foo", "666f6f", "Zm9v"),
+        (b"foob", "666f6f62", "Zm9vYg=="),
+        (b"\x00\xff\x10", "00ff10", "AP8Q"),
+    ];
+    for &(data, hex, base64) in cases {
+        assert_eq!(lua.encode(data, Encoding::Hex)?, hex);
+        assert_eq!(lua.encode(data, Encoding::Base64)?, base64);
+        assert_eq!(lua.decode(hex, Encoding::Hex)?, data);
+        assert_eq!(lua.decode(base64, Encoding::Base64)?, data);
+        assert_eq!(Encoding::Base64.encoded_len(data.len()), base64.len());
+    }
+    assert_eq!(lua.decode("00FF", Encoding::Hex)?, &b"\x00\xff"[..]);
+    assert_eq!(lua.decode("Zm8", Encoding::Base64)?, "fo");
+
+    let err = lua.decode("abc", Encoding::Hex).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "runtime error: invalid hex data: odd number of digits"
+    );
+    let err = lua.decode("Zm9v!A==", Encoding::Base64).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("invalid character '!' at position 5"));
+    assert!(lua.decode("Zm9vY", Encoding::Base64).is_err());
+    for data in ["Zm=", "Zm9v=A", "AAAAAA=A", "Zm9v===", "="] {
+        assert!(lua.decode(data, Encoding::Base64).is_err(), "{data}");
+    }
+
+    // The output buffer is allocated by Lua and accounted against the memory limit
+    #[cfg(not(feature = "luau"))]
+    {
+        let blob = vec![0u8; 1 << 20];
+        lua.set_memory_limit(lua.used_memory() + (1 << 20))?;
+        match lua.encode(&blob, Encoding::Hex) {
+            Err(Error::MemoryLimitExceeded(_)) => {}
+            res => panic!("expected memory error, got {res:?}"),
+        }
+        lua.set_memory_limit(0)?;
+    }
+
+    lua.load_encoding_library()?;
+    lua.load(
+        r#"
+        local encoding = require("encoding")
+        local blob = string.rep("\0\1\2\255", 1000)
+        assert(encoding.decode(encoding.encode(blob, "base64"), "base64") == blob)
+        assert(encoding.encode("hi", "hex") == "6869")
+        assert(not pcall(encoding.encode, "hi", "base32"))
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_heap_analysis() -> Result<()> {
+    let lua = Lua::new();
+
+    lua.load(
+        r#"
+        config = {
+            servers = {
+                { host = "localhost", port = 8080, tls = false },
+                { host = "localhost", port = 8080, tls = false },
+                { host = "example.com", port = 443, tls = true },
+            },
+            ["log level"] = "info",
+        }
+    "#,
+    )
+    .exec()?;
+
+    let analysis = lua.heap_analysis()?;
+    assert!(analysis.tables >= 5);
+    assert!(analysis.strings > 0 && analysis.string_bytes > 0);
+
+    let host = (analysis.duplicate_keys.iter())
+        .find(|usage| usage.key == "host")
+        .expect("`host` key must be reported");
+    assert_eq!(host.tables, 3);
+
+    let group = (analysis.duplicate_tables.iter())
+        .find(|group| group.paths[0].starts_with("_G.config"))
+        .expect("duplicate servers must be reported");
+    assert_eq!(
+        group.paths,
+        ["_G.config.servers[1]", "_G.config.servers[2]"]
+    );
+    assert_eq!(group.entries, 3);
+
+    let config = (analysis.largest_retainers.iter())
+        .find(|retainer| retainer.path == "_G.config")
+        .expect("`config` table must be reported");
+    assert_eq!(config.retained_entries, 2 + 3 + 9);
+    let servers = (analysis.largest_retainers.iter())
+        .find(|retainer| retainer.path == "_G.config.servers")
+        .unwrap();
+    assert!(servers.retained_string_bytes < config.retained_string_bytes);
+
+    Ok(())
+}
+
+#[test]
+fn test_inspect() -> Result<()> {
+    let lua = Lua::new();
+    lua.install_inspect()?;
+
+    struct Point(i32, i32);
+    impl UserData for Point {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_debug_fmt(|_, this| Ok(format!("Point({}, {})", this.0, this.1)));
+        }
+    }
+    struct Opaque;
+    impl UserData for Opaque {}
+    lua.globals().set("point", Point(1, 2))?;
+    lua.globals().set("opaque", Opaque)?;
+
+    let inspect = |code: &str| lua.load(code).eval::<StdString>();
+    assert_eq!(inspect("inspect(1)")?, "1");
+    assert_eq!(inspect("inspect('abc')")?, r#""abc""#);
+    assert_eq!(inspect("inspect(point)")?, "Point(1, 2)");
+    assert!(inspect("inspect(opaque)")?.contains(": 0x"));
+    assert_eq!(
+        inspect("inspect({ 1, p = point })")?,
+        "{\n  [1] = 1,\n  [\"p\"] = Point(1, 2),\n}"
+    );
+    assert_eq!(
+        inspect("inspect({ a = { b = { c = {} } } }, { depth = 1 })")?,
+        "{\n  [\"a\"] = {...},\n}"
+    );
+
+    // Cycles
+    let s = inspect("local t = {}; t.self = t; return inspect(t)")?;
+    assert!(s.starts_with("{\n  [\"self\"] = table: 0x"), "{s}");
+
+    Ok(())
+}
+
+#[test]
+fn test_host_print_and_clock() -> Result<()> {
+    let lua = Lua::new();
+
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let output2 = output.clone();
+    lua.set_print_handler(move |_, line| {
+        output2.lock().unwrap().push(line.to_string());
+        Ok(())
+    })?;
+    lua.load(
+        r#"
+        print("hello", 1, true, nil)
+        print()
+        print(setmetatable({}, {__tostring = function() return "custom" end}))
+    "#,
+    )
+    .exec()?;
+    assert_eq!(
+        *output.lock().unwrap(),
+        vec!["hello\t1\ttrue\tnil", "", "custom"]
+    );
+
+    lua.set_print_handler(|_, _| Err(Error::RuntimeError("no console".to_string())))?;
+    match lua.load("print('x')").exec() {
+        Err(Error::CallbackError { ref cause, .. }) => match cause.as_ref() {
+            Error::RuntimeError(msg) => assert_eq!(msg, "no console"),
+            err => panic!("expected RuntimeError, got {err:?}"),
+        },
+        r => panic!("expected CallbackError, got {r:?}"),
+    }
+
+    let ticks = Arc::new(AtomicU32::new(0));
+    let ticks2 = ticks.clone();
+    lua.set_clock(move || {
+        let n = ticks2.fetch_add(1, Ordering::Relaxed);
+        std::time::Duration::from_millis(500 * n as u64)
+    })?;
+    let (t1, t2) = lua
+        .load("return os.clock(), os.clock()")
+        .eval::<(f64, f64)>()?;
+    assert_eq!((t1, t2), (0.0, 0.5));
+
+    // The clock also measures incremental GC budgets
+    lua.load("data = {} for i = 1, 100000 do data[i] = {i} end data = nil")
+        .exec()?;
+    let ticks2 = ticks.clone();
+    lua.set_clock(move || {
+        std::time::Duration::from_secs(ticks2.fetch_add(1, Ordering::Relaxed) as u64)
+    })?;
+    ticks.store(0, Ordering::Relaxed);
+    lua.gc_collect_incremental(std::time::Duration::from_secs(1))?;
+    assert_eq!(ticks.load(Ordering::Relaxed), 2);
+    lua.set_clock(|| std::time::Duration::ZERO)?;
+    assert!(lua.gc_collect_incremental(std::time::Duration::from_secs(1))?);
+
+    // `os` table is created if the library is not loaded
+    let lua = Lua::new_with(StdLib::NONE, LuaOptions::default())?;
+    lua.set_clock(|| std::time::Duration::from_secs(3))?;
+    assert_eq!(lua.load("return os.clock()").eval::<f64>()?, 3.0);
+
+    Ok(())
+}
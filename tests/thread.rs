@@ -153,6 +153,228 @@ fn test_thread_reset() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(any(
+    feature = "lua54",
+    all(feature = "luajit", feature = "vendored"),
+    feature = "luau",
+))]
+fn test_thread_close() -> Result<()> {
+    let lua = Lua::new();
+    // Local variables of suspended threads are only visible with full debug info
+    #[cfg(feature = "luau")]
+    lua.set_compiler(mlua::Compiler::new().set_debug_level(2));
+
+    let thread: Thread = lua
+        .load("coroutine.create(function() coroutine.yield(1) coroutine.yield(2) end)")
+        .eval()?;
+    assert_eq!(thread.resume::<_, i32>(())?, 1);
+    thread.close()?;
+    assert_eq!(thread.status(), ThreadStatus::Unresumable);
+    assert!(thread.resume::<_, ()>(()).is_err());
+
+    // Pending to-be-closed variables are closed
+    #[cfg(feature = "lua54")]
+    {
+        let thread: Thread = lua
+            .load(
+                r#"
+                closed = 0
+                return coroutine.create(function()
+                    local x <close> = setmetatable({}, { __close = function() closed = closed + 1 end })
+                    coroutine.yield()
+                end)
+            "#,
+            )
+            .eval()?;
+        thread.resume::<_, ()>(())?;
+        thread.close()?;
+        assert_eq!(lua.globals().get::<_, i32>("closed")?, 1);
+    }
+
+    // Running threads cannot be closed
+    let try_close = lua.create_function(|_, thread: Thread| Ok(thread.close().is_err()))?;
+    lua.globals().set("try_close", try_close)?;
+    let running_err: bool = lua
+        .load(
+            r#"
+            local co
+            co = coroutine.create(function() return try_close(co) end)
+            return select(2, coroutine.resume(co))
+        "#,
+        )
+        .eval()?;
+    assert!(running_err);
+
+    // Close all suspended threads: referenced from globals, local variables of other suspended
+    // threads and Rust
+    let rust_thread: Thread = lua
+        .load(
+            r#"
+            local function gen() coroutine.yield() end
+            global_co = coroutine.create(gen)
+            coroutine.resume(global_co)
+            outer_co = coroutine.create(function()
+                local inner = coroutine.create(gen)
+                coroutine.resume(inner)
+                coroutine.yield()
+            end)
+            coroutine.resume(outer_co)
+            local rust_co = coroutine.create(gen)
+            coroutine.resume(rust_co)
+            not_started_co = coroutine.create(gen)
+            return rust_co
+        "#,
+        )
+        .eval()?;
+    assert_eq!(lua.close_all_suspended_threads()?, 4);
+    assert_eq!(rust_thread.status(), ThreadStatus::Unresumable);
+    let global_co: Thread = lua.globals().get("global_co")?;
+    assert_eq!(global_co.status(), ThreadStatus::Unresumable);
+    let not_started_co: Thread = lua.globals().get("not_started_co")?;
+    assert_eq!(not_started_co.status(), ThreadStatus::Resumable);
+    assert_eq!(lua.close_all_suspended_threads()?, 0);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "luau"))]
+#[test]
+fn test_thread_builder_limits() -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use mlua::HookTriggers;
+
+    let lua = Lua::new();
+
+    let recurse: Function = lua
+        .load("local function f(n) if n == 0 then return 0 end return 1 + f(n - 1) end; return f")
+        .eval()?;
+
+    // Stack limit
+    let thread = lua
+        .thread_builder()
+        .stack_limit(50)
+        .build(recurse.clone())?;
+    match thread.resume::<_, i32>(100) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("stack overflow"), "{msg}"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    let thread = lua
+        .thread_builder()
+        .stack_limit(50)
+        .build(recurse.clone())?;
+    assert_eq!(thread.resume::<_, i32>(40)?, 40);
+
+    // The error can be caught by Lua code
+    let f: Function = lua
+        .load("function(f) local ok = pcall(f, 100); return ok, f(10) end")
+        .eval()?;
+    let thread = lua.thread_builder().stack_limit(50).build(f)?;
+    assert_eq!(
+        thread.resume::<_, (bool, i32)>(recurse.clone())?,
+        (false, 10)
+    );
+
+    // Frames unwound by errors are not counted
+    let f: Function = lua
+        .load(
+            r#"
+            function(f)
+                for _ = 1, 100 do
+                    assert(not pcall(f, 100))
+                end
+                return f(45)
+            end
+        "#,
+        )
+        .eval()?;
+    let thread = lua.thread_builder().stack_limit(50).build(f)?;
+    assert_eq!(thread.resume::<_, i32>(recurse.clone())?, 45);
+
+    // C call limit
+    let rust_recurse = lua.create_function(|lua, n: i32| {
+        let f: Function = lua.globals().get("rust_recurse")?;
+        if n == 0 {
+            return Ok(0);
+        }
+        Ok(1 + f.call::<_, i32>(n - 1)?)
+    })?;
+    lua.globals().set("rust_recurse", rust_recurse.clone())?;
+    let thread = lua
+        .thread_builder()
+        .c_call_limit(10)
+        .build(rust_recurse.clone())?;
+    let err = thread.resume::<_, i32>(20).unwrap_err();
+    assert!(err.to_string().contains("C stack overflow"), "{err}");
+    let thread = lua.thread_builder().c_call_limit(10).build(rust_recurse)?;
+    assert_eq!(thread.resume::<_, i32>(5)?, 5);
+
+    // Limits are kept together with hooks
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    lua.set_hook(HookTriggers::EVERY_LINE, |_, _| Ok(()))?;
+    let thread = lua.thread_builder().stack_limit(50).build(recurse)?;
+    thread.set_hook(HookTriggers::ON_CALLS, move |_, _| {
+        calls2.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    });
+    match thread.resume::<_, i32>(100) {
+        Err(Error::RuntimeError(msg)) => assert!(msg.contains("stack overflow"), "{msg}"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    assert!(calls.load(Ordering::Relaxed) >= 50);
+    lua.remove_hook();
+
+    Ok(())
+}
+
+#[test]
+fn test_thread_error_traceback() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread: Thread = lua
+        .load(
+            r#"
+        coroutine.create(function()
+            local function explode() error("boom") end
+            coroutine.yield()
+            explode()
+        end)
+    "#,
+        )
+        .eval()?;
+    thread.resume::<_, ()>(())?;
+    match thread.resume::<_, ()>(()) {
+        Err(Error::RuntimeError(msg)) => {
+            assert!(msg.contains("boom"));
+            assert!(msg.contains("stack traceback:"));
+            assert!(msg.contains("explode"));
+        }
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    #[cfg(not(feature = "luau"))]
+    {
+        let fail =
+            lua.create_function(|_, ()| Err::<(), _>(Error::RuntimeError("rust boom".into())))?;
+        lua.globals().set("fail", fail)?;
+        let thread: Thread = lua
+            .load("coroutine.create(function() local function caller() fail() end caller() end)")
+            .eval()?;
+        match thread.resume::<_, ()>(()) {
+            Err(Error::CallbackError { traceback, cause }) => {
+                assert!(matches!(*cause, Error::RuntimeError(ref msg) if msg == "rust boom"));
+                assert!(traceback.contains("caller"));
+            }
+            r => panic!("expected CallbackError, got {r:?}"),
+        }
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_coroutine_from_closure() -> Result<()> {
     let lua = Lua::new();
@@ -194,3 +416,34 @@ fn test_coroutine_panic() {
         Err(p) => assert!(*p.downcast::<&str>().unwrap() == "test_panic"),
     }
 }
+
+#[cfg(all(feature = "unstable", not(feature = "send")))]
+#[test]
+fn test_owned_thread() -> Result<()> {
+    let lua = Lua::new();
+
+    let thread = lua
+        .create_thread(
+            lua.load("local n = ... while true do n = n + coroutine.yield(n) end")
+                .into_function()?,
+        )?
+        .into_owned();
+
+    // Owned handles can be stored outside of the `'lua` lifetime and converted back
+    lua.globals().set("co", thread.clone())?;
+    let thread2 = lua.globals().get::<_, mlua::OwnedThread>("co")?;
+    let s = lua.create_string("abc")?.into_owned();
+    lua.globals().set("s", s)?;
+    assert_eq!(
+        lua.globals().get::<_, mlua::OwnedString>("s")?.as_bytes(),
+        b"abc"
+    );
+    drop(lua);
+
+    assert_eq!(thread.status(), ThreadStatus::Resumable);
+    assert_eq!(thread.resume::<_, i64>(1)?, 1);
+    assert_eq!(thread2.resume::<_, i64>(2)?, 3);
+    assert_eq!(thread.to_ref().resume::<_, i64>(3)?, 6);
+
+    Ok(())
+}
@@ -14,8 +14,8 @@ use std::{cell::RefCell, rc::Rc};
 use std::sync::atomic::{AtomicI64, Ordering};
 
 use mlua::{
-    AnyUserData, AnyUserDataExt, Error, ExternalError, Function, Lua, MetaMethod, Nil, Result,
-    String, UserData, UserDataFields, UserDataMethods, UserDataRef, Value,
+    AnyUserData, AnyUserDataExt, BorrowPolicy, Error, ExternalError, Function, Lua, MetaMethod,
+    Nil, Result, String, UserData, UserDataFields, UserDataMethods, UserDataRef, Value, Visibility,
 };
 
 #[test]
@@ -253,6 +253,55 @@ fn test_metamethod_close() -> Result<()> {
 
     assert_eq!(ud.0.load(Ordering::Relaxed), 0);
 
+    // The metamethod receives the error when the scope exits with an error
+    struct Transaction {
+        committed: bool,
+        rolled_back: Arc<AtomicI64>,
+    }
+
+    impl UserData for Transaction {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("commit", |_, this, ()| {
+                this.committed = true;
+                Ok(())
+            });
+            methods.add_meta_method_mut(MetaMethod::Close, |_, this, err: Value| {
+                if !this.committed {
+                    assert!(matches!(err, Value::String(_)));
+                    this.rolled_back.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(())
+            });
+        }
+    }
+
+    let rolled_back = Arc::new(AtomicI64::new(0));
+    let rolled_back2 = rolled_back.clone();
+    globals.set(
+        "begin",
+        lua.create_function(move |_, ()| {
+            Ok(Transaction {
+                committed: false,
+                rolled_back: rolled_back2.clone(),
+            })
+        })?,
+    )?;
+    lua.load(
+        r#"
+        do
+            local tx <close> = begin()
+            tx:commit()
+        end
+        local ok = pcall(function()
+            local tx <close> = begin()
+            error("failed")
+        end)
+        assert(not ok)
+    "#,
+    )
+    .exec()?;
+    assert_eq!(rolled_back.load(Ordering::Relaxed), 1);
+
     Ok(())
 }
 
@@ -796,6 +845,85 @@ fn test_any_userdata() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_iter_userdata() -> Result<()> {
+    let lua = Lua::new();
+
+    struct MyUserData(i64);
+    impl UserData for MyUserData {}
+
+    // Untracked type
+    let _ud = lua.create_any_userdata(1i32)?;
+    assert_eq!(lua.iter_userdata::<i32>().count(), 0);
+
+    lua.register_userdata_type::<MyUserData>(|reg| {
+        MyUserData::add_methods(reg);
+        reg.track_instances();
+    })?;
+
+    let ud1 = lua.create_userdata(MyUserData(1))?;
+    let ud2 = lua.create_userdata(MyUserData(2))?;
+    lua.load("local ud = ...; _G.ud3 = ud")
+        .call(MyUserData(3))?;
+    let mut values = lua
+        .iter_userdata::<MyUserData>()
+        .map(|ud| Ok(ud.borrow::<MyUserData>()?.0))
+        .collect::<Result<Vec<_>>>()?;
+    values.sort();
+    assert_eq!(values, vec![1, 2, 3]);
+
+    // Destructed and collected instances are skipped
+    ud1.take::<MyUserData>()?;
+    drop(ud2);
+    lua.globals().set("ud3", Nil)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(lua.iter_userdata::<MyUserData>().count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_reverse_lookup() -> Result<()> {
+    let lua = Lua::new();
+
+    struct Player {
+        id: usize,
+    }
+    impl UserData for Player {}
+
+    // Lookup is not enabled
+    let player = Arc::new(Player { id: 1 });
+    let _ud = lua.create_any_userdata(player.clone())?;
+    assert!(lua.userdata_for(&player).is_none());
+
+    // Lookup by the pointer address
+    lua.register_userdata_type::<Arc<Player>>(|reg| {
+        reg.reverse_lookup_by(|player| Arc::as_ptr(player) as usize);
+    })?;
+    let ud = lua.create_any_userdata(player.clone())?;
+    let ud2 = lua.userdata_for(&player).unwrap();
+    assert_eq!(ud, ud2);
+    ud.set_user_value("state")?;
+    assert_eq!(ud2.get_user_value::<String>()?, "state");
+    assert!(lua.userdata_for(&Arc::new(Player { id: 2 })).is_none());
+
+    // Lookup by a custom key
+    lua.register_userdata_type::<Player>(|reg| reg.reverse_lookup_by(|player| player.id))?;
+    let ud = lua.create_userdata(Player { id: 3 })?;
+    assert_eq!(lua.userdata_for(&Player { id: 3 }), Some(ud.clone()));
+
+    // Destructed and collected instances are not returned
+    ud.take::<Player>()?;
+    assert!(lua.userdata_for(&Player { id: 3 }).is_none());
+    let _ = lua.create_userdata(Player { id: 4 })?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert!(lua.userdata_for(&Player { id: 4 }).is_none());
+
+    Ok(())
+}
+
 #[test]
 fn test_userdata_ext() -> Result<()> {
     let lua = Lua::new();
@@ -869,6 +997,102 @@ fn test_userdata_method_errors() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_userdata_registration_conflicts() -> Result<()> {
+    let lua = Lua::new();
+
+    struct DuplicateMethod;
+    impl UserData for DuplicateMethod {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.deny_overrides();
+            methods.add_method("get", |_, _, ()| Ok(1));
+            methods.add_method("get", |_, _, ()| Ok(2));
+        }
+    }
+    match lua.create_userdata(DuplicateMethod) {
+        Err(Error::RegistrationConflict(msg)) => {
+            assert_eq!(
+                msg,
+                "DuplicateMethod: method `get` is registered more than once"
+            )
+        }
+        r => panic!("expected RegistrationConflict, got {r:?}"),
+    }
+
+    struct FieldShadowsMethod;
+    impl UserData for FieldShadowsMethod {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field("value", 1);
+        }
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.deny_overrides();
+            methods.add_method("value", |_, _, ()| Ok(2));
+        }
+    }
+    match lua.create_userdata(FieldShadowsMethod) {
+        Err(Error::RegistrationConflict(msg)) => assert!(msg.contains("`value` shadows")),
+        r => panic!("expected RegistrationConflict, got {r:?}"),
+    }
+
+    struct MethodNamedLikeMeta;
+    impl UserData for MethodNamedLikeMeta {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.deny_overrides();
+            methods.add_method("__tostring", |_, _, ()| Ok("x"));
+        }
+    }
+    match lua.create_userdata(MethodNamedLikeMeta) {
+        Err(Error::RegistrationConflict(msg)) => assert!(msg.contains("`__tostring`")),
+        r => panic!("expected RegistrationConflict, got {r:?}"),
+    }
+
+    struct DuplicateMeta;
+    impl UserData for DuplicateMeta {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.deny_overrides();
+            methods.add_meta_method(MetaMethod::ToString, |_, _, ()| Ok("a"));
+            methods.add_meta_method(MetaMethod::ToString, |_, _, ()| Ok("b"));
+        }
+    }
+    assert!(matches!(
+        lua.create_userdata(DuplicateMeta),
+        Err(Error::RegistrationConflict(_))
+    ));
+
+    // Overrides are allowed by default
+    struct Overridden;
+    impl UserData for Overridden {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("get", |_, _, ()| Ok(1));
+            methods.add_method("get", |_, _, ()| Ok(2));
+        }
+    }
+    let ud = lua.create_userdata(Overridden)?;
+    lua.globals().set("ud", ud)?;
+    assert_eq!(lua.load("ud:get()").eval::<i64>()?, 2);
+    let ud = lua.create_userdata(Arc::new(Overridden))?;
+    lua.globals().set("ud", ud)?;
+    assert_eq!(lua.load("ud:get()").eval::<i64>()?, 2);
+
+    // Scoped userdata is checked too
+    lua.scope(|scope| {
+        assert!(matches!(
+            scope.create_userdata(DuplicateMethod),
+            Err(Error::RegistrationConflict(_))
+        ));
+        match scope.create_nonstatic_userdata(DuplicateMethod) {
+            Err(Error::RegistrationConflict(msg)) => {
+                assert!(msg.contains("method `get` is registered more than once"))
+            }
+            r => panic!("expected RegistrationConflict, got {r:?}"),
+        }
+        scope.create_nonstatic_userdata(Overridden)?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
 #[cfg(all(feature = "unstable", not(feature = "send")))]
 #[test]
 fn test_owned_userdata() -> Result<()> {
@@ -887,3 +1111,367 @@ fn test_owned_userdata() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "macros")]
+#[test]
+fn test_userdata_derive_object() -> Result<()> {
+    use mlua::LuaObject;
+
+    #[derive(LuaObject)]
+    #[lua(methods = add_methods)]
+    struct Item {
+        name: StdString,
+        #[lua(rename = "qty")]
+        quantity: u32,
+        #[lua(readonly)]
+        id: i64,
+        #[lua(skip)]
+        cache: Option<Vec<u8>>,
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Item>>(methods: &mut M) {
+        methods.add_method_mut("take", |_, this, n: u32| {
+            this.quantity -= n;
+            Ok(this.quantity)
+        });
+    }
+
+    assert_eq!(Item::FIELDS, ["name", "qty", "id"]);
+
+    let lua = Lua::new();
+    let item = Item {
+        name: "apple".into(),
+        quantity: 10,
+        id: 7,
+        cache: Some(vec![1, 2, 3]),
+    };
+    lua.globals().set("item", item)?;
+    lua.load(
+        r#"
+        assert(item.name == "apple" and item.qty == 10 and item.id == 7)
+        assert(item.cache == nil)
+        item.name = "pear"
+        assert(item:take(3) == 7)
+        assert(not pcall(function() item.id = 8 end))
+    "#,
+    )
+    .exec()?;
+
+    let item = lua.globals().get::<_, AnyUserData>("item")?;
+    let table = item.borrow::<Item>()?.to_table(&lua)?;
+    assert_eq!(table.get::<_, StdString>("name")?, "pear");
+    assert_eq!(table.get::<_, u32>("qty")?, 7);
+    assert_eq!(table.get::<_, i64>("id")?, 7);
+    assert_eq!(table.raw_len(), 0);
+    assert_eq!(table.get::<_, Value>("cache")?, Value::Nil);
+
+    table.set("qty", 42)?;
+    let item = Item::from_table(table)?;
+    assert_eq!(item.name, "pear");
+    assert_eq!(item.quantity, 42);
+    assert_eq!(item.id, 7);
+    assert_eq!(item.cache, None);
+
+    let table = lua.create_table()?;
+    table.set("name", "broken")?;
+    assert!(Item::from_table(table).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_method_descriptors() -> Result<()> {
+    use mlua::{FromLuaMulti, IntoLuaMulti, MethodDesc};
+
+    struct Vec2(f64, f64);
+
+    static METHODS: &[MethodDesc<Vec2>] = &[
+        MethodDesc::method("length", |lua, this, _| {
+            (this.0 * this.0 + this.1 * this.1)
+                .sqrt()
+                .into_lua_multi(lua)
+        }),
+        MethodDesc::method_mut("scale", |lua, this, args| {
+            let k = f64::from_lua_multi(args, lua)?;
+            this.0 *= k;
+            this.1 *= k;
+            ().into_lua_multi(lua)
+        }),
+        MethodDesc::method_mut("with", |lua, this, args| {
+            let f = mlua::Function::from_lua_multi(args, lua)?;
+            f.call::<_, ()>(this.0)?;
+            ().into_lua_multi(lua)
+        }),
+        MethodDesc::meta_method(MetaMethod::ToString, |lua, this, _| {
+            format!("({}, {})", this.0, this.1).into_lua_multi(lua)
+        }),
+        MethodDesc::meta_method_mut(MetaMethod::Call, |lua, this, _| {
+            std::mem::swap(&mut this.0, &mut this.1);
+            ().into_lua_multi(lua)
+        }),
+    ];
+    assert_eq!(METHODS[3].name(), "__tostring");
+
+    impl UserData for Vec2 {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_methods_from_descriptors(METHODS);
+        }
+    }
+
+    let lua = Lua::new();
+    lua.globals().set("v", Vec2(3.0, 4.0))?;
+    lua.globals().set("w", Vec2(1.0, 0.0))?;
+    lua.load(
+        r#"
+        assert(v:length() == 5)
+        v:scale(2)
+        v()
+        assert(tostring(v) == "(8, 6)")
+        -- Mutable methods can be nested for different instances
+        local sum = 0
+        v:with(function(x) w:with(function(y) sum = x + y end) end)
+        assert(sum == 9)
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_signal() -> Result<()> {
+    let lua = Lua::new();
+
+    let signal = lua.create_signal::<(&str, i64)>()?;
+    lua.globals().set("signal", signal.clone())?;
+    lua.load(
+        r#"
+        log = {}
+        id1 = signal:connect(function(name, n) table.insert(log, "lua1:" .. name .. n) end)
+        id2 = signal:connect(function(name, n) table.insert(log, "lua2:" .. name .. n) end)
+    "#,
+    )
+    .exec()?;
+    let log = lua.globals().get::<_, mlua::Table>("log")?;
+    let rust_fn = lua.create_function(|lua, (name, n): (String, i64)| {
+        let log = lua.globals().get::<_, mlua::Table>("log")?;
+        log.raw_push(format!("rust:{}{n}", name.to_str()?))
+    })?;
+    let id3 = signal.connect(rust_fn)?;
+    assert_eq!(signal.len()?, 3);
+
+    // Handlers are called in connection order
+    signal.emit(("a", 1))?;
+    lua.load(r#"signal:emit("b", 2)"#).exec()?;
+    assert_eq!(
+        log.clone()
+            .sequence_values()
+            .collect::<Result<Vec<StdString>>>()?,
+        vec!["lua1:a1", "lua2:a1", "rust:a1", "lua1:b2", "lua2:b2", "rust:b2"]
+    );
+
+    // Disconnect from both sides
+    assert!(signal.disconnect(id3)?);
+    assert!(!signal.disconnect(id3)?);
+    lua.load(r#"assert(signal:disconnect(id1) == true)"#)
+        .exec()?;
+    log.clear()?;
+    signal.emit(("c", 3))?;
+    assert_eq!(log.raw_len(), 1);
+    assert_eq!(log.raw_get::<_, StdString>(1)?, "lua2:c3");
+
+    // Weak connections are dropped once the handler is collected
+    lua.load(
+        r#"
+        weak_handler = function() table.insert(log, "weak") end
+        signal:connect(weak_handler, { weak = true })
+    "#,
+    )
+    .exec()?;
+    assert_eq!(lua.load("#signal").eval::<usize>()?, 2);
+    lua.globals().set("weak_handler", Nil)?;
+    lua.gc_collect()?;
+    lua.gc_collect()?;
+    assert_eq!(signal.len()?, 1);
+
+    // Conversion from other userdata fails
+    let other = lua.create_any_userdata(0i32)?;
+    assert!(lua
+        .unpack::<mlua::Signal<()>>(Value::UserData(other))
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_try_borrow() -> Result<()> {
+    struct Account(i64);
+
+    impl UserData for Account {
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.set_borrow_policy(BorrowPolicy::LuaError);
+            methods.add_method("balance", |_, this, ()| Ok(this.0));
+            methods.add_method_mut("update", |_, this, f: Function| {
+                this.0 = f.call(this.0)?;
+                Ok(())
+            });
+        }
+    }
+
+    let lua = Lua::new();
+    let ud = lua.create_userdata(Account(10))?;
+
+    {
+        let _guard = ud.borrow_mut::<Account>()?;
+        match ud.try_borrow::<Account>() {
+            Err(Error::UserDataWouldBlock) => {}
+            r => panic!("expected UserDataWouldBlock, got {:?}", r.map(|_| ())),
+        }
+        match ud.try_borrow_mut::<Account>() {
+            Err(Error::UserDataWouldBlock) => {}
+            r => panic!("expected UserDataWouldBlock, got {:?}", r.map(|_| ())),
+        }
+    }
+    {
+        let _guard = ud.borrow::<Account>()?;
+        assert_eq!(ud.try_borrow::<Account>()?.0, 10);
+        assert!(matches!(
+            ud.try_borrow_mut::<Account>().map(|_| ()),
+            Err(Error::UserDataWouldBlock)
+        ));
+    }
+    assert_eq!(ud.try_borrow::<Account>()?.0, 10);
+    ud.try_borrow_mut::<Account>()?.0 = 20;
+    assert!(matches!(
+        ud.try_borrow::<StdString>(),
+        Err(Error::UserDataTypeMismatch)
+    ));
+
+    // Reentrant calls fail with a plain runtime error
+    lua.globals().set("account", ud)?;
+    lua.load(
+        r#"
+        local ok, err = pcall(account.update, account, function(v) return account:balance() + v end)
+        assert(not ok)
+        assert(string.find(tostring(err), "`Account.balance`: userdata is already mutably borrowed", 1, true))
+        account:update(function(v) return v + 1 end)
+        assert(account:balance() == 21)
+    "#,
+    )
+    .exec()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_dynamic_fields() -> Result<()> {
+    struct Entity {
+        id: u32,
+    }
+
+    impl UserData for Entity {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field_method_get("id", |_, this| Ok(this.id));
+        }
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method("describe", |_, this, ()| Ok(format!("entity {}", this.id)));
+            methods.add_dynamic_fields_storage();
+        }
+    }
+
+    let lua = Lua::new();
+    let e1 = lua.create_userdata(Entity { id: 1 })?;
+    let e2 = lua.create_userdata(Entity { id: 2 })?;
+    lua.globals().set("e1", e1.clone())?;
+    lua.globals().set("e2", e2.clone())?;
+
+    assert_eq!(e1.dynamic_get::<_, Option<String>>("tag")?, None);
+    lua.load(
+        r#"
+        assert(e1.id == 1 and e1:describe() == "entity 1")
+        assert(e1.tag == nil)
+        e1.tag = "enemy"
+        e1[1] = true
+        assert(e1.tag == "enemy" and e2.tag == nil)
+    "#,
+    )
+    .exec()?;
+    assert_eq!(e1.dynamic_get::<_, StdString>("tag")?, "enemy");
+    assert!(e1.dynamic_get::<_, bool>(1)?);
+
+    e2.dynamic_set("speed", 2.5)?;
+    assert_eq!(lua.load("e2.speed * 2").eval::<f64>()?, 5.0);
+    lua.load("e2.speed = nil").exec()?;
+    assert_eq!(e2.dynamic_get::<_, Value>("speed")?, Nil);
+
+    // Fields are not shadowed
+    assert!(lua.load("e1.id = 10").exec().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_userdata_visibility() -> Result<()> {
+    struct Account {
+        balance: i64,
+        pin: u32,
+    }
+
+    impl UserData for Account {
+        fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+            fields.add_field_method_get("balance", |_, this| Ok(this.balance));
+            fields.add_field_method_get("pin", |_, this| Ok(this.pin));
+            fields.add_field_method_set("pin", |_, this, pin| {
+                this.pin = pin;
+                Ok(())
+            });
+            fields.add_field("bank", "mlua");
+            fields.set_visibility("pin", Visibility::HostOnly);
+            fields.set_visibility("bank", Visibility::HostOnly);
+        }
+
+        fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+            methods.add_method_mut("deposit", |_, this, amount: i64| {
+                this.balance += amount;
+                Ok(())
+            });
+            methods.add_method_mut("reset", |_, this, ()| {
+                this.balance = 0;
+                Ok(())
+            });
+            methods.set_visibility("reset", Visibility::HostOnly);
+            // Visibility can be changed back
+            methods.set_visibility("deposit", Visibility::HostOnly);
+            methods.set_visibility("deposit", Visibility::Public);
+        }
+    }
+
+    let lua = Lua::new();
+    let account = lua.create_userdata(Account {
+        balance: 0,
+        pin: 1234,
+    })?;
+    lua.globals().set("account", account.clone())?;
+
+    lua.load(
+        r#"
+        account:deposit(10)
+        assert(account.balance == 10)
+        assert(account.reset == nil)
+        assert(account.pin == nil)
+        assert(account.bank == nil)
+    "#,
+    )
+    .exec()?;
+
+    // Host-only members are accessible from Rust
+    assert_eq!(account.get::<_, u32>("pin")?, 1234);
+    account.set("pin", 4321)?;
+    assert_eq!(account.borrow::<Account>()?.pin, 4321);
+    assert_eq!(account.get::<_, StdString>("bank")?, "mlua");
+    account.call_method::<_, ()>("reset", ())?;
+    assert_eq!(account.get::<_, i64>("balance")?, 0);
+
+    Ok(())
+}
@@ -3,7 +3,9 @@ use std::os::raw::c_void;
 use std::ptr;
 use std::string::String as StdString;
 
-use mlua::{Error, LightUserData, Lua, MultiValue, Result, UserData, UserDataMethods, Value};
+use mlua::{
+    Error, HashableValue, LightUserData, Lua, MultiValue, Result, UserData, UserDataMethods, Value,
+};
 
 #[test]
 fn test_value_eq() -> Result<()> {
@@ -70,6 +72,111 @@ fn test_value_eq() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_value_hash() -> Result<()> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::Hasher;
+
+    fn hash_of(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let lua = Lua::new();
+
+    // Equal values hash equally
+    assert_eq!(hash_of(&Value::Integer(1)), hash_of(&Value::Number(1.0)));
+    assert_eq!(hash_of(&Value::Number(0.0)), hash_of(&Value::Number(-0.0)));
+    let s1 = Value::String(lua.create_string("abc")?);
+    let s2: Value = lua.load(r#""ab" .. "c""#).eval()?;
+    assert_eq!(hash_of(&s1), hash_of(&s2));
+    assert_ne!(hash_of(&s1), hash_of(&Value::Nil));
+    assert_ne!(hash_of(&Value::Boolean(true)), hash_of(&Value::Integer(1)));
+
+    // Reference types are hashed by identity
+    let t1 = Value::Table(lua.create_table()?);
+    let t2 = Value::Table(lua.create_table()?);
+    assert_eq!(hash_of(&t1), hash_of(&t1.clone()));
+    assert_ne!(hash_of(&t1), hash_of(&t2));
+
+    let mut set = HashSet::new();
+    set.insert(HashableValue::from(Value::Integer(1)));
+    set.insert(HashableValue::from(Value::Number(1.0)));
+    set.insert(HashableValue::from(s1));
+    set.insert(HashableValue::from(s2));
+    set.insert(HashableValue::from(t1.clone()));
+    set.insert(HashableValue::from(t1));
+    set.insert(HashableValue::from(t2));
+    set.insert(HashableValue::from(Value::Number(f64::NAN)));
+    set.insert(HashableValue::from(Value::Number(f64::NAN)));
+    assert_eq!(set.len(), 5);
+
+    // Tables can be converted into maps with arbitrary keys
+    let map: HashMap<HashableValue, i32> = lua.load("{ [1] = 10, x = 20, [true] = 30 }").eval()?;
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&HashableValue(Value::Number(1.0))], 10);
+    assert_eq!(map[&HashableValue(Value::Boolean(true))], 30);
+
+    Ok(())
+}
+
+#[test]
+fn test_value_result_convention() -> Result<()> {
+    let lua = Lua::new();
+
+    let f = lua
+        .load(
+            r#"
+        function(kind)
+            if kind == "ok" then return "value", "extra" end
+            if kind == "nil" then return nil end
+            if kind == "nilmsg" then return nil, "not found", 2 end
+            if kind == "false" then return false, 123 end
+            return false
+        end
+    "#,
+        )
+        .eval::<mlua::Function>()?;
+    let call = |kind: &str| -> Result<Value> {
+        Value::from_lua_result_convention(f.call::<_, MultiValue>(kind)?)
+    };
+
+    match call("ok")? {
+        Value::String(s) => assert_eq!(s, "value"),
+        v => panic!("expected string, got {v:?}"),
+    }
+    assert_eq!(call("nil")?, Value::Nil);
+    assert_eq!(call("other")?, Value::Boolean(false));
+    match call("nilmsg") {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "not found"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    match call("false") {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "123"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+    assert_eq!(
+        Value::from_lua_result_convention(MultiValue::new())?,
+        Value::Nil
+    );
+
+    // Reverse conversion
+    let ok = Value::into_lua_result_convention(&lua, Ok(Value::Integer(1)))?;
+    assert_eq!(ok.into_vec(), vec![Value::Integer(1)]);
+    let err = Value::into_lua_result_convention(&lua, Err(Error::RuntimeError("boom".into())))?;
+    let (v, msg): (Value, String) = lua.unpack_multi(err.clone())?;
+    assert_eq!(v, Value::Nil);
+    assert_eq!(msg, "boom");
+    match Value::from_lua_result_convention(err) {
+        Err(Error::RuntimeError(msg)) => assert_eq!(msg, "boom"),
+        r => panic!("expected RuntimeError, got {r:?}"),
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_multi_value() {
     let mut multi_value = MultiValue::new();